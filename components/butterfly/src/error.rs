@@ -21,6 +21,7 @@ pub enum Error {
     NonExistentRumor(String, String),
     OsError(io::Error),
     ProtocolMismatch(&'static str),
+    RingNotEncrypted,
     ServiceConfigDecode(String, String),
     ServiceConfigNotUtf8(String, str::Utf8Error),
     SocketSetReadTimeout(io::Error),
@@ -68,6 +69,9 @@ impl fmt::Display for Error {
                 format!("Received an unsupported or bad protocol message. Missing field: {}",
                         field)
             }
+            Error::RingNotEncrypted => {
+                "This ring isn't encrypted, so it has no ring key to change".to_string()
+            }
             Error::ServiceConfigDecode(ref sg, ref err) => {
                 format!("Cannot decode service config: group={}, {}", sg, err)
             }
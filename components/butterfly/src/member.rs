@@ -9,7 +9,8 @@ use crate::{error::{Error,
                        FromProto},
             rumor::{RumorKey,
                     RumorPayload,
-                    RumorType}};
+                    RumorType},
+            server::stats::MembershipTally};
 use habitat_common::sync::{Lock,
                            ReadGuard,
                            WriteGuard};
@@ -569,6 +570,18 @@ impl MemberList {
         }
     }
 
+    /// How many members are in each state of health.
+    ///
+    /// # Locking (see locking.md)
+    /// * `MemberList::entries` (read)
+    pub fn membership_tally_mlr(&self) -> MembershipTally {
+        let mut tally = MembershipTally::default();
+        for entry in self.read_entries().values() {
+            tally.count(entry.health);
+        }
+        tally
+    }
+
     /// Returns the health of the member, if the member exists.
     ///
     /// # Locking (see locking.md)
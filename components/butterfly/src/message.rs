@@ -1,11 +1,58 @@
 use bytes::BytesMut;
-use habitat_core::crypto::keys::RingKey;
+use habitat_core::crypto::keys::{Key,
+                                 NamedRevision,
+                                 RingKey};
 use prost::Message;
 
 use crate::{error::{Error,
                     Result},
             protocol::Wire};
 
+/// The revisions of a ring key that a Butterfly server uses on the
+/// wire. Everything we send is encrypted with the newest revision,
+/// but messages encrypted with a few older ones are still accepted so
+/// that a ring can be moved onto a new key one member at a time.
+#[derive(Clone, Debug)]
+pub struct RingKeys {
+    current:  RingKey,
+    // Newest first
+    previous: Vec<RingKey>,
+}
+
+impl RingKeys {
+    /// Accept only `current`.
+    pub fn new(current: RingKey) -> Self {
+        RingKeys { current,
+                   previous: Vec::new() }
+    }
+
+    /// Send with the newest of `revisions`, and accept up to `window`
+    /// of the revisions immediately before it. Returns `None` if there
+    /// are no revisions at all.
+    pub fn from_revisions(mut revisions: Vec<RingKey>, window: usize) -> Option<Self> {
+        revisions.sort_by(|a, b| b.named_revision().cmp(a.named_revision()));
+        let mut revisions = revisions.into_iter();
+        let current = revisions.next()?;
+        Some(RingKeys { current,
+                        previous: revisions.take(window).collect() })
+    }
+
+    /// The revision that outgoing messages are encrypted with.
+    pub fn current(&self) -> &RingKey { &self.current }
+
+    /// Every revision an incoming message may be encrypted with,
+    /// newest first.
+    pub fn accepted(&self) -> impl Iterator<Item = &RingKey> {
+        std::iter::once(&self.current).chain(self.previous.iter())
+    }
+
+    pub fn accepted_revisions(&self) -> Vec<NamedRevision> {
+        self.accepted()
+            .map(|key| key.named_revision().clone())
+            .collect()
+    }
+}
+
 pub fn generate_wire(payload: Vec<u8>, ring_key: Option<&RingKey>) -> Result<Vec<u8>> {
     let mut wire = Wire::default();
     if let Some(ring_key) = ring_key {
@@ -21,14 +68,90 @@ pub fn generate_wire(payload: Vec<u8>, ring_key: Option<&RingKey>) -> Result<Vec
     Ok(buf.to_vec())
 }
 
-pub fn unwrap_wire(payload: &[u8], ring_key: Option<&RingKey>) -> Result<Vec<u8>> {
+/// Decode a message, decrypting it with whichever of the accepted
+/// ring keys it was encrypted with. If none of them can decrypt it,
+/// the error is the one from the current key.
+pub fn unwrap_wire(payload: &[u8], ring_keys: Option<&RingKeys>) -> Result<Vec<u8>> {
     let wire = Wire::decode(payload)?;
     let payload = wire.payload
                       .ok_or(Error::ProtocolMismatch("missing payload"))?;
-    if let Some(ring_key) = ring_key {
+    if let Some(ring_keys) = ring_keys {
         let nonce = wire.nonce.ok_or(Error::ProtocolMismatch("missing nonce"))?;
-        Ok(ring_key.decrypt(&nonce, &payload)?)
+        match ring_keys.current().decrypt(&nonce, &payload) {
+            Ok(decrypted) => Ok(decrypted),
+            Err(err) => {
+                ring_keys.previous
+                         .iter()
+                         .find_map(|key| key.decrypt(&nonce, &payload).ok())
+                         .ok_or_else(|| err.into())
+            }
+        }
     } else {
         Ok(payload)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use habitat_core::crypto::keys::KeyRevision;
+    use rand::{rngs::StdRng,
+               SeedableRng};
+
+    fn ring_key(revision: &str) -> RingKey {
+        RingKey::new_with("beyonce",
+                          &mut StdRng::seed_from_u64(revision.parse().unwrap()),
+                          revision.parse::<KeyRevision>().unwrap())
+    }
+
+    fn revisions(ring_keys: &RingKeys) -> Vec<String> {
+        ring_keys.accepted_revisions()
+                 .iter()
+                 .map(ToString::to_string)
+                 .collect()
+    }
+
+    #[test]
+    fn ring_keys_send_with_the_newest_revision() {
+        let ring_keys = RingKeys::from_revisions(vec![ring_key("20200101000000"),
+                                                      ring_key("20220101000000"),
+                                                      ring_key("20210101000000"),],
+                                                 1).unwrap();
+        assert_eq!(ring_keys.current(), &ring_key("20220101000000"));
+        assert_eq!(revisions(&ring_keys),
+                   vec!["beyonce-20220101000000", "beyonce-20210101000000"]);
+    }
+
+    #[test]
+    fn ring_keys_with_no_window_accept_only_the_newest_revision() {
+        let ring_keys = RingKeys::from_revisions(vec![ring_key("20200101000000"),
+                                                      ring_key("20210101000000"),],
+                                                 0).unwrap();
+        assert_eq!(revisions(&ring_keys), vec!["beyonce-20210101000000"]);
+        assert!(RingKeys::from_revisions(Vec::new(), 1).is_none());
+    }
+
+    #[test]
+    fn messages_from_accepted_revisions_are_unwrapped() {
+        let old = ring_key("20200101000000");
+        let new = ring_key("20210101000000");
+        let ring_keys = RingKeys::from_revisions(vec![old.clone(), new.clone()], 1).unwrap();
+
+        for key in &[old, new] {
+            let wire = generate_wire(b"Multipass!".to_vec(), Some(key)).unwrap();
+            assert_eq!(unwrap_wire(&wire, Some(&ring_keys)).unwrap(), b"Multipass!");
+        }
+    }
+
+    #[test]
+    fn messages_from_revisions_outside_the_window_are_rejected() {
+        let oldest = ring_key("20190101000000");
+        let ring_keys = RingKeys::from_revisions(vec![oldest.clone(),
+                                                      ring_key("20200101000000"),
+                                                      ring_key("20210101000000"),],
+                                                 1).unwrap();
+
+        let wire = generate_wire(b"Multipass!".to_vec(), Some(&oldest)).unwrap();
+        assert!(unwrap_wire(&wire, Some(&ring_keys)).is_err());
+    }
+}
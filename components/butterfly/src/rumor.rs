@@ -21,6 +21,8 @@ use crate::{error::{Error,
                        Message},
             rumor::election::ElectionRumor};
 use bytes::BytesMut;
+use habitat_core::crypto::keys::{KeyCache,
+                                 ServiceMessage};
 use prometheus::{register_int_counter_vec,
                  IntCounterVec};
 use prost::Message as ProstMessage;
@@ -138,6 +140,22 @@ impl<'a, T: Rumor> From<&'a T> for RumorKey {
     fn from(rumor: &'a T) -> RumorKey { RumorKey::new(rumor.kind(), rumor.id(), rumor.key()) }
 }
 
+/// Decrypt the encrypted payload of a rumor for a service, whether it
+/// was sent by a user or anonymously, using the keys in `key_cache`.
+fn decrypt_service_payload(payload: &[u8], key_cache: &KeyCache) -> Result<Vec<u8>> {
+    match ServiceMessage::from_bytes(payload)? {
+        ServiceMessage::Signed(secret) => {
+            let user_public_key = key_cache.user_public_encryption_key(secret.encryptor())?;
+            let service_secret_key = key_cache.service_secret_encryption_key(secret.decryptor())?;
+            Ok(service_secret_key.decrypt_user_message(&secret, &user_public_key)?)
+        }
+        ServiceMessage::Anonymous(secret) => {
+            let service_secret_key = key_cache.service_secret_encryption_key(secret.key_pair())?;
+            Ok(service_secret_key.unseal(&secret)?)
+        }
+    }
+}
+
 type RumorSubMap<T> = HashMap<RumorKeyId, T>;
 type RumorMap<T> = HashMap<RumorKeyKey, RumorSubMap<T>>;
 
@@ -274,11 +292,17 @@ mod storage {
     pub struct RumorStore<T> {
         list:           Arc<Lock<RumorMap<T>>>,
         update_counter: Arc<AtomicUsize>,
+        /// How many rumors are in `list`, kept alongside it so that it
+        /// can be read without taking the lock.
+        held:           Arc<AtomicUsize>,
     }
 
     impl<T> RumorStore<T> {
         pub fn get_update_counter(&self) -> usize { self.update_counter.load(Ordering::Relaxed) }
 
+        /// How many rumors the store holds.
+        pub fn held(&self) -> usize { self.held.load(Ordering::Relaxed) }
+
         /// Increment the update counter for this store.
         ///
         /// We don't care if this repeats - it just needs to be unique for any given two states,
@@ -298,7 +322,9 @@ mod storage {
         /// * `RumorStore::list` (write)
         pub fn remove_rsw(&self, key: &str, id: &str) {
             let mut list = self.list.write();
-            list.get_mut(key).and_then(|r| r.remove(id));
+            if list.get_mut(key).and_then(|r| r.remove(id)).is_some() {
+                self.held.fetch_sub(1, Ordering::Relaxed);
+            }
         }
     }
 
@@ -319,6 +345,7 @@ mod storage {
                 Entry::Occupied(mut entry) => entry.get_mut().merge(rumor),
                 Entry::Vacant(entry) => {
                     entry.insert(rumor);
+                    self.held.fetch_add(1, Ordering::Relaxed);
                     true
                 }
             };
@@ -336,7 +363,8 @@ mod storage {
     impl<T> Default for RumorStore<T> {
         fn default() -> RumorStore<T> {
             RumorStore { list:           Arc::default(),
-                         update_counter: Arc::default(), }
+                         update_counter: Arc::default(),
+                         held:           Arc::default(), }
         }
     }
 
@@ -689,6 +717,22 @@ mod tests {
             assert_eq!(rs.lock_rsr().get(&key).unwrap().len(), 2);
         }
 
+        #[test]
+        fn held_counts_rumors_in_and_out() {
+            let rs = RumorStore::default();
+            let f1 = FakeRumor::default();
+            let f2 = FakeRumor::default();
+            let (key, f1_id) = (String::from(f1.key()), String::from(f1.id()));
+            assert!(rs.insert_rsw(f1.clone()));
+            assert!(rs.insert_rsw(f2));
+            assert!(!rs.insert_rsw(f1));
+            assert_eq!(rs.held(), 2);
+
+            rs.remove_rsw(&key, &f1_id);
+            rs.remove_rsw(&key, &f1_id);
+            assert_eq!(rs.held(), 1);
+        }
+
         #[test]
         fn insert_returns_false_on_no_changes() {
             let rs = RumorStore::default();
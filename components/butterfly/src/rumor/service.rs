@@ -174,14 +174,15 @@ impl Rumor for Service {
     fn key(&self) -> &str { self.service_group.as_ref() }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct SysInfo {
     pub ip:                String,
     pub hostname:          String,
     pub gossip_ip:         String,
     pub gossip_port:       u32,
-    pub http_gateway_ip:   String,
-    pub http_gateway_port: u32,
+    /// `None` if the member's HTTP gateway isn't served over TCP.
+    pub http_gateway_ip:   Option<String>,
+    pub http_gateway_port: Option<u32>,
     pub ctl_gateway_ip:    String,
     pub ctl_gateway_port:  u32,
 }
@@ -192,8 +193,8 @@ impl Default for SysInfo {
                   hostname:          "localhost".to_string(),
                   gossip_ip:         "127.0.0.1".to_string(),
                   gossip_port:       0,
-                  http_gateway_ip:   "127.0.0.1".to_string(),
-                  http_gateway_port: 0,
+                  http_gateway_ip:   Some("127.0.0.1".to_string()),
+                  http_gateway_port: Some(0),
                   ctl_gateway_ip:    "127.0.0.1".to_string(),
                   ctl_gateway_port:  0, }
     }
@@ -206,8 +207,8 @@ impl FromProto<newscast::SysInfo> for SysInfo {
                                              .ok_or(Error::ProtocolMismatch("hostname"))?,
                      gossip_ip:         proto.gossip_ip.unwrap_or_default(),
                      gossip_port:       proto.gossip_port.unwrap_or_default(),
-                     http_gateway_ip:   proto.http_gateway_ip.unwrap_or_default(),
-                     http_gateway_port: proto.http_gateway_port.unwrap_or_default(),
+                     http_gateway_ip:   proto.http_gateway_ip,
+                     http_gateway_port: proto.http_gateway_port,
                      ctl_gateway_ip:    proto.ctl_gateway_ip.unwrap_or_default(),
                      ctl_gateway_port:  proto.ctl_gateway_port.unwrap_or_default(), })
     }
@@ -219,8 +220,8 @@ impl From<SysInfo> for newscast::SysInfo {
                             hostname:          Some(value.hostname),
                             gossip_ip:         Some(value.gossip_ip),
                             gossip_port:       Some(value.gossip_port),
-                            http_gateway_ip:   Some(value.http_gateway_ip),
-                            http_gateway_port: Some(value.http_gateway_port),
+                            http_gateway_ip:   value.http_gateway_ip,
+                            http_gateway_port: value.http_gateway_port,
                             ctl_gateway_ip:    Some(value.ctl_gateway_ip),
                             ctl_gateway_port:  Some(value.ctl_gateway_port), }
     }
@@ -8,12 +8,12 @@ use crate::{error::{Error,
                        newscast::{self,
                                   Rumor as ProtoRumor},
                        FromProto},
-            rumor::{ConstIdRumor,
+            rumor::{decrypt_service_payload,
+                    ConstIdRumor,
                     Rumor,
                     RumorPayload,
                     RumorType}};
-use habitat_core::{crypto::keys::{KeyCache,
-                                  SignedBox},
+use habitat_core::{crypto::keys::KeyCache,
                    service::ServiceGroup};
 use serde::Serialize;
 use std::{borrow::Cow,
@@ -73,12 +73,7 @@ impl ServiceConfig {
 
     pub fn config(&self, key_cache: &KeyCache) -> Result<toml::value::Table> {
         let bytes = if self.encrypted {
-            let secret = SignedBox::from_bytes(&self.config)?;
-            let user_public_key = key_cache.user_public_encryption_key(secret.encryptor())?;
-            let service_secret_key = key_cache.service_secret_encryption_key(secret.decryptor())?;
-
-            service_secret_key.decrypt_user_message(&secret, &user_public_key)
-                              .map(Cow::Owned)?
+            decrypt_service_payload(&self.config, key_cache).map(Cow::Owned)?
         } else {
             Cow::Borrowed(&self.config)
         };
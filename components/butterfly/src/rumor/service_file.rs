@@ -8,11 +8,11 @@ use crate::{error::{Error,
                        newscast::{self,
                                   Rumor as ProtoRumor},
                        FromProto},
-            rumor::{Rumor,
+            rumor::{decrypt_service_payload,
+                    Rumor,
                     RumorPayload,
                     RumorType}};
-use habitat_core::{crypto::keys::{KeyCache,
-                                  SignedBox},
+use habitat_core::{crypto::keys::KeyCache,
                    service::ServiceGroup};
 use serde::Serialize;
 use std::{cmp::Ordering,
@@ -86,10 +86,7 @@ impl ServiceFile {
     // refactoring, though.
     pub fn body(&self, key_cache: &KeyCache) -> Result<Vec<u8>> {
         if self.encrypted {
-            let secret = SignedBox::from_bytes(&self.body)?;
-            let user_public_key = key_cache.user_public_encryption_key(secret.encryptor())?;
-            let service_secret_key = key_cache.service_secret_encryption_key(secret.decryptor())?;
-            Ok(service_secret_key.decrypt_user_message(&secret, &user_public_key)?)
+            decrypt_service_payload(&self.body, key_cache)
         } else {
             Ok(self.body.clone())
         }
@@ -234,4 +231,19 @@ mod tests {
                                                                  the body"),
                    String::from("tcp-backlog = 128"));
     }
+
+    #[test]
+    fn anonymously_encrypted_bodies_are_decrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_cache = KeyCache::new(dir.path());
+        key_cache.setup().unwrap();
+        let (public, _secret) = key_cache.new_service_encryption_pair("acme",
+                                                                      "neurosis.production")
+                                         .unwrap();
+
+        let sealed = public.seal(b"tcp-backlog = 128").to_string();
+        let mut s1 = create_service_file("adam", "yep", &sealed);
+        s1.encrypted = true;
+        assert_eq!(s1.body(&key_cache).unwrap(), b"tcp-backlog = 128");
+    }
 }
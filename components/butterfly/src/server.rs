@@ -11,9 +11,12 @@ mod incarnation_store;
 mod outbound;
 mod pull;
 mod push;
+pub mod stats;
 pub mod timing;
 
 use self::{incarnation_store::IncarnationStore,
+           stats::{GossipStats,
+                   GossipStatsSnapshot},
            sync::Myself};
 use crate::{error::{Error,
                     Result},
@@ -22,7 +25,8 @@ use crate::{error::{Error,
                      Member,
                      MemberList,
                      MemberListProxy},
-            message,
+            message::{self,
+                      RingKeys},
             rumor::{dat_file::{DatFileReader,
                                DatFileWriter},
                     departure::Departure,
@@ -43,7 +47,8 @@ use crate::{error::{Error,
 use habitat_common::{liveliness_checker,
                      sync::Lock,
                      FeatureFlag};
-use habitat_core::crypto::keys::RingKey;
+use habitat_core::crypto::keys::{NamedRevision,
+                                 RingKey};
 use lazy_static::lazy_static;
 use log::{debug,
           error,
@@ -286,8 +291,9 @@ pub struct Server {
     // depends on it being so. Refactor so it can be private.
     myself:                   Arc<Myself>,
     pub member_list:          Arc<MemberList>,
-    ring_key:                 Arc<Option<RingKey>>,
+    ring_keys:                Arc<Lock<Option<RingKeys>>>,
     rumor_heat:               Arc<RumorHeat>,
+    stats:                    Arc<GossipStats>,
     pub service_store:        RumorStore<Service>,
     pub service_config_store: RumorStore<ServiceConfig>,
     pub service_file_store:   RumorStore<ServiceFile>,
@@ -315,8 +321,9 @@ impl Clone for Server {
                  member_id:            self.member_id.clone(),
                  myself:               self.myself.clone(),
                  member_list:          self.member_list.clone(),
-                 ring_key:             self.ring_key.clone(),
+                 ring_keys:            self.ring_keys.clone(),
                  rumor_heat:           self.rumor_heat.clone(),
+                 stats:                self.stats.clone(),
                  service_store:        self.service_store.clone(),
                  service_config_store: self.service_config_store.clone(),
                  service_file_store:   self.service_file_store.clone(),
@@ -377,8 +384,9 @@ impl Server {
                             member_id: Arc::new(member_id),
                             myself: Arc::new(myself),
                             member_list: Arc::new(MemberList::new()),
-                            ring_key: Arc::new(ring_key),
+                            ring_keys: Arc::new(Lock::new(ring_key.map(RingKeys::new))),
                             rumor_heat: Arc::default(),
+                            stats: Arc::default(),
                             service_store: RumorStore::default(),
                             service_config_store: RumorStore::default(),
                             service_file_store: RumorStore::default(),
@@ -466,6 +474,7 @@ impl Server {
     /// * Returns `Error::SocketSetReadTimeout` if the socket read timeout cannot be set
     pub fn start_rsw_mlw_smw_rhw_msr(&mut self, timing: &timing::Timing) -> Result<()> {
         debug!("entering habitat_butterfly::server::Server::start");
+        self.stats.set_gossip_interval(timing.gossip_interval());
         let (tx_outbound, rx_inbound) = channel();
         if let Some(ref path) = self.data_path {
             if let Some(err) = fs::create_dir_all(path).err() {
@@ -625,7 +634,8 @@ impl Server {
     /// * `MemberList::entries` (write)
     /// * `Server::member` (write)
     /// * `RumorHeat::inner` (write)
-    pub fn set_departed_mlw_smw_rhw(&self) {
+    /// * `Server::ring_keys` (read)
+    pub fn set_departed_mlw_smw_rhw_srkr(&self) {
         if self.socket.is_some() {
             self.myself.lock_smw().increment_incarnation();
             // TODO (CM): It's not clear that this operation is actually needed.
@@ -653,7 +663,11 @@ impl Server {
             for member in check_list.iter().take(SELF_DEPARTURE_RUMOR_FANOUT) {
                 let addr = member.swim_socket_address();
                 // Safe because we checked above
-                outbound::ack_mlr_smr_rhw(self, self.socket.as_ref().unwrap(), member, addr, None);
+                outbound::ack_mlr_smr_rhw_srkr(self,
+                                               self.socket.as_ref().unwrap(),
+                                               member,
+                                               addr,
+                                               None);
             }
         } else {
             debug!("No socket present; server was never started, so nothing to depart");
@@ -1220,12 +1234,85 @@ impl Server {
         }
     }
 
-    fn generate_wire(&self, payload: Vec<u8>) -> Result<Vec<u8>> {
-        message::generate_wire(payload, (*self.ring_key).as_ref())
+    /// The counters of rumors sent and received, as they are now.
+    pub fn gossip_stats(&self) -> &GossipStats { &self.stats }
+
+    /// What gossip has been up to, with the counts of rumors sent and
+    /// received since the previous call; see `GossipStats::snapshot`.
+    ///
+    /// # Locking (see locking.md)
+    /// * `MemberList::entries` (read)
+    pub fn gossip_stats_snapshot_mlr(&self) -> GossipStatsSnapshot {
+        let held = |kind| {
+            match kind {
+                RumorType::Member => self.member_list.len_mlr(),
+                RumorType::Service => self.service_store.held(),
+                RumorType::ServiceConfig => self.service_config_store.held(),
+                RumorType::ServiceFile => self.service_file_store.held(),
+                RumorType::Election => self.election_store.held(),
+                RumorType::ElectionUpdate => self.update_store.held(),
+                RumorType::Departure => self.departure_store.held(),
+                RumorType::Fake | RumorType::Fake2 => 0,
+            }
+        };
+        self.stats
+            .snapshot(held, self.member_list.membership_tally_mlr())
+    }
+
+    /// The ring key outgoing messages are encrypted with, if the ring
+    /// is encrypted.
+    ///
+    /// # Locking (see locking.md)
+    /// * `Server::ring_keys` (read)
+    pub fn ring_key_srkr(&self) -> Option<RingKey> {
+        self.ring_keys
+            .read()
+            .as_ref()
+            .map(|ring_keys| ring_keys.current().clone())
     }
 
-    fn unwrap_wire(&self, payload: &[u8]) -> Result<Vec<u8>> {
-        message::unwrap_wire(payload, (*self.ring_key).as_ref())
+    /// The revisions of the ring key incoming messages may be
+    /// encrypted with, newest first. Empty if the ring isn't
+    /// encrypted.
+    ///
+    /// # Locking (see locking.md)
+    /// * `Server::ring_keys` (read)
+    pub fn accepted_ring_key_revisions_srkr(&self) -> Vec<NamedRevision> {
+        self.ring_keys
+            .read()
+            .as_ref()
+            .map(RingKeys::accepted_revisions)
+            .unwrap_or_default()
+    }
+
+    /// Start encrypting outgoing messages with the current revision
+    /// in `ring_keys`, and accepting incoming ones encrypted with any
+    /// of its revisions. A ring that wasn't encrypted to begin with
+    /// can't be switched over, since that would cut us off from every
+    /// other member.
+    ///
+    /// # Locking (see locking.md)
+    /// * `Server::ring_keys` (write)
+    pub fn set_ring_keys_srkw(&self, ring_keys: RingKeys) -> Result<()> {
+        let mut current = self.ring_keys.write();
+        if current.is_none() {
+            return Err(Error::RingNotEncrypted);
+        }
+        *current = Some(ring_keys);
+        Ok(())
+    }
+
+    /// # Locking (see locking.md)
+    /// * `Server::ring_keys` (read)
+    fn generate_wire_srkr(&self, payload: Vec<u8>) -> Result<Vec<u8>> {
+        let ring_keys = self.ring_keys.read();
+        message::generate_wire(payload, ring_keys.as_ref().map(RingKeys::current))
+    }
+
+    /// # Locking (see locking.md)
+    /// * `Server::ring_keys` (read)
+    fn unwrap_wire_srkr(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        message::unwrap_wire(payload, self.ring_keys.read().as_ref())
     }
 
     /// # Locking (see locking.md)
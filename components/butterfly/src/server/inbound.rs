@@ -62,7 +62,7 @@ pub fn run_loop(server: &Server, socket: &UdpSocket, tx_outbound: &AckSender) ->
 
         match socket.recv_from(&mut recv_buffer[..]) {
             Ok((length, addr)) => {
-                let swim_payload = match server.unwrap_wire(&recv_buffer[0..length]) {
+                let swim_payload = match server.unwrap_wire_srkr(&recv_buffer[0..length]) {
                     Ok(swim_payload) => swim_payload,
                     Err(e) => {
                         // NOTE: In the future, we might want to block people who send us
@@ -107,7 +107,7 @@ pub fn run_loop(server: &Server, socket: &UdpSocket, tx_outbound: &AckSender) ->
                                    ping.from.id);
                             continue;
                         }
-                        process_ping_mlw_smw_rhw(server, socket, addr, ping);
+                        process_ping_mlw_smw_rhw_srkr(server, socket, addr, ping);
                     }
                     SwimKind::Ack(ack) => {
                         if server.is_member_blocked_sblr(&ack.from.id) && ack.forward_to.is_none() {
@@ -115,7 +115,7 @@ pub fn run_loop(server: &Server, socket: &UdpSocket, tx_outbound: &AckSender) ->
                                    ack.from.id);
                             continue;
                         }
-                        process_ack_mlw_smw_rhw(server, socket, tx_outbound, addr, ack);
+                        process_ack_mlw_smw_rhw_srkr(server, socket, tx_outbound, addr, ack);
                     }
                     SwimKind::PingReq(pingreq) => {
                         if server.is_member_blocked_sblr(&pingreq.from.id) {
@@ -123,7 +123,7 @@ pub fn run_loop(server: &Server, socket: &UdpSocket, tx_outbound: &AckSender) ->
                                    pingreq.from.id);
                             continue;
                         }
-                        process_pingreq_mlr_smr_rhw(server, socket, addr, pingreq);
+                        process_pingreq_mlr_smr_rhw_srkr(server, socket, addr, pingreq);
                     }
                 }
             }
@@ -154,10 +154,11 @@ pub fn run_loop(server: &Server, socket: &UdpSocket, tx_outbound: &AckSender) ->
 /// * `MemberList::entries` (read)
 /// * `Server::member` (read)
 /// * `RumorHeat::inner` (write)
-fn process_pingreq_mlr_smr_rhw(server: &Server,
-                               socket: &UdpSocket,
-                               addr: SocketAddr,
-                               mut msg: PingReq) {
+/// * `Server::ring_keys` (read)
+fn process_pingreq_mlr_smr_rhw_srkr(server: &Server,
+                                    socket: &UdpSocket,
+                                    addr: SocketAddr,
+                                    mut msg: PingReq) {
     if let Some(target) = server.member_list.get_cloned_mlr(&msg.target.id) {
         msg.from.address = addr.ip().to_string();
         let ping_msg = Ping { membership: vec![],
@@ -166,11 +167,11 @@ fn process_pingreq_mlr_smr_rhw(server: &Server,
         let swim = outbound::populate_membership_rumors_mlr_rhw(server, &target, ping_msg);
         // Set the route-back address to the one we received the
         // pingreq from
-        outbound::ping(server,
-                       socket,
-                       target.swim_socket_address(),
-                       Some(&msg.from),
-                       &swim);
+        outbound::ping_srkr(server,
+                            socket,
+                            target.swim_socket_address(),
+                            Some(&msg.from),
+                            &swim);
     } else {
         error!("PingReq request {:?} for invalid target", msg);
     }
@@ -182,11 +183,12 @@ fn process_pingreq_mlr_smr_rhw(server: &Server,
 /// * `MemberList::entries` (write)
 /// * `Server::member` (write)
 /// * `RumorHeat::inner` (write)
-fn process_ack_mlw_smw_rhw(server: &Server,
-                           socket: &UdpSocket,
-                           tx_outbound: &AckSender,
-                           addr: SocketAddr,
-                           mut msg: Ack) {
+/// * `Server::ring_keys` (read)
+fn process_ack_mlw_smw_rhw_srkr(server: &Server,
+                                socket: &UdpSocket,
+                                tx_outbound: &AckSender,
+                                addr: SocketAddr,
+                                mut msg: Ack) {
     trace!("Ack from {}@{}", msg.from.id, addr);
     if msg.forward_to.is_some() && *server.member_id != msg.forward_to.as_ref().unwrap().id {
         let (forward_to_addr, from_addr) = {
@@ -208,7 +210,7 @@ fn process_ack_mlw_smw_rhw(server: &Server,
             (forward_to_addr, addr.ip().to_string())
         };
         msg.from.address = from_addr;
-        outbound::forward_ack(server, socket, forward_to_addr, msg);
+        outbound::forward_ack_srkr(server, socket, forward_to_addr, msg);
         return;
     }
     let memberships = msg.membership.clone();
@@ -226,8 +228,12 @@ fn process_ack_mlw_smw_rhw(server: &Server,
 /// * `MemberList::entries` (write)
 /// * `Server::member` (write)
 /// * `RumorHeat::inner` (write)
-fn process_ping_mlw_smw_rhw(server: &Server, socket: &UdpSocket, addr: SocketAddr, mut msg: Ping) {
-    outbound::ack_mlr_smr_rhw(server, socket, &msg.from, addr, msg.forward_to);
+/// * `Server::ring_keys` (read)
+fn process_ping_mlw_smw_rhw_srkr(server: &Server,
+                                 socket: &UdpSocket,
+                                 addr: SocketAddr,
+                                 mut msg: Ping) {
+    outbound::ack_mlr_smr_rhw_srkr(server, socket, &msg.from, addr, msg.forward_to);
     // Populate the member for this sender with its remote address
     msg.from.address = addr.ip().to_string();
     trace!("Ping from {}@{}", msg.from.id, addr);
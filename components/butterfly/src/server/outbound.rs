@@ -104,11 +104,11 @@ fn run_loop(server: &Server, socket: &UdpSocket, rx_inbound: &AckReceiver, timin
                     have_members = true;
                 } else {
                     server.member_list.with_initial_members_imlr(|member| {
-                                          ping_mlr_smr_rhw(server,
-                                                           socket,
-                                                           member,
-                                                           member.swim_socket_address(),
-                                                           None);
+                                          ping_mlr_smr_rhw_srkr(server,
+                                                                socket,
+                                                                member,
+                                                                member.swim_socket_address(),
+                                                                None);
                                       });
                 }
             }
@@ -129,7 +129,7 @@ fn run_loop(server: &Server, socket: &UdpSocket, rx_inbound: &AckReceiver, timin
                 // If we complete the probe faster than our protocol
                 // period, we'll want to wait after we finish.
                 let probe_start = Instant::now();
-                probe_mlw_smr_rhw(server, socket, rx_inbound, timing, member);
+                probe_mlw_smr_rhw_srkr(server, socket, rx_inbound, timing, member);
                 timing.sleep_for_remaining_swim_protocol_interval(probe_start);
             }
         }
@@ -171,11 +171,12 @@ fn run_loop(server: &Server, socket: &UdpSocket, rx_inbound: &AckReceiver, timin
 /// * `MemberList::entries` (write)
 /// * `Server::member` (read)
 /// * `RumorHeat::inner` (write)
-fn probe_mlw_smr_rhw(server: &Server,
-                     socket: &UdpSocket,
-                     rx_inbound: &AckReceiver,
-                     timing: &Timing,
-                     member: Member) {
+/// * `Server::ring_keys` (read)
+fn probe_mlw_smr_rhw_srkr(server: &Server,
+                          socket: &UdpSocket,
+                          rx_inbound: &AckReceiver,
+                          timing: &Timing,
+                          member: Member) {
     let pa_timer = SWIM_PROBE_DURATION.with_label_values(&["ping/ack"])
                                       .start_timer();
     let mut pr_timer: Option<HistogramTimer> = None;
@@ -183,7 +184,7 @@ fn probe_mlw_smr_rhw(server: &Server,
 
     // Ping the member, and wait for the ack.
     SWIM_PROBES_SENT.with_label_values(&["ping"]).inc();
-    ping_mlr_smr_rhw(server, socket, &member, addr, None);
+    ping_mlr_smr_rhw_srkr(server, socket, &member, addr, None);
 
     if recv_ack_mlw_rhw(server, rx_inbound, timing, &member, addr, AckFrom::Ping) {
         SWIM_PROBES_SENT.with_label_values(&["ack"]).inc();
@@ -201,7 +202,7 @@ fn probe_mlw_smr_rhw(server: &Server,
               SWIM_PROBES_SENT.with_label_values(&["pingreq"]).inc();
               pr_timer = Some(SWIM_PROBE_DURATION.with_label_values(&["pingreq/ack"])
                                                  .start_timer());
-              pingreq(server, socket, pingreq_target, &member, &swim);
+              pingreq_srkr(server, socket, pingreq_target, &member, &swim);
           });
 
     if recv_ack_mlw_rhw(server, rx_inbound, timing, &member, addr, AckFrom::PingReq) {
@@ -330,11 +331,14 @@ pub fn populate_membership_rumors_mlr_rhw(server: &Server,
 /// Send a PingReq: request `pingreq_target` to ping `target` on the behalf of `server` to see if
 /// `target` is alive despite not being directly reachable from `server`. In other words,
 /// `pingreq_target` is the proxy and `target` is the final destination.
-fn pingreq(server: &Server, // TODO: eliminate this arg
-           socket: &UdpSocket,
-           pingreq_target: &Member,
-           target: &Member,
-           swim: &Swim) {
+///
+/// # Locking (see locking.md)
+/// * `Server::ring_keys` (read)
+fn pingreq_srkr(server: &Server, // TODO: eliminate this arg
+                socket: &UdpSocket,
+                pingreq_target: &Member,
+                target: &Member,
+                swim: &Swim) {
     let addr = pingreq_target.swim_socket_address();
     let bytes = match swim.clone().encode() {
         Ok(bytes) => bytes,
@@ -343,7 +347,7 @@ fn pingreq(server: &Server, // TODO: eliminate this arg
             return;
         }
     };
-    let payload = match server.generate_wire(bytes) {
+    let payload = match server.generate_wire_srkr(bytes) {
         Ok(payload) => payload,
         Err(e) => {
             error!("Generating protocol message failed: {}", e);
@@ -379,11 +383,12 @@ fn pingreq(server: &Server, // TODO: eliminate this arg
 /// * `MemberList::entries` (read)
 /// * `Server::member` (read)
 /// * `RumorHeat::inner` (write)
-pub fn ping_mlr_smr_rhw(server: &Server,
-                        socket: &UdpSocket,
-                        target: &Member,
-                        addr: SocketAddr,
-                        forward_to: Option<&Member>) {
+/// * `Server::ring_keys` (read)
+pub fn ping_mlr_smr_rhw_srkr(server: &Server,
+                             socket: &UdpSocket,
+                             target: &Member,
+                             addr: SocketAddr,
+                             forward_to: Option<&Member>) {
     let ping_msg = Ping { membership: vec![],
                           from:       server.myself.lock_smr().to_member(),
                           forward_to: forward_to.cloned(), /* TODO: see if we can eliminate this
@@ -396,7 +401,7 @@ pub fn ping_mlr_smr_rhw(server: &Server,
             return;
         }
     };
-    let payload = match server.generate_wire(bytes) {
+    let payload = match server.generate_wire_srkr(bytes) {
         Ok(payload) => payload,
         Err(e) => {
             error!("Generating protocol message failed: {}", e);
@@ -419,11 +424,13 @@ pub fn ping_mlr_smr_rhw(server: &Server,
     }
 }
 
-pub fn ping(server: &Server,
-            socket: &UdpSocket,
-            addr: SocketAddr,
-            forward_to: Option<&Member>,
-            swim: &Swim) {
+/// # Locking (see locking.md)
+/// * `Server::ring_keys` (read)
+pub fn ping_srkr(server: &Server,
+                 socket: &UdpSocket,
+                 addr: SocketAddr,
+                 forward_to: Option<&Member>,
+                 swim: &Swim) {
     let bytes = match swim.clone().encode() {
         Ok(bytes) => bytes,
         Err(e) => {
@@ -431,7 +438,7 @@ pub fn ping(server: &Server,
             return;
         }
     };
-    let payload = match server.generate_wire(bytes) {
+    let payload = match server.generate_wire_srkr(bytes) {
         Ok(payload) => payload,
         Err(e) => {
             error!("Generating protocol message failed: {}", e);
@@ -455,7 +462,10 @@ pub fn ping(server: &Server,
 }
 
 /// Forward an ack on.
-pub fn forward_ack(server: &Server, socket: &UdpSocket, addr: SocketAddr, msg: Ack) {
+///
+/// # Locking (see locking.md)
+/// * `Server::ring_keys` (read)
+pub fn forward_ack_srkr(server: &Server, socket: &UdpSocket, addr: SocketAddr, msg: Ack) {
     let member_id = msg.from.id.clone();
     let swim: Swim = msg.into();
     let bytes = match swim.encode() {
@@ -465,7 +475,7 @@ pub fn forward_ack(server: &Server, socket: &UdpSocket, addr: SocketAddr, msg: A
             return;
         }
     };
-    let payload = match server.generate_wire(bytes) {
+    let payload = match server.generate_wire_srkr(bytes) {
         Ok(payload) => payload,
         Err(e) => {
             error!("Generating protocol message failed: {}", e);
@@ -484,11 +494,12 @@ pub fn forward_ack(server: &Server, socket: &UdpSocket, addr: SocketAddr, msg: A
 /// * `MemberList::entries` (read)
 /// * `Server::member` (read)
 /// * `RumorHeat::inner` (write)
-pub fn ack_mlr_smr_rhw(server: &Server,
-                       socket: &UdpSocket,
-                       target: &Member,
-                       addr: SocketAddr,
-                       forward_to: Option<Member>) {
+/// * `Server::ring_keys` (read)
+pub fn ack_mlr_smr_rhw_srkr(server: &Server,
+                            socket: &UdpSocket,
+                            target: &Member,
+                            addr: SocketAddr,
+                            forward_to: Option<Member>) {
     let ack_msg = Ack { membership: vec![],
                         from:       server.myself.lock_smr().to_member(),
                         forward_to: forward_to.map(Member::from), };
@@ -501,7 +512,7 @@ pub fn ack_mlr_smr_rhw(server: &Server,
             return;
         }
     };
-    let payload = match server.generate_wire(bytes) {
+    let payload = match server.generate_wire_srkr(bytes) {
         Ok(payload) => payload,
         Err(e) => {
             error!("Generating protocol message failed: {}", e);
@@ -74,7 +74,7 @@ fn run_loop(server: &Server) -> ! {
             }
         };
 
-        let payload = match server.unwrap_wire(&msg) {
+        let payload = match server.unwrap_wire_srkr(&msg) {
             Ok(payload) => payload,
             Err(e) => {
                 // NOTE: In the future, we might want to block people who send us
@@ -116,6 +116,7 @@ fn run_loop(server: &Server) -> ! {
                   proto.from_id);
             continue 'recv;
         }
+        server.stats.rumor_received(proto.r#type);
 
         match proto.kind {
             RumorKind::Membership(membership) => {
@@ -62,6 +62,7 @@ fn run_loop(server: &Server, timing: &Timing) -> ! {
 
         let mut check_list = server.member_list.check_list_mlr(server.member_id());
         let fanout_loop_start_time = Instant::now();
+        let mut outbound_queue = 0;
 
         'fanout: loop {
             let mut thread_list = Vec::with_capacity(FANOUT);
@@ -86,19 +87,21 @@ fn run_loop(server: &Server, timing: &Timing) -> ! {
                                        .lock_rhr()
                                        .currently_hot_rumors(&member.id);
                     if !rumors.is_empty() {
+                        outbound_queue += rumors.len();
                         let sc = server.clone();
-                        let guard = match thread::Builder::new().name(String::from("push-worker"))
-                                                                .spawn(move || {
-                                                                    send_rumors_rsr_mlr_rhw(&sc,
-                                                                                            &member,
-                                                                                            &rumors)
-                                                                }) {
-                            Ok(guard) => guard,
-                            Err(e) => {
-                                error!("Could not spawn thread: {}", e);
-                                continue;
-                            }
-                        };
+                        let guard =
+                            match thread::Builder::new().name(String::from("push-worker"))
+                                                        .spawn(move || {
+                                                            send_rumors_rsr_mlr_rhw_srkr(&sc,
+                                                                                         &member,
+                                                                                         &rumors)
+                                                        }) {
+                                Ok(guard) => guard,
+                                Err(e) => {
+                                    error!("Could not spawn thread: {}", e);
+                                    continue;
+                                }
+                            };
                         thread_list.push(guard);
                     }
                 }
@@ -112,6 +115,7 @@ fn run_loop(server: &Server, timing: &Timing) -> ! {
             // for that long.
             timing.sleep_for_remaining_gossip_interval(gossip_start_time);
         }
+        server.stats.set_outbound_queue(outbound_queue);
 
         // If we've still got any time left in the gossip interval, sleep
         // for that long.
@@ -151,7 +155,9 @@ fn run_loop(server: &Server, timing: &Timing) -> ! {
 // but changing it in the absence of other necessity seems like too much risk for the
 // expected reward.
 #[allow(clippy::cognitive_complexity)]
-fn send_rumors_rsr_mlr_rhw(server: &Server, member: &Member, rumors: &[RumorKey]) {
+/// # Locking (see locking.md)
+/// * `Server::ring_keys` (read)
+fn send_rumors_rsr_mlr_rhw_srkr(server: &Server, member: &Member, rumors: &[RumorKey]) {
     let socket = (**ZMQ_CONTEXT).as_mut()
                                 .socket(zmq::PUSH)
                                 .expect("Failure to create the ZMQ push socket");
@@ -295,7 +301,7 @@ fn send_rumors_rsr_mlr_rhw(server: &Server, member: &Member, rumors: &[RumorKey]
             }
         };
         let rumor_len = rumor_as_bytes.len().to_i64();
-        let payload = match server.generate_wire(rumor_as_bytes) {
+        let payload = match server.generate_wire_srkr(rumor_as_bytes) {
             Ok(payload) => payload,
             Err(e) => {
                 error!("Generating protobuf failed: {}", e);
@@ -308,6 +314,7 @@ fn send_rumors_rsr_mlr_rhw(server: &Server, member: &Member, rumors: &[RumorKey]
         };
         match socket.send(&payload, 0) {
             Ok(()) => {
+                server.stats.rumor_sent(rumor_key.kind);
                 GOSSIP_MESSAGES_SENT.with_label_values(&[&rumor_key.kind.to_string(), "success"])
                                     .inc();
                 GOSSIP_BYTES_SENT.with_label_values(&[&rumor_key.kind.to_string(), "success"])
@@ -0,0 +1,234 @@
+//! Statistics about gossip, for seeing what it's up to without turning on trace logging.
+//!
+//! The counters are atomics, bumped by the push and pull threads as rumors go out and come in;
+//! nothing here takes a rumor store's lock. `GossipStats::snapshot` turns them into something
+//! to show, along with how much changed since the previous snapshot.
+
+use crate::{member::Health,
+            rumor::RumorType};
+use serde::Serialize;
+use std::{collections::BTreeMap,
+          sync::{atomic::{AtomicU64,
+                          AtomicUsize,
+                          Ordering},
+                 Mutex},
+          time::{Duration,
+                 Instant}};
+
+/// The rumor types statistics are kept for; the fake ones only exist in tests.
+const RUMOR_TYPES: [RumorType; 7] = [RumorType::Member,
+                                     RumorType::Service,
+                                     RumorType::ServiceConfig,
+                                     RumorType::ServiceFile,
+                                     RumorType::Election,
+                                     RumorType::ElectionUpdate,
+                                     RumorType::Departure];
+
+type RumorCounters = [AtomicU64; RUMOR_TYPES.len()];
+type RumorCounts = [u64; RUMOR_TYPES.len()];
+
+fn index_of(kind: RumorType) -> Option<usize> { RUMOR_TYPES.iter().position(|k| *k == kind) }
+
+fn load(counters: &RumorCounters) -> RumorCounts {
+    let mut counts = RumorCounts::default();
+    for (count, counter) in counts.iter_mut().zip(counters.iter()) {
+        *count = counter.load(Ordering::Relaxed);
+    }
+    counts
+}
+
+#[derive(Debug, Default)]
+pub struct GossipStats {
+    received:           RumorCounters,
+    sent:               RumorCounters,
+    /// How many rumors the last round of gossip had to send, summed
+    /// over the members they were sent to.
+    outbound_queue:     AtomicUsize,
+    gossip_interval_ms: AtomicU64,
+    /// When the previous snapshot was taken, and the totals as of
+    /// then.
+    previous:           Mutex<Option<(Instant, RumorCounts, RumorCounts)>>,
+}
+
+impl GossipStats {
+    /// Count a rumor of `kind` taken in from another member.
+    pub(crate) fn rumor_received(&self, kind: RumorType) {
+        if let Some(i) = index_of(kind) {
+            self.received[i].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Count a rumor of `kind` sent to another member.
+    pub(crate) fn rumor_sent(&self, kind: RumorType) {
+        if let Some(i) = index_of(kind) {
+            self.sent[i].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn set_outbound_queue(&self, len: usize) {
+        self.outbound_queue.store(len, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_gossip_interval(&self, interval: Duration) {
+        self.gossip_interval_ms
+            .store(interval.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// How many rumors of `kind` have been received since the server
+    /// was created.
+    pub fn received(&self, kind: RumorType) -> u64 {
+        index_of(kind).map_or(0, |i| self.received[i].load(Ordering::Relaxed))
+    }
+
+    /// How many rumors of `kind` have been sent since the server was
+    /// created.
+    pub fn sent(&self, kind: RumorType) -> u64 {
+        index_of(kind).map_or(0, |i| self.sent[i].load(Ordering::Relaxed))
+    }
+
+    /// Everything counted so far, and how much of it was counted since
+    /// the previous snapshot. `held` says how many rumors of a type
+    /// are being held now.
+    pub fn snapshot(&self,
+                    held: impl Fn(RumorType) -> usize,
+                    membership: MembershipTally)
+                    -> GossipStatsSnapshot {
+        let now = Instant::now();
+        let received = load(&self.received);
+        let sent = load(&self.sent);
+        let mut previous = self.previous.lock().expect("GossipStats lock is poisoned");
+        let (interval, received_before, sent_before) = match *previous {
+            Some((taken_at, received_before, sent_before)) => {
+                (Some(now.duration_since(taken_at)), received_before, sent_before)
+            }
+            None => (None, RumorCounts::default(), RumorCounts::default()),
+        };
+        *previous = Some((now, received, sent));
+
+        let mut rumors = BTreeMap::new();
+        for (i, kind) in RUMOR_TYPES.iter().enumerate() {
+            let received_last_interval = received[i] - received_before[i];
+            let sent_last_interval = sent[i] - sent_before[i];
+            rumors.insert(kind.to_string(),
+                          RumorStats { held: held(*kind),
+                                       received: received[i],
+                                       sent: sent[i],
+                                       received_last_interval,
+                                       sent_last_interval });
+        }
+        GossipStatsSnapshot { interval_ms: interval.map(|i| i.as_millis() as u64),
+                              gossip_interval_ms: self.gossip_interval_ms.load(Ordering::Relaxed),
+                              outbound_queue: self.outbound_queue.load(Ordering::Relaxed),
+                              membership,
+                              rumors }
+    }
+}
+
+/// How many members of the ring are in each state of health.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct MembershipTally {
+    pub alive:     usize,
+    pub suspect:   usize,
+    pub confirmed: usize,
+    pub departed:  usize,
+}
+
+impl MembershipTally {
+    pub(crate) fn count(&mut self, health: Health) {
+        match health {
+            Health::Alive => self.alive += 1,
+            Health::Suspect => self.suspect += 1,
+            Health::Confirmed => self.confirmed += 1,
+            Health::Departed => self.departed += 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RumorStats {
+    /// How many rumors of this type are being held.
+    pub held:                   usize,
+    /// How many rumors of this type have been received in all.
+    pub received:               u64,
+    /// How many rumors of this type have been sent in all.
+    pub sent:                   u64,
+    pub received_last_interval: u64,
+    pub sent_last_interval:     u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct GossipStatsSnapshot {
+    /// How long it's been since the previous snapshot, which the
+    /// `*_last_interval` counts cover; `None` for the first snapshot,
+    /// whose counts cover everything.
+    pub interval_ms:        Option<u64>,
+    pub gossip_interval_ms: u64,
+    pub outbound_queue:     usize,
+    pub membership:         MembershipTally,
+    /// Keyed by rumor type, e.g. "service-config".
+    pub rumors:             BTreeMap<String, RumorStats>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_count_what_happened_since_the_previous_one() {
+        let stats = GossipStats::default();
+        stats.rumor_received(RumorType::ServiceConfig);
+        stats.rumor_sent(RumorType::Service);
+        stats.rumor_sent(RumorType::Service);
+
+        let first = stats.snapshot(|_| 0, MembershipTally::default());
+        assert!(first.interval_ms.is_none());
+        assert_eq!(first.rumors["service-config"].received, 1);
+        assert_eq!(first.rumors["service-config"].received_last_interval, 1);
+        assert_eq!(first.rumors["service"].sent_last_interval, 2);
+
+        stats.rumor_received(RumorType::ServiceConfig);
+        let second = stats.snapshot(|_| 0, MembershipTally::default());
+        assert!(second.interval_ms.is_some());
+        assert_eq!(second.rumors["service-config"].received, 2);
+        assert_eq!(second.rumors["service-config"].received_last_interval, 1);
+        assert_eq!(second.rumors["service"].sent, 2);
+        assert_eq!(second.rumors["service"].sent_last_interval, 0);
+    }
+
+    #[test]
+    fn fake_rumors_are_not_counted() {
+        let stats = GossipStats::default();
+        stats.rumor_received(RumorType::Fake);
+        stats.rumor_sent(RumorType::Fake2);
+
+        assert_eq!(stats.received(RumorType::Fake), 0);
+        assert_eq!(stats.sent(RumorType::Fake2), 0);
+        let snapshot = stats.snapshot(|_| 0, MembershipTally::default());
+        assert!(!snapshot.rumors.contains_key("fake"));
+        assert!(snapshot.rumors
+                        .values()
+                        .all(|r| r.received == 0 && r.sent == 0));
+    }
+
+    #[test]
+    fn snapshots_report_what_is_held() {
+        let stats = GossipStats::default();
+        let mut membership = MembershipTally::default();
+        membership.count(Health::Alive);
+        membership.count(Health::Alive);
+        membership.count(Health::Suspect);
+
+        let snapshot = stats.snapshot(|kind| {
+                                          if kind == RumorType::Member {
+                                              3
+                                          } else {
+                                              0
+                                          }
+                                      },
+                                      membership);
+        assert_eq!(snapshot.rumors["member"].held, 3);
+        assert_eq!(snapshot.rumors["service"].held, 0);
+        assert_eq!(snapshot.membership.alive, 2);
+        assert_eq!(snapshot.membership.suspect, 1);
+    }
+}
@@ -56,6 +56,9 @@ impl Timing {
     /// consider it departed.
     pub fn departure(&self) -> Duration { self.departure }
 
+    /// How long to wait between each time we send rumors out.
+    pub fn gossip_interval(&self) -> Duration { self.gossip_interval }
+
     /// If the amount of time since `starting_point` is less than a
     /// gossip interval, sleep for the remainder of that gossip interval.
     pub fn sleep_for_remaining_gossip_interval(&self, starting_point: Instant) {
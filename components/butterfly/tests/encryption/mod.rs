@@ -1,6 +1,8 @@
 use crate::btest;
-use habitat_butterfly::member::Health;
-use habitat_core::crypto::keys::RingKey;
+use habitat_butterfly::{member::Health,
+                        message::RingKeys};
+use habitat_core::crypto::keys::{Key,
+                                 RingKey};
 
 #[test]
 fn symmetric_encryption_of_wire_payloads() {
@@ -15,3 +17,44 @@ fn symmetric_encryption_of_wire_payloads() {
                   .service_group("beast.prod")
                   .contains_id(net[0].member_id()));
 }
+
+#[test]
+fn rumors_keep_flowing_while_the_ring_key_is_rotated() {
+    let old_key = RingKey::new("wolverine");
+    let new_key = RingKey::new_with("wolverine",
+                                    &mut rand::thread_rng(),
+                                    "20991231235959".parse().unwrap());
+    let ring_keys = RingKeys::from_revisions(vec![old_key.clone(), new_key.clone()], 1).unwrap();
+    let mut net = btest::SwimNet::new_ring_encryption_rhw(2, &old_key);
+    net.connect_smr(0, 1);
+    assert_wait_for_health_of_mlr!(net, [0..2, 0..2], Health::Alive);
+
+    // Part way through the rotation: member 0 sends with the new key,
+    // but still accepts rumors sent with the old one
+    net[0].set_ring_keys_srkw(ring_keys.clone()).unwrap();
+    net.add_service(1, "core/beast/1.2.3/20161208121212");
+    net.wait_for_gossip_rounds(2);
+    assert!(net[0].service_store
+                  .lock_rsr()
+                  .service_group("beast.prod")
+                  .contains_id(net[1].member_id()));
+
+    net[1].set_ring_keys_srkw(ring_keys).unwrap();
+    net.add_service(0, "core/storm/1.2.3/20161208121212");
+    net.wait_for_gossip_rounds(2);
+    assert!(net[1].service_store
+                  .lock_rsr()
+                  .service_group("storm.prod")
+                  .contains_id(net[0].member_id()));
+    assert_eq!(net[1].accepted_ring_key_revisions_srkr(),
+               vec![new_key.named_revision().clone(),
+                    old_key.named_revision().clone()]);
+}
+
+#[test]
+fn an_unencrypted_ring_cannot_be_given_a_ring_key() {
+    let net = btest::SwimNet::new_rhw(1);
+    assert!(net[0].set_ring_keys_srkw(RingKeys::new(RingKey::new("wolverine")))
+                  .is_err());
+    assert!(net[0].ring_key_srkr().is_none());
+}
@@ -109,7 +109,7 @@ fn six_members_unmeshed_allows_graceful_departure() {
     net.connect_smr(3, 4);
     net.connect_smr(4, 5);
     assert_wait_for_health_of_mlr!(net, [0..6, 0..6], Health::Alive);
-    net[0].set_departed_mlw_smw_rhw();
+    net[0].set_departed_mlw_smw_rhw_srkr();
     net[0].pause();
     assert_wait_for_health_of_mlr!(net, 0, Health::Departed);
 }
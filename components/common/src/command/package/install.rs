@@ -40,12 +40,15 @@ use habitat_core::{self,
                             keys::{Key,
                                    KeyCache,
                                    NamedRevision,
-                                   PublicOriginSigningKey}},
+                                   PublicOriginSigningKey},
+                            Blake2bHash},
                    fs::{cache_key_path,
                         pkg_install_path,
                         AtomicWriter,
                         DEFAULT_CACHED_ARTIFACT_PERMISSIONS},
                    package::{list::temp_package_directory,
+                             metadata::{self,
+                                        MetaFile},
                              FullyQualifiedPackageIdent,
                              Identifiable,
                              PackageArchive,
@@ -417,6 +420,10 @@ fn read_install_hook_status(path: PathBuf) -> Result<Option<i32>> {
     }
 }
 
+/// The origin key revision that signed a verified artifact, and the
+/// hash the artifact's contents verified to.
+type ArtifactSignature = (NamedRevision, Blake2bHash);
+
 struct InstallTask<'a> {
     install_mode:        &'a InstallMode,
     local_package_usage: &'a LocalPackageUsage,
@@ -609,7 +616,8 @@ impl<'a> InstallTask<'a> {
         where T: UIWriter
     {
         // TODO (CM): rename artifact to archive
-        let mut artifact = self.get_cached_artifact(ui, (ident, target), token).await?;
+        let (mut artifact, signature) =
+            self.get_cached_artifact(ui, (ident, target), token).await?;
 
         // Ensure that all transitive dependencies, as well as the
         // original package itself, are cached locally.
@@ -634,11 +642,11 @@ impl<'a> InstallTask<'a> {
         // The package we're actually trying to install goes last; we
         // want to ensure that its dependencies get installed before
         // it does.
-        artifacts_to_install.push(artifact);
+        artifacts_to_install.push((artifact, signature));
 
         // Ensure all uninstalled artifacts get installed
-        for artifact in artifacts_to_install.iter_mut() {
-            self.unpack_artifact(ui, artifact)?;
+        for (artifact, signature) in artifacts_to_install.iter_mut() {
+            self.unpack_artifact(ui, artifact, signature)?;
         }
 
         if self.install_hook_mode != InstallHookMode::Ignore {
@@ -656,12 +664,13 @@ impl<'a> InstallTask<'a> {
     }
 
     /// This ensures the identified package is in the local cache,
-    /// verifies it, and returns a handle to the package's metadata.
+    /// verifies it, and returns a handle to the package's metadata
+    /// along with who signed it and what it hashed to.
     async fn get_cached_artifact<T>(&self,
                                     ui: &mut T,
                                     (ident, target): (&FullyQualifiedPackageIdent, PackageTarget),
                                     token: Option<&str>)
-                                    -> Result<PackageArchive>
+                                    -> Result<(PackageArchive, ArtifactSignature)>
         where T: UIWriter
     {
         if self.is_artifact_cached(ident) {
@@ -675,13 +684,17 @@ impl<'a> InstallTask<'a> {
 
         let mut artifact = PackageArchive::new(self.cached_artifact_path(ident))?;
         ui.status(Status::Verifying, artifact.ident()?)?;
-        self.verify_artifact(ui, ident, token, &mut artifact)
-            .await?;
-        Ok(artifact)
+        let signature = self.verify_artifact(ui, ident, token, &mut artifact)
+                            .await?;
+        Ok((artifact, signature))
     }
 
     /// Adapter function wrapping `PackageArchive::unpack`
-    fn unpack_artifact<T>(&self, ui: &mut T, artifact: &mut PackageArchive) -> Result<()>
+    fn unpack_artifact<T>(&self,
+                          ui: &mut T,
+                          artifact: &mut PackageArchive,
+                          (signer, hash): &ArtifactSignature)
+                          -> Result<()>
         where T: UIWriter
     {
         let ident = &artifact.ident()?;
@@ -695,6 +708,14 @@ impl<'a> InstallTask<'a> {
                 let temp_install_path = &pkg_install_path(ident, Some(temp_dir.path()));
                 artifact.unpack(Some(temp_dir.path()))?;
 
+                // Record who signed the artifact, and what it hashed
+                // to, so the Supervisor can check the installed
+                // package against its trusted keys later on.
+                metadata::write_metafile(temp_install_path, MetaFile::Signer, &signer.to_string())?;
+                metadata::write_metafile(temp_install_path,
+                                         MetaFile::ArtifactHash,
+                                         &hash.to_string())?;
+
                 if let Err(e) = fs::rename(temp_install_path, real_install_path) {
                     // The rename might fail if the real_install_path
                     // was created while we were unpacking. If the
@@ -934,7 +955,7 @@ impl<'a> InstallTask<'a> {
                                 ident: &FullyQualifiedPackageIdent,
                                 token: Option<&str>,
                                 artifact: &mut PackageArchive)
-                                -> Result<()>
+                                -> Result<ArtifactSignature>
         where T: UIWriter
     {
         let artifact_ident = artifact.ident()?;
@@ -960,14 +981,17 @@ impl<'a> InstallTask<'a> {
         let named_revision = artifact::artifact_signer(&artifact.path)?;
 
         // If we don't have the key locally, fetch it from Builder
-        if self.key_cache.public_signing_key(&named_revision).is_err() {
+        if self.key_cache
+               .try_public_signing_key(&named_revision)?
+               .is_none()
+        {
             self.fetch_origin_key(ui, &named_revision, token).await?;
-        };
+        }
 
-        artifact::verify(&artifact.path, &self.key_cache)?;
+        let signature = artifact::verify(&artifact.path, &self.key_cache)?;
 
         debug!("Verified {} signed by {}", ident, named_revision);
-        Ok(())
+        Ok(signature)
     }
 
     fn is_offline(&self) -> bool { self.install_mode == &InstallMode::Offline }
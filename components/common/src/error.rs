@@ -78,6 +78,8 @@ pub enum Error {
         error:         CommandExecutionError,
     },
     InvalidEventStreamToken(String),
+    InvalidHttpListen(String),
+    InvalidSocketMode(String),
     /// Occurs when making lower level IO calls.
     IO(io::Error),
     /// Errors when joining paths :)
@@ -176,6 +178,15 @@ impl fmt::Display for Error {
             Error::InvalidEventStreamToken(ref s) => {
                 format!("Invalid event stream token provided: '{}'", s)
             }
+            Error::InvalidHttpListen(ref s) => {
+                format!("Invalid HTTP gateway listen address '{}': expected IP:PORT or \
+                         unix:///absolute/path",
+                        s)
+            }
+            Error::InvalidSocketMode(ref s) => {
+                format!("Invalid socket mode '{}': expected an octal mode such as 0660",
+                        s)
+            }
             Error::IO(ref err) => format!("{}", err),
             Error::JoinPathsError(ref err) => format!("{}", err),
             Error::NamedPipeTimeoutOnStart(ref group, ref hook, ref err) => {
@@ -79,6 +79,7 @@ bitflags::bitflags! {
         const TRIGGER_ELECTION           = 0b0010_0000_0000;
         const STRUCTOPT_CLI              = 0b0100_0000_0000;
         const NO_NAMED_PIPE_HEALTH_CHECK = 0b1000_0000_0000;
+        const TEST_PANIC_TASK            = 0b1_0000_0000_0000;
     }
 }
 
@@ -95,6 +96,7 @@ lazy_static! {
                  (FeatureFlag::STRUCTOPT_CLI, "HAB_FEAT_STRUCTOPT_CLI"),
                  (FeatureFlag::NO_NAMED_PIPE_HEALTH_CHECK, "HAB_FEAT_NO_NAMED_PIPE_HEALTH_CHECK"),
                  (FeatureFlag::SERVICE_CONFIG_FILES, "HAB_FEAT_SERVICE_CONFIG_FILES"),
+                 (FeatureFlag::TEST_PANIC_TASK, "HAB_FEAT_TEST_PANIC_TASK"),
                  #[cfg(target_family = "unix")]
                  (FeatureFlag::NATIVE_PACKAGE_SUPPORT, "HAB_FEAT_NATIVE_PACKAGE_SUPPORT")];
 
@@ -31,6 +31,17 @@ static ENV_VAR_PREFIX: &str = "HAB";
 /// is deeper than this value crosses into overly complex territory when describing configuration
 /// for a single service.
 static TOML_MAX_MERGE_DEPTH: u16 = 30;
+/// What `Cfg::to_redacted` shows in place of a secret.
+pub const REDACTED_VALUE: &str = "<redacted>";
+/// Parts of configuration key names that mark their values as secrets, for `Cfg::to_redacted`
+/// to hide.
+const SECRET_KEY_FRAGMENTS: &[&str] = &["password",
+                                        "passwd",
+                                        "secret",
+                                        "token",
+                                        "credential",
+                                        "private_key",
+                                        "api_key"];
 #[cfg(unix)]
 pub const CONFIG_PERMISSIONS: u32 = 0o740;
 #[cfg(unix)]
@@ -194,6 +205,43 @@ impl Cfg {
         self.gossip = Some(gossip);
     }
 
+    /// The configuration the service's templates are rendered with,
+    /// but with the value of every key (at any depth) whose name
+    /// looks like it holds a secret replaced by `REDACTED_VALUE`, so
+    /// that it can be shown to an operator.
+    pub fn to_redacted(&self) -> toml::value::Table {
+        let mut table = self.merged();
+        redact_secrets(&mut table);
+        table
+    }
+
+    /// Every layer of the configuration merged, later layers winning:
+    /// default, environment, user, then gossip.
+    fn merged(&self) -> toml::value::Table {
+        let mut table = toml::value::Table::new();
+        if let Some(ref default_cfg) = self.default {
+            if let Err(err) = toml_merge(&mut table, default_cfg) {
+                outputln!("Error merging default-cfg into config, {}", err);
+            }
+        }
+        if let Some(ref env_cfg) = self.environment {
+            if let Err(err) = toml_merge(&mut table, env_cfg) {
+                outputln!("Error merging environment-cfg into config, {}", err);
+            }
+        }
+        if let Some(ref user_cfg) = self.user {
+            if let Err(err) = toml_merge(&mut table, user_cfg) {
+                outputln!("Error merging user-cfg into config, {}", err);
+            }
+        }
+        if let Some(ref gossip_cfg) = self.gossip {
+            if let Err(err) = toml_merge(&mut table, gossip_cfg) {
+                outputln!("Error merging gossip-cfg into config, {}", err);
+            }
+        }
+        table
+    }
+
     /// Returns a subset of the overall configuration which intersects with the given package
     /// exports.
     pub fn to_exported(&self, pkg: &Pkg) -> Result<toml::value::Table> {
@@ -363,29 +411,7 @@ impl Serialize for Cfg {
     fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
         where S: Serializer
     {
-        let mut table = toml::value::Table::new();
-        if let Some(ref default_cfg) = self.default {
-            if let Err(err) = toml_merge(&mut table, default_cfg) {
-                outputln!("Error merging default-cfg into config, {}", err);
-            }
-        }
-        if let Some(ref env_cfg) = self.environment {
-            if let Err(err) = toml_merge(&mut table, env_cfg) {
-                outputln!("Error merging environment-cfg into config, {}", err);
-            }
-        }
-        if let Some(ref user_cfg) = self.user {
-            if let Err(err) = toml_merge(&mut table, user_cfg) {
-                outputln!("Error merging user-cfg into config, {}", err);
-            }
-        }
-        if let Some(ref gossip_cfg) = self.gossip {
-            if let Err(err) = toml_merge(&mut table, gossip_cfg) {
-                outputln!("Error merging gossip-cfg into config, {}", err);
-            }
-        }
-
-        serializer.collect_map(&table)
+        serializer.collect_map(&self.merged())
     }
 }
 
@@ -515,6 +541,27 @@ fn toml_merge_recurse(me: &mut toml::value::Table,
     Ok(())
 }
 
+fn redact_secrets(table: &mut toml::value::Table) {
+    for (key, value) in table.iter_mut() {
+        let key = key.to_lowercase();
+        if SECRET_KEY_FRAGMENTS.iter()
+                               .any(|fragment| key.contains(fragment))
+        {
+            *value = toml::Value::String(REDACTED_VALUE.to_string());
+        } else {
+            redact_secrets_in_value(value);
+        }
+    }
+}
+
+fn redact_secrets_in_value(value: &mut toml::Value) {
+    match value {
+        toml::Value::Table(table) => redact_secrets(table),
+        toml::Value::Array(array) => array.iter_mut().for_each(redact_secrets_in_value),
+        _ => (),
+    }
+}
+
 fn is_toml_value_a_table(key: &str, table: &toml::value::Table) -> bool {
     match table.get(key) {
         None => false,
@@ -954,6 +1001,44 @@ mod test {
         assert_eq!(default_toml, toml::to_string(&cfg).unwrap());
     }
 
+    #[test]
+    fn redacted_config_hides_secrets_at_any_depth() {
+        let concrete_path = TempDir::new().expect("create temp dir");
+        let pkg = TestPkg::new(&concrete_path);
+        let mut cfg = Cfg::new(&pkg, None).expect("Could not create config");
+        cfg.default = Some(toml_from_str(
+            r#"
+            port = 8080
+            api_key = "default-key"
+
+            [datastore]
+            user = "hab"
+            password = "hunter2"
+
+            [[upstreams]]
+            host = "upstream.example"
+            Auth_Token = "letmein"
+            "#,
+        ));
+        cfg.set_gossip(1, toml_from_str(r#"port = 9090"#));
+
+        let expected = toml_from_str(
+                                     r#"
+            port = 9090
+            api_key = "<redacted>"
+
+            [datastore]
+            user = "hab"
+            password = "<redacted>"
+
+            [[upstreams]]
+            host = "upstream.example"
+            Auth_Token = "<redacted>"
+            "#,
+        );
+        assert_eq!(cfg.to_redacted(), expected);
+    }
+
     // env_key: the name of the environment variable the config should
     //     be read from
     // package_name: the name of the package that would read
@@ -426,6 +426,96 @@ impl ToSocketAddrs for HttpListenAddr {
     fn to_socket_addrs(&self) -> io::Result<Self::Iter> { self.0.to_socket_addrs() }
 }
 
+/// Where the HTTP gateway listens: an `HttpListenAddr`, or a Unix
+/// domain socket given as `unix:///path/to/sup.sock`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum HttpListen {
+    Tcp(HttpListenAddr),
+    Unix(PathBuf),
+}
+
+impl HttpListen {
+    const UNIX_SCHEME: &'static str = "unix://";
+
+    /// The TCP address, if the gateway isn't listening on a Unix
+    /// domain socket.
+    pub fn tcp_addr(&self) -> Option<HttpListenAddr> {
+        match self {
+            HttpListen::Tcp(addr) => Some(*addr),
+            HttpListen::Unix(_) => None,
+        }
+    }
+}
+
+impl FromStr for HttpListen {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix(Self::UNIX_SCHEME) {
+            Some(path) => {
+                let path = PathBuf::from(path);
+                if path.is_absolute() && path.file_name().is_some() {
+                    Ok(HttpListen::Unix(path))
+                } else {
+                    Err(Error::InvalidHttpListen(s.to_string()))
+                }
+            }
+            None => Ok(HttpListen::Tcp(s.parse()?)),
+        }
+    }
+}
+
+impl fmt::Display for HttpListen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HttpListen::Tcp(addr) => write!(f, "{}", addr),
+            HttpListen::Unix(path) => write!(f, "{}{}", Self::UNIX_SCHEME, path.display()),
+        }
+    }
+}
+
+impl Default for HttpListen {
+    fn default() -> Self { HttpListen::Tcp(HttpListenAddr::default()) }
+}
+
+impl From<HttpListenAddr> for HttpListen {
+    fn from(addr: HttpListenAddr) -> Self { HttpListen::Tcp(addr) }
+}
+
+habitat_core::impl_try_from_string_and_into_string!(HttpListen);
+
+/// The permissions given to a Unix domain socket the Supervisor
+/// listens on, written in octal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct SocketMode(u32);
+
+impl SocketMode {
+    pub fn bits(self) -> u32 { self.0 }
+}
+
+impl FromStr for SocketMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match u32::from_str_radix(s, 8) {
+            Ok(mode) if mode <= 0o777 => Ok(SocketMode(mode)),
+            _ => Err(Error::InvalidSocketMode(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for SocketMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{:04o}", self.0) }
+}
+
+impl Default for SocketMode {
+    fn default() -> Self { SocketMode(0o660) }
+}
+
+habitat_core::impl_try_from_string_and_into_string!(SocketMode);
+
 habitat_core::env_config_socketaddr!(#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
                                      pub ListenCtlAddr,
                                      HAB_LISTEN_CTL,
@@ -481,6 +571,47 @@ mod tests {
         }
     }
 
+    mod http_listen {
+        use super::*;
+
+        #[test]
+        fn parses_tcp_addresses_and_unix_sockets() {
+            assert_eq!("1.2.3.4:5678".parse::<HttpListen>().unwrap(),
+                       HttpListen::Tcp("1.2.3.4:5678".parse().unwrap()));
+            assert_eq!("unix:///hab/sup/default/sup.sock".parse::<HttpListen>()
+                                                         .unwrap(),
+                       HttpListen::Unix(PathBuf::from("/hab/sup/default/sup.sock")));
+        }
+
+        #[test]
+        fn unix_sockets_need_an_absolute_path() {
+            for s in &["unix://",
+                       "unix:///",
+                       "unix://sup.sock",
+                       "unix://../sup.sock"]
+            {
+                assert!(s.parse::<HttpListen>().is_err(), "{} should not parse", s);
+            }
+        }
+
+        #[test]
+        fn display_round_trips() {
+            for s in &["0.0.0.0:9631", "unix:///tmp/sup.sock"] {
+                assert_eq!(s.parse::<HttpListen>().unwrap().to_string(), *s);
+            }
+        }
+
+        #[test]
+        fn socket_modes_are_octal() {
+            assert_eq!("0600".parse::<SocketMode>().unwrap().bits(), 0o600);
+            assert_eq!("660".parse::<SocketMode>().unwrap().bits(), 0o660);
+            assert_eq!(SocketMode::default().to_string(), "0660");
+            for s in &["", "0689", "01777", "rw-rw----"] {
+                assert!(s.parse::<SocketMode>().is_err(), "{} should not parse", s);
+            }
+        }
+    }
+
     mod env_config {
         use habitat_core::{env::Config as EnvConfig,
                            locked_env_var};
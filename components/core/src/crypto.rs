@@ -18,8 +18,8 @@
 //!     - A key name: `habitat`
 //!     - A key rev: `201603312016`
 //!     - A key name with rev: `habitat-201603312016`
-//!     - A key file: `habitat-201603312016.pub`
-//!     - A key path or fully qualified key path: `/foo/bar/habitat-201603312016.pub`
+//!     - A key file: `habitat-201603312016.sig.pub`
+//!     - A key path or fully qualified key path: `/foo/bar/habitat-201603312016.sig.pub`
 //! - An **Origin** refers to build-time operations, including signing and verification of a
 //! Habitat artifact.
 //! - An **Organization** or **Org** refers to run-time operations such as deploying a package
@@ -52,46 +52,55 @@
 //! ## Origin key
 //!
 //! ```text
-//! <origin_name>-<revision>.pub
+//! <origin_name>-<revision>.sig.pub
 //! <origin_name>-<revision>.sig.key
 //! ```
 //!
 //! Example origin key file names ("sig" keys):
 //!
 //! ```text
-//! habitat-201603312016.pub
+//! habitat-201603312016.sig.pub
 //! habitat-201603312016.sig.key
-//! your_company-201604021516.pub
+//! your_company-201604021516.sig.pub
 //! your_company-201604021516.sig.key
 //! ```
 //!
 //! ## User key
 //!
 //! ```text
-//! <user_name>-<revision>.pub
+//! <user_name>-<revision>.box.pub
 //! <user_name>-<revision>.box.key
 //! ```
 //!
 //! Example user keys ("box" keys)
 //!
 //! ```text
-//! dave-201603312016.pub
-//! some_user-201603312016.pub
+//! dave-201603312016.box.pub
+//! some_user-201603312016.box.pub
 //! ```
 //!
 //! ## Service key
 //!
 //! ```text
-//! <service_name>.<group>@<organization>-<revision>.pub
+//! <service_name>.<group>@<organization>-<revision>.service.pub
 //! <service_name>.<group>@<organization>-<revision>.box.key
 //! ```
 //!
 //! Example Service keys:
 //!
 //! ```text
-//! redis.default@habitat-201603312016.pub
+//! redis.default@habitat-201603312016.service.pub
 //! ```
 //!
+//! ## Legacy public key names
+//!
+//! Public keys of every kind used to share the `.pub` suffix, so a
+//! signing key and an encryption key for the same origin made in the
+//! same second would be saved to the same file. Keys saved that way
+//! are still read, using the version line of the file to tell them
+//! apart, but are never written. `KeyCache::migrate_legacy_filenames`
+//! renames them.
+//!
 //! ## Ring key
 //!
 //! ```text
@@ -213,7 +222,8 @@
 use crate::error::{Error,
                    Result};
 
-/// The suffix on the end of a public sig/box file
+/// The suffix on the end of a public sig/box file, before each kind
+/// of public key had its own suffix. Such files are still read.
 pub const PUBLIC_KEY_SUFFIX: &str = "pub";
 /// The suffix on the end of a public sig file
 pub const SECRET_SIG_KEY_SUFFIX: &str = "sig.key";
@@ -228,6 +238,7 @@ pub const SIG_HASH_TYPE: &str = "BLAKE2b";
 /// at runtime. This is useful for testing.
 pub const CACHE_KEY_PATH_ENV_VAR: &str = "HAB_CACHE_KEY_PATH";
 pub const HART_FORMAT_VERSION: &str = "HART-1";
+pub const DETACHED_SIG_FORMAT_VERSION: &str = "DETACHED-SIG-1";
 pub const BOX_FORMAT_VERSION: &str = "BOX-1";
 pub const ANONYMOUS_BOX_FORMAT_VERSION: &str = "ANONYMOUS-BOX-1";
 
@@ -243,7 +254,11 @@ pub mod dpapi;
 mod hash;
 pub mod keys;
 
-pub use hash::Blake2bHash;
+pub use hash::{hash_directory,
+               hash_directory_filtered,
+               Blake2bHash,
+               Blake2bHasher,
+               HashedEntry};
 
 pub fn init() -> Result<()> { sodiumoxide::init().map_err(|_| Error::SodiumInitFailed) }
 
@@ -261,13 +276,15 @@ pub fn secure_eq<T, U>(t: T, u: U) -> bool
 #[cfg(test)]
 pub mod test_support {
     use crate::{crypto::keys::{Key,
-                               KeyCache},
+                               KeyCache,
+                               KeyRevision},
                 error as herror};
+    use rand::{rngs::StdRng,
+               SeedableRng};
 
     use std::{fs::File,
               io::Read,
               path::PathBuf,
-              thread,
               time::{Duration,
                      Instant}};
     use tempfile::{Builder,
@@ -314,7 +331,15 @@ pub mod test_support {
         (cache, dir)
     }
 
-    pub fn wait_1_sec() { thread::sleep(Duration::from_secs(1)); }
+    /// A random number generator that always produces the same
+    /// sequence, for generating key material reproducibly with the
+    /// `_with` key generation functions.
+    pub fn seeded_rng() -> StdRng { StdRng::seed_from_u64(0) }
+
+    /// A key revision that sorts after `revision(n - 1)`. Passing these
+    /// to the `_with` key generation functions gives keys distinct
+    /// revisions without having to wait for the clock to tick over.
+    pub fn revision(n: u32) -> KeyRevision { format!("2020010100{:04}", n).parse().unwrap() }
 
     /// Helper function to return a specific kind of key read from a
     /// file in our fixtures directory.
@@ -1,12 +1,15 @@
 use crate::{crypto::{keys::{Key,
                             KeyCache,
+                            KeyFile,
                             NamedRevision,
+                            PublicOriginSigningKey,
                             SecretOriginSigningKey},
                      Blake2bHash,
                      HART_FORMAT_VERSION,
                      SIG_HASH_TYPE},
             error::{Error,
-                    Result}};
+                    Result},
+            origin::Origin};
 use std::{fs::File,
           io::{self,
                prelude::*,
@@ -161,15 +164,83 @@ fn artifact_header_and_archive<P>(path: P) -> Result<(ArtifactHeader, impl BufRe
     Ok((header, reader))
 }
 
+/// Which public signing keys in a `KeyCache` may verify a `.hart`
+/// file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerificationPolicy {
+    /// Only the exact key revision named in the file's header.
+    ExactRevision,
+    /// Any revision of the given origin's signing key. The file must
+    /// still name a revision of that origin's key as its signer.
+    ///
+    /// This allows for key rotation, during which files signed with
+    /// either the old or the new revision are legitimate.
+    AnyRevisionOf(Origin),
+    /// Any of the given key revisions, whichever revision the file
+    /// names.
+    AllowList(Vec<NamedRevision>),
+}
+
+impl Default for VerificationPolicy {
+    fn default() -> Self { VerificationPolicy::ExactRevision }
+}
+
 /// Returns a tuple of the `NamedRevision` of the key that verified
 /// the `.hart` file, along with the Blake2b hash of its contents.
 pub fn verify<P>(hart_file_path: P, cache: &KeyCache) -> Result<(NamedRevision, Blake2bHash)>
     where P: AsRef<Path>
 {
-    let (header, mut reader) = artifact_header_and_archive(hart_file_path)?;
-    let key = cache.public_signing_key(&header.signer)?;
-    let hash = key.verify(header.signature.as_slice(), &mut reader)?;
-    Ok((key.named_revision().clone(), hash))
+    verify_with_policy(hart_file_path, cache, &VerificationPolicy::default())
+}
+
+/// Like `verify`, but the `.hart` file may be verified by any of the
+/// keys in the cache that `policy` allows, rather than only the one
+/// it names. The key it names is tried first.
+pub fn verify_with_policy<P>(hart_file_path: P,
+                             cache: &KeyCache,
+                             policy: &VerificationPolicy)
+                             -> Result<(NamedRevision, Blake2bHash)>
+    where P: AsRef<Path>
+{
+    let hart_file_path = hart_file_path.as_ref();
+    let header = get_artifact_header(hart_file_path)?;
+    let mut keys = match policy {
+        VerificationPolicy::ExactRevision => vec![cache.public_signing_key(&header.signer)?],
+        VerificationPolicy::AnyRevisionOf(origin) => {
+            if header.signer.name() != origin.as_ref() {
+                return Err(Error::CryptoError(format!("Habitat artifact was signed \
+                                                       by {}, which is not a key \
+                                                       for the {} origin",
+                                                      header.signer, origin)));
+            }
+            cache.public_signing_keys(origin)?
+        }
+        VerificationPolicy::AllowList(allowed) => {
+            allowed.iter()
+                   .filter_map(|named_revision| {
+                       cache.try_public_signing_key(named_revision).transpose()
+                   })
+                   .collect::<Result<Vec<_>>>()?
+        }
+    };
+    if keys.is_empty() {
+        return Err(Error::KeyNotFound { name:     header.signer.to_string(),
+                                        key_type: PublicOriginSigningKey::key_type(), });
+    }
+    // `false` sorts first, and the sort is stable.
+    keys.sort_by_key(|key| key.named_revision() != &header.signer);
+
+    let mut first_error = None;
+    for key in keys {
+        let (_, reader) = artifact_header_and_archive(hart_file_path)?;
+        match key.verify_reader(header.signature.as_slice(), reader) {
+            Ok(hash) => return Ok((key.named_revision().clone(), hash)),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    Err(first_error.expect("at least one key was tried"))
 }
 
 /// Parse a HART file (referred to by filesystem path) to discover the
@@ -420,4 +491,114 @@ mod test {
             artifact_signer(hart_path).unwrap();
         }
     }
+
+    mod verify_with_policy {
+        use super::*;
+        use crate::crypto::keys::generate_signing_key_pair_with;
+
+        /// Sign some content with a revision of the "unicorn" origin's
+        /// key made from a fixed seed, so that every revision made
+        /// this way has the same key material.
+        fn signed_by_revision(dir: &Path, n: u32) -> std::path::PathBuf {
+            let origin = "unicorn".parse().unwrap();
+            let (_, secret) =
+                generate_signing_key_pair_with(&origin, &mut seeded_rng(), revision(n));
+            let src = dir.join("src.in");
+            let dst = dir.join(format!("signed-{}.hart", n));
+            std::fs::write(&src, b"hearty goodness").unwrap();
+            sign(&src, &dst, &secret).unwrap();
+            dst
+        }
+
+        /// Put a revision of the "unicorn" origin's public key, with
+        /// the same key material as those used by `signed_by_revision`,
+        /// into the cache.
+        fn cache_revision(cache: &KeyCache, n: u32) -> NamedRevision {
+            let origin = "unicorn".parse().unwrap();
+            let (public, _) =
+                generate_signing_key_pair_with(&origin, &mut seeded_rng(), revision(n));
+            cache.write_key(&public).unwrap();
+            public.named_revision().clone()
+        }
+
+        #[test]
+        fn the_exact_revision_is_required_by_default() {
+            let (cache, dir) = new_cache();
+            let hart = signed_by_revision(dir.path(), 1);
+            cache_revision(&cache, 2);
+
+            assert_eq!(VerificationPolicy::default(),
+                       VerificationPolicy::ExactRevision);
+            match verify(&hart, &cache) {
+                Err(Error::KeyNotFound { name, .. }) => assert_eq!(name, "unicorn-20200101000001"),
+                other => panic!("Expected KeyNotFound, got {:?}", other),
+            }
+        }
+
+        #[test]
+        fn any_revision_of_the_origin() {
+            let (cache, dir) = new_cache();
+            let hart = signed_by_revision(dir.path(), 1);
+            let newer = cache_revision(&cache, 2);
+            let policy = VerificationPolicy::AnyRevisionOf("unicorn".parse().unwrap());
+
+            let (verified_by, hash) = verify_with_policy(&hart, &cache, &policy).unwrap();
+            assert_eq!(verified_by, newer);
+            assert_eq!(hash, Blake2bHash::from_bytes(b"hearty goodness"));
+        }
+
+        #[test]
+        fn any_revision_prefers_the_revision_named_in_the_artifact() {
+            let (cache, dir) = new_cache();
+            let hart = signed_by_revision(dir.path(), 1);
+            let named = cache_revision(&cache, 1);
+            cache_revision(&cache, 2);
+            let policy = VerificationPolicy::AnyRevisionOf("unicorn".parse().unwrap());
+
+            let (verified_by, _) = verify_with_policy(&hart, &cache, &policy).unwrap();
+            assert_eq!(verified_by, named);
+        }
+
+        #[test]
+        fn any_revision_of_another_origin_fails() {
+            let (cache, dir) = new_cache();
+            let hart = signed_by_revision(dir.path(), 1);
+            cache_revision(&cache, 1);
+            let policy = VerificationPolicy::AnyRevisionOf("pegasus".parse().unwrap());
+
+            let err = verify_with_policy(&hart, &cache, &policy).unwrap_err();
+            assert!(err.to_string().contains("not a key for the pegasus origin"));
+        }
+
+        #[test]
+        fn revisions_with_other_key_material_fail() {
+            let (cache, dir) = new_cache();
+            let hart = signed_by_revision(dir.path(), 1);
+            let origin = "unicorn".parse().unwrap();
+            cache.new_signing_pair(&origin).unwrap();
+            let policy = VerificationPolicy::AnyRevisionOf(origin);
+
+            let err = verify_with_policy(&hart, &cache, &policy).unwrap_err();
+            assert!(err.to_string().contains("Verification failed"));
+        }
+
+        #[test]
+        fn allowed_revisions() {
+            let (cache, dir) = new_cache();
+            let hart = signed_by_revision(dir.path(), 1);
+            let newer = cache_revision(&cache, 2);
+            let older: NamedRevision = "unicorn-20200101000001".parse().unwrap();
+
+            let policy = VerificationPolicy::AllowList(vec![older.clone(), newer.clone()]);
+            let (verified_by, _) = verify_with_policy(&hart, &cache, &policy).unwrap();
+            assert_eq!(verified_by, newer);
+
+            // The only allowed revision isn't in the cache.
+            let policy = VerificationPolicy::AllowList(vec![older]);
+            match verify_with_policy(&hart, &cache, &policy) {
+                Err(Error::KeyNotFound { .. }) => {}
+                other => panic!("Expected KeyNotFound, got {:?}", other),
+            }
+        }
+    }
 }
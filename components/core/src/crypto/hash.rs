@@ -4,17 +4,28 @@ use blake2b_simd::{Params,
                    State};
 use hex::FromHex;
 use serde::Serialize;
-use std::{convert::TryInto,
+use std::{collections::{BTreeMap,
+                        VecDeque},
+          convert::{TryFrom,
+                    TryInto},
           fmt,
-          fs::File,
-          io::{BufReader,
-               Read},
-          path::Path,
-          str::FromStr};
-
-/// When hashing byte streams, we'll read 1KB at a time, adding this to the
-/// internal hashing state as we compute the final digest.
-const BUF_SIZE: usize = 1024;
+          fs::{self,
+               File},
+          io::{self,
+               Read,
+               Write},
+          path::{Path,
+                 PathBuf},
+          str::FromStr,
+          sync::atomic::{AtomicUsize,
+                         Ordering},
+          thread};
+
+/// When hashing byte streams, we'll read up to 128KB at a time,
+/// adding this to the internal hashing state as we compute the final
+/// digest. Reading in large chunks keeps the number of reads down
+/// for big files, like artifacts.
+const BUF_SIZE: usize = 128 * 1024;
 
 /// The size of our Blake2b hash digests (32 bytes)
 const HASH_DIGEST_SIZE: usize = 32;
@@ -37,9 +48,26 @@ impl Blake2bHash {
     pub fn from_file<P>(filename: P) -> Result<Self>
         where P: AsRef<Path>
     {
-        let file = File::open(filename.as_ref())?;
-        let mut reader = BufReader::new(file);
-        Self::from_reader(&mut reader)
+        let mut file = File::open(filename.as_ref())?;
+        // There's no need for a buffer any bigger than the file
+        // (plus a byte to find its end with), which keeps hashing lots
+        // of small files, as `hash_directory` does, cheap.
+        let len = file.metadata()?.len();
+        let buf_size = usize::try_from(len).map_or(BUF_SIZE, |len| len.saturating_add(1))
+                                           .min(BUF_SIZE);
+        hash_with_buffer(&mut file, &mut vec![0u8; buf_size])
+    }
+
+    /// Calculate the BLAKE2b hash of the contents of a file, without
+    /// blocking the async runtime while doing so.
+    pub async fn from_file_async<P>(filename: P) -> Result<Self>
+        where P: AsRef<Path>
+    {
+        let filename = filename.as_ref().to_path_buf();
+        match tokio::task::spawn_blocking(move || Self::from_file(filename)).await {
+            Ok(result) => result,
+            Err(e) => Err(io::Error::new(io::ErrorKind::Other, e).into()),
+        }
     }
 
     /// Calculate the BLAKE2b hash of a sequence of bytes.
@@ -51,22 +79,76 @@ impl Blake2bHash {
         state.finalize().into()
     }
 
+    /// Check that the contents of a file hash to `expected`, returning
+    /// `Error::FileHashMismatch` with both hashes if they don't. The
+    /// hashes are compared in constant time.
+    pub fn verify_file<P>(filename: P, expected: &Blake2bHash) -> Result<()>
+        where P: AsRef<Path>
+    {
+        let actual = Self::from_file(filename.as_ref())?;
+        if actual == *expected {
+            Ok(())
+        } else {
+            Err(Error::FileHashMismatch { path: filename.as_ref().to_path_buf(),
+                                          expected: expected.clone(),
+                                          actual })
+        }
+    }
+
     /// Calculate the BLAKE2b hash of a Read implentation.
     pub fn from_reader(reader: &mut dyn Read) -> Result<Self> {
-        let mut state = hash_state();
+        hash_with_buffer(reader, &mut vec![0u8; BUF_SIZE])
+    }
+}
 
-        let mut buf = [0u8; BUF_SIZE];
-        loop {
-            let bytes_read = reader.read(&mut buf)?;
-            if bytes_read == 0 {
-                break;
+/// Hash everything `reader` produces, reading it into `buf` one chunk
+/// at a time.
+fn hash_with_buffer(reader: &mut dyn Read, buf: &mut [u8]) -> Result<Blake2bHash> {
+    let mut state = hash_state();
+    loop {
+        match reader.read(buf) {
+            Ok(0) => break,
+            Ok(bytes_read) => {
+                state.update(&buf[..bytes_read]);
             }
-            let chunk = &buf[0..bytes_read];
-            state.update(chunk);
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
         }
+    }
+    Ok(state.finalize().into())
+}
+
+/// Calculates a `Blake2bHash` a piece at a time, for data that isn't
+/// all available at once (while it's being downloaded, say).
+///
+/// As a `Write` implementation, it can be handed to `io::copy` (or
+/// anything else that writes) to hash whatever passes through it.
+#[derive(Clone, Debug)]
+pub struct Blake2bHasher {
+    state: State,
+}
+
+impl Blake2bHasher {
+    pub fn new() -> Self { Blake2bHasher { state: hash_state(), } }
+
+    /// Add `data` to what's been hashed so far.
+    pub fn update(&mut self, data: &[u8]) { self.state.update(data); }
 
-        Ok(state.finalize().into())
+    /// The hash of everything added so far.
+    pub fn finalize(&self) -> Blake2bHash { self.state.finalize().into() }
+}
+
+impl Default for Blake2bHasher {
+    fn default() -> Self { Self::new() }
+}
+
+impl Write for Blake2bHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
     }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
 }
 
 // We *could* just wrap the `blake2b_simd::Hash` directly in our
@@ -145,6 +227,120 @@ impl Serialize for Blake2bHash {
 
 ////////////////////////////////////////////////////////////////////////
 
+/// What `hash_directory` records for each entry it finds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HashedEntry {
+    /// The hash of the contents of a file.
+    File(Blake2bHash),
+    /// The target of a symlink. Symlinks are recorded as they are,
+    /// rather than followed.
+    Symlink(PathBuf),
+}
+
+impl HashedEntry {
+    /// Hash the file at `path`, or read the target of the symlink at
+    /// `path`.
+    pub fn of<P>(path: P) -> Result<Self>
+        where P: AsRef<Path>
+    {
+        let path = path.as_ref();
+        if fs::symlink_metadata(path)?.file_type().is_symlink() {
+            Ok(HashedEntry::Symlink(fs::read_link(path)?))
+        } else {
+            Ok(HashedEntry::File(Blake2bHash::from_file(path)?))
+        }
+    }
+}
+
+/// Hash every file beneath the directory `path`, using at most
+/// `concurrency` threads to do so.
+///
+/// The results are keyed by the full path of each file (that is,
+/// `path` joined with its location in the tree), and so are always in
+/// the same order. Symlinks are recorded by their target rather than
+/// followed. Failing to read a file, or a directory beneath `path`,
+/// shows up as an error for that path in the results; only failing to
+/// read `path` itself is an error for the walk as a whole.
+pub fn hash_directory<P>(path: P,
+                         concurrency: usize)
+                         -> Result<BTreeMap<PathBuf, Result<HashedEntry>>>
+    where P: AsRef<Path>
+{
+    hash_directory_filtered(path, concurrency, |_, _| true)
+}
+
+/// Like `hash_directory`, but only for the entries beneath `path` that
+/// `filter` holds for. `filter` is given the full path and type of each
+/// entry as it's found; directories it doesn't hold for are skipped
+/// along with everything in them, without ever being read.
+pub fn hash_directory_filtered<P, F>(path: P,
+                                     concurrency: usize,
+                                     filter: F)
+                                     -> Result<BTreeMap<PathBuf, Result<HashedEntry>>>
+    where P: AsRef<Path>,
+          F: Fn(&Path, &fs::FileType) -> bool
+{
+    let root = path.as_ref();
+    let mut results = BTreeMap::new();
+    let mut files = Vec::new();
+
+    let mut dirs = VecDeque::new();
+    dirs.push_back(root.to_path_buf());
+    while let Some(dir) = dirs.pop_front() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if dir == root => return Err(e.into()),
+            Err(e) => {
+                results.insert(dir, Err(e.into()));
+                continue;
+            }
+        };
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    results.insert(dir.clone(), Err(e.into()));
+                    continue;
+                }
+            };
+            // `DirEntry::file_type` doesn't follow symlinks, so
+            // symlinks to directories are recorded, not descended into.
+            match entry.file_type() {
+                Ok(file_type) if !filter(&entry.path(), &file_type) => {}
+                Ok(file_type) if file_type.is_dir() => dirs.push_back(entry.path()),
+                Ok(_) => files.push(entry.path()),
+                Err(e) => {
+                    results.insert(entry.path(), Err(e.into()));
+                }
+            }
+        }
+    }
+
+    let next = AtomicUsize::new(0);
+    let workers = concurrency.max(1).min(files.len());
+    thread::scope(|scope| {
+        let workers = (0..workers).map(|_| scope.spawn(|| hash_files(&files, &next)))
+                                  .collect::<Vec<_>>();
+        for worker in workers {
+            results.extend(worker.join().expect("Directory hashing thread panicked"));
+        }
+    });
+
+    Ok(results)
+}
+
+/// Hash files from `files`, taking the next one not yet taken by
+/// another worker until there are none left.
+fn hash_files(files: &[PathBuf], next: &AtomicUsize) -> Vec<(PathBuf, Result<HashedEntry>)> {
+    let mut hashed = Vec::new();
+    while let Some(file) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+        hashed.push((file.clone(), HashedEntry::of(file)));
+    }
+    hashed
+}
+
+////////////////////////////////////////////////////////////////////////
+
 /// Initialize the hasher state. In particular, set the digest length
 /// to 32 bytes. All hashing functions must use this to ensure
 /// consistency!
@@ -227,6 +423,144 @@ mod tests {
         assert_eq!(computed, expected);
     }
 
+    /// A file of `len` bytes that aren't all the same, along with the
+    /// directory it's in.
+    fn file_of_len(len: usize) -> (tempfile::TempDir, PathBuf, Vec<u8>) {
+        let dir = tempfile::Builder::new().prefix("hash_file")
+                                          .tempdir()
+                                          .unwrap();
+        let path = dir.path().join("data");
+        let content = (0..len).map(|n| (n % 251) as u8).collect::<Vec<_>>();
+        fs::write(&path, &content).unwrap();
+        (dir, path, content)
+    }
+
+    #[test]
+    fn hash_file_matches_hashing_the_same_bytes() {
+        for len in &[0,
+                     1,
+                     BUF_SIZE - 1,
+                     BUF_SIZE,
+                     BUF_SIZE + 1,
+                     BUF_SIZE * 3 + 17]
+        {
+            let (_dir, path, content) = file_of_len(*len);
+            assert_eq!(Blake2bHash::from_file(&path).unwrap(),
+                       Blake2bHash::from_bytes(&content),
+                       "Hashes differ for a file of {} bytes",
+                       len);
+            assert_eq!(Blake2bHash::from_reader(&mut content.as_slice()).unwrap(),
+                       Blake2bHash::from_bytes(&content));
+        }
+    }
+
+    #[test]
+    fn empty_file_hash() {
+        let (_dir, path, _) = file_of_len(0);
+        let expected =
+            hash_from_hex("0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8");
+        assert_eq!(Blake2bHash::from_file(&path).unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn hash_file_async_matches_hash_file() {
+        let (_dir, path, _) = file_of_len(BUF_SIZE * 2 + 5);
+        assert_eq!(Blake2bHash::from_file_async(&path).await.unwrap(),
+                   Blake2bHash::from_file(&path).unwrap());
+        assert!(Blake2bHash::from_file_async(path.with_file_name("not-there")).await
+                                                                              .is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "functional")]
+    fn hash_file_gigabyte_timing() {
+        use std::{io::{BufReader,
+                       Write},
+                  time::Instant};
+
+        let dir = tempfile::Builder::new().prefix("large_file")
+                                          .tempdir()
+                                          .unwrap();
+        let path = dir.path().join("gigabyte");
+        let chunk = (0..1024 * 1024).map(|n| (n % 251) as u8)
+                                    .collect::<Vec<_>>();
+        let mut file = File::create(&path).unwrap();
+        for _ in 0..1024 {
+            file.write_all(&chunk).unwrap();
+        }
+        drop(file);
+
+        // How files used to be hashed: 1KB at a time, through a
+        // `BufReader`.
+        let start = Instant::now();
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        let mut state = hash_state();
+        let mut buf = [0u8; 1024];
+        loop {
+            let bytes_read = reader.read(&mut buf).unwrap();
+            if bytes_read == 0 {
+                break;
+            }
+            state.update(&buf[..bytes_read]);
+        }
+        let old: Blake2bHash = state.finalize().into();
+        let old_elapsed = start.elapsed();
+
+        let start = Instant::now();
+        let new = Blake2bHash::from_file(&path).unwrap();
+        let new_elapsed = start.elapsed();
+
+        println!("Hashed 1GB in {:?} with 1KB reads, and {:?} with {}KB reads",
+                 old_elapsed,
+                 new_elapsed,
+                 BUF_SIZE / 1024);
+        assert_eq!(old, new);
+    }
+
+    #[test]
+    fn hashing_incrementally_matches_hashing_all_at_once() {
+        let message = "supercalifragilisticexpialadocious".as_bytes();
+        for chunk_size in 1..=message.len() {
+            let mut hasher = Blake2bHasher::new();
+            for chunk in message.chunks(chunk_size) {
+                hasher.update(chunk);
+            }
+            assert_eq!(hasher.finalize(), Blake2bHash::from_bytes(message));
+        }
+        assert_eq!(Blake2bHasher::new().finalize(), Blake2bHash::from_bytes(""));
+    }
+
+    #[test]
+    fn hasher_can_be_copied_into() {
+        let (_dir, path, content) = file_of_len(BUF_SIZE + 1);
+        let mut hasher = Blake2bHasher::new();
+        let copied = io::copy(&mut File::open(&path).unwrap(), &mut hasher).unwrap();
+        assert_eq!(copied, content.len() as u64);
+        assert_eq!(hasher.finalize(), Blake2bHash::from_file(&path).unwrap());
+    }
+
+    #[test]
+    fn verify_file_matching() {
+        let (_dir, path, content) = file_of_len(1000);
+        Blake2bHash::verify_file(&path, &Blake2bHash::from_bytes(&content)).unwrap();
+    }
+
+    #[test]
+    fn verify_file_mismatch() {
+        let (_dir, path, content) = file_of_len(1000);
+        let wrong = Blake2bHash::from_bytes("something else");
+        match Blake2bHash::verify_file(&path, &wrong) {
+            Err(Error::FileHashMismatch { path: p,
+                                          expected,
+                                          actual, }) => {
+                assert_eq!(p, path);
+                assert_eq!(expected, wrong);
+                assert_eq!(actual, Blake2bHash::from_bytes(&content));
+            }
+            other => panic!("Expected FileHashMismatch, got {:?}", other),
+        }
+    }
+
     #[test]
     fn eq() {
         let zeroes = Blake2bHash { digest: [0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8,
@@ -296,4 +630,129 @@ mod tests {
         let hash: Blake2bHash = input.parse().unwrap();
         serde_test::assert_ser_tokens(&hash, &[serde_test::Token::Str(input)]);
     }
+
+    mod hash_directory {
+        use super::*;
+        use tempfile::TempDir;
+
+        /// A directory with a few files at various depths.
+        fn tree() -> TempDir {
+            let dir = tempfile::Builder::new().prefix("hash_directory")
+                                              .tempdir()
+                                              .unwrap();
+            fs::create_dir_all(dir.path().join("a/b/c")).unwrap();
+            fs::create_dir_all(dir.path().join("empty")).unwrap();
+            fs::write(dir.path().join("top.txt"), "top").unwrap();
+            fs::write(dir.path().join("a/middle.txt"), "middle").unwrap();
+            for n in 0..50 {
+                fs::write(dir.path().join(format!("a/b/c/{}.txt", n)), n.to_string()).unwrap();
+            }
+            dir
+        }
+
+        #[test]
+        fn hashes_every_file_in_the_tree() {
+            let dir = tree();
+            let hashes = hash_directory(dir.path(), 4).unwrap();
+
+            assert_eq!(hashes.len(), 52);
+            for (path, entry) in &hashes {
+                assert_eq!(entry.as_ref().unwrap(),
+                           &HashedEntry::File(Blake2bHash::from_file(path).unwrap()));
+            }
+            assert_eq!(hashes[&dir.path().join("top.txt")].as_ref().unwrap(),
+                       &HashedEntry::File(Blake2bHash::from_bytes("top")));
+        }
+
+        #[test]
+        fn results_do_not_depend_on_concurrency() {
+            let dir = tree();
+            let serial = hash_directory(dir.path(), 1).unwrap();
+            let parallel = hash_directory(dir.path(), 16).unwrap();
+
+            assert_eq!(serial.keys().collect::<Vec<_>>(),
+                       parallel.keys().collect::<Vec<_>>());
+            for (path, entry) in serial {
+                assert_eq!(entry.unwrap(), *parallel[&path].as_ref().unwrap());
+            }
+        }
+
+        #[test]
+        fn missing_directory_is_an_error() {
+            let dir = tree();
+            assert!(hash_directory(dir.path().join("not-there"), 4).is_err());
+        }
+
+        #[test]
+        #[cfg(unix)]
+        fn symlinks_are_recorded_not_followed() {
+            use std::os::unix::fs::symlink;
+
+            let dir = tree();
+            symlink("top.txt", dir.path().join("link-to-file")).unwrap();
+            symlink("a", dir.path().join("link-to-dir")).unwrap();
+            symlink("nowhere", dir.path().join("dangling")).unwrap();
+            let hashes = hash_directory(dir.path(), 4).unwrap();
+
+            assert_eq!(hashes.len(), 55);
+            for (name, target) in &[("link-to-file", "top.txt"),
+                                    ("link-to-dir", "a"),
+                                    ("dangling", "nowhere")]
+            {
+                assert_eq!(hashes[&dir.path().join(name)].as_ref().unwrap(),
+                           &HashedEntry::Symlink(PathBuf::from(target)));
+            }
+        }
+
+        #[test]
+        #[cfg(unix)]
+        fn unreadable_files_are_errors_for_just_that_file() {
+            use std::os::unix::fs::PermissionsExt;
+
+            let dir = tree();
+            let unreadable = dir.path().join("a/middle.txt");
+            fs::set_permissions(&unreadable, fs::Permissions::from_mode(0o000)).unwrap();
+            if File::open(&unreadable).is_ok() {
+                // Permissions don't stop root from reading anything.
+                return;
+            }
+            let hashes = hash_directory(dir.path(), 4).unwrap();
+
+            assert_eq!(hashes.len(), 52);
+            assert!(hashes[&unreadable].is_err());
+            assert_eq!(hashes.values().filter(|entry| entry.is_ok()).count(), 51);
+        }
+
+        #[test]
+        fn filtered_out_directories_are_not_read() {
+            let dir = tree();
+            let skipped = dir.path().join("a/b");
+            let hashes = hash_directory_filtered(dir.path(), 4, |path, file_type| {
+                             path != skipped && (file_type.is_dir() || path.ends_with("top.txt"))
+                         }).unwrap();
+
+            assert_eq!(hashes.keys().collect::<Vec<_>>(),
+                       vec![&dir.path().join("top.txt")]);
+        }
+
+        #[test]
+        #[cfg(unix)]
+        fn unreadable_directories_can_be_filtered_out() {
+            use std::os::unix::fs::PermissionsExt;
+
+            let dir = tree();
+            let unreadable = dir.path().join("a/b");
+            fs::set_permissions(&unreadable, fs::Permissions::from_mode(0o000)).unwrap();
+            if fs::read_dir(&unreadable).is_ok() {
+                // Permissions don't stop root from reading anything.
+                return;
+            }
+            let hashes = hash_directory_filtered(dir.path(), 4, |path, _| path != unreadable);
+            fs::set_permissions(&unreadable, fs::Permissions::from_mode(0o755)).unwrap();
+
+            let hashes = hashes.unwrap();
+            assert_eq!(hashes.len(), 2);
+            assert!(hashes.values().all(Result::is_ok));
+        }
+    }
 }
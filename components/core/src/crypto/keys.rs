@@ -1,8 +1,15 @@
 use crate::{error::Error,
             fs::Permissions};
-use chrono::Utc;
+use chrono::{DateTime,
+             NaiveDate,
+             SecondsFormat,
+             TimeZone,
+             Utc};
 use regex::Regex;
+use serde::{Serialize,
+            Serializer};
 use std::{self,
+          cmp::Ordering,
           fmt,
           ops::Deref,
           path::PathBuf,
@@ -10,22 +17,36 @@ use std::{self,
           str::FromStr};
 
 lazy_static::lazy_static! {
-    static ref NAME_WITH_REV_RE: Regex = Regex::new(r"\A(?P<name>.+)-(?P<rev>\d{14})\z").unwrap();
     static ref KEYFILE_RE: Regex =
         Regex::new(r"\A(?P<name>.+)-(?P<rev>\d{14})\.(?P<suffix>[a-z]+(\.[a-z]+)?)\z").unwrap();
 }
 
 #[macro_use]
 mod util;
+mod async_key_cache;
 mod cache;
+mod cache_watcher;
+mod cached_key_cache;
 mod encryption;
 mod ring_key;
 mod signing;
 
-pub use cache::KeyCache;
+pub use async_key_cache::AsyncKeyCache;
+pub use cache::{BadPermissions,
+                CacheReport,
+                CachedKeyInfo,
+                KeyCache,
+                KeyKind,
+                MisnamedKey,
+                UnparseableKey};
+pub use cache_watcher::{KeyCacheEvent,
+                        KeyCacheWatcher};
+pub use cached_key_cache::CachedKeyCache;
 pub use encryption::*;
 pub use ring_key::RingKey;
 pub use signing::{generate_signing_key_pair,
+                  generate_signing_key_pair_with,
+                  DetachedSignature,
                   PublicOriginSigningKey,
                   SecretOriginSigningKey};
 
@@ -66,6 +87,14 @@ pub trait KeyFile: Key {
     /// The file extension to use when exporting this key to disk.
     fn extension() -> &'static str;
 
+    /// The file extension keys of this type were saved with before
+    /// each kind of key had its own, if it differs from `extension`.
+    /// Keys saved that way are still read, but never written.
+    fn legacy_extension() -> Option<&'static str> { None }
+
+    /// The name of this kind of key, for use in error messages.
+    fn key_type() -> &'static str;
+
     /// Given a `NamedRevision`, return the name of the file a key of
     /// this type with that identifier would be saved as in the key
     /// cache.
@@ -80,6 +109,15 @@ pub trait KeyFile: Key {
         PathBuf::from(format!("{}.{}", named_revision, Self::extension()))
     }
 
+    /// When this key stops being valid for use, if ever.
+    fn expiration(&self) -> Option<DateTime<Utc>>;
+
+    /// Whether this key has expired as of `now`.
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expiration()
+            .map_or(false, |expiration| expiration <= now)
+    }
+
     /// Same as `filename`, but for a specific key.
     fn own_filename(&self) -> PathBuf { Self::filename(self.named_revision()) }
 
@@ -92,20 +130,85 @@ pub trait KeyFile: Key {
     /// logging or other output.
     fn to_key_string(&self) -> String {
         let k = self.key();
-        format!("{}\n{}\n\n{}",
-                Self::version(),
-                self.named_revision(),
-                &crate::base64::encode(k))
+        let mut content = format!("{}\n{}\n\n{}",
+                                  Self::version(),
+                                  self.named_revision(),
+                                  &crate::base64::encode(k));
+        if let Some(expiration) = self.expiration() {
+            content.push_str(&format!("\n{}{}",
+                                      EXPIRATION_PREFIX,
+                                      expiration.to_rfc3339_opts(SecondsFormat::Secs, true)));
+        }
+        content
     }
 }
 
+/// Marks the optional line following the key material of a key file
+/// that records when the key expires.
+const EXPIRATION_PREFIX: &str = "expires: ";
+
+/// Parses the expiration, if any, out of the lines of a key file that
+/// follow its key material. Any other lines are ignored, as they
+/// always have been.
+pub(crate) fn parse_expiration<'a, I>(lines: I) -> result::Result<Option<DateTime<Utc>>, Error>
+    where I: Iterator<Item = &'a str>
+{
+    for line in lines {
+        if let Some(value) = line.strip_prefix(EXPIRATION_PREFIX.trim_end()) {
+            let value = value.trim();
+            return match DateTime::parse_from_rfc3339(value) {
+                Ok(expiration) => Ok(Some(expiration.with_timezone(&Utc))),
+                Err(_) => Err(Error::CryptoError(format!("Invalid key expiration: {}", value))),
+            };
+        }
+    }
+    Ok(None)
+}
+
+/// Encodings a public key can be exported in, for tools other than
+/// Habitat. Only the key material is exported; the format returned
+/// by `KeyFile::to_key_string` remains the one keys are saved in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PublicKeyFormat {
+    /// The bare key material.
+    Raw,
+    /// The bare key material, in standard base64.
+    Base64,
+    /// A PEM encoded SubjectPublicKeyInfo, as read by OpenSSL.
+    Pem,
+}
+
 ////////////////////////////////////////////////////////////////////////
 
+/// Check that `name` can safely name a key. Keys are saved in files
+/// named for them, so names are limited to the characters origins,
+/// users, and service groups (`service.group@org`) are made of, and
+/// can never name a path outside of the key cache.
+pub(crate) fn validate_key_name(name: &str) -> result::Result<(), Error> {
+    let invalid = |reason: String| {
+        Err(Error::InvalidKeyName { name: name.to_string(),
+                                    reason })
+    };
+    match name.chars().next() {
+        None => return invalid("it is empty".to_string()),
+        Some(c) if !(c.is_ascii_alphanumeric() || c == '_') => {
+            return invalid(format!("it begins with '{}'", c));
+        }
+        Some(_) => {}
+    }
+    match name.chars()
+              .find(|&c| !(c.is_ascii_alphanumeric() || "_-.@".contains(c)))
+    {
+        Some(c) => invalid(format!("it contains '{}'", c)),
+        None => Ok(()),
+    }
+}
+
 /// The combination of a key name and a revision timestamp. For any
 /// given type of Habitat key, this will uniquely identify that key,
 /// allowing it to be retrieved from a local key cache or from the
 /// Builder API.
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct NamedRevision {
     name:     String,
     revision: KeyRevision,
@@ -126,39 +229,50 @@ impl NamedRevision {
     pub fn name(&self) -> &String { &self.name }
 
     pub fn revision(&self) -> &KeyRevision { &self.revision }
+
+    /// The moment this key was created.
+    pub fn revision_timestamp(&self) -> DateTime<Utc> { self.revision.timestamp() }
+}
+
+impl PartialOrd for NamedRevision {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for NamedRevision {
+    /// Orders chronologically by revision, and then by name.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.revision
+            .cmp(&other.revision)
+            .then_with(|| self.name.cmp(&other.name))
+    }
 }
 
 impl FromStr for NamedRevision {
     type Err = Error;
 
+    /// Parses a `{name}-{revision}` string. Names may themselves
+    /// contain dashes, so the revision is whatever follows the last
+    /// one, and must be a valid `KeyRevision`.
     fn from_str(value: &str) -> result::Result<Self, Self::Err> {
-        let caps = match NAME_WITH_REV_RE.captures(value) {
-            Some(c) => c,
-            None => {
-                let msg = format!("Cannot parse named revision '{}'", value);
-                return Err(Error::CryptoError(msg));
-            }
-        };
-        let name = match caps.name("name") {
-            Some(r) => r.as_str().to_string(),
-            None => {
-                let msg = format!("Cannot parse name from '{}'", value);
+        let (name, revision) = match value.rsplit_once('-') {
+            Some((name, revision)) if !name.is_empty() => (name, revision),
+            _ => {
+                let msg = format!("Cannot parse named revision '{}': expected a name and a \
+                                   revision separated by '-'",
+                                  value);
                 return Err(Error::CryptoError(msg));
             }
         };
-        let revision = match caps.name("rev") {
-            // TODO (CM): This is a bit of an awkward constructor at the
-            // moment, but we'll allow it as we've already validated this
-            // with the larger regex. It would be nice to harmonize
-            // this a bit more, but it's not the worst situation.
-            Some(r) => KeyRevision(r.as_str().to_string()),
-            None => {
-                let msg = format!("Cannot parse revision from '{}'", value);
+        let revision = match KeyRevision::validate(revision) {
+            Ok(()) => KeyRevision(revision.to_string()),
+            Err(reason) => {
+                let msg = format!("Cannot parse named revision '{}': {}", value, reason);
                 return Err(Error::CryptoError(msg));
             }
         };
 
-        Ok(NamedRevision { name, revision })
+        Ok(NamedRevision { name: name.to_string(),
+                           revision })
     }
 }
 
@@ -168,10 +282,22 @@ impl fmt::Display for NamedRevision {
     }
 }
 
+impl Serialize for NamedRevision {
+    /// Serializes a `NamedRevision` according to its `Display`
+    /// implementation (i.e., `{name}-{revision}`).
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.collect_str(self)
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////
 
-/// A timestamp string used to identify Habitat keys.
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// A timestamp string used to identify Habitat keys. Being of a
+/// fixed-width format, revisions order chronologically. Every
+/// `KeyRevision` is a real moment in time, in UTC.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct KeyRevision(String);
 
 impl KeyRevision {
@@ -181,8 +307,66 @@ impl KeyRevision {
     ///
     /// Only visible in this crate because nothing outside should be
     /// directly generating these.
-    pub(crate) fn new() -> KeyRevision {
-        KeyRevision(Utc::now().format("%Y%m%d%H%M%S").to_string())
+    pub(crate) fn new() -> KeyRevision { Self::from_timestamp(Utc::now()) }
+
+    /// Like `new`, but always later than `latest`: if the clock hasn't
+    /// moved past `latest` yet (say, because it was generated within
+    /// the same second), the revision one second after it.
+    pub(crate) fn new_after(latest: &KeyRevision) -> KeyRevision {
+        let now = Self::new();
+        if now > *latest {
+            now
+        } else {
+            Self::from_timestamp(latest.timestamp() + chrono::Duration::seconds(1))
+        }
+    }
+
+    fn from_timestamp(timestamp: DateTime<Utc>) -> KeyRevision {
+        KeyRevision(timestamp.format("%Y%m%d%H%M%S").to_string())
+    }
+
+    /// The moment in time this revision represents.
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        Self::parse_timestamp(&self.0).expect("KeyRevisions are validated when created")
+    }
+
+    /// Explains what is wrong with `value` as a revision, if anything.
+    fn validate(value: &str) -> result::Result<(), String> {
+        if value.len() != 14 {
+            Err(format!("revision '{}' must be 14 digits long, not {}",
+                        value,
+                        value.len()))
+        } else if !value.bytes().all(|b| b.is_ascii_digit()) {
+            Err(format!("revision '{}' must contain only digits", value))
+        } else if Self::parse_timestamp(value).is_none() {
+            Err(format!("revision '{}' is not a valid UTC timestamp", value))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Interprets a string of 14 digits as
+    /// `{year}{month}{day}{hour24}{minute}{second}`, if it's a real
+    /// moment in time.
+    fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+        let field = |range: std::ops::Range<usize>| value.get(range)?.parse::<u32>().ok();
+        let year = value.get(0..4)?.parse::<i32>().ok()?;
+        let date = NaiveDate::from_ymd_opt(year, field(4..6)?, field(6..8)?)?;
+        let time = date.and_hms_opt(field(8..10)?, field(10..12)?, field(12..14)?)?;
+        Some(Utc.from_utc_datetime(&time))
+    }
+}
+
+impl FromStr for KeyRevision {
+    type Err = Error;
+
+    fn from_str(value: &str) -> result::Result<Self, Self::Err> {
+        match Self::validate(value) {
+            Ok(()) => Ok(KeyRevision(value.to_string())),
+            Err(reason) => {
+                Err(Error::CryptoError(format!("Cannot parse key revision: {}", reason)))
+            }
+        }
     }
 }
 
@@ -220,6 +404,43 @@ impl KeyRevision {
 mod tests {
     use super::*;
 
+    mod validate_key_name {
+        use super::*;
+
+        #[test]
+        fn names_of_origins_users_and_service_groups_are_valid() {
+            for name in &["core",
+                          "my-org",
+                          "_private",
+                          "bobo_the_clown",
+                          "foo.default@acme"]
+            {
+                assert!(validate_key_name(name).is_ok(), "{} should be valid", name);
+            }
+        }
+
+        #[test]
+        fn names_that_could_escape_the_cache_are_invalid() {
+            for (name, reason) in &[("", "it is empty"),
+                                    ("../../etc/passwd", "it begins with '.'"),
+                                    ("foo/../../etc/passwd", "it contains '/'"),
+                                    ("foo\\bar", "it contains '\\'"),
+                                    (".hidden", "it begins with '.'"),
+                                    ("-flag", "it begins with '-'"),
+                                    ("foo bar", "it contains ' '"),
+                                    ("foo*", "it contains '*'")]
+            {
+                match validate_key_name(name) {
+                    Err(Error::InvalidKeyName { name: n, reason: r }) => {
+                        assert_eq!(n, *name);
+                        assert_eq!(r, *reason);
+                    }
+                    other => panic!("Expected InvalidKeyName for {:?}, got {:?}", name, other),
+                }
+            }
+        }
+    }
+
     mod named_revision {
         use super::*;
 
@@ -266,5 +487,80 @@ mod tests {
             let input = "foo-20160504220722";
             assert_eq!(input.parse::<NamedRevision>().unwrap().to_string(), input);
         }
+
+        fn parse_error(value: &str) -> String {
+            match value.parse::<NamedRevision>() {
+                Err(Error::CryptoError(msg)) => msg,
+                other => panic!("Expected CryptoError, got {:?}", other),
+            }
+        }
+
+        #[test]
+        fn parse_errors_say_what_is_wrong() {
+            assert_eq!(parse_error("barf"),
+                       "Cannot parse named revision 'barf': expected a name and a revision \
+                        separated by '-'");
+            assert_eq!(parse_error("-20160504220722"),
+                       "Cannot parse named revision '-20160504220722': expected a name and a \
+                        revision separated by '-'");
+            assert_eq!(parse_error("barf-123"),
+                       "Cannot parse named revision 'barf-123': revision '123' must be 14 digits \
+                        long, not 3");
+            assert_eq!(parse_error("barf-2016050422072x"),
+                       "Cannot parse named revision 'barf-2016050422072x': revision \
+                        '2016050422072x' must contain only digits");
+            assert_eq!(parse_error("barf-20161304220722"),
+                       "Cannot parse named revision 'barf-20161304220722': revision \
+                        '20161304220722' is not a valid UTC timestamp");
+            assert_eq!(parse_error("barf-20160230220722"),
+                       "Cannot parse named revision 'barf-20160230220722': revision \
+                        '20160230220722' is not a valid UTC timestamp");
+        }
+
+        #[test]
+        fn revision_timestamp() {
+            let nr: NamedRevision = "foo-20160504220722".parse().unwrap();
+            assert_eq!(nr.revision_timestamp(),
+                       "2016-05-04T22:07:22Z".parse::<DateTime<Utc>>().unwrap());
+        }
+
+        #[test]
+        fn orders_by_revision_then_name() {
+            let mut revisions = vec!["foo-20160504220722",
+                                     "bar-20160504220722",
+                                     "zzz-20150101000000",
+                                     "aaa-20170101000000"].into_iter()
+                                                          .map(|nr| nr.parse().unwrap())
+                                                          .collect::<Vec<NamedRevision>>();
+            revisions.sort();
+
+            assert_eq!(revisions.iter()
+                                .map(ToString::to_string)
+                                .collect::<Vec<_>>(),
+                       vec!["zzz-20150101000000",
+                            "bar-20160504220722",
+                            "foo-20160504220722",
+                            "aaa-20170101000000"]);
+        }
+    }
+
+    mod key_revision {
+        use super::*;
+
+        #[test]
+        fn parse() {
+            let revision: KeyRevision = "20160504220722".parse().unwrap();
+            assert_eq!(revision, KeyRevision::unchecked("20160504220722"));
+            assert!("2016050422072".parse::<KeyRevision>().is_err());
+            assert!("20160504226722".parse::<KeyRevision>().is_err());
+        }
+
+        #[test]
+        fn new_revisions_are_valid() {
+            let revision = KeyRevision::new();
+            assert_eq!(revision.to_string().parse::<KeyRevision>().unwrap(),
+                       revision);
+            assert!(Utc::now() - revision.timestamp() < chrono::Duration::seconds(5));
+        }
     }
 }
@@ -0,0 +1,281 @@
+use crate::{crypto::keys::{BuilderSecretEncryptionKey,
+                           CachedKeyInfo,
+                           KeyCache,
+                           KeyFile,
+                           NamedRevision,
+                           OriginPublicEncryptionKey,
+                           PublicOriginSigningKey,
+                           RingKey,
+                           SecretOriginSigningKey,
+                           ServicePublicEncryptionKey,
+                           ServiceSecretEncryptionKey,
+                           UserPublicEncryptionKey,
+                           UserSecretEncryptionKey},
+            error::Result,
+            origin::Origin};
+use std::io;
+use tokio::task;
+
+/// A `KeyCache` for use from async code.
+///
+/// Every operation is run on Tokio's blocking thread pool, rather than
+/// on the executor thread, by way of the sync `KeyCache` API. Keys are
+/// therefore read, written, and locked exactly as they are by
+/// `KeyCache`, including writing them atomically with the right
+/// permissions.
+#[derive(Clone, Debug)]
+pub struct AsyncKeyCache {
+    key_cache: KeyCache,
+}
+
+impl AsyncKeyCache {
+    pub fn new(key_cache: KeyCache) -> Self { AsyncKeyCache { key_cache } }
+
+    /// The `KeyCache` this runs operations on.
+    pub fn key_cache(&self) -> &KeyCache { &self.key_cache }
+
+    /// See `KeyCache::setup`.
+    pub async fn setup(&self) -> Result<()> { self.run(|cache| cache.setup()).await }
+
+    /// See `KeyCache::setup_permissive`.
+    pub async fn setup_permissive(&self) -> Result<()> {
+        self.run(|cache| cache.setup_permissive()).await
+    }
+
+    /// See `KeyCache::write_key`.
+    pub async fn write_key<K>(&self, key: &K) -> Result<()>
+        where K: KeyFile + Clone + Send + 'static
+    {
+        let key = key.clone();
+        self.run(move |cache| cache.write_key(&key)).await
+    }
+
+    /// See `KeyCache::write_key_str`.
+    pub async fn write_key_str(&self, content: &str) -> Result<CachedKeyInfo> {
+        let content = content.to_string();
+        self.run(move |cache| cache.write_key_str(&content)).await
+    }
+
+    /// Note: name is just the name, not the name + revision
+    pub async fn latest_ring_key_revision(&self, name: &str) -> Result<RingKey> {
+        let name = name.to_string();
+        self.run(move |cache| cache.latest_ring_key_revision(&name))
+            .await
+    }
+
+    pub async fn latest_secret_origin_signing_key(&self,
+                                                  origin: &Origin)
+                                                  -> Result<SecretOriginSigningKey> {
+        let origin = origin.clone();
+        self.run(move |cache| cache.latest_secret_origin_signing_key(&origin))
+            .await
+    }
+
+    pub async fn latest_public_origin_signing_key(&self,
+                                                  origin: &Origin)
+                                                  -> Result<PublicOriginSigningKey> {
+        let origin = origin.clone();
+        self.run(move |cache| cache.latest_public_origin_signing_key(&origin))
+            .await
+    }
+
+    pub async fn latest_user_secret_key(&self, user_name: &str) -> Result<UserSecretEncryptionKey> {
+        let user_name = user_name.to_string();
+        self.run(move |cache| cache.latest_user_secret_key(&user_name))
+            .await
+    }
+
+    pub async fn latest_origin_public_encryption_key(&self,
+                                                     origin: &Origin)
+                                                     -> Result<OriginPublicEncryptionKey> {
+        let origin = origin.clone();
+        self.run(move |cache| cache.latest_origin_public_encryption_key(&origin))
+            .await
+    }
+
+    /// Name should be in `"service.group@org"` format.
+    pub async fn latest_service_public_key(&self,
+                                           name: &str)
+                                           -> Result<ServicePublicEncryptionKey> {
+        let name = name.to_string();
+        self.run(move |cache| cache.latest_service_public_key(name.as_str()))
+            .await
+    }
+
+    pub async fn latest_builder_key(&self) -> Result<BuilderSecretEncryptionKey> {
+        self.run(|cache| cache.latest_builder_key()).await
+    }
+
+    pub async fn public_signing_key(&self,
+                                    named_revision: &NamedRevision)
+                                    -> Result<PublicOriginSigningKey> {
+        let named_revision = named_revision.clone();
+        self.run(move |cache| cache.public_signing_key(&named_revision))
+            .await
+    }
+
+    pub async fn secret_signing_key(&self,
+                                    named_revision: &NamedRevision)
+                                    -> Result<SecretOriginSigningKey> {
+        let named_revision = named_revision.clone();
+        self.run(move |cache| cache.secret_signing_key(&named_revision))
+            .await
+    }
+
+    pub async fn user_public_encryption_key(&self,
+                                            named_revision: &NamedRevision)
+                                            -> Result<UserPublicEncryptionKey> {
+        let named_revision = named_revision.clone();
+        self.run(move |cache| cache.user_public_encryption_key(&named_revision))
+            .await
+    }
+
+    pub async fn service_secret_encryption_key(&self,
+                                               named_revision: &NamedRevision)
+                                               -> Result<ServiceSecretEncryptionKey> {
+        let named_revision = named_revision.clone();
+        self.run(move |cache| cache.service_secret_encryption_key(&named_revision))
+            .await
+    }
+
+    pub async fn builder_secret_encryption_key(&self,
+                                               named_revision: &NamedRevision)
+                                               -> Result<BuilderSecretEncryptionKey> {
+        let named_revision = named_revision.clone();
+        self.run(move |cache| cache.builder_secret_encryption_key(&named_revision))
+            .await
+    }
+
+    ////////////////////////////////////////////////////////////////////////
+
+    /// Run `op` against the `KeyCache` on the blocking thread pool.
+    async fn run<F, T>(&self, op: F) -> Result<T>
+        where F: FnOnce(&KeyCache) -> Result<T> + Send + 'static,
+              T: Send + 'static
+    {
+        let key_cache = self.key_cache.clone();
+        match task::spawn_blocking(move || op(&key_cache)).await {
+            Ok(result) => result,
+            Err(e) => Err(io::Error::new(io::ErrorKind::Other, e).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{keys::{generate_origin_encryption_key_pair,
+                               generate_service_encryption_key_pair,
+                               generate_signing_key_pair,
+                               generate_user_encryption_key_pair,
+                               Key},
+                        test_support::*};
+
+    #[tokio::test]
+    async fn ring_key_round_trip() {
+        let (key_cache, _dir) = new_cache();
+        let cache = AsyncKeyCache::new(key_cache);
+        let key = RingKey::new("beyonce");
+        cache.write_key(&key).await.unwrap();
+        assert_eq!(cache.latest_ring_key_revision("beyonce").await.unwrap(),
+                   key);
+    }
+
+    #[tokio::test]
+    async fn signing_keys_round_trip() {
+        let (key_cache, _dir) = new_cache();
+        let cache = AsyncKeyCache::new(key_cache);
+        let origin = "my-origin".parse::<Origin>().unwrap();
+        let (public, secret) = generate_signing_key_pair(&origin);
+        cache.write_key(&public).await.unwrap();
+        cache.write_key(&secret).await.unwrap();
+
+        assert_eq!(cache.public_signing_key(public.named_revision())
+                        .await
+                        .unwrap(),
+                   public);
+        assert_eq!(cache.secret_signing_key(secret.named_revision())
+                        .await
+                        .unwrap(),
+                   secret);
+        assert_eq!(cache.latest_public_origin_signing_key(&origin)
+                        .await
+                        .unwrap(),
+                   public);
+        assert_eq!(cache.latest_secret_origin_signing_key(&origin)
+                        .await
+                        .unwrap(),
+                   secret);
+    }
+
+    #[tokio::test]
+    async fn user_keys_round_trip() {
+        let (key_cache, _dir) = new_cache();
+        let cache = AsyncKeyCache::new(key_cache);
+        let (public, secret) = generate_user_encryption_key_pair("my-user");
+        cache.write_key(&public).await.unwrap();
+        cache.write_key(&secret).await.unwrap();
+
+        assert_eq!(cache.latest_user_secret_key("my-user").await.unwrap(),
+                   secret);
+        assert_eq!(cache.user_public_encryption_key(public.named_revision())
+                        .await
+                        .unwrap(),
+                   public);
+    }
+
+    #[tokio::test]
+    async fn origin_keys_round_trip() {
+        let (key_cache, _dir) = new_cache();
+        let cache = AsyncKeyCache::new(key_cache);
+        let origin = "my-origin".parse::<Origin>().unwrap();
+        let (public, _secret) = generate_origin_encryption_key_pair(&origin);
+        cache.write_key(&public).await.unwrap();
+        assert_eq!(cache.latest_origin_public_encryption_key(&origin)
+                        .await
+                        .unwrap(),
+                   public);
+    }
+
+    #[tokio::test]
+    async fn service_keys_round_trip() {
+        let (key_cache, _dir) = new_cache();
+        let cache = AsyncKeyCache::new(key_cache);
+        let (public, secret) = generate_service_encryption_key_pair("my-org", "foo.default");
+        cache.write_key(&public).await.unwrap();
+        cache.write_key(&secret).await.unwrap();
+
+        assert_eq!(cache.latest_service_public_key("foo.default@my-org")
+                        .await
+                        .unwrap(),
+                   public);
+        assert_eq!(cache.service_secret_encryption_key(secret.named_revision())
+                        .await
+                        .unwrap(),
+                   secret);
+    }
+
+    #[tokio::test]
+    async fn missing_keys_are_errors() {
+        let (key_cache, _dir) = new_cache();
+        let cache = AsyncKeyCache::new(key_cache);
+        assert!(cache.latest_ring_key_revision("beyonce").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn writes_are_written_with_permissions() {
+        let (key_cache, _dir) = new_cache();
+        let cache = AsyncKeyCache::new(key_cache.clone());
+        let key = RingKey::new("beyonce");
+        cache.write_key(&key).await.unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(key_cache.path_in_cache(&key)).unwrap()
+                                                                       .permissions()
+                                                                       .mode();
+            assert_eq!(mode & 0o777, 0o400);
+        }
+        assert_eq!(key_cache.latest_ring_key_revision("beyonce").unwrap(), key);
+    }
+}
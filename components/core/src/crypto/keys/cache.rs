@@ -1,61 +1,448 @@
+#[cfg(not(windows))]
+use crate::util::posix_perm;
+#[cfg(windows)]
+use crate::util::win_perm;
 use crate::{crypto::{hash::Blake2bHash,
-                     keys::{encryption::{generate_origin_encryption_key_pair,
-                                         generate_service_encryption_key_pair,
+                     keys::{cache_watcher::KeyCacheWatcher,
+                            encryption::{generate_origin_encryption_key_pair,
+                                         generate_service_encryption_key_pair_for,
                                          generate_user_encryption_key_pair,
+                                         ServiceMessage,
                                          BUILDER_KEY_NAME},
                             generate_signing_key_pair,
+                            generate_user_encryption_key_pair_with,
+                            validate_key_name,
                             BuilderSecretEncryptionKey,
+                            DetachedSignature,
+                            Key,
                             KeyFile,
+                            KeyRevision,
                             NamedRevision,
                             OriginPublicEncryptionKey,
                             OriginSecretEncryptionKey,
+                            PublicKeyFormat,
                             PublicOriginSigningKey,
                             RingKey,
                             SecretOriginSigningKey,
                             ServicePublicEncryptionKey,
                             ServiceSecretEncryptionKey,
                             UserPublicEncryptionKey,
-                            UserSecretEncryptionKey}},
+                            UserSecretEncryptionKey,
+                            KEYFILE_RE}},
             error::{Error,
                     Result},
-            fs::AtomicWriter,
-            origin::Origin};
-use serde::Deserialize;
-use std::{convert::TryFrom,
-          io::Write,
+            fs::{self as hfs,
+                 AtomicWriter,
+                 Permissions,
+                 StagedWrite},
+            origin::Origin,
+            service::ServiceGroupIdent};
+use chrono::{DateTime,
+             Utc};
+use fs2::FileExt;
+use log::warn;
+use rand::rngs::OsRng;
+use serde::{Deserialize,
+            Serialize};
+#[cfg(not(windows))]
+use std::os::unix::fs::{DirBuilderExt,
+                        PermissionsExt};
+use std::{cmp,
+          collections::{HashMap,
+                        HashSet},
+          convert::TryInto,
+          ffi::OsStr,
+          fs,
+          io::{self,
+               BufRead,
+               Read,
+               Write},
           path::{Path,
-                 PathBuf}};
+                 PathBuf},
+          result,
+          str::FromStr,
+          thread,
+          time::{Duration,
+                 Instant}};
+
+/// The file in a cache's directory that writers hold an exclusive
+/// lock on, so that processes sharing a cache take turns writing to
+/// it. Readers don't need it, since keys are renamed into place.
+const LOCK_FILE: &str = ".lock";
+
+/// How long to wait for another writer to release the lock on a
+/// cache before giving up.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The permissions a cache's directory is given, since it holds
+/// secret keys.
+#[cfg(not(windows))]
+const CACHE_DIR_PERMISSIONS: u32 = 0o700;
+
+/// The kinds of key that can be found in a `KeyCache`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub enum KeyKind {
+    Ring,
+    SigningPublic,
+    SigningSecret,
+    /// User and origin encryption keys share a file format and
+    /// naming scheme, so they can't be told apart on disk.
+    UserOrOriginEncryptionPublic,
+    UserOrOriginEncryptionSecret,
+    ServiceEncryptionPublic,
+    ServiceEncryptionSecret,
+    BuilderEncryptionSecret,
+}
+
+impl KeyKind {
+    /// Work out what kind of key a file holds from the version line
+    /// of its contents, its file extension, and the key's name.
+    fn of(version: &str, extension: &str, name: &str) -> Option<KeyKind> {
+        Self::of_content(version, name).filter(|kind| {
+                                           kind.extension() == extension
+                                           || kind.legacy_extension() == Some(extension)
+                                       })
+    }
+
+    /// Work out what kind of key some content holds from its version
+    /// line and the key's name alone.
+    fn of_content(version: &str, name: &str) -> Option<KeyKind> {
+        let is_service_key = name.contains('@');
+        if version == RingKey::version() {
+            Some(KeyKind::Ring)
+        } else if version == PublicOriginSigningKey::version() {
+            Some(KeyKind::SigningPublic)
+        } else if version == SecretOriginSigningKey::version() {
+            Some(KeyKind::SigningSecret)
+        } else if version == ServicePublicEncryptionKey::version() {
+            if is_service_key {
+                Some(KeyKind::ServiceEncryptionPublic)
+            } else {
+                Some(KeyKind::UserOrOriginEncryptionPublic)
+            }
+        } else if version == ServiceSecretEncryptionKey::version() {
+            if is_service_key {
+                Some(KeyKind::ServiceEncryptionSecret)
+            } else if name == BUILDER_KEY_NAME {
+                Some(KeyKind::BuilderEncryptionSecret)
+            } else {
+                Some(KeyKind::UserOrOriginEncryptionSecret)
+            }
+        } else {
+            None
+        }
+    }
+
+    /// The kind of key that makes up the other half of a pair with
+    /// keys of this kind, if they come in pairs.
+    fn counterpart(self) -> Option<KeyKind> {
+        match self {
+            KeyKind::SigningPublic => Some(KeyKind::SigningSecret),
+            KeyKind::SigningSecret => Some(KeyKind::SigningPublic),
+            KeyKind::UserOrOriginEncryptionPublic => Some(KeyKind::UserOrOriginEncryptionSecret),
+            KeyKind::UserOrOriginEncryptionSecret => Some(KeyKind::UserOrOriginEncryptionPublic),
+            KeyKind::ServiceEncryptionPublic => Some(KeyKind::ServiceEncryptionSecret),
+            KeyKind::ServiceEncryptionSecret => Some(KeyKind::ServiceEncryptionPublic),
+            KeyKind::Ring | KeyKind::BuilderEncryptionSecret => None,
+        }
+    }
+
+    /// Whether keys of this kind must be kept secret.
+    pub fn is_secret(self) -> bool {
+        match self {
+            KeyKind::Ring
+            | KeyKind::SigningSecret
+            | KeyKind::UserOrOriginEncryptionSecret
+            | KeyKind::ServiceEncryptionSecret
+            | KeyKind::BuilderEncryptionSecret => true,
+            KeyKind::SigningPublic
+            | KeyKind::UserOrOriginEncryptionPublic
+            | KeyKind::ServiceEncryptionPublic => false,
+        }
+    }
+
+    /// The permissions keys of this kind are saved with.
+    fn permissions(self) -> Permissions {
+        match self {
+            KeyKind::Ring => RingKey::permissions(),
+            KeyKind::SigningPublic => PublicOriginSigningKey::permissions(),
+            KeyKind::SigningSecret => SecretOriginSigningKey::permissions(),
+            KeyKind::UserOrOriginEncryptionPublic => UserPublicEncryptionKey::permissions(),
+            KeyKind::UserOrOriginEncryptionSecret => UserSecretEncryptionKey::permissions(),
+            KeyKind::ServiceEncryptionPublic => ServicePublicEncryptionKey::permissions(),
+            KeyKind::ServiceEncryptionSecret => ServiceSecretEncryptionKey::permissions(),
+            KeyKind::BuilderEncryptionSecret => BuilderSecretEncryptionKey::permissions(),
+        }
+    }
+
+    /// The file extension keys of this kind were saved with before
+    /// each kind of key had its own, if it differs from `extension`.
+    fn legacy_extension(self) -> Option<&'static str> {
+        match self {
+            KeyKind::Ring => RingKey::legacy_extension(),
+            KeyKind::SigningPublic => PublicOriginSigningKey::legacy_extension(),
+            KeyKind::SigningSecret => SecretOriginSigningKey::legacy_extension(),
+            KeyKind::UserOrOriginEncryptionPublic => UserPublicEncryptionKey::legacy_extension(),
+            KeyKind::UserOrOriginEncryptionSecret => UserSecretEncryptionKey::legacy_extension(),
+            KeyKind::ServiceEncryptionPublic => ServicePublicEncryptionKey::legacy_extension(),
+            KeyKind::ServiceEncryptionSecret => ServiceSecretEncryptionKey::legacy_extension(),
+            KeyKind::BuilderEncryptionSecret => BuilderSecretEncryptionKey::legacy_extension(),
+        }
+    }
+
+    /// The file extension keys of this kind are saved with.
+    fn extension(self) -> &'static str {
+        match self {
+            KeyKind::Ring => RingKey::extension(),
+            KeyKind::SigningPublic => PublicOriginSigningKey::extension(),
+            KeyKind::SigningSecret => SecretOriginSigningKey::extension(),
+            KeyKind::UserOrOriginEncryptionPublic => UserPublicEncryptionKey::extension(),
+            KeyKind::UserOrOriginEncryptionSecret => UserSecretEncryptionKey::extension(),
+            KeyKind::ServiceEncryptionPublic => ServicePublicEncryptionKey::extension(),
+            KeyKind::ServiceEncryptionSecret => ServiceSecretEncryptionKey::extension(),
+            KeyKind::BuilderEncryptionSecret => BuilderSecretEncryptionKey::extension(),
+        }
+    }
+
+    /// Parse `content` as this kind of key, returning its
+    /// `NamedRevision` if it is valid, or why it isn't.
+    fn parse(self, content: &str) -> result::Result<NamedRevision, String> {
+        fn parse<K>(content: &str) -> result::Result<NamedRevision, String>
+            where K: Key + FromStr<Err = Error>
+        {
+            match content.parse::<K>() {
+                Ok(key) => Ok(key.named_revision().clone()),
+                Err(Error::CryptoError(reason)) => Err(reason),
+                Err(e) => Err(e.to_string()),
+            }
+        }
+
+        match self {
+            KeyKind::Ring => parse::<RingKey>(content),
+            KeyKind::SigningPublic => parse::<PublicOriginSigningKey>(content),
+            KeyKind::SigningSecret => parse::<SecretOriginSigningKey>(content),
+            KeyKind::UserOrOriginEncryptionPublic => parse::<UserPublicEncryptionKey>(content),
+            KeyKind::UserOrOriginEncryptionSecret => parse::<UserSecretEncryptionKey>(content),
+            KeyKind::ServiceEncryptionPublic => parse::<ServicePublicEncryptionKey>(content),
+            KeyKind::ServiceEncryptionSecret => parse::<ServiceSecretEncryptionKey>(content),
+            KeyKind::BuilderEncryptionSecret => parse::<BuilderSecretEncryptionKey>(content),
+        }
+    }
+}
+
+/// A key found in a `KeyCache` by `KeyCache::list_keys`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CachedKeyInfo {
+    pub named_revision: NamedRevision,
+    pub kind:           KeyKind,
+    pub path:           PathBuf,
+}
+
+impl CachedKeyInfo {
+    /// Describe the key file at `path`, if it holds a valid key and
+    /// is named for the key it holds.
+    pub(super) fn from_path(path: PathBuf) -> Option<Self> {
+        let filename = path.file_name()?.to_str()?;
+        let content = fs::read_to_string(&path).ok()?;
+        let (kind, named_revision) = identify(filename, &content)?;
+        Some(CachedKeyInfo { named_revision,
+                             kind,
+                             path })
+    }
+}
+
+/// Work out the kind and `NamedRevision` of the key in `content`, if
+/// it is a valid key and `filename` is the name it would be saved as.
+fn identify(filename: &str, content: &str) -> Option<(KeyKind, NamedRevision)> {
+    let caps = KEYFILE_RE.captures(filename)?;
+    let extension = caps.name("suffix")?.as_str();
+    let kind = KeyKind::of(content.lines().next()?, extension, &caps["name"])?;
+    let named_revision = kind.parse(content).ok()?;
+    if filename != format!("{}.{}", named_revision, extension) {
+        return None;
+    }
+    Some((kind, named_revision))
+}
+
+/// What `KeyCache::verify` found wrong with a cache. Each list is
+/// ordered by path.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CacheReport {
+    pub unparseable: Vec<UnparseableKey>,
+    pub misnamed: Vec<MisnamedKey>,
+    pub bad_permissions: Vec<BadPermissions>,
+    pub secret_keys_without_public_keys: Vec<CachedKeyInfo>,
+    /// Public keys downloaded to verify or encrypt things never have
+    /// their secret keys alongside them, so these are only of
+    /// interest in caches that should hold both.
+    pub public_keys_without_secret_keys: Vec<CachedKeyInfo>,
+}
+
+impl CacheReport {
+    /// Whether nothing but public keys without their secret keys was
+    /// found; see `public_keys_without_secret_keys`.
+    pub fn is_clean(&self) -> bool {
+        self.unparseable.is_empty()
+        && self.misnamed.is_empty()
+        && self.bad_permissions.is_empty()
+        && self.secret_keys_without_public_keys.is_empty()
+    }
+}
+
+/// A file named like a key that doesn't hold a valid key of the kind
+/// its name says it does.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct UnparseableKey {
+    pub path:   PathBuf,
+    pub reason: String,
+}
+
+/// A valid key saved under a name other than its own.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MisnamedKey {
+    pub path:           PathBuf,
+    pub named_revision: NamedRevision,
+}
+
+/// A key file whose permissions aren't those it would be written
+/// with. Only ever reported on Unix.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct BadPermissions {
+    pub path:     PathBuf,
+    pub mode:     u32,
+    pub expected: u32,
+}
 
 /// Represents the location of all Habitat keys (user, service,
 /// origin, signing, and ring) locally on disk, as well as the APIs
 /// for retrieving and storing keys.
+///
+/// A cache may also have read-only fallback directories, which are
+/// searched for keys after its own directory. Keys are only ever
+/// written to (or removed from) the cache's own directory.
+///
+/// On Unix, secret keys whose files other users can read are refused,
+/// unless the cache is made with `without_permission_checks`.
 #[derive(Clone, Debug, PartialEq, Deserialize)]
-pub struct KeyCache(PathBuf);
+#[serde(from = "PathBuf")]
+pub struct KeyCache {
+    path:              PathBuf,
+    fallbacks:         Vec<PathBuf>,
+    check_permissions: bool,
+    lock_timeout:      Duration,
+}
 
 impl AsRef<Path> for KeyCache {
     /// Expose the path to this key cache.
-    fn as_ref(&self) -> &Path { self.0.as_ref() }
+    fn as_ref(&self) -> &Path { self.path.as_ref() }
+}
+
+impl From<PathBuf> for KeyCache {
+    fn from(path: PathBuf) -> Self { KeyCache::new(path) }
 }
 
 impl KeyCache {
     pub fn new<P>(path: P) -> Self
         where P: Into<PathBuf>
     {
-        KeyCache(path.into())
+        Self::with_fallbacks(path, Vec::new())
+    }
+
+    /// A cache in `primary` that also reads keys from each of
+    /// `fallbacks`, in order. Where the same revision of a key is in
+    /// more than one directory, the first one found wins. Fallback
+    /// directories need not exist.
+    pub fn with_fallbacks<P>(primary: P, fallbacks: Vec<PathBuf>) -> Self
+        where P: Into<PathBuf>
+    {
+        KeyCache { path: primary.into(),
+                   fallbacks,
+                   check_permissions: true,
+                   lock_timeout: LOCK_TIMEOUT }
+    }
+
+    /// This cache, but loading secret keys whatever the permissions on
+    /// their files. Meant for tests, and for directories whose own
+    /// permissions already keep other users out.
+    pub fn without_permission_checks(mut self) -> Self {
+        self.check_permissions = false;
+        self
     }
 
-    /// Ensure that the directory backing the cache exists on disk.
+    /// Ensure that the directory backing the cache exists on disk,
+    /// and that only its owner can use it. Fallback directories are
+    /// left alone.
+    ///
+    /// On Unix, a new directory is created with mode 0700, whatever
+    /// the process umask, and an existing one that other users can
+    /// get into has its permissions tightened to match. If that can't
+    /// be done, `Error::InsecureKeyCacheDirectory` is returned. On
+    /// Windows, the directory's ACL is hardened to give access to
+    /// only the current user, Administrators, and SYSTEM.
+    ///
+    /// Temporary files left behind by key writes that were
+    /// interrupted are cleaned up, too.
     pub fn setup(&self) -> Result<()> {
-        if !self.0.is_dir() {
-            std::fs::create_dir_all(&self.0)?;
+        if !self.path.is_dir() {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut builder = fs::DirBuilder::new();
+            #[cfg(not(windows))]
+            builder.mode(CACHE_DIR_PERMISSIONS);
+            match builder.create(&self.path) {
+                Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e.into()),
+                _ => {}
+            }
+        }
+        self.restrict_permissions()?;
+        if let Err(e) = hfs::cleanup_stale_tmp_files(&self.path, hfs::STALE_TMP_FILE_AGE) {
+            warn!("Unable to clean up stale temporary files in key cache {}: {}",
+                  self.path.display(),
+                  e);
+        }
+        Ok(())
+    }
+
+    /// Like `setup`, but leaves the permissions of the directory to
+    /// the process umask, or to whatever they already are. Meant for
+    /// tests and for directories deliberately shared between users.
+    pub fn setup_permissive(&self) -> Result<()> {
+        if !self.path.is_dir() {
+            fs::create_dir_all(&self.path)?;
+        }
+        Ok(())
+    }
+
+    /// Make sure that only the cache directory's owner can use it.
+    #[cfg(not(windows))]
+    fn restrict_permissions(&self) -> Result<()> {
+        let mode = fs::metadata(&self.path)?.permissions().mode() & 0o777;
+        if mode & !CACHE_DIR_PERMISSIONS != 0 {
+            warn!("Key cache directory {} can be used by other users (mode {:#o}); restricting \
+                   it to mode {:#o}",
+                  self.path.display(),
+                  mode,
+                  CACHE_DIR_PERMISSIONS);
+            posix_perm::set_permissions(&self.path, CACHE_DIR_PERMISSIONS).map_err(|e| {
+                Error::InsecureKeyCacheDirectory { path: self.path.clone(),
+                                                   mode,
+                                                   source: Box::new(e) }
+            })?;
         }
         Ok(())
     }
 
+    /// Make sure that only the cache directory's owner (along with
+    /// Administrators and SYSTEM) can use it.
+    #[cfg(windows)]
+    fn restrict_permissions(&self) -> Result<()> { win_perm::harden_path(&self.path) }
+
     ////////////////////////////////////////////////////////////////////////
 
     /// Generate a new ring key and save it to disk.
     pub fn new_ring_key(&self, name: &str) -> Result<RingKey> {
+        validate_key_name(name)?;
         let key = RingKey::new(name);
         self.write_key(&key)?;
         Ok(key)
@@ -85,6 +472,7 @@ impl KeyCache {
         &self,
         user: &str)
         -> Result<(UserPublicEncryptionKey, UserSecretEncryptionKey)> {
+        validate_key_name(user)?;
         let (public, secret) = generate_user_encryption_key_pair(user);
         self.write_pair(&public, &secret)?;
         Ok((public, secret))
@@ -96,7 +484,8 @@ impl KeyCache {
         org: &str,
         service_group: &str)
         -> Result<(ServicePublicEncryptionKey, ServiceSecretEncryptionKey)> {
-        let (public, secret) = generate_service_encryption_key_pair(org, service_group);
+        let service_group = ServiceGroupIdent::from_org_and_service_group(org, service_group)?;
+        let (public, secret) = generate_service_encryption_key_pair_for(&service_group);
         self.write_pair(&public, &secret)?;
         Ok((public, secret))
     }
@@ -107,62 +496,118 @@ impl KeyCache {
     /// and the content has the same hash value, nothing will be
     /// done. If the file exists and it has *different* content, an
     /// Error is returned.
-    // Note that this is potentially problematic for public origin
-    // encryption keys and public origin signing keys. Even though
-    // they have different header values within a file, they could
-    // have the same name and revision (and thus the same filename)
-    // if new encryption and signing pairs were made within the same
-    // second.
-    //
-    // That is, if you made encryption and signing key pairs for
-    // "my-origin" in the same second, the public keys of both would
-    // both try to be saved in the same file, which is not great.
-    //
-    // This is probably _highly_ unlikely to manifest outside of
-    // tests, particularly since only Builder will be generating
-    // origin encryption pairs, but it's worth mentioning.
-    //
-    // This could also cause problems trying to retrieve one of these
-    // keys from the cache, either by name or named revision. If
-    // you're trying to fetch a public origin signing key, but happen
-    // to have a public origin *encryption* key in your cache, we'll
-    // find the file, but won't be able to give you a key, because it
-    // won't parse to the correct type.
-    //
-    // TODO (CM): Consider new filename extensions that capture what
-    // kind of key we're dealing with. For example "service.pub" and
-    // "service.box.key" for service encryption keys, "sig.pub"
-    // and "sig.key" for signing keys, etc. This would, of course,
-    // have to be done in a backwards-compatible way for all the keys
-    // currently in existence.
+    ///
+    /// Keys are always written under their current file extension,
+    /// even if the cache holds older revisions under a legacy one.
     pub fn write_key<K>(&self, key: &K) -> Result<()>
         where K: KeyFile
     {
-        let keyfile = self.path_in_cache(key);
-        let content = key.to_key_string();
+        let _lock = self.lock_for_writing()?;
+        self.write_key_while_locked(key)
+    }
 
-        if keyfile.is_file() {
-            let new_hash = Blake2bHash::from_bytes(&content);
-            let existing_hash = Blake2bHash::from_file(&keyfile)?;
-            if existing_hash != new_hash {
-                let msg = format!("Existing key file {} found but new version hash is different, \
-                                   failing to write new file over existing. (existing = {}, \
-                                   incoming = {})",
-                                  keyfile.display(),
-                                  existing_hash,
-                                  new_hash);
-                return Err(Error::CryptoError(msg));
+    /// The latest revision of the ring key named `name`, generating
+    /// and saving a new one if the cache holds none. A latest revision
+    /// that can't be read is an error; it is never replaced.
+    pub fn ring_key_or_generate(&self, name: &str) -> Result<RingKey> {
+        validate_key_name(name)?;
+        self.setup()?;
+        let _lock = self.lock_for_writing()?;
+        match self.try_fetch_latest_revision(name)? {
+            Some(key) => Ok(key),
+            None => {
+                let key = RingKey::new(name);
+                self.write_key_while_locked(&key)?;
+                Ok(key)
             }
-        } else {
-            // Technically speaking, this probably doesn't really need
-            // to be an atomic write process, since we just tested
-            // that the file doesn't currently exist. It does,
-            // however, bundle up writing with platform-independent
-            // permission setting, which is *super* convenient.
-            let w = AtomicWriter::new_with_permissions(&keyfile, K::permissions())?;
-            w.with_writer(|f| f.write_all(content.as_ref()))?;
         }
-        Ok(())
+    }
+
+    /// The latest complete user encryption key pair for `user`,
+    /// generating and saving a new pair if the cache holds none. A
+    /// latest pair that can't be read is an error; it is never
+    /// replaced.
+    pub fn user_encryption_pair_or_generate(
+        &self,
+        user: &str)
+        -> Result<(UserPublicEncryptionKey, UserSecretEncryptionKey)> {
+        validate_key_name(user)?;
+        self.setup()?;
+        let _lock = self.lock_for_writing()?;
+        match self.try_fetch_latest_pair(user)? {
+            Some(pair) => Ok(pair),
+            None => {
+                let (public, secret) = generate_user_encryption_key_pair(user);
+                self.write_pair_while_locked(&public, &secret)?;
+                Ok((public, secret))
+            }
+        }
+    }
+
+    /// Generate and save a new revision of the ring key named `name`,
+    /// returning the revision that was the latest before, if there
+    /// was one, along with the new key. The new key's revision is the
+    /// one to distribute to other Supervisors.
+    ///
+    /// The new revision is always later than any already in the
+    /// cache, even one generated within the same second, so that it
+    /// becomes the latest.
+    pub fn rotate_ring_key(&self, name: &str) -> Result<(Option<NamedRevision>, RingKey)> {
+        validate_key_name(name)?;
+        self.setup()?;
+        let _lock = self.lock_for_writing()?;
+        let previous = self.latest_revision_for::<RingKey>(name)?;
+        let key = RingKey::new_with(name, &mut OsRng, Self::rotated_revision(previous.as_ref()));
+        self.write_key_while_locked(&key)?;
+        Ok((previous.map(|revision| NamedRevision::from_parts(name.to_string(), revision)), key))
+    }
+
+    /// Like `rotate_ring_key`, but for the user encryption key pair of
+    /// `user`. The previous revision is the latest of either key of
+    /// the pair.
+    pub fn rotate_user_encryption_pair(
+        &self,
+        user: &str)
+        -> Result<(Option<NamedRevision>, (UserPublicEncryptionKey, UserSecretEncryptionKey))> {
+        validate_key_name(user)?;
+        self.setup()?;
+        let _lock = self.lock_for_writing()?;
+        let previous = cmp::max(self.latest_revision_for::<UserPublicEncryptionKey>(user)?,
+                                self.latest_revision_for::<UserSecretEncryptionKey>(user)?);
+        let (public, secret) =
+            generate_user_encryption_key_pair_with(user,
+                                                   &mut OsRng,
+                                                   Self::rotated_revision(previous.as_ref()));
+        self.write_pair_while_locked(&public, &secret)?;
+        Ok((previous.map(|revision| NamedRevision::from_parts(user.to_string(), revision)),
+            (public, secret)))
+    }
+
+    /// Write the key in `content` into the cache, working out what
+    /// kind of key it is from its version line and name, for when the
+    /// kind isn't known ahead of time. As with `write_key`, writing a
+    /// key the cache already holds does nothing, but writing a
+    /// different key with the same name and revision is an error.
+    pub fn write_key_str(&self, content: &str) -> Result<CachedKeyInfo> {
+        let mut lines = content.lines();
+        let version = lines.next()
+                           .ok_or_else(|| Error::CryptoError("Missing key version".to_string()))?;
+        let named_revision =
+            lines.next()
+                 .ok_or_else(|| Error::CryptoError("Missing name+revision".to_string()))?
+                 .parse::<NamedRevision>()?;
+        let kind = KeyKind::of_content(version, named_revision.name()).ok_or_else(|| {
+                       Error::CryptoError(format!("Unsupported key version: {}", version))
+                   })?;
+        let _lock = self.lock_for_writing()?;
+        if let Some(staged) = self.stage_key_of_kind(kind, content)? {
+            staged.commit()?;
+        }
+        let path = self.path
+                       .join(format!("{}.{}", named_revision, kind.extension()));
+        Ok(CachedKeyInfo { named_revision,
+                           kind,
+                           path })
     }
 
     /// Note: name is just the name, not the name + revision
@@ -170,18 +615,45 @@ impl KeyCache {
         self.fetch_latest_revision::<RingKey>(name)
     }
 
+    /// Every revision of the ring key `name` in the cache, newest
+    /// first. The newest revision has to be valid, but earlier ones
+    /// that aren't are skipped, so that a corrupt old revision can't
+    /// keep the current one from being used.
+    pub fn ring_keys(&self, name: &str) -> Result<Vec<RingKey>> {
+        self.fetch_latest_and_valid_previous_revisions::<RingKey>(name)
+    }
+
     pub fn latest_secret_origin_signing_key(&self,
                                             origin: &Origin)
                                             -> Result<SecretOriginSigningKey> {
         self.fetch_latest_revision::<SecretOriginSigningKey>(origin.as_ref())
     }
 
+    /// Like `latest_secret_origin_signing_key`, but skips over any
+    /// revisions that have expired as of `now`, returning the newest
+    /// one that hasn't.
+    ///
+    /// Returns `Error::KeyExpired` if there are revisions of the key,
+    /// but all of them have expired.
+    pub fn latest_valid_secret_origin_signing_key(&self,
+                                                  origin: &Origin,
+                                                  now: DateTime<Utc>)
+                                                  -> Result<SecretOriginSigningKey> {
+        self.fetch_latest_valid_revision::<SecretOriginSigningKey>(origin.as_ref(), now)
+    }
+
     pub fn latest_public_origin_signing_key(&self,
                                             origin: &Origin)
                                             -> Result<PublicOriginSigningKey> {
         self.fetch_latest_revision::<PublicOriginSigningKey>(origin.as_ref())
     }
 
+    /// Every revision of the public signing key for `origin` in the
+    /// cache, newest first.
+    pub fn public_signing_keys(&self, origin: &Origin) -> Result<Vec<PublicOriginSigningKey>> {
+        self.fetch_all_revisions::<PublicOriginSigningKey>(origin.as_ref())
+    }
+
     pub fn latest_user_secret_key(&self, user_name: &str) -> Result<UserSecretEncryptionKey> {
         self.fetch_latest_revision::<UserSecretEncryptionKey>(user_name)
     }
@@ -192,9 +664,16 @@ impl KeyCache {
         self.fetch_latest_revision::<OriginPublicEncryptionKey>(origin.as_ref())
     }
 
-    /// Name should be in `"service.group@org"` format.
-    pub fn latest_service_public_key(&self, name: &str) -> Result<ServicePublicEncryptionKey> {
-        self.fetch_latest_revision::<ServicePublicEncryptionKey>(name)
+    /// Accepts a `ServiceGroupIdent`, or anything that can be
+    /// converted into one, like a `"service.group@org"` string.
+    pub fn latest_service_public_key<S>(&self,
+                                        service_group: S)
+                                        -> Result<ServicePublicEncryptionKey>
+        where S: TryInto<ServiceGroupIdent>,
+              Error: From<S::Error>
+    {
+        let service_group = service_group.try_into()?;
+        self.fetch_latest_revision::<ServicePublicEncryptionKey>(&service_group.to_string())
     }
 
     /// Returns the latest Builder secret encryption key. All Builder
@@ -205,6 +684,267 @@ impl KeyCache {
         self.fetch_latest_revision::<BuilderSecretEncryptionKey>(BUILDER_KEY_NAME)
     }
 
+    /// The latest origin signing key pair for which the cache holds
+    /// both keys. A newer revision missing either key is passed over.
+    pub fn latest_signing_pair(&self,
+                               origin: &Origin)
+                               -> Result<(PublicOriginSigningKey, SecretOriginSigningKey)> {
+        self.fetch_latest_pair(origin.as_ref())
+    }
+
+    /// The latest origin encryption key pair for which the cache holds
+    /// both keys.
+    pub fn latest_origin_encryption_pair(
+        &self,
+        origin: &Origin)
+        -> Result<(OriginPublicEncryptionKey, OriginSecretEncryptionKey)> {
+        self.fetch_latest_pair(origin.as_ref())
+    }
+
+    /// The latest user encryption key pair for which the cache holds
+    /// both keys.
+    pub fn latest_user_encryption_pair(
+        &self,
+        user_name: &str)
+        -> Result<(UserPublicEncryptionKey, UserSecretEncryptionKey)> {
+        self.fetch_latest_pair(user_name)
+    }
+
+    /// The latest service encryption key pair for which the cache holds
+    /// both keys. Name should be in `"service.group@org"` format.
+    pub fn latest_service_encryption_pair(
+        &self,
+        name: &str)
+        -> Result<(ServicePublicEncryptionKey, ServiceSecretEncryptionKey)> {
+        self.fetch_latest_pair(name)
+    }
+
+    // The `try_` variants below return `Ok(None)` when the cache holds
+    // no such key, for keys that are genuinely optional. A key that
+    // is present but can't be read is still an error.
+
+    pub fn try_latest_ring_key_revision(&self, name: &str) -> Result<Option<RingKey>> {
+        self.try_fetch_latest_revision(name)
+    }
+
+    pub fn try_latest_secret_origin_signing_key(&self,
+                                                origin: &Origin)
+                                                -> Result<Option<SecretOriginSigningKey>> {
+        self.try_fetch_latest_revision(origin.as_ref())
+    }
+
+    pub fn try_latest_public_origin_signing_key(&self,
+                                                origin: &Origin)
+                                                -> Result<Option<PublicOriginSigningKey>> {
+        self.try_fetch_latest_revision(origin.as_ref())
+    }
+
+    pub fn try_latest_user_secret_key(&self,
+                                      user_name: &str)
+                                      -> Result<Option<UserSecretEncryptionKey>> {
+        self.try_fetch_latest_revision(user_name)
+    }
+
+    pub fn try_latest_origin_public_encryption_key(&self,
+                                                   origin: &Origin)
+                                                   -> Result<Option<OriginPublicEncryptionKey>>
+    {
+        self.try_fetch_latest_revision(origin.as_ref())
+    }
+
+    pub fn try_latest_service_public_key<S>(&self,
+                                            service_group: S)
+                                            -> Result<Option<ServicePublicEncryptionKey>>
+        where S: TryInto<ServiceGroupIdent>,
+              Error: From<S::Error>
+    {
+        let service_group = service_group.try_into()?;
+        self.try_fetch_latest_revision(&service_group.to_string())
+    }
+
+    pub fn try_latest_builder_key(&self) -> Result<Option<BuilderSecretEncryptionKey>> {
+        self.try_fetch_latest_revision(BUILDER_KEY_NAME)
+    }
+
+    /// Every valid key in the cache, ordered by file name. Files that
+    /// aren't keys, or that don't parse as the kind of key they
+    /// claim to be, are skipped, as are files in fallback directories
+    /// that are shadowed by a file of the same name in an earlier one.
+    pub fn list_keys(&self) -> Result<Vec<CachedKeyInfo>> {
+        let mut seen = HashSet::new();
+        let mut keys = Vec::new();
+        for dir in self.layers() {
+            let entries = match fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(ref e) if dir != self.path && e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            keys.extend(entries.filter_map(|entry| entry.ok())
+                               .map(|entry| entry.path())
+                               .filter(|path| path.is_file())
+                               .filter(|path| seen.insert(path.file_name().map(ToOwned::to_owned)))
+                               .filter_map(CachedKeyInfo::from_path));
+        }
+        keys.sort_by(|a, b| a.path.file_name().cmp(&b.path.file_name()));
+        Ok(keys)
+    }
+
+    /// Every valid revision of every kind of key with the given
+    /// name, ordered by file name.
+    pub fn list_keys_for_name(&self, name: &str) -> Result<Vec<CachedKeyInfo>> {
+        Ok(self.list_keys()?
+               .into_iter()
+               .filter(|key| key.named_revision.name() == name)
+               .collect())
+    }
+
+    /// Start watching the cache's own directory for keys being added
+    /// or removed. See `KeyCacheWatcher`.
+    pub fn watch(&self) -> Result<KeyCacheWatcher> { KeyCacheWatcher::new(&self.path) }
+
+    /// Rename the keys in the cache that are saved under a legacy file
+    /// extension to the names they'd be saved under today, returning
+    /// them as they are afterward, ordered by path. A legacy file for
+    /// a key that's also saved under its current name is removed if
+    /// the two are identical, and left alone otherwise. Fallback
+    /// directories are never touched.
+    pub fn migrate_legacy_filenames(&self) -> Result<Vec<CachedKeyInfo>> {
+        let _lock = self.lock_for_writing()?;
+        let mut migrated = Vec::new();
+        for entry in fs::read_dir(&self.path)? {
+            let legacy_path = entry?.path();
+            let key = match CachedKeyInfo::from_path(legacy_path.clone()) {
+                Some(key) => key,
+                None => continue,
+            };
+            let path = self.path
+                           .join(format!("{}.{}", key.named_revision, key.kind.extension()));
+            if path == legacy_path {
+                continue;
+            }
+            if !path.exists() {
+                fs::rename(&legacy_path, &path)?;
+            } else if fs::read(&path)? == fs::read(&legacy_path)? {
+                fs::remove_file(&legacy_path)?;
+            } else {
+                warn!("Not migrating {}, because {} already exists with different contents",
+                      legacy_path.display(),
+                      path.display());
+                continue;
+            }
+            migrated.push(CachedKeyInfo { path, ..key });
+        }
+        migrated.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(migrated)
+    }
+
+    /// Set the permissions of every secret key in the cache back to
+    /// those it would be written with, returning the paths of the keys
+    /// that needed it, ordered by file name. Keys in fallback
+    /// directories are left alone.
+    pub fn fix_permissions(&self) -> Result<Vec<PathBuf>> {
+        let mut fixed = Vec::new();
+        for key in self.list_keys()? {
+            if key.kind.is_secret()
+               && key.path.parent() == Some(self.path.as_path())
+               && Self::apply_permissions(&key.path, key.kind.permissions())?
+            {
+                fixed.push(key.path);
+            }
+        }
+        Ok(fixed)
+    }
+
+    /// Check every file named like a key in the cache's own directory,
+    /// reporting any that don't hold a valid key of the kind their
+    /// name says, that hold a key other than the one they're named
+    /// for, or whose permissions aren't those they'd be written with,
+    /// along with keys missing the other half of their pair. Nothing
+    /// in the cache is changed.
+    pub fn verify(&self) -> Result<CacheReport> {
+        let mut report = CacheReport::default();
+        let mut paths = fs::read_dir(&self.path)?.map(|entry| entry.map(|e| e.path()))
+                                                 .collect::<io::Result<Vec<_>>>()?;
+        paths.sort();
+
+        let mut keys = Vec::new();
+        for path in paths {
+            let filename = match path.file_name().and_then(|f| f.to_str()) {
+                Some(filename) => filename.to_string(),
+                None => continue,
+            };
+            let caps = match KEYFILE_RE.captures(&filename) {
+                Some(caps) if path.is_file() => caps,
+                _ => continue,
+            };
+            let unparseable = |path, reason: &str| {
+                UnparseableKey { path,
+                                 reason: reason.to_string() }
+            };
+
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    report.unparseable
+                          .push(unparseable(path, "File is not valid UTF-8"));
+                    continue;
+                }
+                Err(source) => return Err(Error::KeyRead { path, source }),
+            };
+            let version = content.lines().next().unwrap_or_default();
+            let kind = match KeyKind::of(version, &caps["suffix"], &caps["name"]) {
+                Some(kind) => kind,
+                None => {
+                    let reason = format!("Unsupported key version for its file extension: {}",
+                                         version);
+                    report.unparseable.push(unparseable(path, &reason));
+                    continue;
+                }
+            };
+            let named_revision = match kind.parse(&content) {
+                Ok(named_revision) => named_revision,
+                Err(reason) => {
+                    report.unparseable.push(unparseable(path, &reason));
+                    continue;
+                }
+            };
+            if filename != format!("{}.{}", named_revision, &caps["suffix"]) {
+                report.misnamed.push(MisnamedKey { path,
+                                                   named_revision });
+                continue;
+            }
+            if let Some((mode, expected)) = Self::unexpected_mode(&path, kind.permissions())? {
+                report.bad_permissions
+                      .push(BadPermissions { path: path.clone(),
+                                             mode,
+                                             expected });
+            }
+            keys.push(CachedKeyInfo { named_revision,
+                                      kind,
+                                      path });
+        }
+
+        for key in &keys {
+            let counterpart = match key.kind.counterpart() {
+                Some(counterpart) => counterpart,
+                None => continue,
+            };
+            if keys.iter()
+                   .any(|other| {
+                       other.kind == counterpart && other.named_revision == key.named_revision
+                   })
+            {
+                continue;
+            }
+            if key.kind.is_secret() {
+                report.secret_keys_without_public_keys.push(key.clone());
+            } else {
+                report.public_keys_without_secret_keys.push(key.clone());
+            }
+        }
+        Ok(report)
+    }
+
     /// Attempt to retrieve the specified signing key from the cache,
     /// if it exists and is valid.
     pub fn public_signing_key(&self,
@@ -213,6 +953,25 @@ impl KeyCache {
         self.fetch_specific_revision::<PublicOriginSigningKey>(named_revision)
     }
 
+    /// The specified public signing key, encoded for tools other than
+    /// Habitat.
+    pub fn export_public_key(&self,
+                             named_revision: &NamedRevision,
+                             format: PublicKeyFormat)
+                             -> Result<Vec<u8>> {
+        Ok(self.public_signing_key(named_revision)?.export(format))
+    }
+
+    /// Verify a detached signature of the contents of the given file
+    /// with the public signing key it names, returning the verified
+    /// Blake2b hash of the contents.
+    pub fn verify_detached<P>(&self, path: P, signature: &DetachedSignature) -> Result<Blake2bHash>
+        where P: AsRef<Path>
+    {
+        self.public_signing_key(signature.signer())?
+            .verify_file_detached(path, signature)
+    }
+
     pub fn secret_signing_key(&self,
                               named_revision: &NamedRevision)
                               -> Result<SecretOriginSigningKey> {
@@ -239,110 +998,934 @@ impl KeyCache {
         self.fetch_specific_revision::<BuilderSecretEncryptionKey>(named_revision)
     }
 
-    ////////////////////////////////////////////////////////////////////////
+    /// Decrypt a message addressed to a secret key in this cache,
+    /// looking up the keys it was encrypted with by the revisions it
+    /// names.
+    ///
+    /// Messages from a user to a service or to another user can be
+    /// decrypted, as can messages sealed anonymously for a service.
+    /// Missing keys are reported as `Error::RecipientKeyNotFound` or
+    /// `Error::SenderKeyNotFound`, and a message that the keys don't
+    /// open as `Error::MessageAuthenticationFailed`.
+    pub fn decrypt(&self, encrypted: &str) -> Result<Vec<u8>> {
+        match encrypted.parse()? {
+            ServiceMessage::Signed(signed_box) => {
+                let recipient = signed_box.decryptor();
+                let sender = signed_box.encryptor();
+                let decrypted = if recipient.name().contains('@') {
+                    let secret = self.recipient_key::<ServiceSecretEncryptionKey>(recipient)?;
+                    let public = self.sender_key::<UserPublicEncryptionKey>(sender)?;
+                    secret.decrypt_user_message(&signed_box, &public)
+                } else {
+                    let secret = self.recipient_key::<UserSecretEncryptionKey>(recipient)?;
+                    let public = self.sender_key::<UserPublicEncryptionKey>(sender)?;
+                    secret.decrypt_user_message(&signed_box, &public)
+                };
+                decrypted.map_err(|_| {
+                    Error::MessageAuthenticationFailed { sender:    sender.to_string(),
+                                                         recipient: recipient.to_string(), }
+                })
+            }
+            ServiceMessage::Anonymous(anonymous_box) => {
+                let recipient = anonymous_box.key_pair();
+                let secret = self.recipient_key::<ServiceSecretEncryptionKey>(recipient)?;
+                let sender = "an anonymous sender";
+                secret.unseal(&anonymous_box).map_err(|_| {
+                    Error::MessageAuthenticationFailed { sender:    sender.to_string(),
+                                                         recipient: recipient.to_string(), }
+                })
+            }
+        }
+    }
 
-    /// Write a pair of keys to the cache.
-    fn write_pair<P, S>(&self, public: &P, secret: &S) -> Result<()>
-        where P: KeyFile,
-              S: KeyFile
+    pub fn try_public_signing_key(&self,
+                                  named_revision: &NamedRevision)
+                                  -> Result<Option<PublicOriginSigningKey>> {
+        self.try_fetch_specific_revision(named_revision)
+    }
+
+    pub fn try_secret_signing_key(&self,
+                                  named_revision: &NamedRevision)
+                                  -> Result<Option<SecretOriginSigningKey>> {
+        self.try_fetch_specific_revision(named_revision)
+    }
+
+    pub fn try_user_public_encryption_key(&self,
+                                          named_revision: &NamedRevision)
+                                          -> Result<Option<UserPublicEncryptionKey>> {
+        self.try_fetch_specific_revision(named_revision)
+    }
+
+    pub fn try_service_secret_encryption_key(&self,
+                                             named_revision: &NamedRevision)
+                                             -> Result<Option<ServiceSecretEncryptionKey>> {
+        self.try_fetch_specific_revision(named_revision)
+    }
+
+    pub fn try_builder_secret_encryption_key(&self,
+                                             named_revision: &NamedRevision)
+                                             -> Result<Option<BuilderSecretEncryptionKey>> {
+        self.try_fetch_specific_revision(named_revision)
+    }
+
+    /// Remove the key of type `K` identified by `named_revision` from
+    /// the cache, returning the number of files removed. Only that one
+    /// key is removed; removing a secret key leaves its public key in
+    /// place (see `remove_pair`).
+    ///
+    /// Returns `Error::KeyNotFound` if the cache holds no such key.
+    pub fn remove_key<K>(&self, named_revision: &NamedRevision) -> Result<usize>
+        where K: KeyFile
     {
-        if public.named_revision() != secret.named_revision() {
-            return Err(Error::CryptoError(format!("Not saving key pair because \
-                                                   they are not actually a \
-                                                   pair! public: {}, secret: {}",
-                                                  public.named_revision(),
-                                                  secret.named_revision())));
+        fs::remove_file(self.existing_key_path::<K>(named_revision)?)?;
+        Ok(1)
+    }
+
+    /// Remove every revision of the key of type `K` named `name` from
+    /// the cache, returning the number of files removed. Keys of other
+    /// types with the same name are untouched.
+    pub fn remove_all_revisions<K>(&self, name: &str) -> Result<usize>
+        where K: KeyFile
+    {
+        let mut removed = 0;
+        let paths = Self::revisions_in::<K>(&self.path, name)?;
+        for (_, path) in paths.into_iter()
+                              .filter(|(_, path)| Self::holds_key_of_type::<K>(path))
+        {
+            fs::remove_file(path)?;
+            removed += 1;
         }
+        Ok(removed)
+    }
 
-        // TODO (CM): It would be interesting to make this an
-        // all-or-nothing operation, such that both keys were written
-        // or neither were. Once the errors in this part of the crate
-        // are cleaned up, we can better distinguish between different
-        // kinds of error conditions so we don't, say, delete a
-        // previously existing public key if writing a secret key
-        // fails.
-        self.write_key(public)?;
-        self.write_key(secret)?;
-        Ok(())
+    /// Remove both halves of the key pair identified by
+    /// `named_revision`, returning the number of files removed.
+    ///
+    /// Returns `Error::KeyNotFound`, and removes nothing, unless the
+    /// cache holds both keys.
+    pub fn remove_pair<P, S>(&self, named_revision: &NamedRevision) -> Result<usize>
+        where P: KeyFile,
+              S: KeyFile
+    {
+        let public = self.existing_key_path::<P>(named_revision)?;
+        let secret = self.existing_key_path::<S>(named_revision)?;
+        fs::remove_file(secret)?;
+        fs::remove_file(public)?;
+        Ok(2)
     }
 
-    /// Given the name and type of a key, fetch the latest revision of
-    /// that key from the cache.
+    /// Remove all but the newest `keep` revisions of the key of type
+    /// `K` named `name`, returning the revisions removed, oldest
+    /// first. The newest revision is always kept, even if `keep` is
+    /// 0. With `dry_run`, nothing is removed, but the revisions that
+    /// would be are still returned.
     ///
-    /// As it happens, we currently have no real need to distinguish
-    /// between "key not present" and "key present, but invalid", so
-    /// we can just collapse them into an Error case.
-    fn fetch_latest_revision<K>(&self, name: &str) -> Result<K>
-        where K: KeyFile + TryFrom<PathBuf, Error = Error>
-    {
-        match self.get_latest_path_for(name, <K as KeyFile>::extension())? {
-            Some(path) => <K as TryFrom<PathBuf>>::try_from(path),
-            None => {
-                let msg = format!("No revisions found for {}", name);
-                Err(Error::CryptoError(msg))
+    /// Use `prune_pairs` for keys that come in pairs, so that secret
+    /// keys aren't left behind without their public keys.
+    pub fn prune<K>(&self, name: &str, keep: usize, dry_run: bool) -> Result<Vec<NamedRevision>>
+        where K: KeyFile
+    {
+        let mut revisions = self.revisions_of::<K>(name)?;
+        revisions.truncate(revisions.len().saturating_sub(cmp::max(keep, 1)));
+        if !dry_run {
+            for named_revision in &revisions {
+                fs::remove_file(self.existing_key_path::<K>(named_revision)?)?;
             }
         }
+        Ok(revisions)
     }
 
-    /// Generic retrieval function to grab the key of the specified
-    /// type `K` identified by `named_revision`
-    fn fetch_specific_revision<K>(&self, named_revision: &NamedRevision) -> Result<K>
-        where K: KeyFile + TryFrom<PathBuf, Error = Error>
+    /// Like `prune`, but for key pairs: revisions are counted across
+    /// both halves, and an old revision is only removed if both of its
+    /// halves are present to be removed together.
+    pub fn prune_pairs<P, S>(&self,
+                             name: &str,
+                             keep: usize,
+                             dry_run: bool)
+                             -> Result<Vec<NamedRevision>>
+        where P: KeyFile,
+              S: KeyFile
     {
-        let path_in_cache = self.0.join(<K as KeyFile>::filename(named_revision));
-        if path_in_cache.exists() {
-            <K as TryFrom<PathBuf>>::try_from(path_in_cache)
-        } else {
-            Err(Error::CryptoError(format!("Key not found in cache: {}", path_in_cache.display())))
+        let public = self.revisions_of::<P>(name)?;
+        let secret = self.revisions_of::<S>(name)?;
+        let mut revisions = public.iter()
+                                  .chain(secret.iter())
+                                  .cloned()
+                                  .collect::<Vec<_>>();
+        revisions.sort_by(|a, b| a.revision().cmp(b.revision()));
+        revisions.dedup();
+        revisions.truncate(revisions.len().saturating_sub(cmp::max(keep, 1)));
+        revisions.retain(|r| public.contains(r) && secret.contains(r));
+        if !dry_run {
+            for named_revision in &revisions {
+                // Secret first, so that a failure can only ever leave
+                // a public key behind.
+                fs::remove_file(self.existing_key_path::<S>(named_revision)?)?;
+                fs::remove_file(self.existing_key_path::<P>(named_revision)?)?;
+            }
         }
+        Ok(revisions)
     }
 
-    ////////////////////////////////////////////////////////////////////////
-
-    /// Provides the path at which this file would be found in the
-    /// cache, if it exists (or, alternatively, where it would be
-    /// written to).
-    // TODO (CM): Only making this public temporarily
-    pub fn path_in_cache<K>(&self, key: &K) -> PathBuf
-        where K: KeyFile
+    /// Write a tar archive of every valid key named in `names` to
+    /// `writer`, returning the number of keys written. Secret keys
+    /// (including ring keys) are left out unless `include_secret_keys`
+    /// is set.
+    pub fn export_bundle<W>(&self,
+                            names: &[&str],
+                            include_secret_keys: bool,
+                            writer: W)
+                            -> Result<usize>
+        where W: Write
     {
-        self.0.join(key.own_filename())
+        let keys = self.list_keys()?
+                       .into_iter()
+                       .filter(|key| names.contains(&key.named_revision.name().as_str()))
+                       .filter(|key| include_secret_keys || !key.kind.is_secret())
+                       .collect::<Vec<_>>();
+
+        let mut builder = tar::Builder::new(writer);
+        for key in &keys {
+            let content = fs::read(&key.path)?;
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(if key.kind.is_secret() { 0o400 } else { 0o444 });
+            header.set_cksum();
+            let filename = key.path.file_name().expect("listed keys are files");
+            builder.append_data(&mut header, filename, content.as_slice())?;
+        }
+        builder.into_inner()?;
+        Ok(keys.len())
     }
 
-    /// Search the key cache for all files that are revisions of the
-    /// given key. Returns the full paths to those files within the
-    /// cache.
-    fn get_all_paths_for(&self,
-                         name: &str,
-                         key_extension: &str)
-                         -> Result<impl Iterator<Item = PathBuf>> {
-        // Ideally, we'd want that `*` to be `\d{14}` to match the
-        // structure of our revisions... perhaps that can be an
-        // additional filter later on with an actual regex?
-        let pattern = self.0.join(format!("{}-*.{}", name, key_extension));
-        let pattern = pattern.to_string_lossy();
+    /// Unpack a tar archive written by `export_bundle` into the cache,
+    /// returning the keys it contained. Keys already in the cache
+    /// with identical content are left as they are, but a key that
+    /// differs from one already in the cache is an error, as with
+    /// `write_key`. Keys are saved with the usual permissions for
+    /// their kind, whatever the archive says.
+    ///
+    /// Every key in the archive is validated, and checked against the
+    /// cache, before any is saved. An archive holding anything other
+    /// than valid key files is rejected.
+    pub fn import_bundle<R>(&self, reader: R) -> Result<Vec<CachedKeyInfo>>
+        where R: Read
+    {
+        fn invalid_entry(path: PathBuf, reason: &str) -> Error {
+            Error::KeyParse { path,
+                              reason: reason.to_string() }
+        }
 
-        // TODO (CM): this is a bogus error
-        Ok(glob::glob(&pattern).map_err(|_e| Error::CryptoError("Couldn't glob!".to_string()))?
-                               .filter_map(std::result::Result::ok)
-                               .filter(|p| p.metadata().map(|m| m.is_file()).unwrap_or(false)))
+        let _lock = self.lock_for_writing()?;
+        let mut keys: Vec<CachedKeyInfo> = Vec::new();
+        let mut staged = Vec::new();
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+            if !entry.header().entry_type().is_file() {
+                return Err(invalid_entry(path, "Not a regular file"));
+            }
+            let filename = match path.to_str() {
+                Some(filename) if path.file_name() == Some(path.as_os_str()) => {
+                    filename.to_string()
+                }
+                _ => return Err(invalid_entry(path, "Not at the top level of the bundle")),
+            };
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+
+            let (kind, named_revision) = match identify(&filename, &content) {
+                Some(identified) => identified,
+                None => return Err(invalid_entry(path, "Not a key named for its contents")),
+            };
+            // Keys are imported under their current file extension,
+            // whatever they were called in the bundle.
+            let path = self.path
+                           .join(format!("{}.{}", named_revision, kind.extension()));
+            if keys.iter().any(|key| key.path == path) {
+                return Err(invalid_entry(path, "Appears more than once in the bundle"));
+            }
+            if let Some(staged_write) = self.stage_key_of_kind(kind, &content)? {
+                staged.push(staged_write);
+            }
+            keys.push(CachedKeyInfo { named_revision,
+                                      kind,
+                                      path });
+        }
+        for staged_write in staged {
+            staged_write.commit()?;
+        }
+        Ok(keys)
     }
 
-    /// Given a key name and extension, find the path that corresponds
-    /// to the most recent revision of that key in the cache, if it
-    /// exists.
-    fn get_latest_path_for(&self, name: &str, key_extension: &str) -> Result<Option<PathBuf>> {
-        Ok(self.get_all_paths_for(name, key_extension)?.max())
+    ////////////////////////////////////////////////////////////////////////
+
+    /// Write a pair of keys to the cache.
+    fn write_pair<P, S>(&self, public: &P, secret: &S) -> Result<()>
+        where P: KeyFile,
+              S: KeyFile
+    {
+        let _lock = self.lock_for_writing()?;
+        self.write_pair_while_locked(public, secret)
     }
-}
 
-#[cfg(test)]
-mod test {
+    /// `write_pair`, for callers already holding the lock on the cache.
+    fn write_pair_while_locked<P, S>(&self, public: &P, secret: &S) -> Result<()>
+        where P: KeyFile,
+              S: KeyFile
+    {
+        if public.named_revision() != secret.named_revision() {
+            return Err(Error::CryptoError(format!("Not saving key pair because \
+                                                   they are not actually a \
+                                                   pair! public: {}, secret: {}",
+                                                  public.named_revision(),
+                                                  secret.named_revision())));
+        }
+
+        // Write both keys out before renaming either into place, so
+        // a failure writing one leaves nothing of the pair
+        // behind. Any staged file is removed when dropped.
+        let staged_public = self.stage_key(public)?;
+        let staged_secret = self.stage_key(secret)?;
+
+        let public_path = staged_public.as_ref()
+                                       .map(|staged| staged.dest().to_path_buf());
+        if let Some(staged) = staged_public {
+            staged.commit()?;
+        }
+        if let Some(staged) = staged_secret {
+            if let Err(e) = staged.commit() {
+                // Only a public key that didn't exist before this call
+                // was staged, so it's safe to take it back out.
+                if let Some(path) = public_path {
+                    if let Err(remove_err) = fs::remove_file(&path) {
+                        warn!("Failed to remove public key {} after failing to write its secret \
+                               key: {}",
+                              path.display(),
+                              remove_err);
+                    }
+                }
+                return Err(e.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// `write_key`, for callers already holding the lock on the cache.
+    fn write_key_while_locked<K>(&self, key: &K) -> Result<()>
+        where K: KeyFile
+    {
+        if let Some(staged) = self.stage_key(key)? {
+            staged.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Take the lock on the cache's directory, waiting for any other
+    /// writer to release it first. The lock is held until the returned
+    /// file is dropped.
+    ///
+    /// Returns `Error::KeyCacheLocked` if the lock can't be taken
+    /// before the cache's lock timeout runs out.
+    fn lock_for_writing(&self) -> Result<fs::File> {
+        let path = self.path.join(LOCK_FILE);
+        let file = fs::OpenOptions::new().create(true)
+                                         .write(true)
+                                         .open(&path)?;
+        let start = Instant::now();
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => return Ok(file),
+                Err(e) if e.kind() == fs2::lock_contended_error().kind() => {
+                    if start.elapsed() >= self.lock_timeout {
+                        return Err(Error::KeyCacheLocked { path,
+                                                           timeout: self.lock_timeout });
+                    }
+                    thread::sleep(Duration::from_millis(50));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Prepare to write `key` to the cache, returning the staged file
+    /// to commit, or `None` if an identical key is already present.
+    /// A different key that is already present at the same path is
+    /// an error; it is never overwritten.
+    ///
+    /// Keys are generated with names that have already been checked,
+    /// but they're checked again here, since a key from anywhere else
+    /// (like one being imported) could be named for a path outside
+    /// the cache.
+    fn stage_key<K>(&self, key: &K) -> Result<Option<StagedWrite>>
+        where K: KeyFile
+    {
+        validate_key_name(key.named_revision().name())?;
+        let keyfile = self.path_for::<K>(key.named_revision());
+        let content = key.to_key_string();
+
+        if keyfile.is_file() {
+            let new_hash = Blake2bHash::from_bytes(&content);
+            let existing_hash = Blake2bHash::from_file(&keyfile)?;
+            if existing_hash != new_hash {
+                let msg = format!("Existing key file {} found but new version hash is different, \
+                                   failing to write new file over existing. (existing = {}, \
+                                   incoming = {})",
+                                  keyfile.display(),
+                                  existing_hash,
+                                  new_hash);
+                return Err(Error::CryptoError(msg));
+            }
+            Ok(None)
+        } else {
+            // Technically speaking, this probably doesn't really need
+            // to be an atomic write process, since we just tested
+            // that the file doesn't currently exist. It does,
+            // however, bundle up writing with platform-independent
+            // permission setting, which is *super* convenient.
+            let w = AtomicWriter::new_with_permissions(&keyfile, K::permissions())?;
+            let ((), staged) = w.stage(|f| f.write_all(content.as_ref()))?;
+            Ok(Some(staged))
+        }
+    }
+
+    /// Given the name and type of a key, fetch the latest revision of
+    /// that key from the cache.
+    ///
+    /// Returns `Error::KeyNotFound` if there are no revisions of the
+    /// key, or `Error::KeyParse` if the latest revision is invalid.
+    pub(super) fn fetch_latest_revision<K>(&self, name: &str) -> Result<K>
+        where K: KeyFile + FromStr<Err = Error>
+    {
+        match self.try_fetch_latest_revision(name)? {
+            Some(key) => Ok(key),
+            None => {
+                Err(Error::KeyNotFound { name:     name.to_string(),
+                                         key_type: K::key_type(), })
+            }
+        }
+    }
+
+    /// Every revision of the key of type `K` named `name`, newest
+    /// first. An invalid revision is an error.
+    fn fetch_all_revisions<K>(&self, name: &str) -> Result<Vec<K>>
+        where K: KeyFile + FromStr<Err = Error>
+    {
+        let mut paths = self.get_all_paths_for::<K>(name)?;
+        paths.sort_by(|(a, _), (b, _)| b.cmp(a));
+        paths.into_iter()
+             .map(|(_, path)| self.read_key(path))
+             .collect()
+    }
+
+    /// Like `fetch_all_revisions`, but only an invalid latest revision
+    /// is an error; invalid earlier ones are skipped with a warning.
+    fn fetch_latest_and_valid_previous_revisions<K>(&self, name: &str) -> Result<Vec<K>>
+        where K: KeyFile + FromStr<Err = Error>
+    {
+        let mut paths = self.get_all_paths_for::<K>(name)?;
+        paths.sort_by(|(a, _), (b, _)| b.cmp(a));
+        let mut paths = paths.into_iter().map(|(_, path)| path);
+        let mut keys = match paths.next() {
+            Some(latest) => vec![self.read_key(latest)?],
+            None => return Ok(Vec::new()),
+        };
+        for path in paths {
+            match self.read_key(path) {
+                Ok(key) => keys.push(key),
+                Err(e) => warn!("Skipping invalid revision of {}: {}", name, e),
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Like `fetch_latest_revision`, but returns `None` if there are
+    /// no revisions of the key. An invalid latest revision is still
+    /// an error.
+    fn try_fetch_latest_revision<K>(&self, name: &str) -> Result<Option<K>>
+        where K: KeyFile + FromStr<Err = Error>
+    {
+        self.get_latest_path_for::<K>(name)?
+            .map(|path| self.read_key(path))
+            .transpose()
+    }
+
+    /// Like `fetch_latest_revision`, but skips over revisions that
+    /// have expired as of `now`.
+    ///
+    /// Returns `Error::KeyNotFound` if there are no revisions of the
+    /// key, or `Error::KeyExpired` if every one of them has expired.
+    fn fetch_latest_valid_revision<K>(&self, name: &str, now: DateTime<Utc>) -> Result<K>
+        where K: KeyFile + FromStr<Err = Error>
+    {
+        let mut paths = self.get_all_paths_for::<K>(name)?;
+        if paths.is_empty() {
+            return Err(Error::KeyNotFound { name:     name.to_string(),
+                                            key_type: K::key_type(), });
+        }
+        paths.sort_by(|(a, _), (b, _)| b.cmp(a));
+        for (_, path) in paths {
+            let key: K = self.read_key(path)?;
+            if !key.is_expired(now) {
+                return Ok(key);
+            }
+        }
+        Err(Error::KeyExpired { name:     name.to_string(),
+                                key_type: K::key_type(), })
+    }
+
+    /// Given the name and types of a key pair, fetch the latest
+    /// revision of that pair for which the cache holds both keys, so
+    /// that the two always share a `NamedRevision`.
+    ///
+    /// Returns `Error::KeyPairNotFound` if there is no such revision.
+    fn fetch_latest_pair<P, S>(&self, name: &str) -> Result<(P, S)>
+        where P: KeyFile + FromStr<Err = Error>,
+              S: KeyFile + FromStr<Err = Error>
+    {
+        match self.try_fetch_latest_pair(name)? {
+            Some(pair) => Ok(pair),
+            None => {
+                Err(Error::KeyPairNotFound { name:        name.to_string(),
+                                             public_type: P::key_type(),
+                                             secret_type: S::key_type(), })
+            }
+        }
+    }
+
+    /// Like `fetch_latest_pair`, but returns `None` if there is no
+    /// revision for which the cache holds both keys.
+    fn try_fetch_latest_pair<P, S>(&self, name: &str) -> Result<Option<(P, S)>>
+        where P: KeyFile + FromStr<Err = Error>,
+              S: KeyFile + FromStr<Err = Error>
+    {
+        let mut public = self.get_all_paths_for::<P>(name)?
+                             .into_iter()
+                             .collect::<HashMap<_, _>>();
+        let latest = self.get_all_paths_for::<S>(name)?
+                         .into_iter()
+                         .filter_map(|(revision, secret)| {
+                             let public = public.remove(&revision)?;
+                             Some((revision, public, secret))
+                         })
+                         .max_by(|(a, ..), (b, ..)| a.cmp(b));
+        latest.map(|(_, public, secret)| Ok((self.read_key(public)?, self.read_key(secret)?)))
+              .transpose()
+    }
+
+    /// Generic retrieval function to grab the key of the specified
+    /// type `K` identified by `named_revision`
+    pub(super) fn fetch_specific_revision<K>(&self, named_revision: &NamedRevision) -> Result<K>
+        where K: KeyFile + FromStr<Err = Error>
+    {
+        self.read_key(self.find_key_path::<K>(named_revision)?)
+    }
+
+    /// Like `fetch_specific_revision`, but a missing key is reported
+    /// as the missing recipient of an encrypted message.
+    fn recipient_key<K>(&self, named_revision: &NamedRevision) -> Result<K>
+        where K: KeyFile + FromStr<Err = Error>
+    {
+        self.fetch_specific_revision(named_revision)
+            .map_err(|e| {
+                match e {
+                    Error::KeyNotFound { name, key_type } => {
+                        Error::RecipientKeyNotFound { name, key_type }
+                    }
+                    e => e,
+                }
+            })
+    }
+
+    /// Like `fetch_specific_revision`, but a missing key is reported
+    /// as the missing sender of an encrypted message.
+    fn sender_key<K>(&self, named_revision: &NamedRevision) -> Result<K>
+        where K: KeyFile + FromStr<Err = Error>
+    {
+        self.fetch_specific_revision(named_revision)
+            .map_err(|e| {
+                match e {
+                    Error::KeyNotFound { name, key_type } => {
+                        Error::SenderKeyNotFound { name, key_type }
+                    }
+                    e => e,
+                }
+            })
+    }
+
+    /// Like `fetch_specific_revision`, but returns `None` if the cache
+    /// holds no such key. An invalid key is still an error.
+    fn try_fetch_specific_revision<K>(&self, named_revision: &NamedRevision) -> Result<Option<K>>
+        where K: KeyFile + FromStr<Err = Error>
+    {
+        self.try_find_key_path::<K>(named_revision)
+            .map(|path| self.read_key(path))
+            .transpose()
+    }
+
+    /// The path to the file the key of type `K` identified by
+    /// `named_revision` is read from. This may be in a fallback
+    /// directory, or under the key's legacy file extension, so it
+    /// isn't necessarily `path_for`.
+    pub fn find_key_path<K>(&self, named_revision: &NamedRevision) -> Result<PathBuf>
+        where K: KeyFile
+    {
+        match self.try_find_key_path::<K>(named_revision) {
+            Some(path) => Ok(path),
+            None => {
+                Err(Error::KeyNotFound { name:     named_revision.to_string(),
+                                         key_type: K::key_type(), })
+            }
+        }
+    }
+
+    /// Whether the cache, including its fallback directories, holds
+    /// the key of type `K` identified by `named_revision`. The file
+    /// has to start with `K`'s version line, so a different type of
+    /// key saved under the same name (as public signing and
+    /// encryption keys once were) doesn't count. See `contains_valid`
+    /// to make sure the key is usable, too.
+    pub fn contains<K>(&self, named_revision: &NamedRevision) -> bool
+        where K: KeyFile
+    {
+        self.try_find_key_path::<K>(named_revision)
+            .map_or(false, |path| Self::holds_key_of_type::<K>(&path))
+    }
+
+    /// Like `contains`, but the key must also be one that can be
+    /// read from the cache; that is, it must parse as a key of type
+    /// `K` and, if it is a secret key, have safe permissions.
+    pub fn contains_valid<K>(&self, named_revision: &NamedRevision) -> bool
+        where K: KeyFile + FromStr<Err = Error>
+    {
+        matches!(self.try_fetch_specific_revision::<K>(named_revision),
+                 Ok(Some(_)))
+    }
+
+    /// The path the key of type `K` identified by `named_revision` is
+    /// written to in the cache's own directory, whether it is there
+    /// or not. Use `find_key_path` for where an existing key is read
+    /// from.
+    pub fn path_for<K>(&self, named_revision: &NamedRevision) -> PathBuf
+        where K: KeyFile
+    {
+        self.path.join(K::filename(named_revision))
+    }
+
+    /// Like `find_key_path`, but returns `None` if the cache holds no
+    /// such key.
+    fn try_find_key_path<K>(&self, named_revision: &NamedRevision) -> Option<PathBuf>
+        where K: KeyFile
+    {
+        self.layers()
+            .find_map(|dir| Self::key_path_in::<K>(dir, named_revision))
+    }
+
+    /// Read and parse the key file at `path`. Any failure to make
+    /// sense of the file's contents, including it holding a different
+    /// type of key, is reported as `Error::KeyParse`. A valid secret
+    /// key that other users can read is reported as
+    /// `Error::InsecureKeyPermissions`.
+    fn read_key<K>(&self, path: PathBuf) -> Result<K>
+        where K: KeyFile + FromStr<Err = Error>
+    {
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                return Err(Error::KeyParse { path,
+                                             reason: "File is not valid UTF-8".to_string() });
+            }
+            Err(source) => return Err(Error::KeyRead { path, source }),
+        };
+        let key = content.parse().map_err(|e| {
+                                      let reason = match e {
+                                          Error::CryptoError(reason) => reason,
+                                          e => e.to_string(),
+                                      };
+                                      Error::KeyParse { path: path.clone(),
+                                                        reason }
+                                  })?;
+        if self.check_permissions {
+            Self::check_permissions(&path, K::permissions())?;
+        }
+        Ok(key)
+    }
+
+    /// Make sure that other users can't read the file at `path` if
+    /// `permissions` would keep them from it.
+    #[cfg(not(windows))]
+    fn check_permissions(path: &Path, permissions: Permissions) -> Result<()> {
+        if let Permissions::Explicit(expected) = permissions {
+            let mode = fs::metadata(path)?.permissions().mode() & 0o777;
+            if expected & 0o044 == 0 && mode & 0o044 != 0 {
+                return Err(Error::InsecureKeyPermissions { path: path.to_path_buf(),
+                                                           mode });
+            }
+        }
+        Ok(())
+    }
+
+    /// Secret keys are saved with the same permissions as any other
+    /// file on Windows, so there's nothing to check.
+    #[cfg(windows)]
+    fn check_permissions(_path: &Path, _permissions: Permissions) -> Result<()> { Ok(()) }
+
+    /// The mode of the file at `path`, and the one `permissions` calls
+    /// for, if they differ.
+    #[cfg(not(windows))]
+    fn unexpected_mode(path: &Path, permissions: Permissions) -> Result<Option<(u32, u32)>> {
+        if let Permissions::Explicit(expected) = permissions {
+            let mode = fs::metadata(path)?.permissions().mode() & 0o777;
+            if mode != expected {
+                return Ok(Some((mode, expected)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Keys are saved with the same permissions as any other file on
+    /// Windows, so there's nothing to compare.
+    #[cfg(windows)]
+    fn unexpected_mode(_path: &Path, _permissions: Permissions) -> Result<Option<(u32, u32)>> {
+        Ok(None)
+    }
+
+    /// Set the permissions of the file at `path` to `permissions`,
+    /// returning whether they needed changing.
+    #[cfg(not(windows))]
+    fn apply_permissions(path: &Path, permissions: Permissions) -> Result<bool> {
+        if let Permissions::Explicit(expected) = permissions {
+            if fs::metadata(path)?.permissions().mode() & 0o777 != expected {
+                posix_perm::set_permissions(path, expected)?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Keys are saved with the same permissions as any other file on
+    /// Windows, so there's nothing to apply.
+    #[cfg(windows)]
+    fn apply_permissions(_path: &Path, _permissions: Permissions) -> Result<bool> { Ok(false) }
+
+    /// The path to the key of type `K` identified by `named_revision`,
+    /// if the cache holds it.
+    fn existing_key_path<K>(&self, named_revision: &NamedRevision) -> Result<PathBuf>
+        where K: KeyFile
+    {
+        Self::key_path_in::<K>(&self.path, named_revision)
+            .filter(|path| Self::holds_key_of_type::<K>(path))
+            .ok_or_else(|| {
+                Error::KeyNotFound { name:     named_revision.to_string(),
+                                     key_type: K::key_type(), }
+            })
+    }
+
+    /// The path to the file in `dir` holding the key of type `K`
+    /// identified by `named_revision`, if there is one. A file under
+    /// the legacy extension only counts if it holds a key of type `K`,
+    /// since other kinds of key were saved under the same name.
+    fn key_path_in<K>(dir: &Path, named_revision: &NamedRevision) -> Option<PathBuf>
+        where K: KeyFile
+    {
+        let path = dir.join(K::filename(named_revision));
+        if path.is_file() {
+            return Some(path);
+        }
+        let legacy_path = dir.join(format!("{}.{}", named_revision, K::legacy_extension()?));
+        if legacy_path.is_file() && Self::holds_key_of_type::<K>(&legacy_path) {
+            Some(legacy_path)
+        } else {
+            None
+        }
+    }
+
+    /// `stage_key` for a key of the given kind that hasn't been parsed
+    /// yet.
+    fn stage_key_of_kind(&self, kind: KeyKind, content: &str) -> Result<Option<StagedWrite>> {
+        match kind {
+            KeyKind::Ring => self.stage_key(&content.parse::<RingKey>()?),
+            KeyKind::SigningPublic => self.stage_key(&content.parse::<PublicOriginSigningKey>()?),
+            KeyKind::SigningSecret => self.stage_key(&content.parse::<SecretOriginSigningKey>()?),
+            KeyKind::UserOrOriginEncryptionPublic => {
+                self.stage_key(&content.parse::<UserPublicEncryptionKey>()?)
+            }
+            KeyKind::UserOrOriginEncryptionSecret => {
+                self.stage_key(&content.parse::<UserSecretEncryptionKey>()?)
+            }
+            KeyKind::ServiceEncryptionPublic => {
+                self.stage_key(&content.parse::<ServicePublicEncryptionKey>()?)
+            }
+            KeyKind::ServiceEncryptionSecret => {
+                self.stage_key(&content.parse::<ServiceSecretEncryptionKey>()?)
+            }
+            KeyKind::BuilderEncryptionSecret => {
+                self.stage_key(&content.parse::<BuilderSecretEncryptionKey>()?)
+            }
+        }
+    }
+
+    /// Every revision of the key of type `K` named `name` in the
+    /// cache, oldest first.
+    fn revisions_of<K>(&self, name: &str) -> Result<Vec<NamedRevision>>
+        where K: KeyFile
+    {
+        let paths = Self::revisions_in::<K>(&self.path, name)?;
+        let mut revisions =
+            paths.into_iter()
+                 .filter(|(_, path)| Self::holds_key_of_type::<K>(path))
+                 .map(|(revision, _)| NamedRevision::from_parts(name.to_string(), revision))
+                 .collect::<Vec<_>>();
+        revisions.sort_by(|a, b| a.revision().cmp(b.revision()));
+        revisions.dedup();
+        Ok(revisions)
+    }
+
+    /// Whether the file at `path` is in the file format of keys of
+    /// type `K`, judging by its version line. Some kinds of key share
+    /// an extension, so the file name alone isn't enough to go on.
+    fn holds_key_of_type<K>(path: &Path) -> bool
+        where K: KeyFile
+    {
+        fs::File::open(path).ok()
+                            .and_then(|f| io::BufReader::new(f).lines().next())
+                            .and_then(result::Result::ok)
+                            .map_or(false, |line| line == K::version())
+    }
+
+    ////////////////////////////////////////////////////////////////////////
+
+    /// `path_for` the given key; a shorthand for tests.
+    #[cfg(test)]
+    pub(crate) fn path_in_cache<K>(&self, key: &K) -> PathBuf
+        where K: KeyFile
+    {
+        self.path_for::<K>(key.named_revision())
+    }
+
+    /// Search the key cache, including its fallback directories, for
+    /// all files that are revisions of the key of type `K` named
+    /// `name`. Returns each revision with the full path to its file,
+    /// leaving out any shadowed by a file for the same revision in an
+    /// earlier directory, or under the current extension.
+    fn get_all_paths_for<K>(&self, name: &str) -> Result<Vec<(KeyRevision, PathBuf)>>
+        where K: KeyFile
+    {
+        let mut seen = HashSet::new();
+        let mut paths = Vec::new();
+        for dir in self.layers() {
+            for (revision, path) in Self::revisions_in::<K>(dir, name)? {
+                if seen.insert(revision.clone()) {
+                    paths.push((revision, path));
+                }
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Every revision of the key of type `K` named `name` in `dir`,
+    /// with the path to its file; those under the current extension
+    /// come first. A file under the legacy extension only counts if it
+    /// holds a key of type `K`, since other kinds of key were saved
+    /// under the same name.
+    fn revisions_in<K>(dir: &Path, name: &str) -> Result<Vec<(KeyRevision, PathBuf)>>
+        where K: KeyFile
+    {
+        let mut revisions = Self::paths_in(dir, name, K::extension())?;
+        if let Some(legacy_extension) = K::legacy_extension() {
+            revisions.extend(Self::paths_in(dir, name, legacy_extension)?
+                                 .into_iter()
+                                 .filter(|(_, path)| Self::holds_key_of_type::<K>(path)));
+        }
+        Ok(revisions)
+    }
+
+    /// The directories keys are read from, most important first.
+    fn layers(&self) -> impl Iterator<Item = &Path> {
+        std::iter::once(self.path.as_path()).chain(self.fallbacks.iter().map(PathBuf::as_path))
+    }
+
+    /// All files in `dir` that are revisions of the given key, with
+    /// their revisions. A directory that doesn't exist holds none.
+    fn paths_in(dir: &Path,
+                name: &str,
+                key_extension: &str)
+                -> Result<Vec<(KeyRevision, PathBuf)>> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut paths = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if let Some(revision) = Self::revision_in(&entry.file_name(), name, key_extension) {
+                let path = entry.path();
+                if path.is_file() {
+                    paths.push((revision, path));
+                }
+            }
+        }
+        Ok(paths)
+    }
+
+    /// The revision in `file_name`, if it's the name of a revision of
+    /// the key named `name` with extension `key_extension`; that is,
+    /// `{name}-{revision}.{key_extension}`, exactly. Names are
+    /// compared as plain strings, so any characters in them are taken
+    /// literally.
+    fn revision_in(file_name: &OsStr, name: &str, key_extension: &str) -> Option<KeyRevision> {
+        file_name.to_str()?
+                 .strip_prefix(name)?
+                 .strip_prefix('-')?
+                 .strip_suffix(key_extension)?
+                 .strip_suffix('.')?
+                 .parse()
+                 .ok()
+    }
+
+    /// The latest revision of the key of type `K` named `name`, if
+    /// there is one, without reading the key.
+    fn latest_revision_for<K>(&self, name: &str) -> Result<Option<KeyRevision>>
+        where K: KeyFile
+    {
+        Ok(self.get_all_paths_for::<K>(name)?
+               .into_iter()
+               .map(|(revision, _)| revision)
+               .max())
+    }
+
+    /// The revision to give a key rotated from `previous`.
+    fn rotated_revision(previous: Option<&KeyRevision>) -> KeyRevision {
+        previous.map_or_else(KeyRevision::new, KeyRevision::new_after)
+    }
+
+    /// Given a key name and type, find the path that corresponds to
+    /// the most recent revision of that key in the cache, if it
+    /// exists.
+    fn get_latest_path_for<K>(&self, name: &str) -> Result<Option<PathBuf>>
+        where K: KeyFile
+    {
+        Ok(self.get_all_paths_for::<K>(name)?
+               .into_iter()
+               .max_by(|(a, _), (b, _)| a.cmp(b))
+               .map(|(_, path)| path))
+    }
+}
+
+#[cfg(test)]
+mod test {
     use super::*;
-    use crate::crypto::{keys::{generate_origin_encryption_key_pair,
+    use crate::crypto::{keys::{generate_builder_encryption_key,
+                               generate_origin_encryption_key_pair,
+                               generate_origin_encryption_key_pair_with,
                                generate_service_encryption_key_pair,
+                               generate_service_encryption_key_pair_with,
                                generate_signing_key_pair,
+                               generate_signing_key_pair_with,
                                generate_user_encryption_key_pair,
+                               generate_user_encryption_key_pair_with,
                                Key,
                                KeyFile,
                                OriginSecretEncryptionKey},
@@ -350,192 +1933,2025 @@ mod test {
     static VALID_KEY: &str = "ring-key-valid-20160504220722.sym.key";
     static VALID_NAME_WITH_REV: &str = "ring-key-valid-20160504220722";
 
-    /// Helper to call `cache.get_all_paths_for`, but return a list of
-    /// the plain file names, rather than full paths in the
-    /// cache. This makes testing a bit more straightforward, and less
-    /// verbose.
-    fn ring_key_paths(cache: &KeyCache, name: &str) -> Vec<PathBuf> {
-        cache.get_all_paths_for(name, RingKey::extension())
-             .unwrap()
-             .map(|pb| Path::new(pb.file_name().unwrap()).to_path_buf())
-             .collect()
+    /// Helper to call `cache.get_all_paths_for`, but return a list of
+    /// the plain file names, rather than full paths in the
+    /// cache. This makes testing a bit more straightforward, and less
+    /// verbose.
+    fn ring_key_paths(cache: &KeyCache, name: &str) -> Vec<PathBuf> {
+        cache.get_all_paths_for::<RingKey>(name)
+             .unwrap()
+             .into_iter()
+             .map(|(_, pb)| Path::new(pb.file_name().unwrap()).to_path_buf())
+             .collect()
+    }
+
+    #[test]
+    fn get_all_paths_for() {
+        let (cache, _dir) = new_cache();
+
+        let ring_name = "beyonce";
+
+        let paths = ring_key_paths(&cache, ring_name);
+        assert!(paths.is_empty());
+
+        let mut rng = seeded_rng();
+        let k1 = RingKey::new_with(ring_name, &mut rng, revision(1));
+        cache.write_key(&k1).unwrap();
+        let paths = ring_key_paths(&cache, ring_name);
+        assert_eq!(paths.len(), 1);
+        assert!(paths.contains(&k1.own_filename()));
+
+        let k2 = RingKey::new_with(ring_name, &mut rng, revision(2));
+        cache.write_key(&k2).unwrap();
+
+        let paths = ring_key_paths(&cache, ring_name);
+        assert_eq!(paths.len(), 2);
+        assert!(paths.contains(&k1.own_filename()));
+        assert!(paths.contains(&k2.own_filename()));
+
+        // We should not include another named key in the count
+        let other = RingKey::new("jayz");
+        cache.write_key(&other).unwrap();
+        let paths = ring_key_paths(&cache, ring_name);
+        assert_eq!(paths.len(), 2);
+        assert!(!paths.contains(&other.own_filename()));
+        assert!(paths.contains(&k1.own_filename()));
+        assert!(paths.contains(&k2.own_filename()));
+    }
+
+    #[test]
+    fn revisions_generated_within_the_same_second_are_kept_apart() {
+        let (cache, _dir) = new_cache();
+        let mut rng = seeded_rng();
+        let old = RingKey::new_with("beyonce", &mut rng, revision(1));
+        let new = RingKey::new_with("beyonce", &mut rng, revision(2));
+        cache.write_key(&old).unwrap();
+        cache.write_key(&new).unwrap();
+
+        assert_ne!(old.key(), new.key());
+        assert_eq!(ring_key_paths(&cache, "beyonce").len(), 2);
+        assert_eq!(cache.latest_ring_key_revision("beyonce").unwrap(), new);
+    }
+
+    #[test]
+    fn get_all_paths_for_ignores_decoys() {
+        let (cache, _dir) = new_cache();
+        let key = cache.new_ring_key("beyonce").unwrap();
+        for decoy in &["beyonce-backup.sym.key",
+                       "beyonce-2016050422072.sym.key",
+                       "beyonce-201605042207222.sym.key",
+                       "beyonce-20160504220722.sym.key.bak"]
+        {
+            std::fs::write(cache.as_ref().join(decoy), key.to_key_string()).unwrap();
+        }
+
+        assert_eq!(ring_key_paths(&cache, "beyonce"), vec![key.own_filename()]);
+        assert_eq!(cache.latest_ring_key_revision("beyonce").unwrap(), key);
+    }
+
+    #[test]
+    fn get_all_paths_for_ignores_names_with_the_same_prefix() {
+        let (cache, _dir) = new_cache();
+        let mut rng = seeded_rng();
+        let svc = RingKey::new_with("svc", &mut rng, revision(1));
+        let svc_worker = RingKey::new_with("svc-worker", &mut rng, revision(2));
+        cache.write_key(&svc).unwrap();
+        cache.write_key(&svc_worker).unwrap();
+
+        assert_eq!(ring_key_paths(&cache, "svc"), vec![svc.own_filename()]);
+        assert_eq!(cache.latest_ring_key_revision("svc").unwrap(), svc);
+        assert_eq!(cache.latest_ring_key_revision("svc-worker").unwrap(),
+                   svc_worker);
+    }
+
+    #[test]
+    fn get_all_paths_for_takes_names_literally() {
+        let (cache, _dir) = new_cache();
+        let mut rng = seeded_rng();
+        // Keys with names like these can't be made through the cache
+        // any more, but could have been put there by hand.
+        for (name, decoy) in &[("foo.default@my[org]", "foo.default@myo"),
+                               ("foo*", "foobar"),
+                               ("f.o", "fxo"),
+                               ("wh?t", "what")]
+        {
+            let key = RingKey::new_with(name, &mut rng, revision(1));
+            let decoy = RingKey::new_with(decoy, &mut rng, revision(2));
+            for k in &[&key, &decoy] {
+                std::fs::write(cache.as_ref().join(k.own_filename()), k.to_key_string()).unwrap();
+            }
+
+            assert_eq!(ring_key_paths(&cache, name), vec![key.own_filename()]);
+            assert_eq!(cache.latest_ring_key_revision(name).unwrap(), key);
+        }
+    }
+
+    #[test]
+    fn latest_cached_revision_nonexistent() {
+        let (cache, _dir) = new_cache();
+        match cache.latest_ring_key_revision("nope-nope") {
+            Err(Error::KeyNotFound { name, key_type }) => {
+                assert_eq!(name, "nope-nope");
+                assert_eq!(key_type, "RingKey");
+            }
+            other => panic!("Expected KeyNotFound, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn specific_revision_nonexistent() {
+        let (cache, _dir) = new_cache();
+        let named_revision: NamedRevision = VALID_NAME_WITH_REV.parse().unwrap();
+        match cache.public_signing_key(&named_revision) {
+            Err(Error::KeyNotFound { name, key_type }) => {
+                assert_eq!(name, VALID_NAME_WITH_REV);
+                assert_eq!(key_type, "PublicOriginSigningKey");
+            }
+            other => panic!("Expected KeyNotFound, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn try_lookups_of_missing_keys_are_none() {
+        let (cache, _dir) = new_cache();
+        let named_revision: NamedRevision = VALID_NAME_WITH_REV.parse().unwrap();
+        assert!(cache.try_latest_ring_key_revision("ring-key-valid")
+                     .unwrap()
+                     .is_none());
+        assert!(cache.try_public_signing_key(&named_revision)
+                     .unwrap()
+                     .is_none());
+
+        let key = cache.new_ring_key("ring-key-valid").unwrap();
+        assert_eq!(cache.try_latest_ring_key_revision("ring-key-valid")
+                        .unwrap(),
+                   Some(key));
+    }
+
+    #[test]
+    fn try_lookups_of_corrupt_keys_are_errors() {
+        let (cache, dir) = new_cache();
+        std::fs::write(dir.path().join(VALID_KEY), "SYM-SEC-1\ngarbage").unwrap();
+        let named_revision: NamedRevision = VALID_NAME_WITH_REV.parse().unwrap();
+
+        match cache.try_latest_ring_key_revision("ring-key-valid") {
+            Err(Error::KeyParse { .. }) => (),
+            other => panic!("Expected KeyParse, got {:?}", other),
+        }
+        match cache.try_fetch_specific_revision::<RingKey>(&named_revision) {
+            Err(Error::KeyParse { .. }) => (),
+            other => panic!("Expected KeyParse, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn corrupt_key_file_is_a_parse_error() {
+        let (cache, dir) = new_cache();
+        let path = dir.path().join(VALID_KEY);
+        std::fs::write(
+                       &path,
+                       "SYM-SEC-1
+ring-key-valid-20160504220722
+
+not base64!",
+        ).unwrap();
+
+        match cache.latest_ring_key_revision("ring-key-valid") {
+            Err(Error::KeyParse { path: p, reason }) => {
+                assert_eq!(p, path);
+                assert_eq!(reason, "Invalid base64 key material");
+            }
+            other => panic!("Expected KeyParse, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn wrong_key_type_in_file_is_a_parse_error() {
+        let (cache, dir) = new_cache();
+        let origin = "my-origin".parse().unwrap();
+        let (public, secret) = generate_signing_key_pair(&origin);
+        // A secret key saved where the public key belongs
+        let path = dir.path().join(public.own_filename());
+        std::fs::write(&path, secret.to_key_string()).unwrap();
+
+        match cache.public_signing_key(public.named_revision()) {
+            Err(Error::KeyParse { path: p, reason }) => {
+                assert_eq!(p, path);
+                assert!(reason.starts_with("Unsupported key version"),
+                        "Unexpected reason: {}",
+                        reason);
+            }
+            other => panic!("Expected KeyParse, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unreadable_key_file_keeps_the_underlying_io_error() {
+        let (cache, dir) = new_cache();
+        let path = dir.path().join(VALID_KEY);
+
+        let err = cache.read_key::<RingKey>(path.clone()).unwrap_err();
+        match err {
+            Error::KeyRead { path: ref p, .. } => assert_eq!(p, &path),
+            ref other => panic!("Expected KeyRead, got {:?}", other),
+        }
+        let source = std::error::Error::source(&err).expect("KeyRead should have a source");
+        let io_err = source.downcast_ref::<io::Error>()
+                           .expect("The source should be an io::Error");
+        assert_eq!(io_err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn write_key_with_existing_identical() {
+        let (cache, dir) = new_cache();
+        let content = fixture_as_string(&format!("keys/{}", VALID_KEY));
+        let new_key_file = dir.path().join(VALID_KEY);
+
+        // install the key into the cache
+        std::fs::copy(fixture(&format!("keys/{}", VALID_KEY)), &new_key_file).unwrap();
+
+        let key: RingKey = content.parse().unwrap();
+        cache.write_key(&key).unwrap();
+        assert_eq!(key.named_revision().to_string(), VALID_NAME_WITH_REV);
+        assert!(new_key_file.is_file());
+    }
+
+    #[test]
+    #[should_panic(expected = "Existing key file")]
+    fn write_key_exists_but_hashes_differ() {
+        let (cache, dir) = new_cache();
+        let old_content = fixture_as_string("keys/ring-key-valid-20160504220722.sym.key");
+
+        std::fs::write(dir.path().join("ring-key-valid-20160504220722.sym.key"),
+                       &old_content).unwrap();
+
+        #[rustfmt::skip]
+        let new_content = "SYM-SEC-1\nring-key-valid-20160504220722\n\nkA+c03Ly5qEoOZIjJ5zCD2vHI05pAW59PfCOb8thmZw=";
+
+        assert_ne!(old_content, new_content);
+
+        let new_key: RingKey = new_content.parse().unwrap();
+        // this should fail
+        cache.write_key(&new_key).unwrap();
+    }
+
+    #[test]
+    fn list_keys_reports_every_kind_of_key() {
+        let (cache, dir) = new_cache();
+        let origin = "my-origin".parse().unwrap();
+        cache.new_ring_key("beyonce").unwrap();
+        cache.new_signing_pair(&origin).unwrap();
+        cache.new_user_encryption_pair("my-user").unwrap();
+        cache.new_service_encryption_pair("my-org", "foo.default")
+             .unwrap();
+        cache.write_key(&generate_builder_encryption_key()).unwrap();
+
+        let mut found = cache.list_keys()
+                             .unwrap()
+                             .into_iter()
+                             .map(|key| {
+                                 assert_eq!(key.path.parent(), Some(dir.path()));
+                                 (key.named_revision.name().clone(), key.kind)
+                             })
+                             .collect::<Vec<_>>();
+        found.sort();
+
+        assert_eq!(found,
+                   vec![("beyonce".to_string(), KeyKind::Ring),
+                        ("bldr".to_string(), KeyKind::BuilderEncryptionSecret),
+                        ("foo.default@my-org".to_string(), KeyKind::ServiceEncryptionPublic),
+                        ("foo.default@my-org".to_string(), KeyKind::ServiceEncryptionSecret),
+                        ("my-origin".to_string(), KeyKind::SigningPublic),
+                        ("my-origin".to_string(), KeyKind::SigningSecret),
+                        ("my-user".to_string(), KeyKind::UserOrOriginEncryptionPublic),
+                        ("my-user".to_string(), KeyKind::UserOrOriginEncryptionSecret),]);
+    }
+
+    #[test]
+    fn list_keys_skips_files_that_are_not_keys() {
+        let (cache, dir) = new_cache();
+        let key = cache.new_ring_key("beyonce").unwrap();
+        std::fs::write(dir.path().join("README"), "not a key").unwrap();
+        std::fs::write(dir.path().join(VALID_KEY), "SYM-SEC-1\ngarbage").unwrap();
+        // A valid key, saved under another key's name
+        std::fs::write(dir.path().join("other-20160504220722.sym.key"),
+                       key.to_key_string()).unwrap();
+        std::fs::create_dir(dir.path().join("dir-20160504220722.sym.key")).unwrap();
+
+        let keys = cache.list_keys().unwrap();
+        assert_eq!(keys,
+                   vec![CachedKeyInfo { named_revision: key.named_revision().clone(),
+                                        kind:           KeyKind::Ring,
+                                        path:           cache.path_in_cache(&key), }]);
+    }
+
+    #[test]
+    fn list_keys_for_name() {
+        let (cache, _dir) = new_cache();
+        let origin = "my-origin".parse().unwrap();
+        let (public, _secret) = cache.new_signing_pair(&origin).unwrap();
+        cache.new_ring_key("my-ring").unwrap();
+
+        let keys = cache.list_keys_for_name("my-origin").unwrap();
+        assert_eq!(keys.iter().map(|key| key.kind).collect::<Vec<_>>(),
+                   vec![KeyKind::SigningPublic, KeyKind::SigningSecret]);
+        assert!(keys.iter()
+                    .all(|key| key.named_revision == *public.named_revision()));
+        assert!(cache.list_keys_for_name("nope").unwrap().is_empty());
+    }
+
+    /// Helper macro to assert that a given key can be saved and
+    /// retrieved from the cache in different ways.
+    macro_rules! assert_cache_round_trip {
+        ($t:ty, $key:expr, $cache:expr) => {
+            $cache.write_key::<$t>(&$key).unwrap();
+            let fetched_latest: $t = $cache.fetch_latest_revision($key.named_revision().name())
+                                           .unwrap();
+            assert_eq!(fetched_latest, $key,
+                       "Expected to retrieve the latest key by name");
+
+            let fetched_specific: $t = $cache.fetch_specific_revision($key.named_revision())
+                                             .unwrap();
+            assert_eq!(fetched_specific, $key,
+                       "Expected to retrieve the key by specific revision");
+        };
+    }
+
+    /// Populate a cache with multiple revisions of all kinds of our
+    /// keys. This can be useful background data against which to
+    /// evaluate `KeyCache::fetch_latest_revision`
+    fn populate_cache(cache: &KeyCache) {
+        let origin = "my-origin".parse().unwrap();
+        let mut rng = seeded_rng();
+        for n in 0..=2 {
+            let (public, secret) =
+                generate_user_encryption_key_pair_with("my-user", &mut rng, revision(n));
+            cache.write_pair(&public, &secret).unwrap();
+            let (public, secret) =
+                generate_origin_encryption_key_pair_with(&origin, &mut rng, revision(n));
+            cache.write_pair(&public, &secret).unwrap();
+            let (public, secret) = generate_service_encryption_key_pair_with("my-org",
+                                                                             "foo.default",
+                                                                             &mut rng,
+                                                                             revision(n));
+            cache.write_pair(&public, &secret).unwrap();
+            let (public, secret) = generate_signing_key_pair_with(&origin, &mut rng, revision(n));
+            cache.write_pair(&public, &secret).unwrap();
+            cache.write_key(&RingKey::new_with("beyonce", &mut rng, revision(n)))
+                 .unwrap();
+        }
+    }
+
+    #[test]
+    fn ring_key_round_trip() {
+        let (cache, _dir) = new_cache();
+        populate_cache(&cache);
+        let key = RingKey::new("beyonce");
+        assert_cache_round_trip!(RingKey, key, cache);
+    }
+
+    #[test]
+    fn user_keys_round_trip() {
+        let (cache, _dir) = new_cache();
+        populate_cache(&cache);
+        let (public, secret) = generate_user_encryption_key_pair("my-user");
+        assert_cache_round_trip!(UserPublicEncryptionKey, public, cache);
+        assert_cache_round_trip!(UserSecretEncryptionKey, secret, cache);
+    }
+
+    #[test]
+    fn origin_keys_round_trip() {
+        let (cache, _dir) = new_cache();
+        populate_cache(&cache);
+        let origin = "my-origin".parse().unwrap();
+        let (public, secret) = generate_origin_encryption_key_pair(&origin);
+        assert_cache_round_trip!(OriginPublicEncryptionKey, public, cache);
+        assert_cache_round_trip!(OriginSecretEncryptionKey, secret, cache);
+    }
+
+    #[test]
+    fn service_keys_round_trip() {
+        let (cache, _dir) = new_cache();
+        populate_cache(&cache);
+        let (public, secret) = generate_service_encryption_key_pair("my-org", "foo.default");
+        assert_cache_round_trip!(ServicePublicEncryptionKey, public, cache);
+        assert_cache_round_trip!(ServiceSecretEncryptionKey, secret, cache);
+    }
+
+    #[test]
+    fn signing_keys_round_trip() {
+        let (cache, _dir) = new_cache();
+        populate_cache(&cache);
+        let origin = "my-org".parse().unwrap();
+        let (public, secret) = generate_signing_key_pair(&origin);
+        assert_cache_round_trip!(PublicOriginSigningKey, public, cache);
+        assert_cache_round_trip!(SecretOriginSigningKey, secret, cache);
+    }
+
+    #[cfg(not(windows))]
+    mod setup {
+        use super::*;
+        use tempfile::TempDir;
+
+        fn mode_of(path: &Path) -> u32 { fs::metadata(path).unwrap().permissions().mode() & 0o777 }
+
+        #[test]
+        fn new_directories_are_only_accessible_to_their_owner() {
+            let outer = TempDir::new().unwrap();
+            let path = outer.path().join("cache").join("keys");
+            KeyCache::new(&path).setup().unwrap();
+            assert_eq!(mode_of(&path), 0o700);
+        }
+
+        #[test]
+        fn existing_directories_are_restricted_to_their_owner() {
+            let outer = TempDir::new().unwrap();
+            let path = outer.path().join("keys");
+            fs::create_dir(&path).unwrap();
+            posix_perm::set_permissions(&path, 0o755).unwrap();
+
+            KeyCache::new(&path).setup().unwrap();
+            assert_eq!(mode_of(&path), 0o700);
+        }
+
+        #[test]
+        fn interrupted_key_writes_are_cleaned_up() {
+            let outer = TempDir::new().unwrap();
+            let path = outer.path().join("keys");
+            let cache = KeyCache::new(&path);
+            cache.setup().unwrap();
+            let key = RingKey::new("beyonce");
+            cache.write_key(&key).unwrap();
+
+            // As if the process writing this key had been killed
+            let keyfile = cache.path_in_cache(&key);
+            let (_, staged) = AtomicWriter::new(&keyfile).unwrap()
+                                                         .stage(|f| f.write_all(b"partial"))
+                                                         .unwrap();
+            std::mem::forget(staged);
+            let orphan = fs::read_dir(&path).unwrap()
+                                            .map(|entry| entry.unwrap().path())
+                                            .find(|p| {
+                                                p.file_name()
+                                                 .unwrap()
+                                                 .to_string_lossy()
+                                                 .starts_with(".hab-tmp-")
+                                            })
+                                            .unwrap();
+            let then = std::time::SystemTime::now() - hfs::STALE_TMP_FILE_AGE * 2;
+            filetime::set_file_mtime(&orphan, filetime::FileTime::from_system_time(then)).unwrap();
+
+            cache.setup().unwrap();
+            assert!(!orphan.exists());
+            assert!(keyfile.exists());
+        }
+
+        #[test]
+        fn permissive_setup_leaves_permissions_alone() {
+            let outer = TempDir::new().unwrap();
+            let path = outer.path().join("keys");
+            fs::create_dir(&path).unwrap();
+            posix_perm::set_permissions(&path, 0o755).unwrap();
+
+            KeyCache::new(&path).setup_permissive().unwrap();
+            assert_eq!(mode_of(&path), 0o755);
+        }
+    }
+
+    mod key_names {
+        use super::*;
+        use tempfile::TempDir;
+
+        /// Every file under `dir`, however deeply nested.
+        fn all_files_under(dir: &Path) -> Vec<PathBuf> {
+            let mut files = vec![];
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    files.extend(all_files_under(&path));
+                } else {
+                    files.push(path);
+                }
+            }
+            files
+        }
+
+        fn assert_invalid_key_name<T: std::fmt::Debug>(result: Result<T>) {
+            match result {
+                Err(Error::InvalidKeyName { .. }) => {}
+                other => panic!("Expected InvalidKeyName, got {:?}", other),
+            }
+        }
+
+        #[test]
+        fn keys_with_unsafe_names_are_never_written() {
+            let outer = TempDir::new().unwrap();
+            let cache_dir = outer.path().join("deeply").join("nested");
+            let cache = KeyCache::new(&cache_dir);
+            cache.setup().unwrap();
+
+            assert_invalid_key_name(cache.new_ring_key("../../etc/passwd"));
+            assert_invalid_key_name(cache.ring_key_or_generate("../../etc/passwd"));
+            assert_invalid_key_name(cache.rotate_ring_key("../../etc/passwd"));
+            assert_invalid_key_name(cache.new_user_encryption_pair("../../etc/passwd"));
+            assert_invalid_key_name(cache.user_encryption_pair_or_generate("../../etc/passwd"));
+            assert_invalid_key_name(cache.rotate_user_encryption_pair("../../etc/passwd"));
+            // Keys made outside the cache are checked when written...
+            let key = RingKey::new("../../etc/passwd");
+            assert_invalid_key_name(cache.write_key(&key));
+            // ...as are keys being imported.
+            assert_invalid_key_name(cache.write_key_str(&key.to_key_string()));
+
+            // At most, the lock was taken.
+            let lock = cache_dir.join(LOCK_FILE);
+            assert!(all_files_under(outer.path()).iter().all(|p| p == &lock));
+        }
+
+        #[test]
+        fn empty_names_are_rejected() {
+            let (cache, dir) = new_cache();
+            match cache.new_ring_key("") {
+                Err(Error::InvalidKeyName { name, reason }) => {
+                    assert_eq!(name, "");
+                    assert_eq!(reason, "it is empty");
+                }
+                other => panic!("Expected InvalidKeyName, got {:?}", other),
+            }
+            let lock = dir.path().join(LOCK_FILE);
+            assert!(all_files_under(dir.path()).iter().all(|p| p == &lock));
+        }
+    }
+
+    mod latest_service_public_key {
+        use super::*;
+        use crate::service::ServiceGroup;
+
+        #[test]
+        fn looked_up_by_anything_that_identifies_a_service_group() {
+            let (cache, _dir) = new_cache();
+            let (public, _) = cache.new_service_encryption_pair("my-org", "foo.default")
+                                   .unwrap();
+
+            let ident: ServiceGroupIdent = "foo.default@my-org".parse().unwrap();
+            let service_group: ServiceGroup = "foo.default@my-org".parse().unwrap();
+            assert_eq!(cache.latest_service_public_key("foo.default@my-org")
+                            .unwrap(),
+                       public);
+            assert_eq!(cache.latest_service_public_key(ident).unwrap(), public);
+            assert_eq!(cache.latest_service_public_key(&service_group).unwrap(),
+                       public);
+        }
+
+        #[test]
+        fn malformed_service_groups_are_rejected() {
+            let (cache, _dir) = new_cache();
+            cache.new_service_encryption_pair("my-org", "foo.default")
+                 .unwrap();
+
+            match cache.latest_service_public_key("foo.default") {
+                Err(Error::InvalidServiceGroupIdent { reason, .. }) => {
+                    assert_eq!(reason, "the org is missing")
+                }
+                other => panic!("Expected InvalidServiceGroupIdent, got {:?}", other),
+            }
+            let no_org: ServiceGroup = "foo.default".parse().unwrap();
+            assert!(cache.try_latest_service_public_key(&no_org).is_err());
+            assert!(cache.new_service_encryption_pair("my-org", "foo").is_err());
+        }
+    }
+
+    mod verify_detached {
+        use super::*;
+
+        #[test]
+        fn signatures_are_verified_with_the_key_they_name() {
+            let (cache, dir) = new_cache();
+            let origin = "fhloston".parse().unwrap();
+            let (old_public, old) =
+                generate_signing_key_pair_with(&origin, &mut seeded_rng(), revision(1));
+            cache.write_key(&old_public).unwrap();
+            let (_, new) = cache.new_signing_pair(&origin).unwrap();
+
+            let path = dir.path().join("SHA256SUMS");
+            fs::write(&path, "some checksums").unwrap();
+            for key in &[old, new] {
+                let signature = key.sign_file_detached(&path).unwrap();
+                assert_eq!(cache.verify_detached(&path, &signature).unwrap(),
+                           Blake2bHash::from_file(&path).unwrap());
+            }
+        }
+
+        #[test]
+        fn signatures_by_keys_not_in_the_cache_fail() {
+            let (cache, dir) = new_cache();
+            let origin = "fhloston".parse().unwrap();
+            let (_, secret) = generate_signing_key_pair(&origin);
+
+            let path = dir.path().join("SHA256SUMS");
+            fs::write(&path, "some checksums").unwrap();
+            let signature = secret.sign_file_detached(&path).unwrap();
+            match cache.verify_detached(&path, &signature) {
+                Err(Error::KeyNotFound { .. }) => {}
+                other => panic!("Expected KeyNotFound, got {:?}", other),
+            }
+        }
+
+        #[test]
+        fn tampered_files_fail() {
+            let (cache, dir) = new_cache();
+            let origin = "fhloston".parse().unwrap();
+            let (_, secret) = cache.new_signing_pair(&origin).unwrap();
+
+            let path = dir.path().join("SHA256SUMS");
+            fs::write(&path, "some checksums").unwrap();
+            let signature = secret.sign_file_detached(&path).unwrap();
+            fs::write(&path, "some checksumt").unwrap();
+            assert!(cache.verify_detached(&path, &signature).is_err());
+        }
+    }
+
+    mod decrypt {
+        use super::*;
+
+        /// Flip a bit in the ciphertext of an encrypted message,
+        /// leaving it otherwise intact.
+        fn tamper_with(encrypted: &str) -> String {
+            let mut lines: Vec<String> = encrypted.lines().map(String::from).collect();
+            let ciphertext = lines.last_mut().unwrap();
+            let mut bytes = crate::base64::decode(ciphertext.as_str()).unwrap();
+            bytes[0] ^= 1;
+            *ciphertext = crate::base64::encode(bytes);
+            lines.join("\n")
+        }
+
+        #[test]
+        fn messages_from_users_to_services() {
+            let (cache, _dir) = new_cache();
+            let (_, user) = cache.new_user_encryption_pair("korben-dallas").unwrap();
+            let (service, _) = cache.new_service_encryption_pair("fhloston", "paradise.default")
+                                    .unwrap();
+
+            let encrypted = user.encrypt_for_service(b"Multipass!", &service)
+                                .to_string();
+            assert_eq!(cache.decrypt(&encrypted).unwrap(), b"Multipass!");
+        }
+
+        #[test]
+        fn messages_from_users_to_users() {
+            let (cache, _dir) = new_cache();
+            let (_, sender) = cache.new_user_encryption_pair("korben-dallas").unwrap();
+            let (recipient, _) = cache.new_user_encryption_pair("leeloo").unwrap();
+
+            let encrypted = sender.encrypt_for_user(b"Multipass!", &recipient)
+                                  .to_string();
+            assert_eq!(cache.decrypt(&encrypted).unwrap(), b"Multipass!");
+        }
+
+        #[test]
+        fn messages_sealed_anonymously_for_services() {
+            let (cache, _dir) = new_cache();
+            let (service, _) = cache.new_service_encryption_pair("fhloston", "paradise.default")
+                                    .unwrap();
+
+            let encrypted = service.seal(b"Multipass!").to_string();
+            assert_eq!(cache.decrypt(&encrypted).unwrap(), b"Multipass!");
+        }
+
+        #[test]
+        fn missing_recipient_key() {
+            let (cache, _dir) = new_cache();
+            let (_, sender) = cache.new_user_encryption_pair("korben-dallas").unwrap();
+            let (recipient, _) =
+                generate_service_encryption_key_pair("fhloston", "paradise.default");
+
+            let encrypted = sender.encrypt_for_service(b"Multipass!", &recipient)
+                                  .to_string();
+            match cache.decrypt(&encrypted) {
+                Err(Error::RecipientKeyNotFound { name, .. }) => {
+                    assert_eq!(name, recipient.named_revision().to_string())
+                }
+                other => panic!("Expected RecipientKeyNotFound, got {:?}", other),
+            }
+        }
+
+        #[test]
+        fn missing_sender_key() {
+            let (cache, _dir) = new_cache();
+            let (_, sender) = generate_user_encryption_key_pair("korben-dallas");
+            let (recipient, _) = cache.new_user_encryption_pair("leeloo").unwrap();
+
+            let encrypted = sender.encrypt_for_user(b"Multipass!", &recipient)
+                                  .to_string();
+            match cache.decrypt(&encrypted) {
+                Err(Error::SenderKeyNotFound { name, .. }) => {
+                    assert_eq!(name, sender.named_revision().to_string())
+                }
+                other => panic!("Expected SenderKeyNotFound, got {:?}", other),
+            }
+        }
+
+        #[test]
+        fn tampered_messages_fail_authentication() {
+            let (cache, _dir) = new_cache();
+            let (_, user) = cache.new_user_encryption_pair("korben-dallas").unwrap();
+            let (service, _) = cache.new_service_encryption_pair("fhloston", "paradise.default")
+                                    .unwrap();
+
+            let encrypted = user.encrypt_for_service(b"Multipass!", &service)
+                                .to_string();
+            match cache.decrypt(&tamper_with(&encrypted)) {
+                Err(Error::MessageAuthenticationFailed { .. }) => {}
+                other => panic!("Expected MessageAuthenticationFailed, got {:?}", other),
+            }
+
+            let encrypted = service.seal(b"Multipass!").to_string();
+            match cache.decrypt(&tamper_with(&encrypted)) {
+                Err(Error::MessageAuthenticationFailed { .. }) => {}
+                other => panic!("Expected MessageAuthenticationFailed, got {:?}", other),
+            }
+        }
+    }
+
+    mod rotate {
+        use super::*;
+
+        #[test]
+        fn rotating_a_ring_key_that_does_not_exist_yet() {
+            let (cache, _dir) = new_cache();
+            let (previous, key) = cache.rotate_ring_key("beyonce").unwrap();
+            assert_eq!(previous, None);
+            assert_eq!(cache.latest_ring_key_revision("beyonce").unwrap(), key);
+        }
+
+        #[test]
+        fn rotated_ring_keys_become_the_latest() {
+            let (cache, _dir) = new_cache();
+            let (_, first) = cache.rotate_ring_key("beyonce").unwrap();
+            // Almost certainly within the same second as the first.
+            let (previous, second) = cache.rotate_ring_key("beyonce").unwrap();
+
+            assert_eq!(previous.as_ref(), Some(first.named_revision()));
+            assert!(second.named_revision() > first.named_revision());
+            assert_eq!(cache.latest_ring_key_revision("beyonce").unwrap(), second);
+            assert_eq!(ring_key_paths(&cache, "beyonce").len(), 2);
+        }
+
+        #[test]
+        fn every_revision_of_a_rotated_ring_key_is_kept() {
+            let (cache, _dir) = new_cache();
+            assert!(cache.ring_keys("beyonce").unwrap().is_empty());
+
+            let (_, first) = cache.rotate_ring_key("beyonce").unwrap();
+            let (_, second) = cache.rotate_ring_key("beyonce").unwrap();
+            cache.rotate_ring_key("jay-z").unwrap();
+
+            assert_eq!(cache.ring_keys("beyonce").unwrap(), vec![second, first]);
+        }
+
+        #[test]
+        fn invalid_earlier_ring_key_revisions_are_skipped() {
+            let (cache, _dir) = new_cache();
+            let (_, first) = cache.rotate_ring_key("beyonce").unwrap();
+            let (_, second) = cache.rotate_ring_key("beyonce").unwrap();
+            let corrupt = "beyonce-20000101000000".parse().unwrap();
+            std::fs::write(cache.path_for::<RingKey>(&corrupt), "SYM-SEC-1\ngarbage").unwrap();
+
+            assert_eq!(cache.ring_keys("beyonce").unwrap(), vec![second, first]);
+        }
+
+        #[test]
+        fn an_invalid_latest_ring_key_revision_is_an_error() {
+            let (cache, _dir) = new_cache();
+            cache.rotate_ring_key("beyonce").unwrap();
+            let corrupt = "beyonce-20991231235959".parse().unwrap();
+            std::fs::write(cache.path_for::<RingKey>(&corrupt), "SYM-SEC-1\ngarbage").unwrap();
+
+            assert!(cache.ring_keys("beyonce").is_err());
+        }
+
+        #[test]
+        fn rotated_revisions_are_later_than_any_existing_one() {
+            let (cache, _dir) = new_cache();
+            let future = RingKey::new_with("beyonce",
+                                           &mut seeded_rng(),
+                                           "20991231235959".parse().unwrap());
+            cache.write_key(&future).unwrap();
+
+            let (previous, key) = cache.rotate_ring_key("beyonce").unwrap();
+            assert_eq!(previous.as_ref(), Some(future.named_revision()));
+            assert_eq!(key.named_revision().to_string(), "beyonce-21000101000000");
+        }
+
+        #[test]
+        fn rotating_user_encryption_pairs() {
+            let (cache, _dir) = new_cache();
+            let (previous, (first_public, first_secret)) =
+                cache.rotate_user_encryption_pair("my-user").unwrap();
+            assert_eq!(previous, None);
+            assert_eq!(first_public.named_revision(), first_secret.named_revision());
+
+            let (previous, (public, secret)) =
+                cache.rotate_user_encryption_pair("my-user").unwrap();
+            assert_eq!(previous.as_ref(), Some(first_secret.named_revision()));
+            assert!(secret.named_revision() > first_secret.named_revision());
+            assert_eq!(cache.latest_user_secret_key("my-user").unwrap(), secret);
+            assert_eq!(cache.user_public_encryption_key(secret.named_revision())
+                            .unwrap(),
+                       public);
+        }
+    }
+
+    mod export_public_key {
+        use super::*;
+
+        #[test]
+        fn exported_keys_can_be_imported_again() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (public, _secret) =
+                generate_signing_key_pair_with(&origin, &mut seeded_rng(), revision(1));
+            cache.write_key(&public).unwrap();
+
+            let raw = cache.export_public_key(public.named_revision(), PublicKeyFormat::Raw)
+                           .unwrap();
+            let imported =
+                PublicOriginSigningKey::from_raw_bytes("my-origin", revision(1), &raw).unwrap();
+            assert_eq!(imported.to_key_string(), public.to_key_string());
+        }
+
+        #[test]
+        fn missing_keys_are_not_exported() {
+            let (cache, _dir) = new_cache();
+            let named_revision = "my-origin-20200101000001".parse().unwrap();
+            match cache.export_public_key(&named_revision, PublicKeyFormat::Pem) {
+                Err(Error::KeyNotFound { .. }) => (),
+                other => panic!("Expected KeyNotFound, got {:?}", other),
+            }
+        }
+    }
+
+    mod contains {
+        use super::*;
+
+        #[test]
+        fn keys_are_only_contained_once_written() {
+            let (cache, _dir) = new_cache();
+            let key = RingKey::new_with("beyonce", &mut seeded_rng(), revision(1));
+            let path = cache.path_for::<RingKey>(key.named_revision());
+            assert_eq!(path,
+                       cache.as_ref()
+                            .join(format!("{}.sym.key", key.named_revision())));
+            assert!(!path.exists());
+            assert!(!cache.contains::<RingKey>(key.named_revision()));
+            assert!(!cache.contains_valid::<RingKey>(key.named_revision()));
+
+            cache.write_key(&key).unwrap();
+            assert!(path.is_file());
+            assert!(cache.contains::<RingKey>(key.named_revision()));
+            assert!(cache.contains_valid::<RingKey>(key.named_revision()));
+        }
+
+        #[test]
+        fn a_different_type_of_key_under_the_same_name_does_not_count() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (signing, _) =
+                generate_signing_key_pair_with(&origin, &mut seeded_rng(), revision(1));
+            // Saved the way public keys were before each kind had its
+            // own extension.
+            std::fs::write(cache.as_ref()
+                                .join(format!("{}.pub", signing.named_revision())),
+                           signing.to_key_string()).unwrap();
+
+            assert!(cache.contains::<PublicOriginSigningKey>(signing.named_revision()));
+            assert!(!cache.contains::<OriginPublicEncryptionKey>(signing.named_revision()));
+            assert!(!cache.contains_valid::<OriginPublicEncryptionKey>(signing.named_revision()));
+        }
+
+        #[test]
+        fn the_header_is_checked_under_the_current_extension_too() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (encryption, _) =
+                generate_origin_encryption_key_pair_with(&origin, &mut seeded_rng(), revision(1));
+            std::fs::write(cache.path_for::<PublicOriginSigningKey>(encryption.named_revision()),
+                           encryption.to_key_string()).unwrap();
+
+            assert!(!cache.contains::<PublicOriginSigningKey>(encryption.named_revision()));
+        }
+
+        #[test]
+        fn contains_valid_requires_the_key_to_parse() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (signing, _) =
+                generate_signing_key_pair_with(&origin, &mut seeded_rng(), revision(1));
+            std::fs::write(cache.path_for::<PublicOriginSigningKey>(signing.named_revision()),
+                           format!("{}\n{}\n\nnot a key",
+                                   PublicOriginSigningKey::version(),
+                                   signing.named_revision())).unwrap();
+
+            assert!(cache.contains::<PublicOriginSigningKey>(signing.named_revision()));
+            assert!(!cache.contains_valid::<PublicOriginSigningKey>(signing.named_revision()));
+        }
+    }
+
+    mod expiration {
+        use super::*;
+        use chrono::Duration as ChronoDuration;
+
+        fn expired_signing_key(cache: &KeyCache,
+                               origin: &Origin,
+                               revision: KeyRevision)
+                               -> SecretOriginSigningKey {
+            let (_public, secret) =
+                generate_signing_key_pair_with(origin, &mut seeded_rng(), revision);
+            let secret = secret.with_expiration(Utc::now() - ChronoDuration::days(1));
+            cache.write_key(&secret).unwrap();
+            secret
+        }
+
+        #[test]
+        fn expired_latest_revision_is_skipped() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (public, valid) =
+                generate_signing_key_pair_with(&origin, &mut seeded_rng(), revision(1));
+            cache.write_pair(&public, &valid).unwrap();
+            let expired = expired_signing_key(&cache, &origin, revision(2));
+
+            assert_eq!(cache.latest_secret_origin_signing_key(&origin).unwrap(),
+                       expired);
+            assert_eq!(cache.latest_valid_secret_origin_signing_key(&origin, Utc::now())
+                            .unwrap(),
+                       valid);
+        }
+
+        #[test]
+        fn unexpired_keys_are_valid() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (_public, secret) = generate_signing_key_pair(&origin);
+            let secret = secret.with_expiration(Utc::now() + ChronoDuration::days(1));
+            cache.write_key(&secret).unwrap();
+
+            assert_eq!(cache.latest_valid_secret_origin_signing_key(&origin, Utc::now())
+                            .unwrap(),
+                       secret);
+        }
+
+        #[test]
+        fn all_revisions_expired_is_an_error() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            expired_signing_key(&cache, &origin, revision(1));
+
+            match cache.latest_valid_secret_origin_signing_key(&origin, Utc::now()) {
+                Err(Error::KeyExpired { .. }) => (),
+                other => panic!("Expected KeyExpired, got {:?}", other),
+            }
+        }
+
+        #[test]
+        fn no_revisions_is_not_found() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+
+            match cache.latest_valid_secret_origin_signing_key(&origin, Utc::now()) {
+                Err(Error::KeyNotFound { .. }) => (),
+                other => panic!("Expected KeyNotFound, got {:?}", other),
+            }
+        }
+
+        #[test]
+        fn keys_with_an_expiration_can_be_rewritten() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let secret = expired_signing_key(&cache, &origin, revision(1));
+            cache.write_key(&secret).unwrap();
+            assert_eq!(cache.secret_signing_key(secret.named_revision()).unwrap(),
+                       secret);
+        }
+    }
+
+    mod verify {
+        use super::*;
+
+        #[test]
+        fn a_healthy_cache_is_clean() {
+            let (cache, _dir) = new_cache();
+            populate_cache(&cache);
+
+            let report = cache.verify().unwrap();
+            assert!(report.is_clean(), "Unexpected problems: {:?}", report);
+            assert_eq!(report, CacheReport::default());
+        }
+
+        #[test]
+        fn truncated_keys_are_unparseable() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (_public, secret) = cache.new_signing_pair(&origin).unwrap();
+            let path = cache.path_in_cache(&secret);
+            let content = secret.to_key_string();
+            set_writable(&path);
+            std::fs::write(&path, &content[..content.len() / 2]).unwrap();
+
+            let report = cache.verify().unwrap();
+            assert!(!report.is_clean());
+            assert_eq!(report.unparseable.len(), 1);
+            assert_eq!(report.unparseable[0].path, path);
+        }
+
+        #[test]
+        fn keys_under_the_wrong_name_are_misnamed() {
+            let (cache, dir) = new_cache();
+            let key = cache.new_ring_key("beyonce").unwrap();
+            let path = dir.path().join("beyonce-20160504220722.sym.key");
+            std::fs::copy(cache.path_in_cache(&key), &path).unwrap();
+
+            let report = cache.verify().unwrap();
+            assert_eq!(report.misnamed,
+                       vec![MisnamedKey { path,
+                                          named_revision: key.named_revision().clone() }]);
+        }
+
+        #[test]
+        fn unpaired_keys_are_reported() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (signing_public, _) = generate_signing_key_pair(&origin);
+            let (_, encryption_secret) = generate_origin_encryption_key_pair(&origin);
+            cache.write_key(&signing_public).unwrap();
+            cache.write_key(&encryption_secret).unwrap();
+
+            let report = cache.verify().unwrap();
+            assert_eq!(filenames(&report.public_keys_without_secret_keys),
+                       vec![signing_public.own_filename()]);
+            assert_eq!(filenames(&report.secret_keys_without_public_keys),
+                       vec![encryption_secret.own_filename()]);
+            assert!(!report.is_clean());
+        }
+
+        #[test]
+        fn public_keys_alone_are_clean() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (public, _) = generate_signing_key_pair(&origin);
+            cache.write_key(&public).unwrap();
+
+            assert!(cache.verify().unwrap().is_clean());
+        }
+
+        #[test]
+        #[cfg(not(windows))]
+        fn unexpected_permissions_are_reported() {
+            let (cache, _dir) = new_cache();
+            let key = cache.new_ring_key("beyonce").unwrap();
+            let path = cache.path_in_cache(&key);
+            set_writable(&path);
+
+            let report = cache.verify().unwrap();
+            assert_eq!(report.bad_permissions,
+                       vec![BadPermissions { path:     path.clone(),
+                                             mode:     0o600,
+                                             expected: 0o400, }]);
+            // Verifying changes nothing
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+                       0o600);
+        }
+
+        #[test]
+        fn reports_serialize() {
+            let (cache, dir) = new_cache();
+            std::fs::write(dir.path().join(VALID_KEY), "SYM-SEC-1\ngarbage").unwrap();
+
+            let json = serde_json::to_value(cache.verify().unwrap()).unwrap();
+            assert_eq!(json["unparseable"][0]["reason"],
+                       "Cannot parse named revision 'garbage': expected a name and a revision \
+                        separated by '-'");
+        }
+
+        fn filenames(keys: &[CachedKeyInfo]) -> Vec<PathBuf> {
+            keys.iter()
+                .map(|key| PathBuf::from(key.path.file_name().unwrap()))
+                .collect()
+        }
+
+        /// Key files are written read-only; make one writable so it
+        /// can be mangled.
+        fn set_writable(path: &Path) {
+            let mut permissions = std::fs::metadata(path).unwrap().permissions();
+            #[cfg(not(windows))]
+            permissions.set_mode(0o600);
+            #[cfg(windows)]
+            permissions.set_readonly(false);
+            std::fs::set_permissions(path, permissions).unwrap();
+        }
+    }
+
+    mod or_generate {
+        use super::*;
+
+        #[test]
+        fn ring_key_is_generated_only_when_missing() {
+            let (_, dir) = new_cache();
+            let cache = KeyCache::new(dir.path().join("not-there-yet"));
+            let key = cache.ring_key_or_generate("beyonce").unwrap();
+
+            assert_eq!(cache.latest_ring_key_revision("beyonce").unwrap(), key);
+            assert_eq!(cache.ring_key_or_generate("beyonce").unwrap(), key);
+            assert_eq!(cache.list_keys().unwrap().len(), 1);
+        }
+
+        #[test]
+        fn corrupt_ring_key_is_not_replaced() {
+            let (cache, dir) = new_cache();
+            std::fs::write(dir.path().join(VALID_KEY), "SYM-SEC-1\ngarbage").unwrap();
+
+            match cache.ring_key_or_generate("ring-key-valid") {
+                Err(Error::KeyParse { .. }) => (),
+                other => panic!("Expected KeyParse, got {:?}", other),
+            }
+            assert_eq!(std::fs::read_to_string(dir.path().join(VALID_KEY)).unwrap(),
+                       "SYM-SEC-1\ngarbage");
+        }
+
+        #[test]
+        fn user_encryption_pair_is_generated_only_when_missing() {
+            let (cache, _dir) = new_cache();
+            let pair = cache.user_encryption_pair_or_generate("me").unwrap();
+
+            assert_eq!(cache.latest_user_encryption_pair("me").unwrap(), pair);
+            assert_eq!(cache.user_encryption_pair_or_generate("me").unwrap(), pair);
+            assert_eq!(cache.list_keys().unwrap().len(), 2);
+        }
+    }
+
+    mod locking {
+        use super::*;
+        use std::sync::Arc;
+
+        #[test]
+        fn concurrent_writes_of_the_same_key_all_succeed() {
+            let (cache, _dir) = new_cache();
+            let cache = Arc::new(cache);
+            let key = Arc::new(RingKey::new("beyonce"));
+            let writers =
+                (0..8).map(|_| {
+                          let cache = Arc::clone(&cache);
+                          let key = Arc::clone(&key);
+                          thread::spawn(move || cache.write_key(&*key).map_err(|e| e.to_string()))
+                      })
+                      .collect::<Vec<_>>();
+            for writer in writers {
+                writer.join().unwrap().unwrap();
+            }
+
+            assert_eq!(cache.latest_ring_key_revision("beyonce").unwrap(), *key);
+            assert_eq!(cache.list_keys().unwrap().len(), 1);
+        }
+
+        #[test]
+        fn writing_times_out_while_another_writer_holds_the_lock() {
+            let (mut cache, _dir) = new_cache();
+            cache.lock_timeout = Duration::from_millis(100);
+            let _held = cache.lock_for_writing().unwrap();
+
+            match cache.write_key(&RingKey::new("beyonce")) {
+                Err(Error::KeyCacheLocked { path, .. }) => {
+                    assert_eq!(path, cache.as_ref().join(LOCK_FILE))
+                }
+                other => panic!("Expected KeyCacheLocked, got {:?}", other),
+            }
+            assert!(cache.list_keys().unwrap().is_empty());
+        }
+
+        #[test]
+        fn reading_does_not_need_the_lock() {
+            let (mut cache, _dir) = new_cache();
+            cache.lock_timeout = Duration::from_millis(100);
+            let key = cache.new_ring_key("beyonce").unwrap();
+            let _held = cache.lock_for_writing().unwrap();
+
+            assert_eq!(cache.latest_ring_key_revision("beyonce").unwrap(), key);
+        }
+    }
+
+    mod write_key_str {
+        use super::*;
+
+        #[test]
+        fn every_kind_of_key_is_recognized() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let ring = RingKey::new("beyonce");
+            let (sig_public, sig_secret) = generate_signing_key_pair(&origin);
+            let (box_public, box_secret) = generate_origin_encryption_key_pair(&origin);
+            let (svc_public, svc_secret) =
+                generate_service_encryption_key_pair("my-org", "foo.default");
+            let builder = generate_builder_encryption_key();
+
+            let mut written = vec![cache.write_key_str(&ring.to_key_string()).unwrap(),
+                                   cache.write_key_str(&sig_public.to_key_string()).unwrap(),
+                                   cache.write_key_str(&sig_secret.to_key_string()).unwrap(),
+                                   cache.write_key_str(&box_public.to_key_string()).unwrap(),
+                                   cache.write_key_str(&box_secret.to_key_string()).unwrap(),
+                                   cache.write_key_str(&svc_public.to_key_string()).unwrap(),
+                                   cache.write_key_str(&svc_secret.to_key_string()).unwrap(),
+                                   cache.write_key_str(&builder.to_key_string()).unwrap(),];
+            assert_eq!(written.iter().map(|key| key.kind).collect::<Vec<_>>(),
+                       vec![KeyKind::Ring,
+                            KeyKind::SigningPublic,
+                            KeyKind::SigningSecret,
+                            KeyKind::UserOrOriginEncryptionPublic,
+                            KeyKind::UserOrOriginEncryptionSecret,
+                            KeyKind::ServiceEncryptionPublic,
+                            KeyKind::ServiceEncryptionSecret,
+                            KeyKind::BuilderEncryptionSecret]);
+            assert_eq!(written[2].named_revision, *sig_secret.named_revision());
+            assert_eq!(written[2].path, cache.path_in_cache(&sig_secret));
+
+            let listed = cache.list_keys().unwrap();
+            written.sort_by(|a, b| a.path.cmp(&b.path));
+            assert_eq!(listed, written);
+            assert_eq!(cache.secret_signing_key(sig_secret.named_revision())
+                            .unwrap(),
+                       sig_secret);
+        }
+
+        #[test]
+        fn writing_the_same_key_twice_is_fine() {
+            let (cache, _dir) = new_cache();
+            let key = RingKey::new("beyonce");
+            cache.write_key_str(&key.to_key_string()).unwrap();
+            cache.write_key_str(&key.to_key_string()).unwrap();
+            assert_eq!(cache.latest_ring_key_revision("beyonce").unwrap(), key);
+        }
+
+        #[test]
+        fn writing_a_different_key_with_the_same_name_is_an_error() {
+            let (cache, _dir) = new_cache();
+            let key = RingKey::new("beyonce");
+            cache.write_key(&key).unwrap();
+            let other = RingKey::new("other");
+            let content = other.to_key_string()
+                               .replace(&other.named_revision().to_string(),
+                                        &key.named_revision().to_string());
+
+            assert!(cache.write_key_str(&content).is_err());
+            assert_eq!(cache.latest_ring_key_revision("beyonce").unwrap(), key);
+        }
+
+        #[test]
+        fn unknown_versions_are_an_error() {
+            let (cache, _dir) = new_cache();
+            match cache.write_key_str("NOPE-1\nbeyonce-20160504220722\n\nabcd") {
+                Err(Error::CryptoError(reason)) => {
+                    assert_eq!(reason, "Unsupported key version: NOPE-1")
+                }
+                other => panic!("Expected CryptoError, got {:?}", other),
+            }
+        }
+
+        #[test]
+        fn truncated_content_is_an_error() {
+            let (cache, _dir) = new_cache();
+            let content = RingKey::new("beyonce").to_key_string();
+            let mut lines = content.lines();
+            let truncated = format!("{}\n{}", lines.next().unwrap(), lines.next().unwrap());
+
+            assert!(cache.write_key_str("").is_err());
+            assert!(cache.write_key_str("SYM-SEC-1").is_err());
+            assert!(cache.write_key_str(&truncated).is_err());
+            assert!(cache.list_keys().unwrap().is_empty());
+        }
+    }
+
+    mod latest_pair {
+        use super::*;
+
+        #[test]
+        fn both_keys_share_a_revision() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let mut rng = seeded_rng();
+            let (public, secret) = generate_signing_key_pair_with(&origin, &mut rng, revision(1));
+            cache.write_pair(&public, &secret).unwrap();
+            let pair = generate_signing_key_pair_with(&origin, &mut rng, revision(2));
+            cache.write_pair(&pair.0, &pair.1).unwrap();
+
+            assert_eq!(cache.latest_signing_pair(&origin).unwrap(), pair);
+        }
+
+        #[test]
+        fn newest_secret_key_without_a_public_key_is_passed_over() {
+            let (cache, _dir) = new_cache();
+            let mut rng = seeded_rng();
+            let pair = generate_user_encryption_key_pair_with("me", &mut rng, revision(1));
+            cache.write_pair(&pair.0, &pair.1).unwrap();
+            let (_public, secret) =
+                generate_user_encryption_key_pair_with("me", &mut rng, revision(2));
+            cache.write_key(&secret).unwrap();
+
+            assert_eq!(cache.latest_user_encryption_pair("me").unwrap(), pair);
+        }
+
+        #[test]
+        fn newest_public_key_without_a_secret_key_is_passed_over() {
+            let (cache, _dir) = new_cache();
+            let mut rng = seeded_rng();
+            let pair = generate_service_encryption_key_pair_with("my-org",
+                                                                 "foo.default",
+                                                                 &mut rng,
+                                                                 revision(1));
+            cache.write_pair(&pair.0, &pair.1).unwrap();
+            let (public, _secret) = generate_service_encryption_key_pair_with("my-org",
+                                                                              "foo.default",
+                                                                              &mut rng,
+                                                                              revision(2));
+            cache.write_key(&public).unwrap();
+
+            assert_eq!(cache.latest_service_encryption_pair("foo.default@my-org")
+                            .unwrap(),
+                       pair);
+        }
+
+        #[test]
+        fn no_complete_pair_is_an_error() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let mut rng = seeded_rng();
+            let (public, _secret) =
+                generate_origin_encryption_key_pair_with(&origin, &mut rng, revision(1));
+            let (_public, secret) =
+                generate_origin_encryption_key_pair_with(&origin, &mut rng, revision(2));
+            cache.write_key(&public).unwrap();
+            cache.write_key(&secret).unwrap();
+
+            match cache.latest_origin_encryption_pair(&origin) {
+                Err(Error::KeyPairNotFound { name, .. }) => assert_eq!(name, "my-origin"),
+                other => panic!("Expected KeyPairNotFound, got {:?}", other),
+            }
+        }
+    }
+
+    mod write_pair {
+        use super::*;
+
+        #[test]
+        fn pair_must_actually_be_a_pair_in_order_to_save() {
+            let (cache, _dir) = new_cache();
+
+            let (me_public, _me_secret) = generate_user_encryption_key_pair("me");
+            let (_you_public, you_secret) = generate_user_encryption_key_pair("you");
+
+            let result = cache.write_pair(&me_public, &you_secret);
+            assert!(result.is_err(), "Threw an error: {:?}", result);
+        }
+
+        /// The names of everything in the cache directory.
+        fn cache_contents(cache: &KeyCache) -> Vec<PathBuf> {
+            let mut contents =
+                std::fs::read_dir(cache.as_ref()).unwrap()
+                                                 .map(|e| PathBuf::from(e.unwrap().file_name()))
+                                                 .filter(|p| p != Path::new(LOCK_FILE))
+                                                 .collect::<Vec<_>>();
+            contents.sort();
+            contents
+        }
+
+        #[test]
+        fn identical_existing_pair_is_ok() {
+            let (cache, _dir) = new_cache();
+            let (public, secret) = generate_user_encryption_key_pair("me");
+            cache.write_pair(&public, &secret).unwrap();
+            cache.write_pair(&public, &secret).unwrap();
+
+            assert_eq!(cache_contents(&cache),
+                       vec![secret.own_filename(), public.own_filename()]);
+        }
+
+        #[test]
+        fn conflicting_secret_key_writes_nothing() {
+            let (cache, _dir) = new_cache();
+            let (public, secret) = generate_user_encryption_key_pair("me");
+            let secret_path = cache.path_in_cache(&secret);
+            std::fs::write(&secret_path, "something else").unwrap();
+
+            assert!(cache.write_pair(&public, &secret).is_err());
+            assert_eq!(cache_contents(&cache), vec![secret.own_filename()]);
+            assert_eq!(std::fs::read_to_string(&secret_path).unwrap(),
+                       "something else");
+        }
+
+        #[test]
+        fn failing_to_write_secret_key_removes_new_public_key() {
+            let (cache, _dir) = new_cache();
+            let (public, secret) = generate_user_encryption_key_pair("me");
+            // The secret key can be staged, but not renamed over a
+            // directory.
+            std::fs::create_dir(cache.path_in_cache(&secret)).unwrap();
+
+            assert!(cache.write_pair(&public, &secret).is_err());
+            assert_eq!(cache_contents(&cache), vec![secret.own_filename()]);
+        }
+
+        #[test]
+        fn failing_to_write_secret_key_keeps_existing_public_key() {
+            let (cache, _dir) = new_cache();
+            let (public, secret) = generate_user_encryption_key_pair("me");
+            cache.write_key(&public).unwrap();
+            std::fs::create_dir(cache.path_in_cache(&secret)).unwrap();
+
+            assert!(cache.write_pair(&public, &secret).is_err());
+            assert_eq!(cache_contents(&cache),
+                       vec![secret.own_filename(), public.own_filename()]);
+            assert_eq!(std::fs::read_to_string(cache.path_in_cache(&public)).unwrap(),
+                       public.to_key_string());
+        }
+    }
+
+    mod remove {
+        use super::*;
+
+        #[test]
+        fn remove_key_removes_only_that_revision() {
+            let (cache, _dir) = new_cache();
+            let mut rng = seeded_rng();
+            let old = RingKey::new_with("beyonce", &mut rng, revision(1));
+            let new = RingKey::new_with("beyonce", &mut rng, revision(2));
+            cache.write_key(&old).unwrap();
+            cache.write_key(&new).unwrap();
+
+            assert_eq!(cache.remove_key::<RingKey>(old.named_revision()).unwrap(),
+                       1);
+            assert!(!cache.path_in_cache(&old).exists());
+            assert!(cache.path_in_cache(&new).exists());
+        }
+
+        #[test]
+        fn removing_a_missing_key_is_an_error() {
+            let (cache, _dir) = new_cache();
+            let key = cache.new_ring_key("beyonce").unwrap();
+            cache.remove_key::<RingKey>(key.named_revision()).unwrap();
+
+            match cache.remove_key::<RingKey>(key.named_revision()) {
+                Err(Error::KeyNotFound { key_type, .. }) => assert_eq!(key_type, "RingKey"),
+                other => panic!("Expected KeyNotFound, got {:?}", other),
+            }
+        }
+
+        #[test]
+        fn removing_a_secret_key_leaves_the_public_key() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (public, secret) = cache.new_signing_pair(&origin).unwrap();
+
+            cache.remove_key::<SecretOriginSigningKey>(secret.named_revision())
+                 .unwrap();
+            assert!(!cache.path_in_cache(&secret).exists());
+            assert!(cache.path_in_cache(&public).exists());
+        }
+
+        #[test]
+        fn remove_pair_removes_both_keys() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (public, secret) = cache.new_signing_pair(&origin).unwrap();
+
+            let removed = cache.remove_pair::<PublicOriginSigningKey, SecretOriginSigningKey>(
+                public.named_revision());
+            assert_eq!(removed.unwrap(), 2);
+            assert!(!cache.path_in_cache(&secret).exists());
+            assert!(!cache.path_in_cache(&public).exists());
+        }
+
+        #[test]
+        fn remove_pair_requires_both_keys() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (public, secret) = cache.new_signing_pair(&origin).unwrap();
+            std::fs::remove_file(cache.path_in_cache(&secret)).unwrap();
+
+            let removed = cache.remove_pair::<PublicOriginSigningKey, SecretOriginSigningKey>(
+                public.named_revision());
+            assert!(removed.is_err());
+            assert!(cache.path_in_cache(&public).exists());
+        }
+
+        #[test]
+        fn remove_all_revisions_leaves_other_keys_with_the_same_name() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (public_enc, secret_enc) = cache.new_origin_encryption_pair(&origin).unwrap();
+            let mut rng = seeded_rng();
+            for n in 1..=2 {
+                let (public, secret) =
+                    generate_signing_key_pair_with(&origin, &mut rng, revision(n));
+                cache.write_pair(&public, &secret).unwrap();
+            }
+            let other_origin = "my-origin-too".parse().unwrap();
+            let (other_public, other_secret) = cache.new_signing_pair(&other_origin).unwrap();
+
+            assert_eq!(cache.remove_all_revisions::<PublicOriginSigningKey>("my-origin")
+                            .unwrap(),
+                       2);
+            assert_eq!(cache.remove_all_revisions::<SecretOriginSigningKey>("my-origin")
+                            .unwrap(),
+                       2);
+            assert_eq!(cache.remove_all_revisions::<SecretOriginSigningKey>("my-origin")
+                            .unwrap(),
+                       0);
+
+            for path in &[cache.path_in_cache(&public_enc),
+                          cache.path_in_cache(&secret_enc),
+                          cache.path_in_cache(&other_public),
+                          cache.path_in_cache(&other_secret)]
+            {
+                assert!(path.exists(), "{} should not be removed", path.display());
+            }
+            assert_eq!(cache.list_keys().unwrap().len(), 4);
+        }
     }
 
-    #[test]
-    fn get_all_paths_for() {
-        let (cache, _dir) = new_cache();
+    mod prune {
+        use super::*;
 
-        let ring_name = "beyonce";
+        const REVISIONS: [&str; 3] = ["20200101000000", "20210101000000", "20220101000000"];
 
-        let paths = ring_key_paths(&cache, ring_name);
-        assert!(paths.is_empty());
+        /// Write copies of `key` to the cache as each of `revisions`,
+        /// without having to wait a second between generating them.
+        fn write_revisions<K>(cache: &KeyCache, key: &K, revisions: &[&str]) -> Vec<NamedRevision>
+            where K: KeyFile + FromStr<Err = Error>
+        {
+            revisions.iter()
+                     .map(|revision| {
+                         let named_revision: NamedRevision =
+                             format!("{}-{}", key.named_revision().name(), revision).parse()
+                                                                                    .unwrap();
+                         let content = key.to_key_string()
+                                          .replace(&key.named_revision().to_string(),
+                                                   &named_revision.to_string());
+                         cache.write_key(&content.parse::<K>().unwrap()).unwrap();
+                         named_revision
+                     })
+                     .collect()
+        }
 
-        let k1 = RingKey::new(ring_name);
-        cache.write_key(&k1).unwrap();
-        let paths = ring_key_paths(&cache, ring_name);
-        assert_eq!(paths.len(), 1);
-        assert!(paths.contains(&k1.own_filename()));
+        #[test]
+        fn prune_keeps_the_newest_revisions() {
+            let (cache, _dir) = new_cache();
+            let revisions = write_revisions(&cache, &RingKey::new("beyonce"), &REVISIONS);
+
+            assert_eq!(cache.prune::<RingKey>("beyonce", 1, false).unwrap(),
+                       &revisions[..2]);
+            assert_eq!(cache.list_keys_for_name("beyonce")
+                            .unwrap()
+                            .into_iter()
+                            .map(|key| key.named_revision)
+                            .collect::<Vec<_>>(),
+                       &revisions[2..]);
+        }
 
-        wait_1_sec(); // ensure new revision
-                      // will be different.
+        #[test]
+        fn prune_never_removes_the_newest_revision() {
+            let (cache, _dir) = new_cache();
+            let revisions = write_revisions(&cache, &RingKey::new("beyonce"), &REVISIONS);
+
+            assert_eq!(cache.prune::<RingKey>("beyonce", 0, false).unwrap(),
+                       &revisions[..2]);
+            assert!(cache.prune::<RingKey>("beyonce", 0, false)
+                         .unwrap()
+                         .is_empty());
+            assert_eq!(cache.latest_ring_key_revision("beyonce")
+                            .unwrap()
+                            .named_revision(),
+                       &revisions[2]);
+        }
 
-        let k2 = RingKey::new(ring_name);
-        cache.write_key(&k2).unwrap();
+        #[test]
+        fn prune_dry_run_removes_nothing() {
+            let (cache, _dir) = new_cache();
+            let revisions = write_revisions(&cache, &RingKey::new("beyonce"), &REVISIONS);
 
-        let paths = ring_key_paths(&cache, ring_name);
-        assert_eq!(paths.len(), 2);
-        assert!(paths.contains(&k1.own_filename()));
-        assert!(paths.contains(&k2.own_filename()));
+            assert_eq!(cache.prune::<RingKey>("beyonce", 2, true).unwrap(),
+                       &revisions[..1]);
+            assert_eq!(cache.list_keys_for_name("beyonce").unwrap().len(), 3);
+        }
 
-        // We should not include another named key in the count
-        let other = RingKey::new("jayz");
-        cache.write_key(&other).unwrap();
-        let paths = ring_key_paths(&cache, ring_name);
-        assert_eq!(paths.len(), 2);
-        assert!(!paths.contains(&other.own_filename()));
-        assert!(paths.contains(&k1.own_filename()));
-        assert!(paths.contains(&k2.own_filename()));
+        #[test]
+        fn prune_pairs_never_orphans_a_secret_key() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (public, secret) = generate_signing_key_pair(&origin);
+            let revisions = write_revisions(&cache, &public, &REVISIONS);
+            write_revisions(&cache, &secret, &REVISIONS);
+            // The oldest revision has lost its public key
+            std::fs::remove_file(cache.as_ref()
+                                      .join(PublicOriginSigningKey::filename(&revisions[0]))).unwrap();
+
+            let pruned =
+                cache.prune_pairs::<PublicOriginSigningKey, SecretOriginSigningKey>("my-origin",
+                                                                                    1,
+                                                                                    false)
+                     .unwrap();
+            assert_eq!(pruned, &revisions[1..2]);
+            assert_eq!(cache.list_keys_for_name("my-origin")
+                            .unwrap()
+                            .into_iter()
+                            .map(|key| (key.named_revision, key.kind))
+                            .collect::<Vec<_>>(),
+                       vec![(revisions[0].clone(), KeyKind::SigningSecret),
+                            (revisions[2].clone(), KeyKind::SigningPublic),
+                            (revisions[2].clone(), KeyKind::SigningSecret),]);
+        }
     }
 
-    #[test]
-    #[should_panic(expected = "No revisions found for")]
-    fn latest_cached_revision_nonexistent() {
-        let (cache, _dir) = new_cache();
-        cache.latest_ring_key_revision("nope-nope").unwrap();
-    }
+    mod bundle {
+        use super::*;
 
-    #[test]
-    fn write_key_with_existing_identical() {
-        let (cache, dir) = new_cache();
-        let content = fixture_as_string(&format!("keys/{}", VALID_KEY));
-        let new_key_file = dir.path().join(VALID_KEY);
+        fn filenames(keys: &[CachedKeyInfo]) -> Vec<PathBuf> {
+            keys.iter()
+                .map(|key| PathBuf::from(key.path.file_name().unwrap()))
+                .collect()
+        }
 
-        // install the key into the cache
-        std::fs::copy(fixture(&format!("keys/{}", VALID_KEY)), &new_key_file).unwrap();
+        /// A bundle holding a single file, with the given mode.
+        fn bundle_of(filename: &str, content: &str, mode: u32) -> Vec<u8> {
+            let mut builder = tar::Builder::new(Vec::new());
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(mode);
+            header.set_cksum();
+            builder.append_data(&mut header, filename, content.as_bytes())
+                   .unwrap();
+            builder.into_inner().unwrap()
+        }
 
-        let key: RingKey = content.parse().unwrap();
-        cache.write_key(&key).unwrap();
-        assert_eq!(key.named_revision().to_string(), VALID_NAME_WITH_REV);
-        assert!(new_key_file.is_file());
-    }
+        #[test]
+        fn round_trip() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (public, secret) = cache.new_signing_pair(&origin).unwrap();
+            let ring_key = cache.new_ring_key("beyonce").unwrap();
+            cache.new_ring_key("not-exported").unwrap();
+
+            let mut bundle = Vec::new();
+            assert_eq!(cache.export_bundle(&["my-origin", "beyonce"], true, &mut bundle)
+                            .unwrap(),
+                       3);
+
+            let (other_cache, _other_dir) = new_cache();
+            let imported = other_cache.import_bundle(bundle.as_slice()).unwrap();
+            assert_eq!(filenames(&imported),
+                       vec![ring_key.own_filename(),
+                            public.own_filename(),
+                            secret.own_filename()]);
+            assert_eq!(other_cache.list_keys().unwrap(), imported);
+            assert_eq!(other_cache.public_signing_key(public.named_revision())
+                                  .unwrap(),
+                       public);
+        }
 
-    #[test]
-    #[should_panic(expected = "Existing key file")]
-    fn write_key_exists_but_hashes_differ() {
-        let (cache, dir) = new_cache();
-        let old_content = fixture_as_string("keys/ring-key-valid-20160504220722.sym.key");
+        #[test]
+        fn secret_keys_can_be_left_out() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (public, _secret) = cache.new_signing_pair(&origin).unwrap();
+            cache.new_ring_key("my-origin").unwrap();
+
+            let mut bundle = Vec::new();
+            assert_eq!(cache.export_bundle(&["my-origin"], false, &mut bundle)
+                            .unwrap(),
+                       1);
+
+            let (other_cache, _other_dir) = new_cache();
+            let imported = other_cache.import_bundle(bundle.as_slice()).unwrap();
+            assert_eq!(filenames(&imported), vec![public.own_filename()]);
+        }
 
-        std::fs::write(dir.path().join("ring-key-valid-20160504220722.sym.key"),
-                       &old_content).unwrap();
+        #[test]
+        fn importing_identical_keys_is_a_no_op() {
+            let (cache, _dir) = new_cache();
+            let key = cache.new_ring_key("beyonce").unwrap();
+            let mut bundle = Vec::new();
+            cache.export_bundle(&["beyonce"], true, &mut bundle)
+                 .unwrap();
 
-        #[rustfmt::skip]
-        let new_content = "SYM-SEC-1\nring-key-valid-20160504220722\n\nkA+c03Ly5qEoOZIjJ5zCD2vHI05pAW59PfCOb8thmZw=";
+            let imported = cache.import_bundle(bundle.as_slice()).unwrap();
+            assert_eq!(filenames(&imported), vec![key.own_filename()]);
+            assert_eq!(cache.list_keys().unwrap().len(), 1);
+        }
 
-        assert_ne!(old_content, new_content);
+        #[test]
+        fn conflicting_key_imports_nothing() {
+            let (cache, _dir) = new_cache();
+            let (other_cache, _other_dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (public, secret) = other_cache.new_signing_pair(&origin).unwrap();
+            let mut bundle = Vec::new();
+            other_cache.export_bundle(&["my-origin"], true, &mut bundle)
+                       .unwrap();
+            // The secret key comes after the public key in the bundle
+            let conflicting = cache.path_in_cache(&secret);
+            std::fs::write(&conflicting, "something else").unwrap();
+
+            assert!(cache.import_bundle(bundle.as_slice()).is_err());
+            assert!(!cache.path_in_cache(&public).exists());
+            let files = std::fs::read_dir(cache.as_ref()).unwrap()
+                                                         .filter(|e| {
+                                                             e.as_ref().unwrap().file_name()
+                                                             != LOCK_FILE
+                                                         })
+                                                         .count();
+            assert_eq!(files, 1);
+            assert_eq!(std::fs::read_to_string(&conflicting).unwrap(),
+                       "something else");
+        }
 
-        let new_key: RingKey = new_content.parse().unwrap();
-        // this should fail
-        cache.write_key(&new_key).unwrap();
-    }
+        #[test]
+        fn imported_keys_get_their_own_permissions() {
+            let (cache, _dir) = new_cache();
+            let key = RingKey::new("beyonce");
+            let filename = key.own_filename();
+            let bundle = bundle_of(filename.to_str().unwrap(), &key.to_key_string(), 0o666);
+
+            cache.import_bundle(bundle.as_slice()).unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = std::fs::metadata(cache.as_ref().join(&filename)).unwrap()
+                                                                            .permissions()
+                                                                            .mode();
+                assert_eq!(mode & 0o777, 0o400);
+            }
+        }
 
-    /// Helper macro to assert that a given key can be saved and
-    /// retrieved from the cache in different ways.
-    macro_rules! assert_cache_round_trip {
-        ($t:ty, $key:expr, $cache:expr) => {
-            $cache.write_key::<$t>(&$key).unwrap();
-            let fetched_latest: $t = $cache.fetch_latest_revision($key.named_revision().name())
-                                           .unwrap();
-            assert_eq!(fetched_latest, $key,
-                       "Expected to retrieve the latest key by name");
+        #[test]
+        fn files_outside_the_top_level_are_rejected() {
+            let (cache, _dir) = new_cache();
+            let key = RingKey::new("beyonce");
+            let bundle = bundle_of(&format!("nested/{}", key.own_filename().display()),
+                                   &key.to_key_string(),
+                                   0o400);
+
+            match cache.import_bundle(bundle.as_slice()) {
+                Err(Error::KeyParse { .. }) => (),
+                other => panic!("Expected KeyParse, got {:?}", other),
+            }
+            assert!(cache.list_keys().unwrap().is_empty());
+        }
 
-            let fetched_specific: $t = $cache.fetch_specific_revision($key.named_revision())
-                                             .unwrap();
-            assert_eq!(fetched_specific, $key,
-                       "Expected to retrieve the key by specific revision");
-        };
+        #[test]
+        fn files_that_are_not_keys_are_rejected() {
+            let (cache, _dir) = new_cache();
+            let bundle = bundle_of("README", "not a key", 0o444);
+
+            match cache.import_bundle(bundle.as_slice()) {
+                Err(Error::KeyParse { .. }) => (),
+                other => panic!("Expected KeyParse, got {:?}", other),
+            }
+        }
     }
 
-    /// Populate a cache with multiple revisions of all kinds of our
-    /// keys. This can be useful background data against which to
-    /// evaluate `KeyCache::fetch_latest_revision`
-    fn populate_cache(cache: &KeyCache) {
-        let origin = "my-origin".parse().unwrap();
-        for _ in 0..=2 {
-            cache.new_user_encryption_pair("my-user").unwrap();
-            cache.new_origin_encryption_pair(&origin).unwrap();
-            cache.new_service_encryption_pair("my-org", "foo.default")
-                 .unwrap();
+    mod legacy_extensions {
+        use super::*;
 
-            // If we're going to be using the same origin name for the
-            // encryption key and the signing key, we have to wait a
-            // second, because the public keys will both have the same
-            // filename :/
-            wait_1_sec();
+        const REVISION: &str = "20200101000000";
 
-            cache.new_signing_pair(&origin).unwrap();
-            cache.new_ring_key("beyonce").unwrap();
-            wait_1_sec();
+        /// `key`, but with its revision replaced by `revision`, as
+        /// though it had been generated at another time.
+        fn at_revision<K>(key: &K, revision: &str) -> K
+            where K: KeyFile + FromStr<Err = Error>
+        {
+            let named_revision = format!("{}-{}", key.named_revision().name(), revision);
+            key.to_key_string()
+               .replace(&key.named_revision().to_string(), &named_revision)
+               .parse()
+               .unwrap()
         }
-    }
 
-    #[test]
-    fn ring_key_round_trip() {
-        let (cache, _dir) = new_cache();
-        populate_cache(&cache);
-        let key = RingKey::new("beyonce");
-        assert_cache_round_trip!(RingKey, key, cache);
-    }
+        /// Save `key` in `cache` the way it would have been saved
+        /// before each kind of public key had its own extension.
+        fn write_legacy<K>(cache: &KeyCache, key: &K) -> PathBuf
+            where K: KeyFile
+        {
+            let path = cache.as_ref().join(format!("{}.pub", key.named_revision()));
+            std::fs::write(&path, key.to_key_string()).unwrap();
+            path
+        }
 
-    #[test]
-    fn user_keys_round_trip() {
-        let (cache, _dir) = new_cache();
-        populate_cache(&cache);
-        let (public, secret) = generate_user_encryption_key_pair("my-user");
-        assert_cache_round_trip!(UserPublicEncryptionKey, public, cache);
-        assert_cache_round_trip!(UserSecretEncryptionKey, secret, cache);
-    }
+        #[test]
+        fn public_keys_made_in_the_same_second_do_not_collide() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (signing, _) = generate_signing_key_pair(&origin);
+            let (encryption, _) = generate_origin_encryption_key_pair(&origin);
+            let signing = at_revision(&signing, REVISION);
+            let encryption = at_revision(&encryption, REVISION);
+            assert_eq!(signing.named_revision(), encryption.named_revision());
+
+            cache.write_key(&signing).unwrap();
+            cache.write_key(&encryption).unwrap();
+
+            assert_eq!(cache.public_signing_key(signing.named_revision()).unwrap(),
+                       signing);
+            assert_eq!(cache.latest_public_origin_signing_key(&origin).unwrap(),
+                       signing);
+            assert_eq!(cache.latest_origin_public_encryption_key(&origin).unwrap(),
+                       encryption);
+        }
 
-    #[test]
-    fn origin_keys_round_trip() {
-        let (cache, _dir) = new_cache();
-        populate_cache(&cache);
-        let origin = "my-origin".parse().unwrap();
-        let (public, secret) = generate_origin_encryption_key_pair(&origin);
-        assert_cache_round_trip!(OriginPublicEncryptionKey, public, cache);
-        assert_cache_round_trip!(OriginSecretEncryptionKey, secret, cache);
-    }
+        #[test]
+        fn legacy_public_keys_are_read_by_their_header() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (signing, _) = generate_signing_key_pair(&origin);
+            let (encryption, _) = generate_origin_encryption_key_pair(&origin);
+            let signing = at_revision(&signing, REVISION);
+            // Newer, so that it'd be found first if the header were
+            // ignored.
+            let encryption = at_revision(&encryption, "20210101000000");
+            write_legacy(&cache, &signing);
+            write_legacy(&cache, &encryption);
+
+            assert_eq!(cache.public_signing_key(signing.named_revision()).unwrap(),
+                       signing);
+            assert_eq!(cache.latest_public_origin_signing_key(&origin).unwrap(),
+                       signing);
+            assert_eq!(cache.latest_origin_public_encryption_key(&origin).unwrap(),
+                       encryption);
+            assert_eq!(cache.find_key_path::<PublicOriginSigningKey>(signing.named_revision())
+                            .unwrap(),
+                       cache.as_ref()
+                            .join(format!("{}.pub", signing.named_revision())));
+        }
 
-    #[test]
-    fn service_keys_round_trip() {
-        let (cache, _dir) = new_cache();
-        populate_cache(&cache);
-        let (public, secret) = generate_service_encryption_key_pair("my-org", "foo.default");
-        assert_cache_round_trip!(ServicePublicEncryptionKey, public, cache);
-        assert_cache_round_trip!(ServiceSecretEncryptionKey, secret, cache);
-    }
+        #[test]
+        fn keys_are_written_under_the_current_extension() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (public, _secret) = cache.new_signing_pair(&origin).unwrap();
 
-    #[test]
-    fn signing_keys_round_trip() {
-        let (cache, _dir) = new_cache();
-        populate_cache(&cache);
-        let origin = "my-org".parse().unwrap();
-        let (public, secret) = generate_signing_key_pair(&origin);
-        assert_cache_round_trip!(PublicOriginSigningKey, public, cache);
-        assert_cache_round_trip!(SecretOriginSigningKey, secret, cache);
+            assert_eq!(cache.path_in_cache(&public),
+                       cache.as_ref()
+                            .join(format!("{}.sig.pub", public.named_revision())));
+            assert!(cache.path_in_cache(&public).is_file());
+        }
+
+        #[test]
+        fn legacy_keys_can_be_pruned() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (signing, _) = generate_signing_key_pair(&origin);
+            let old = write_legacy(&cache, &at_revision(&signing, REVISION));
+            cache.write_key(&signing).unwrap();
+
+            assert_eq!(cache.prune::<PublicOriginSigningKey>("my-origin", 1, false)
+                            .unwrap()
+                            .len(),
+                       1);
+            assert!(!old.exists());
+            assert!(cache.path_in_cache(&signing).is_file());
+        }
+
+        #[test]
+        fn migrate_legacy_filenames() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (signing, _) = generate_signing_key_pair(&origin);
+            let (encryption, _) = generate_origin_encryption_key_pair(&origin);
+            let (service, _) = generate_service_encryption_key_pair("my-org", "foo.default");
+            let signing = at_revision(&signing, REVISION);
+            let encryption = at_revision(&encryption, "20210101000000");
+            write_legacy(&cache, &signing);
+            write_legacy(&cache, &encryption);
+            write_legacy(&cache, &service);
+            // Already saved under its current name, too
+            cache.write_key(&service).unwrap();
+            let ring_key = cache.new_ring_key("beyonce").unwrap();
+
+            let migrated = cache.migrate_legacy_filenames().unwrap();
+            assert_eq!(migrated.iter().map(|key| &key.path).collect::<Vec<_>>(),
+                       vec![&cache.path_in_cache(&service),
+                            &cache.path_in_cache(&signing),
+                            &cache.path_in_cache(&encryption)]);
+
+            let paths = cache.list_keys()
+                             .unwrap()
+                             .into_iter()
+                             .map(|key| key.path)
+                             .collect::<Vec<_>>();
+            assert_eq!(paths,
+                       vec![cache.path_in_cache(&ring_key),
+                            cache.path_in_cache(&service),
+                            cache.path_in_cache(&signing),
+                            cache.path_in_cache(&encryption)]);
+            assert_eq!(cache.public_signing_key(signing.named_revision()).unwrap(),
+                       signing);
+            assert!(cache.migrate_legacy_filenames().unwrap().is_empty());
+        }
+
+        #[test]
+        fn migration_leaves_conflicting_legacy_files_alone() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (signing, _) = generate_signing_key_pair(&origin);
+            let (other, _) = generate_signing_key_pair(&origin);
+            let other = at_revision(&other, signing.named_revision().revision());
+            cache.write_key(&signing).unwrap();
+            let legacy_path = write_legacy(&cache, &other);
+
+            assert!(cache.migrate_legacy_filenames().unwrap().is_empty());
+            assert!(legacy_path.is_file());
+            assert_eq!(cache.public_signing_key(signing.named_revision()).unwrap(),
+                       signing);
+        }
     }
 
-    mod write_pair {
+    mod fallbacks {
         use super::*;
+        use tempfile::TempDir;
+
+        /// A cache layered over a single fallback, along with a plain
+        /// cache of that fallback directory for putting keys there.
+        fn new_layered_cache() -> (KeyCache, KeyCache, TempDir, TempDir) {
+            let (primary, primary_dir) = new_cache();
+            let (fallback, fallback_dir) = new_cache();
+            let layered =
+                KeyCache::with_fallbacks(primary.as_ref(), vec![fallback.as_ref().to_path_buf()]);
+            (layered, fallback, primary_dir, fallback_dir)
+        }
 
         #[test]
-        fn pair_must_actually_be_a_pair_in_order_to_save() {
-            let (cache, _dir) = new_cache();
+        fn keys_are_read_from_fallbacks() {
+            let (cache, fallback, _p, _f) = new_layered_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (public, _secret) = fallback.new_signing_pair(&origin).unwrap();
+
+            assert_eq!(cache.public_signing_key(public.named_revision()).unwrap(),
+                       public);
+            assert_eq!(cache.latest_public_origin_signing_key(&origin).unwrap(),
+                       public);
+        }
 
-            let (me_public, _me_secret) = generate_user_encryption_key_pair("me");
-            let (_you_public, you_secret) = generate_user_encryption_key_pair("you");
+        #[test]
+        fn latest_revision_considers_every_layer() {
+            let (cache, fallback, _p, _f) = new_layered_cache();
+            let mut rng = seeded_rng();
+            let new = RingKey::new_with("beyonce", &mut rng, revision(2));
+            cache.write_key(&RingKey::new_with("beyonce", &mut rng, revision(1)))
+                 .unwrap();
+            fallback.write_key(&new).unwrap();
 
-            let result = cache.write_pair(&me_public, &you_secret);
-            assert!(result.is_err(), "Threw an error: {:?}", result);
+            assert_eq!(cache.latest_ring_key_revision("beyonce").unwrap(), new);
+            assert_eq!(cache.list_keys_for_name("beyonce").unwrap().len(), 2);
+        }
+
+        #[test]
+        fn primary_shadows_fallbacks() {
+            let (cache, fallback, _p, _f) = new_layered_cache();
+            let key = cache.new_ring_key("beyonce").unwrap();
+            std::fs::write(fallback.path_in_cache(&key), "not a key").unwrap();
+
+            assert_eq!(cache.latest_ring_key_revision("beyonce").unwrap(), key);
+            let keys = cache.list_keys().unwrap();
+            assert_eq!(keys.len(), 1);
+            assert_eq!(keys[0].path, cache.path_in_cache(&key));
+        }
+
+        #[test]
+        fn writes_only_go_to_the_primary() {
+            let (cache, fallback, _p, _f) = new_layered_cache();
+            let key = cache.new_ring_key("beyonce").unwrap();
+
+            assert!(cache.path_in_cache(&key).is_file());
+            assert!(!fallback.path_in_cache(&key).exists());
+        }
+
+        #[test]
+        fn keys_in_fallbacks_are_never_removed() {
+            let (cache, fallback, _p, _f) = new_layered_cache();
+            let key = fallback.new_ring_key("beyonce").unwrap();
+
+            match cache.remove_key::<RingKey>(key.named_revision()) {
+                Err(Error::KeyNotFound { .. }) => (),
+                other => panic!("Expected KeyNotFound, got {:?}", other),
+            }
+            assert_eq!(cache.remove_all_revisions::<RingKey>("beyonce").unwrap(), 0);
+            assert!(fallback.path_in_cache(&key).is_file());
+        }
+
+        #[test]
+        fn missing_fallback_directories_are_ignored() {
+            let (primary, _dir) = new_cache();
+            let missing = primary.as_ref().join("no-such-directory");
+            let cache = KeyCache::with_fallbacks(primary.as_ref(), vec![missing]);
+            let key = cache.new_ring_key("beyonce").unwrap();
+
+            assert_eq!(cache.latest_ring_key_revision("beyonce").unwrap(), key);
+            assert_eq!(cache.list_keys().unwrap().len(), 1);
         }
     }
 
@@ -559,9 +3975,10 @@ mod test {
 
             let key = RingKey::new("symlinks_are_ok");
 
-            // Write the key into the "real directory"
+            // Write the key into the "real directory", with the
+            // permissions a secret key needs to be read.
             let path_in_real_dir = real_dir.path().join(key.own_filename());
-            std::fs::write(&path_in_real_dir, key.to_key_string()).unwrap();
+            KeyCache::new(real_dir.path()).write_key(&key).unwrap();
 
             // Create a symlink to the key in the actual KeyCache directory
             let path_in_cache_dir = cache.as_ref().join(key.own_filename());
@@ -605,4 +4022,68 @@ mod test {
             ::std::os::unix::fs::symlink(src.as_ref(), dest.as_ref())
         }
     }
+
+    #[cfg(not(windows))]
+    mod permissions {
+        use super::*;
+
+        fn set_mode(path: &Path, mode: u32) {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+        }
+
+        fn mode(path: &Path) -> u32 { fs::metadata(path).unwrap().permissions().mode() & 0o777 }
+
+        #[test]
+        fn secret_keys_others_can_read_are_refused() {
+            let (cache, _dir) = new_cache();
+            let key = cache.new_ring_key("beyonce").unwrap();
+            let path = cache.path_in_cache(&key);
+            set_mode(&path, 0o644);
+
+            match cache.latest_ring_key_revision("beyonce") {
+                Err(Error::InsecureKeyPermissions { path: p, mode }) => {
+                    assert_eq!(p, path);
+                    assert_eq!(mode, 0o644);
+                }
+                other => panic!("Expected InsecureKeyPermissions, got {:?}", other),
+            }
+        }
+
+        #[test]
+        fn public_keys_others_can_read_are_fine() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (public, _secret) = cache.new_signing_pair(&origin).unwrap();
+            set_mode(&cache.path_in_cache(&public), 0o644);
+
+            assert_eq!(cache.public_signing_key(public.named_revision()).unwrap(),
+                       public);
+        }
+
+        #[test]
+        fn checks_can_be_turned_off() {
+            let (cache, _dir) = new_cache();
+            let key = cache.new_ring_key("beyonce").unwrap();
+            set_mode(&cache.path_in_cache(&key), 0o644);
+
+            let cache = cache.without_permission_checks();
+            assert_eq!(cache.latest_ring_key_revision("beyonce").unwrap(), key);
+        }
+
+        #[test]
+        fn fix_permissions_restores_secret_key_modes() {
+            let (cache, _dir) = new_cache();
+            let origin = "my-origin".parse().unwrap();
+            let (public, secret) = cache.new_signing_pair(&origin).unwrap();
+            let secret_path = cache.path_in_cache(&secret);
+            set_mode(&secret_path, 0o644);
+
+            assert_eq!(cache.fix_permissions().unwrap(), vec![secret_path.clone()]);
+            assert_eq!(mode(&secret_path), 0o400);
+            assert_eq!(mode(&cache.path_in_cache(&public)), 0o444);
+            assert_eq!(cache.secret_signing_key(secret.named_revision()).unwrap(),
+                       secret);
+            assert!(cache.fix_permissions().unwrap().is_empty());
+        }
+    }
 }
@@ -0,0 +1,234 @@
+use crate::{crypto::keys::{CachedKeyInfo,
+                           KeyKind,
+                           NamedRevision},
+            error::Result};
+use log::{debug,
+          warn};
+use notify::{Event,
+             RecommendedWatcher,
+             RecursiveMode,
+             Watcher};
+use std::{collections::{BTreeSet,
+                        HashMap},
+          fs,
+          path::{Path,
+                 PathBuf},
+          sync::mpsc::{self,
+                       Receiver,
+                       RecvTimeoutError},
+          thread,
+          time::Duration};
+use tokio::sync::mpsc::{error::TryRecvError,
+                        unbounded_channel,
+                        UnboundedReceiver,
+                        UnboundedSender};
+
+crate::env_config_duration!(
+    /// How long a key cache has to be left alone before the changes
+    /// made to it are reported.
+    ///
+    /// Writing a key creates a file, writes to it, and renames it
+    /// into place; waiting for things to settle reports that as a
+    /// single change.
+    KeyCacheWatcherDelay,
+    HAB_KEY_CACHE_WATCHER_DELAY_MS => from_millis,
+    Duration::from_millis(200));
+
+/// A change to the keys in a `KeyCache`, as reported by a
+/// `KeyCacheWatcher`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyCacheEvent {
+    /// A key file was created, or its contents changed.
+    KeyAdded(NamedRevision, KeyKind),
+    /// A key file was removed, or no longer holds a valid key.
+    KeyRemoved(NamedRevision, KeyKind),
+}
+
+/// Reports the keys that come and go in a `KeyCache`'s own directory
+/// (but not in its fallback directories). Made with
+/// `KeyCache::watch`.
+///
+/// Only files that hold a valid key and are named for it are
+/// reported, so the temporary files keys are written to before being
+/// renamed into place (as well as the cache's lock file) are never
+/// seen.
+///
+/// Events are available both to sync code, through `try_recv` and
+/// `blocking_recv`, and to async code, through `recv`. Dropping the
+/// watcher stops it.
+#[derive(Debug)]
+pub struct KeyCacheWatcher {
+    // Not used; only held onto so that the threads it watches the
+    // filesystem with live as long as we do.
+    _watcher: RecommendedWatcher,
+    events:   UnboundedReceiver<KeyCacheEvent>,
+}
+
+impl KeyCacheWatcher {
+    pub(super) fn new(dir: &Path) -> Result<Self> {
+        let (raw_tx, raw_rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(raw_tx)?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        // Anything that shows up between starting to watch and
+        // looking at what's there is both known and reported, which
+        // is harmless, where the other way around it would be missed.
+        let known = fs::read_dir(dir)?.filter_map(|entry| entry.ok())
+                                      .filter_map(|entry| CachedKeyInfo::from_path(entry.path()))
+                                      .map(|info| (info.path.clone(), info))
+                                      .collect();
+        let (tx, rx) = unbounded_channel();
+        let delay = KeyCacheWatcherDelay::configured_value().0;
+        thread::Builder::new().name(String::from("key-cache-watcher"))
+                              .spawn(move || debounce(&raw_rx, &tx, known, delay))?;
+        Ok(KeyCacheWatcher { _watcher: watcher,
+                             events:   rx, })
+    }
+
+    /// Wait for the next change to the cache. Returns `None` only if
+    /// the watcher has stopped.
+    pub async fn recv(&mut self) -> Option<KeyCacheEvent> { self.events.recv().await }
+
+    /// The next change to the cache, if there is one waiting.
+    pub fn try_recv(&mut self) -> Option<KeyCacheEvent> {
+        match self.events.try_recv() {
+            Ok(event) => Some(event),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None,
+        }
+    }
+
+    /// Block the current thread until the next change to the cache.
+    /// Returns `None` only if the watcher has stopped.
+    ///
+    /// Panics if called from async code; use `recv` there instead.
+    pub fn blocking_recv(&mut self) -> Option<KeyCacheEvent> { self.events.blocking_recv() }
+}
+
+/// Gather up the paths touched by filesystem events until none have
+/// arrived for `delay`, then report how the keys at those paths
+/// changed. Runs until the watcher or the receiver goes away.
+fn debounce(raw_events: &Receiver<notify::Result<Event>>,
+            events: &UnboundedSender<KeyCacheEvent>,
+            mut known: HashMap<PathBuf, CachedKeyInfo>,
+            delay: Duration) {
+    let mut touched = BTreeSet::new();
+    loop {
+        let raw_event = if touched.is_empty() {
+            raw_events.recv()
+                      .map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            raw_events.recv_timeout(delay)
+        };
+        match raw_event {
+            Ok(Ok(event)) => touched.extend(event.paths),
+            Ok(Err(e)) => warn!("Error watching key cache: {}", e),
+            Err(RecvTimeoutError::Timeout) => {
+                for path in std::mem::take(&mut touched) {
+                    if let Some(event) = change_at(path, &mut known) {
+                        if events.send(event).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                debug!("Key cache watcher stopped");
+                return;
+            }
+        }
+    }
+}
+
+/// Compare what's now at `path` with the key last seen there,
+/// updating `known` to match. A key whose contents changed is
+/// reported as added again.
+fn change_at(path: PathBuf, known: &mut HashMap<PathBuf, CachedKeyInfo>) -> Option<KeyCacheEvent> {
+    match CachedKeyInfo::from_path(path.clone()) {
+        Some(info) => {
+            let event = KeyCacheEvent::KeyAdded(info.named_revision.clone(), info.kind);
+            known.insert(path, info);
+            Some(event)
+        }
+        None => {
+            known.remove(&path)
+                 .map(|old| KeyCacheEvent::KeyRemoved(old.named_revision, old.kind))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{keys::{Key,
+                               RingKey},
+                        test_support::*};
+    use tokio::{task,
+                time::{self,
+                       timeout}};
+
+    async fn next_event(watcher: &mut KeyCacheWatcher) -> KeyCacheEvent {
+        timeout(Duration::from_secs(10), watcher.recv()).await
+                                                        .expect("Timed out waiting for an event")
+                                                        .expect("Watcher stopped")
+    }
+
+    /// Wait for long enough that anything the watcher was going to
+    /// report has been.
+    async fn settle() { time::sleep(KeyCacheWatcherDelay::configured_value().0 * 5).await; }
+
+    #[tokio::test]
+    async fn writing_a_key_is_reported_once() {
+        let (cache, _dir) = new_cache();
+        let mut watcher = cache.watch().unwrap();
+        let key = RingKey::new_with("beyonce", &mut seeded_rng(), revision(1));
+        cache.write_key(&key).unwrap();
+
+        assert_eq!(next_event(&mut watcher).await,
+                   KeyCacheEvent::KeyAdded(key.named_revision().clone(), KeyKind::Ring));
+        settle().await;
+        assert_eq!(watcher.try_recv(), None);
+    }
+
+    #[tokio::test]
+    async fn removing_a_key_is_reported() {
+        let (cache, _dir) = new_cache();
+        let key = RingKey::new_with("beyonce", &mut seeded_rng(), revision(1));
+        cache.write_key(&key).unwrap();
+        let mut watcher = cache.watch().unwrap();
+
+        fs::remove_file(cache.path_in_cache(&key)).unwrap();
+        assert_eq!(next_event(&mut watcher).await,
+                   KeyCacheEvent::KeyRemoved(key.named_revision().clone(), KeyKind::Ring));
+    }
+
+    #[tokio::test]
+    async fn files_that_are_not_keys_are_ignored() {
+        let (cache, dir) = new_cache();
+        let mut watcher = cache.watch().unwrap();
+
+        fs::write(dir.path().join("beyonce-20200101000001.sym.key"),
+                  "not a key").unwrap();
+        fs::write(dir.path().join(".tmpAbCdEf"), "half a key").unwrap();
+        settle().await;
+        fs::remove_file(dir.path().join(".tmpAbCdEf")).unwrap();
+        settle().await;
+        assert_eq!(watcher.try_recv(), None);
+
+        let key = RingKey::new_with("beyonce", &mut seeded_rng(), revision(2));
+        cache.write_key(&key).unwrap();
+        assert_eq!(next_event(&mut watcher).await,
+                   KeyCacheEvent::KeyAdded(key.named_revision().clone(), KeyKind::Ring));
+    }
+
+    #[tokio::test]
+    async fn keys_can_be_waited_for_from_sync_code() {
+        let (cache, _dir) = new_cache();
+        let mut watcher = cache.watch().unwrap();
+        let key = RingKey::new_with("beyonce", &mut seeded_rng(), revision(1));
+        cache.write_key(&key).unwrap();
+
+        let event = task::spawn_blocking(move || watcher.blocking_recv()).await
+                                                                         .unwrap();
+        assert_eq!(event,
+                   Some(KeyCacheEvent::KeyAdded(key.named_revision().clone(), KeyKind::Ring)));
+    }
+}
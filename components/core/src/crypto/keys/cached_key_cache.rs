@@ -0,0 +1,278 @@
+use crate::{crypto::keys::{BuilderSecretEncryptionKey,
+                           KeyCache,
+                           KeyFile,
+                           NamedRevision,
+                           OriginPublicEncryptionKey,
+                           PublicOriginSigningKey,
+                           RingKey,
+                           SecretOriginSigningKey,
+                           ServicePublicEncryptionKey,
+                           ServiceSecretEncryptionKey,
+                           UserPublicEncryptionKey,
+                           UserSecretEncryptionKey,
+                           BUILDER_KEY_NAME},
+            error::{Error,
+                    Result},
+            origin::Origin};
+use std::{any::Any,
+          collections::HashMap,
+          str::FromStr,
+          sync::Mutex};
+
+/// A read-through, in-memory cache of the keys in a `KeyCache`, for
+/// long-running processes that look the same keys up over and over.
+///
+/// Keys are parsed from disk the first time they're asked for, and
+/// served from memory after that, as is the latest revision of each
+/// named key. Failed lookups aren't remembered, so a key that shows
+/// up on disk later is found. However, a newer revision of a key
+/// whose latest revision has already been looked up is not noticed,
+/// nor is a key removed from disk, until `invalidate` is called.
+/// Keys written through `write_key` are seen immediately.
+#[derive(Debug)]
+pub struct CachedKeyCache {
+    key_cache: KeyCache,
+    inner:     Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// Parsed keys, by their `KeyFile::key_type` and revision.
+    keys:   HashMap<(&'static str, NamedRevision), Box<dyn Any + Send>>,
+    /// The latest revision of each key, by its `KeyFile::key_type`
+    /// and name.
+    latest: HashMap<(&'static str, String), NamedRevision>,
+}
+
+impl Inner {
+    fn get<K>(&self, named_revision: &NamedRevision) -> Option<K>
+        where K: KeyFile + Clone + 'static
+    {
+        self.keys
+            .get(&(K::key_type(), named_revision.clone()))
+            .and_then(|key| key.downcast_ref::<K>())
+            .cloned()
+    }
+
+    fn insert<K>(&mut self, key: &K)
+        where K: KeyFile + Clone + Send + 'static
+    {
+        self.keys
+            .insert((K::key_type(), key.named_revision().clone()),
+                    Box::new(key.clone()));
+    }
+}
+
+impl CachedKeyCache {
+    pub fn new(key_cache: KeyCache) -> Self {
+        CachedKeyCache { key_cache,
+                         inner: Mutex::new(Inner::default()) }
+    }
+
+    /// The `KeyCache` this reads through to.
+    pub fn key_cache(&self) -> &KeyCache { &self.key_cache }
+
+    /// Forget everything read so far, so that subsequent lookups go
+    /// back to disk.
+    pub fn invalidate(&self) { *self.inner() = Inner::default(); }
+
+    /// Write `key` to the underlying `KeyCache`, making it available
+    /// from memory.
+    pub fn write_key<K>(&self, key: &K) -> Result<()>
+        where K: KeyFile + Clone + Send + 'static
+    {
+        self.key_cache.write_key(key)?;
+        let mut inner = self.inner();
+        inner.insert(key);
+        let named_revision = key.named_revision();
+        // Only a latest revision we already know of can be replaced;
+        // if we don't know it, there may be newer revisions on disk.
+        if let Some(latest) = inner.latest
+                                   .get_mut(&(K::key_type(), named_revision.name().clone()))
+        {
+            if named_revision.revision() > latest.revision() {
+                *latest = named_revision.clone();
+            }
+        }
+        Ok(())
+    }
+
+    /// Note: name is just the name, not the name + revision
+    pub fn latest_ring_key_revision(&self, name: &str) -> Result<RingKey> {
+        self.fetch_latest_revision(name)
+    }
+
+    pub fn latest_secret_origin_signing_key(&self,
+                                            origin: &Origin)
+                                            -> Result<SecretOriginSigningKey> {
+        self.fetch_latest_revision(origin.as_ref())
+    }
+
+    pub fn latest_public_origin_signing_key(&self,
+                                            origin: &Origin)
+                                            -> Result<PublicOriginSigningKey> {
+        self.fetch_latest_revision(origin.as_ref())
+    }
+
+    pub fn latest_user_secret_key(&self, user_name: &str) -> Result<UserSecretEncryptionKey> {
+        self.fetch_latest_revision(user_name)
+    }
+
+    pub fn latest_origin_public_encryption_key(&self,
+                                               origin: &Origin)
+                                               -> Result<OriginPublicEncryptionKey> {
+        self.fetch_latest_revision(origin.as_ref())
+    }
+
+    /// Name should be in `"service.group@org"` format.
+    pub fn latest_service_public_key(&self, name: &str) -> Result<ServicePublicEncryptionKey> {
+        self.fetch_latest_revision(name)
+    }
+
+    pub fn latest_builder_key(&self) -> Result<BuilderSecretEncryptionKey> {
+        self.fetch_latest_revision(BUILDER_KEY_NAME)
+    }
+
+    pub fn public_signing_key(&self,
+                              named_revision: &NamedRevision)
+                              -> Result<PublicOriginSigningKey> {
+        self.fetch_specific_revision(named_revision)
+    }
+
+    pub fn secret_signing_key(&self,
+                              named_revision: &NamedRevision)
+                              -> Result<SecretOriginSigningKey> {
+        self.fetch_specific_revision(named_revision)
+    }
+
+    pub fn user_public_encryption_key(&self,
+                                      named_revision: &NamedRevision)
+                                      -> Result<UserPublicEncryptionKey> {
+        self.fetch_specific_revision(named_revision)
+    }
+
+    pub fn service_secret_encryption_key(&self,
+                                         named_revision: &NamedRevision)
+                                         -> Result<ServiceSecretEncryptionKey> {
+        self.fetch_specific_revision(named_revision)
+    }
+
+    pub fn builder_secret_encryption_key(&self,
+                                         named_revision: &NamedRevision)
+                                         -> Result<BuilderSecretEncryptionKey> {
+        self.fetch_specific_revision(named_revision)
+    }
+
+    ////////////////////////////////////////////////////////////////////////
+
+    fn fetch_latest_revision<K>(&self, name: &str) -> Result<K>
+        where K: KeyFile + FromStr<Err = Error> + Clone + Send + 'static
+    {
+        let mut inner = self.inner();
+        let cached = inner.latest
+                          .get(&(K::key_type(), name.to_string()))
+                          .and_then(|named_revision| inner.get::<K>(named_revision));
+        if let Some(key) = cached {
+            return Ok(key);
+        }
+        let key = self.key_cache.fetch_latest_revision::<K>(name)?;
+        inner.insert(&key);
+        inner.latest.insert((K::key_type(), name.to_string()),
+                            key.named_revision().clone());
+        Ok(key)
+    }
+
+    fn fetch_specific_revision<K>(&self, named_revision: &NamedRevision) -> Result<K>
+        where K: KeyFile + FromStr<Err = Error> + Clone + Send + 'static
+    {
+        let mut inner = self.inner();
+        if let Some(key) = inner.get::<K>(named_revision) {
+            return Ok(key);
+        }
+        let key = self.key_cache
+                      .fetch_specific_revision::<K>(named_revision)?;
+        inner.insert(&key);
+        Ok(key)
+    }
+
+    fn inner(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().expect("CachedKeyCache lock poisoned")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{keys::Key,
+                        test_support::*};
+
+    #[test]
+    fn latest_revision_is_read_from_disk_only_once() {
+        let (key_cache, _dir) = new_cache();
+        let key = key_cache.new_ring_key("beyonce").unwrap();
+        let cache = CachedKeyCache::new(key_cache.clone());
+
+        assert_eq!(cache.latest_ring_key_revision("beyonce").unwrap(), key);
+        std::fs::remove_file(key_cache.path_in_cache(&key)).unwrap();
+        assert_eq!(cache.latest_ring_key_revision("beyonce").unwrap(), key);
+
+        cache.invalidate();
+        match cache.latest_ring_key_revision("beyonce") {
+            Err(Error::KeyNotFound { .. }) => (),
+            other => panic!("Expected KeyNotFound, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn specific_revision_is_read_from_disk_only_once() {
+        let (key_cache, _dir) = new_cache();
+        let origin = "my-origin".parse().unwrap();
+        let (public, _secret) = key_cache.new_signing_pair(&origin).unwrap();
+        let cache = CachedKeyCache::new(key_cache.clone());
+
+        assert_eq!(cache.public_signing_key(public.named_revision()).unwrap(),
+                   public);
+        std::fs::remove_file(key_cache.path_in_cache(&public)).unwrap();
+        assert_eq!(cache.public_signing_key(public.named_revision()).unwrap(),
+                   public);
+    }
+
+    #[test]
+    fn missing_keys_are_looked_for_again() {
+        let (key_cache, _dir) = new_cache();
+        let cache = CachedKeyCache::new(key_cache.clone());
+        assert!(cache.latest_ring_key_revision("beyonce").is_err());
+
+        let key = key_cache.new_ring_key("beyonce").unwrap();
+        assert_eq!(cache.latest_ring_key_revision("beyonce").unwrap(), key);
+    }
+
+    #[test]
+    fn writes_update_the_latest_revision() {
+        let (key_cache, _dir) = new_cache();
+        let cache = CachedKeyCache::new(key_cache);
+        let mut rng = seeded_rng();
+        let old = RingKey::new_with("beyonce", &mut rng, revision(1));
+        cache.write_key(&old).unwrap();
+        assert_eq!(cache.latest_ring_key_revision("beyonce").unwrap(), old);
+
+        let new = RingKey::new_with("beyonce", &mut rng, revision(2));
+        cache.write_key(&new).unwrap();
+        assert_eq!(cache.latest_ring_key_revision("beyonce").unwrap(), new);
+    }
+
+    #[test]
+    fn keys_of_different_types_are_kept_apart() {
+        let (key_cache, _dir) = new_cache();
+        let origin = "my-origin".parse().unwrap();
+        let (public, secret) = key_cache.new_signing_pair(&origin).unwrap();
+        let cache = CachedKeyCache::new(key_cache);
+
+        assert_eq!(cache.latest_public_origin_signing_key(&origin).unwrap(),
+                   public);
+        assert_eq!(cache.latest_secret_origin_signing_key(&origin).unwrap(),
+                   secret);
+        assert_eq!(cache.secret_signing_key(secret.named_revision()).unwrap(),
+                   secret);
+    }
+}
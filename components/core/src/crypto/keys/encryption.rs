@@ -8,29 +8,48 @@ pub use builder_key::{generate_builder_encryption_key,
                       BuilderSecretEncryptionKey,
                       BUILDER_KEY_NAME};
 pub use message::{AnonymousBox,
+                  MultiRecipientBox,
+                  ServiceMessage,
                   SignedBox};
 pub use origin_key::{generate_origin_encryption_key_pair,
+                     generate_origin_encryption_key_pair_with,
                      OriginPublicEncryptionKey,
                      OriginSecretEncryptionKey};
 pub use service_key::{generate_service_encryption_key_pair,
+                      generate_service_encryption_key_pair_for,
+                      generate_service_encryption_key_pair_with,
                       ServicePublicEncryptionKey,
                       ServiceSecretEncryptionKey};
 pub use user_key::{generate_user_encryption_key_pair,
+                   generate_user_encryption_key_pair_with,
                    UserPublicEncryptionKey,
                    UserSecretEncryptionKey};
 
-/// The suffix on the end of a public encryption key file
-const PUBLIC_KEY_SUFFIX: &str = "pub";
+/// The suffix on the end of a public user or origin encryption key
+/// file
+const PUBLIC_BOX_KEY_SUFFIX: &str = "box.pub";
+/// The suffix on the end of a public service encryption key file
+const PUBLIC_SERVICE_KEY_SUFFIX: &str = "service.pub";
+/// The suffix on the end of a public encryption key file, before
+/// public signing and encryption keys had different suffixes
+const LEGACY_PUBLIC_KEY_SUFFIX: &str = "pub";
 /// The suffix on the end of a secret encryption key file
 const SECRET_BOX_KEY_SUFFIX: &str = "box.key";
 /// Format version identifier for public encryption keys.
 const PUBLIC_BOX_KEY_VERSION: &str = "BOX-PUB-1";
 /// Format version identifier for secret encryption keys.
 const SECRET_BOX_KEY_VERSION: &str = "BOX-SEC-1";
+/// The DER encoding of a SubjectPublicKeyInfo for an X25519 public
+/// key, up to the key itself.
+const X25519_SPKI_PREFIX: [u8; 12] =
+    [0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x6e, 0x03, 0x21, 0x00];
 
 /// Private module to re-export the various sodiumoxide concepts we
 /// use, to ensure everyone is using them consistently.
 mod primitives {
+    use rand::{CryptoRng,
+               RngCore};
+    use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::SECRETKEYBYTES;
     pub use sodiumoxide::crypto::{box_::{curve25519xsalsa20poly1305::{gen_nonce,
                                                                       Nonce,
                                                                       PublicKey,
@@ -38,5 +57,16 @@ mod primitives {
                                          gen_keypair,
                                          open,
                                          seal},
-                                  sealedbox};
+                                  sealedbox,
+                                  secretbox};
+
+    /// Like `gen_keypair`, but with the secret key drawn from `rng`.
+    pub fn gen_keypair_from<R>(rng: &mut R) -> (PublicKey, SecretKey)
+        where R: RngCore + CryptoRng
+    {
+        let mut bytes = [0; SECRETKEYBYTES];
+        rng.fill_bytes(&mut bytes);
+        let sk = SecretKey(bytes);
+        (sk.public_key(), sk)
+    }
 }
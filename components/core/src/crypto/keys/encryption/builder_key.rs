@@ -22,7 +22,8 @@ pub fn generate_builder_encryption_key() -> BuilderSecretEncryptionKey {
     let (_pk, sk) = primitives::gen_keypair();
 
     BuilderSecretEncryptionKey { named_revision,
-                                 key: sk }
+                                 key: sk,
+                                 expiration: None }
 }
 
 ////////////////////////////////////////////////////////////////////////
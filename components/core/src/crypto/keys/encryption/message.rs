@@ -12,6 +12,10 @@ const BOX_FORMAT_VERSION: &str = "BOX-1";
 /// Version identifier for anonymous encrypted messages.
 const ANONYMOUS_BOX_FORMAT_VERSION: &str = "ANONYMOUS-BOX-1";
 
+/// Version identifier for signed encrypted messages with multiple
+/// recipients.
+const MULTI_BOX_FORMAT_VERSION: &str = "MULTI-BOX-1";
+
 /// An anonymously encrypted message. The message was encrypted with
 /// the recipient's public key, and can only be decoded by the
 /// recipient's private key. The identity of the sender cannot be
@@ -35,6 +39,13 @@ impl AnonymousBox {
     pub fn key_pair(&self) -> &NamedRevision { &self.key_pair }
 
     pub fn ciphertext(&self) -> &[u8] { &self.ciphertext }
+
+    /// Helper function to parse an `AnonymousBox` from raw bytes.
+    pub fn from_bytes<B>(bytes: B) -> Result<Self>
+        where B: AsRef<[u8]>
+    {
+        str::from_utf8(bytes.as_ref())?.parse()
+    }
 }
 
 impl fmt::Display for AnonymousBox {
@@ -218,6 +229,227 @@ impl FromStr for SignedBox {
     }
 }
 
+////////////////////////////////////////////////////////////////////////
+
+/// An encrypted message signed by the sender and addressed to any
+/// number of receivers at once.
+///
+/// The message itself is encrypted just once, with a freshly
+/// generated symmetric key. That key is then encrypted separately for
+/// each receiver, just as the whole message would be in a
+/// `SignedBox`, using the sender's secret key and that receiver's
+/// public key. Each receiver finds the copy of the key addressed to
+/// it by the identifier of its own key pair.
+///
+/// Messages in this format are marked with their own version, so
+/// they're never confused with `SignedBox` messages.
+#[derive(Debug)]
+pub struct MultiRecipientBox {
+    /// The identity of the keypair of the sender of this message.
+    encryptor:  NamedRevision,
+    /// The nonce used to encrypt the message with its symmetric key.
+    nonce:      primitives::secretbox::Nonce,
+    /// The encrypted ciphertext of the message.
+    ciphertext: Vec<u8>,
+    /// The symmetric key of the message, encrypted for each receiver.
+    recipients: Vec<RecipientSlot>,
+}
+
+/// The symmetric key of a `MultiRecipientBox`, encrypted for a single
+/// receiver.
+#[derive(Debug)]
+pub(super) struct RecipientSlot {
+    /// The identity of the keypair of the receiver.
+    pub(super) decryptor:  NamedRevision,
+    /// The cryptographic nonce used to encrypt the key.
+    pub(super) nonce:      primitives::Nonce,
+    /// The encrypted symmetric key.
+    pub(super) sealed_key: Vec<u8>,
+}
+
+impl MultiRecipientBox {
+    /// Create a new MultiRecipientBox. Intentionally private to the
+    /// encryption module.
+    pub(super) fn new(encryptor: NamedRevision,
+                      nonce: primitives::secretbox::Nonce,
+                      ciphertext: Vec<u8>,
+                      recipients: Vec<RecipientSlot>)
+                      -> Self {
+        Self { encryptor,
+               nonce,
+               ciphertext,
+               recipients }
+    }
+
+    pub fn encryptor(&self) -> &NamedRevision { &self.encryptor }
+
+    /// The identities of the keypairs of every receiver of this
+    /// message.
+    pub fn decryptors(&self) -> impl Iterator<Item = &NamedRevision> {
+        self.recipients.iter().map(|slot| &slot.decryptor)
+    }
+
+    pub fn ciphertext(&self) -> &[u8] { &self.ciphertext }
+
+    pub(super) fn nonce(&self) -> &primitives::secretbox::Nonce { &self.nonce }
+
+    /// The encrypted key addressed to the given keypair, if there is
+    /// one.
+    pub(super) fn slot_for(&self, decryptor: &NamedRevision) -> Option<&RecipientSlot> {
+        self.recipients
+            .iter()
+            .find(|slot| &slot.decryptor == decryptor)
+    }
+
+    /// Helper function to parse a `MultiRecipientBox` from raw bytes.
+    pub fn from_bytes<B>(bytes: B) -> Result<Self>
+        where B: AsRef<[u8]>
+    {
+        str::from_utf8(bytes.as_ref())?.parse()
+    }
+}
+
+impl fmt::Display for MultiRecipientBox {
+    /// The version and encryptor identifier are in plaintext,
+    /// followed by the base64 encoded nonce and ciphertext of the
+    /// message. Each remaining line holds a decryptor identifier in
+    /// plaintext, then the base64 encoded nonce and encrypted key for
+    /// that decryptor, separated by spaces.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{}\n{}\n{}\n{}",
+               MULTI_BOX_FORMAT_VERSION,
+               self.encryptor,
+               crate::base64::encode(self.nonce),
+               crate::base64::encode(&self.ciphertext))?;
+        for slot in &self.recipients {
+            write!(f,
+                   "\n{} {} {}",
+                   slot.decryptor,
+                   crate::base64::encode(slot.nonce),
+                   crate::base64::encode(&slot.sealed_key))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for MultiRecipientBox {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut lines = s.lines();
+
+        lines.next()
+             .ok_or_else(|| Error::CryptoError("Corrupt payload, can't read version".to_string()))
+             .map(|line| {
+                 if line == MULTI_BOX_FORMAT_VERSION {
+                     Ok(line)
+                 } else {
+                     Err(Error::CryptoError(format!("Unsupported version: {}", line)))
+                 }
+             })??;
+
+        let encryptor = lines.next()
+                             .ok_or_else(|| {
+                                 Error::CryptoError("Corrupt payload, can't read encryptor \
+                                                     identifier"
+                                                                .to_string())
+                             })?
+                             .parse()?;
+
+        let nonce =
+            lines.next()
+                 .ok_or_else(|| Error::CryptoError("Corrupt payload, can't read nonce".to_string()))
+                 .map(crate::base64::decode)?
+                 .map_err(|e| Error::CryptoError(format!("Can't decode nonce: {}", e)))
+                 .map(|bytes| primitives::secretbox::Nonce::from_slice(bytes.as_ref()))?
+                 .ok_or_else(|| Error::CryptoError("Invalid size of nonce".to_string()))?;
+
+        let ciphertext =
+            lines.next()
+                 .ok_or_else(|| {
+                     Error::CryptoError("Corrupt payload, can't read ciphertext".to_string())
+                 })
+                 .map(crate::base64::decode)?
+                 .map_err(|e| Error::CryptoError(format!("Can't decode ciphertext: {}", e)))?;
+
+        let recipients = lines.map(str::parse)
+                              .collect::<Result<Vec<RecipientSlot>>>()?;
+
+        Ok(MultiRecipientBox { encryptor,
+                               nonce,
+                               ciphertext,
+                               recipients })
+    }
+}
+
+impl FromStr for RecipientSlot {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut fields = s.split(' ');
+
+        let decryptor = fields.next()
+                              .ok_or_else(|| {
+                                  Error::CryptoError("Corrupt payload, can't read decryptor \
+                                                      identifier"
+                                                                 .to_string())
+                              })?
+                              .parse()?;
+
+        let nonce =
+            fields.next()
+                  .ok_or_else(|| {
+                      Error::CryptoError("Corrupt payload, can't read nonce".to_string())
+                  })
+                  .map(crate::base64::decode)?
+                  .map_err(|e| Error::CryptoError(format!("Can't decode nonce: {}", e)))
+                  .map(|bytes| primitives::Nonce::from_slice(bytes.as_ref()))?
+                  .ok_or_else(|| Error::CryptoError("Invalid size of nonce".to_string()))?;
+
+        let sealed_key =
+            fields.next()
+                  .ok_or_else(|| Error::CryptoError("Corrupt payload, can't read key".to_string()))
+                  .map(crate::base64::decode)?
+                  .map_err(|e| Error::CryptoError(format!("Can't decode key: {}", e)))?;
+
+        Ok(RecipientSlot { decryptor,
+                           nonce,
+                           sealed_key })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////
+
+/// An encrypted message for a service, which may have been sent by a
+/// user (a `SignedBox`) or by someone without a user key at all (an
+/// `AnonymousBox`). The two are told apart by their versions.
+#[derive(Debug)]
+pub enum ServiceMessage {
+    Signed(SignedBox),
+    Anonymous(AnonymousBox),
+}
+
+impl ServiceMessage {
+    /// Helper function to parse a `ServiceMessage` from raw bytes.
+    pub fn from_bytes<B>(bytes: B) -> Result<Self>
+        where B: AsRef<[u8]>
+    {
+        str::from_utf8(bytes.as_ref())?.parse()
+    }
+}
+
+impl FromStr for ServiceMessage {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.lines().next() {
+            Some(ANONYMOUS_BOX_FORMAT_VERSION) => s.parse().map(ServiceMessage::Anonymous),
+            _ => s.parse().map(ServiceMessage::Signed),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -308,4 +540,48 @@ mod tests {
             assert_eq!(signed.to_string(), SIGNED_TEXT);
         }
     }
+
+    mod multi_recipient {
+        use super::*;
+        use crate::crypto::keys::{generate_service_encryption_key_pair,
+                                  generate_user_encryption_key_pair,
+                                  Key};
+
+        #[test]
+        fn string_round_trip() {
+            let (_, user) = generate_user_encryption_key_pair("my-user");
+            let (alpha, _) = generate_service_encryption_key_pair("my-org", "alpha.default");
+            let (beta, _) = generate_service_encryption_key_pair("my-org", "beta.default");
+            let multi_box = user.encrypt_for_many(b"payload", &[&alpha, &beta]);
+
+            let parsed = multi_box.to_string().parse::<MultiRecipientBox>().unwrap();
+            assert_eq!(parsed.to_string(), multi_box.to_string());
+            assert_eq!(parsed.encryptor(), user.named_revision());
+            assert_eq!(parsed.decryptors().collect::<Vec<_>>(),
+                       vec![alpha.named_revision(), beta.named_revision()]);
+        }
+
+        #[test]
+        fn formats_are_not_confused() {
+            let (_, user) = generate_user_encryption_key_pair("my-user");
+            let (service, _) = generate_service_encryption_key_pair("my-org", "alpha.default");
+
+            let signed = user.encrypt_for_service(b"payload", &service).to_string();
+            assert!(signed.parse::<MultiRecipientBox>().is_err());
+            assert!(signed.parse::<SignedBox>().is_ok());
+
+            let multi = user.encrypt_for_many(b"payload", &[&service]).to_string();
+            assert!(multi.parse::<SignedBox>().is_err());
+        }
+
+        #[test]
+        fn corrupt_recipient_is_an_error() {
+            let (_, user) = generate_user_encryption_key_pair("my-user");
+            let (service, _) = generate_service_encryption_key_pair("my-org", "alpha.default");
+            let multi = user.encrypt_for_many(b"payload", &[&service]).to_string();
+
+            let truncated = format!("{}\n{}", multi, service.named_revision());
+            assert!(truncated.parse::<MultiRecipientBox>().is_err());
+        }
+    }
 }
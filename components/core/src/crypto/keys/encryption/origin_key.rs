@@ -16,17 +16,22 @@
 //! for them in the system. Thus, they only deal with anonymous
 //! messages.
 use crate::{crypto::keys::{encryption::{primitives,
+                                        LEGACY_PUBLIC_KEY_SUFFIX,
+                                        PUBLIC_BOX_KEY_SUFFIX,
                                         PUBLIC_BOX_KEY_VERSION,
-                                        PUBLIC_KEY_SUFFIX,
                                         SECRET_BOX_KEY_SUFFIX,
-                                        SECRET_BOX_KEY_VERSION},
+                                        SECRET_BOX_KEY_VERSION,
+                                        X25519_SPKI_PREFIX},
                            AnonymousBox,
                            Key,
+                           KeyRevision,
                            NamedRevision},
             error::{Error,
                     Result},
             fs::Permissions,
             origin::Origin};
+use rand::{CryptoRng,
+           RngCore};
 
 /// Given the name of an origin, generate a new encryption key pair.
 ///
@@ -39,13 +44,33 @@ use crate::{crypto::keys::{encryption::{primitives,
 pub fn generate_origin_encryption_key_pair(
     origin: &Origin)
     -> (OriginPublicEncryptionKey, OriginSecretEncryptionKey) {
-    let named_revision = NamedRevision::new(origin.to_string());
-    let (pk, sk) = primitives::gen_keypair();
+    origin_encryption_key_pair(NamedRevision::new(origin.to_string()),
+                               primitives::gen_keypair())
+}
+
+/// Like `generate_origin_encryption_key_pair`, but with key material
+/// drawn from `rng` and the given revision, so that tests can create
+/// keys reproducibly.
+pub fn generate_origin_encryption_key_pair_with<R>(
+    origin: &Origin,
+    rng: &mut R,
+    revision: KeyRevision)
+    -> (OriginPublicEncryptionKey, OriginSecretEncryptionKey)
+    where R: RngCore + CryptoRng
+{
+    origin_encryption_key_pair(NamedRevision::from_parts(origin.to_string(), revision),
+                               primitives::gen_keypair_from(rng))
+}
 
+fn origin_encryption_key_pair(named_revision: NamedRevision,
+                              (pk, sk): (primitives::PublicKey, primitives::SecretKey))
+                              -> (OriginPublicEncryptionKey, OriginSecretEncryptionKey) {
     let public = OriginPublicEncryptionKey { named_revision: named_revision.clone(),
-                                             key:            pk, };
+                                             key:            pk,
+                                             expiration:     None, };
     let secret = OriginSecretEncryptionKey { named_revision,
-                                             key: sk };
+                                             key: sk,
+                                             expiration: None };
     (public, secret)
 }
 
@@ -56,8 +81,11 @@ gen_key!(/// Public key used to anonymously encrypt secrets for
          OriginPublicEncryptionKey,
          key_material: primitives::PublicKey,
          file_format_version: PUBLIC_BOX_KEY_VERSION,
-         file_extension: PUBLIC_KEY_SUFFIX,
-         file_permissions: crate::fs::DEFAULT_PUBLIC_KEY_PERMISSIONS);
+         file_extension: PUBLIC_BOX_KEY_SUFFIX,
+         file_permissions: crate::fs::DEFAULT_PUBLIC_KEY_PERMISSIONS,
+         legacy_file_extension: LEGACY_PUBLIC_KEY_SUFFIX);
+
+public_key_encodings_for_key!(OriginPublicEncryptionKey, X25519_SPKI_PREFIX);
 
 impl OriginPublicEncryptionKey {
     pub fn encrypt(&self, data: &[u8]) -> AnonymousBox {
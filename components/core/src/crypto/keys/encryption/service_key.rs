@@ -11,53 +11,108 @@
 //! such rumors by controlling which user public keys are present on a
 //! Supervisor.
 use crate::{crypto::keys::{encryption::{primitives,
+                                        AnonymousBox,
+                                        MultiRecipientBox,
                                         SignedBox,
+                                        LEGACY_PUBLIC_KEY_SUFFIX,
                                         PUBLIC_BOX_KEY_VERSION,
-                                        PUBLIC_KEY_SUFFIX,
+                                        PUBLIC_SERVICE_KEY_SUFFIX,
                                         SECRET_BOX_KEY_SUFFIX,
-                                        SECRET_BOX_KEY_VERSION},
+                                        SECRET_BOX_KEY_VERSION,
+                                        X25519_SPKI_PREFIX},
                            Key,
+                           KeyRevision,
                            NamedRevision,
                            UserPublicEncryptionKey},
             error::{Error,
                     Result},
-            fs::Permissions};
+            fs::Permissions,
+            service::ServiceGroupIdent};
+use rand::{CryptoRng,
+           RngCore};
 
-/// Given the name of an org and a service group, generate a new
-/// encryption key pair.
+/// Given a service group, generate a new encryption key pair.
 ///
 /// The resulting keys will need to be saved to a cache in order to
 /// persist.
+pub fn generate_service_encryption_key_pair_for(
+    service_group: &ServiceGroupIdent)
+    -> (ServicePublicEncryptionKey, ServiceSecretEncryptionKey) {
+    service_encryption_key_pair(NamedRevision::new(service_key_name(service_group)),
+                                primitives::gen_keypair())
+}
+
+/// Like `generate_service_encryption_key_pair_for`, but with the
+/// org and service group (like `"redis.default"`, not simply
+/// `"redis"`) given separately.
+///
+/// Panics if they don't make a valid `ServiceGroupIdent`.
 pub fn generate_service_encryption_key_pair(
     org_name: &str,
     service_group_name: &str)
     -> (ServicePublicEncryptionKey, ServiceSecretEncryptionKey) {
-    let key_name = service_key_name(org_name, service_group_name);
-    let named_revision = NamedRevision::new(key_name);
-    let (pk, sk) = primitives::gen_keypair();
+    generate_service_encryption_key_pair_for(&service_group_ident(org_name, service_group_name))
+}
 
+/// Like `generate_service_encryption_key_pair`, but with key material
+/// drawn from `rng` and the given revision, so that tests can create
+/// keys reproducibly.
+pub fn generate_service_encryption_key_pair_with<R>(
+    org_name: &str,
+    service_group_name: &str,
+    rng: &mut R,
+    revision: KeyRevision)
+    -> (ServicePublicEncryptionKey, ServiceSecretEncryptionKey)
+    where R: RngCore + CryptoRng
+{
+    let key_name = service_key_name(&service_group_ident(org_name, service_group_name));
+    service_encryption_key_pair(NamedRevision::from_parts(key_name, revision),
+                                primitives::gen_keypair_from(rng))
+}
+
+fn service_group_ident(org_name: &str, service_group_name: &str) -> ServiceGroupIdent {
+    ServiceGroupIdent::from_org_and_service_group(org_name, service_group_name)
+        .unwrap_or_else(|e| panic!("{}", e))
+}
+
+fn service_encryption_key_pair(named_revision: NamedRevision,
+                               (pk, sk): (primitives::PublicKey, primitives::SecretKey))
+                               -> (ServicePublicEncryptionKey, ServiceSecretEncryptionKey) {
     let public = ServicePublicEncryptionKey { named_revision: named_revision.clone(),
-                                              key:            pk, };
+                                              key:            pk,
+                                              expiration:     None, };
     let secret = ServiceSecretEncryptionKey { named_revision,
-                                              key: sk };
+                                              key: sk,
+                                              expiration: None };
     (public, secret)
 }
 
-/// Generate the name of a service key.
-///
-/// Note that `service_group_name` is like `"redis.default"`, not
-/// simply `"redis"`.
-fn service_key_name(org_name: &str, service_group_name: &str) -> String {
-    format!("{}@{}", service_group_name, org_name)
-}
+/// Generate the name of a service key, which is always the
+/// `service.group@org` form of the service group it's for.
+fn service_key_name(service_group: &ServiceGroupIdent) -> String { service_group.to_string() }
 
 ////////////////////////////////////////////////////////////////////////
 
 gen_key!(ServicePublicEncryptionKey,
          key_material: primitives::PublicKey,
          file_format_version: PUBLIC_BOX_KEY_VERSION,
-         file_extension: PUBLIC_KEY_SUFFIX,
-         file_permissions: crate::fs::DEFAULT_PUBLIC_KEY_PERMISSIONS);
+         file_extension: PUBLIC_SERVICE_KEY_SUFFIX,
+         file_permissions: crate::fs::DEFAULT_PUBLIC_KEY_PERMISSIONS,
+         legacy_file_extension: LEGACY_PUBLIC_KEY_SUFFIX);
+
+public_key_encodings_for_key!(ServicePublicEncryptionKey, X25519_SPKI_PREFIX);
+
+impl ServicePublicEncryptionKey {
+    /// Encrypt a message for a service anonymously, for senders that
+    /// have no user key of their own. Only this key's revision is
+    /// recorded in the message; nothing identifies the sender.
+    ///
+    /// Decrypt with `ServiceSecretEncryptionKey::unseal`.
+    pub fn seal(&self, data: &[u8]) -> AnonymousBox {
+        let ciphertext = primitives::sealedbox::seal(data, self.key());
+        AnonymousBox::new(self.named_revision().clone(), ciphertext)
+    }
+}
 
 ////////////////////////////////////////////////////////////////////////
 
@@ -90,14 +145,66 @@ impl ServiceSecretEncryptionKey {
                                                                    .to_string())
         })
     }
+
+    /// Decrypt a message sent from a user to several services at once,
+    /// this one among them.
+    ///
+    /// Returns an error if the message wasn't addressed to this key.
+    pub fn decrypt_multi_recipient_message(&self,
+                                           multi_box: &MultiRecipientBox,
+                                           sending_user: &UserPublicEncryptionKey)
+                                           -> Result<Vec<u8>> {
+        let slot = match multi_box.slot_for(self.named_revision()) {
+            Some(slot) => slot,
+            None => {
+                let msg = format!("Message is not addressed to {}", self.named_revision());
+                return Err(Error::CryptoError(msg));
+            }
+        };
+        let message_key = match primitives::open(&slot.sealed_key,
+                                                 &slot.nonce,
+                                                 sending_user.key(),
+                                                 self.key())
+        {
+            Ok(bytes) => primitives::secretbox::Key::from_slice(&bytes),
+            Err(_) => None,
+        };
+        let message_key = match message_key {
+            Some(key) => key,
+            None => {
+                let msg = "Secret key, public key, and nonce could not decrypt message key";
+                return Err(Error::CryptoError(msg.to_string()));
+            }
+        };
+        primitives::secretbox::open(multi_box.ciphertext(), multi_box.nonce(), &message_key)
+            .map_err(|_| Error::CryptoError("Message key could not decrypt ciphertext".to_string()))
+    }
+
+    /// Decrypt a message sealed anonymously with the corresponding
+    /// `ServicePublicEncryptionKey`.
+    ///
+    /// Returns an error if the message wasn't addressed to this key.
+    pub fn unseal(&self, anonymous_box: &AnonymousBox) -> Result<Vec<u8>> {
+        if anonymous_box.key_pair() != self.named_revision() {
+            let msg = format!("Message is not addressed to {}", self.named_revision());
+            return Err(Error::CryptoError(msg));
+        }
+        let pk = self.key().public_key();
+        primitives::sealedbox::open(anonymous_box.ciphertext(), &pk, self.key())
+            .map_err(|_| Error::CryptoError("Could not decrypt anonymous message".to_string()))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::crypto::{keys::{SignedBox,
+    use crate::crypto::{keys::{generate_service_encryption_key_pair_with,
+                               generate_user_encryption_key_pair,
+                               SignedBox,
                                UserSecretEncryptionKey},
-                        test_support::fixture_key};
+                        test_support::{fixture_key,
+                                       revision,
+                                       seeded_rng}};
 
     #[test]
     fn decryption() {
@@ -138,4 +245,184 @@ mod tests {
 
         assert_eq!(decrypted_message, message);
     }
+
+    mod sealed {
+        use super::*;
+        use crate::crypto::keys::ServiceMessage;
+
+        #[test]
+        fn round_trip() {
+            let (public, secret) = generate_service_encryption_key_pair_with("acme",
+                                                                             "svc.default",
+                                                                             &mut seeded_rng(),
+                                                                             revision(1));
+            let message = "Multipass!";
+            let sealed = public.seal(message.as_bytes());
+            let sealed = AnonymousBox::from_bytes(sealed.to_string()).unwrap();
+
+            assert_eq!(secret.unseal(&sealed).unwrap(), message.as_bytes());
+        }
+
+        #[test]
+        fn other_services_cannot_unseal() {
+            let mut rng = seeded_rng();
+            let (public, _) = generate_service_encryption_key_pair_with("acme",
+                                                                        "svc.default",
+                                                                        &mut rng,
+                                                                        revision(1));
+            let (_, other) = generate_service_encryption_key_pair_with("acme",
+                                                                       "other.default",
+                                                                       &mut rng,
+                                                                       revision(1));
+            let sealed = public.seal(b"Multipass!");
+
+            match other.unseal(&sealed) {
+                Err(Error::CryptoError(msg)) => {
+                    assert_eq!(msg,
+                               format!("Message is not addressed to {}", other.named_revision()))
+                }
+                other => panic!("Expected CryptoError, got {:?}", other),
+            }
+        }
+
+        #[test]
+        fn a_different_key_with_the_same_name_cannot_unseal() {
+            let mut rng = seeded_rng();
+            let (public, _) = generate_service_encryption_key_pair_with("acme",
+                                                                        "svc.default",
+                                                                        &mut rng,
+                                                                        revision(1));
+            let (_, imposter) = generate_service_encryption_key_pair_with("acme",
+                                                                          "svc.default",
+                                                                          &mut rng,
+                                                                          revision(1));
+            let sealed = public.seal(b"Multipass!");
+
+            assert!(imposter.unseal(&sealed).is_err());
+        }
+
+        #[test]
+        fn only_the_recipient_is_identified() {
+            let (public, _) = generate_service_encryption_key_pair_with("acme",
+                                                                        "svc.default",
+                                                                        &mut seeded_rng(),
+                                                                        revision(1));
+            let serialized = public.seal(b"Multipass!").to_string();
+            let lines = serialized.lines().collect::<Vec<_>>();
+
+            assert_eq!(lines.len(), 3);
+            assert_eq!(lines[0], "ANONYMOUS-BOX-1");
+            assert_eq!(lines[1], public.named_revision().to_string());
+            assert!(lines[2].parse::<NamedRevision>().is_err());
+        }
+
+        #[test]
+        fn service_messages_are_told_apart_by_version() {
+            let (service_public, _) = generate_service_encryption_key_pair_with("acme",
+                                                                                "svc.default",
+                                                                                &mut seeded_rng(),
+                                                                                revision(1));
+            let (_, user_secret) = generate_user_encryption_key_pair("ruby-rhod");
+
+            let sealed = service_public.seal(b"Multipass!").to_string();
+            let signed = user_secret.encrypt_for_service(b"Multipass!", &service_public)
+                                    .to_string();
+
+            assert!(matches!(ServiceMessage::from_bytes(sealed).unwrap(),
+                             ServiceMessage::Anonymous(_)));
+            assert!(matches!(ServiceMessage::from_bytes(signed).unwrap(),
+                             ServiceMessage::Signed(_)));
+            assert!(ServiceMessage::from_bytes("NOT-A-BOX-1\n").is_err());
+        }
+    }
+
+    mod multi_recipient {
+        use super::*;
+
+        /// Generate service key pairs for `count` different service
+        /// groups.
+        fn service_pairs(count: usize)
+                         -> Vec<(ServicePublicEncryptionKey, ServiceSecretEncryptionKey)> {
+            let mut rng = seeded_rng();
+            (0..count).map(|n| {
+                          generate_service_encryption_key_pair_with("acme",
+                                                                    &format!("svc{}.default", n),
+                                                                    &mut rng,
+                                                                    revision(1))
+                      })
+                      .collect()
+        }
+
+        #[test]
+        fn every_recipient_can_decrypt() {
+            let (user_public, user_secret) = generate_user_encryption_key_pair("ruby-rhod");
+            let services = service_pairs(3);
+            let recipients = services.iter()
+                                     .map(|(public, _)| public)
+                                     .collect::<Vec<_>>();
+
+            let message = "Bzzzz! Bzzzz!";
+            let multi_box = user_secret.encrypt_for_many(message.as_bytes(), &recipients);
+            let multi_box = MultiRecipientBox::from_bytes(multi_box.to_string()).unwrap();
+
+            for (_, service_secret) in &services {
+                let decrypted = service_secret.decrypt_multi_recipient_message(&multi_box,
+                                                                               &user_public)
+                                              .unwrap();
+                assert_eq!(std::str::from_utf8(&decrypted).unwrap(), message);
+            }
+        }
+
+        #[test]
+        fn dozens_of_recipients() {
+            let (user_public, user_secret) = generate_user_encryption_key_pair("ruby-rhod");
+            let services = service_pairs(48);
+            let recipients = services.iter()
+                                     .map(|(public, _)| public)
+                                     .collect::<Vec<_>>();
+
+            let multi_box = user_secret.encrypt_for_many(b"everyone", &recipients);
+            let multi_box = MultiRecipientBox::from_bytes(multi_box.to_string()).unwrap();
+            assert_eq!(multi_box.decryptors().count(), 48);
+
+            for (_, service_secret) in &services {
+                assert_eq!(service_secret.decrypt_multi_recipient_message(&multi_box,
+                                                                          &user_public)
+                                         .unwrap(),
+                           b"everyone");
+            }
+        }
+
+        #[test]
+        fn absent_recipient_cannot_decrypt() {
+            let (user_public, user_secret) = generate_user_encryption_key_pair("ruby-rhod");
+            let mut services = service_pairs(3);
+            let (_, outsider) = services.pop().unwrap();
+            let recipients = services.iter()
+                                     .map(|(public, _)| public)
+                                     .collect::<Vec<_>>();
+
+            let multi_box = user_secret.encrypt_for_many(b"not for you", &recipients);
+
+            match outsider.decrypt_multi_recipient_message(&multi_box, &user_public) {
+                Err(Error::CryptoError(msg)) => {
+                    assert_eq!(msg,
+                               format!("Message is not addressed to {}", outsider.named_revision()))
+                }
+                other => panic!("Expected CryptoError, got {:?}", other),
+            }
+        }
+
+        #[test]
+        fn wrong_sender_cannot_be_used_to_decrypt() {
+            let (_, user_secret) = generate_user_encryption_key_pair("ruby-rhod");
+            let (impostor, _) = generate_user_encryption_key_pair("zorg");
+            let services = service_pairs(1);
+
+            let multi_box = user_secret.encrypt_for_many(b"secret", &[&services[0].0]);
+            assert!(services[0].1
+                               .decrypt_multi_recipient_message(&multi_box, &impostor)
+                               .is_err());
+        }
+    }
 }
@@ -11,18 +11,25 @@
 //! encrypted rumor. It also allows operators to control who can send
 //! such rumors by controlling which user public keys are present on a
 //! Supervisor.
-use crate::{crypto::keys::{encryption::{primitives,
+use crate::{crypto::keys::{encryption::{message::RecipientSlot,
+                                        primitives,
+                                        MultiRecipientBox,
                                         SignedBox,
+                                        LEGACY_PUBLIC_KEY_SUFFIX,
+                                        PUBLIC_BOX_KEY_SUFFIX,
                                         PUBLIC_BOX_KEY_VERSION,
-                                        PUBLIC_KEY_SUFFIX,
                                         SECRET_BOX_KEY_SUFFIX,
-                                        SECRET_BOX_KEY_VERSION},
+                                        SECRET_BOX_KEY_VERSION,
+                                        X25519_SPKI_PREFIX},
                            Key,
+                           KeyRevision,
                            NamedRevision,
                            ServicePublicEncryptionKey},
             error::{Error,
                     Result},
             fs::Permissions};
+use rand::{CryptoRng,
+           RngCore};
 
 /// Given the name of a user, generate a new encryption key pair.
 ///
@@ -30,12 +37,33 @@ use crate::{crypto::keys::{encryption::{primitives,
 /// persist.
 pub fn generate_user_encryption_key_pair(user_name: &str)
                                          -> (UserPublicEncryptionKey, UserSecretEncryptionKey) {
-    let named_revision = NamedRevision::new(user_name.to_string());
-    let (pk, sk) = primitives::gen_keypair();
+    user_encryption_key_pair(NamedRevision::new(user_name.to_string()),
+                             primitives::gen_keypair())
+}
+
+/// Like `generate_user_encryption_key_pair`, but with key material
+/// drawn from `rng` and the given revision, so that tests can create
+/// keys reproducibly.
+pub fn generate_user_encryption_key_pair_with<R>(
+    user_name: &str,
+    rng: &mut R,
+    revision: KeyRevision)
+    -> (UserPublicEncryptionKey, UserSecretEncryptionKey)
+    where R: RngCore + CryptoRng
+{
+    user_encryption_key_pair(NamedRevision::from_parts(user_name.to_string(), revision),
+                             primitives::gen_keypair_from(rng))
+}
+
+fn user_encryption_key_pair(named_revision: NamedRevision,
+                            (pk, sk): (primitives::PublicKey, primitives::SecretKey))
+                            -> (UserPublicEncryptionKey, UserSecretEncryptionKey) {
     let public = UserPublicEncryptionKey { named_revision: named_revision.clone(),
-                                           key:            pk, };
+                                           key:            pk,
+                                           expiration:     None, };
     let secret = UserSecretEncryptionKey { named_revision,
-                                           key: sk };
+                                           key: sk,
+                                           expiration: None };
     (public, secret)
 }
 
@@ -44,8 +72,11 @@ pub fn generate_user_encryption_key_pair(user_name: &str)
 gen_key!(UserPublicEncryptionKey,
          key_material: primitives::PublicKey,
          file_format_version: PUBLIC_BOX_KEY_VERSION,
-         file_extension: PUBLIC_KEY_SUFFIX,
-         file_permissions: crate::fs::DEFAULT_PUBLIC_KEY_PERMISSIONS);
+         file_extension: PUBLIC_BOX_KEY_SUFFIX,
+         file_permissions: crate::fs::DEFAULT_PUBLIC_KEY_PERMISSIONS,
+         legacy_file_extension: LEGACY_PUBLIC_KEY_SUFFIX);
+
+public_key_encodings_for_key!(UserPublicEncryptionKey, X25519_SPKI_PREFIX);
 
 ////////////////////////////////////////////////////////////////////////
 
@@ -69,6 +100,63 @@ impl UserSecretEncryptionKey {
                        ciphertext,
                        nonce)
     }
+
+    /// Encrypt some data with a user's private key for decryption by
+    /// another user's private key.
+    pub fn encrypt_for_user(&self,
+                            data: &[u8],
+                            receiving_user: &UserPublicEncryptionKey)
+                            -> SignedBox {
+        let nonce = primitives::gen_nonce();
+        let ciphertext = primitives::seal(data, &nonce, receiving_user.key(), self.key());
+        SignedBox::new(self.named_revision.clone(),
+                       receiving_user.named_revision().clone(),
+                       ciphertext,
+                       nonce)
+    }
+
+    /// Decrypt a message sent to this user by another user.
+    pub fn decrypt_user_message(&self,
+                                signed_box: &SignedBox,
+                                sending_user: &UserPublicEncryptionKey)
+                                -> Result<Vec<u8>> {
+        primitives::open(signed_box.ciphertext(),
+                         signed_box.nonce(),
+                         sending_user.key(),
+                         self.key()).map_err(|_| {
+            Error::CryptoError("Secret key, public key, and nonce could not \
+                                                decrypt ciphertext"
+                                                                   .to_string())
+        })
+    }
+
+    /// Encrypt some data with a user's private key for decryption by
+    /// the private key of any one of several receiving services.
+    ///
+    /// The data is only encrypted once, no matter how many services
+    /// receive it.
+    pub fn encrypt_for_many(&self,
+                            data: &[u8],
+                            receiving_services: &[&ServicePublicEncryptionKey])
+                            -> MultiRecipientBox {
+        let message_key = primitives::secretbox::gen_key();
+        let nonce = primitives::secretbox::gen_nonce();
+        let ciphertext = primitives::secretbox::seal(data, &nonce, &message_key);
+        let recipients =
+            receiving_services.iter()
+                              .map(|service| {
+                                  let nonce = primitives::gen_nonce();
+                                  let sealed_key = primitives::seal(message_key.as_ref(),
+                                                                    &nonce,
+                                                                    service.key(),
+                                                                    self.key());
+                                  RecipientSlot { decryptor: service.named_revision().clone(),
+                                                  nonce,
+                                                  sealed_key }
+                              })
+                              .collect();
+        MultiRecipientBox::new(self.named_revision.clone(), nonce, ciphertext, recipients)
+    }
 }
 
 #[cfg(test)]
@@ -94,6 +182,24 @@ mod tests {
         assert_eq!(signed.decryptor(), service.named_revision());
     }
 
+    #[test]
+    fn user_to_user_round_trip() {
+        let (korben_public, korben) = generate_user_encryption_key_pair("korben-dallas");
+        let (leeloo_public, leeloo) = generate_user_encryption_key_pair("leeloo");
+
+        let message = "Multipass!".as_bytes();
+        let signed = korben.encrypt_for_user(message, &leeloo_public);
+        assert_eq!(signed.encryptor(), korben.named_revision());
+        assert_eq!(signed.decryptor(), leeloo.named_revision());
+
+        let decrypted = leeloo.decrypt_user_message(&signed, &korben_public)
+                              .unwrap();
+        assert_eq!(decrypted, message);
+
+        let (zorg_public, _) = generate_user_encryption_key_pair("zorg");
+        assert!(leeloo.decrypt_user_message(&signed, &zorg_public).is_err());
+    }
+
     // Choosing to put the "round trip" encryption test over in
     // `service_key.rs`, since it involves both user and service, and
     // service is the one that does the decrypting.
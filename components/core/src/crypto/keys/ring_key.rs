@@ -1,8 +1,11 @@
-use crate::{crypto::{keys::NamedRevision,
+use crate::{crypto::{keys::{KeyRevision,
+                            NamedRevision},
                      SECRET_SYM_KEY_VERSION},
             error::{Error,
                     Result},
             fs::Permissions};
+use rand::{CryptoRng,
+           RngCore};
 
 /// Private module to re-export the various sodiumoxide concepts we
 /// use, to keep them all consolidated and abstracted.
@@ -13,7 +16,8 @@ mod primitives {
                                              open,
                                              seal,
                                              Key,
-                                             Nonce};
+                                             Nonce,
+                                             KEYBYTES};
 }
 
 gen_key!(
@@ -34,7 +38,20 @@ impl RingKey {
         let named_revision = NamedRevision::new(name.to_string());
         let key = primitives::gen_key();
         RingKey { named_revision,
-                  key }
+                  key,
+                  expiration: None }
+    }
+
+    /// Like `new`, but with key material drawn from `rng` and the
+    /// given revision, so that tests can create keys reproducibly.
+    pub fn new_with<R>(name: &str, rng: &mut R, revision: KeyRevision) -> Self
+        where R: RngCore + CryptoRng
+    {
+        let mut bytes = [0; primitives::KEYBYTES];
+        rng.fill_bytes(&mut bytes);
+        RingKey { named_revision: NamedRevision::from_parts(name.to_string(), revision),
+                  key:            primitives::Key(bytes),
+                  expiration:     None, }
     }
 
     /// Encrypts a sequence of bytes.
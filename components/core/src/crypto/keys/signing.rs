@@ -1,37 +1,79 @@
-use crate::{crypto::{keys::NamedRevision,
+use crate::{crypto::{keys::{KeyRevision,
+                            NamedRevision},
                      Blake2bHash,
+                     DETACHED_SIG_FORMAT_VERSION,
                      PUBLIC_SIG_KEY_VERSION,
-                     SECRET_SIG_KEY_VERSION},
+                     SECRET_SIG_KEY_VERSION,
+                     SIG_HASH_TYPE},
             error::{Error,
                     Result},
             fs::Permissions,
             origin::Origin};
-use std::{io::Read,
-          path::Path};
+use rand::{CryptoRng,
+           RngCore};
+use std::{convert::TryFrom,
+          fmt,
+          fs::File,
+          io::{BufReader,
+               Read},
+          path::Path,
+          str::FromStr};
 
 /// Private module to re-export the various sodiumoxide concepts we
 /// use, to keep them all consolidated and abstracted.
 mod primitives {
-    pub use sodiumoxide::crypto::sign::{ed25519::{PublicKey,
-                                                  SecretKey},
+    pub use sodiumoxide::crypto::sign::{ed25519::{keypair_from_seed,
+                                                  PublicKey,
+                                                  SecretKey,
+                                                  Seed,
+                                                  SEEDBYTES},
                                         gen_keypair,
                                         sign,
-                                        verify};
+                                        sign_detached,
+                                        verify,
+                                        verify_detached,
+                                        Signature};
 }
 
+/// The DER encoding of a SubjectPublicKeyInfo for an Ed25519 public
+/// key, up to the key itself.
+const ED25519_SPKI_PREFIX: [u8; 12] =
+    [0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+
 /// Given the name of an origin, generate a new signing key pair.
 ///
 /// The resulting keys will need to be saved to a cache in order to
 /// persist.
 pub fn generate_signing_key_pair(origin: &Origin)
                                  -> (PublicOriginSigningKey, SecretOriginSigningKey) {
-    let named_revision = NamedRevision::new(origin.to_string());
-    let (pk, sk) = primitives::gen_keypair();
+    signing_key_pair(NamedRevision::new(origin.to_string()),
+                     primitives::gen_keypair())
+}
+
+/// Like `generate_signing_key_pair`, but with key material drawn from
+/// `rng` and the given revision, so that tests can create keys
+/// reproducibly.
+pub fn generate_signing_key_pair_with<R>(origin: &Origin,
+                                         rng: &mut R,
+                                         revision: KeyRevision)
+                                         -> (PublicOriginSigningKey, SecretOriginSigningKey)
+    where R: RngCore + CryptoRng
+{
+    let mut seed = [0; primitives::SEEDBYTES];
+    rng.fill_bytes(&mut seed);
+    signing_key_pair(NamedRevision::from_parts(origin.to_string(), revision),
+                     primitives::keypair_from_seed(&primitives::Seed(seed)))
+}
 
+fn signing_key_pair(named_revision: NamedRevision,
+                    (pk, sk): (primitives::PublicKey, primitives::SecretKey))
+                    -> (PublicOriginSigningKey, SecretOriginSigningKey) {
     let public = PublicOriginSigningKey { named_revision: named_revision.clone(),
-                                          key:            pk, };
+                                          key:            pk,
+                                          expiration:     None, };
     let secret = SecretOriginSigningKey { named_revision,
-                                          key: sk };
+                                          key: sk,
+                                          expiration: None };
     (public, secret)
 }
 
@@ -43,8 +85,11 @@ gen_key!(
     PublicOriginSigningKey,
          key_material: primitives::PublicKey,
          file_format_version: PUBLIC_SIG_KEY_VERSION,
-         file_extension: "pub",
-         file_permissions: crate::fs::DEFAULT_PUBLIC_KEY_PERMISSIONS);
+         file_extension: "sig.pub",
+         file_permissions: crate::fs::DEFAULT_PUBLIC_KEY_PERMISSIONS,
+         legacy_file_extension: "pub");
+
+public_key_encodings_for_key!(PublicOriginSigningKey, ED25519_SPKI_PREFIX);
 
 impl PublicOriginSigningKey {
     /// Accept a signed, hex-encoded Blake2b hash, along with the
@@ -53,13 +98,30 @@ impl PublicOriginSigningKey {
     ///
     /// Returns the verified, Blake2b hash of the contents.
     pub fn verify(&self, signed_hash: &[u8], content: &mut dyn Read) -> Result<Blake2bHash> {
+        self.verify_reader(signed_hash, content)
+    }
+
+    /// Same as `verify`, but for the contents of the given file.
+    pub fn verify_file<P>(&self, signed_hash: &[u8], path: P) -> Result<Blake2bHash>
+        where P: AsRef<Path>
+    {
+        let file = File::open(path)?;
+        self.verify_reader(signed_hash, BufReader::new(file))
+    }
+
+    /// Same as `verify`, but takes ownership of the reader. The
+    /// content is hashed a chunk at a time as it's read, so it never
+    /// has to be held in memory all at once.
+    pub fn verify_reader<R>(&self, signed_hash: &[u8], mut content: R) -> Result<Blake2bHash>
+        where R: Read
+    {
         let expected_blake2b_hash = primitives::verify(signed_hash, &self.key)
             .map_err(|_| Error::CryptoError("Verification failed".to_string()))
             .map(String::from_utf8)? // get the hex-encoded hash
             .map_err(|_| Error::CryptoError("Error parsing artifact hash".to_string()))?
             .parse()?; // convert to Blake2bHash
 
-        let computed_blake2b_hash = Blake2bHash::from_reader(content)?;
+        let computed_blake2b_hash = Blake2bHash::from_reader(&mut content)?;
 
         if computed_blake2b_hash == expected_blake2b_hash {
             Ok(expected_blake2b_hash)
@@ -70,6 +132,45 @@ impl PublicOriginSigningKey {
             Err(Error::CryptoError(msg))
         }
     }
+
+    /// Verify a detached signature of the contents of the given file.
+    ///
+    /// The signature must name this key as its signer; one made by
+    /// any other key (even another revision of it) is rejected
+    /// outright, rather than just failing to verify.
+    ///
+    /// Returns the verified, Blake2b hash of the contents.
+    pub fn verify_file_detached<P>(&self,
+                                   path: P,
+                                   signature: &DetachedSignature)
+                                   -> Result<Blake2bHash>
+        where P: AsRef<Path>
+    {
+        if signature.signer != self.named_revision {
+            return Err(Error::CryptoError(format!("Signature was made by {}, \
+                                                   not {}",
+                                                  signature.signer,
+                                                  self.named_revision)));
+        }
+        let ed25519_signature = primitives::Signature::try_from(signature.signature.as_slice())
+            .map_err(|_| Error::CryptoError("Invalid signature".to_string()))?;
+        if !primitives::verify_detached(&ed25519_signature,
+                                        signature.hash.to_string().as_bytes(),
+                                        &self.key)
+        {
+            return Err(Error::CryptoError("Verification failed".to_string()));
+        }
+
+        let computed_blake2b_hash = Blake2bHash::from_file(path)?;
+        if computed_blake2b_hash == signature.hash {
+            Ok(computed_blake2b_hash)
+        } else {
+            let msg = format!("Signed file is invalid, hashes don't match (expected: {}, \
+                               computed: {})",
+                              signature.hash, computed_blake2b_hash);
+            Err(Error::CryptoError(msg))
+        }
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////
@@ -91,15 +192,39 @@ impl SecretOriginSigningKey {
     /// intended.
     pub fn sign<P>(&self, path: P) -> Result<Vec<u8>>
         where P: AsRef<Path>
+    {
+        let file = File::open(path)?;
+        self.sign_reader(BufReader::new(file))
+    }
+
+    /// Same as `sign`, but for the contents of a reader. The content
+    /// is hashed a chunk at a time as it's read, so it never has to be
+    /// held in memory all at once.
+    pub fn sign_reader<R>(&self, mut content: R) -> Result<Vec<u8>>
+        where R: Read
     {
         // Note that we're signing the *lower-case, hex-encoded
         // string* of the Blake2b hash, NOT the hash itself! This will
         // have implications if we ever want to change in the future
         // :(
-        let hex_encoded_hash = Blake2bHash::from_file(&path)?;
+        let hex_encoded_hash = Blake2bHash::from_reader(&mut content)?;
         Ok(self.sign_inner(hex_encoded_hash.to_string().as_bytes()))
     }
 
+    /// Sign the contents of the given file, producing a signature that
+    /// is kept apart from it. Any file can be signed this way, not
+    /// just Habitat artifacts.
+    pub fn sign_file_detached<P>(&self, path: P) -> Result<DetachedSignature>
+        where P: AsRef<Path>
+    {
+        // As with `sign`, it's the hex-encoded hash that's signed.
+        let hash = Blake2bHash::from_file(path)?;
+        let signature = primitives::sign_detached(hash.to_string().as_bytes(), &self.key);
+        Ok(DetachedSignature { signer: self.named_revision.clone(),
+                               hash,
+                               signature: signature.as_ref().to_vec() })
+    }
+
     /// Does the actual heavy lifting of signing a string of bytes.
     ///
     /// Mainly separate to facilitate testing.
@@ -110,13 +235,93 @@ impl SecretOriginSigningKey {
     }
 }
 
+////////////////////////////////////////////////////////////////////////
+
+/// A signature of the contents of a file, made with a
+/// `SecretOriginSigningKey` and kept apart from the file, rather than
+/// in a header in front of it as with `.hart` files.
+///
+/// Its string form holds the format version, the revision of the key
+/// that made it, the hash type, and the hex-encoded hash of the
+/// contents in plaintext, followed by the base64-encoded signature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DetachedSignature {
+    /// The revision of the key that made this signature, and whose
+    /// public key must be used to verify it.
+    signer:    NamedRevision,
+    /// The hash of the signed contents.
+    hash:      Blake2bHash,
+    /// The signature of the hex-encoded `hash`.
+    signature: Vec<u8>,
+}
+
+impl DetachedSignature {
+    pub fn signer(&self) -> &NamedRevision { &self.signer }
+
+    pub fn hash(&self) -> &Blake2bHash { &self.hash }
+}
+
+impl fmt::Display for DetachedSignature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{}\n{}\n{}\n{}\n{}",
+               DETACHED_SIG_FORMAT_VERSION,
+               self.signer,
+               SIG_HASH_TYPE,
+               self.hash,
+               crate::base64::encode(&self.signature))
+    }
+}
+
+impl FromStr for DetachedSignature {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut lines = s.lines();
+        let mut next_line = |what: &str| {
+            lines.next().map(str::trim).ok_or_else(|| {
+                                           Error::CryptoError(format!("Corrupt signature, can't \
+                                                                       read {}",
+                                                                      what))
+                                       })
+        };
+
+        let version = next_line("format version")?;
+        if version != DETACHED_SIG_FORMAT_VERSION {
+            return Err(Error::CryptoError(format!("Unsupported format version: \
+                                                   {}",
+                                                  version)));
+        }
+        let signer = next_line("signer")?.parse()?;
+        let hash_type = next_line("hash type")?;
+        if hash_type != SIG_HASH_TYPE {
+            return Err(Error::CryptoError(format!("Unsupported signature type: \
+                                                   {}",
+                                                  hash_type)));
+        }
+        let hash = next_line("hash")?.parse()?;
+        let signature =
+            crate::base64::decode(next_line("signature")?).map_err(|e| {
+                Error::CryptoError(format!("Can't decode signature: {}", e))
+            })?;
+
+        Ok(DetachedSignature { signer,
+                               hash,
+                               signature })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::crypto::test_support::{fixture,
-                                      fixture_key};
-    use std::{fs::File,
-              io::BufReader};
+    use crate::crypto::{keys::{generate_origin_encryption_key_pair,
+                               Key,
+                               KeyFile,
+                               PublicKeyFormat},
+                        test_support::{fixture,
+                                       fixture_key}};
+    use std::{fs,
+              io};
 
     /// The hash of the contents of the `tests/fixtures/signme.dat`
     /// file, signed by
@@ -186,6 +391,55 @@ mod tests {
         assert_eq!(verified_hash, expected_hash);
     }
 
+    #[test]
+    fn streaming_signatures_match_the_fixture() {
+        let key: SecretOriginSigningKey =
+            fixture_key("keys/origin-key-valid-20160509190508.sig.key");
+        let file = File::open(fixture("signme.dat")).unwrap();
+
+        assert_eq!(key.sign_reader(file).unwrap(),
+                   SIGNED_SIGNME_DAT_BLAKE2B_HASH.to_vec());
+    }
+
+    #[test]
+    fn streaming_and_file_signing_are_interchangeable() {
+        let sk: SecretOriginSigningKey =
+            fixture_key("keys/origin-key-valid-20160509190508.sig.key");
+        let pk: PublicOriginSigningKey = fixture_key("keys/origin-key-valid-20160509190508.pub");
+        let file_to_sign = fixture("signme.dat");
+        let expected_hash = SIGNME_DAT_BLAKE2B_HASH.parse::<Blake2bHash>().unwrap();
+
+        // Signed by streaming, verified the old way
+        let streamed = sk.sign_reader(File::open(&file_to_sign).unwrap()).unwrap();
+        let mut reader = BufReader::new(File::open(&file_to_sign).unwrap());
+        assert_eq!(pk.verify(&streamed, &mut reader).unwrap(), expected_hash);
+
+        // Signed the old way, verified by streaming
+        let signed = sk.sign(&file_to_sign).unwrap();
+        assert_eq!(signed, streamed);
+        assert_eq!(pk.verify_reader(&signed, File::open(&file_to_sign).unwrap())
+                     .unwrap(),
+                   expected_hash);
+        assert_eq!(pk.verify_file(&signed, &file_to_sign).unwrap(),
+                   expected_hash);
+    }
+
+    #[test]
+    fn large_content_is_signed_and_verified_by_streaming() {
+        let origin = "test-origin".parse().unwrap();
+        let (public, secret) = generate_signing_key_pair(&origin);
+        // Far more content than is ever read at once, but never
+        // actually held in memory.
+        let content = || io::repeat(0x42).take(16 * 1024 * 1024 + 7);
+
+        let signed = secret.sign_reader(content()).unwrap();
+        let verified = public.verify_reader(&signed, content()).unwrap();
+        assert_eq!(verified, Blake2bHash::from_reader(&mut content()).unwrap());
+
+        let tampered = io::repeat(0x42).take(16 * 1024 * 1024 + 8);
+        assert!(public.verify_reader(&signed, tampered).is_err());
+    }
+
     /// This is mainly to encapsulate knowledge about how Habitat's
     /// signing behaves. We historically have signed the lowercase
     /// hex-encoded Blake2b hash digest of a file, rather than
@@ -217,4 +471,161 @@ mod tests {
         let lc_signed_2 = secret.sign_inner(lower_case.as_bytes());
         assert_eq!(lc_signed, lc_signed_2);
     }
+
+    #[test]
+    fn public_keys_round_trip_through_raw_bytes() {
+        let key: PublicOriginSigningKey = fixture_key("keys/origin-key-valid-20160509190508.pub");
+        let raw = key.to_raw_bytes();
+        assert_eq!(raw.len(), 32);
+
+        let imported =
+            PublicOriginSigningKey::from_raw_bytes(key.named_revision().name(),
+                                                   key.named_revision().revision().clone(),
+                                                   &raw).unwrap();
+        assert_eq!(imported.to_key_string(), key.to_key_string());
+    }
+
+    #[test]
+    fn public_keys_round_trip_through_base64() {
+        let key: PublicOriginSigningKey = fixture_key("keys/origin-key-valid-20160509190508.pub");
+        let encoded = key.to_base64();
+        assert!(key.to_key_string().ends_with(&encoded));
+
+        let imported = PublicOriginSigningKey::from_base64(key.named_revision().name(),
+                                                           key.named_revision().revision().clone(),
+                                                           &encoded).unwrap();
+        assert_eq!(imported.to_key_string(), key.to_key_string());
+    }
+
+    #[test]
+    fn public_keys_round_trip_through_pem() {
+        let key: PublicOriginSigningKey = fixture_key("keys/origin-key-valid-20160509190508.pub");
+        let encoded = key.to_pem();
+        let pem = pem::parse(&encoded).unwrap();
+        assert_eq!(pem.tag(), "PUBLIC KEY");
+        assert_eq!(pem.contents()[..12], ED25519_SPKI_PREFIX);
+        assert_eq!(pem.contents()[12..], key.to_raw_bytes()[..]);
+
+        let imported = PublicOriginSigningKey::from_pem(key.named_revision().name(),
+                                                        key.named_revision().revision().clone(),
+                                                        &encoded).unwrap();
+        assert_eq!(imported.to_key_string(), key.to_key_string());
+    }
+
+    #[test]
+    fn public_keys_are_exported_in_each_format() {
+        let key: PublicOriginSigningKey = fixture_key("keys/origin-key-valid-20160509190508.pub");
+        assert_eq!(key.export(PublicKeyFormat::Raw), key.to_raw_bytes());
+        assert_eq!(key.export(PublicKeyFormat::Base64),
+                   key.to_base64().into_bytes());
+        assert_eq!(key.export(PublicKeyFormat::Pem), key.to_pem().into_bytes());
+    }
+
+    #[test]
+    fn importing_the_wrong_kind_of_key_material_fails() {
+        let revision = "20160509190508".parse::<KeyRevision>().unwrap();
+        assert!(PublicOriginSigningKey::from_raw_bytes("origin-key-valid",
+                                                       revision.clone(),
+                                                       &[0; 31]).is_err());
+
+        let origin = "origin-key-valid".parse().unwrap();
+        let (encryption, _) = generate_origin_encryption_key_pair(&origin);
+        assert!(PublicOriginSigningKey::from_pem("origin-key-valid",
+                                                 revision,
+                                                 &encryption.to_pem()).is_err());
+    }
+
+    mod detached {
+        use super::*;
+        use tempfile::TempDir;
+
+        fn signed_file() -> (TempDir, std::path::PathBuf) {
+            let dir = TempDir::new().unwrap();
+            let path = dir.path().join("manifest.json");
+            fs::copy(fixture("signme.dat"), &path).unwrap();
+            (dir, path)
+        }
+
+        #[test]
+        fn sign_and_verify_roundtrip() {
+            let sk: SecretOriginSigningKey =
+                fixture_key("keys/origin-key-valid-20160509190508.sig.key");
+            let pk: PublicOriginSigningKey =
+                fixture_key("keys/origin-key-valid-20160509190508.pub");
+            let (_dir, path) = signed_file();
+            let expected_hash = SIGNME_DAT_BLAKE2B_HASH.parse::<Blake2bHash>().unwrap();
+
+            let signature = sk.sign_file_detached(&path).unwrap();
+            assert_eq!(signature.signer(), sk.named_revision());
+            assert_eq!(signature.hash(), &expected_hash);
+            assert_eq!(pk.verify_file_detached(&path, &signature).unwrap(),
+                       expected_hash);
+        }
+
+        #[test]
+        fn signatures_round_trip_through_strings() {
+            let sk: SecretOriginSigningKey =
+                fixture_key("keys/origin-key-valid-20160509190508.sig.key");
+            let (_dir, path) = signed_file();
+
+            let signature = sk.sign_file_detached(&path).unwrap();
+            let armored = signature.to_string();
+            let header = "DETACHED-SIG-1\norigin-key-valid-20160509190508\nBLAKE2b\n";
+            assert!(armored.starts_with(header));
+            assert_eq!(armored.parse::<DetachedSignature>().unwrap(), signature);
+
+            // Missing the hash and signature
+            assert!(header.parse::<DetachedSignature>().is_err());
+            assert!(armored.replace("DETACHED-SIG-1", "DETACHED-SIG-2")
+                           .parse::<DetachedSignature>()
+                           .is_err());
+        }
+
+        #[test]
+        fn tampered_files_fail_verification() {
+            let sk: SecretOriginSigningKey =
+                fixture_key("keys/origin-key-valid-20160509190508.sig.key");
+            let pk: PublicOriginSigningKey =
+                fixture_key("keys/origin-key-valid-20160509190508.pub");
+            let (_dir, path) = signed_file();
+            let signature = sk.sign_file_detached(&path).unwrap();
+
+            let mut contents = fs::read(&path).unwrap();
+            contents[0] ^= 1;
+            fs::write(&path, contents).unwrap();
+
+            let err = pk.verify_file_detached(&path, &signature).unwrap_err();
+            assert!(err.to_string().contains("hashes don't match"));
+        }
+
+        #[test]
+        fn tampered_signatures_fail_verification() {
+            let sk: SecretOriginSigningKey =
+                fixture_key("keys/origin-key-valid-20160509190508.sig.key");
+            let pk: PublicOriginSigningKey =
+                fixture_key("keys/origin-key-valid-20160509190508.pub");
+            let (_dir, path) = signed_file();
+            let mut signature = sk.sign_file_detached(&path).unwrap();
+            signature.signature[0] ^= 1;
+
+            let err = pk.verify_file_detached(&path, &signature).unwrap_err();
+            assert!(err.to_string().contains("Verification failed"));
+        }
+
+        #[test]
+        fn signatures_by_other_keys_are_rejected() {
+            let origin = "origin-key-valid".parse().unwrap();
+            let (_, other) = generate_signing_key_pair(&origin);
+            let pk: PublicOriginSigningKey =
+                fixture_key("keys/origin-key-valid-20160509190508.pub");
+            let (_dir, path) = signed_file();
+            let signature = other.sign_file_detached(&path).unwrap();
+
+            let err = pk.verify_file_detached(&path, &signature).unwrap_err();
+            assert!(err.to_string()
+                       .contains(&format!("Signature was made by {}, not \
+                                           origin-key-valid-20160509190508",
+                                          other.named_revision())));
+        }
+    }
 }
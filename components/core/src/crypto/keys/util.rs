@@ -9,6 +9,7 @@ macro_rules! gen_key {
         $version:expr,file_extension:
         $extension:expr,file_permissions:
         $permissions:expr
+        $(,legacy_file_extension: $legacy_extension:expr)?
     ) => {
 
 
@@ -17,6 +18,15 @@ macro_rules! gen_key {
         pub struct $t {
             named_revision: NamedRevision,
             key:            $key,
+            expiration:     Option<chrono::DateTime<chrono::Utc>>,
+        }
+
+        impl $t {
+            /// This key, marked as expiring at `expiration`.
+            pub fn with_expiration(self, expiration: chrono::DateTime<chrono::Utc>) -> Self {
+                $t { expiration: Some(expiration),
+                     ..self }
+            }
         }
 
         impl crate::crypto::keys::Key for $t {
@@ -35,6 +45,12 @@ macro_rules! gen_key {
             fn version() -> &'static str { $version }
 
             fn extension() -> &'static str { $extension }
+
+            $(fn legacy_extension() -> Option<&'static str> { Some($legacy_extension) })?
+
+            fn key_type() -> &'static str { stringify!($t) }
+
+            fn expiration(&self) -> Option<chrono::DateTime<chrono::Utc>> { self.expiration }
         }
 
         from_str_impl_for_key!($t);
@@ -84,8 +100,9 @@ macro_rules! from_str_impl_for_key {
                                                    named_revision))
                     })?;
 
+                let expiration = crate::crypto::keys::parse_expiration(lines)?;
 
-                Ok(Self {named_revision, key})
+                Ok(Self {named_revision, key, expiration})
             }
         }
     };
@@ -147,12 +164,99 @@ macro_rules! try_from_bytes_for_key {
     };
 }
 
+/// Helper macro to encode the bare key material of a public key for
+/// tools other than Habitat, and to read it back. `$spki_prefix` is
+/// the DER encoding of a SubjectPublicKeyInfo for the key's
+/// algorithm, up to the key material itself; it is what a PEM
+/// encoded "PUBLIC KEY" holds.
+macro_rules! public_key_encodings_for_key {
+    ($t:ty, $spki_prefix:expr) => {
+        impl $t {
+            /// The key, encoded as `format`. The key's name, revision,
+            /// and expiration are left out.
+            pub fn export(&self, format: crate::crypto::keys::PublicKeyFormat) -> Vec<u8> {
+                match format {
+                    crate::crypto::keys::PublicKeyFormat::Raw => self.to_raw_bytes(),
+                    crate::crypto::keys::PublicKeyFormat::Base64 => self.to_base64().into_bytes(),
+                    crate::crypto::keys::PublicKeyFormat::Pem => self.to_pem().into_bytes(),
+                }
+            }
+
+            /// The bare key material.
+            pub fn to_raw_bytes(&self) -> Vec<u8> { self.key.as_ref().to_vec() }
+
+            /// The bare key material, in standard base64.
+            pub fn to_base64(&self) -> String { crate::base64::encode(&self.key) }
+
+            /// The key as a PEM encoded SubjectPublicKeyInfo.
+            pub fn to_pem(&self) -> String {
+                let mut der = $spki_prefix.to_vec();
+                der.extend_from_slice(self.key.as_ref());
+                pem::encode(&pem::Pem::new("PUBLIC KEY", der))
+            }
+
+            /// Build the key named `name` with the given revision out
+            /// of bare key material, as from `to_raw_bytes`.
+            pub fn from_raw_bytes(name: &str,
+                                  revision: crate::crypto::keys::KeyRevision,
+                                  bytes: &[u8])
+                                  -> Result<Self> {
+                let named_revision =
+                    crate::crypto::keys::NamedRevision::from_parts(name.to_string(), revision);
+                let key = <Self as crate::crypto::keys::Key>::Crypto::from_slice(bytes)
+                            .ok_or_else(|| {
+                                Error::CryptoError(format!("Could not parse bytes as key for {}",
+                                                           named_revision))
+                            })?;
+                Ok(Self { named_revision,
+                          key,
+                          expiration: None })
+            }
+
+            /// Like `from_raw_bytes`, but for key material in standard
+            /// base64, as from `to_base64`.
+            pub fn from_base64(name: &str,
+                               revision: crate::crypto::keys::KeyRevision,
+                               encoded: &str)
+                               -> Result<Self> {
+                let bytes = crate::base64::decode(encoded.trim()).map_err(|_| {
+                                Error::CryptoError("Invalid base64 key material".to_string())
+                            })?;
+                Self::from_raw_bytes(name, revision, &bytes)
+            }
+
+            /// Like `from_raw_bytes`, but for a PEM encoded
+            /// SubjectPublicKeyInfo, as from `to_pem`.
+            pub fn from_pem(name: &str,
+                            revision: crate::crypto::keys::KeyRevision,
+                            encoded: &str)
+                            -> Result<Self> {
+                let pem = pem::parse(encoded)?;
+                if pem.tag() != "PUBLIC KEY" {
+                    return Err(Error::CryptoError(format!("Expected a PEM encoded \
+                                                           PUBLIC KEY, found {}",
+                                                          pem.tag())));
+                }
+                match pem.contents().strip_prefix(&$spki_prefix[..]) {
+                    Some(bytes) => Self::from_raw_bytes(name, revision, bytes),
+                    None => {
+                        Err(Error::CryptoError(format!("PEM encoded key is not a {}",
+                                                       stringify!($t))))
+                    }
+                }
+            }
+        }
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use crate::crypto::keys::{generate_origin_encryption_key_pair,
                               generate_service_encryption_key_pair,
                               generate_signing_key_pair,
+                              generate_signing_key_pair_with,
                               generate_user_encryption_key_pair,
+                              generate_user_encryption_key_pair_with,
                               Key,
                               NamedRevision,
                               OriginPublicEncryptionKey,
@@ -220,6 +324,63 @@ mod tests {
             assert_parse_round_trip!(SecretOriginSigningKey, secret);
         }
 
+        #[test]
+        fn keys_generated_from_a_seeded_rng_are_reproducible() {
+            use crate::crypto::test_support::{revision,
+                                              seeded_rng};
+
+            let origin = "my-origin".parse().unwrap();
+            let (public, secret) =
+                generate_signing_key_pair_with(&origin, &mut seeded_rng(), revision(1));
+            assert_eq!(generate_signing_key_pair_with(&origin, &mut seeded_rng(), revision(1)),
+                       (public.clone(), secret.clone()));
+            assert_parse_round_trip!(PublicOriginSigningKey, public);
+            assert_parse_round_trip!(SecretOriginSigningKey, secret);
+
+            let (public, secret) =
+                generate_user_encryption_key_pair_with("my-user", &mut seeded_rng(), revision(1));
+            assert_eq!(generate_user_encryption_key_pair_with("my-user",
+                                                              &mut seeded_rng(),
+                                                              revision(1)),
+                       (public.clone(), secret.clone()));
+            assert_parse_round_trip!(UserPublicEncryptionKey, public);
+            assert_parse_round_trip!(UserSecretEncryptionKey, secret);
+
+            let key = RingKey::new_with("beyonce", &mut seeded_rng(), revision(1));
+            assert_eq!(RingKey::new_with("beyonce", &mut seeded_rng(), revision(1)),
+                       key.clone());
+            assert_parse_round_trip!(RingKey, key);
+        }
+
+        #[test]
+        fn expiration_round_trips() {
+            let origin = "my-origin".parse().unwrap();
+            let (_public, secret) = generate_signing_key_pair(&origin);
+            let expiration = "2030-01-01T00:00:00Z".parse::<chrono::DateTime<chrono::Utc>>()
+                                                   .unwrap();
+            let secret = secret.with_expiration(expiration);
+
+            let key_string = secret.to_key_string();
+            assert!(key_string.ends_with("\nexpires: 2030-01-01T00:00:00Z"));
+            let parsed: SecretOriginSigningKey = key_string.parse().unwrap();
+            assert_eq!(parsed.expiration(), Some(expiration));
+            assert_eq!(parsed, secret);
+        }
+
+        #[test]
+        fn keys_without_an_expiration_are_written_as_before() {
+            let key = RingKey::new("beyonce");
+            assert_eq!(key.expiration(), None);
+            assert_eq!(key.to_key_string().lines().count(), 4);
+        }
+
+        #[test]
+        fn malformed_expiration_is_an_error() {
+            let key = RingKey::new("beyonce");
+            let key_string = format!("{}\nexpires: next tuesday", key.to_key_string());
+            assert!(key_string.parse::<RingKey>().is_err());
+        }
+
         /// Ensure that we can take various files as keys and
         /// correctly parse them into their appropriate types
         mod parse {
@@ -410,22 +571,22 @@ mod tests {
                        PathBuf::from("foo-20160504220722.sym.key"));
 
             assert_eq!(PublicOriginSigningKey::filename(&source),
-                       PathBuf::from("foo-20160504220722.pub"));
+                       PathBuf::from("foo-20160504220722.sig.pub"));
             assert_eq!(SecretOriginSigningKey::filename(&source),
                        PathBuf::from("foo-20160504220722.sig.key"));
 
             assert_eq!(UserPublicEncryptionKey::filename(&source),
-                       PathBuf::from("foo-20160504220722.pub"));
+                       PathBuf::from("foo-20160504220722.box.pub"));
             assert_eq!(UserSecretEncryptionKey::filename(&source),
                        PathBuf::from("foo-20160504220722.box.key"));
 
             assert_eq!(OriginPublicEncryptionKey::filename(&source),
-                       PathBuf::from("foo-20160504220722.pub"));
+                       PathBuf::from("foo-20160504220722.box.pub"));
             assert_eq!(OriginSecretEncryptionKey::filename(&source),
                        PathBuf::from("foo-20160504220722.box.key"));
 
             assert_eq!(ServicePublicEncryptionKey::filename(&service_source),
-                       PathBuf::from("redis.default@chef-20160504220722.pub"));
+                       PathBuf::from("redis.default@chef-20160504220722.service.pub"));
             assert_eq!(ServiceSecretEncryptionKey::filename(&service_source),
                        PathBuf::from("redis.default@chef-20160504220722.box.key"));
 
@@ -433,7 +594,7 @@ mod tests {
             // that does not really belong to a service key from being
             // pathed as though it were.
             assert_eq!(ServicePublicEncryptionKey::filename(&source),
-                       PathBuf::from("foo-20160504220722.pub"));
+                       PathBuf::from("foo-20160504220722.service.pub"));
             assert_eq!(ServiceSecretEncryptionKey::filename(&source),
                        PathBuf::from("foo-20160504220722.box.key"));
         }
@@ -451,7 +612,7 @@ mod tests {
             #[test]
             fn user_keys() {
                 let (public, secret) = generate_user_encryption_key_pair("my-user");
-                assert_eq!(PathBuf::from(&format!("{}.pub", public.named_revision())),
+                assert_eq!(PathBuf::from(&format!("{}.box.pub", public.named_revision())),
                            public.own_filename());
                 assert_eq!(PathBuf::from(&format!("{}.box.key", secret.named_revision())),
                            secret.own_filename());
@@ -461,7 +622,7 @@ mod tests {
             fn origin_keys() {
                 let origin = "my-origin".parse().unwrap();
                 let (public, secret) = generate_origin_encryption_key_pair(&origin);
-                assert_eq!(PathBuf::from(&format!("{}.pub", public.named_revision())),
+                assert_eq!(PathBuf::from(&format!("{}.box.pub", public.named_revision())),
                            public.own_filename());
                 assert_eq!(PathBuf::from(&format!("{}.box.key", secret.named_revision())),
                            secret.own_filename());
@@ -471,7 +632,7 @@ mod tests {
             fn service_keys() {
                 let (public, secret) =
                     generate_service_encryption_key_pair("my-org", "foo.default");
-                assert_eq!(PathBuf::from(&format!("{}.pub", public.named_revision())),
+                assert_eq!(PathBuf::from(&format!("{}.service.pub", public.named_revision())),
                            public.own_filename());
                 assert_eq!(PathBuf::from(&format!("{}.box.key", secret.named_revision())),
                            secret.own_filename());
@@ -481,7 +642,7 @@ mod tests {
             fn signing_keys() {
                 let origin = "my-origin".parse().unwrap();
                 let (public, secret) = generate_signing_key_pair(&origin);
-                assert_eq!(PathBuf::from(&format!("{}.pub", public.named_revision())),
+                assert_eq!(PathBuf::from(&format!("{}.sig.pub", public.named_revision())),
                            public.own_filename());
                 assert_eq!(PathBuf::from(&format!("{}.sig.key", secret.named_revision())),
                            secret.own_filename());
@@ -510,19 +671,33 @@ mod tests {
 
             extension!(RingKey, "sym.key");
 
-            extension!(PublicOriginSigningKey, "pub");
+            extension!(PublicOriginSigningKey, "sig.pub");
             extension!(SecretOriginSigningKey, "sig.key");
 
-            extension!(OriginPublicEncryptionKey, "pub");
+            extension!(OriginPublicEncryptionKey, "box.pub");
             extension!(OriginSecretEncryptionKey, "box.key");
 
-            extension!(ServicePublicEncryptionKey, "pub");
+            extension!(ServicePublicEncryptionKey, "service.pub");
             extension!(ServiceSecretEncryptionKey, "box.key");
 
-            extension!(UserPublicEncryptionKey, "pub");
+            extension!(UserPublicEncryptionKey, "box.pub");
             extension!(UserSecretEncryptionKey, "box.key");
         }
 
+        #[test]
+        fn legacy_extension() {
+            assert_eq!(PublicOriginSigningKey::legacy_extension(), Some("pub"));
+            assert_eq!(OriginPublicEncryptionKey::legacy_extension(), Some("pub"));
+            assert_eq!(ServicePublicEncryptionKey::legacy_extension(), Some("pub"));
+            assert_eq!(UserPublicEncryptionKey::legacy_extension(), Some("pub"));
+
+            assert_eq!(RingKey::legacy_extension(), None);
+            assert_eq!(SecretOriginSigningKey::legacy_extension(), None);
+            assert_eq!(OriginSecretEncryptionKey::legacy_extension(), None);
+            assert_eq!(ServiceSecretEncryptionKey::legacy_extension(), None);
+            assert_eq!(UserSecretEncryptionKey::legacy_extension(), None);
+        }
+
         mod permissions {
             use super::*;
 
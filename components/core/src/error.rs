@@ -1,18 +1,22 @@
-use crate::{package::{self,
+use crate::{crypto::Blake2bHash,
+            package::{self,
                       Identifiable},
             tls::{ctl_gateway::Error as CtlGatewayTls,
                   rustls_wrapper::Error as RustlsReaderError}};
 use pem;
-use std::{env,
+use std::{convert::Infallible,
+          env,
           error,
           ffi,
           fmt,
           io,
           num,
           num::ParseIntError,
+          path::PathBuf,
           result,
           str,
-          string};
+          string,
+          time::Duration};
 
 pub type Result<T> = result::Result<T, Error>;
 
@@ -74,14 +78,42 @@ pub enum Error {
     CtlGatewayTls(CtlGatewayTls),
     /// Occurs when unable to locate the docker cli on the path
     DockerCommandNotFound(&'static str),
+    /// Occurs when the contents of a file don't have the hash they
+    /// were expected to.
+    FileHashMismatch {
+        path:     PathBuf,
+        expected: Blake2bHash,
+        actual:   Blake2bHash,
+    },
     /// Occurs when a file that should exist does not or could not be read.
     FileNotFound(String),
     /// Occurs when a fully-qualified package identifier is required,
     /// but a non-qualified identifier (e.g. "foo/bar" or
     /// "foo/bar/1.0.0") was given instead.
     FullyQualifiedPackageIdentRequired(String),
+    /// Occurs when a key cache's directory can be read or written by
+    /// users other than its owner, and that can't be fixed.
+    InsecureKeyCacheDirectory {
+        path:   PathBuf,
+        mode:   u32,
+        source: Box<Error>,
+    },
+    /// Occurs when a secret key file in the key cache can be read by
+    /// users other than its owner.
+    InsecureKeyPermissions {
+        path: PathBuf,
+        mode: u32,
+    },
     /// Occurs when a service binding cannot be successfully parsed.
     InvalidBinding(String),
+    /// Occurs when Builder URL overrides for origins can't be parsed.
+    InvalidBldrUrlOverrides(String),
+    /// Occurs when a key would be given a name that can't safely name
+    /// a file in a key cache.
+    InvalidKeyName {
+        name:   String,
+        reason: String,
+    },
     /// Occurs when an origin is in an invalid format
     InvalidOrigin(String),
     /// Occurs when a package identifier string cannot be successfully parsed.
@@ -96,16 +128,65 @@ pub enum Error {
     InvalidPathString(ffi::OsString),
     /// Occurs when a service group string cannot be successfully parsed.
     InvalidServiceGroup(String),
+    /// Occurs when a `service.group@org` identifier cannot be
+    /// parsed; `reason` says which part of it is malformed.
+    InvalidServiceGroupIdent {
+        ident:  String,
+        reason: String,
+    },
     /// Occurs when a Url is in an invalid format.
     InvalidUrl(String),
     /// Occurs when making lower level IO calls.
     IO(io::Error),
     /// Errors when joining paths :)
     JoinPathsError(env::JoinPathsError),
+    /// Occurs when another process holds the lock on the key cache for
+    /// longer than we're willing to wait.
+    KeyCacheLocked {
+        path:    PathBuf,
+        timeout: Duration,
+    },
+    /// Occurs when every key of the given type and name in the key
+    /// cache has expired.
+    KeyExpired {
+        name:     String,
+        key_type: &'static str,
+    },
+    /// Occurs when the key cache holds no key of the given type and
+    /// name (or name and revision).
+    KeyNotFound {
+        name:     String,
+        key_type: &'static str,
+    },
+    /// Occurs when the key cache holds no revision of the given key
+    /// pair for which both halves are present.
+    KeyPairNotFound {
+        name:        String,
+        public_type: &'static str,
+        secret_type: &'static str,
+    },
+    /// Occurs when a key file in the key cache can't be parsed as the
+    /// kind of key it was expected to be.
+    KeyParse {
+        path:   PathBuf,
+        reason: String,
+    },
+    /// Occurs when a key file in the key cache can't be read.
+    KeyRead {
+        path:   PathBuf,
+        source: io::Error,
+    },
     // When LogonUserW does not have the correct logon type
     LogonTypeNotGranted,
     /// Occurs when a call to LogonUserW fails
     LogonUserFailed(io::Error),
+    /// Occurs when an encrypted message can't be decrypted with the
+    /// keys it names, because it was encrypted with other keys or has
+    /// been tampered with.
+    MessageAuthenticationFailed {
+        sender:    String,
+        recipient: String,
+    },
     NativeTlsError(native_tls::Error),
     /// Occurs when a BIND, BIND_OPTIONAL, or BIND_MAP MetaFile is
     /// read and contains a bad entry.
@@ -120,10 +201,24 @@ pub enum Error {
     Nix(nix::Error),
     /// Occurs when we can't find an outbound IP address
     NoOutboundIpAddr(io::Error),
+    /// Occurs when watching the filesystem for changes fails
+    NotifyError(notify::Error),
     /// Occurs when a call to OpenDesktopW fails
     OpenDesktopFailed(String),
+    /// Occurs when a package's dependencies depend on each other in a
+    /// cycle. Holds the packages in the cycle, beginning and ending
+    /// with the same one.
+    PackageDependencyCycle(Vec<package::PackageIdent>),
+    /// Occurs when a transitive dependency of a package isn't
+    /// installed. Holds the chain of dependencies that led to it, from
+    /// the package whose dependencies were being walked to the missing
+    /// one.
+    PackageDependencyNotFound(Vec<package::PackageIdent>),
     /// Occurs when a suitable installed package cannot be found.
     PackageNotFound(Box<package::PackageIdent>),
+    /// Occurs when no installed package, or at least none with a
+    /// well-formed version and release, matches a partial identifier.
+    PackageNotInstalled(Box<package::PackageIdent>),
     /// Occurs where trying to unpack a package
     PackageUnpackFailed(String),
     /// When an error occurs parsing an integer.
@@ -138,11 +233,23 @@ pub enum Error {
     PlanMalformed,
     // When CreateProcessAsUserW does not have the correct privileges
     PrivilegeNotHeld,
+    /// Occurs when the key cache doesn't hold the secret key an
+    /// encrypted message is addressed to.
+    RecipientKeyNotFound {
+        name:     String,
+        key_type: &'static str,
+    },
     /// When an error occurs parsing or compiling a regular expression.
     RegexParse(regex::Error),
     /// When an error occurs serializing rendering context
     RenderContextSerialization(serde_json::Error),
     RustlsReader(RustlsReaderError),
+    /// Occurs when the key cache doesn't hold the public key of the
+    /// sender of an encrypted message.
+    SenderKeyNotFound {
+        name:     String,
+        key_type: &'static str,
+    },
     /// When an error occurs converting a `String` from a UTF-8 byte vector.
     StringFromUtf8Error(string::FromUtf8Error),
     /// When the system target (platform and architecture) do not match the package target.
@@ -265,16 +372,48 @@ impl fmt::Display for Error {
                 format!("Docker command `{}' was not found on the filesystem or in PATH",
                         c)
             }
+            Error::FileHashMismatch { ref path,
+                                      ref expected,
+                                      ref actual, } => {
+                format!("The contents of {} hash to {}, not the expected {}",
+                        path.display(),
+                        actual,
+                        expected)
+            }
             Error::FileNotFound(ref e) => format!("File not found at: {}", e),
             Error::FullyQualifiedPackageIdentRequired(ref ident) => {
                 format!("Fully-qualified package identifier was expected, but found: {:?}",
                         ident)
             }
+            Error::InsecureKeyCacheDirectory { ref path,
+                                               mode,
+                                               ref source, } => {
+                format!("The key cache directory {} can be used by other users (mode {:#o}), and \
+                         its permissions could not be restricted to its owner: {}",
+                        path.display(),
+                        mode,
+                        source)
+            }
+            Error::InsecureKeyPermissions { ref path, mode } => {
+                format!("Refusing to load secret key file {}, which other users can read (mode \
+                         {:#o}). Its permissions should allow only its owner to read it.",
+                        path.display(),
+                        mode)
+            }
             Error::InvalidBinding(ref binding) => {
                 format!("Invalid binding '{}', must be of the form <NAME>:<SERVICE_GROUP> where \
                          <NAME> is a service name, and <SERVICE_GROUP> is a valid service group",
                         binding)
             }
+            Error::InvalidBldrUrlOverrides(ref e) => {
+                format!("Invalid Builder URL overrides: {}", e)
+            }
+            Error::InvalidKeyName { ref name,
+                                    ref reason, } => {
+                format!("Invalid key name '{}': {}. Key names may only contain letters, numbers, \
+                         '_', '-', '.', and '@', and must begin with a letter, number, or '_'",
+                        name, reason)
+            }
             Error::InvalidOrigin(ref origin) => {
                 format!("Invalid origin: {}. Origins must begin with a lowercase letter or \
                          number. Allowed characters include lowercase letters, numbers, -, and _. \
@@ -301,15 +440,54 @@ impl fmt::Display for Error {
                          service.group (example: redis.production)",
                         e)
             }
+            Error::InvalidServiceGroupIdent { ref ident,
+                                              ref reason, } => {
+                format!("Invalid service group identifier '{}': {}. A valid identifier is in the \
+                         form service.group@org (example: redis.production@acme)",
+                        ident, reason)
+            }
             Error::InvalidUrl(ref url) => format!("Invalid url: {}", url),
             Error::IO(ref err) => format!("{}", err),
             Error::JoinPathsError(ref err) => format!("{}", err),
+            Error::KeyCacheLocked { ref path, timeout } => {
+                format!("Timed out after {} seconds waiting for another process to finish writing \
+                         to the key cache (lock file {})",
+                        timeout.as_secs(),
+                        path.display())
+            }
+            Error::KeyExpired { ref name, key_type } => {
+                format!("Every {} in the key cache for {} has expired",
+                        key_type, name)
+            }
+            Error::KeyNotFound { ref name, key_type } => {
+                format!("No {} found in the key cache for {}", key_type, name)
+            }
+            Error::KeyPairNotFound { ref name,
+                                     public_type,
+                                     secret_type, } => {
+                format!("No {} with a matching {} found in the key cache for {}",
+                        public_type, secret_type, name)
+            }
+            Error::KeyParse { ref path,
+                              ref reason, } => {
+                format!("Could not parse key file {}: {}", path.display(), reason)
+            }
+            Error::KeyRead { ref path,
+                             ref source, } => {
+                format!("Could not read key file {}: {}", path.display(), source)
+            }
             Error::LogonTypeNotGranted => {
                 "hab_svc_user user must possess the 'SE_SERVICE_LOGON_NAME' account right to be \
                  spawned as a service by the Supervisor"
                                                         .to_string()
             }
             Error::LogonUserFailed(ref e) => format!("Failure calling LogonUserW: {:?}", e),
+            Error::MessageAuthenticationFailed { ref sender,
+                                                 ref recipient, } => {
+                format!("Could not decrypt message from {} to {}: it was not encrypted with those \
+                         keys, or has been tampered with",
+                        sender, recipient)
+            }
             Error::NativeTlsError(ref e) => format!("{}", e),
             Error::MetaFileBadBind => {
                 "Bad value parsed from BIND, BIND_OPTIONAL, or BIND_MAP".to_string()
@@ -324,7 +502,22 @@ impl fmt::Display for Error {
             Error::NoOutboundIpAddr(ref e) => {
                 format!("Failed to discover this host's outbound IP address: {}", e)
             }
+            Error::NotifyError(ref e) => format!("Notify error: {}", e),
             Error::OpenDesktopFailed(ref e) => e.to_string(),
+            Error::PackageDependencyCycle(ref cycle) => {
+                format!("Package dependencies form a cycle: {}",
+                        cycle.iter()
+                             .map(ToString::to_string)
+                             .collect::<Vec<_>>()
+                             .join(" depends on "))
+            }
+            Error::PackageDependencyNotFound(ref chain) => {
+                format!("Cannot find package dependency: {}, which is not installed",
+                        chain.iter()
+                             .map(ToString::to_string)
+                             .collect::<Vec<_>>()
+                             .join(" depends on "))
+            }
             Error::PackageNotFound(ref pkg) => {
                 if pkg.fully_qualified() {
                     format!("Cannot find package: {}", pkg)
@@ -332,6 +525,9 @@ impl fmt::Display for Error {
                     format!("Cannot find a release of package: {}", pkg)
                 }
             }
+            Error::PackageNotInstalled(ref pkg) => {
+                format!("No installed package matches {}", pkg)
+            }
             Error::PackageUnpackFailed(ref e) => format!("Package could not be unpacked. {}", e),
             Error::ParseIntError(ref e) => format!("{}", e),
             Error::ParsePemError(ref e) => format!("{}", e),
@@ -342,11 +538,19 @@ impl fmt::Display for Error {
                                         and 'SE_ASSIGNPRIMARYTOKEN_NAME' privilege to spawn a new \
                                         process as a different user"
                                                                     .to_string(),
+            Error::RecipientKeyNotFound { ref name, key_type } => {
+                format!("Cannot decrypt message: the recipient's {} {} is not in the key cache",
+                        key_type, name)
+            }
             Error::RustlsReader(ref e) => format!("{}", e),
             Error::RenderContextSerialization(ref e) => {
                 format!("Unable to serialize rendering context, {}", e)
             }
             Error::RegexParse(ref e) => format!("{}", e),
+            Error::SenderKeyNotFound { ref name, key_type } => {
+                format!("Cannot decrypt message: the sender's {} {} is not in the key cache",
+                        key_type, name)
+            }
             Error::StringFromUtf8Error(ref e) => format!("{}", e),
             Error::TargetMatchError(ref e) => e.to_string(),
             Error::UnameFailed(ref e) => e.to_string(),
@@ -375,7 +579,44 @@ impl fmt::Display for Error {
     }
 }
 
-impl error::Error for Error {}
+impl error::Error for Error {
+    /// The underlying error, for those variants that wrap one. Their
+    /// messages include its message, too, so that the most common
+    /// ways of showing an error don't lose anything.
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::CreateProcessAsUserFailed(e)
+            | Error::IO(e)
+            | Error::LogonUserFailed(e)
+            | Error::MetaFileIO(e)
+            | Error::NoOutboundIpAddr(e)
+            | Error::SignalFailed(_, e)
+            | Error::TerminateProcessFailed(_, e)
+            | Error::KeyRead { source: e, .. } => Some(e),
+            Error::CtlGatewayTls(e) => Some(e),
+            Error::InsecureKeyCacheDirectory { source, .. } => Some(source.as_ref()),
+            Error::InvalidPort(e) | Error::ParseIntError(e) => Some(e),
+            Error::JoinPathsError(e) => Some(e),
+            Error::NativeTlsError(e) => Some(e),
+            #[cfg(not(windows))]
+            Error::Nix(e) => Some(e),
+            Error::NotifyError(e) => Some(e),
+            Error::ParsePemError(e) => Some(e),
+            Error::RegexParse(e) => Some(e),
+            Error::RenderContextSerialization(e) => Some(e),
+            Error::RustlsReader(e) => Some(e),
+            Error::StringFromUtf8Error(e) => Some(e),
+            Error::Utf8Error(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Lets conversions that can't fail be used wherever ones that can
+/// are accepted.
+impl From<Infallible> for Error {
+    fn from(err: Infallible) -> Self { match err {} }
+}
 
 impl From<CtlGatewayTls> for Error {
     fn from(err: CtlGatewayTls) -> Self { Error::CtlGatewayTls(err) }
@@ -402,6 +643,10 @@ impl From<nix::Error> for Error {
     fn from(err: nix::Error) -> Self { Error::Nix(err) }
 }
 
+impl From<notify::Error> for Error {
+    fn from(err: notify::Error) -> Self { Error::NotifyError(err) }
+}
+
 impl From<native_tls::Error> for Error {
     fn from(err: native_tls::Error) -> Self { Error::NativeTlsError(err) }
 }
@@ -51,9 +51,11 @@ impl Backoff {
                 let mut rng = thread_rng();
                 // We use the decorrelated jitter algorithm mentioned here:
                 // https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/
-                let new_sleep_duration =
-                    self.max_backoff.min(rng.gen_range(self.base_backoff
-                                                       ..=sleep_duration.mul_f64(self.multiplier)));
+                // The previous sleep may predate a change to the bounds (see `set_bounds`).
+                let upper = sleep_duration.mul_f64(self.multiplier)
+                                          .max(self.base_backoff);
+                let new_sleep_duration = self.max_backoff
+                                             .min(rng.gen_range(self.base_backoff..=upper));
                 self.last_attempt = Some(RetryAttempt { attempt_started_at: Instant::now(),
                                                         attempt_ended_at:   None,
                                                         sleep_duration:     new_sleep_duration, });
@@ -77,6 +79,27 @@ impl Backoff {
         }
     }
 
+    /// Picks up where an earlier backoff left off, as though its last
+    /// attempt had waited `sleep_duration` and ended `ended_ago`. Used
+    /// to carry a backoff over from a previous run of the process.
+    pub fn resume(&mut self, sleep_duration: Duration, ended_ago: Duration) {
+        let now = Instant::now();
+        let ended_at = now.checked_sub(ended_ago).unwrap_or(now);
+        let sleep_duration = sleep_duration.clamp(self.base_backoff, self.max_backoff);
+        let started_at = ended_at.checked_sub(sleep_duration).unwrap_or(ended_at);
+        self.last_attempt = Some(RetryAttempt { attempt_started_at: started_at,
+                                                attempt_ended_at: Some(ended_at),
+                                                sleep_duration });
+    }
+
+    /// Changes the shortest and longest durations waited between
+    /// attempts. An attempt already being waited on keeps the duration
+    /// it was given; only the ones after it are affected.
+    pub fn set_bounds(&mut self, base_backoff: Duration, max_backoff: Duration) {
+        self.base_backoff = base_backoff.min(max_backoff);
+        self.max_backoff = max_backoff;
+    }
+
     /// Resets the backoff state erasing any attempt information
     pub fn reset(&mut self) { self.last_attempt = None }
 
@@ -97,6 +120,14 @@ impl Backoff {
             .map(|attempt_ended_at| attempt_ended_at.elapsed())
     }
 
+    /// The duration waited, or being waited, before the last attempt started, if there was one.
+    /// This grows with each attempt, up to `max_backoff`, until the backoff is reset.
+    pub fn current_backoff_duration(&self) -> Option<Duration> {
+        self.last_attempt
+            .as_ref()
+            .map(|attempt| attempt.sleep_duration)
+    }
+
     /// Get the duration until the next attempt. There are several possible scenarios:
     /// - returns None if no attempts have been made yet, or we are not in the middle of an attempt
     /// - returns Some(Duration) if there is time remaining until the attempt can be ended
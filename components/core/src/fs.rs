@@ -21,14 +21,22 @@ use crate::{env as henv,
             package::{Identifiable,
                       PackageIdent,
                       PackageInstall}};
+use filetime::FileTime;
 use std::{env,
+          ffi::OsStr,
           fmt,
           fs,
+          future::Future,
           io::{self,
                Write},
           path::{Path,
                  PathBuf},
-          str::FromStr};
+          pin::Pin,
+          str::FromStr,
+          time::{Duration,
+                 SystemTime}};
+use tokio::{io::AsyncWriteExt,
+            task};
 
 /// The default root path of the Habitat filesystem
 pub const ROOT_PATH: &str = "hab";
@@ -751,6 +759,98 @@ fn parent(p: &Path) -> io::Result<&Path> {
     }
 }
 
+/// The prefix of the temporary files `AtomicWriter` and
+/// `AtomicWriterAsync` write to. The full name is the prefix, the ID
+/// of the writing process, a `-`, and `TMP_FILE_RAND_LEN` random
+/// alphanumeric characters, so that concurrent writers in the same
+/// directory, whether in the same process or not, never collide.
+const TMP_FILE_PREFIX: &str = ".hab-tmp-";
+const TMP_FILE_RAND_LEN: usize = 12;
+
+/// How old an `AtomicWriter` temporary file has to be before
+/// `cleanup_stale_tmp_files` is asked to remove it at startup. No
+/// write takes anywhere near this long, so a file this old was left
+/// behind by a process that was killed part way through.
+pub const STALE_TMP_FILE_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Create a temporary file in `dir` named as `TMP_FILE_PREFIX`
+/// describes, which is removed when it's dropped.
+fn atomic_tmp_file_in(dir: &Path) -> io::Result<tempfile::NamedTempFile> {
+    tempfile::Builder::new().prefix(&format!("{}{}-", TMP_FILE_PREFIX, process::current_pid()))
+                            .rand_bytes(TMP_FILE_RAND_LEN)
+                            .tempfile_in(dir)
+}
+
+/// Whether `name` is exactly the name of an `AtomicWriter` temporary
+/// file. Nothing else is ever considered one, however similar.
+fn is_atomic_tmp_file_name(name: &OsStr) -> bool {
+    let rest = match name.to_str()
+                         .and_then(|name| name.strip_prefix(TMP_FILE_PREFIX))
+    {
+        Some(rest) => rest,
+        None => return false,
+    };
+    match rest.split_once('-') {
+        Some((pid, rand)) => {
+            !pid.is_empty()
+            && pid.bytes().all(|b| b.is_ascii_digit())
+            && rand.len() == TMP_FILE_RAND_LEN
+            && rand.bytes().all(|b| b.is_ascii_alphanumeric())
+        }
+        None => false,
+    }
+}
+
+/// Remove the temporary files that `AtomicWriter` and
+/// `AtomicWriterAsync` left directly in `dir` when the process writing
+/// them was killed part way through, returning how many were removed.
+///
+/// Only regular files whose names match the temporary file naming
+/// exactly, and that were last modified more than `older_than` ago,
+/// are removed; a younger one may belong to a write still in
+/// progress. A file that can't be removed is logged and skipped. A
+/// `dir` that doesn't exist has nothing to clean up.
+pub fn cleanup_stale_tmp_files(dir: &Path, older_than: Duration) -> io::Result<usize> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in entries {
+        let entry = entry?;
+        if !is_atomic_tmp_file_name(&entry.file_name()) {
+            continue;
+        }
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            // Its writer finished with it in the meantime
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let age = metadata.modified()
+                          .ok()
+                          .and_then(|modified| now.duration_since(modified).ok());
+        if !metadata.is_file() || age.map_or(true, |age| age <= older_than) {
+            continue;
+        }
+        match fs::remove_file(entry.path()) {
+            Ok(()) => {
+                debug!("Removed stale temporary file {}", entry.path().display());
+                removed += 1;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => {
+                warn!("Unable to remove stale temporary file {}: {}",
+                      entry.path().display(),
+                      e)
+            }
+        }
+    }
+    Ok(removed)
+}
+
 /// An AtomicWriter atomically writes content to a file at the
 /// specified path using a tempfile+rename strategy to achieve
 /// atomicity.
@@ -763,6 +863,13 @@ fn parent(p: &Path) -> io::Result<&Path> {
 ///
 /// without seeing any possible intermediate state.
 ///
+/// On Unix, the destination's directory is synced after the rename,
+/// so that the new file survives a crash or power failure.
+///
+/// The temporary file is written in the destination's directory, and
+/// left there if the process is killed part way through; see
+/// `cleanup_stale_tmp_files`.
+///
 /// Assumes that the parent directory of dest_path exists.
 pub struct AtomicWriter {
     dest:        PathBuf,
@@ -787,25 +894,34 @@ impl AtomicWriter {
     // it.
     pub fn new_with_permissions(dest_path: &Path, permissions: Permissions) -> io::Result<Self> {
         let parent = parent(dest_path)?;
-        let tempfile = tempfile::NamedTempFile::new_in(parent)?;
+        let tempfile = atomic_tmp_file_in(parent)?;
         Ok(Self { dest: dest_path.to_path_buf(),
                   tempfile,
                   permissions })
     }
 
-    pub fn with_writer<F, T, E>(mut self, op: F) -> std::result::Result<T, E>
+    pub fn with_writer<F, T, E>(self, op: F) -> std::result::Result<T, E>
         where F: FnOnce(&mut std::fs::File) -> std::result::Result<T, E>,
               E: From<std::io::Error>
     {
-        let r = op(self.tempfile.as_file_mut())?;
-        self.finish()?;
+        let (r, staged) = self.stage(op)?;
+        staged.commit()?;
         Ok(r)
     }
 
-    /// Completes the atomic write by calling sync on the temporary
-    /// file to ensure all data is flushed to disk and then renaming
-    /// the file into place.
-    fn finish(self) -> io::Result<()> {
+    /// Like `with_writer`, but stops short of renaming the file into
+    /// place. The content is written and synced to disk, and only
+    /// appears at the destination once the returned `StagedWrite` is
+    /// committed. Dropping it instead removes the temporary file.
+    ///
+    /// This lets several files be written before any of them are
+    /// renamed into place, so that a failure to write one doesn't
+    /// leave the others half done.
+    pub fn stage<F, T, E>(mut self, op: F) -> std::result::Result<(T, StagedWrite), E>
+        where F: FnOnce(&mut std::fs::File) -> std::result::Result<T, E>,
+              E: From<std::io::Error>
+    {
+        let r = op(self.tempfile.as_file_mut())?;
         // Note that we only set permissions if given explicit ones to
         // override whatever permissions the file was created with.
         if let Permissions::Explicit(ref permissions) = self.permissions {
@@ -815,14 +931,14 @@ impl AtomicWriter {
             let permissions = *permissions;
 
             set_permissions(self.tempfile.path(), permissions).map_err(|e| {
-                io::Error::new(io::ErrorKind::Other, e.to_string())
+                io::Error::new(io::ErrorKind::Other, e)
             })?;
         }
         self.tempfile.as_file().sync_all()?;
 
-        atomic_rename(self.tempfile.into_temp_path(), self.dest.as_path())?;
-
-        Ok(())
+        Ok((r,
+            StagedWrite { dest:     self.dest,
+                          tempfile: self.tempfile, }))
     }
 
     /// sync_parent syncs the parent directory. This is required on
@@ -853,6 +969,23 @@ impl AtomicWriter {
     }
 }
 
+/// The fully written, but not yet renamed, temporary file of an
+/// `AtomicWriter`. See `AtomicWriter::stage`.
+pub struct StagedWrite {
+    dest:     PathBuf,
+    tempfile: tempfile::NamedTempFile,
+}
+
+impl StagedWrite {
+    /// The path the file will be renamed to when committed.
+    pub fn dest(&self) -> &Path { &self.dest }
+
+    /// Completes the atomic write by renaming the file into place.
+    pub fn commit(self) -> io::Result<()> {
+        atomic_rename(self.tempfile.into_temp_path(), self.dest.as_path())
+    }
+}
+
 // `fs::rename` calls `MoveFileExW` on Windows, however the underlying implementation only
 // utilizes the `MOVEFILE_REPLACE_EXISTING` flag which allows for file overwrite but no
 // guarantee on durability. For this, we additionally pass `MOVEFILE_WRITE_THROUGH`.
@@ -886,11 +1019,36 @@ fn rename_unix<P: AsRef<Path>, Q: AsRef<Path> + std::convert::AsRef<std::path::P
     from: P,
     to: Q)
     -> io::Result<()> {
-    fs::rename(from, to)?;
+    rename_or_copy_unix(from.as_ref(), to.as_ref(), |from, to| fs::rename(from, to))?;
     AtomicWriter::sync_parent(&PathBuf::from(to.as_ref()))?;
     Ok(())
 }
 
+/// Rename `from` to `to` with `rename`. A file can't be renamed onto
+/// another filesystem (`EXDEV`), so in that case it's copied to a
+/// temporary file next to `to`, synced, and renamed from there
+/// instead, keeping the replacement of `to` atomic. `from` is removed
+/// once that's done.
+#[cfg(unix)]
+fn rename_or_copy_unix<R>(from: &Path, to: &Path, rename: R) -> io::Result<()>
+    where R: Fn(&Path, &Path) -> io::Result<()>
+{
+    match rename(from, to) {
+        Err(e) if e.raw_os_error() == Some(libc::EXDEV) => {
+            debug!("{} is on a different filesystem than {}; copying it instead",
+                   from.display(),
+                   to.display());
+            let staged = atomic_tmp_file_in(parent(to)?)?;
+            // `fs::copy` carries the permissions over, too.
+            fs::copy(from, staged.path())?;
+            staged.as_file().sync_all()?;
+            rename(&staged.into_temp_path(), to)?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
 /// atomic_rename is a cross platform  helper function for renaming a file atomically with
 /// durability guarantees.
 pub fn atomic_rename<P: AsRef<Path>,
@@ -914,6 +1072,422 @@ pub fn atomic_write(dest_path: &Path, data: impl AsRef<[u8]>) -> io::Result<()>
     w.with_writer(|f| f.write_all(data.as_ref()))
 }
 
+/// The future an `AtomicWriterAsync` caller writes its content with.
+pub type AsyncWriteOp<'a, T, E> =
+    Pin<Box<dyn Future<Output = std::result::Result<T, E>> + Send + 'a>>;
+
+/// The async counterpart of `AtomicWriter`, for writing files from
+/// async code without blocking the runtime.
+///
+/// The same guarantees hold: the content is written to a temporary
+/// file in the destination's directory, synced, given the requested
+/// permissions, and only then renamed into place. If writing fails,
+/// or the future doing it panics or is dropped, the temporary file is
+/// removed and the destination is left alone.
+///
+/// Assumes that the parent directory of dest_path exists.
+pub struct AtomicWriterAsync {
+    dest:        PathBuf,
+    permissions: Permissions,
+}
+
+impl AtomicWriterAsync {
+    /// Create a new `AtomicWriterAsync` that writes to a file at
+    /// `dest_path` with default permissions.
+    pub fn new(dest_path: &Path) -> Self {
+        Self::new_with_permissions(dest_path, Permissions::default())
+    }
+
+    /// Create a new `AtomicWriterAsync` that writes to a file at
+    /// `dest_path` with the specified permissions, which are applied
+    /// just as `AtomicWriter::new_with_permissions` applies them.
+    pub fn new_with_permissions(dest_path: &Path, permissions: Permissions) -> Self {
+        Self { dest: dest_path.to_path_buf(),
+               permissions }
+    }
+
+    /// Write the file's content with `op`, then move it into place.
+    ///
+    /// Since `op` borrows the file it writes to, it has to box up the
+    /// future it returns:
+    ///
+    /// ```ignore
+    /// writer.with_writer(|f| Box::pin(async move { f.write_all(b"content").await }))
+    ///       .await?;
+    /// ```
+    pub async fn with_writer<F, T, E>(self, op: F) -> std::result::Result<T, E>
+        where F: for<'a> FnOnce(&'a mut tokio::fs::File) -> AsyncWriteOp<'a, T, E>,
+              E: From<io::Error>
+    {
+        let parent = parent(&self.dest)?.to_path_buf();
+        // The temporary file is removed when `tempfile` is dropped,
+        // which covers every way of leaving here short of the rename.
+        let (file, tempfile) =
+            run_blocking(move || atomic_tmp_file_in(&parent).map(|t| t.into_parts())).await?;
+        let mut file = tokio::fs::File::from_std(file);
+
+        let r = op(&mut file).await?;
+        file.flush().await?;
+        // As with `AtomicWriter`, permissions are only set if given
+        // explicit ones.
+        if let Permissions::Explicit(permissions) = self.permissions {
+            let path = tempfile.to_path_buf();
+            run_blocking(move || {
+                #[cfg(not(windows))]
+                let result = set_permissions(&path, permissions);
+                #[cfg(windows)]
+                let result = set_permissions(&path, &permissions);
+                result.map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+            }).await?;
+        }
+        file.sync_all().await?;
+        drop(file);
+
+        let dest = self.dest;
+        run_blocking(move || atomic_rename(tempfile, dest.as_path())).await?;
+        Ok(r)
+    }
+}
+
+/// The async counterpart of `atomic_write`.
+pub async fn atomic_write_async(dest_path: &Path, data: impl Into<Vec<u8>>) -> io::Result<()> {
+    let data = data.into();
+    let w = AtomicWriterAsync::new(dest_path);
+    w.with_writer(|f| Box::pin(async move { f.write_all(&data).await }))
+     .await
+}
+
+/// What `copy_dir` does about files (and symlinks) that are already
+/// in the destination.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overwrite {
+    #[default]
+    Always,
+    /// Leave them as they are.
+    Never,
+    /// Replace only those modified before their originals were.
+    IfNewer,
+}
+
+/// How `copy_dir` copies a directory tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CopyDirOptions {
+    /// Copy what symlinks point to, rather than the symlinks
+    /// themselves.
+    pub follow_symlinks:      bool,
+    /// Give each copy the modification time of its original.
+    pub preserve_mtimes:      bool,
+    /// Give each copy the permissions of its original. Otherwise, new
+    /// files and directories get the permissions they'd normally be
+    /// created with, and existing ones keep theirs.
+    pub preserve_permissions: bool,
+    pub overwrite:            Overwrite,
+    /// Remove whatever is in the destination but not in the source,
+    /// so that it ends up an exact copy. Requires `mirror_root`.
+    pub mirror:               bool,
+    /// The directory that the destination must be strictly inside of
+    /// for `mirror` to be allowed, so that a badly joined path can't
+    /// delete far more than was meant to be.
+    pub mirror_root:          Option<PathBuf>,
+}
+
+impl Default for CopyDirOptions {
+    fn default() -> Self {
+        CopyDirOptions { follow_symlinks:      false,
+                         preserve_mtimes:      false,
+                         preserve_permissions: true,
+                         overwrite:            Overwrite::default(),
+                         mirror:               false,
+                         mirror_root:          None, }
+    }
+}
+
+/// What `copy_dir` copied, e.g. for logging.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CopyDirSummary {
+    pub files:       u64,
+    pub bytes:       u64,
+    pub symlinks:    u64,
+    pub directories: u64,
+    /// Files and symlinks left alone because of `Overwrite`.
+    pub skipped:     u64,
+    /// Entries removed from the destination because of `mirror`,
+    /// counting each removed directory once, whatever was in it.
+    pub removed:     u64,
+}
+
+impl fmt::Display for CopyDirSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f,
+               "{} files ({} bytes), {} symlinks, {} directories, {} skipped, {} removed",
+               self.files, self.bytes, self.symlinks, self.directories, self.skipped, self.removed)
+    }
+}
+
+/// Recursively copy the contents of `source_dir` into `dest_dir`,
+/// creating it (and its parents) as needed. `dest_dir` is given the
+/// permissions of `source_dir`.
+///
+/// Files and directories, empty or not, keep their permissions, and
+/// symlinks are copied as symlinks pointing at exactly what the
+/// originals did, unless `options` says otherwise. A directory is
+/// given its permissions only once its contents are copied, so
+/// read-only directories copy fine. Anything that is neither a file,
+/// a directory, nor a symlink (e.g. a socket) is skipped.
+///
+/// Whatever is already in `dest_dir` is merged with, replacing files
+/// and symlinks as `options.overwrite` says; with `options.mirror`,
+/// anything that isn't in `source_dir` is removed as well.
+pub fn copy_dir(source_dir: &Path,
+                dest_dir: &Path,
+                options: CopyDirOptions)
+                -> io::Result<CopyDirSummary> {
+    let metadata = fs::metadata(source_dir).map_err(failed_to("read", source_dir))?;
+    if !metadata.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("'{}' is not a directory",
+                                          source_dir.display())));
+    }
+    if options.mirror {
+        check_mirror_dest(dest_dir, options.mirror_root.as_deref())?;
+    }
+    let mut summary = CopyDirSummary::default();
+    copy_dir_contents(source_dir,
+                      dest_dir,
+                      &metadata,
+                      &options,
+                      &mut summary,
+                      &mut Vec::new())?;
+    Ok(summary)
+}
+
+/// The async counterpart of `copy_dir`.
+pub async fn copy_dir_async(source_dir: impl Into<PathBuf>,
+                            dest_dir: impl Into<PathBuf>,
+                            options: CopyDirOptions)
+                            -> io::Result<CopyDirSummary> {
+    let source_dir = source_dir.into();
+    let dest_dir = dest_dir.into();
+    run_blocking(move || copy_dir(&source_dir, &dest_dir, options)).await
+}
+
+/// Refuse to mirror into `dest_dir` unless it's strictly inside
+/// `root`. Paths are compared as written, so ones that climb back up
+/// with `..` are refused too.
+fn check_mirror_dest(dest_dir: &Path, root: Option<&Path>) -> io::Result<()> {
+    let root = root.ok_or_else(|| {
+                       io::Error::new(io::ErrorKind::InvalidInput,
+                                      "Mirroring a directory requires a root to mirror within")
+                   })?;
+    let climbs = dest_dir.components()
+                         .any(|c| c == std::path::Component::ParentDir);
+    if climbs || dest_dir == root || !dest_dir.starts_with(root) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("Refusing to mirror into '{}', \
+                                           which is not strictly inside '{}'",
+                                          dest_dir.display(),
+                                          root.display())));
+    }
+    Ok(())
+}
+
+/// `ancestors` holds the (canonical) directories being copied above
+/// this one, so that following symlinks can't go around in circles.
+fn copy_dir_contents(source_dir: &Path,
+                     dest_dir: &Path,
+                     dir_metadata: &fs::Metadata,
+                     options: &CopyDirOptions,
+                     summary: &mut CopyDirSummary,
+                     ancestors: &mut Vec<PathBuf>)
+                     -> io::Result<()> {
+    if options.follow_symlinks {
+        let canonical = source_dir.canonicalize()
+                                  .map_err(failed_to("read", source_dir))?;
+        if ancestors.contains(&canonical) {
+            return Err(io::Error::new(io::ErrorKind::Other,
+                                      format!("Symlinks loop back around to '{}'",
+                                              source_dir.display())));
+        }
+        ancestors.push(canonical);
+    }
+
+    // Something other than a directory in the way is replaced if files
+    // may be overwritten. A symlink to a directory is copied into,
+    // unless mirroring, which mustn't reach outside of the destination.
+    if let Ok(existing) = fs::symlink_metadata(dest_dir) {
+        let in_the_way = !existing.is_dir() && (options.mirror || !dest_dir.is_dir());
+        if in_the_way && (options.mirror || options.overwrite == Overwrite::Always) {
+            remove_entry(dest_dir)?;
+        }
+    }
+    fs::create_dir_all(dest_dir).map_err(failed_to("create", dest_dir))?;
+    summary.directories += 1;
+    let mut names = Vec::new();
+    for entry in fs::read_dir(source_dir).map_err(failed_to("read", source_dir))? {
+        let entry = entry.map_err(failed_to("read", source_dir))?;
+        let source = entry.path();
+        let dest = dest_dir.join(entry.file_name());
+        names.push(entry.file_name());
+        let mut metadata = entry.metadata().map_err(failed_to("read", &source))?;
+
+        if metadata.file_type().is_symlink() {
+            if options.follow_symlinks {
+                metadata = fs::metadata(&source).map_err(failed_to("follow", &source))?;
+            } else {
+                if !make_way(&dest, &metadata, options.overwrite)? {
+                    summary.skipped += 1;
+                    continue;
+                }
+                copy_symlink(&source, &dest).map_err(failed_to("copy", &source))?;
+                if options.preserve_mtimes {
+                    let atime = FileTime::from_last_access_time(&metadata);
+                    let mtime = FileTime::from_last_modification_time(&metadata);
+                    filetime::set_symlink_file_times(&dest, atime, mtime)
+                        .map_err(failed_to("set the times of", &dest))?;
+                }
+                summary.symlinks += 1;
+                continue;
+            }
+        }
+
+        if metadata.is_dir() {
+            copy_dir_contents(&source, &dest, &metadata, options, summary, ancestors)?;
+        } else if metadata.is_file() {
+            if !make_way(&dest, &metadata, options.overwrite)? {
+                summary.skipped += 1;
+                continue;
+            }
+            summary.bytes += copy_file(&source, &dest, options.preserve_permissions)
+                .map_err(failed_to("copy", &source))?;
+            summary.files += 1;
+            if options.preserve_mtimes {
+                set_mtime(&dest, &metadata)?;
+            }
+        } else {
+            warn!("Not copying '{}', which is neither a file, a directory, nor a symlink",
+                  source.display());
+        }
+    }
+
+    if options.mirror {
+        for entry in fs::read_dir(dest_dir).map_err(failed_to("read", dest_dir))? {
+            let entry = entry.map_err(failed_to("read", dest_dir))?;
+            if !names.contains(&entry.file_name()) {
+                remove_entry(&entry.path())?;
+                summary.removed += 1;
+            }
+        }
+    }
+
+    if options.preserve_permissions {
+        fs::set_permissions(dest_dir, dir_metadata.permissions())
+            .map_err(failed_to("set the permissions of", dest_dir))?;
+    }
+    if options.preserve_mtimes {
+        set_mtime(dest_dir, dir_metadata)?;
+    }
+    if options.follow_symlinks {
+        ancestors.pop();
+    }
+    Ok(())
+}
+
+/// Get whatever is at `dest` out of the way of copying a file or
+/// symlink there, if `overwrite` allows it, returning whether it does.
+/// Only a file that keeps its permissions is left in place, to be
+/// written over.
+fn make_way(dest: &Path, source_metadata: &fs::Metadata, overwrite: Overwrite) -> io::Result<bool> {
+    let existing = match fs::symlink_metadata(dest) {
+        Ok(existing) => existing,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(true),
+        Err(e) => return Err(failed_to("read", dest)(e)),
+    };
+    let replace = match overwrite {
+        Overwrite::Always => true,
+        Overwrite::Never => false,
+        Overwrite::IfNewer => {
+            match (source_metadata.modified(), existing.modified()) {
+                (Ok(source), Ok(existing)) => source > existing,
+                // Without times to go by, it's safest to copy
+                _ => true,
+            }
+        }
+    };
+    if replace && (existing.is_dir() || existing.file_type().is_symlink()) {
+        remove_entry(dest)?;
+    }
+    Ok(replace)
+}
+
+/// Copy a single file, returning how many bytes were copied. A file
+/// that's already at `dest` keeps its permissions unless
+/// `preserve_permissions` is set.
+fn copy_file(source: &Path, dest: &Path, preserve_permissions: bool) -> io::Result<u64> {
+    if preserve_permissions {
+        // A read-only file can't be copied over, but can be replaced
+        if fs::symlink_metadata(dest).map(|m| m.permissions().readonly())
+                                     .unwrap_or(false)
+        {
+            fs::remove_file(dest)?;
+        }
+        fs::copy(source, dest)
+    } else {
+        io::copy(&mut fs::File::open(source)?, &mut fs::File::create(dest)?)
+    }
+}
+
+/// Remove a file, symlink, or directory and everything in it. Symlinks
+/// are removed themselves, never what they point to.
+fn remove_entry(path: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path).map_err(failed_to("read", path))?;
+    if metadata.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }.map_err(failed_to("remove", path))
+}
+
+#[cfg(not(windows))]
+fn copy_symlink(source: &Path, dest: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(source)?, dest)
+}
+
+#[cfg(windows)]
+fn copy_symlink(source: &Path, dest: &Path) -> io::Result<()> {
+    let target = fs::read_link(source)?;
+    // Windows symlinks are either for files or for directories. One
+    // that leads nowhere is copied as a file symlink.
+    if fs::metadata(source).map(|m| m.is_dir()).unwrap_or(false) {
+        std::os::windows::fs::symlink_dir(target, dest)
+    } else {
+        std::os::windows::fs::symlink_file(target, dest)
+    }
+}
+
+fn set_mtime(path: &Path, metadata: &fs::Metadata) -> io::Result<()> {
+    filetime::set_file_mtime(path, FileTime::from_last_modification_time(metadata))
+        .map_err(failed_to("set the modification time of", path))
+}
+
+/// Add what was being done, and to what, to an `io::Error`.
+fn failed_to<'a>(action: &'a str, path: &'a Path) -> impl FnOnce(io::Error) -> io::Error + 'a {
+    move |e| {
+        io::Error::new(e.kind(),
+                       format!("Could not {} '{}': {}", action, path.display(), e))
+    }
+}
+
+/// Run blocking filesystem work on a thread meant for it.
+async fn run_blocking<F, T>(op: F) -> io::Result<T>
+    where F: FnOnce() -> io::Result<T> + Send + 'static,
+          T: Send + 'static
+{
+    match task::spawn_blocking(op).await {
+        Ok(result) => result,
+        Err(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -962,6 +1536,374 @@ mod tests {
     }
 }
 
+#[cfg(all(test, not(windows)))]
+mod test_copy_dir {
+    use super::{copy_dir,
+                copy_dir_async,
+                CopyDirOptions,
+                CopyDirSummary,
+                Overwrite};
+    use filetime::FileTime;
+    use std::{fs,
+              os::unix::fs::{symlink,
+                             PermissionsExt},
+              path::Path};
+    use tempfile::TempDir;
+
+    fn mode(path: &Path) -> u32 { fs::metadata(path).unwrap().permissions().mode() & 0o7777 }
+
+    fn set_mode(path: &Path, mode: u32) {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    /// A tree with an executable, a read-only file, a symlink, and an
+    /// empty directory with unusual permissions.
+    fn source_tree() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("bin")).unwrap();
+        fs::write(root.join("bin/run"), "#!/bin/sh\necho hi\n").unwrap();
+        set_mode(&root.join("bin/run"), 0o755);
+        fs::write(root.join("config"), "setting = true\n").unwrap();
+        set_mode(&root.join("config"), 0o444);
+        symlink("bin/run", root.join("run")).unwrap();
+        fs::create_dir(root.join("empty")).unwrap();
+        set_mode(&root.join("empty"), 0o710);
+        dir
+    }
+
+    #[test]
+    fn copies_modes_and_symlinks_faithfully() {
+        let source = source_tree();
+        let dest = TempDir::new().unwrap();
+        let dest_dir = dest.path().join("nested/copy");
+
+        let summary = copy_dir(source.path(), &dest_dir, CopyDirOptions::default()).unwrap();
+
+        assert_eq!(summary,
+                   CopyDirSummary { files:       2,
+                                    bytes:       33,
+                                    symlinks:    1,
+                                    directories: 3,
+                                    skipped:     0,
+                                    removed:     0, });
+        assert_eq!(fs::read_to_string(dest_dir.join("bin/run")).unwrap(),
+                   "#!/bin/sh\necho hi\n");
+        assert_eq!(mode(&dest_dir.join("bin/run")), 0o755);
+        assert_eq!(mode(&dest_dir.join("config")), 0o444);
+        assert_eq!(mode(&dest_dir.join("empty")), 0o710);
+        assert!(fs::symlink_metadata(dest_dir.join("run")).unwrap()
+                                                          .file_type()
+                                                          .is_symlink());
+        assert_eq!(fs::read_link(dest_dir.join("run")).unwrap(),
+                   Path::new("bin/run"));
+    }
+
+    #[test]
+    fn symlinks_can_be_followed() {
+        let source = source_tree();
+        let dest = TempDir::new().unwrap();
+        let options = CopyDirOptions { follow_symlinks: true,
+                                       ..Default::default() };
+
+        let summary = copy_dir(source.path(), dest.path(), options).unwrap();
+
+        assert_eq!(summary.files, 3);
+        assert_eq!(summary.symlinks, 0);
+        let run = dest.path().join("run");
+        assert!(fs::symlink_metadata(&run).unwrap().is_file());
+        assert_eq!(mode(&run), 0o755);
+    }
+
+    #[test]
+    fn following_symlinks_in_circles_is_an_error() {
+        let source = TempDir::new().unwrap();
+        fs::create_dir(source.path().join("sub")).unwrap();
+        symlink("..", source.path().join("sub/up")).unwrap();
+        let dest = TempDir::new().unwrap();
+        let options = CopyDirOptions { follow_symlinks: true,
+                                       ..Default::default() };
+
+        assert!(copy_dir(source.path(), dest.path(), options).is_err());
+        assert!(copy_dir(source.path(), dest.path(), CopyDirOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn mtimes_are_preserved_only_when_asked() {
+        let source = source_tree();
+        let long_ago = FileTime::from_unix_time(1_000_000_000, 0);
+        filetime::set_file_mtime(source.path().join("bin/run"), long_ago).unwrap();
+        filetime::set_file_mtime(source.path().join("empty"), long_ago).unwrap();
+        let mtime =
+            |path: &Path| FileTime::from_last_modification_time(&fs::metadata(path).unwrap());
+
+        let dest = TempDir::new().unwrap();
+        copy_dir(source.path(), dest.path(), CopyDirOptions::default()).unwrap();
+        assert_ne!(mtime(&dest.path().join("bin/run")), long_ago);
+
+        let dest = TempDir::new().unwrap();
+        let options = CopyDirOptions { preserve_mtimes: true,
+                                       ..Default::default() };
+        copy_dir(source.path(), dest.path(), options).unwrap();
+        assert_eq!(mtime(&dest.path().join("bin/run")), long_ago);
+        assert_eq!(mtime(&dest.path().join("empty")), long_ago);
+    }
+
+    #[test]
+    fn source_must_be_a_directory() {
+        let source = source_tree();
+        let dest = TempDir::new().unwrap();
+
+        assert!(copy_dir(&source.path().join("missing"),
+                         dest.path(),
+                         CopyDirOptions::default()).is_err());
+        assert!(copy_dir(&source.path().join("config"),
+                         dest.path(),
+                         CopyDirOptions::default()).is_err());
+    }
+
+    #[test]
+    fn existing_files_are_overwritten_only_as_asked() {
+        let source = source_tree();
+        let long_ago = FileTime::from_unix_time(1_000_000_000, 0);
+        filetime::set_file_mtime(source.path().join("bin/run"), long_ago).unwrap();
+        let stale = |dest: &TempDir| {
+            fs::write(dest.path().join("config"), "setting = false\n").unwrap();
+            fs::create_dir(dest.path().join("bin")).unwrap();
+            fs::write(dest.path().join("bin/run"), "#!/bin/sh\necho bye\n").unwrap();
+        };
+        let copy_with = |dest: &TempDir, overwrite| {
+            let options = CopyDirOptions { overwrite,
+                                           ..Default::default() };
+            copy_dir(source.path(), dest.path(), options).unwrap()
+        };
+
+        let dest = TempDir::new().unwrap();
+        stale(&dest);
+        let summary = copy_with(&dest, Overwrite::Always);
+        assert_eq!(summary.skipped, 0);
+        assert_eq!(fs::read_to_string(dest.path().join("config")).unwrap(),
+                   "setting = true\n");
+        assert_eq!(mode(&dest.path().join("config")), 0o444);
+
+        // Copying over a symlink and a read-only file works too
+        let summary = copy_with(&dest, Overwrite::Always);
+        assert_eq!((summary.files, summary.symlinks), (2, 1));
+
+        let dest = TempDir::new().unwrap();
+        stale(&dest);
+        let summary = copy_with(&dest, Overwrite::Never);
+        assert_eq!(summary.skipped, 2);
+        assert_eq!(fs::read_to_string(dest.path().join("config")).unwrap(),
+                   "setting = false\n");
+        assert!(fs::symlink_metadata(dest.path().join("run")).is_ok());
+
+        // Only the destination's `config` is older than its original
+        let dest = TempDir::new().unwrap();
+        stale(&dest);
+        filetime::set_file_mtime(dest.path().join("config"), long_ago).unwrap();
+        let summary = copy_with(&dest, Overwrite::IfNewer);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(fs::read_to_string(dest.path().join("config")).unwrap(),
+                   "setting = true\n");
+        assert_eq!(fs::read_to_string(dest.path().join("bin/run")).unwrap(),
+                   "#!/bin/sh\necho bye\n");
+    }
+
+    #[test]
+    fn permissions_are_preserved_only_when_asked() {
+        let source = source_tree();
+        let dest = TempDir::new().unwrap();
+        fs::write(dest.path().join("config"), "setting = false\n").unwrap();
+        set_mode(&dest.path().join("config"), 0o600);
+        let options = CopyDirOptions { preserve_permissions: false,
+                                       ..Default::default() };
+
+        copy_dir(source.path(), dest.path(), options).unwrap();
+
+        assert_eq!(fs::read_to_string(dest.path().join("config")).unwrap(),
+                   "setting = true\n");
+        assert_eq!(mode(&dest.path().join("config")), 0o600);
+        assert_ne!(mode(&dest.path().join("bin/run")), 0o755);
+        assert_ne!(mode(&dest.path().join("empty")), 0o710);
+    }
+
+    #[test]
+    fn mirroring_removes_what_is_not_in_the_source() {
+        let source = source_tree();
+        let root = TempDir::new().unwrap();
+        let dest_dir = root.path().join("pkg");
+        copy_dir(source.path(), &dest_dir, CopyDirOptions::default()).unwrap();
+        fs::write(dest_dir.join("stale"), "left over\n").unwrap();
+        fs::create_dir_all(dest_dir.join("bin/old/deeper")).unwrap();
+        fs::write(dest_dir.join("bin/old/deeper/file"), "left over\n").unwrap();
+        // A symlink out of the destination is removed, not followed
+        let outside = TempDir::new().unwrap();
+        fs::write(outside.path().join("precious"), "keep me\n").unwrap();
+        symlink(outside.path(), dest_dir.join("outside")).unwrap();
+
+        let summary = copy_dir(source.path(), &dest_dir, CopyDirOptions::default()).unwrap();
+        assert_eq!(summary.removed, 0);
+        assert!(dest_dir.join("stale").exists());
+
+        let options = CopyDirOptions { mirror: true,
+                                       mirror_root: Some(root.path().to_path_buf()),
+                                       ..Default::default() };
+        let summary = copy_dir(source.path(), &dest_dir, options).unwrap();
+
+        assert_eq!(summary.removed, 3);
+        assert!(!dest_dir.join("stale").exists());
+        assert!(!dest_dir.join("bin/old").exists());
+        assert!(fs::symlink_metadata(dest_dir.join("outside")).is_err());
+        assert!(outside.path().join("precious").exists());
+        assert!(dest_dir.join("bin/run").exists());
+        assert!(dest_dir.join("empty").exists());
+    }
+
+    #[test]
+    fn mirroring_must_stay_strictly_inside_its_root() {
+        let source = source_tree();
+        let root = TempDir::new().unwrap();
+        fs::write(root.path().join("precious"), "keep me\n").unwrap();
+        let mirror_into = |dest: &Path, mirror_root: Option<&Path>| {
+            let options = CopyDirOptions { mirror: true,
+                                           mirror_root: mirror_root.map(Path::to_path_buf),
+                                           ..Default::default() };
+            copy_dir(source.path(), dest, options)
+        };
+
+        assert!(mirror_into(&root.path().join("pkg"), None).is_err());
+        assert!(mirror_into(root.path(), Some(root.path())).is_err());
+        assert!(mirror_into(&root.path().join("pkg/../.."), Some(root.path())).is_err());
+        assert!(mirror_into(Path::new("/tmp/elsewhere"), Some(root.path())).is_err());
+        assert!(root.path().join("precious").exists());
+        assert!(mirror_into(&root.path().join("pkg"), Some(root.path())).is_ok());
+    }
+
+    #[tokio::test]
+    async fn copies_from_async_code() {
+        let source = source_tree();
+        let dest = TempDir::new().unwrap();
+
+        let summary =
+            copy_dir_async(source.path(), dest.path(), CopyDirOptions::default()).await
+                                                                                 .unwrap();
+        assert_eq!(summary.files, 2);
+        assert_eq!(mode(&dest.path().join("config")), 0o444);
+    }
+}
+
+#[cfg(test)]
+mod test_atomic_writer_async {
+    use super::{atomic_write_async,
+                AsyncWriteOp,
+                AtomicWriterAsync,
+                Permissions};
+    use std::{fs,
+              io,
+              path::Path};
+    use tempfile::TempDir;
+    use tokio::io::AsyncWriteExt;
+
+    /// The names of everything in `dir`.
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names =
+            fs::read_dir(dir).unwrap()
+                             .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+                             .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn atomic_write_async_writes_file() {
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("file");
+        atomic_write_async(&dest, "A very good file format").await
+                                                            .unwrap();
+        assert_eq!(fs::read_to_string(&dest).unwrap(),
+                   "A very good file format");
+        assert_eq!(entries(dir.path()), vec!["file"]);
+    }
+
+    fn write_then_fail(f: &mut tokio::fs::File) -> AsyncWriteOp<'_, (), io::Error> {
+        Box::pin(async move {
+            f.write_all(b"half of the new content").await?;
+            Err(io::Error::new(io::ErrorKind::Other, "boom"))
+        })
+    }
+
+    #[allow(unreachable_code)]
+    fn write_then_panic(f: &mut tokio::fs::File) -> AsyncWriteOp<'_, (), io::Error> {
+        Box::pin(async move {
+            f.write_all(b"half of the new content").await?;
+            panic!("Panicking mid-write");
+            Ok(())
+        })
+    }
+
+    #[tokio::test]
+    async fn errors_while_writing_leave_nothing_behind() {
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("file");
+        fs::write(&dest, "old content").unwrap();
+
+        let result = AtomicWriterAsync::new(&dest).with_writer(write_then_fail)
+                                                  .await;
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "old content");
+        assert_eq!(entries(dir.path()), vec!["file"]);
+    }
+
+    #[tokio::test]
+    async fn panics_while_writing_leave_nothing_behind() {
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("file");
+        let writer = AtomicWriterAsync::new(&dest);
+        let result = tokio::spawn(async move { writer.with_writer(write_then_panic).await }).await;
+        assert!(result.is_err());
+        assert!(entries(dir.path()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn concurrent_writers_never_mix_their_content() {
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("file");
+        let contents = (0..20).map(|n| n.to_string().repeat(10_000))
+                              .collect::<Vec<_>>();
+        let writers =
+            contents.iter()
+                    .cloned()
+                    .map(|content| {
+                        let dest = dest.clone();
+                        tokio::spawn(async move { atomic_write_async(&dest, content).await })
+                    })
+                    .collect::<Vec<_>>();
+        for writer in writers {
+            writer.await.unwrap().unwrap();
+        }
+
+        let written = fs::read_to_string(&dest).unwrap();
+        assert!(contents.contains(&written));
+        assert_eq!(entries(dir.path()), vec!["file"]);
+    }
+
+    #[tokio::test]
+    #[cfg(not(windows))]
+    async fn explicit_permissions_are_applied() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("file");
+        AtomicWriterAsync::new_with_permissions(&dest, Permissions::Explicit(0o400))
+            .with_writer(|f| Box::pin(async move { f.write_all(b"secret").await }))
+            .await
+            .unwrap();
+        assert_eq!(fs::metadata(&dest).unwrap().permissions().mode() & 0o777,
+                   0o400);
+    }
+}
+
 #[cfg(test)]
 mod test_find_command {
     pub use super::find_command;
@@ -1196,11 +2138,20 @@ mod test_find_command {
 #[cfg(test)]
 mod test_atomic_writer {
     use super::{atomic_write,
-                AtomicWriter};
-    use std::{fs::File,
+                cleanup_stale_tmp_files,
+                is_atomic_tmp_file_name,
+                AtomicWriter,
+                STALE_TMP_FILE_AGE};
+    use filetime::FileTime;
+    use std::{ffi::OsStr,
+              fs::{self,
+                   File},
               io::{Read,
                    Seek,
-                   Write}};
+                   Write},
+              path::Path,
+              time::{Duration,
+                     SystemTime}};
 
     const EXPECTED_CONTENT: &str = "A very good file format";
 
@@ -1243,4 +2194,152 @@ mod test_atomic_writer {
          .expect("failed to read file");
         assert_eq!(EXPECTED_CONTENT, actual_content);
     }
+
+    #[test]
+    #[cfg(unix)]
+    fn renames_across_filesystems_fall_back_to_copying() {
+        use super::rename_or_copy_unix;
+        use std::{fs,
+                  os::unix::fs::PermissionsExt,
+                  path::Path};
+
+        let elsewhere = tempfile::tempdir().expect("could not create temp dir");
+        let dest_dir = tempfile::tempdir().expect("could not create temp dir");
+        let from = elsewhere.path().join("staged");
+        let to = dest_dir.path().join("dest");
+        fs::write(&from, EXPECTED_CONTENT).unwrap();
+        fs::set_permissions(&from, fs::Permissions::from_mode(0o400)).unwrap();
+        fs::write(&to, "old content").unwrap();
+
+        // Pretend the two directories are on different filesystems.
+        let rename = |from: &Path, to: &Path| {
+            if from.parent() == to.parent() {
+                fs::rename(from, to)
+            } else {
+                Err(std::io::Error::from_raw_os_error(libc::EXDEV))
+            }
+        };
+        rename_or_copy_unix(&from, &to, rename).expect("could not rename");
+
+        assert_eq!(fs::read_to_string(&to).unwrap(), EXPECTED_CONTENT);
+        assert_eq!(fs::metadata(&to).unwrap().permissions().mode() & 0o777,
+                   0o400);
+        assert!(!from.exists());
+        assert_eq!(fs::read_dir(dest_dir.path()).unwrap().count(), 1);
+    }
+
+    fn tmp_files_in(dir: &Path) -> Vec<String> {
+        let mut names =
+            fs::read_dir(dir).unwrap()
+                             .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                             .filter(|name| is_atomic_tmp_file_name(OsStr::new(name)))
+                             .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    fn backdate(path: &Path, by: Duration) {
+        let then = FileTime::from_system_time(SystemTime::now() - by);
+        filetime::set_file_mtime(path, then).unwrap();
+    }
+
+    #[test]
+    fn concurrent_writers_get_distinct_tmp_files() {
+        let dir = tempfile::tempdir().expect("could not create temp dir");
+        let dest = dir.path().join("dest");
+        let (_, first) = AtomicWriter::new(&dest).unwrap()
+                                                 .stage(|f| f.write_all(b"first"))
+                                                 .unwrap();
+        let (_, second) = AtomicWriter::new(&dest).unwrap()
+                                                  .stage(|f| f.write_all(b"second"))
+                                                  .unwrap();
+
+        let names = tmp_files_in(dir.path());
+        assert_eq!(names.len(), 2, "{:?}", names);
+        let pid_prefix = format!(".hab-tmp-{}-", crate::os::process::current_pid());
+        assert!(names.iter().all(|name| name.starts_with(&pid_prefix)),
+                "{:?}",
+                names);
+
+        first.commit().unwrap();
+        second.commit().unwrap();
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "second");
+        assert!(tmp_files_in(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn only_the_exact_tmp_file_naming_is_recognized() {
+        for name in &[".hab-tmp-1234-aB3dE5gH7jK9", ".hab-tmp-1-000000000000"] {
+            assert!(is_atomic_tmp_file_name(OsStr::new(name)), "{}", name);
+        }
+        for name in &[".hab-tmp-1234-aB3dE5gH7jK",
+                      ".hab-tmp-1234-aB3dE5gH7jK9x",
+                      ".hab-tmp--aB3dE5gH7jK9",
+                      ".hab-tmp-12a4-aB3dE5gH7jK9",
+                      ".hab-tmp-1234-aB3dE5gH7j.9",
+                      ".hab-tmp-1234aB3dE5gH7jK9",
+                      "hab-tmp-1234-aB3dE5gH7jK9",
+                      "x.hab-tmp-1234-aB3dE5gH7jK9",
+                      ".tmpaB3dE5"]
+        {
+            assert!(!is_atomic_tmp_file_name(OsStr::new(name)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn stale_tmp_files_from_interrupted_writes_are_cleaned_up() {
+        let dir = tempfile::tempdir().expect("could not create temp dir");
+        let dest = dir.path().join("dest");
+        fs::write(&dest, EXPECTED_CONTENT).unwrap();
+
+        // A write that's interrupted, as if its process were killed,
+        // leaves its temporary file behind...
+        let (_, interrupted) = AtomicWriter::new(&dest).unwrap()
+                                                       .stage(|f| f.write_all(b"interrupted"))
+                                                       .unwrap();
+        std::mem::forget(interrupted);
+        let orphan = dir.path().join(&tmp_files_in(dir.path())[0]);
+        backdate(&orphan, STALE_TMP_FILE_AGE * 2);
+
+        // ...while another is still in progress
+        let (_, in_progress) = AtomicWriter::new(&dest).unwrap()
+                                                       .stage(|f| f.write_all(b"in progress"))
+                                                       .unwrap();
+
+        // Old files that only look like temporary files are left alone
+        let lookalikes = [".hab-tmp-notes",
+                          ".hab-tmp-1234-short",
+                          ".tmpaB3dE5",
+                          "dest.bak"];
+        for name in &lookalikes {
+            let path = dir.path().join(name);
+            fs::write(&path, "keep me").unwrap();
+            backdate(&path, STALE_TMP_FILE_AGE * 2);
+        }
+        // As is a directory named like one
+        let tmp_dir = dir.path().join(".hab-tmp-1234-aB3dE5gH7jK9");
+        fs::create_dir(&tmp_dir).unwrap();
+        backdate(&tmp_dir, STALE_TMP_FILE_AGE * 2);
+
+        assert_eq!(cleanup_stale_tmp_files(dir.path(), STALE_TMP_FILE_AGE).unwrap(),
+                   1);
+        assert!(!orphan.exists());
+        for name in &lookalikes {
+            assert!(dir.path().join(name).exists(), "{} was removed", name);
+        }
+        assert!(tmp_dir.is_dir());
+
+        in_progress.commit().unwrap();
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "in progress");
+        assert_eq!(cleanup_stale_tmp_files(dir.path(), STALE_TMP_FILE_AGE).unwrap(),
+                   0);
+    }
+
+    #[test]
+    fn cleaning_up_a_missing_directory_removes_nothing() {
+        let dir = tempfile::tempdir().expect("could not create temp dir");
+        let missing = dir.path().join("missing");
+        assert_eq!(cleanup_stale_tmp_files(&missing, STALE_TMP_FILE_AGE).unwrap(),
+                   0);
+    }
 }
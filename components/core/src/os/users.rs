@@ -4,6 +4,8 @@ mod windows;
 #[cfg(windows)]
 use windows as implementation;
 
+#[cfg(unix)]
+mod cache;
 #[cfg(unix)]
 mod unix;
 #[cfg(unix)]
@@ -16,11 +18,14 @@ pub use implementation::{assert_pkg_user_and_group,
                          get_effective_uid,
                          get_gid_by_name,
                          get_home_for_user,
-                         get_uid_by_name};
+                         get_uid_by_name,
+                         invalidate_cache};
 
 // Unix-specific functions
 #[cfg(unix)]
 pub use unix::{get_effective_gid,
                get_effective_groupname,
                get_effective_username,
-               get_members_by_groupname};
+               get_groupname_by_gid,
+               get_members_by_groupname,
+               get_username_by_uid};
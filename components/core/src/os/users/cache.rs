@@ -0,0 +1,271 @@
+use crate::error::Result;
+use std::{collections::HashMap,
+          path::PathBuf,
+          sync::{Mutex,
+                 MutexGuard}};
+
+/// What we keep of a user's entry in the user database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct UserEntry {
+    pub name: String,
+    pub uid:  u32,
+    pub home: PathBuf,
+}
+
+/// What we keep of a group's entry in the group database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct GroupEntry {
+    pub name: String,
+    pub gid:  u32,
+}
+
+/// Where a `Cache` gets the entries it doesn't have yet. A lookup for
+/// something that doesn't exist is `Ok(None)`; `Err` is kept for the
+/// lookup itself failing.
+pub(super) trait Resolver {
+    fn user_by_name(&self, name: &str) -> Result<Option<UserEntry>>;
+    fn user_by_uid(&self, uid: u32) -> Result<Option<UserEntry>>;
+    fn group_by_name(&self, name: &str) -> Result<Option<GroupEntry>>;
+    fn group_by_gid(&self, gid: u32) -> Result<Option<GroupEntry>>;
+}
+
+/// A read-through, in-memory cache of user and group entries, for
+/// hosts where asking the system (e.g. through sssd or LDAP) is slow.
+///
+/// Entries are looked up the first time they're asked for, by name or
+/// by id, and served from memory after that, whichever way they're
+/// asked for next. Failed lookups, and lookups for users or groups
+/// that don't exist, aren't remembered, so a user created later is
+/// found. However, changes to a user or group that has already been
+/// looked up aren't noticed until `invalidate` is called.
+///
+/// The lock isn't held while the `Resolver` is asked, so one slow
+/// lookup doesn't hold up others; two threads asking for the same
+/// uncached entry at once may both resolve it.
+#[derive(Debug)]
+pub(super) struct Cache<R> {
+    resolver: R,
+    inner:    Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    users_by_name:  HashMap<String, UserEntry>,
+    users_by_uid:   HashMap<u32, UserEntry>,
+    groups_by_name: HashMap<String, GroupEntry>,
+    groups_by_gid:  HashMap<u32, GroupEntry>,
+}
+
+impl Inner {
+    fn insert_user(&mut self, user: UserEntry) {
+        self.users_by_uid.insert(user.uid, user.clone());
+        self.users_by_name.insert(user.name.clone(), user);
+    }
+
+    fn insert_group(&mut self, group: GroupEntry) {
+        self.groups_by_gid.insert(group.gid, group.clone());
+        self.groups_by_name.insert(group.name.clone(), group);
+    }
+}
+
+impl<R: Resolver> Cache<R> {
+    pub fn new(resolver: R) -> Self {
+        Cache { resolver,
+                inner: Mutex::new(Inner::default()) }
+    }
+
+    /// Forget everything looked up so far, so that subsequent lookups
+    /// go back to the `Resolver`.
+    pub fn invalidate(&self) { *self.inner() = Inner::default(); }
+
+    pub fn user_by_name(&self, name: &str) -> Result<Option<UserEntry>> {
+        self.read_through(|inner| inner.users_by_name.get(name).cloned(),
+                          |resolver| resolver.user_by_name(name),
+                          Inner::insert_user)
+    }
+
+    pub fn user_by_uid(&self, uid: u32) -> Result<Option<UserEntry>> {
+        self.read_through(|inner| inner.users_by_uid.get(&uid).cloned(),
+                          |resolver| resolver.user_by_uid(uid),
+                          Inner::insert_user)
+    }
+
+    pub fn group_by_name(&self, name: &str) -> Result<Option<GroupEntry>> {
+        self.read_through(|inner| inner.groups_by_name.get(name).cloned(),
+                          |resolver| resolver.group_by_name(name),
+                          Inner::insert_group)
+    }
+
+    pub fn group_by_gid(&self, gid: u32) -> Result<Option<GroupEntry>> {
+        self.read_through(|inner| inner.groups_by_gid.get(&gid).cloned(),
+                          |resolver| resolver.group_by_gid(gid),
+                          Inner::insert_group)
+    }
+
+    fn read_through<T>(&self,
+                       cached: impl FnOnce(&Inner) -> Option<T>,
+                       resolve: impl FnOnce(&R) -> Result<Option<T>>,
+                       insert: impl FnOnce(&mut Inner, T))
+                       -> Result<Option<T>>
+        where T: Clone
+    {
+        if let Some(entry) = cached(&self.inner()) {
+            return Ok(Some(entry));
+        }
+        let entry = resolve(&self.resolver)?;
+        if let Some(ref entry) = entry {
+            insert(&mut self.inner(), entry.clone());
+        }
+        Ok(entry)
+    }
+
+    fn inner(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().expect("users Cache lock poisoned")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::Error;
+    use std::{sync::{atomic::{AtomicUsize,
+                              Ordering},
+                     Arc},
+              thread};
+
+    /// Answers from its own list of users and groups, counting how
+    /// often it's asked.
+    #[derive(Default)]
+    struct FakeResolver {
+        users:   Mutex<Vec<UserEntry>>,
+        groups:  Mutex<Vec<GroupEntry>>,
+        failing: Mutex<bool>,
+        calls:   AtomicUsize,
+    }
+
+    impl FakeResolver {
+        fn with_user(self, name: &str, uid: u32) -> Self {
+            self.add_user(name, uid);
+            self
+        }
+
+        fn with_group(self, name: &str, gid: u32) -> Self {
+            self.groups
+                .lock()
+                .unwrap()
+                .push(GroupEntry { name: name.to_string(),
+                                   gid });
+            self
+        }
+
+        fn add_user(&self, name: &str, uid: u32) {
+            self.users
+                .lock()
+                .unwrap()
+                .push(UserEntry { name: name.to_string(),
+                                  uid,
+                                  home: PathBuf::from("/home").join(name) });
+        }
+
+        fn set_failing(&self, failing: bool) { *self.failing.lock().unwrap() = failing; }
+
+        fn calls(&self) -> usize { self.calls.load(Ordering::SeqCst) }
+
+        fn find<T: Clone>(&self,
+                          entries: &Mutex<Vec<T>>,
+                          matches: impl Fn(&T) -> bool)
+                          -> Result<Option<T>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if *self.failing.lock().unwrap() {
+                return Err(Error::PermissionFailed(String::from("directory unreachable")));
+            }
+            Ok(entries.lock().unwrap().iter().find(|e| matches(e)).cloned())
+        }
+    }
+
+    impl Resolver for FakeResolver {
+        fn user_by_name(&self, name: &str) -> Result<Option<UserEntry>> {
+            self.find(&self.users, |u| u.name == name)
+        }
+
+        fn user_by_uid(&self, uid: u32) -> Result<Option<UserEntry>> {
+            self.find(&self.users, |u| u.uid == uid)
+        }
+
+        fn group_by_name(&self, name: &str) -> Result<Option<GroupEntry>> {
+            self.find(&self.groups, |g| g.name == name)
+        }
+
+        fn group_by_gid(&self, gid: u32) -> Result<Option<GroupEntry>> {
+            self.find(&self.groups, |g| g.gid == gid)
+        }
+    }
+
+    #[test]
+    fn entries_are_resolved_only_once_by_name_or_id() {
+        let cache = Cache::new(FakeResolver::default().with_user("hab", 42)
+                                                      .with_group("hab", 43));
+
+        assert_eq!(cache.user_by_name("hab").unwrap().unwrap().uid, 42);
+        assert_eq!(cache.user_by_name("hab").unwrap().unwrap().uid, 42);
+        assert_eq!(cache.user_by_uid(42).unwrap().unwrap().name, "hab");
+        assert_eq!(cache.resolver.calls(), 1);
+
+        assert_eq!(cache.group_by_gid(43).unwrap().unwrap().name, "hab");
+        assert_eq!(cache.group_by_name("hab").unwrap().unwrap().gid, 43);
+        assert_eq!(cache.resolver.calls(), 2);
+    }
+
+    #[test]
+    fn missing_entries_are_not_remembered() {
+        let cache = Cache::new(FakeResolver::default());
+
+        assert_eq!(cache.user_by_name("hab").unwrap(), None);
+        cache.resolver.add_user("hab", 42);
+        assert_eq!(cache.user_by_name("hab").unwrap().unwrap().uid, 42);
+        assert_eq!(cache.resolver.calls(), 2);
+    }
+
+    #[test]
+    fn failed_lookups_are_errors_and_are_not_remembered() {
+        let cache = Cache::new(FakeResolver::default().with_user("hab", 42));
+
+        cache.resolver.set_failing(true);
+        assert!(cache.user_by_name("hab").is_err());
+        assert!(cache.group_by_gid(43).is_err());
+
+        cache.resolver.set_failing(false);
+        assert_eq!(cache.user_by_name("hab").unwrap().unwrap().uid, 42);
+        assert_eq!(cache.resolver.calls(), 3);
+    }
+
+    #[test]
+    fn invalidate_forgets_everything() {
+        let cache = Cache::new(FakeResolver::default().with_user("hab", 42));
+
+        assert_eq!(cache.user_by_name("hab").unwrap().unwrap().uid, 42);
+        cache.resolver.users.lock().unwrap().clear();
+        cache.resolver.add_user("hab", 1042);
+        assert_eq!(cache.user_by_name("hab").unwrap().unwrap().uid, 42);
+
+        cache.invalidate();
+        assert_eq!(cache.user_by_name("hab").unwrap().unwrap().uid, 1042);
+        assert_eq!(cache.user_by_uid(42).unwrap(), None);
+    }
+
+    #[test]
+    fn lookups_can_be_made_from_many_threads() {
+        let cache = Arc::new(Cache::new(FakeResolver::default().with_user("hab", 42)));
+        cache.user_by_name("hab").unwrap();
+
+        let handles = (0..8).map(|_| {
+                                let cache = Arc::clone(&cache);
+                                thread::spawn(move || cache.user_by_uid(42).unwrap().unwrap())
+                            })
+                            .collect::<Vec<_>>();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap().name, "hab");
+        }
+        assert_eq!(cache.resolver.calls(), 1);
+    }
+}
@@ -1,35 +1,95 @@
+use super::cache::{Cache,
+                   GroupEntry,
+                   Resolver,
+                   UserEntry};
 use crate::error::{Error,
                    Result};
-use nix::unistd::{Group,
+use nix::unistd::{Gid,
+                  Group,
+                  Uid,
                   User};
 use std::path::PathBuf;
 
+lazy_static::lazy_static! {
+    static ref CACHE: Cache<SystemResolver> = Cache::new(SystemResolver);
+}
+
+/// Looks entries up in the system's user and group databases.
+#[derive(Debug)]
+struct SystemResolver;
+
+impl From<User> for UserEntry {
+    fn from(user: User) -> Self {
+        UserEntry { name: user.name,
+                    uid:  user.uid.as_raw(),
+                    home: user.dir, }
+    }
+}
+
+impl From<Group> for GroupEntry {
+    fn from(group: Group) -> Self {
+        GroupEntry { name: group.name,
+                     gid:  group.gid.as_raw(), }
+    }
+}
+
+impl Resolver for SystemResolver {
+    fn user_by_name(&self, name: &str) -> Result<Option<UserEntry>> {
+        Ok(User::from_name(name)?.map(UserEntry::from))
+    }
+
+    fn user_by_uid(&self, uid: u32) -> Result<Option<UserEntry>> {
+        Ok(User::from_uid(Uid::from_raw(uid))?.map(UserEntry::from))
+    }
+
+    fn group_by_name(&self, name: &str) -> Result<Option<GroupEntry>> {
+        Ok(Group::from_name(name)?.map(GroupEntry::from))
+    }
+
+    fn group_by_gid(&self, gid: u32) -> Result<Option<GroupEntry>> {
+        Ok(Group::from_gid(Gid::from_raw(gid))?.map(GroupEntry::from))
+    }
+}
+
+/// Forget the users and groups looked up so far. Lookups are cached
+/// for the life of the process, so call this after changing a user or
+/// group that may already have been looked up. Users and groups that
+/// weren't found are never cached.
+pub fn invalidate_cache() { CACHE.invalidate(); }
+
 pub fn get_uid_by_name(owner: &str) -> Result<Option<u32>> {
-    Ok(User::from_name(owner)?.map(|u| u.uid.as_raw()))
+    Ok(CACHE.user_by_name(owner)?.map(|u| u.uid))
 }
 
 pub fn get_gid_by_name(group: &str) -> Result<Option<u32>> {
-    Ok(Group::from_name(group)?.map(|g| g.gid.as_raw()))
+    Ok(CACHE.group_by_name(group)?.map(|g| g.gid))
+}
+
+pub fn get_username_by_uid(uid: u32) -> Result<Option<String>> {
+    Ok(CACHE.user_by_uid(uid)?.map(|u| u.name))
+}
+
+pub fn get_groupname_by_gid(gid: u32) -> Result<Option<String>> {
+    Ok(CACHE.group_by_gid(gid)?.map(|g| g.name))
 }
 
 /// Any members that fail conversion from OsString to string will be omitted
+///
+/// Group membership isn't cached, so this always asks the system.
 pub fn get_members_by_groupname(group: &str) -> Result<Option<Vec<String>>> {
     Ok(Group::from_name(group)?.map(|g| g.mem))
 }
 
 pub fn get_current_username() -> Result<Option<String>> {
-    let uid = nix::unistd::getuid();
-    Ok(User::from_uid(uid)?.map(|u| u.name))
+    get_username_by_uid(nix::unistd::getuid().as_raw())
 }
 
 pub fn get_current_groupname() -> Result<Option<String>> {
-    let gid = nix::unistd::getgid();
-    Ok(Group::from_gid(gid)?.map(|g| g.name))
+    get_groupname_by_gid(nix::unistd::getgid().as_raw())
 }
 
 pub fn get_effective_username() -> Result<Option<String>> {
-    let euid = nix::unistd::geteuid();
-    Ok(User::from_uid(euid)?.map(|u| u.name))
+    get_username_by_uid(get_effective_uid())
 }
 
 pub fn get_effective_uid() -> u32 { nix::unistd::geteuid().as_raw() }
@@ -37,12 +97,11 @@ pub fn get_effective_uid() -> u32 { nix::unistd::geteuid().as_raw() }
 pub fn get_effective_gid() -> u32 { nix::unistd::getegid().as_raw() }
 
 pub fn get_effective_groupname() -> Result<Option<String>> {
-    let egid = nix::unistd::getegid();
-    Ok(Group::from_gid(egid)?.map(|g| g.name))
+    get_groupname_by_gid(get_effective_gid())
 }
 
 pub fn get_home_for_user(username: &str) -> Result<Option<PathBuf>> {
-    Ok(User::from_name(username)?.map(|u| u.dir))
+    Ok(CACHE.user_by_name(username)?.map(|u| u.home))
 }
 
 /// This function checks to see if a user and group and if:
@@ -84,3 +143,30 @@ pub fn assert_pkg_user_and_group(user: &str, group: &str) -> Result<()> {
         Err(Error::PermissionFailed(msg))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups_by_name_and_id_agree() {
+        let username = get_effective_username().unwrap()
+                                               .expect("Effective user has no name");
+        assert_eq!(get_uid_by_name(&username).unwrap(),
+                   Some(get_effective_uid()));
+        assert_eq!(get_username_by_uid(get_effective_uid()).unwrap(),
+                   Some(username.clone()));
+        assert!(get_home_for_user(&username).unwrap().is_some());
+
+        invalidate_cache();
+        assert_eq!(get_uid_by_name(&username).unwrap(),
+                   Some(get_effective_uid()));
+    }
+
+    #[test]
+    fn missing_users_and_groups_are_not_errors() {
+        assert_eq!(get_uid_by_name("no-such-hab-user").unwrap(), None);
+        assert_eq!(get_gid_by_name("no-such-hab-group").unwrap(), None);
+        assert_eq!(get_home_for_user("no-such-hab-user").unwrap(), None);
+    }
+}
@@ -37,10 +37,13 @@ pub fn get_current_groupname() -> Result<Option<String>> { Ok(Some(String::new()
 
 pub fn get_effective_uid() -> u32 { unsafe { GetUserTokenStatus() } }
 
-pub fn get_home_for_user(username: &str) -> Option<PathBuf> {
+pub fn get_home_for_user(username: &str) -> Result<Option<PathBuf>> {
     unimplemented!();
 }
 
+/// Lookups aren't cached on Windows, so there's nothing to forget.
+pub fn invalidate_cache() {}
+
 /// Windows does not have a concept of "group" in a Linux sense
 /// So we just validate the user
 pub fn assert_pkg_user_and_group(user: &str, _group: &str) -> Result<()> {
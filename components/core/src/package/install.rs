@@ -1,6 +1,7 @@
 #[cfg(test)]
 use super::PackageTarget;
-use super::{list::package_list_for_ident,
+use super::{ident::version_sort,
+            list::package_list_for_ident,
             metadata::{parse_key_value,
                        read_metafile,
                        Bind,
@@ -8,7 +9,9 @@ use super::{list::package_list_for_ident,
                        PackageType},
             Identifiable,
             PackageIdent};
-use crate::{error::{Error,
+use crate::{crypto::{keys::NamedRevision,
+                     Blake2bHash},
+            error::{Error,
                     Result},
             fs,
             os::process::{ShutdownSignal,
@@ -75,6 +78,51 @@ impl PackageInstall {
         Ok(package_install)
     }
 
+    /// Load the newest installed package matching `ident`, which may
+    /// be as partial as `origin/name`. Versions are compared the way
+    /// Habitat orders them everywhere else, so `1.10.0` is newer than
+    /// `1.9.0`, and `1.0.0` is newer than `1.0.0-rc1`; releases break
+    /// ties.
+    ///
+    /// Directories whose names aren't a well-formed version or release
+    /// are passed over. If nothing is left, the error is
+    /// `Error::PackageNotInstalled`.
+    ///
+    /// An optional `fs_root` path may be provided to search for a package that is mounted on a
+    /// filesystem not currently rooted at `/`.
+    pub fn load_newest(ident: &PackageIdent,
+                       fs_root_path: Option<&Path>)
+                       -> Result<PackageInstall> {
+        let fs_root_path = fs_root_path.map_or_else(|| PathBuf::from("/"), PathBuf::from);
+        let package_root_path = fs::pkg_root_path(Some(&fs_root_path));
+        let not_installed = || Error::PackageNotInstalled(Box::new(ident.clone()));
+        if !package_root_path.is_dir() {
+            return Err(not_installed());
+        }
+
+        let candidates = package_list_for_ident(&package_root_path, ident)?;
+        let newest = candidates.into_iter()
+                               .filter(|p| p.satisfies(ident) && Self::is_well_formed(p))
+                               .max_by(|a, b| a.cmp(b))
+                               .ok_or_else(not_installed)?;
+        Ok(PackageInstall { installed_path: fs::pkg_install_path(&newest, Some(&fs_root_path)),
+                            fs_root_path,
+                            package_root_path,
+                            ident: newest })
+    }
+
+    /// Whether `ident` has a version and release that can be ordered.
+    fn is_well_formed(ident: &PackageIdent) -> bool {
+        let version_ok = ident.version
+                              .as_ref()
+                              .map_or(false, |v| version_sort(v, v).is_ok());
+        let release_ok =
+            ident.release.as_ref().map_or(false, |r| {
+                                      !r.is_empty() && r.bytes().all(|b| b.is_ascii_digit())
+                                  });
+        version_ok && release_ok
+    }
+
     fn resolve_package_install<T>(ident: &PackageIdent,
                                   fs_root_path: Option<T>)
                                   -> Result<PackageInstall>
@@ -325,6 +373,58 @@ impl PackageInstall {
     /// Return all transitive dependencies of the package
     pub fn tdeps(&self) -> Result<Vec<PackageIdent>> { self.read_deps(MetaFile::TDeps) }
 
+    /// Return all transitive dependencies of the package, found by
+    /// walking the `DEPS` of each installed dependency rather than
+    /// trusting the `TDEPS` metafile. Dependencies are listed in the
+    /// order they're first reached, depth first.
+    ///
+    /// # Failures
+    ///
+    /// * `Error::PackageDependencyCycle`, with the cycle, if the dependencies depend on each other
+    ///   in a cycle
+    /// * `Error::PackageDependencyNotFound`, with the chain of dependencies leading to it, if a
+    ///   dependency isn't installed
+    pub fn tdeps_checked(&self) -> Result<Vec<PackageIdent>> {
+        let mut tdeps = Vec::new();
+        let mut seen = HashSet::new();
+        let mut chain = vec![self.ident.clone()];
+        self.walk_deps(&mut chain, &mut seen, &mut tdeps)?;
+        Ok(tdeps)
+    }
+
+    /// Add the dependencies of this package, the last in `chain`, and
+    /// theirs in turn, to `tdeps`, skipping any already `seen`.
+    fn walk_deps(&self,
+                 chain: &mut Vec<PackageIdent>,
+                 seen: &mut HashSet<PackageIdent>,
+                 tdeps: &mut Vec<PackageIdent>)
+                 -> Result<()> {
+        for dep in self.deps()? {
+            // Anything still in the chain is still having its own
+            // dependencies walked, so getting back to it is a cycle.
+            if let Some(start) = chain.iter().position(|ident| *ident == dep) {
+                let mut cycle = chain[start..].to_vec();
+                cycle.push(dep);
+                return Err(Error::PackageDependencyCycle(cycle));
+            }
+            if !seen.insert(dep.clone()) {
+                continue;
+            }
+            chain.push(dep.clone());
+            let dep_install = match Self::load(&dep, Some(&*self.fs_root_path)) {
+                Ok(dep_install) => dep_install,
+                Err(Error::PackageNotFound(_)) => {
+                    return Err(Error::PackageDependencyNotFound(chain.clone()));
+                }
+                Err(e) => return Err(e),
+            };
+            tdeps.push(dep);
+            dep_install.walk_deps(chain, seen, tdeps)?;
+            chain.pop();
+        }
+        Ok(())
+    }
+
     /// Return all build dependencies of the package
     pub fn build_deps(&self) -> Result<Vec<PackageIdent>> { self.read_deps(MetaFile::BuildDeps) }
 
@@ -590,6 +690,28 @@ impl PackageInstall {
         }
     }
 
+    /// Returns the `NamedRevision` of the origin key that signed the
+    /// artifact this package was installed from, or None if the
+    /// package was installed before this was recorded.
+    pub fn signer(&self) -> Result<Option<NamedRevision>> {
+        match self.read_metafile(MetaFile::Signer) {
+            Ok(body) => Ok(Some(body.parse()?)),
+            Err(Error::MetaFileNotFound(MetaFile::Signer)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the verified hash of the artifact this package was
+    /// installed from, or None if the package was installed before
+    /// this was recorded.
+    pub fn artifact_hash(&self) -> Result<Option<Blake2bHash>> {
+        match self.read_metafile(MetaFile::ArtifactHash) {
+            Ok(body) => Ok(Some(body.parse()?)),
+            Err(Error::MetaFileNotFound(MetaFile::ArtifactHash)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
     /// Read the contents of a given metafile.
     ///
     /// # Failures
@@ -1170,6 +1292,120 @@ mod test {
                    pkg_install.runtime_paths().unwrap());
     }
 
+    #[test]
+    fn load_newest_orders_versions_properly() {
+        let fs_root = Builder::new().prefix("fs-root").tempdir().unwrap();
+        for ident in &["acme/app/1.9.0/20200101000000",
+                       "acme/app/1.9.0/20200202000000",
+                       "acme/app/1.10.0-rc1/20210101000000",
+                       "acme/app/1.10.0/20190101000000"]
+        {
+            testing_package_install(ident, fs_root.path());
+        }
+
+        let newest = PackageInstall::load_newest(&"acme/app".parse().unwrap(),
+                                                 Some(fs_root.path())).unwrap();
+        assert_eq!(newest.ident().to_string(), "acme/app/1.10.0/20190101000000");
+
+        let newest = PackageInstall::load_newest(&"acme/app/1.9.0".parse().unwrap(),
+                                                 Some(fs_root.path())).unwrap();
+        assert_eq!(newest.ident().to_string(), "acme/app/1.9.0/20200202000000");
+    }
+
+    #[test]
+    fn load_newest_ignores_malformed_directories() {
+        let fs_root = Builder::new().prefix("fs-root").tempdir().unwrap();
+        testing_package_install("acme/app/1.0.0/20200101000000", fs_root.path());
+        testing_package_install("acme/app/not-a-version/20210101000000", fs_root.path());
+        testing_package_install("acme/app/2.0.0/not-a-release", fs_root.path());
+
+        let newest = PackageInstall::load_newest(&"acme/app".parse().unwrap(),
+                                                 Some(fs_root.path())).unwrap();
+        assert_eq!(newest.ident().to_string(), "acme/app/1.0.0/20200101000000");
+    }
+
+    #[test]
+    fn load_newest_with_nothing_installed() {
+        let fs_root = Builder::new().prefix("fs-root").tempdir().unwrap();
+        let ident: PackageIdent = "acme/app".parse().unwrap();
+        match PackageInstall::load_newest(&ident, Some(fs_root.path())) {
+            Err(Error::PackageNotInstalled(i)) => assert_eq!(*i, ident),
+            other => panic!("Expected PackageNotInstalled, got {:?}", other),
+        }
+
+        testing_package_install("acme/other", fs_root.path());
+        testing_package_install("acme/app/not-a-version/20210101000000", fs_root.path());
+        match PackageInstall::load_newest(&ident, Some(fs_root.path())) {
+            Err(Error::PackageNotInstalled(i)) => assert_eq!(*i, ident),
+            other => panic!("Expected PackageNotInstalled, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn tdeps_checked_walks_every_dependency_once() {
+        let fs_root = Builder::new().prefix("fs-root").tempdir().unwrap();
+
+        let charlie = testing_package_install("acme/charlie", fs_root.path());
+        let bravo = testing_package_install("acme/bravo", fs_root.path());
+        set_deps_for(&bravo, &[&charlie]);
+        let alpha = testing_package_install("acme/alpha", fs_root.path());
+        set_deps_for(&alpha, &[&bravo, &charlie]);
+
+        assert_eq!(alpha.tdeps_checked().unwrap(),
+                   vec![bravo.ident().clone(), charlie.ident().clone()]);
+        assert!(charlie.tdeps_checked().unwrap().is_empty());
+    }
+
+    #[test]
+    fn tdeps_checked_reports_dependency_cycles() {
+        let fs_root = Builder::new().prefix("fs-root").tempdir().unwrap();
+
+        let alpha = testing_package_install("acme/alpha", fs_root.path());
+        let bravo = testing_package_install("acme/bravo", fs_root.path());
+        let charlie = testing_package_install("acme/charlie", fs_root.path());
+        set_deps_for(&alpha, &[&bravo]);
+        set_deps_for(&bravo, &[&charlie]);
+        set_deps_for(&charlie, &[&bravo]);
+
+        match alpha.tdeps_checked() {
+            Err(Error::PackageDependencyCycle(cycle)) => {
+                assert_eq!(cycle,
+                           vec![bravo.ident().clone(),
+                                charlie.ident().clone(),
+                                bravo.ident().clone()]);
+            }
+            other => panic!("Expected PackageDependencyCycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn tdeps_checked_reports_the_chain_to_a_missing_dependency() {
+        let fs_root = Builder::new().prefix("fs-root").tempdir().unwrap();
+
+        let alpha = testing_package_install("acme/alpha", fs_root.path());
+        let bravo = testing_package_install("acme/bravo", fs_root.path());
+        set_deps_for(&alpha, &[&bravo]);
+        let missing = PackageIdent::from_str("acme/missing/1.0.0/20200227153400").unwrap();
+        write_metafile(&bravo, MetaFile::Deps, &format!("{}\n", missing));
+
+        let err = alpha.tdeps_checked().unwrap_err();
+        match err {
+            Error::PackageDependencyNotFound(ref chain) => {
+                assert_eq!(chain,
+                           &vec![alpha.ident().clone(),
+                                 bravo.ident().clone(),
+                                 missing.clone()]);
+            }
+            ref other => panic!("Expected PackageDependencyNotFound, got {:?}", other),
+        }
+        assert_eq!(err.to_string(),
+                   format!("Cannot find package dependency: {} depends on {} depends on {}, \
+                            which is not installed",
+                           alpha.ident(),
+                           bravo.ident(),
+                           missing));
+    }
+
     // This test uses the legacy/fallback implementation of determining the runtime path
     #[test]
     fn runtime_paths_metafile_missing_with_path_metafiles() {
@@ -1349,4 +1585,26 @@ mod test {
 
         assert_eq!(expected, pkg_install.environment_for_command().unwrap());
     }
+
+    #[test]
+    fn signer_metadata_is_absent_for_legacy_installs() {
+        let fs_root = Builder::new().prefix("fs-root").tempdir().unwrap();
+        let pkg_install = testing_package_install("acme/legacy", fs_root.path());
+
+        assert!(pkg_install.signer().unwrap().is_none());
+        assert!(pkg_install.artifact_hash().unwrap().is_none());
+    }
+
+    #[test]
+    fn signer_metadata_round_trips() {
+        let fs_root = Builder::new().prefix("fs-root").tempdir().unwrap();
+        let pkg_install = testing_package_install("acme/signed", fs_root.path());
+        let hash = Blake2bHash::from_bytes("signed content");
+        write_metafile(&pkg_install, MetaFile::Signer, "acme-20160810182414");
+        write_metafile(&pkg_install, MetaFile::ArtifactHash, &hash.to_string());
+
+        assert_eq!(pkg_install.signer().unwrap().unwrap().to_string(),
+                   "acme-20160810182414");
+        assert_eq!(pkg_install.artifact_hash().unwrap().unwrap(), hash);
+    }
 }
@@ -107,6 +107,8 @@ impl IntoIterator for PkgEnv {
 
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub enum MetaFile {
+    /// Written at install time; not present in the artifact itself.
+    ArtifactHash,
     Binds,
     BindsOptional,
     BuildDeps,
@@ -128,6 +130,8 @@ pub enum MetaFile {
     RuntimePath,
     ShutdownSignal,
     ShutdownTimeout,
+    /// Written at install time; not present in the artifact itself.
+    Signer,
     SvcGroup,
     SvcUser,
     Target,
@@ -138,6 +142,7 @@ pub enum MetaFile {
 impl fmt::Display for MetaFile {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let id = match *self {
+            MetaFile::ArtifactHash => "ARTIFACT_HASH",
             MetaFile::Binds => "BINDS",
             MetaFile::BindsOptional => "BINDS_OPTIONAL",
             MetaFile::BuildDeps => "BUILD_DEPS",
@@ -159,6 +164,7 @@ impl fmt::Display for MetaFile {
             MetaFile::RuntimePath => "RUNTIME_PATH",
             MetaFile::ShutdownSignal => "SHUTDOWN_SIGNAL",
             MetaFile::ShutdownTimeout => "SHUTDOWN_TIMEOUT",
+            MetaFile::Signer => "SIGNER",
             MetaFile::SvcGroup => "SVC_GROUP",
             MetaFile::SvcUser => "SVC_USER",
             MetaFile::Target => "TARGET",
@@ -190,6 +196,16 @@ pub fn read_metafile<P: AsRef<Path>>(installed_path: P, file: MetaFile) -> Resul
     }
 }
 
+/// Write a metadata file into a package directory, replacing any
+/// existing one.
+pub fn write_metafile<P: AsRef<Path>>(installed_path: P,
+                                      file: MetaFile,
+                                      content: &str)
+                                      -> Result<()> {
+    let filepath = installed_path.as_ref().join(file.to_string());
+    std::fs::write(filepath, content).map_err(Error::MetaFileIO)
+}
+
 /// Returns the path to a specified MetaFile in an installed path if it exists.
 ///
 /// Useful for fallback logic for dealing with older Habitat packages.
@@ -3,7 +3,8 @@ use crate::error::{Error,
 use regex::Regex;
 use serde::{Deserialize,
             Serialize};
-use std::{fmt,
+use std::{convert::TryFrom,
+          fmt,
           num::ParseIntError,
           ops::{Deref,
                 DerefMut},
@@ -251,6 +252,104 @@ impl FromStr for ServiceGroup {
     }
 }
 
+/// Identifies a service group within an organization, in
+/// `service.group@org` form. Service encryption keys are named for
+/// these.
+///
+/// Unlike a `ServiceGroup`, the organization is required, and each
+/// part may only contain ASCII letters and digits, `-`, and `_`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ServiceGroupIdent {
+    service: String,
+    group:   String,
+    org:     String,
+}
+
+impl ServiceGroupIdent {
+    pub fn new<S1, S2, S3>(service: S1, group: S2, org: S3) -> Result<Self>
+        where S1: Into<String>,
+              S2: Into<String>,
+              S3: Into<String>
+    {
+        let ident = ServiceGroupIdent { service: service.into(),
+                                        group:   group.into(),
+                                        org:     org.into(), };
+        let as_given = ident.to_string();
+        Self::validate_part(&as_given, "service", &ident.service)?;
+        Self::validate_part(&as_given, "group", &ident.group)?;
+        Self::validate_part(&as_given, "org", &ident.org)?;
+        Ok(ident)
+    }
+
+    /// Combine an org with a `service.group` name given separately,
+    /// like `"redis.default"`.
+    pub fn from_org_and_service_group(org: &str, service_group: &str) -> Result<Self> {
+        format!("{}@{}", service_group, org).parse()
+    }
+
+    pub fn service(&self) -> &str { &self.service }
+
+    pub fn group(&self) -> &str { &self.group }
+
+    pub fn org(&self) -> &str { &self.org }
+
+    fn validate_part(ident: &str, part: &str, value: &str) -> Result<()> {
+        let reason = if value.is_empty() {
+            format!("the {} is empty", part)
+        } else if let Some(c) =
+            value.chars()
+                 .find(|c| !(c.is_ascii_alphanumeric() || *c == '-' || *c == '_'))
+        {
+            format!("the {} '{}' contains '{}', which is not allowed",
+                    part, value, c)
+        } else {
+            return Ok(());
+        };
+        Err(Error::InvalidServiceGroupIdent { ident: ident.to_string(),
+                                              reason })
+    }
+}
+
+impl fmt::Display for ServiceGroupIdent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}@{}", self.service, self.group, self.org)
+    }
+}
+
+impl FromStr for ServiceGroupIdent {
+    type Err = Error;
+
+    fn from_str(value: &str) -> result::Result<Self, Self::Err> {
+        let missing = |part: &str| {
+            Error::InvalidServiceGroupIdent { ident:  value.to_string(),
+                                              reason: format!("the {} is missing", part), }
+        };
+        let (service_group, org) = value.split_once('@').ok_or_else(|| missing("org"))?;
+        let (service, group) = service_group.split_once('.')
+                                            .ok_or_else(|| missing("group"))?;
+        Self::new(service, group, org)
+    }
+}
+
+impl TryFrom<&str> for ServiceGroupIdent {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self> { value.parse() }
+}
+
+/// A `ServiceGroup` with an org can be used as an identifier; one
+/// without can't.
+impl TryFrom<&ServiceGroup> for ServiceGroupIdent {
+    type Error = Error;
+
+    fn try_from(service_group: &ServiceGroup) -> Result<Self> {
+        match service_group.org() {
+            Some(org) => Self::new(service_group.service(), service_group.group(), org),
+            None => service_group.as_ref().parse(),
+        }
+    }
+}
+
 /// Represents how far apart to run health checks for individual services
 #[derive(Debug,
          Clone,
@@ -419,6 +518,59 @@ mod test {
         }
     }
 
+    #[test]
+    fn service_group_ident_from_str() {
+        let ident = ServiceGroupIdent::from_str("redis.default@acme").unwrap();
+        assert_eq!(ident.service(), "redis");
+        assert_eq!(ident.group(), "default");
+        assert_eq!(ident.org(), "acme");
+        assert_eq!(ident.to_string(), "redis.default@acme");
+        assert_eq!(ident,
+                   ServiceGroupIdent::new("redis", "default", "acme").unwrap());
+        assert_eq!(ident,
+                   ServiceGroupIdent::from_org_and_service_group("acme", "redis.default").unwrap());
+        assert_eq!(ServiceGroupIdent::from_str("my_svc.us-east-1@my-org").unwrap()
+                                                                         .to_string(),
+                   "my_svc.us-east-1@my-org");
+    }
+
+    #[test]
+    fn service_group_ident_errors_say_which_part_is_malformed() {
+        let reason = |value: &str| {
+            match ServiceGroupIdent::from_str(value) {
+                Err(Error::InvalidServiceGroupIdent { ident, reason }) => {
+                    assert_eq!(ident, value);
+                    reason
+                }
+                other => panic!("Expected '{}' to be invalid, got {:?}", value, other),
+            }
+        };
+
+        assert_eq!(reason("redis.default"), "the org is missing");
+        assert_eq!(reason("redis@acme"), "the group is missing");
+        assert_eq!(reason(".default@acme"), "the service is empty");
+        assert_eq!(reason("redis.@acme"), "the group is empty");
+        assert_eq!(reason("redis.default@"), "the org is empty");
+        assert_eq!(reason("re dis.default@acme"),
+                   "the service 're dis' contains ' ', which is not allowed");
+        assert_eq!(reason("redis.prod.east@acme"),
+                   "the group 'prod.east' contains '.', which is not allowed");
+        assert_eq!(reason("redis.default@acme@evil"),
+                   "the org 'acme@evil' contains '@', which is not allowed");
+    }
+
+    #[test]
+    fn service_group_ident_try_from() {
+        let ident = ServiceGroupIdent::try_from("redis.default@acme").unwrap();
+        assert_eq!(ident.to_string(), "redis.default@acme");
+
+        let with_org = ServiceGroup::from_str("redis.default@acme").unwrap();
+        assert_eq!(ServiceGroupIdent::try_from(&with_org).unwrap(), ident);
+
+        let without_org = ServiceGroup::from_str("redis.default").unwrap();
+        assert!(ServiceGroupIdent::try_from(&without_org).is_err());
+    }
+
     #[test]
     fn service_bind_from_str() {
         let bind_str = "name:service.group@organization";
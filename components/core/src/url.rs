@@ -1,5 +1,12 @@
-use crate::env;
-use std::borrow::Borrow;
+use crate::{env,
+            error::{Error,
+                    Result},
+            origin::Origin};
+use std::{borrow::Borrow,
+          collections::HashMap,
+          fs,
+          path::Path,
+          str::FromStr};
 use url::Url;
 
 /// Default Builder URL environment variable
@@ -8,6 +15,12 @@ pub const BLDR_URL_ENVVAR: &str = "HAB_BLDR_URL";
 pub const DEFAULT_BLDR_URL: &str = "https://bldr.habitat.sh";
 /// Legacy environment variable for defining a default Builder endpoint
 const LEGACY_BLDR_URL_ENVVAR: &str = "HAB_DEPOT_URL";
+/// Environment variable for overriding the Builder URL of particular
+/// origins, as comma-separated `origin=url` pairs
+pub const BLDR_URL_OVERRIDES_ENVVAR: &str = "HAB_BLDR_URL_OVERRIDES";
+/// Environment variable naming a TOML file of `origin = "url"` Builder
+/// URL overrides
+pub const BLDR_URL_OVERRIDES_FILE_ENVVAR: &str = "HAB_BLDR_URL_OVERRIDES_FILE";
 
 // Returns a Builder URL value if set in the environment. Does *not*
 // return any default value if the value was not found in the environment!
@@ -26,3 +39,207 @@ pub fn bldr_url<U: Borrow<Url>>(bldr_url: Option<U>) -> String {
     bldr_url.map(|u| u.borrow().to_string())
             .unwrap_or_else(default_bldr_url)
 }
+
+/// Parse a Builder URL, making sure it's one that requests can
+/// actually be made to: an `http` or `https` URL, with nothing (not
+/// even whitespace) after its path.
+pub fn parse_bldr_url(url: &str) -> Result<Url> {
+    let invalid = |reason: &str| Error::InvalidUrl(format!("'{}' ({})", url, reason));
+    if url.trim() != url {
+        return Err(invalid("it has leading or trailing whitespace"));
+    }
+    let parsed = Url::parse(url).map_err(|e| invalid(&e.to_string()))?;
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err(invalid("the scheme must be http or https"));
+    }
+    if parsed.query().is_some() || parsed.fragment().is_some() {
+        return Err(invalid("it can't have a query or fragment"));
+    }
+    Ok(parsed)
+}
+
+/// Builder URLs to use for particular origins, rather than the
+/// default one.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BldrUrlOverrides(HashMap<String, Url>);
+
+impl BldrUrlOverrides {
+    /// The overrides in the file named by
+    /// `BLDR_URL_OVERRIDES_FILE_ENVVAR`, if any, together with those
+    /// in `BLDR_URL_OVERRIDES_ENVVAR`. Where both have an override for
+    /// the same origin, the environment variable wins.
+    pub fn from_env() -> Result<Self> {
+        let mut overrides = match env::var(BLDR_URL_OVERRIDES_FILE_ENVVAR) {
+            Ok(path) => Self::from_file(path)?,
+            Err(_) => Self::default(),
+        };
+        if let Ok(value) = env::var(BLDR_URL_OVERRIDES_ENVVAR) {
+            overrides.0.extend(value.parse::<Self>()?.0);
+        }
+        Ok(overrides)
+    }
+
+    /// Read overrides from a TOML file of `origin = "url"` entries.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|e| {
+                           Error::InvalidBldrUrlOverrides(format!("couldn't read '{}': {}",
+                                                                  path.display(),
+                                                                  e))
+                       })?;
+        let overrides: HashMap<String, String> =
+            toml::from_str(&contents).map_err(Error::ConfigFileSyntax)?;
+        Self::from_pairs(overrides)
+    }
+
+    /// The Builder URL to use for `origin`, if it has been overridden.
+    pub fn get(&self, origin: &str) -> Option<&Url> { self.0.get(origin) }
+
+    fn from_pairs(pairs: impl IntoIterator<Item = (String, String)>) -> Result<Self> {
+        pairs.into_iter()
+             .map(|(origin, url)| {
+                 Origin::from_str(&origin)?;
+                 Ok((origin, parse_bldr_url(&url)?))
+             })
+             .collect::<Result<_>>()
+             .map(BldrUrlOverrides)
+    }
+}
+
+impl FromStr for BldrUrlOverrides {
+    type Err = Error;
+
+    /// Parse comma-separated `origin=url` pairs, as found in
+    /// `BLDR_URL_OVERRIDES_ENVVAR`. Should an origin appear more than
+    /// once, the last URL given for it is used.
+    fn from_str(s: &str) -> Result<Self> {
+        s.split(',')
+         .map(str::trim)
+         .filter(|pair| !pair.is_empty())
+         .map(|pair| {
+             pair.split_once('=')
+                 .map(|(origin, url)| (origin.trim().to_string(), url.trim().to_string()))
+                 .ok_or_else(|| {
+                     Error::InvalidBldrUrlOverrides(format!("'{}' is not of the form origin=url",
+                                                            pair))
+                 })
+         })
+         .collect::<Result<Vec<_>>>()
+         .and_then(Self::from_pairs)
+    }
+}
+
+/// The Builder URL to use for packages and keys from `origin`: its
+/// override from `BldrUrlOverrides::from_env`, if it has one, or else
+/// the URL from `default_bldr_url`. Either way, the URL is checked
+/// with `parse_bldr_url`, so a bad one is reported here rather than
+/// by the first request made with it.
+pub fn bldr_url_for_origin(origin: &str) -> Result<Url> {
+    bldr_url_with_overrides(origin, &BldrUrlOverrides::from_env()?)
+}
+
+fn bldr_url_with_overrides(origin: &str, overrides: &BldrUrlOverrides) -> Result<Url> {
+    match overrides.get(origin) {
+        Some(url) => Ok(url.clone()),
+        None => parse_bldr_url(&default_bldr_url()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locked_env_var;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    locked_env_var!(HAB_BLDR_URL, lock_bldr_url);
+    locked_env_var!(HAB_DEPOT_URL, lock_depot_url);
+    locked_env_var!(HAB_BLDR_URL_OVERRIDES, lock_overrides);
+    locked_env_var!(HAB_BLDR_URL_OVERRIDES_FILE, lock_overrides_file);
+
+    fn overrides_file(contents: &str) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    #[test]
+    fn parse_bldr_url_accepts_http_and_https() {
+        assert_eq!(parse_bldr_url("https://bldr.habitat.sh").unwrap().as_str(),
+                   "https://bldr.habitat.sh/");
+        assert!(parse_bldr_url("http://bldr.internal:9636/v1").is_ok());
+    }
+
+    #[test]
+    fn parse_bldr_url_rejects_bad_urls() {
+        for url in &["",
+                     "bldr.habitat.sh",
+                     "ftp://bldr.habitat.sh",
+                     "file:///hab/bldr",
+                     "https://bldr.habitat.sh ",
+                     "https://bldr.habitat.sh?channel=stable",
+                     "https://bldr.habitat.sh#junk"]
+        {
+            match parse_bldr_url(url) {
+                Err(Error::InvalidUrl(_)) => {}
+                other => panic!("Expected InvalidUrl for {:?}, got {:?}", url, other),
+            }
+        }
+    }
+
+    #[test]
+    fn overrides_parse_from_pairs() {
+        let overrides: BldrUrlOverrides =
+            " myorigin=https://bldr.internal, other = http://other.internal:9636 ,".parse()
+                                                                                   .unwrap();
+        assert_eq!(overrides.get("myorigin").unwrap().as_str(),
+                   "https://bldr.internal/");
+        assert_eq!(overrides.get("other").unwrap().as_str(),
+                   "http://other.internal:9636/");
+        assert_eq!(overrides.get("core"), None);
+    }
+
+    #[test]
+    fn malformed_overrides_are_errors() {
+        assert!("myorigin".parse::<BldrUrlOverrides>().is_err());
+        assert!("myorigin=ftp://bldr.internal".parse::<BldrUrlOverrides>()
+                                              .is_err());
+        assert!("My Origin=https://bldr.internal".parse::<BldrUrlOverrides>()
+                                                 .is_err());
+        assert!(BldrUrlOverrides::from_file(overrides_file("myorigin = 42").path()).is_err());
+    }
+
+    #[test]
+    fn precedence_of_overrides_env_var_and_default() {
+        let bldr_url = lock_bldr_url();
+        let depot_url = lock_depot_url();
+        let overrides = lock_overrides();
+        let overrides_file_var = lock_overrides_file();
+        bldr_url.unset();
+        depot_url.unset();
+        overrides.unset();
+        overrides_file_var.unset();
+
+        assert_eq!(bldr_url_for_origin("myorigin").unwrap().as_str(),
+                   "https://bldr.habitat.sh/");
+
+        bldr_url.set("https://bldr.example.com");
+        assert_eq!(bldr_url_for_origin("myorigin").unwrap().as_str(),
+                   "https://bldr.example.com/");
+
+        let file = overrides_file("myorigin = \"https://from-file.internal\"\n\
+                                   other = \"https://other.internal\"\n");
+        overrides_file_var.set(file.path());
+        overrides.set("myorigin=https://from-env.internal");
+        assert_eq!(bldr_url_for_origin("myorigin").unwrap().as_str(),
+                   "https://from-env.internal/");
+        assert_eq!(bldr_url_for_origin("other").unwrap().as_str(),
+                   "https://other.internal/");
+        assert_eq!(bldr_url_for_origin("core").unwrap().as_str(),
+                   "https://bldr.example.com/");
+
+        bldr_url.set("bldr.example.com");
+        assert!(bldr_url_for_origin("core").is_err());
+        assert!(bldr_url_for_origin("myorigin").is_ok());
+    }
+}
@@ -23,15 +23,18 @@ use habitat_common::{cli::{RING_ENVVAR,
                              EventStreamServerCertificate,
                              EventStreamToken,
                              GossipListenAddr,
+                             HttpListen,
                              HttpListenAddr,
                              ListenCtlAddr,
-                             ResolvedListenCtlAddr},
+                             ResolvedListenCtlAddr,
+                             SocketMode},
                      FeatureFlag,
                      FEATURE_FLAGS};
 use habitat_core::{env::Config,
                    fs::HAB_CTL_KEYS_CACHE,
                    package::PackageIdent,
                    util as core_util};
+use habitat_sup_protocol::types::SignerVerification;
 use rants::{error::Error as RantsError,
             Address as NatsAddress};
 use serde::{Deserialize,
@@ -70,12 +73,32 @@ pub enum HabSup {
         #[structopt(flatten)]
         remote_sup: RemoteSup,
     },
+    /// Converge a Supervisor on a declared set of services, loading, unloading, updating, and
+    /// configuring services as needed
+    #[structopt(no_version)]
+    Converge {
+        /// A TOML file listing every service the Supervisor should be running
+        #[structopt(name = "DESIRED_STATE")]
+        desired_state: PathBuf,
+        /// Report the actions needed to converge without applying them
+        #[structopt(name = "DRY_RUN", long = "dry-run")]
+        dry_run:       bool,
+        #[structopt(flatten)]
+        remote_sup:    RemoteSup,
+    },
     /// Restart a Supervisor without restarting its services
     #[structopt(no_version)]
     Restart {
         #[structopt(flatten)]
         remote_sup: RemoteSup,
     },
+    /// Reload a Supervisor's ring key from its key cache, sending gossip with the newest revision
+    /// while still accepting gossip encrypted with the revisions just before it
+    #[structopt(no_version)]
+    ReloadRingKey {
+        #[structopt(flatten)]
+        remote_sup: RemoteSup,
+    },
     #[cfg(not(target_os = "macos"))]
     #[structopt(flatten)]
     Sup(Sup),
@@ -159,10 +182,16 @@ pub struct SupRun {
                 conflicts_with_all = &["LISTEN_GOSSIP", "PEER", "PEER_WATCH_FILE"])]
     pub local_gossip_mode: bool,
     /// The listen address for the HTTP Gateway
+    ///
+    /// Either IP:PORT, or unix:///path/to/sup.sock to serve the gateway on a Unix domain socket
+    /// instead of a TCP port (not supported on Windows).
     #[structopt(long = "listen-http",
                 env = HttpListenAddr::ENVVAR,
                 default_value = HttpListenAddr::default_as_str())]
-    pub listen_http: HttpListenAddr,
+    pub listen_http: HttpListen,
+    /// The permissions, in octal, of the HTTP Gateway's Unix domain socket
+    #[structopt(long = "listen-http-socket-mode", default_value = "0660")]
+    pub listen_http_socket_mode: SocketMode,
     /// Disable the HTTP Gateway completely
     #[structopt(long = "http-disable", short = "D")]
     pub http_disable: bool,
@@ -327,6 +356,17 @@ pub struct SupRun {
     /// automatic package cleanup is performed.
     #[structopt(long = "keep-latest-packages", env = "HAB_KEEP_LATEST_PACKAGES")]
     pub keep_latest_packages: Option<usize>,
+    /// Verify that installed packages were signed by a key in the key cache before starting
+    /// services from them
+    ///
+    /// off: no verification is performed. permissive: packages are verified, but packages
+    /// installed before their signer was recorded are allowed to start. strict: packages are
+    /// verified, and packages installed before their signer was recorded are refused. This can
+    /// be overridden per service with `hab svc load --signer-verification`.
+    #[structopt(long = "verify-package-signers",
+                default_value = SignerVerification::Off.as_str(),
+                possible_values = SignerVerification::VARIANTS)]
+    pub verify_package_signers: SignerVerification,
     /// Paths to files or directories of service config files to load on startup
     ///
     /// See `hab svc bulkload --help` for details
@@ -19,7 +19,8 @@ use habitat_core::{os::process::ShutdownTimeout,
                              ServiceGroup},
                    ChannelIdent};
 use habitat_sup_protocol::{ctl,
-                           types::UpdateCondition};
+                           types::{SignerVerification,
+                                   UpdateCondition}};
 use log::warn;
 use serde::Deserialize;
 use std::{convert::TryFrom,
@@ -206,6 +207,32 @@ pub struct SharedLoad {
     #[structopt(long = "health-check-interval", short = "i", default_value = "30")]
     #[serde(default = "health_check_interval_default")]
     pub health_check_interval: u64,
+    /// Spread the service's health checks out over time, by up to this many seconds
+    ///
+    /// The first check is put off by an amount derived from the service group, so that services
+    /// on the same interval don't all check at once, and each check after it is moved by up to
+    /// this many seconds either way. Given to `hab sup run`, this is also the default for every
+    /// service loaded without one of its own.
+    #[structopt(long = "health-check-splay")]
+    pub health_check_splay:    Option<u32>,
+    /// The minimum period of time in seconds to wait before attempting to restart the service
+    /// if it fails to start up
+    ///
+    /// Overrides `hab sup run --service-min-backoff-period` for this service.
+    #[structopt(long = "min-backoff-period")]
+    pub min_backoff_period:    Option<u32>,
+    /// The maximum period of time in seconds to wait before attempting to restart the service
+    /// if it fails to start up
+    ///
+    /// Overrides `hab sup run --service-max-backoff-period` for this service.
+    #[structopt(long = "max-backoff-period")]
+    pub max_backoff_period:    Option<u32>,
+    /// The period of time in seconds to wait before assuming that the service started up
+    /// successfully after a restart
+    ///
+    /// Overrides `hab sup run --service-restart-cooldown-period` for this service.
+    #[structopt(long = "restart-cooldown-period")]
+    pub cooldown_period:       Option<u32>,
     /// The delay in seconds after sending the shutdown signal to wait before killing the service
     /// process
     ///
@@ -231,6 +258,26 @@ pub struct SharedLoad {
     /// Use the package config from this path rather than the package itself
     #[structopt(long = "config-from")]
     pub config_from:           Option<PathBuf>,
+    /// Override the Supervisor's package signer verification for this service
+    ///
+    /// See `hab sup run --help` for details.
+    #[structopt(long = "signer-verification",
+                possible_values = SignerVerification::VARIANTS)]
+    pub signer_verification:   Option<SignerVerification>,
+    /// Keep a file in the service's files directory populated with the alive members of the
+    /// service group
+    ///
+    /// The file is rewritten whenever the group's membership changes, and the `file-updated`
+    /// hook is run. By default it contains one line per member with its IP address and member
+    /// ID; use `--peer-file-template` to change the format.
+    #[structopt(long = "peer-file")]
+    pub peer_file:             Option<String>,
+    /// The Handlebars template used to render the file given by `--peer-file`
+    ///
+    /// The template is rendered with a `members` list, where each member has `member_id`,
+    /// `address`, `hostname`, and `leader` fields.
+    #[structopt(long = "peer-file-template", requires = "PEER_FILE")]
+    pub peer_file_template:    Option<String>,
 }
 
 fn load_default_config_files() -> Vec<PathBuf> {
@@ -380,8 +427,15 @@ pub fn shared_load_cli_to_ctl(ident: PackageIdent,
                  update_strategy: Some(shared_load.strategy as i32),
                  health_check_interval:
                      Some(HealthCheckInterval { seconds: shared_load.health_check_interval, }),
+                 health_check_splay: shared_load.health_check_splay,
+                 min_backoff_period: shared_load.min_backoff_period,
+                 max_backoff_period: shared_load.max_backoff_period,
+                 cooldown_period: shared_load.cooldown_period,
                  shutdown_timeout: shared_load.shutdown_timeout.map(u32::from),
-                 update_condition: Some(shared_load.update_condition as i32) })
+                 update_condition: Some(shared_load.update_condition as i32),
+                 signer_verification: shared_load.signer_verification.map(i32::from),
+                 peer_file: shared_load.peer_file,
+                 peer_file_template: shared_load.peer_file_template })
 }
 
 impl TryFrom<Load> for habitat_sup_protocol::ctl::SvcLoad {
@@ -471,6 +525,12 @@ pub struct Update {
     #[structopt(long = "health-check-interval", short = "i")]
     pub health_check_interval: Option<HealthCheckInterval>,
 
+    /// Spread the service's health checks out over time, by up to this many seconds
+    ///
+    /// See `hab svc load --help` for details.
+    #[structopt(long = "health-check-splay")]
+    pub health_check_splay: Option<u32>,
+
     /// The delay in seconds after sending the shutdown signal to wait before killing the service
     /// process
     ///
@@ -478,6 +538,30 @@ pub struct Update {
     #[structopt(long = "shutdown-timeout")]
     pub shutdown_timeout: Option<ShutdownTimeout>,
 
+    /// Override the Supervisor's package signer verification for this service
+    #[structopt(long = "signer-verification",
+                possible_values = SignerVerification::VARIANTS)]
+    pub signer_verification: Option<SignerVerification>,
+
+    /// Keep a file in the service's files directory populated with the alive members of the
+    /// service group
+    #[structopt(long = "peer-file")]
+    pub peer_file: Option<String>,
+
+    /// The Handlebars template used to render the service's peer file
+    #[structopt(long = "peer-file-template")]
+    pub peer_file_template: Option<String>,
+
+    /// Reset a field of the service's spec to its default value
+    ///
+    /// Takes the name of the field as it appears in the service's spec file. A field can't be
+    /// both set and unset in the same update.
+    #[structopt(long = "unset",
+                number_of_values = 1,
+                possible_values = ctl::SvcUpdate::UNSETTABLE_FIELDS)]
+    #[serde(default)]
+    pub unset: Vec<String>,
+
     /// Password of the service user
     #[cfg(target_os = "windows")]
     #[structopt(long = "password")]
@@ -496,6 +580,7 @@ impl TryFrom<Update> for ctl::SvcUpdate {
                                    binds: u.bind.map(FromIterator::from_iter),
                                    group: u.group,
                                    health_check_interval: u.health_check_interval.map(Into::into),
+                                   health_check_splay: u.health_check_splay,
                                    binding_mode: u.binding_mode.map(|v| v as i32),
                                    topology: u.topology.map(|v| v as i32),
                                    update_strategy: u.strategy.map(|v| v as i32),
@@ -504,7 +589,11 @@ impl TryFrom<Update> for ctl::SvcUpdate {
                                    #[cfg(windows)]
                                    svc_encrypted_password: u.password,
                                    #[cfg(not(windows))]
-                                   svc_encrypted_password: None, };
+                                   svc_encrypted_password: None,
+                                   signer_verification: u.signer_verification.map(i32::from),
+                                   peer_file: u.peer_file,
+                                   peer_file_template: u.peer_file_template,
+                                   unset: u.unset, };
 
         // Compiler-assisted validation that the user has indeed
         // specified *something* to change. If they didn't, all the
@@ -519,12 +608,18 @@ impl TryFrom<Update> for ctl::SvcUpdate {
                                 topology: None,
                                 update_strategy: None,
                                 health_check_interval: None,
+                                health_check_splay: None,
                                 shutdown_timeout: None,
-                                update_condition: None, } = &msg
+                                update_condition: None,
+                                signer_verification: None,
+                                peer_file: None,
+                                peer_file_template: None,
+                                unset, } = &msg
         {
-            Err(Error::ArgumentError("No fields specified for update".to_string()))
-        } else {
-            Ok(msg)
+            if unset.is_empty() {
+                return Err(Error::ArgumentError("No fields specified for update".to_string()));
+            }
         }
+        Ok(msg)
     }
 }
@@ -8,7 +8,9 @@ use clap::{App,
            ArgSettings};
 use configopt::ConfigOpt;
 use habitat_common::FeatureFlag;
-use std::str;
+use habitat_sup_protocol::ctl;
+use std::{convert::TryFrom,
+          str};
 
 fn feature_flags_for_cli_test() -> FeatureFlag {
     let mut f = FeatureFlag::empty();
@@ -465,3 +467,29 @@ fn test_hab_svc_update_empty_binds() {
     let update = extract_hab_svc_update(hab);
     assert_eq!(update.bind.unwrap().len(), 1);
 }
+
+#[test]
+fn test_hab_svc_update_unset() {
+    let hab = Hab::try_from_iter_with_configopt(&["hab",
+                                                  "svc",
+                                                  "update",
+                                                  "--unset",
+                                                  "binds",
+                                                  "--unset",
+                                                  "shutdown_timeout",
+                                                  "core/redis"]).unwrap();
+    let update = extract_hab_svc_update(hab);
+    assert_eq!(update.unset,
+               vec!["binds".to_string(), "shutdown_timeout".to_string()]);
+    // Unsetting fields is enough of an update on its own.
+    let msg = ctl::SvcUpdate::try_from(update).unwrap();
+    assert_eq!(msg.unset,
+               vec!["binds".to_string(), "shutdown_timeout".to_string()]);
+
+    assert!(Hab::try_from_iter_with_configopt(&["hab",
+                                                "svc",
+                                                "update",
+                                                "core/redis",
+                                                "--unset",
+                                                "ident"]).is_err());
+}
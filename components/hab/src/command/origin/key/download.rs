@@ -14,7 +14,8 @@ use crate::{api_client::{self,
             PRODUCT,
             VERSION};
 use habitat_core::{crypto::keys::{KeyCache,
-                                  NamedRevision},
+                                  NamedRevision,
+                                  PublicOriginSigningKey},
                    origin::Origin};
 use reqwest::StatusCode;
 
@@ -169,7 +170,7 @@ async fn download_key(ui: &mut UI,
                       token: Option<&str>,
                       key_cache: &KeyCache)
                       -> Result<()> {
-    if key_cache.public_signing_key(named_revision).is_ok() {
+    if key_cache.contains_valid::<PublicOriginSigningKey>(named_revision) {
         ui.status(Status::Using,
                   &format!("{} in {}", named_revision, key_cache.as_ref().display()))?;
         Ok(())
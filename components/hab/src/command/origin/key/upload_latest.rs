@@ -28,7 +28,8 @@ pub async fn start(ui: &mut UI,
     let public_key: PublicOriginSigningKey = key_cache.latest_public_origin_signing_key(origin)?;
 
     // The path to the key in the cache
-    let public_keyfile = key_cache.path_in_cache(&public_key);
+    let public_keyfile =
+        key_cache.find_key_path::<PublicOriginSigningKey>(public_key.named_revision())?;
 
     ui.status(Status::Uploading, public_keyfile.display())?;
 
@@ -56,7 +57,8 @@ pub async fn start(ui: &mut UI,
         // get matching secret key
         let secret_key: SecretOriginSigningKey =
             key_cache.secret_signing_key(public_key.named_revision())?;
-        let secret_keyfile = key_cache.path_in_cache(&secret_key);
+        let secret_keyfile =
+            key_cache.find_key_path::<SecretOriginSigningKey>(secret_key.named_revision())?;
 
         ui.status(Status::Uploading, secret_keyfile.display())?;
         match api_client.put_origin_secret_key(secret_key.named_revision().name(),
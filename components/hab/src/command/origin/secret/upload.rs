@@ -21,9 +21,9 @@ pub async fn start(ui: &mut UI,
                    -> Result<()> {
     let api_client = Client::new(bldr_url, PRODUCT, VERSION, None).map_err(Error::APIClient)?;
 
-    let encryption_key = match key_cache.latest_origin_public_encryption_key(origin) {
-        Ok(key) => key,
-        Err(_) => {
+    let encryption_key = match key_cache.try_latest_origin_public_encryption_key(origin)? {
+        Some(key) => key,
+        None => {
             debug!("Didn't find public encryption key in cache path");
             download_public_encryption_key(ui, &api_client, origin, token, key_cache).await?;
             key_cache.latest_origin_public_encryption_key(origin)?
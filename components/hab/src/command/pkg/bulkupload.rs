@@ -23,15 +23,12 @@ use crate::{api_client::{self,
             PRODUCT,
             VERSION};
 use glob::glob_with;
-use habitat_core::{crypto::{keys::{Key,
-                                   KeyCache,
-                                   PublicOriginSigningKey},
-                            PUBLIC_KEY_SUFFIX},
+use habitat_core::{crypto::keys::{KeyCache,
+                                  KeyKind},
                    ChannelIdent};
 use log::debug;
 use reqwest::StatusCode;
 use std::{collections::BTreeSet,
-          convert::TryFrom,
           path::{Path,
                  PathBuf}};
 
@@ -53,7 +50,6 @@ pub async fn start(ui: &mut UI,
                    key_cache: &KeyCache)
                    -> Result<()> {
     let artifact_paths = paths_with_extension(artifact_path, "hart");
-    let pub_keys_paths = paths_with_extension(key_cache.as_ref(), PUBLIC_KEY_SUFFIX);
 
     ui.begin(format!("Preparing to upload artifacts to the '{}' channel on {}",
                      additional_release_channel.clone()
@@ -69,14 +65,16 @@ pub async fn start(ui: &mut UI,
               String::from("origin names from local key cache"))?;
 
     let mut origins = BTreeSet::new();
-    for pub_key_path in pub_keys_paths {
+    for public_key in key_cache.list_keys()?
+                               .into_iter()
+                               .filter(|key| key.kind == KeyKind::SigningPublic)
+    {
         // We discover origin names from the public signing keys as opposed to building the
         // list from the packages themselves. Previously, we looped through all the artifacts
         // using PackageArchive::new() but that proves too expensive an operation at any sort of
         // scale. Relevant: https://github.com/habitat-sh/habitat/issues/5153
-        debug!("Parsing public signing key {}", pub_key_path.display());
-        let public_key: PublicOriginSigningKey = TryFrom::try_from(pub_key_path)?;
-        origins.insert(public_key.named_revision().name().to_string());
+        debug!("Found public signing key {}", public_key.path.display());
+        origins.insert(public_key.named_revision.name().to_string());
     }
     let mut origins_to_create: Vec<String> = Vec::new();
     let api_client = Client::new(bldr_url, PRODUCT, VERSION, None)?;
@@ -411,12 +411,12 @@ impl<'a> DownloadTask<'a> {
         let cache = KeyCache::new(self.path_for_keys());
         cache.setup()?;
 
-        if cache.public_signing_key(&signer).is_err() {
+        if cache.try_public_signing_key(&signer)?.is_none() {
             ui.status(Status::Downloading,
                       format!("public key for signer {}", signer))?;
             self.fetch_origin_key(ui, signer.clone(), self.token)
                 .await?;
-        };
+        }
 
         if self.verify {
             ui.status(Status::Verifying, artifact.ident()?)?;
@@ -28,7 +28,8 @@ use crate::{api_client::{self,
             VERSION};
 use habitat_core::{crypto::{artifact::get_artifact_header,
                             keys::{KeyCache,
-                                   KeyFile}},
+                                   KeyFile,
+                                   PublicOriginSigningKey}},
                    package::{PackageArchive,
                              PackageIdent,
                              PackageTarget},
@@ -261,7 +262,8 @@ async fn upload_public_key(ui: &mut UI,
 
     let public_key = key_cache.public_signing_key(header.signer())?;
     let public_keyfile_name = public_key.own_filename();
-    let path_in_cache = key_cache.path_in_cache(&public_key);
+    let path_in_cache =
+        key_cache.find_key_path::<PublicOriginSigningKey>(public_key.named_revision())?;
 
     let name = header.signer().name();
     let rev = header.signer().revision();
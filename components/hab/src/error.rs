@@ -34,6 +34,8 @@ pub enum Error {
     CommandNotFoundInPkg((String, String)),
     CliConfig(cli_config::Error),
     ConfigOpt(configopt::Error),
+    /// The number of converge actions that failed
+    ConvergeFailed(usize),
     CryptoCLI(String),
     CtlClient(SrvClientError),
     CtrlcError(ctrlc::Error),
@@ -107,6 +109,10 @@ impl fmt::Display for Error {
             }
             Error::CliConfig(ref err) => format!("{}", err),
             Error::ConfigOpt(ref err) => format!("{}", err),
+            Error::ConvergeFailed(count) => {
+                format!("Failed to apply {} converge action(s); see the plan above for details",
+                        count)
+            }
             Error::CryptoCLI(ref e) => e.to_string(),
             Error::CtlClient(ref e) => e.to_string(),
             Error::CtrlcError(ref err) => format!("{}", err),
@@ -1,5 +1,6 @@
 #![recursion_limit = "128"]
 
+use chrono::Utc;
 use clap::{value_t,
            ArgMatches,
            ErrorKind as ClapErrorKind,
@@ -260,6 +261,14 @@ async fn start(ui: &mut UI, feature_flags: FeatureFlag) -> Result<()> {
                         HabSup::Restart { remote_sup } => {
                             return sub_sup_restart(remote_sup.inner()).await;
                         }
+                        HabSup::ReloadRingKey { remote_sup } => {
+                            return sub_sup_reload_ring_key(remote_sup.inner()).await;
+                        }
+                        HabSup::Converge { desired_state,
+                                           dry_run,
+                                           remote_sup, } => {
+                            return sub_sup_converge(&desired_state, dry_run, remote_sup.inner()).await;
+                        }
                     }
                 }
                 Hab::Svc(svc) => {
@@ -815,9 +824,9 @@ async fn sub_pkg_build(ui: &mut UI, m: &ArgMatches<'_>, feature_flags: FeatureFl
         init()?;
         let key_cache = key_cache_from_matches(m)?;
         for origin in origins.iter() {
-            // Validate that a secret signing key is present on disk
-            // for each origin.
-            key_cache.latest_secret_origin_signing_key(origin)?;
+            // Validate that an unexpired secret signing key is present
+            // on disk for each origin.
+            key_cache.latest_valid_secret_origin_signing_key(origin, Utc::now())?;
         }
     }
 
@@ -1213,7 +1222,7 @@ fn sub_pkg_sign(ui: &mut UI, m: &ArgMatches<'_>) -> Result<()> {
 
     init()?;
 
-    let key = key_cache.latest_secret_origin_signing_key(&origin)?;
+    let key = key_cache.latest_valid_secret_origin_signing_key(&origin, Utc::now())?;
     command::pkg::sign::start(ui, &key, src, dst)
 }
 
@@ -1652,6 +1661,95 @@ async fn sub_sup_restart(remote_sup: Option<&ResolvedListenCtlAddr>) -> Result<(
     Ok(())
 }
 
+#[cfg(not(target_os = "macos"))]
+async fn sub_sup_reload_ring_key(remote_sup: Option<&ResolvedListenCtlAddr>) -> Result<()> {
+    let remote_sup = SrvClient::ctl_addr(remote_sup)?;
+    let mut ui = ui::ui();
+    let msg = sup_proto::ctl::SupRingKeyReload::default();
+
+    ui.begin(format!("Reloading the ring key of supervisor {}", remote_sup))?;
+    let mut response = SrvClient::request(Some(&remote_sup), msg).await?;
+    while let Some(message_result) = response.next().await {
+        let reply = message_result?;
+        match reply.message_id() {
+            "RingKeyRevisions" => {
+                let revisions = reply.parse::<sup_proto::ctl::RingKeyRevisions>()
+                                     .map_err(SrvClientError::Decode)?;
+                for (i, revision) in revisions.accepted.iter().enumerate() {
+                    let status = if i == 0 { "sending and accepting" } else { "accepting" };
+                    ui.status(Status::Using, format!("{} ({})", revision, status))?;
+                }
+            }
+            "NetErr" => {
+                let m = reply.parse::<sup_proto::net::NetErr>()
+                             .map_err(SrvClientError::Decode)?;
+                return Err(SrvClientError::from(m).into());
+            }
+            _ => return Err(SrvClientError::from(io::Error::from(io::ErrorKind::UnexpectedEof)).into()),
+        }
+    }
+    ui.end("Ring key reloaded.")?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+async fn sub_sup_converge(desired_state: &Path,
+                          dry_run: bool,
+                          remote_sup: Option<&ResolvedListenCtlAddr>)
+                          -> Result<()> {
+    use sup_proto::ctl::converge_action::{Kind as ConvergeKind,
+                                          Status as ConvergeStatus};
+
+    let desired_state = std::fs::read_to_string(desired_state)?;
+    let msg = sup_proto::ctl::SupConverge { desired_state: Some(desired_state),
+                                            dry_run:       Some(dry_run), };
+
+    let mut out = TabWriter::new(io::stdout());
+    let mut failed = 0;
+    let mut response = SrvClient::request(remote_sup, msg).await?;
+    while let Some(message_result) = response.next().await {
+        let reply = message_result?;
+        match reply.message_id() {
+            "ConvergencePlan" => {
+                let plan = reply.parse::<sup_proto::ctl::ConvergencePlan>()
+                                .map_err(SrvClientError::Decode)?;
+                if plan.actions.is_empty() {
+                    writeln!(out, "Already converged; nothing to do.")?;
+                    continue;
+                }
+                writeln!(out, "status\taction\tpackage\tdescription\terror")?;
+                for action in plan.actions {
+                    let status = action.status.and_then(ConvergeStatus::from_i32);
+                    if status == Some(ConvergeStatus::Failed) {
+                        failed += 1;
+                    }
+                    let kind = action.kind.and_then(ConvergeKind::from_i32);
+                    writeln!(out,
+                             "{}\t{}\t{}\t{}\t{}",
+                             status.map_or_else(|| "unknown".to_string(), |s| s.to_string()),
+                             kind.map_or_else(|| "unknown".to_string(), |k| k.to_string()),
+                             action.ident
+                                   .map(|i| i.to_string())
+                                   .unwrap_or_else(|| "<none>".to_string()),
+                             action.description.unwrap_or_default(),
+                             action.error.unwrap_or_default())?;
+                }
+            }
+            "NetErr" => {
+                let m = reply.parse::<sup_proto::net::NetErr>()
+                             .map_err(SrvClientError::Decode)?;
+                return Err(SrvClientError::from(m).into());
+            }
+            _ => return Err(SrvClientError::from(io::Error::from(io::ErrorKind::UnexpectedEof)).into()),
+        }
+    }
+    out.flush()?;
+    if failed > 0 {
+        return Err(Error::ConvergeFailed(failed));
+    }
+    Ok(())
+}
+
 #[cfg(not(target_os = "macos"))]
 fn sub_sup_secret_generate() -> Result<()> {
     let mut ui = ui::ui();
@@ -46,10 +46,13 @@ pub enum LauncherStatus {
 }
 
 pub struct LauncherCli {
-    tx:      IpcSender<Vec<u8>>,
-    rx:      IpcReceiver<Vec<u8>>,
+    tx:               IpcSender<Vec<u8>>,
+    rx:               IpcReceiver<Vec<u8>>,
     /// Maximum wait time for interactions that can timeout.
-    timeout: Duration,
+    timeout:          Duration,
+    /// The IPC protocol version agreed upon with the Launcher at
+    /// registration.
+    protocol_version: u32,
 }
 
 impl LauncherCli {
@@ -61,16 +64,60 @@ impl LauncherCli {
         let (ipc_srv, pipe_to_sup) = IpcServer::new().map_err(ConnectError::IPCServerStartup)?;
         debug!("IpcServer::new() returned pipe_to_sup: {}", pipe_to_sup);
         // Register the supervisor with the launcher by sending a register command
-        let cmd = protocol::Register { pipe: pipe_to_sup };
+        let cmd = protocol::Register::new(pipe_to_sup);
         Self::send(&tx, &cmd).map_err(ConnectError::LauncherRegisterSend)?;
         // Accpet the incoming connection from the launcher and read the response
         let (rx, raw) = ipc_srv.accept()
                                .map_err(ConnectError::IPCIncomingConnection)?;
-        Self::read::<protocol::NetOk>(&raw).map_err(ConnectError::LauncherRegisterReceive)?;
+        let reply = Self::read_register_reply(&raw)?;
+        let protocol_version =
+            protocol::negotiate_protocol_version(reply.protocol_version,
+                                                 reply.min_protocol_version).map_err(|err| {
+                error!("{}", err);
+                ConnectError::IncompatibleProtocolVersion(err)
+            })?;
+        debug!("Negotiated launcher protocol version {}", protocol_version);
 
         let timeout = LauncherInteractionTimeout::configured_value().into();
 
-        Ok(LauncherCli { tx, rx, timeout })
+        Ok(LauncherCli { tx,
+                         rx,
+                         timeout,
+                         protocol_version })
+    }
+
+    /// The IPC protocol version agreed upon with the Launcher.
+    pub fn protocol_version(&self) -> u32 { self.protocol_version }
+
+    /// Read the Launcher's reply to `Register`.
+    ///
+    /// Older Launchers reply with a plain `NetOk` rather than a
+    /// `RegisterOk`; the payloads are wire-compatible, so both are
+    /// decoded as a `RegisterOk` (with no versions set, in the former
+    /// case).
+    fn read_register_reply(bytes: &[u8]) -> Result<protocol::RegisterOk, ConnectError> {
+        let txn = protocol::NetTxn::from_bytes(bytes).map_err(|err| {
+                      ConnectError::LauncherRegisterReceive(IPCReadError::ProtocolDeserialize(err))
+                  })?;
+        match txn.message_id() {
+            "NetErr" => {
+                let err = txn.decode::<protocol::NetErr>().map_err(|err| {
+                              ConnectError::LauncherRegisterReceive(
+                                  IPCReadError::PayloadDeserialize(err))
+                          })?;
+                if err.code == protocol::ErrCode::IncompatibleProtocolVersion {
+                    error!("Launcher rejected registration: {}", err.msg);
+                    Err(ConnectError::ProtocolVersionRejected(err))
+                } else {
+                    Err(ConnectError::LauncherRegisterReceive(IPCReadError::LauncherCommand(err)))
+                }
+            }
+            _ => {
+                txn.decode::<protocol::RegisterOk>().map_err(|err| {
+                       ConnectError::LauncherRegisterReceive(IPCReadError::PayloadDeserialize(err))
+                   })
+            }
+        }
     }
 
     /// Read a launcher protocol message from a byte array
@@ -83,6 +130,10 @@ impl LauncherCli {
                          .map_err(IPCReadError::PayloadDeserialize)?;
             return Err(IPCReadError::LauncherCommand(err));
         }
+        let message_id = txn.message_id();
+        if message_id != T::MESSAGE_ID {
+            return Err(IPCReadError::UnexpectedMessage(message_id.to_string(), T::MESSAGE_ID));
+        }
         let msg = txn.decode::<T>()
                      .map_err(IPCReadError::PayloadDeserialize)?;
         Ok(msg)
@@ -171,6 +222,12 @@ impl LauncherCli {
             Ok(None) => LauncherStatus::Running,
             // Received a shutdown command
             Ok(Some(_)) => LauncherStatus::GracefullyShutdown,
+            // Received a message we don't know about, probably from a
+            // newer Launcher; it's safe to carry on without it.
+            Err(ReceiveError::IPCRead(IPCReadError::UnexpectedMessage(message_id, _))) => {
+                warn!("Ignoring unexpected message from launcher: {}", message_id);
+                LauncherStatus::Running
+            }
             // Launcher IPC channel was disconnected
             Err(ReceiveError::IPCReceive(IPCError(IpcError::Disconnected))) => {
                 LauncherStatus::Shutdown
@@ -17,6 +17,21 @@ pub enum ConnectError {
     LauncherRegisterSend(#[source] SendError),
     #[error("Failed to receive registration IPC command response from the launcher")]
     LauncherRegisterReceive(#[source] IPCReadError),
+    #[error("The launcher and supervisor do not share a compatible IPC protocol version")]
+    IncompatibleProtocolVersion(#[source] protocol::Error),
+    #[error("The launcher rejected the supervisor's IPC protocol version: {}", .0.msg)]
+    ProtocolVersionRejected(protocol::NetErr),
+}
+
+impl ConnectError {
+    /// Whether this failure is due to the launcher and supervisor
+    /// being unable to agree on a protocol version. Retrying the
+    /// connection will never resolve this.
+    pub fn is_protocol_version_mismatch(&self) -> bool {
+        matches!(self,
+                 ConnectError::IncompatibleProtocolVersion(_)
+                 | ConnectError::ProtocolVersionRejected(_))
+    }
 }
 
 /// Errors that occur when remotely executing a command on the Habitat Launcher
@@ -46,6 +61,8 @@ pub enum IPCReadError {
     PayloadDeserialize(protocol::Error),
     #[error("Launcher command execution failed: {0}")]
     LauncherCommand(protocol::NetErr),
+    #[error("Received unexpected launcher protocol message '{0}' while waiting for '{1}'")]
+    UnexpectedMessage(String, &'static str),
 }
 
 ///  Errors that occur when attempting to send a command to the Habitat Launcher via IPC
@@ -5,6 +5,14 @@ use std::{fmt,
 #[derive(Debug)]
 pub enum Error {
     Deserialize(prost::DecodeError),
+    /// The two sides of the IPC connection share no common protocol
+    /// version.
+    IncompatibleProtocolVersion {
+        local_version:     u32,
+        local_min_version: u32,
+        peer_version:      u32,
+        peer_min_version:  u32,
+    },
     NetErr(NetErr),
     ProtocolMismatch(&'static str),
     Serialize(prost::EncodeError),
@@ -16,6 +24,14 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let msg = match *self {
             Error::Deserialize(ref e) => format!("Unable to deserialize message: {}", e),
+            Error::IncompatibleProtocolVersion { local_version,
+                                                 local_min_version,
+                                                 peer_version,
+                                                 peer_min_version, } => {
+                format!("Incompatible launcher protocol versions: this side speaks versions \
+                         {}..={}, but the other side speaks versions {}..={}",
+                        local_min_version, local_version, peer_min_version, peer_version)
+            }
             Error::NetErr(ref e) => format!("Net error: {}", e),
             Error::ProtocolMismatch(ref field) => {
                 format!("Received an unsupported or bad protocol message. Missing field: {}",
@@ -27,6 +43,8 @@ impl fmt::Display for Error {
     }
 }
 
+impl std::error::Error for Error {}
+
 impl From<prost::DecodeError> for Error {
     fn from(err: prost::DecodeError) -> Error { Error::Deserialize(err) }
 }
@@ -5,6 +5,7 @@ mod types;
 use crate::error::Result;
 pub use crate::{error::Error,
                 types::*};
+use std::cmp;
 
 pub const LAUNCHER_PIPE_ENV: &str = "HAB_LAUNCHER_PIPE";
 pub const LAUNCHER_PID_ENV: &str = "HAB_LAUNCHER_PID";
@@ -16,6 +17,17 @@ pub const OK_NO_RETRY_EXCODE: i32 = 84;
 /// exit code. The Launcher should exit immediately with a non-zero exit code.
 pub const ERR_NO_RETRY_EXCODE: i32 = 86;
 
+/// The newest version of the IPC protocol spoken between the Launcher and the Supervisor.
+///
+/// Bump this whenever either side starts relying on a message or field the other side may not
+/// understand.
+pub const PROTOCOL_VERSION: u32 = 2;
+/// The oldest version of the IPC protocol this build is still able to speak.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+/// The protocol version assumed for a peer that does not announce one, i.e. one that predates
+/// protocol versioning altogether.
+pub const LEGACY_PROTOCOL_VERSION: u32 = 1;
+
 #[derive(Debug)]
 pub struct NetTxn(Envelope);
 
@@ -50,3 +62,177 @@ pub fn error<T>(err: T) -> NetErr
     NetErr { msg:  err.to_string(),
              code: err.into(), }
 }
+
+/// Pick the protocol version to use with a peer, given the range of versions it announced.
+///
+/// A peer that doesn't announce a version is assumed to speak only `LEGACY_PROTOCOL_VERSION`.
+/// The newest version both sides understand wins; if there is no such version, an
+/// `Error::IncompatibleProtocolVersion` naming both ranges is returned.
+pub fn negotiate_protocol_version(peer_version: Option<u32>,
+                                  peer_min_version: Option<u32>)
+                                  -> Result<u32> {
+    negotiate(PROTOCOL_VERSION,
+              MIN_PROTOCOL_VERSION,
+              peer_version,
+              peer_min_version)
+}
+
+fn negotiate(local_version: u32,
+             local_min_version: u32,
+             peer_version: Option<u32>,
+             peer_min_version: Option<u32>)
+             -> Result<u32> {
+    let peer_version = peer_version.unwrap_or(LEGACY_PROTOCOL_VERSION);
+    let peer_min_version = peer_min_version.unwrap_or(peer_version);
+    let version = cmp::min(local_version, peer_version);
+    if version >= local_min_version && version >= peer_min_version {
+        Ok(version)
+    } else {
+        Err(Error::IncompatibleProtocolVersion { local_version,
+                                                 local_min_version,
+                                                 peer_version,
+                                                 peer_min_version })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn round_trip<T>(msg: &T) -> T
+        where T: LauncherMessage
+    {
+        let bytes = NetTxn::build(msg).unwrap().to_bytes().unwrap();
+        let txn = NetTxn::from_bytes(&bytes).unwrap();
+        assert_eq!(txn.message_id(), T::MESSAGE_ID);
+        txn.decode::<T>().unwrap()
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let register = Register::new("pipe-1".to_string());
+        assert_eq!(round_trip(&register), register);
+
+        let register_ok = RegisterOk { protocol_version:     Some(PROTOCOL_VERSION),
+                                       min_protocol_version: Some(MIN_PROTOCOL_VERSION), };
+        assert_eq!(round_trip(&register_ok), register_ok);
+
+        let net_err = NetErr { code: ErrCode::IncompatibleProtocolVersion,
+                               msg:  "nope".to_string(), };
+        assert_eq!(round_trip(&net_err), net_err);
+        assert_eq!(round_trip(&NetOk {}), NetOk {});
+        assert_eq!(round_trip(&Restart { pid: 42 }), Restart { pid: 42 });
+
+        let mut env = BTreeMap::new();
+        env.insert("FOO".to_string(), "bar".to_string());
+        let spawn = Spawn { id: "redis.default".to_string(),
+                            binary: "/bin/run".to_string(),
+                            svc_user: Some("hab".to_string()),
+                            svc_group: Some("hab".to_string()),
+                            svc_password: None,
+                            env,
+                            svc_user_id: Some(42),
+                            svc_group_id: Some(42) };
+        assert_eq!(round_trip(&spawn), spawn);
+        assert_eq!(round_trip(&SpawnOk { pid: 42 }), SpawnOk { pid: 42 });
+        assert_eq!(round_trip(&Terminate { pid: 42 }), Terminate { pid: 42 });
+
+        let terminate_ok = TerminateOk { exit_code:       1,
+                                         shutdown_method: ShutdownMethod::Killed, };
+        assert_eq!(round_trip(&terminate_ok), terminate_ok);
+        assert_eq!(round_trip(&Shutdown {}), Shutdown {});
+        assert_eq!(round_trip(&Version {}), Version {});
+
+        let pid_of = round_trip(&PidOf { service_name: "redis.default".to_string(), });
+        assert_eq!(pid_of.service_name, "redis.default");
+        assert_eq!(round_trip(&PidIs { pid: Some(42) }).pid, Some(42));
+        assert_eq!(round_trip(&PidIs { pid: None }).pid, None);
+        assert_eq!(round_trip(&VersionNumber { version: 14227 }).version, 14227);
+    }
+
+    /// A `Register` exactly as sent by a Supervisor that predates
+    /// protocol versioning, with a pipe name of "pipe-1".
+    #[rustfmt::skip]
+    const LEGACY_REGISTER: &[u8] = &[
+        0x0a, 0x08, b'R', b'e', b'g', b'i', b's', b't', b'e', b'r',
+        0x12, 0x08, 0x0a, 0x06, b'p', b'i', b'p', b'e', b'-', b'1',
+    ];
+
+    /// The `NetOk` exactly as sent by a Launcher that predates
+    /// protocol versioning in response to `Register`.
+    #[rustfmt::skip]
+    const LEGACY_REGISTER_REPLY: &[u8] = &[
+        0x0a, 0x05, b'N', b'e', b't', b'O', b'k',
+        0x12, 0x00,
+    ];
+
+    #[test]
+    fn old_supervisor_registration_negotiates_legacy_version() {
+        let txn = NetTxn::from_bytes(LEGACY_REGISTER).unwrap();
+        assert_eq!(txn.message_id(), "Register");
+        let register = txn.decode::<Register>().unwrap();
+        assert_eq!(register.pipe, "pipe-1");
+        assert_eq!(register.protocol_version, None);
+        assert_eq!(register.min_protocol_version, None);
+
+        let version = negotiate_protocol_version(register.protocol_version,
+                                                 register.min_protocol_version).unwrap();
+        assert_eq!(version, LEGACY_PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn old_launcher_registration_reply_negotiates_legacy_version() {
+        let txn = NetTxn::from_bytes(LEGACY_REGISTER_REPLY).unwrap();
+        assert_eq!(txn.message_id(), "NetOk");
+        let reply = txn.decode::<RegisterOk>().unwrap();
+        assert_eq!(reply, RegisterOk::default());
+
+        let version =
+            negotiate_protocol_version(reply.protocol_version, reply.min_protocol_version).unwrap();
+        assert_eq!(version, LEGACY_PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn old_supervisor_can_read_new_registration_reply() {
+        let reply = RegisterOk { protocol_version:     Some(LEGACY_PROTOCOL_VERSION),
+                                 min_protocol_version: Some(MIN_PROTOCOL_VERSION), };
+        let bytes = NetTxn::build(&reply).unwrap().to_bytes().unwrap();
+        // Older Supervisors don't look at the message id here; they
+        // just decode the payload as a `NetOk`.
+        NetTxn::from_bytes(&bytes).unwrap()
+                                  .decode::<NetOk>()
+                                  .unwrap();
+    }
+
+    #[test]
+    fn unknown_messages_still_decode_as_envelopes() {
+        let env = Envelope { message_id: "SomeFutureMessage".to_string(),
+                             payload:    vec![0x08, 0x01], };
+        let bytes = env.to_bytes().unwrap();
+        let txn = NetTxn::from_bytes(&bytes).unwrap();
+        assert_eq!(txn.message_id(), "SomeFutureMessage");
+    }
+
+    #[test]
+    fn negotiation_picks_newest_common_version() {
+        assert_eq!(negotiate(3, 1, Some(2), Some(1)).unwrap(), 2);
+        assert_eq!(negotiate(2, 1, Some(3), Some(2)).unwrap(), 2);
+        assert_eq!(negotiate(2, 2, Some(2), None).unwrap(), 2);
+        assert_eq!(negotiate(2, 1, None, None).unwrap(),
+                   LEGACY_PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn negotiation_fails_without_common_version() {
+        match negotiate(4, 3, Some(2), Some(1)) {
+            Err(Error::IncompatibleProtocolVersion { local_version: 4,
+                                                     local_min_version: 3,
+                                                     peer_version: 2,
+                                                     peer_min_version: 1, }) => {}
+            other => panic!("Expected an incompatible version error, got {:?}", other),
+        }
+        assert!(negotiate(2, 1, Some(4), Some(3)).is_err());
+        assert!(negotiate(3, 2, None, None).is_err());
+    }
+}
@@ -1,6 +1,8 @@
 use crate::{error::{Error,
                     Result},
-            generated};
+            generated,
+            MIN_PROTOCOL_VERSION,
+            PROTOCOL_VERSION};
 use prost::Message;
 use std::{collections::BTreeMap,
           fmt};
@@ -82,11 +84,21 @@ impl From<NetOk> for generated::NetOk {
 
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct Register {
-    pub pipe: String,
+    pub pipe:                 String,
+    /// The newest protocol version the Supervisor speaks. `None` when
+    /// registering from a Supervisor that predates protocol
+    /// versioning.
+    pub protocol_version:     Option<u32>,
+    /// The oldest protocol version the Supervisor can still speak.
+    pub min_protocol_version: Option<u32>,
 }
 
 impl Register {
-    pub fn new(pipe: String) -> Self { Register { pipe } }
+    pub fn new(pipe: String) -> Self {
+        Register { pipe,
+                   protocol_version: Some(PROTOCOL_VERSION),
+                   min_protocol_version: Some(MIN_PROTOCOL_VERSION) }
+    }
 }
 
 impl LauncherMessage for Register {
@@ -95,12 +107,49 @@ impl LauncherMessage for Register {
     const MESSAGE_ID: &'static str = "Register";
 
     fn from_proto(proto: generated::Register) -> Result<Self> {
-        Ok(Register { pipe: proto.pipe.ok_or(Error::ProtocolMismatch("pipe"))?, })
+        Ok(Register { pipe:                 proto.pipe.ok_or(Error::ProtocolMismatch("pipe"))?,
+                      protocol_version:     proto.protocol_version,
+                      min_protocol_version: proto.min_protocol_version, })
     }
 }
 
 impl From<Register> for generated::Register {
-    fn from(value: Register) -> Self { generated::Register { pipe: Some(value.pipe), } }
+    fn from(value: Register) -> Self {
+        generated::Register { pipe:                 Some(value.pipe),
+                              protocol_version:     value.protocol_version,
+                              min_protocol_version: value.min_protocol_version, }
+    }
+}
+
+/// The Launcher's reply to a `Register` message.
+///
+/// An older Launcher replies to `Register` with a plain `NetOk`, which
+/// decodes as a `RegisterOk` with neither version set.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RegisterOk {
+    /// The protocol version both sides will use for the rest of the
+    /// connection.
+    pub protocol_version:     Option<u32>,
+    /// The oldest protocol version the Launcher can still speak.
+    pub min_protocol_version: Option<u32>,
+}
+
+impl LauncherMessage for RegisterOk {
+    type Generated = generated::RegisterOk;
+
+    const MESSAGE_ID: &'static str = "RegisterOk";
+
+    fn from_proto(proto: generated::RegisterOk) -> Result<Self> {
+        Ok(RegisterOk { protocol_version:     proto.protocol_version,
+                        min_protocol_version: proto.min_protocol_version, })
+    }
+}
+
+impl From<RegisterOk> for generated::RegisterOk {
+    fn from(value: RegisterOk) -> Self {
+        generated::RegisterOk { protocol_version:     value.protocol_version,
+                                min_protocol_version: value.min_protocol_version, }
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq)]
@@ -462,6 +462,11 @@ pub fn run(args: Vec<String>) -> Result<i32> {
                 error!("Launcher will attempt to reload the supervisor: {:?}", err);
                 match server.reload() {
                     Ok(_) => {}
+                    Err(err) if is_protocol_version_mismatch(&err) => {
+                        // Reloading the same supervisor binary will
+                        // never make the versions line up.
+                        break checked_thread.unregister(Err(err));
+                    }
                     Err(err) => {
                         error!("Launcher failed to reload supervisor: {:?}", err);
                         thread::sleep(Duration::from_millis(1_000));
@@ -495,6 +500,11 @@ pub fn send<T>(tx: &Sender, msg: &T) -> Result<()>
 // Private Func
 //
 
+fn is_protocol_version_mismatch(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<protocol::Error>(),
+             Some(protocol::Error::IncompatibleProtocolVersion { .. }))
+}
+
 fn dispatch(tx: &Sender, bytes: &[u8], services: &mut ServiceTable) {
     let msg = match protocol::NetTxn::from_bytes(bytes) {
         Ok(msg) => msg,
@@ -526,19 +536,30 @@ fn dispatch(tx: &Sender, bytes: &[u8], services: &mut ServiceTable) {
     func(tx, msg, services);
 }
 
+/// Marks the connect thread of `setup_connection` finished, and wakes
+/// the thread waiting on it, however it finishes.
+struct NotifyOnDrop(Arc<(Mutex<bool>, Condvar)>);
+
+impl Drop for NotifyOnDrop {
+    fn drop(&mut self) {
+        let (ref lock, ref cvar) = *self.0;
+        // Still notify if the lock was poisoned, so the waiter isn't
+        // left waiting out the timeout.
+        *lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = true;
+        debug!("Connect thread finished; notifying waiting thread");
+        cvar.notify_one();
+    }
+}
+
 #[allow(clippy::mutex_atomic)] // A Mutex is required for Condvar::wait_timeout
 fn setup_connection(server: IpcOneShotServer<Vec<u8>>) -> Result<(Receiver, Sender)> {
     let pair = Arc::new((Mutex::new(false), Condvar::new()));
-    let pair2 = pair.clone();
+    let notify_on_drop = NotifyOnDrop(pair.clone());
 
     // Set up the connection in a separate thread because ipc-channel doesn't support timeouts
     let handle = thread::spawn(move || {
-        {
-            let (ref lock, _) = *pair2;
-            let mut started = lock.lock().unwrap();
-            *started = true;
-            debug!("connect thread started");
-        }
+        let _notify_on_drop = notify_on_drop;
+        debug!("connect thread started");
         let (rx, raw) = server.accept()
                               .context("Failed to accept IPC connection from supervisor")?;
         let txn = protocol::NetTxn::from_bytes(&raw).map_err(|err| {
@@ -555,12 +576,25 @@ fn setup_connection(server: IpcOneShotServer<Vec<u8>>) -> Result<(Receiver, Send
                                                      })?;
         let tx = IpcSender::connect(msg.pipe).context("Failed to establish IPC connection to \
                                                        the supervisor")?;
-        send(&tx, &protocol::NetOk::default())?;
-        {
-            let (_, ref cvar) = *pair2;
-            debug!("Connect thread finished; notifying waiting thread");
-            cvar.notify_one();
-        }
+        let negotiated =
+            protocol::negotiate_protocol_version(msg.protocol_version, msg.min_protocol_version);
+        let protocol_version = match negotiated {
+            Ok(version) => version,
+            Err(err) => {
+                error!("Refusing supervisor registration: {}", err);
+                let reply = protocol::NetErr { code: protocol::ErrCode::IncompatibleProtocolVersion,
+                                               msg:  err.to_string(), };
+                if let Err(send_err) = send(&tx, &reply) {
+                    warn!("Failed to notify supervisor of protocol version mismatch: {}",
+                          send_err);
+                }
+                return Err(anyhow::Error::new(err));
+            }
+        };
+        debug!("Negotiated launcher protocol version {}", protocol_version);
+        send(&tx,
+             &protocol::RegisterOk { protocol_version:     Some(protocol_version),
+                                     min_protocol_version: Some(protocol::MIN_PROTOCOL_VERSION), })?;
         Ok((rx, tx))
     });
 
@@ -571,16 +605,21 @@ fn setup_connection(server: IpcOneShotServer<Vec<u8>>) -> Result<(Receiver, Send
                                                 .unwrap_or(DEFAULT_IPC_CONNECT_TIMEOUT_SECS);
 
     debug!("Waiting on connect thread for {} secs", timeout_secs);
-    let (started, wait_result) = cvar.wait_timeout(lock.lock().expect("IPC connection startup \
-                                                                       lock poisoned"),
-                                                   Duration::from_secs(timeout_secs))
-                                     .expect("IPC connection startup lock poisoned");
-
-    if *started && !wait_result.timed_out() {
+    // The thread may well have finished before we get here, so only
+    // wait while it hasn't.
+    let (finished, _) = cvar.wait_timeout_while(lock.lock().expect("IPC connection startup lock \
+                                                                    poisoned"),
+                                                Duration::from_secs(timeout_secs),
+                                                |finished| !*finished)
+                            .expect("IPC connection startup lock poisoned");
+
+    if *finished {
+        drop(finished);
+        // Whether the connection was set up or refused, the thread's
+        // result says why.
         handle.join().unwrap()
     } else {
-        debug!("Timeout exceeded waiting for IPC connection (started: {})",
-               *started);
+        debug!("Timeout exceeded waiting for IPC connection");
         Err(anyhow!("Timeout exceeded waiting for IPC connection from supervisor"))
     }
 }
@@ -692,3 +731,32 @@ fn supervisor_cmd() -> Result<PathBuf> {
         Err(_) => Err(anyhow!("Failed to locate supervisor package, {}", SUP_PACKAGE_IDENT)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn incompatible_supervisors_are_refused_with_a_protocol_version_mismatch() {
+        let (server, pipe_to_launcher) = IpcOneShotServer::<Vec<u8>>::new().unwrap();
+        // Play the part of a Supervisor that only speaks protocol
+        // versions far newer than this Launcher does
+        let sup = thread::spawn(move || {
+            let tx = Sender::connect(pipe_to_launcher).unwrap();
+            let (reply_server, pipe_to_sup) = IpcOneShotServer::<Vec<u8>>::new().unwrap();
+            let register = protocol::Register { pipe:                 pipe_to_sup,
+                                                protocol_version:     Some(1_000),
+                                                min_protocol_version: Some(1_000), };
+            send(&tx, &register).unwrap();
+            let (_, raw) = reply_server.accept().unwrap();
+            protocol::NetTxn::from_bytes(&raw).unwrap()
+                                              .decode::<protocol::NetErr>()
+                                              .unwrap()
+        });
+
+        let err = setup_connection(server).unwrap_err();
+        assert!(is_protocol_version_mismatch(&err), "{:?}", err);
+        assert_eq!(sup.join().unwrap().code,
+                   protocol::ErrCode::IncompatibleProtocolVersion);
+    }
+}
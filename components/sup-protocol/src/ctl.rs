@@ -28,6 +28,22 @@ impl message::MessageStatic for SupRestart {
     const MESSAGE_ID: &'static str = "SupRestart";
 }
 
+impl message::MessageStatic for SupRingKeyReload {
+    const MESSAGE_ID: &'static str = "SupRingKeyReload";
+}
+
+impl message::MessageStatic for RingKeyRevisions {
+    const MESSAGE_ID: &'static str = "RingKeyRevisions";
+}
+
+impl message::MessageStatic for SupConverge {
+    const MESSAGE_ID: &'static str = "SupConverge";
+}
+
+impl message::MessageStatic for ConvergencePlan {
+    const MESSAGE_ID: &'static str = "ConvergencePlan";
+}
+
 impl message::MessageStatic for SvcFilePut {
     const MESSAGE_ID: &'static str = "SvcFilePut";
 }
@@ -36,6 +52,22 @@ impl message::MessageStatic for SvcGetDefaultCfg {
     const MESSAGE_ID: &'static str = "SvcGetDefaultCfg";
 }
 
+impl message::MessageStatic for SvcGetEffectiveCfg {
+    const MESSAGE_ID: &'static str = "SvcGetEffectiveCfg";
+}
+
+impl message::MessageStatic for ServiceEffectiveCfg {
+    const MESSAGE_ID: &'static str = "ServiceEffectiveCfg";
+}
+
+impl message::MessageStatic for SvcHealthCheckRun {
+    const MESSAGE_ID: &'static str = "SvcHealthCheckRun";
+}
+
+impl message::MessageStatic for ServiceHealthCheck {
+    const MESSAGE_ID: &'static str = "ServiceHealthCheck";
+}
+
 impl message::MessageStatic for SvcValidateCfg {
     const MESSAGE_ID: &'static str = "SvcValidateCfg";
 }
@@ -90,3 +122,46 @@ impl Into<Vec<habitat_core::service::ServiceBind>> for ServiceBindList {
         self.binds.into_iter().map(Into::into).collect()
     }
 }
+
+impl SvcUpdate {
+    /// The names of the spec fields that `unset` may reset to their defaults.
+    /// The Supervisor's `ServiceSpec::merge_svc_update` must handle each
+    /// one, and its tests check that every field it merges is listed here.
+    pub const UNSETTABLE_FIELDS: &'static [&'static str] = &["bldr_url",
+                                                             "channel",
+                                                             "topology",
+                                                             "update_strategy",
+                                                             "update_condition",
+                                                             "binds",
+                                                             "binding_mode",
+                                                             "health_check_interval",
+                                                             "health_check_splay",
+                                                             "shutdown_timeout",
+                                                             "svc_encrypted_password",
+                                                             "signer_verification",
+                                                             "peer_file",
+                                                             "peer_file_template"];
+}
+
+impl fmt::Display for converge_action::Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = match *self {
+            converge_action::Kind::Load => "load",
+            converge_action::Kind::Unload => "unload",
+            converge_action::Kind::Update => "update",
+            converge_action::Kind::ApplyConfig => "apply-config",
+        };
+        write!(f, "{}", value)
+    }
+}
+
+impl fmt::Display for converge_action::Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = match *self {
+            converge_action::Status::Planned => "planned",
+            converge_action::Status::Applied => "applied",
+            converge_action::Status::Failed => "failed",
+        };
+        write!(f, "{}", value)
+    }
+}
@@ -79,6 +79,18 @@ impl fmt::Display for ProcessState {
     }
 }
 
+impl fmt::Display for HealthCheckResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let result = match *self {
+            HealthCheckResult::Ok => "OK",
+            HealthCheckResult::Warning => "WARNING",
+            HealthCheckResult::Critical => "CRITICAL",
+            HealthCheckResult::Unknown => "UNKNOWN",
+        };
+        write!(f, "{}", result)
+    }
+}
+
 impl fmt::Display for DesiredState {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let state = match *self {
@@ -354,6 +366,35 @@ impl fmt::Display for UpdateCondition {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "{}", self.as_str()) }
 }
 
+impl SignerVerification {
+    pub const VARIANTS: &'static [&'static str] = &["off", "permissive", "strict"];
+
+    pub fn as_str(&self) -> &str {
+        match *self {
+            SignerVerification::Off => "off",
+            SignerVerification::Permissive => "permissive",
+            SignerVerification::Strict => "strict",
+        }
+    }
+}
+
+impl FromStr for SignerVerification {
+    type Err = NetErr;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "off" => Ok(SignerVerification::Off),
+            "permissive" => Ok(SignerVerification::Permissive),
+            "strict" => Ok(SignerVerification::Strict),
+            _ => Err(net::err(ErrCode::InvalidPayload, "Invalid signer verification.")),
+        }
+    }
+}
+
+impl fmt::Display for SignerVerification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "{}", self.as_str()) }
+}
+
 #[cfg(test)]
 mod test {
     use serde::{Deserialize,
@@ -469,4 +510,36 @@ mod test {
 
         assert!(toml.starts_with(r#"key = "at-once""#));
     }
+
+    #[test]
+    fn signer_verification_default() {
+        // Verification is opt-in; if this default gets changed, we have a failing test to
+        // confirm we changed our minds
+        assert_eq!(SignerVerification::default(), SignerVerification::Off);
+    }
+
+    #[test]
+    fn signer_verification_from_str() {
+        assert_eq!(SignerVerification::from_str("off").unwrap(),
+                   SignerVerification::Off);
+        assert_eq!(SignerVerification::from_str("permissive").unwrap(),
+                   SignerVerification::Permissive);
+        assert_eq!(SignerVerification::from_str("strict").unwrap(),
+                   SignerVerification::Strict);
+        assert!(SignerVerification::from_str("dope").is_err());
+    }
+
+    #[test]
+    fn signer_verification_toml_round_trip() {
+        #[derive(Deserialize, Serialize)]
+        struct Data {
+            key: SignerVerification,
+        }
+        let data = Data { key: SignerVerification::Strict, };
+        let toml = toml::to_string(&data).unwrap();
+        assert!(toml.starts_with(r#"key = "strict""#));
+
+        let data: Data = toml::from_str(&toml).unwrap();
+        assert_eq!(data.key, SignerVerification::Strict);
+    }
 }
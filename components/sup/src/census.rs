@@ -17,7 +17,10 @@ use habitat_core::{self,
                    crypto::keys::KeyCache,
                    package::PackageIdent,
                    service::ServiceGroup};
+use lazy_static::lazy_static;
 use log::warn;
+use prometheus::{register_int_gauge_vec,
+                 IntGaugeVec};
 use serde::{ser::SerializeStruct,
             Serialize,
             Serializer};
@@ -33,6 +36,13 @@ use std::{borrow::Cow,
 
 static LOGKEY: &str = "CE";
 
+lazy_static! {
+    static ref CENSUS_GROUP_GENERATION: IntGaugeVec =
+        register_int_gauge_vec!("hab_sup_census_group_generation",
+                                "The number of material changes seen in a service group's census",
+                                &["service_group"]).unwrap();
+}
+
 pub type MemberId = String;
 
 #[derive(Debug, Serialize)]
@@ -95,6 +105,9 @@ impl CensusRing {
             self.update_from_election_update_store_rsr(election_update_rumors);
             self.update_from_service_config_rsr(key_cache, service_config_rumors);
             self.update_from_service_files_rsr(key_cache, service_file_rumors);
+            for census_group in self.census_groups.values_mut() {
+                census_group.bump_generation_if_changed();
+            }
 
             // Update our counters to reflect current state.
             self.last_membership_counter = member_list.get_update_counter();
@@ -148,10 +161,7 @@ impl CensusRing {
 
         member_list.with_memberships_mlr(|Membership { member, health }| {
                        for group in self.census_groups.values_mut() {
-                           if let Some(census_member) = group.find_member_mut(&member.id) {
-                               census_member.update_from_member(&member);
-                               census_member.update_from_health(health);
-                           }
+                           group.update_from_membership(&member, health);
                        }
                        Ok(())
                    })
@@ -322,6 +332,10 @@ pub struct CensusGroup {
     update_leader_id:      Option<MemberId>,
     changed_service_files: HashSet<String>,
     service_files:         HashMap<String, ServiceFile>,
+    generation:            u64,
+    /// Set by the `update_from_*` functions when they change anything
+    /// that `generation` tracks.
+    changed:               bool,
 }
 
 impl CensusGroup {
@@ -336,7 +350,30 @@ impl CensusGroup {
                       update_leader_id:       None,
                       service_config:         None,
                       service_files:          HashMap::new(),
-                      changed_service_files:  HashSet::new(), }
+                      changed_service_files:  HashSet::new(),
+                      generation:             0,
+                      changed:                true, }
+    }
+
+    /// A counter that is bumped each time the census is updated from
+    /// rumors and something that templates or binds can see has
+    /// changed: the group's members or their health, exported
+    /// configuration, or system information, its leaders or election
+    /// status, its package incarnation, its gossiped configuration,
+    /// or its service files. Rumors that change none of these (an
+    /// unchanged service rumor gossiped again, or an update to some
+    /// other group) leave it alone, so a service can skip
+    /// re-rendering while the generations of the groups it depends on
+    /// stay the same.
+    pub fn generation(&self) -> u64 { self.generation }
+
+    fn bump_generation_if_changed(&mut self) {
+        if self.changed {
+            self.generation += 1;
+            self.changed = false;
+            CENSUS_GROUP_GENERATION.with_label_values(&[self.service_group.as_ref()])
+                                   .set(self.generation as i64);
+        }
     }
 
     /// Returns the census member in the census ring for the running Supervisor.
@@ -419,22 +456,40 @@ impl CensusGroup {
             // the update leader can increment the incarnation of its service rumor.
             if service_rumor.pkg_incarnation > self.pkg_incarnation {
                 self.pkg_incarnation = service_rumor.pkg_incarnation;
+                self.changed = true;
             }
             // Yeah - we are ourself - we're alive.
             let is_self = member_id == &self.local_member_id;
+            let mut is_new = false;
             let member = self.population
                              .entry(member_id.to_string())
                              .or_insert_with(|| {
+                                 is_new = true;
                                  // Note: this is where CensusMembers are created
                                  CensusMember { alive: is_self,
                                                 ..Default::default() }
                              });
+            let before = member.clone();
             member.update_from_service_rumor(&self.service_group, service_rumor);
+            if is_new || *member != before {
+                self.changed = true;
+            }
+        }
+    }
+
+    fn update_from_membership(&mut self, member: &Member, health: Health) {
+        if let Some(census_member) = self.population.get_mut(&member.id) {
+            let before = census_member.clone();
+            census_member.update_from_member(member);
+            census_member.update_from_health(health);
+            if *census_member != before {
+                self.changed = true;
+            }
         }
     }
 
     fn update_from_election_rumor(&mut self, election: &ElectionRumor) {
-        self.leader_id = None;
+        let before = (self.leader_id.take(), self.election_status);
         for census_member in self.population.values_mut() {
             if census_member.update_from_election_rumor(election) {
                 self.leader_id = Some(census_member.member_id.clone());
@@ -451,10 +506,13 @@ impl CensusGroup {
                 self.election_status = ElectionStatus::ElectionFinished;
             }
         }
+        if (&self.leader_id, self.election_status) != (&before.0, before.1) {
+            self.changed = true;
+        }
     }
 
     fn update_from_election_update_rumor(&mut self, election: &ElectionUpdateRumor) {
-        self.update_leader_id = None;
+        let before = (self.update_leader_id.take(), self.update_election_status);
         for census_member in self.population.values_mut() {
             if census_member.update_from_election_update_rumor(election) {
                 self.update_leader_id = Some(census_member.member_id.clone());
@@ -471,6 +529,9 @@ impl CensusGroup {
                 self.update_election_status = ElectionStatus::ElectionFinished;
             }
         }
+        if (&self.update_leader_id, self.update_election_status) != (&before.0, before.1) {
+            self.changed = true;
+        }
     }
 
     fn update_from_service_config_rumor(&mut self,
@@ -484,6 +545,7 @@ impl CensusGroup {
                     self.service_config = Some(ServiceConfig { incarnation:
                                                                    service_config.incarnation,
                                                                value:       config, });
+                    self.changed = true;
                 }
             }
             Err(err) => warn!("{}", err),
@@ -505,6 +567,7 @@ impl CensusGroup {
                 match service_file_rumor.body(key_cache) {
                     Ok(body) => {
                         self.changed_service_files.insert(filename.clone());
+                        self.changed = true;
                         file.filename = filename.clone();
                         file.incarnation = service_file_rumor.incarnation;
                         file.body = body;
@@ -521,10 +584,6 @@ impl CensusGroup {
         }
     }
 
-    fn find_member_mut(&mut self, member_id: &str) -> Option<&mut CensusMember> {
-        self.population.get_mut(member_id)
-    }
-
     /// Determine what configuration keys the group as a whole
     /// exports. Returns a set of the top-level exported keys.
     ///
@@ -573,7 +632,7 @@ impl Serialize for CensusGroup {
 // User-facing documentation is available at
 // https://www.habitat.sh/docs/reference/#template-data; update that
 // as required.
-#[derive(Clone, Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
 pub struct CensusMember {
     pub member_id: MemberId,
     pub pkg: PackageIdent,
@@ -791,13 +850,102 @@ mod tests {
         assert_valid(&json, "http_gateway_census_schema.json");
     }
 
+    #[test]
+    fn generation_only_changes_with_the_census() {
+        let pg_id = PackageIdent::new("starkandwayne",
+                                      "shield",
+                                      Some("0.10.4"),
+                                      Some("20170419115548"));
+        let sg_one = ServiceGroup::new("shield", "one", None).unwrap();
+        let sg_two = ServiceGroup::new("shield", "two", None).unwrap();
+        let key_cache = KeyCache::new(&*CACHE_KEY_PATH);
+        let service_store: RumorStore<ServiceRumor> = RumorStore::default();
+        let election_store: RumorStore<ElectionRumor> = RumorStore::default();
+        let election_update_store: RumorStore<ElectionUpdateRumor> = RumorStore::default();
+        let member_list = MemberList::new();
+        let service_config_store: RumorStore<ServiceConfigRumor> = RumorStore::default();
+        let service_file_store: RumorStore<ServiceFileRumor> = RumorStore::default();
+        let mut ring = CensusRing::new("member-a".to_string());
+        let update = |ring: &mut CensusRing| {
+            ring.update_from_rumors_rsr_mlr(&key_cache,
+                                            &service_store,
+                                            &election_store,
+                                            &election_update_store,
+                                            &member_list,
+                                            &service_config_store,
+                                            &service_file_store);
+        };
+        let generation = |ring: &CensusRing, sg: &ServiceGroup| {
+            ring.census_group_for(sg).map(CensusGroup::generation)
+        };
+
+        service_store.insert_rsw(ServiceRumor::new("member-a",
+                                                   &pg_id,
+                                                   sg_one.clone(),
+                                                   SysInfo::default(),
+                                                   None));
+        update(&mut ring);
+        assert_eq!(generation(&ring, &sg_one), Some(1));
+
+        // The same service rumor gossiped again is news to the
+        // census ring, but doesn't change the group.
+        let mut rumor =
+            ServiceRumor::new("member-a", &pg_id, sg_one.clone(), SysInfo::default(), None);
+        rumor.incarnation = 1;
+        service_store.insert_rsw(rumor);
+        update(&mut ring);
+        assert!(ring.changed());
+        assert_eq!(generation(&ring, &sg_one), Some(1));
+
+        // A new group doesn't change the existing one.
+        service_store.insert_rsw(ServiceRumor::new("member-b",
+                                                   &pg_id,
+                                                   sg_two.clone(),
+                                                   SysInfo::default(),
+                                                   None));
+        update(&mut ring);
+        assert_eq!(generation(&ring, &sg_one), Some(1));
+        assert_eq!(generation(&ring, &sg_two), Some(1));
+
+        // New exported configuration does.
+        let mut cfg = toml::value::Table::new();
+        cfg.insert("port".to_string(), toml::Value::Integer(1234));
+        let mut rumor = ServiceRumor::new("member-a",
+                                          &pg_id,
+                                          sg_one.clone(),
+                                          SysInfo::default(),
+                                          Some(cfg));
+        rumor.incarnation = 2;
+        service_store.insert_rsw(rumor);
+        update(&mut ring);
+        assert_eq!(generation(&ring, &sg_one), Some(2));
+        assert_eq!(generation(&ring, &sg_two), Some(1));
+
+        // So does a change in leadership.
+        let mut election = ElectionRumor::new("member-a",
+                                              &sg_one,
+                                              election::Term::default(),
+                                              10,
+                                              true /* has_quorum */);
+        election.finish();
+        election_store.insert_rsw(election);
+        update(&mut ring);
+        assert_eq!(generation(&ring, &sg_one), Some(3));
+        assert_eq!(generation(&ring, &sg_two), Some(1));
+
+        // With no new rumors at all, nothing changes.
+        update(&mut ring);
+        assert!(!ring.changed());
+        assert_eq!(generation(&ring, &sg_one), Some(3));
+    }
+
     fn test_census_ring() -> (CensusRing, ServiceGroup, ServiceGroup) {
         let sys_info = SysInfo { ip: "1.2.3.4".to_string(),
                                  hostname: "hostname".to_string(),
                                  gossip_ip: "0.0.0.0".to_string(),
                                  gossip_port: 7777,
-                                 http_gateway_ip: "0.0.0.0".to_string(),
-                                 http_gateway_port: 9631,
+                                 http_gateway_ip: Some("0.0.0.0".to_string()),
+                                 http_gateway_port: Some(9631),
                                  ..Default::default() };
         let pg_id = PackageIdent::new("starkandwayne",
                                       "shield",
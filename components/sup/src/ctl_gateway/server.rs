@@ -304,20 +304,38 @@ impl SrvHandler {
     }
 
     /// # Locking (see locking.md)
-    /// * `GatewayState::inner` (read)
+    /// * `GatewayState::inner` (write)
     /// * `ManagerServices::inner` (read)
-    async fn command_from_message_gsr_msr(msg: &SrvMessage,
-                                          ctl_sender: CtlSender)
-                                          -> std::result::Result<CtlCommand, HandlerError> {
+    /// * `Server::ring_keys` (write)
+    async fn command_from_message_gsw_msr_srkw(msg: &SrvMessage,
+                                               ctl_sender: CtlSender)
+                                               -> std::result::Result<CtlCommand, HandlerError>
+    {
         match msg.message_id() {
             "SvcGetDefaultCfg" => util::to_command(msg, ctl_sender, commands::service_cfg_msr),
-            "SvcFilePut" => util::to_command(msg, ctl_sender, commands::service_file_put),
-            "SvcSetCfg" => util::to_command(msg, ctl_sender, commands::service_cfg_set),
+            "SvcGetEffectiveCfg" => {
+                util::to_command(msg, ctl_sender, commands::service_effective_cfg_msr)
+            }
+            "SvcHealthCheckRun" => {
+                let m = msg.parse::<protocol::ctl::SvcHealthCheckRun>()
+                           .map_err(HandlerError::from)?;
+                Ok(CtlCommand::new(ctl_sender,
+                                   msg.transaction(),
+                                   move |state, req, _action_sender| {
+                                       task::block_in_place(|| {
+                                           executor::block_on(commands::service_health_check_run_msr(state,
+                                                                                                     req,
+                                                                                                     m.clone()))
+                                       })
+                                   }))
+            }
+            "SvcFilePut" => util::to_command(msg, ctl_sender, commands::service_file_put_srkr),
+            "SvcSetCfg" => util::to_command(msg, ctl_sender, commands::service_cfg_set_srkr),
             "SvcValidateCfg" => util::to_command(msg, ctl_sender, commands::service_cfg_validate),
             "SvcLoad" => {
-                // This arm doesn't use a `util` module helper because
-                // it's currently the only thing that behaves like
-                // this.
+                // This arm, `SvcHealthCheckRun` and `SupConverge` don't
+                // use a `util` module helper because they're currently
+                // the only things that behave like this.
                 let m = msg.parse::<protocol::ctl::SvcLoad>()
                            .map_err(HandlerError::from)?;
                 Ok(CtlCommand::new(ctl_sender,
@@ -340,8 +358,27 @@ impl SrvHandler {
             "SvcStart" => util::to_command(msg, ctl_sender, commands::service_start),
             "SvcStop" => util::to_supervisor_command(msg, ctl_sender, commands::service_stop),
             "SvcStatus" => util::to_command(msg, ctl_sender, commands::service_status_gsr),
-            "SupDepart" => util::to_command(msg, ctl_sender, commands::supervisor_depart),
+            "SupDepart" => util::to_command(msg, ctl_sender, commands::supervisor_depart_srkr),
             "SupRestart" => util::to_command(msg, ctl_sender, commands::supervisor_restart),
+            "SupRingKeyReload" => {
+                util::to_command(msg,
+                                 ctl_sender,
+                                 commands::supervisor_ring_key_reload_gsw_srkw)
+            }
+            "SupConverge" => {
+                let m = msg.parse::<protocol::ctl::SupConverge>()
+                           .map_err(HandlerError::from)?;
+                Ok(CtlCommand::new(ctl_sender,
+                                   msg.transaction(),
+                                   move |state, req, action_sender| {
+                                       task::block_in_place(|| {
+                                           executor::block_on(commands::supervisor_converge_gsr_srkr(state,
+                                                                                                req,
+                                                                                                m.clone(),
+                                                                                                &action_sender))
+                                       })
+                                   }))
+            }
             _ => {
                 warn!("Unhandled message, {}", msg.message_id());
                 Err(HandlerError::from(io::Error::from(io::ErrorKind::InvalidData)))
@@ -375,8 +412,9 @@ impl Future for SrvHandler {
                             self.start_timer(msg.message_id());
                             trace!("OnMessage, {}", msg.message_id());
 
-                            let fut =
-                                Self::command_from_message_gsr_msr(&msg, self.ctl_sender.clone());
+                            let fut = Self::command_from_message_gsw_msr_srkw(&msg,
+                                                                              self.ctl_sender
+                                                                                  .clone());
                             tokio::pin!(fut);
                             let cmd = match futures::ready!(fut.poll_unpin(cx)) {
                                 Ok(cmd) => cmd,
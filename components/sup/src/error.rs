@@ -50,9 +50,12 @@ pub enum Error {
     HabitatCore(habitat_core::Error),
     InvalidBinds(Vec<String>),
     InvalidCertFile(PathBuf),
+    InvalidDesiredState(String),
     InvalidHealthCheckResult(i32),
     InvalidKeyFile(PathBuf),
     InvalidKeyParameter(String),
+    InvalidPeerFile(String),
+    InvalidPeerFileTemplate(String),
     InvalidPidFile,
     InvalidTopology(String),
     InvalidUpdateStrategy(String),
@@ -61,6 +64,7 @@ pub enum Error {
     LauncherIPCCommand(habitat_launcher_client::IPCCommandError),
     LauncherTryIPCCommand(habitat_launcher_client::TryIPCCommandError),
     LockFileError(crate::lock_file::Error),
+    MissingPackageSignerMetadata(package::PackageIdent),
     MissingRequiredBind(Vec<String>),
     MissingRequiredIdent,
     NameLookup(io::Error),
@@ -73,6 +77,7 @@ pub enum Error {
     NotifyError(notify::Error),
     NulError(ffi::NulError),
     OneshotCanceled(oneshot::Canceled),
+    PackageArtifactHashMismatch(package::PackageIdent, String, String),
     PackageNotFound(package::PackageIdent),
     PackageNotRunnable(package::PackageIdent),
     Permissions(String),
@@ -93,7 +98,9 @@ pub enum Error {
     TLSError(rustls::Error),
     TomlEncode(toml::ser::Error),
     TryRecvError(mpsc::TryRecvError),
+    UnknownPackageSigner(package::PackageIdent, String),
     UnpackFailed,
+    UnsupportedHttpListen(String, &'static str),
     UserNotFound(String),
     WithDuration(Box<Self>, Duration),
 }
@@ -175,6 +182,7 @@ impl fmt::Display for Error {
             Error::GroupNotFound(ref e) => format!("No GID for group '{}' could be found", e),
             Error::InvalidBinds(ref e) => format!("Invalid bind(s), {}", e.join(", ")),
             Error::InvalidCertFile(ref path) => format!("Invalid cert file: {}", path.display()),
+            Error::InvalidDesiredState(ref e) => format!("Invalid desired state: {}", e),
             Error::InvalidHealthCheckResult(code) => {
                 format!("Invalid health check result: {}", code)
             }
@@ -182,6 +190,10 @@ impl fmt::Display for Error {
             Error::InvalidKeyParameter(ref e) => {
                 format!("Invalid parameter for key generation: {:?}", e)
             }
+            Error::InvalidPeerFile(ref f) => {
+                format!("Invalid peer file '{}': must be a file name, not a path", f)
+            }
+            Error::InvalidPeerFileTemplate(ref e) => format!("Invalid peer file template: {}", e),
             Error::InvalidPidFile => "Invalid child process PID file".to_string(),
             Error::InvalidTopology(ref t) => format!("Invalid topology: {}", t),
             Error::InvalidUpdateStrategy(ref s) => format!("Invalid update strategy: {}", s),
@@ -207,6 +219,12 @@ impl fmt::Display for Error {
                 format!("Supervisor failed to try executing launcher command via IPC: {}",
                         chain.join(", "))
             }
+            Error::MissingPackageSignerMetadata(ref pkg) => {
+                format!("Package {} has no recorded signer or artifact hash; it was likely \
+                         installed before signer metadata was recorded. Reinstall it or relax the \
+                         signer verification policy.",
+                        pkg)
+            }
             Error::MissingRequiredBind(ref e) => {
                 format!("Missing required bind(s), {}", e.join(", "))
             }
@@ -225,6 +243,11 @@ impl fmt::Display for Error {
             Error::NotifyError(ref e) => format!("Notify error: {}", e),
             Error::NulError(ref e) => e.to_string(),
             Error::OneshotCanceled(ref e) => e.to_string(),
+            Error::PackageArtifactHashMismatch(ref pkg, ref recorded, ref actual) => {
+                format!("Artifact hash mismatch for package {}: recorded {}, cached artifact has \
+                         {}",
+                        pkg, recorded, actual)
+            }
             Error::PackageNotFound(ref pkg) => {
                 if pkg.fully_qualified() {
                     format!("Cannot find package: {}", pkg)
@@ -265,7 +288,15 @@ impl fmt::Display for Error {
             Error::TLSError(ref e) => e.to_string(),
             Error::TomlEncode(ref e) => format!("Failed to encode TOML: {}", e),
             Error::TryRecvError(ref err) => err.to_string(),
+            Error::UnknownPackageSigner(ref pkg, ref signer) => {
+                format!("Package {} was signed by {}, which is not a public signing key in the \
+                         key cache",
+                        pkg, signer)
+            }
             Error::UnpackFailed => "Failed to unpack a package".to_string(),
+            Error::UnsupportedHttpListen(ref listen, reason) => {
+                format!("Cannot serve the HTTP gateway on {}: {}", listen, reason)
+            }
             Error::UserNotFound(ref e) => format!("No UID for user '{}' could be found", e),
             Error::WithDuration(ref e, ref duration) => {
                 format!("{} ({} s)", e, duration.as_secs_f64())
@@ -306,7 +337,9 @@ impl From<habitat_api_client::Error> for Error {
 impl From<Error> for habitat_sup_protocol::net::NetErr {
     fn from(err: Error) -> habitat_sup_protocol::net::NetErr {
         match err {
-            Error::MissingRequiredBind(_) | Error::InvalidBinds(_) => {
+            Error::MissingRequiredBind(_)
+            | Error::InvalidBinds(_)
+            | Error::InvalidDesiredState(_) => {
                 habitat_sup_protocol::net::err(habitat_sup_protocol::net::ErrCode::InvalidPayload,
                                                err)
             }
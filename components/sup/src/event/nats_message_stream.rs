@@ -1,6 +1,9 @@
-use crate::event::{Error,
-                   EventStreamConfig,
-                   Result};
+use crate::{event::{Error,
+                    EventStreamConfig,
+                    Result},
+            manager::task_supervisor::{self,
+                                       RestartPolicy,
+                                       ShutdownStage}};
 use futures::{channel::{mpsc as futures_mpsc,
                         mpsc::UnboundedSender},
               stream::StreamExt};
@@ -10,7 +13,9 @@ use rants::{error::Error as RantsError,
             native_tls::TlsConnector,
             Client,
             Subject};
-use tokio::time;
+use std::sync::Arc;
+use tokio::{sync::Mutex,
+            time};
 
 /// The subject and payload of a NATS message.
 #[derive(Debug)]
@@ -75,26 +80,24 @@ impl NatsMessageStream {
             tokio::spawn(async move { client.connect().await });
         }
 
-        let (tx, mut rx) = futures_mpsc::unbounded::<NatsMessage>();
+        let (tx, rx) = futures_mpsc::unbounded::<NatsMessage>();
+        // The receiver is shared between restarts of the publishing task.
+        let rx = Arc::new(Mutex::new(rx));
 
         // Spawn a task to handle publishing received messages
-        tokio::spawn(async move {
-            while let Some(packet) = rx.next().await {
-                if let Err(e) = client.publish(packet.subject, packet.payload()).await {
-                    // We do not retry any messages. If we are not connected when the message is
-                    // processed or there is an error in publishing the message, the message will
-                    // never be sent.
-                    if let RantsError::NotConnected = e {
-                        trace!("Failed to publish message to subject '{}' because the client is \
-                                not connected",
-                               packet.subject);
-                    } else {
-                        error!("Failed to publish message to subject '{}', err: {}",
-                               packet.subject, e);
-                    }
-                }
-            }
-        });
+        task_supervisor::spawn("event-exporter",
+                               ShutdownStage::EventExporter,
+                               RestartPolicy::Always,
+                               move || {
+                                   let client = Client::clone(&client);
+                                   let rx = Arc::clone(&rx);
+                                   async move {
+                                       let mut rx = rx.lock().await;
+                                       while let Some(packet) = rx.next().await {
+                                           publish(&client, packet).await;
+                                       }
+                                   }
+                               });
 
         Ok(NatsMessageStream(tx))
     }
@@ -107,3 +110,18 @@ impl NatsMessageStream {
         }
     }
 }
+
+async fn publish(client: &Client, packet: NatsMessage) {
+    if let Err(e) = client.publish(packet.subject, packet.payload()).await {
+        // We do not retry any messages. If we are not connected when the message is
+        // processed or there is an error in publishing the message, the message will
+        // never be sent.
+        if let RantsError::NotConnected = e {
+            trace!("Failed to publish message to subject '{}' because the client is not connected",
+                   packet.subject);
+        } else {
+            error!("Failed to publish message to subject '{}', err: {}",
+                   packet.subject, e);
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use crate::manager::{self,
                      service::{HealthCheckHook,
-                               HealthCheckResult}};
+                               HealthCheckResult,
+                               ServiceEvent},
+                     task_supervisor};
 use actix_rt::System;
 use actix_web::{body::BoxBody,
                 dev::{Service,
@@ -11,23 +13,29 @@ use actix_web::{body::BoxBody,
                 web::{self,
                       Data,
                       Path,
+                      Query,
                       ServiceConfig},
                 App,
                 Error,
                 HttpResponse,
                 HttpServer};
-use futures::future::{ok,
-                      Either,
-                      Future};
+use bytes::Bytes;
+use futures::{future::{ok,
+                       Either,
+                       Future},
+              stream::{self,
+                       StreamExt}};
 use habitat_common::{self,
                      templating::hooks,
-                     types::HttpListenAddr,
+                     types::{HttpListen,
+                             SocketMode},
                      FeatureFlag};
 use habitat_core::{crypto,
                    env as henv,
                    service::ServiceGroup};
 use log::{debug,
-          error};
+          error,
+          warn};
 use manager::sync::GatewayState;
 
 use lazy_static::lazy_static;
@@ -45,12 +53,16 @@ use serde_json::{self,
                  Value as Json};
 use std::{self,
           cell::Cell,
+          collections::BTreeMap,
+          convert::Infallible,
           fs::File,
           io::Read,
+          str::FromStr,
           sync::{Arc,
                  Condvar,
                  Mutex},
           thread};
+use tokio::sync::broadcast::error::RecvError;
 
 const APIDOCS: &str = include_str!(concat!(env!("OUT_DIR"), "/api.html"));
 pub const HTTP_THREADS_ENVVAR: &str = "HAB_SUP_HTTP_THREADS";
@@ -59,6 +71,14 @@ pub const HTTP_THREAD_COUNT: usize = 2;
 /// Default listening port for the HTTPGateway listener.
 pub const DEFAULT_PORT: u16 = 9631;
 
+/// The query parameters that `/services` can be filtered and paged
+/// with.
+const SERVICES_QUERY_PARAMS: &[&str] = &["origin", "service_group", "state", "limit", "offset"];
+
+/// The header giving how many services matched the filters of a
+/// `/services` query, across every page.
+const TOTAL_COUNT_HEADER: &str = "X-Total-Count";
+
 lazy_static! {
     static ref HTTP_GATEWAY_REQUESTS: CounterVec =
         register_counter_vec!("hab_sup_http_gateway_requests_total",
@@ -102,6 +122,84 @@ impl Into<StatusCode> for HealthCheckResult {
     }
 }
 
+/// Which of the loaded services `/services` shows.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ServicesQuery {
+    origin:        Option<String>,
+    /// The group part of the service group, e.g. "default"
+    service_group: Option<String>,
+    /// The state of the service's process, "up" or "down"
+    state:         Option<String>,
+    limit:         Option<usize>,
+    offset:        usize,
+}
+
+impl ServicesQuery {
+    fn parse(params: Vec<(String, String)>) -> Result<Self, String> {
+        let mut query = ServicesQuery::default();
+        for (name, value) in params {
+            match name.as_str() {
+                "origin" => query.origin = Some(value),
+                "service_group" => query.service_group = Some(value),
+                "state" if value == "up" || value == "down" => query.state = Some(value),
+                "state" => {
+                    return Err(format!("Invalid state '{}'; expected 'up' or 'down'", value))
+                }
+                "limit" => query.limit = Some(parse_count(&name, &value)?),
+                "offset" => query.offset = parse_count(&name, &value)?,
+                _ => {
+                    return Err(format!("Unsupported query parameter '{}'; supported \
+                                        parameters are: {}",
+                                       name,
+                                       SERVICES_QUERY_PARAMS.join(", ")))
+                }
+            }
+        }
+        Ok(query)
+    }
+
+    fn matches(&self, service: &Json) -> bool {
+        let in_group = |group: &str| {
+            service["service_group"].as_str()
+                                    .and_then(|sg| ServiceGroup::from_str(sg).ok())
+                                    .map_or(false, |sg| sg.group() == group)
+        };
+        self.origin
+            .as_ref()
+            .map_or(true, |origin| service["pkg"]["origin"] == origin.as_str())
+        && self.service_group.as_deref().map_or(true, in_group)
+        && self.state
+               .as_ref()
+               .map_or(true, |state| service["process"]["state"] == state.as_str())
+    }
+
+    /// The matching services on the page asked for, ordered by service
+    /// group so that pages don't overlap, along with how many matched
+    /// in all.
+    fn apply(&self, services: Vec<Json>) -> (usize, Vec<Json>) {
+        let mut matching = services.into_iter()
+                                   .filter(|service| self.matches(service))
+                                   .collect::<Vec<_>>();
+        matching.sort_by(|a, b| {
+                    a["service_group"].as_str()
+                                      .cmp(&b["service_group"].as_str())
+                });
+        let total = matching.len();
+        let page = matching.into_iter()
+                           .skip(self.offset)
+                           .take(self.limit.unwrap_or(usize::MAX))
+                           .collect();
+        (total, page)
+    }
+}
+
+fn parse_count(name: &str, value: &str) -> Result<usize, String> {
+    value.parse().map_err(|_| {
+                     format!("Invalid {} '{}'; expected a non-negative integer",
+                             name, value)
+                 })
+}
+
 struct AppState {
     gateway_state:        Arc<GatewayState>,
     authentication_token: Option<String>,
@@ -236,12 +334,16 @@ impl Services {
                   web::get().to(config_without_org_gsr))
            .route("/services/{svc}/{group}/health",
                   web::get().to(health_without_org_gsr))
+           .route("/services/{svc}/{group}/health/history",
+                  web::get().to(health_history_without_org_gsr))
            .route("/services/{svc}/{group}/{org}",
                   web::get().to(service_with_org_gsr))
            .route("/services/{svc}/{group}/{org}/config",
                   web::get().to(config_with_org_gsr))
            .route("/services/{svc}/{group}/{org}/health",
-                  web::get().to(health_with_org_gsr));
+                  web::get().to(health_with_org_gsr))
+           .route("/services/{svc}/{group}/{org}/health/history",
+                  web::get().to(health_history_with_org_gsr));
     }
 }
 
@@ -252,7 +354,9 @@ impl Butterfly {
     //
     pub fn register(cfg: &mut ServiceConfig) {
         cfg.service(web::resource("/butterfly").route(web::get().to(butterfly_gsr))
-                                               .wrap_fn(redact_http_middleware));
+                                               .wrap_fn(redact_http_middleware))
+           .route("/butterfly/ring-key", web::get().to(ring_key_gsr))
+           .route("/butterfly/stats", web::get().to(gossip_stats_gsr));
     }
 }
 
@@ -267,10 +371,39 @@ impl Census {
     }
 }
 
+struct Supervisor {}
+
+impl Supervisor {
+    // Route registration
+    //
+    pub fn register(cfg: &mut ServiceConfig) {
+        cfg.route("/supervisor/config", web::get().to(supervisor_config_gsr))
+           .route("/supervisor/tasks", web::get().to(supervisor_tasks));
+    }
+}
+
+struct Events {}
+
+impl Events {
+    // Route registration
+    //
+    pub fn register(cfg: &mut ServiceConfig) { cfg.route("/events", web::get().to(events_gsr)); }
+}
+
+struct Errors {}
+
+impl Errors {
+    // Route registration
+    //
+    pub fn register(cfg: &mut ServiceConfig) { cfg.route("/errors", web::get().to(errors_gsr)); }
+}
+
 pub struct Server;
 
 impl Server {
-    pub fn run(listen_addr: HttpListenAddr,
+    #[cfg_attr(windows, allow(unused_variables))]
+    pub fn run(listen: HttpListen,
+               socket_mode: SocketMode,
                tls_config: Option<ServerConfig>,
                gateway_state: Arc<GatewayState>,
                authentication_token: GatewayAuthenticationToken,
@@ -297,18 +430,39 @@ impl Server {
                               .wrap_fn(authentication_middleware)
                               .wrap_fn(metrics_middleware)
                               .service(web::resource("/").route(web::get().to(doc)))
+                              .service(web::resource("/ready").route(web::get().to(ready_gsr)))
                               .configure(Services::register)
                               .configure(Butterfly::register)
                               .configure(Census::register)
+                              .configure(Supervisor::register)
+                              .configure(Errors::register)
+                              .configure(Events::register)
                               .service(web::resource("/metrics").route(web::get().to(metrics)))
                              }).workers(thread_count);
 
             server = server.disable_signals();
             debug!("http_gateway server configured");
 
-            let bind = match tls_config {
-                Some(c) => server.bind_rustls(listen_addr.to_string(), c),
-                None => server.bind(listen_addr.to_string()),
+            let bind = match listen {
+                HttpListen::Tcp(addr) => {
+                    match tls_config {
+                        Some(c) => server.bind_rustls(addr.to_string(), c),
+                        None => server.bind(addr.to_string()),
+                    }
+                }
+                #[cfg(unix)]
+                HttpListen::Unix(ref path) => {
+                    remove_stale_socket(path).and_then(|_| server.bind_uds(path))
+                                             .and_then(|server| {
+                                                 set_socket_mode(path, socket_mode)?;
+                                                 Ok(server)
+                                             })
+                }
+                // The Manager refuses to start with a Unix domain socket on Windows
+                #[cfg(windows)]
+                HttpListen::Unix(_) => {
+                    unreachable!("Unix domain sockets are not supported on Windows")
+                }
             };
             debug!("http_gateway server port bound");
 
@@ -333,6 +487,48 @@ impl Server {
     }
 }
 
+/// Clear the way to bind a Unix domain socket at `path`. A socket
+/// left behind by a Supervisor that didn't shut down cleanly is
+/// removed; one that something is still listening on is left alone,
+/// as is anything that isn't a socket.
+#[cfg(unix)]
+fn remove_stale_socket(path: &std::path::Path) -> std::io::Result<()> {
+    use std::{fs,
+              io,
+              os::unix::{fs::FileTypeExt,
+                         net::UnixStream}};
+
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            match UnixStream::connect(path) {
+                Ok(_) => {
+                    Err(io::Error::new(io::ErrorKind::AddrInUse,
+                                       format!("{} is in use", path.display())))
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                    warn!("Removing stale HTTP gateway socket {}", path.display());
+                    fs::remove_file(path)
+                }
+                Err(e) => Err(e),
+            }
+        }
+        Ok(_) => {
+            Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                               format!("{} exists and is not a socket",
+                                       path.display())))
+        }
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(unix)]
+fn set_socket_mode(path: &std::path::Path, mode: SocketMode) -> std::io::Result<()> {
+    use std::{fs,
+              os::unix::fs::PermissionsExt};
+    fs::set_permissions(path, fs::Permissions::from_mode(mode.bits()))
+}
+
 fn json_response(data: String) -> HttpResponse {
     HttpResponse::Ok().content_type("application/json")
                       .body(data)
@@ -348,6 +544,25 @@ async fn butterfly_gsr(state: Data<AppState>) -> HttpResponse {
     json_response(data)
 }
 
+/// # Locking (see locking.md)
+/// * `GatewayState::inner` (read)
+#[allow(clippy::needless_pass_by_value)]
+async fn ring_key_gsr(state: Data<AppState>) -> HttpResponse {
+    let data = state.gateway_state.lock_gsr().ring_key_data().to_string();
+    json_response(data)
+}
+
+/// # Locking (see locking.md)
+/// * `GatewayState::inner` (read)
+#[allow(clippy::needless_pass_by_value)]
+async fn gossip_stats_gsr(state: Data<AppState>) -> HttpResponse {
+    let data = state.gateway_state
+                    .lock_gsr()
+                    .gossip_stats_data()
+                    .to_string();
+    json_response(data)
+}
+
 /// # Locking (see locking.md)
 /// * `GatewayState::inner` (read)
 #[allow(clippy::needless_pass_by_value)]
@@ -356,14 +571,110 @@ async fn census_gsr(state: Data<AppState>) -> HttpResponse {
     json_response(data)
 }
 
+/// Without any query parameters, every loaded service is returned as
+/// is. Otherwise, they're filtered and paged as `ServicesQuery` says,
+/// with the total number matching in the `X-Total-Count` header.
+///
 /// # Locking (see locking.md)
 /// * `GatewayState::inner` (read)
 #[allow(clippy::needless_pass_by_value)]
-async fn services_gsr(state: Data<AppState>) -> HttpResponse {
+async fn services_gsr(query: Query<Vec<(String, String)>>, state: Data<AppState>) -> HttpResponse {
+    let params = query.into_inner();
     let data = state.gateway_state.lock_gsr().services_data().to_string();
+    if params.is_empty() {
+        return json_response(data);
+    }
+    let query = match ServicesQuery::parse(params) {
+        Ok(query) => query,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let services = match serde_json::from_str(&data) {
+        Ok(Json::Array(services)) => services,
+        _ => Vec::new(),
+    };
+    let (total, page) = query.apply(services);
+    HttpResponse::Ok().insert_header((TOTAL_COUNT_HEADER, total.to_string()))
+                      .json(page)
+}
+
+/// # Locking (see locking.md)
+/// * `GatewayState::inner` (read)
+#[allow(clippy::needless_pass_by_value)]
+async fn supervisor_config_gsr(state: Data<AppState>) -> HttpResponse {
+    let data = state.gateway_state.lock_gsr().supervisor_data().to_string();
     json_response(data)
 }
 
+async fn supervisor_tasks() -> HttpResponse { HttpResponse::Ok().json(task_supervisor::tasks()) }
+
+/// Whether the Supervisor has started up its services, rather than
+/// just whether it's running, which the gateway answering at all says.
+/// Services still waited on are listed either way.
+///
+/// # Locking (see locking.md)
+/// * `GatewayState::inner` (read)
+#[allow(clippy::needless_pass_by_value)]
+async fn ready_gsr(state: Data<AppState>) -> HttpResponse {
+    let gsr = state.gateway_state.lock_gsr();
+    let readiness = gsr.readiness();
+    if readiness.is_ready() {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+/// # Locking (see locking.md)
+/// * `GatewayState::inner` (read)
+#[allow(clippy::needless_pass_by_value)]
+async fn errors_gsr(state: Data<AppState>) -> HttpResponse {
+    let errors = service_errors_gsr(&state.gateway_state);
+    HttpResponse::Ok().json(errors)
+}
+
+/// Streams events about services as server-sent events, starting with
+/// a snapshot of every service loaded when the subscriber connects. A
+/// subscriber that falls too far behind is disconnected, rather than
+/// silently missing events, and can reconnect for a fresh snapshot.
+///
+/// # Locking (see locking.md)
+/// * `GatewayState::inner` (read)
+#[allow(clippy::needless_pass_by_value)]
+async fn events_gsr(state: Data<AppState>) -> HttpResponse {
+    let (snapshot, receiver) = state.gateway_state.lock_gsr().subscribe_events();
+    let events = stream::unfold(receiver, |mut receiver| {
+        async move {
+            match receiver.recv().await {
+                Ok(event) => Some((event, receiver)),
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Disconnecting HTTP gateway event subscriber that fell {} events behind",
+                          missed);
+                    None
+                }
+                Err(RecvError::Closed) => None,
+            }
+        }
+    });
+    let body = stream::iter(snapshot).chain(events)
+                                     .map(|event| Ok::<_, Infallible>(server_sent_event(&event)));
+    HttpResponse::Ok().content_type("text/event-stream")
+                      .insert_header((http::header::CACHE_CONTROL, "no-cache"))
+                      .streaming(body)
+}
+
+/// Returns the errors recorded for services that the Supervisor
+/// refused to load, keyed by package identifier.
+///
+/// # Locking (see locking.md)
+/// * `GatewayState::inner` (read)
+fn service_errors_gsr(gateway_state: &GatewayState) -> BTreeMap<String, String> {
+    gateway_state.lock_gsr()
+                 .service_errors()
+                 .iter()
+                 .map(|(ident, err)| (ident.to_string(), err.clone()))
+                 .collect()
+}
+
 /// # Locking (see locking.md)
 /// * `GatewayState::inner` (read)
 // Honestly, this doesn't feel great, but it's the pattern builder-api uses, and at the
@@ -452,6 +763,53 @@ fn health_gsr(svc: String, group: String, org: Option<&str>, state: &AppState) -
     }
 }
 
+/// # Locking (see locking.md)
+/// * `GatewayState::inner` (read)
+#[allow(clippy::needless_pass_by_value)]
+async fn health_history_with_org_gsr(path: Path<(String, String, String)>,
+                                     state: Data<AppState>)
+                                     -> HttpResponse {
+    let (svc, group, org) = path.into_inner();
+    health_history_gsr(svc, group, Some(&org), &state)
+}
+
+/// # Locking (see locking.md)
+/// * `GatewayState::inner` (read)
+#[allow(clippy::needless_pass_by_value)]
+async fn health_history_without_org_gsr(path: Path<(String, String)>,
+                                        state: Data<AppState>)
+                                        -> HttpResponse {
+    let (svc, group) = path.into_inner();
+    health_history_gsr(svc, group, None, &state)
+}
+
+/// A service's most recent health checks, oldest first. It's empty for
+/// a service that's started but yet to finish a check.
+///
+/// # Locking (see locking.md)
+/// * `GatewayState::inner` (read)
+fn health_history_gsr(svc: String,
+                      group: String,
+                      org: Option<&str>,
+                      state: &AppState)
+                      -> HttpResponse {
+    let service_group = match ServiceGroup::new(svc, group, org) {
+        Ok(sg) => sg,
+        Err(_) => return HttpResponse::BadRequest().finish(),
+    };
+
+    let gateway_state = state.gateway_state.lock_gsr();
+    if let Some(history) = gateway_state.health_history_of(&service_group) {
+        HttpResponse::Ok().json(history)
+    } else if gateway_state.health_of(&service_group).is_some() {
+        HttpResponse::Ok().json(Vec::<Json>::new())
+    } else {
+        debug!("Didn't find any health data for service group {:?}",
+               &service_group);
+        HttpResponse::NotFound().finish()
+    }
+}
+
 /// # Locking (see locking.md)
 /// * `GatewayState::inner` (read)
 #[allow(clippy::needless_pass_by_value)]
@@ -512,6 +870,11 @@ async fn metrics() -> HttpResponse {
 async fn doc() -> HttpResponse { HttpResponse::Ok().content_type("text/html").body(APIDOCS) }
 // End route handlers
 
+fn server_sent_event(event: &ServiceEvent) -> Bytes {
+    let data = serde_json::to_string(event).expect("ServiceEvent::serialize failure");
+    Bytes::from(format!("event: {}\ndata: {}\n\n", event.event, data))
+}
+
 fn service_from_services(service_group: &ServiceGroup, services_json: &str) -> Option<Json> {
     match serde_json::from_str(services_json) {
         Ok(Json::Array(services)) => {
@@ -524,12 +887,15 @@ fn service_from_services(service_group: &ServiceGroup, services_json: &str) -> O
 
 #[cfg(test)]
 mod tests {
+    use super::ServicesQuery;
     use crate::test_helpers::*;
     use habitat_butterfly::{member::Member,
                             server::{Server,
                                      ServerProxy,
                                      Suitability}};
     use lazy_static::lazy_static;
+    use serde_json::{json,
+                     Value as Json};
     use std::{fs::File,
               io::Read,
               net::{IpAddr,
@@ -638,6 +1004,82 @@ mod tests {
                                             "http_gateway_services_schema.json");
     }
 
+    fn service(origin: &str, service_group: &str, state: &str) -> Json {
+        json!({
+            "pkg": { "origin": origin },
+            "service_group": service_group,
+            "process": { "state": state },
+        })
+    }
+
+    fn query(params: &[(&str, &str)]) -> Result<ServicesQuery, String> {
+        ServicesQuery::parse(params.iter()
+                                   .map(|(name, value)| (name.to_string(), value.to_string()))
+                                   .collect())
+    }
+
+    fn service_groups(services: &[Json]) -> Vec<&str> {
+        services.iter()
+                .map(|s| s["service_group"].as_str().unwrap())
+                .collect()
+    }
+
+    #[test]
+    fn services_are_filtered_by_origin_group_and_state() {
+        let services = vec![service("core", "redis.default", "up"),
+                            service("core", "nginx.canary@acme", "down"),
+                            service("acme", "app.default", "up"),];
+
+        let (total, page) = query(&[("origin", "core")]).unwrap()
+                                                        .apply(services.clone());
+        assert_eq!(total, 2);
+        assert_eq!(service_groups(&page),
+                   vec!["nginx.canary@acme", "redis.default"]);
+
+        let (_, page) =
+            query(&[("service_group", "default"), ("state", "up")]).unwrap()
+                                                                   .apply(services.clone());
+        assert_eq!(service_groups(&page), vec!["app.default", "redis.default"]);
+
+        let (_, page) = query(&[("service_group", "canary")]).unwrap()
+                                                             .apply(services);
+        assert_eq!(service_groups(&page), vec!["nginx.canary@acme"]);
+    }
+
+    #[test]
+    fn services_are_paged_after_filtering() {
+        let services = vec![service("core", "c.default", "up"),
+                            service("core", "a.default", "up"),
+                            service("core", "b.default", "up"),
+                            service("acme", "d.default", "up"),];
+
+        let (total, page) = query(&[("origin", "core"), ("limit", "2")]).unwrap()
+                                                                        .apply(services.clone());
+        assert_eq!(total, 3);
+        assert_eq!(service_groups(&page), vec!["a.default", "b.default"]);
+
+        let (total, page) =
+            query(&[("origin", "core"), ("limit", "2"), ("offset", "2")]).unwrap()
+                                                                         .apply(services.clone());
+        assert_eq!(total, 3);
+        assert_eq!(service_groups(&page), vec!["c.default"]);
+
+        let (total, page) = query(&[("offset", "10")]).unwrap().apply(services);
+        assert_eq!(total, 4);
+        assert!(page.is_empty());
+    }
+
+    #[test]
+    fn unsupported_services_queries_are_rejected() {
+        let err = query(&[("name", "redis")]).unwrap_err();
+        assert!(err.contains("origin, service_group, state, limit, offset"),
+                "{}",
+                err);
+        assert!(query(&[("state", "sideways")]).is_err());
+        assert!(query(&[("limit", "-1")]).is_err());
+        assert!(query(&[("offset", "many")]).is_err());
+    }
+
     #[test]
     fn trivial_services_failure() {
         let failure = validate_string(r#"[{"lulz": true}]"#, "http_gateway_services_schema.json");
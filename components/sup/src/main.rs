@@ -103,9 +103,16 @@ fn boot() -> Option<LauncherCli> {
             match LauncherCli::connect(pipe) {
                 Ok(launcher) => Some(launcher),
                 Err(err) => {
+                    // A protocol version mismatch won't go away if
+                    // the launcher restarts us, so tell it not to.
+                    let exit_code = if err.is_protocol_version_mismatch() {
+                        ERR_NO_RETRY_EXCODE
+                    } else {
+                        1
+                    };
                     error!("Failed to connect to launcher: {:?}",
                            anyhow::Error::new(err));
-                    process::exit(1);
+                    process::exit(exit_code);
                 }
             }
         }
@@ -219,7 +226,7 @@ async fn sub_run_rsr_imlw_mlw_gsw_smw_rhw_msw(sup_run: SupRun,
         svc_load_msgs.push(svc_load_msg);
     }
     let manager = Manager::load_imlw(manager_cfg, launcher).await?;
-    manager.run_rsw_imlw_mlw_gsw_smw_rhw_msw(svc_load_msgs)
+    manager.run_rsw_imlw_mlw_gsw_smw_rhw_srkw_msw(svc_load_msgs)
            .await
 }
 
@@ -315,10 +322,13 @@ async fn split_apart_sup_run(sup_run: SupRun,
                             sup_run.ctl_client_ca_certificate
                                    .map(RootCertificateStoreCli::into_inner),
                         http_listen: sup_run.listen_http,
+                        http_socket_mode: sup_run.listen_http_socket_mode,
                         tls_config,
                         feature_flags,
                         event_stream_config,
                         keep_latest_packages: sup_run.keep_latest_packages,
+                        verify_package_signers: sup_run.verify_package_signers,
+                        health_check_splay: shared_load.health_check_splay,
                         sys_ip: sup_run.sys_ip_address
                                        .or_else(|| {
                                            let result_ip = habitat_core::util::sys::ip();
@@ -402,18 +412,22 @@ mod test {
     use super::*;
     use hab::cli::hab::sup::Sup;
     use habitat_common::types::{GossipListenAddr,
+                                HttpListen,
                                 HttpListenAddr,
-                                ListenCtlAddr};
+                                ListenCtlAddr,
+                                SocketMode};
     use habitat_core::{fs::CACHE_KEY_PATH,
                        locked_env_var};
     use habitat_sup_protocol::{ctl::ServiceBindList,
                                types::{BindingMode,
                                        ServiceBind,
+                                       SignerVerification,
                                        Topology,
                                        UpdateCondition,
                                        UpdateStrategy}};
-    use std::net::{SocketAddr,
-                   ToSocketAddrs};
+    use std::{net::{SocketAddr,
+                    ToSocketAddrs},
+              path::PathBuf};
     use tempfile::TempDir;
 
     fn no_feature_flags() -> FeatureFlag { FeatureFlag::empty() }
@@ -561,14 +575,25 @@ mod test {
             let expected_addr =
                 HttpListenAddr::from_str("2.2.2.2:2222").expect("Could not create http listen \
                                                                  addr");
-            assert_eq!(config.http_listen, expected_addr);
+            assert_eq!(config.http_listen, HttpListen::Tcp(expected_addr));
         }
 
         #[test]
         fn http_listen_is_set_default_when_not_specified() {
             let config = config_from_cmd_str("hab-sup run");
             let expected_addr = HttpListenAddr::default();
-            assert_eq!(config.http_listen, expected_addr);
+            assert_eq!(config.http_listen, HttpListen::Tcp(expected_addr));
+            assert_eq!(config.http_socket_mode, SocketMode::default());
+        }
+
+        #[test]
+        fn http_listen_can_be_a_unix_socket() {
+            let config = config_from_cmd_str("hab-sup run --listen-http \
+                                              unix:///hab/sup/default/sup.sock \
+                                              --listen-http-socket-mode 0600");
+            assert_eq!(config.http_listen,
+                       HttpListen::Unix(PathBuf::from("/hab/sup/default/sup.sock")));
+            assert_eq!(config.http_socket_mode.bits(), 0o600);
         }
 
         #[test]
@@ -740,8 +765,9 @@ gpoVMSncu2jMIDZX63IkQII=
                                        ctl_server_certificates:    None,
                                        ctl_server_key:             None,
                                        ctl_client_ca_certificates: None,
-                                       http_listen:                HttpListenAddr::default(),
+                                       http_listen:                HttpListen::default(),
                                        http_disable:               false,
+                                       http_socket_mode:           SocketMode::default(),
                                        gossip_peers:               vec![],
                                        gossip_permanent:           false,
                                        ring_key:                   None,
@@ -751,6 +777,8 @@ gpoVMSncu2jMIDZX63IkQII=
                                        feature_flags:              FeatureFlag::empty(),
                                        event_stream_config:        None,
                                        keep_latest_packages:       None,
+                                       verify_package_signers:     SignerVerification::Off,
+                                       health_check_splay:         None,
                                        sys_ip:
                                            habitat_core::util::sys::ip().unwrap(), },
                        config);
@@ -816,8 +844,9 @@ gpoVMSncu2jMIDZX63IkQII=
                                        ctl_server_key: None,
                                        ctl_client_ca_certificates: None,
                                        http_listen:
-                                           HttpListenAddr::from_str("5.5.5.5:11111").unwrap(),
+                                           HttpListen::from_str("5.5.5.5:11111").unwrap(),
                                        http_disable: true,
+                                       http_socket_mode: SocketMode::default(),
                                        gossip_peers,
                                        gossip_permanent: true,
                                        ring_key: Some(ring_key),
@@ -830,6 +859,8 @@ gpoVMSncu2jMIDZX63IkQII=
                                        feature_flags: FeatureFlag::empty(),
                                        event_stream_config: None,
                                        keep_latest_packages: Some(5),
+                                       verify_package_signers: SignerVerification::Off,
+                                       health_check_splay: None,
                                        sys_ip: "7.8.9.0".parse().unwrap() },
                        config);
         }
@@ -857,8 +888,9 @@ gpoVMSncu2jMIDZX63IkQII=
                                        ctl_server_certificates:    None,
                                        ctl_server_key:             None,
                                        ctl_client_ca_certificates: None,
-                                       http_listen:                HttpListenAddr::default(),
+                                       http_listen:                HttpListen::default(),
                                        http_disable:               false,
+                                       http_socket_mode:           SocketMode::default(),
                                        gossip_peers:               vec![],
                                        gossip_permanent:           false,
                                        ring_key:                   None,
@@ -868,6 +900,8 @@ gpoVMSncu2jMIDZX63IkQII=
                                        feature_flags:              FeatureFlag::empty(),
                                        event_stream_config:        None,
                                        keep_latest_packages:       None,
+                                       verify_package_signers:     SignerVerification::Off,
+                                       health_check_splay:         None,
                                        sys_ip:
                                            habitat_core::util::sys::ip().unwrap(), },
                        config);
@@ -895,8 +929,9 @@ gpoVMSncu2jMIDZX63IkQII=
                                        ctl_server_certificates:    None,
                                        ctl_server_key:             None,
                                        ctl_client_ca_certificates: None,
-                                       http_listen:                HttpListenAddr::default(),
+                                       http_listen:                HttpListen::default(),
                                        http_disable:               false,
+                                       http_socket_mode:           SocketMode::default(),
                                        gossip_peers:               vec![],
                                        gossip_permanent:           false,
                                        ring_key:                   None,
@@ -906,6 +941,8 @@ gpoVMSncu2jMIDZX63IkQII=
                                        feature_flags:              FeatureFlag::empty(),
                                        event_stream_config:        None,
                                        keep_latest_packages:       None,
+                                       verify_package_signers:     SignerVerification::Off,
+                                       health_check_splay:         None,
                                        sys_ip:
                                            habitat_core::util::sys::ip().unwrap(), },
                        config);
@@ -967,8 +1004,9 @@ gpoVMSncu2jMIDZX63IkQII=
                     ctl_server_certificates: None,
                     ctl_server_key: None,
                     ctl_client_ca_certificates: None,
-                    http_listen: HttpListenAddr::default(),
+                    http_listen: HttpListen::default(),
                     http_disable: false,
+                    http_socket_mode: SocketMode::default(),
                     gossip_peers: vec![],
                     gossip_permanent: false,
                     ring_key: None,
@@ -987,6 +1025,8 @@ gpoVMSncu2jMIDZX63IkQII=
                         server_certificate: Some(certificate_path_str.parse().unwrap()),
                     }),
                     keep_latest_packages: None,
+                    verify_package_signers: SignerVerification::Off,
+                    health_check_splay: None,
                     sys_ip: habitat_core::util::sys::ip().unwrap(),
                 },
                 config,
@@ -1002,7 +1042,9 @@ gpoVMSncu2jMIDZX63IkQII=
                                 two:service2.default --binding-mode relaxed --url http://my_url.com \
                                 --config-from={} --group MyGroup --topology leader \
                                 --strategy rolling --update-condition track-channel --health-check-interval 17 \
-                                --shutdown-timeout=12 core/redis",
+                                --shutdown-timeout=12 --health-check-splay 5 \
+                                --min-backoff-period 1 --max-backoff-period 60 \
+                                --restart-cooldown-period 120 core/redis",
                                temp_dir_str);
 
             let mut binds = ServiceBindList::default();
@@ -1029,12 +1071,19 @@ gpoVMSncu2jMIDZX63IkQII=
                                                  group:
                                                      Some(String::from("MyGroup")),
                                                  svc_encrypted_password: None,
+                                                 signer_verification:    None,
+                                                 peer_file:              None,
+                                                 peer_file_template:     None,
                                                  topology:
                                                      Some(Topology::Leader.into()),
                                                  update_strategy:
                                                      Some(UpdateStrategy::Rolling.into()),
                                                  health_check_interval:
                                                      Some(health_check_interval),
+                                                 health_check_splay:     Some(5),
+                                                 min_backoff_period:     Some(1),
+                                                 max_backoff_period:     Some(60),
+                                                 cooldown_period:        Some(120),
                                                  shutdown_timeout:       Some(12),
                                                  update_condition:
                                                      Some(UpdateCondition::TrackChannel.into()), },
@@ -1151,8 +1200,9 @@ sys_ip_address = "7.8.9.0"
                                        ctl_server_key: None,
                                        ctl_client_ca_certificates: None,
                                        http_listen:
-                                           HttpListenAddr::from_str("5.5.5.5:11111").unwrap(),
+                                           HttpListen::from_str("5.5.5.5:11111").unwrap(),
                                        http_disable: true,
+                                       http_socket_mode: SocketMode::default(),
                                        gossip_peers,
                                        gossip_permanent: true,
                                        ring_key: Some(ring_key),
@@ -1165,6 +1215,8 @@ sys_ip_address = "7.8.9.0"
                                        feature_flags: FeatureFlag::empty(),
                                        event_stream_config: None,
                                        keep_latest_packages: Some(5),
+                                       verify_package_signers: SignerVerification::Off,
+                                       health_check_splay: None,
                                        sys_ip: "7.8.9.0".parse().unwrap() },
                        config);
         }
@@ -1201,8 +1253,9 @@ sys_ip_address = "7.8.9.0"
                                        ctl_server_certificates:    None,
                                        ctl_server_key:             None,
                                        ctl_client_ca_certificates: None,
-                                       http_listen:                HttpListenAddr::default(),
+                                       http_listen:                HttpListen::default(),
                                        http_disable:               false,
+                                       http_socket_mode:           SocketMode::default(),
                                        gossip_peers:               vec![],
                                        gossip_permanent:           false,
                                        ring_key:                   None,
@@ -1212,6 +1265,8 @@ sys_ip_address = "7.8.9.0"
                                        feature_flags:              FeatureFlag::empty(),
                                        event_stream_config:        None,
                                        keep_latest_packages:       None,
+                                       verify_package_signers:     SignerVerification::Off,
+                                       health_check_splay:         None,
                                        sys_ip:
                                            habitat_core::util::sys::ip().unwrap(), },
                        config);
@@ -1248,8 +1303,9 @@ sys_ip_address = "7.8.9.0"
                                        ctl_server_certificates:    None,
                                        ctl_server_key:             None,
                                        ctl_client_ca_certificates: None,
-                                       http_listen:                HttpListenAddr::default(),
+                                       http_listen:                HttpListen::default(),
                                        http_disable:               false,
+                                       http_socket_mode:           SocketMode::default(),
                                        gossip_peers:               vec![],
                                        gossip_permanent:           false,
                                        ring_key:                   None,
@@ -1259,6 +1315,8 @@ sys_ip_address = "7.8.9.0"
                                        feature_flags:              FeatureFlag::empty(),
                                        event_stream_config:        None,
                                        keep_latest_packages:       None,
+                                       verify_package_signers:     SignerVerification::Off,
+                                       health_check_splay:         None,
                                        sys_ip:
                                            habitat_core::util::sys::ip().unwrap(), },
                        config);
@@ -1301,8 +1359,9 @@ sys_ip_address = "7.8.9.0"
                                        ctl_server_certificates: None,
                                        ctl_server_key: None,
                                        ctl_client_ca_certificates: None,
-                                       http_listen: HttpListenAddr::default(),
+                                       http_listen: HttpListen::default(),
                                        http_disable: false,
+                                       http_socket_mode: SocketMode::default(),
                                        gossip_peers,
                                        gossip_permanent: false,
                                        ring_key: None,
@@ -1312,6 +1371,8 @@ sys_ip_address = "7.8.9.0"
                                        feature_flags: FeatureFlag::empty(),
                                        event_stream_config: None,
                                        keep_latest_packages: None,
+                                       verify_package_signers: SignerVerification::Off,
+                                       health_check_splay: None,
                                        sys_ip: habitat_core::util::sys::ip().unwrap() },
                        config);
         }
@@ -1409,8 +1470,9 @@ event_stream_server_certificate = "{}"
                     ctl_server_certificates: None,
                     ctl_server_key: None,
                     ctl_client_ca_certificates: None,
-                    http_listen: HttpListenAddr::default(),
+                    http_listen: HttpListen::default(),
                     http_disable: false,
+                    http_socket_mode: SocketMode::default(),
                     gossip_peers: vec![],
                     gossip_permanent: false,
                     ring_key: None,
@@ -1429,6 +1491,8 @@ event_stream_server_certificate = "{}"
                         server_certificate: Some(certificate_path_str.parse().unwrap()),
                     }),
                     keep_latest_packages: None,
+                    verify_package_signers: SignerVerification::Off,
+                    health_check_splay: None,
                     sys_ip: habitat_core::util::sys::ip().unwrap(),
                 },
                 config,
@@ -1453,6 +1517,7 @@ topology = "standalone"
 strategy = "at-once"
 update_condition = "track-channel"
 health_check_interval = 17
+health_check_splay = 5
 shutdown_timeout = 12
 pkg_ident_or_artifact = "core/redis"
 "#,
@@ -1489,12 +1554,19 @@ pkg_ident_or_artifact = "core/redis"
                                                  group:
                                                      Some(String::from("MyGroup")),
                                                  svc_encrypted_password: None,
+                                                 signer_verification:    None,
+                                                 peer_file:              None,
+                                                 peer_file_template:     None,
                                                  topology:
                                                      Some(Topology::Standalone.into()),
                                                  update_strategy:
                                                      Some(UpdateStrategy::AtOnce.into()),
                                                  health_check_interval:
                                                      Some(health_check_interval),
+                                                 health_check_splay:     Some(5),
+                                                 min_backoff_period:     None,
+                                                 max_backoff_period:     None,
+                                                 cooldown_period:        None,
                                                  shutdown_timeout:       Some(12),
                                                  update_condition:
                                                      Some(UpdateCondition::TrackChannel.into()), },
@@ -1602,8 +1674,9 @@ organization = "MY_ORG_FROM_SECOND_CONFG"
                                        ctl_server_key:             None,
                                        ctl_client_ca_certificates: None,
                                        http_listen:
-                                           HttpListenAddr::from_str("3.3.3.3:3333").unwrap(),
+                                           HttpListen::from_str("3.3.3.3:3333").unwrap(),
                                        http_disable:               false,
+                                       http_socket_mode:           SocketMode::default(),
                                        gossip_peers:               vec![],
                                        gossip_permanent:           false,
                                        ring_key:                   None,
@@ -1614,6 +1687,8 @@ organization = "MY_ORG_FROM_SECOND_CONFG"
                                        feature_flags:              FeatureFlag::empty(),
                                        event_stream_config:        None,
                                        keep_latest_packages:       None,
+                                       verify_package_signers:     SignerVerification::Off,
+                                       health_check_splay:         None,
                                        sys_ip:
                                            habitat_core::util::sys::ip().unwrap(), },
                        config);
@@ -1,7 +1,9 @@
 pub(crate) mod action;
 pub mod commands;
+pub mod converge;
 mod file_watcher;
 mod peer_watcher;
+pub(crate) mod readiness;
 mod self_updater;
 pub mod service;
 mod service_updater;
@@ -9,20 +11,27 @@ mod spec_dir;
 mod spec_watcher;
 mod sup_watcher;
 pub(crate) mod sys;
+pub(crate) mod task_supervisor;
 mod user_config_watcher;
 
 use self::{action::{ShutdownInput,
                     SupervisorAction},
            peer_watcher::PeerWatcher,
+           readiness::Readiness,
            self_updater::{SelfUpdater,
                           SUP_PKG_IDENT},
            service::{spec::{RefreshOperation,
                             ServiceOperation},
                      ConfigRendering,
                      DesiredState,
+                     HealthCheckHistory,
+                     HealthCheckRecord,
                      HealthCheckResult,
                      PersistentServiceWrapper,
                      Service,
+                     ServiceEvent,
+                     ServiceEventKind,
+                     ServiceLifecycle,
                      ServiceProxy,
                      ServiceRunState,
                      ServiceSpec,
@@ -31,6 +40,8 @@ use self::{action::{ShutdownInput,
            spec_dir::SpecDir,
            spec_watcher::SpecWatcher,
            sys::Sys,
+           task_supervisor::{RestartPolicy,
+                             ShutdownStage},
            user_config_watcher::UserConfigWatcher};
 use crate::{census::{CensusRing,
                      CensusRingProxy},
@@ -60,18 +71,21 @@ use habitat_butterfly::{member::Member,
 use habitat_common::{liveliness_checker,
                      outputln,
                      types::{GossipListenAddr,
-                             HttpListenAddr,
-                             ListenCtlAddr},
+                             HttpListen,
+                             ListenCtlAddr,
+                             SocketMode},
                      FeatureFlag};
 #[cfg(unix)]
 use habitat_core::os::{process::{ShutdownSignal,
                                  Signal},
                        signals};
 use habitat_core::{crypto::keys::{KeyCache,
+                                  NamedRevision,
                                   RingKey},
                    env,
                    env::Config,
-                   fs::FS_ROOT_PATH,
+                   fs::{self as hfs,
+                        FS_ROOT_PATH},
                    os::process::{self,
                                  ShutdownTimeout},
                    package::{Identifiable,
@@ -82,7 +96,8 @@ use habitat_core::{crypto::keys::{KeyCache,
                    ChannelIdent};
 use habitat_launcher_client::{LauncherCli,
                               LauncherStatus};
-use habitat_sup_protocol::{self};
+use habitat_sup_protocol::{self,
+                           types::SignerVerification};
 use lazy_static::lazy_static;
 use log::{debug,
           error,
@@ -115,10 +130,12 @@ use std::{collections::{HashMap,
                  IntoIterator},
           net::{IpAddr,
                 SocketAddr},
+          panic,
           path::{Path,
                  PathBuf},
           str::FromStr,
           sync::{atomic::{AtomicBool,
+                          AtomicU64,
                           Ordering},
                  mpsc as std_mpsc,
                  Arc,
@@ -128,11 +145,18 @@ use std::{collections::{HashMap,
           time::{Duration,
                  Instant,
                  SystemTime}};
+use tokio::{task,
+            time};
 #[cfg(windows)]
 use winapi::{shared::minwindef::PDWORD,
              um::processthreadsapi};
 
 const MEMBER_ID_FILE: &str = "MEMBER_ID";
+
+/// How often the "spec-reconciliation" task looks for changes to the
+/// spec files, and the "census" task for new rumors.
+const SPEC_RECONCILIATION_INTERVAL: Duration = Duration::from_millis(250);
+const CENSUS_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
 pub const PROC_LOCK_FILE: &str = "LOCK";
 
 static LOGKEY: &str = "MR";
@@ -165,6 +189,13 @@ habitat_core::env_config_duration!( HttpStartupTimeout,
                                     HAB_HTTP_STARTUP_TIMEOUT_SECS => from_secs,
                                     Duration::from_secs(10));
 
+habitat_core::env_config_duration!(
+    /// How often the gossip statistics served at /butterfly/stats are
+    /// refreshed; the counts "over the last interval" cover this long.
+    GossipStatsInterval,
+    HAB_SUP_GOSSIP_STATS_INTERVAL_SECS => from_secs,
+    Duration::from_secs(10));
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 /// Determines whether the new pidfile-less behavior is enabled, or
 /// the old behavior is used.
@@ -283,6 +314,20 @@ impl ServiceRestartConfig {
                                max_backoff_period,
                                cooldown_period: restart_cooldown_period }
     }
+
+    /// This configuration, with whichever of its periods `spec` gives
+    /// overriding it.
+    pub fn for_service(&self, spec: &ServiceSpec) -> ServiceRestartConfig {
+        let period = |secs: Option<u32>, default: Duration| {
+            secs.map_or(default, |secs| Duration::from_secs(secs.into()))
+        };
+        ServiceRestartConfig { min_backoff_period: period(spec.min_backoff_period,
+                                                          self.min_backoff_period),
+                               max_backoff_period: period(spec.max_backoff_period,
+                                                          self.max_backoff_period),
+                               cooldown_period:    period(spec.cooldown_period,
+                                                          self.cooldown_period), }
+    }
 }
 
 impl Default for ServiceRestartConfig {
@@ -310,8 +355,11 @@ pub struct ManagerConfig {
     pub ctl_server_key:             Option<PrivateKey>,
     #[derivative(PartialEq = "ignore")]
     pub ctl_client_ca_certificates: Option<RootCertStore>,
-    pub http_listen:                HttpListenAddr,
+    pub http_listen:                HttpListen,
     pub http_disable:               bool,
+    /// The permissions of the HTTP gateway's socket, if `http_listen`
+    /// is a Unix domain socket
+    pub http_socket_mode:           SocketMode,
     pub gossip_peers:               Vec<SocketAddr>,
     pub gossip_permanent:           bool,
     pub ring_key:                   Option<RingKey>,
@@ -324,6 +372,14 @@ pub struct ManagerConfig {
     /// others during service start. If this field is `None`, automatic package cleanup is
     /// disabled.
     pub keep_latest_packages:       Option<usize>,
+    /// How strictly to check the recorded signer and artifact hash of a service's installed
+    /// package against the public signing keys in `key_cache` before starting it. Individual
+    /// service specs may override this.
+    pub verify_package_signers:     SignerVerification,
+    /// How many seconds either way to move each health check of services whose specs don't
+    /// give a `health_check_splay` of their own. If this field is `None`, their health checks
+    /// aren't spread out.
+    pub health_check_splay:         Option<u32>,
     pub sys_ip:                     IpAddr,
 }
 
@@ -349,6 +405,11 @@ impl ManagerConfig {
         spec.to_file(self.spec_path_for(&spec.ident))
     }
 
+    /// Return the specs of all loaded services.
+    pub fn specs(&self) -> Result<Vec<ServiceSpec>> {
+        Ok(SpecDir::new(self.sup_root().join("specs"))?.specs())
+    }
+
     /// Given a `PackageIdent`, return current spec if it exists.
     pub fn spec_for_ident(&self, ident: &PackageIdent) -> Option<ServiceSpec> {
         let spec_file = self.spec_path_for(ident);
@@ -359,6 +420,22 @@ impl ManagerConfig {
     }
 }
 
+/// The data returned by the HTTP gateway's `/supervisor/config`
+/// endpoint.
+#[derive(Debug, Serialize)]
+struct SupervisorConfigProxy<'a> {
+    version:                   &'a str,
+    launcher_protocol_version: u32,
+}
+
+/// The data returned by the HTTP gateway's `/butterfly/ring-key`
+/// endpoint: the revisions of the ring key gossip is accepted with,
+/// newest first. Gossip is sent with the first of them.
+#[derive(Debug, Serialize)]
+struct RingKeyRevisionsProxy {
+    accepted: Vec<NamedRevision>,
+}
+
 /// Once a formerly-busy service is no longer doing something
 /// asynchronously, we mark that we should take a look at the spec
 /// files on disk to ensure that we're still "in sync".
@@ -424,6 +501,133 @@ impl ReconciliationFlag {
     }
 }
 
+/// Operations the "spec-reconciliation" task has worked out, for the
+/// main loop to carry out. `done` is sent once they have been.
+struct Reconciliation {
+    ops:  Vec<ServiceOperation>,
+    done: oneshot::Sender<()>,
+}
+
+/// What the "spec-reconciliation" task needs, shared between restarts.
+#[derive(Clone)]
+struct SpecReconciler {
+    state: Arc<ManagerState>,
+    spec_dir: SpecDir,
+    spec_watcher: Arc<Mutex<SpecWatcher>>,
+    busy_services: Arc<Mutex<HashSet<PackageIdent>>>,
+    services_need_reconciliation: ReconciliationFlag,
+    reconciliations: fut_mpsc::UnboundedSender<Reconciliation>,
+}
+
+impl SpecReconciler {
+    /// Work out what has to be done to bring the running services in
+    /// line with the spec files whenever they change, or a service
+    /// asks for them to be looked at again. The main loop carries out
+    /// the operations, since it owns the services, and the specs
+    /// aren't looked at again until it has; otherwise they'd be
+    /// compared against services that are about to change.
+    ///
+    /// # Locking (see locking.md)
+    /// * `ManagerServices::inner` (read)
+    async fn run_msr(self) {
+        let mut interval = time::interval(SPEC_RECONCILIATION_INTERVAL);
+        loop {
+            interval.tick().await;
+            let spec_files_changed = self.spec_watcher.lock().has_events();
+            if !spec_files_changed && !self.services_need_reconciliation.is_set() {
+                continue;
+            }
+            // This call *must* come before looking at the specs. If
+            // some service finishes an asynchronous operation and sets
+            // the flag while we're working out what to do, toggling it
+            // afterward would "lose" that signal, and its spec file
+            // wouldn't be looked at again until something else
+            // happened to change.
+            self.services_need_reconciliation.toggle_if_set();
+            let ops = Manager::compute_service_operations_msr(&self.state,
+                                                              &self.spec_dir,
+                                                              &self.busy_services);
+            if ops.is_empty() {
+                continue;
+            }
+            let (done, applied) = oneshot::channel();
+            if self.reconciliations
+                   .unbounded_send(Reconciliation { ops, done })
+                   .is_err()
+            {
+                // The main loop has exited
+                return;
+            }
+            applied.await.ok();
+        }
+    }
+}
+
+/// What the "census" task needs, shared between restarts.
+#[derive(Clone)]
+struct CensusUpdater {
+    state:          Arc<ManagerState>,
+    butterfly:      habitat_butterfly::Server,
+    census_ring:    Arc<RwLock<CensusRing>>,
+    /// Set when the census has changed, and cleared by the main loop
+    /// once every service has seen the change.
+    census_changed: ReconciliationFlag,
+    /// How many times the census has been updated from the rumors.
+    census_updates: Arc<AtomicU64>,
+}
+
+impl CensusUpdater {
+    /// Keep the census up to date with the rumors the Supervisor has
+    /// heard. Once it has changed, it's left alone until the main loop
+    /// has ticked every service with it, so that no service misses a
+    /// change (such as a newly gossiped file) that a later update
+    /// would overwrite.
+    ///
+    /// # Locking (see locking.md)
+    /// * `RumorStore::list` (write)
+    /// * `MemberList::entries` (read)
+    async fn run_rsr_mlr(self) {
+        let mut interval = time::interval(CENSUS_UPDATE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if self.census_changed.is_set() {
+                continue;
+            }
+            // The update blocks on the census ring and rumor locks, so
+            // it's kept off the runtime's worker threads.
+            let updater = self.clone();
+            let changed = match task::spawn_blocking(move || updater.update_rsr_mlr()).await {
+                Ok(changed) => changed,
+                // Let the task supervisor see the panic, and restart us
+                Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+                // The runtime is shutting down
+                Err(_) => return,
+            };
+            if changed {
+                self.census_changed.set();
+            }
+            self.census_updates.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Update the census from the rumors, returning whether it changed.
+    ///
+    /// # Locking (see locking.md)
+    /// * `RumorStore::list` (write)
+    /// * `MemberList::entries` (read)
+    fn update_rsr_mlr(&self) -> bool {
+        let mut census_ring = self.census_ring.write();
+        census_ring.update_from_rumors_rsr_mlr(&self.state.cfg.key_cache,
+                                               &self.butterfly.service_store,
+                                               &self.butterfly.election_store,
+                                               &self.butterfly.update_store,
+                                               &self.butterfly.member_list,
+                                               &self.butterfly.service_config_store,
+                                               &self.butterfly.service_file_store);
+        census_ring.changed()
+    }
+}
+
 /// This struct encapsulates the shared state for the supervisor. It's worth noting that if there's
 /// something you want the CtlGateway to be able to operate on, it needs to be put in here. This
 /// state gets shared with all the CtlGateway handlers.
@@ -433,6 +637,21 @@ pub struct ManagerState {
     services:       Arc<sync::ManagerServices>,
     gateway_state:  Arc<sync::GatewayState>,
     should_restart: AtomicBool,
+    /// The Supervisor's gossip server, whose ring key can be changed
+    /// while it's running.
+    butterfly:      habitat_butterfly::Server,
+}
+
+impl ManagerState {
+    /// # Locking (see locking.md)
+    /// * `GatewayState::inner` (write)
+    /// * `Server::ring_keys` (read)
+    fn persist_ring_key_state_gsw_srkr(&self) {
+        let proxy =
+            RingKeyRevisionsProxy { accepted: self.butterfly.accepted_ring_key_revisions_srkr(), };
+        let json = serde_json::to_string(&proxy).expect("RingKeyRevisionsProxy::serialize failure");
+        self.gateway_state.lock_gsw().set_ring_key_data(json);
+    }
 }
 
 pub(crate) mod sync {
@@ -440,11 +659,21 @@ pub(crate) mod sync {
     use habitat_common::sync::{Lock,
                                ReadGuard,
                                WriteGuard};
+    use tokio::sync::broadcast;
+
+    /// How many events a subscriber can fall behind by before it's
+    /// disconnected, to catch up again from a fresh snapshot.
+    const EVENT_CAPACITY: usize = 256;
 
-    pub struct GatewayStateReadGuard<'a>(ReadGuard<'a, GatewayStateInner>);
+    pub struct GatewayStateReadGuard<'a>(ReadGuard<'a, GatewayStateInner>,
+                                         &'a broadcast::Sender<ServiceEvent>);
 
     impl<'a> GatewayStateReadGuard<'a> {
-        fn new(lock: &'a Lock<GatewayStateInner>) -> Self { Self(lock.read()) }
+        fn new(lock: &'a Lock<GatewayStateInner>,
+               events: &'a broadcast::Sender<ServiceEvent>)
+               -> Self {
+            Self(lock.read(), events)
+        }
 
         pub fn butterfly_data(&self) -> &str { &self.0.butterfly_data }
 
@@ -452,15 +681,55 @@ pub(crate) mod sync {
 
         pub fn services_data(&self) -> &str { &self.0.services_data }
 
+        pub fn supervisor_data(&self) -> &str { &self.0.supervisor_data }
+
+        pub fn ring_key_data(&self) -> &str { &self.0.ring_key_data }
+
+        pub fn gossip_stats_data(&self) -> &str { &self.0.gossip_stats_data }
+
         pub fn health_of(&self, service_group: &ServiceGroup) -> Option<HealthCheckResult> {
             self.0.health_check_data.get(service_group).copied()
         }
+
+        pub fn health_history_of(&self,
+                                 service_group: &ServiceGroup)
+                                 -> Option<&HealthCheckHistory> {
+            self.0.health_check_history.get(service_group)
+        }
+
+        pub fn service_errors(&self) -> &HashMap<PackageIdent, String> { &self.0.service_errors }
+
+        pub fn readiness(&self) -> &Readiness { &self.0.readiness }
+
+        /// Subscribe to the events published about services, along
+        /// with a `Snapshot` event for each service loaded right now.
+        /// Events are only published with the write lock held, so
+        /// none are missed, or already covered by the snapshot.
+        pub fn subscribe_events(&self) -> (Vec<ServiceEvent>, broadcast::Receiver<ServiceEvent>) {
+            let mut snapshot = self.0
+                                   .lifecycles
+                                   .iter()
+                                   .map(|(service_group, state)| {
+                                       ServiceEvent::new(ServiceEventKind::Snapshot,
+                                                         service_group.clone(),
+                                                         *state,
+                                                         self.health_of(service_group))
+                                   })
+                                   .collect::<Vec<_>>();
+            snapshot.sort_by(|a, b| a.service_group.as_ref().cmp(b.service_group.as_ref()));
+            (snapshot, self.1.subscribe())
+        }
     }
 
-    pub struct GatewayStateWriteGuard<'a>(WriteGuard<'a, GatewayStateInner>);
+    pub struct GatewayStateWriteGuard<'a>(WriteGuard<'a, GatewayStateInner>,
+                                          &'a broadcast::Sender<ServiceEvent>);
 
     impl<'a> GatewayStateWriteGuard<'a> {
-        fn new(lock: &'a Lock<GatewayStateInner>) -> Self { Self(lock.write()) }
+        fn new(lock: &'a Lock<GatewayStateInner>,
+               events: &'a broadcast::Sender<ServiceEvent>)
+               -> Self {
+            Self(lock.write(), events)
+        }
 
         pub fn set_census_data(&mut self, new_data: String) { self.0.census_data = new_data }
 
@@ -468,43 +737,165 @@ pub(crate) mod sync {
 
         pub fn set_services_data(&mut self, new_data: String) { self.0.services_data = new_data }
 
+        pub fn set_supervisor_data(&mut self, new_data: String) {
+            self.0.supervisor_data = new_data
+        }
+
+        pub fn set_ring_key_data(&mut self, new_data: String) { self.0.ring_key_data = new_data }
+
+        pub fn set_gossip_stats_data(&mut self, new_data: String) {
+            self.0.gossip_stats_data = new_data
+        }
+
         pub fn remove(&mut self, service_group: &ServiceGroup) {
             self.0.health_check_data.remove(service_group);
         }
 
+        /// Services start out with an `Unknown` health, so only a
+        /// result other than the one before it is published.
         pub fn set_health_of(&mut self, service_group: ServiceGroup, value: HealthCheckResult) {
-            self.0.health_check_data.insert(service_group, value);
+            let previous = self.0
+                               .health_check_data
+                               .insert(service_group.clone(), value)
+                               .unwrap_or(HealthCheckResult::Unknown);
+            if previous != value {
+                self.publish(ServiceEventKind::HealthCheck, &service_group);
+            }
+        }
+
+        /// Kept until the service is unloaded, so a service's history
+        /// carries on through its restarts.
+        pub fn record_health_check(&mut self,
+                                   service_group: ServiceGroup,
+                                   record: HealthCheckRecord) {
+            self.0
+                .health_check_history
+                .entry(service_group)
+                .or_default()
+                .push(record);
+        }
+
+        /// Record what's observed of every loaded service (see
+        /// `ServiceLifecycle::next`), along with the state it's meant
+        /// to be in, publishing an event for each that has been
+        /// loaded, unloaded, or has changed state since last time.
+        pub fn set_lifecycles(&mut self,
+                              observed: HashMap<ServiceGroup, (ServiceLifecycle, DesiredState)>)
+        {
+            let mut previous = std::mem::take(&mut self.0.lifecycles);
+            for (service_group, (observed, desired_state)) in observed {
+                let last_state = previous.remove(&service_group);
+                let state = ServiceLifecycle::next(last_state, observed);
+                self.0.lifecycles.insert(service_group.clone(), state);
+                self.0
+                    .readiness
+                    .observe(&service_group, state, desired_state);
+                if last_state.is_none() {
+                    self.publish_as(ServiceEventKind::Loaded,
+                                    &service_group,
+                                    ServiceLifecycle::Loaded);
+                }
+                if state != last_state.unwrap_or(ServiceLifecycle::Loaded) {
+                    self.publish(state.into(), &service_group);
+                }
+            }
+            for service_group in previous.into_keys() {
+                self.0.health_check_history.remove(&service_group);
+                self.0.readiness.forget(&service_group);
+                self.publish_as(ServiceEventKind::Unloaded,
+                                &service_group,
+                                ServiceLifecycle::Unloaded);
+            }
+        }
+
+        /// Publish an event about a service, in whatever state it was
+        /// last recorded as being in.
+        pub fn publish(&self, event: ServiceEventKind, service_group: &ServiceGroup) {
+            let state = self.0
+                            .lifecycles
+                            .get(service_group)
+                            .copied()
+                            .unwrap_or(ServiceLifecycle::Loaded);
+            self.publish_as(event, service_group, state);
+        }
+
+        fn publish_as(&self,
+                      event: ServiceEventKind,
+                      service_group: &ServiceGroup,
+                      state: ServiceLifecycle) {
+            let health = self.0.health_check_data.get(service_group).copied();
+            // Sending only fails if there's nobody subscribed to hear it.
+            let _ = self.1
+                        .send(ServiceEvent::new(event, service_group.clone(), state, health));
         }
+
+        pub fn set_service_error(&mut self, ident: PackageIdent, error: String) {
+            self.0.service_errors.insert(ident, error);
+        }
+
+        pub fn clear_service_error(&mut self, ident: &PackageIdent) {
+            self.0.service_errors.remove(ident);
+        }
+
+        pub fn mark_specs_reconciled(&mut self) { self.0.readiness.mark_specs_reconciled() }
     }
 
     /// All the data that is ultimately served from the Supervisor's HTTP
     /// gateway.
-    #[derive(Debug, Default)]
+    #[derive(Debug)]
     pub struct GatewayState {
-        inner: Lock<GatewayStateInner>,
+        inner:  Lock<GatewayStateInner>,
+        /// Events served from the /events endpoint
+        events: broadcast::Sender<ServiceEvent>,
+    }
+
+    impl Default for GatewayState {
+        fn default() -> Self {
+            GatewayState { inner:  Lock::default(),
+                           events: broadcast::channel(EVENT_CAPACITY).0, }
+        }
     }
 
     impl GatewayState {
         #[must_use]
-        pub fn lock_gsr(&self) -> GatewayStateReadGuard { GatewayStateReadGuard::new(&self.inner) }
+        pub fn lock_gsr(&self) -> GatewayStateReadGuard {
+            GatewayStateReadGuard::new(&self.inner, &self.events)
+        }
 
         #[must_use]
         pub fn lock_gsw(&self) -> GatewayStateWriteGuard {
-            GatewayStateWriteGuard::new(&self.inner)
+            GatewayStateWriteGuard::new(&self.inner, &self.events)
         }
     }
 
     #[derive(Debug, Default)]
     struct GatewayStateInner {
         /// JSON returned by the /census endpoint
-        census_data:       String,
+        census_data:          String,
         /// JSON returned by the /butterfly endpoint
-        butterfly_data:    String,
+        butterfly_data:       String,
         /// JSON returned by the /services endpoint
-        services_data:     String,
+        services_data:        String,
+        /// JSON returned by the /supervisor/config endpoint
+        supervisor_data:      String,
+        /// JSON returned by the /butterfly/ring-key endpoint
+        ring_key_data:        String,
+        /// JSON returned by the /butterfly/stats endpoint
+        gossip_stats_data:    String,
         /// Data returned by /services/<SERVICE_NAME>/<GROUP_NAME>/health
         /// endpoint
-        health_check_data: HashMap<ServiceGroup, HealthCheckResult>,
+        health_check_data:    HashMap<ServiceGroup, HealthCheckResult>,
+        /// Data returned by /services/<SERVICE_NAME>/<GROUP_NAME>/health/history
+        /// endpoint
+        health_check_history: HashMap<ServiceGroup, HealthCheckHistory>,
+        /// Errors returned by the /errors endpoint, recording why a
+        /// service could not be loaded
+        service_errors:       HashMap<PackageIdent, String>,
+        /// The state of each loaded service, as of the last events
+        /// published about it
+        lifecycles:           HashMap<ServiceGroup, ServiceLifecycle>,
+        /// Returned by the /ready endpoint
+        readiness:            Readiness,
     }
 
     type ManagerServicesInner = HashMap<PackageIdent, PersistentServiceWrapper>;
@@ -615,7 +1006,7 @@ pub struct Manager {
     launcher:            LauncherCli,
     service_updater:     Arc<Mutex<ServiceUpdater>>,
     peer_watcher:        Option<PeerWatcher>,
-    spec_watcher:        SpecWatcher,
+    spec_watcher:        Arc<Mutex<SpecWatcher>>,
     // This Arc<RwLock<>> business is a potentially temporary
     // change. Right now, in order to asynchronously shut down
     // services, we need to be able to have a safe reference to this
@@ -657,6 +1048,9 @@ pub struct Manager {
     busy_services: Arc<Mutex<HashSet<PackageIdent>>>,
     updated_service_pkg_incarnations: Arc<Mutex<HashMap<ServiceGroup, u64>>>,
     services_need_reconciliation:     ReconciliationFlag,
+    /// See `CensusUpdater`.
+    census_changed:                   ReconciliationFlag,
+    census_updates:                   Arc<AtomicU64>,
 
     feature_flags: FeatureFlag,
     pid_source:    ServicePidSource,
@@ -676,6 +1070,7 @@ impl Manager {
     /// # Locking (see locking.md)
     /// * `MemberList::initial_members` (write)
     pub async fn load_imlw(cfg: ManagerConfig, launcher: LauncherCli) -> Result<Manager> {
+        Self::check_http_listen(&cfg)?;
         let state_path = cfg.sup_root();
         let fs_cfg = FsCfg::new(state_path);
         Self::create_state_path_dirs(&fs_cfg)?;
@@ -686,6 +1081,27 @@ impl Manager {
         Self::new_imlw(cfg, fs_cfg, lock_file, launcher).await
     }
 
+    /// Refuse HTTP gateway configurations that can't be served, before
+    /// anything else is started.
+    fn check_http_listen(cfg: &ManagerConfig) -> Result<()> {
+        if cfg.http_disable {
+            return Ok(());
+        }
+        if let HttpListen::Unix(_) = cfg.http_listen {
+            if cfg!(windows) {
+                return Err(Error::UnsupportedHttpListen(cfg.http_listen.to_string(),
+                                                        "Unix domain sockets are not \
+                                                         supported on Windows"));
+            }
+            if cfg.tls_config.is_some() {
+                return Err(Error::UnsupportedHttpListen(cfg.http_listen.to_string(),
+                                                        "TLS is only supported on a \
+                                                         TCP address"));
+            }
+        }
+        Ok(())
+    }
+
     /// Terminate the locally-running Supervisor/Launcher (assuming it is
     /// running, of course).
     ///
@@ -727,7 +1143,7 @@ impl Manager {
         let mut sys = Sys::new(cfg.gossip_permanent,
                                cfg.gossip_listen,
                                cfg.ctl_listen,
-                               cfg.http_listen,
+                               cfg.http_listen.tcp_addr(),
                                cfg.sys_ip);
         let member = Self::load_member(&mut sys, &fs_cfg)?;
         let services = Arc::default();
@@ -775,7 +1191,8 @@ impl Manager {
         Ok(Manager { state: Arc::new(ManagerState { cfg: cfg_static,
                                                     services,
                                                     gateway_state: Arc::default(),
-                                                    should_restart: AtomicBool::default() }),
+                                                    should_restart: AtomicBool::default(),
+                                                    butterfly: server.clone() }),
                      self_updater,
                      service_updater:
                          Arc::new(Mutex::new(ServiceUpdater::new(server.clone(),
@@ -785,7 +1202,7 @@ impl Manager {
                      butterfly: server,
                      launcher,
                      peer_watcher,
-                     spec_watcher,
+                     spec_watcher: Arc::new(Mutex::new(spec_watcher)),
                      user_config_watcher: UserConfigWatcher::new(),
                      spec_dir,
                      fs_cfg: Arc::new(fs_cfg),
@@ -796,6 +1213,8 @@ impl Manager {
                      busy_services: Arc::default(),
                      updated_service_pkg_incarnations: Arc::default(),
                      services_need_reconciliation: ReconciliationFlag::new(false),
+                     census_changed: ReconciliationFlag::new(false),
+                     census_updates: Arc::default(),
                      feature_flags: cfg.feature_flags,
                      pid_source,
                      _lock_file: lock_file })
@@ -857,10 +1276,20 @@ impl Manager {
                         _ => continue,
                     }
                 }
-                Ok(())
             }
-            Err(err) => Err(Error::BadDataPath(data_path.clone(), err)),
+            Err(err) => return Err(Error::BadDataPath(data_path.clone(), err)),
         }
+        // Temporary files left behind by writes that a previous
+        // Supervisor was killed in the middle of
+        for path in &[data_path, &fs_cfg.specs_path] {
+            debug!("Cleaning stale temporary files from {}", path.display());
+            if let Err(err) = hfs::cleanup_stale_tmp_files(path, hfs::STALE_TMP_FILE_AGE) {
+                warn!("Unable to clean up stale temporary files in {}: {}",
+                      path.display(),
+                      err);
+            }
+        }
+        Ok(())
     }
 
     fn create_state_path_dirs(fs_cfg: &FsCfg) -> Result<()> {
@@ -893,13 +1322,48 @@ impl Manager {
         }
     }
 
+    /// Check the signer of the package `service` was loaded from,
+    /// recording the outcome for the HTTP gateway's `/errors`
+    /// endpoint.
+    ///
+    /// # Locking (see locking.md)
+    /// * `GatewayState::inner` (write)
+    fn verify_package_signer_gsw(&self,
+                                 service: &Service,
+                                 policy: SignerVerification)
+                                 -> Result<()> {
+        let result = if policy == SignerVerification::Off {
+            Ok(())
+        } else {
+            let artifact_cache_path = habitat_core::fs::cache_artifact_path(Some(&*FS_ROOT_PATH));
+            PackageInstall::load(service.pkg.ident.as_ref(),
+                                 Some(Path::new(&*FS_ROOT_PATH)))
+                .map_err(Error::from)
+                .and_then(|package| {
+                    pkg::verify_signer(&package,
+                                       &self.state.cfg.key_cache,
+                                       &artifact_cache_path,
+                                       policy)
+                })
+        };
+        let mut gateway_state = self.state.gateway_state.lock_gsw();
+        match result {
+            Ok(()) => gateway_state.clear_service_error(&service.spec_ident()),
+            Err(ref err) => gateway_state.set_service_error(service.spec_ident(), err.to_string()),
+        }
+        result
+    }
+
     /// # Locking (see locking.md)
     /// * `RumorStore::list` (write)
     /// * `MemberList::entries` (write)
+    /// * `GatewayState::inner` (write)
     /// * `RumorHeat::inner` (write)
     /// * `ManagerServices::inner` (read)
-    async fn add_service_rsw_mlw_rhw_msr(&mut self, spec: ServiceSpec) {
+    async fn add_service_rsw_mlw_gsw_rhw_msr(&mut self, spec: ServiceSpec) {
         let ident = spec.ident.clone();
+        let signer_verification = spec.signer_verification
+                                      .unwrap_or(self.state.cfg.verify_package_signers);
         let mut service = match Service::new(self.sys.clone(),
                                              spec,
                                              self.fs_cfg.clone(),
@@ -907,7 +1371,8 @@ impl Manager {
                                              self.census_ring.clone(),
                                              self.state.gateway_state.clone(),
                                              self.pid_source,
-                                             self.feature_flags).await
+                                             self.feature_flags,
+                                             self.state.cfg.health_check_splay).await
         {
             Ok(service) => {
                 outputln!("Starting {} ({})", ident, service.pkg.ident);
@@ -921,6 +1386,13 @@ impl Manager {
             }
         };
 
+        if let Err(err) = self.verify_package_signer_gsw(&service, signer_verification) {
+            outputln!("Refusing to start {}, {}", ident, err);
+            // Remove the spec file so it does not look like this service is loaded.
+            self.remove_spec_file(&ident).ok();
+            return;
+        }
+
         if let Ok(package) =
             PackageInstall::load(service.pkg.ident.as_ref(), Some(Path::new(&*FS_ROOT_PATH)))
         {
@@ -992,14 +1464,16 @@ impl Manager {
     /// * `GatewayState::inner` (write)
     /// * `Server::member` (write)
     /// * `RumorHeat::inner` (write)
+    /// * `Server::ring_keys` (write)
     /// * `ManagerServices::inner` (write)
     #[allow(clippy::cognitive_complexity)]
-    pub async fn run_rsw_imlw_mlw_gsw_smw_rhw_msw(mut self,
-                                                  svc_load_msgs: Vec<habitat_sup_protocol::ctl::SvcLoad>)
-                                                  -> Result<()> {
+    pub async fn run_rsw_imlw_mlw_gsw_smw_rhw_srkw_msw(mut self,
+                                                       svc_load_msgs: Vec<habitat_sup_protocol::ctl::SvcLoad>)
+                                                       -> Result<()> {
         let main_hist = RUN_LOOP_DURATION.with_label_values(&["sup"]);
         let service_hist = RUN_LOOP_DURATION.with_label_values(&["service"]);
         let mut next_cpu_measurement = Instant::now();
+        let gossip_stats_interval: Duration = GossipStatsInterval::configured_value().into();
         let mut cpu_start = ProcessTime::now();
 
         // TODO (CM): consider bundling up these disparate channel
@@ -1034,6 +1508,8 @@ impl Manager {
         self.butterfly
             .start_rsw_mlw_smw_rhw_msr(&Timing::default())?;
         debug!("gossip-listener started");
+        self.persist_gossip_stats_mlr_gsw();
+        let mut next_gossip_stats = Instant::now() + gossip_stats_interval;
 
         // Update the census state from the butterfly service rumours.
         // We do this to ensure that service configuration data is always
@@ -1059,8 +1535,42 @@ impl Manager {
         }
 
         // Ensure that the updated census state is saved to the gateway
+        self.persist_supervisor_state_gsw();
+        self.state.persist_ring_key_state_gsw_srkr();
         self.persist_state_rsr_mlr_gsw_msr().await;
-        let http_listen_addr = self.sys.http_listen();
+        self.state.gateway_state.lock_gsw().mark_specs_reconciled();
+
+        // From here on, spec files are reconciled and the census is
+        // updated by supervised tasks, which hand their results to the
+        // main loop below.
+        let (reconciliation_sender, mut reconciliation_receiver) = fut_mpsc::unbounded();
+        let reconciler = SpecReconciler { state: Arc::clone(&self.state),
+                                          spec_dir: self.spec_dir.clone(),
+                                          spec_watcher: Arc::clone(&self.spec_watcher),
+                                          busy_services: Arc::clone(&self.busy_services),
+                                          services_need_reconciliation:
+                                              self.services_need_reconciliation.clone(),
+                                          reconciliations: reconciliation_sender, };
+        task_supervisor::spawn("spec-reconciliation",
+                               ShutdownStage::SpecReconciliation,
+                               RestartPolicy::Always,
+                               move || reconciler.clone().run_msr());
+        let census_updater = CensusUpdater { state:          Arc::clone(&self.state),
+                                             butterfly:      self.butterfly.clone(),
+                                             census_ring:    Arc::clone(&self.census_ring),
+                                             census_changed: self.census_changed.clone(),
+                                             census_updates: Arc::clone(&self.census_updates), };
+        task_supervisor::spawn("census",
+                               ShutdownStage::Census,
+                               RestartPolicy::Always,
+                               move || census_updater.clone().run_rsr_mlr());
+        // Services started (or restarted) by the main loop, along with
+        // how many census updates there had been at the time. Their
+        // updaters aren't registered until the census has been
+        // updated since; otherwise they may start from stale census
+        // data.
+        let mut updaters_to_register: Vec<(PackageIdent, u64)> = Vec::new();
+        let http_listen = self.state.cfg.http_listen.clone();
         let ctl_gateway_server =
             CtlGatewayServer { listen_addr: self.sys.ctl_listen(),
                                secret_key: ctl_gateway::readgen_secret_key(&self.fs_cfg
@@ -1102,8 +1612,9 @@ impl Manager {
             let pair =
                 Arc::new((StdMutex::new(http_gateway::ServerStartup::NotStarted), Condvar::new()));
 
-            outputln!("Starting http-gateway on {}", &http_listen_addr);
-            http_gateway::Server::run(http_listen_addr,
+            outputln!("Starting http-gateway on {}", &http_listen);
+            http_gateway::Server::run(http_listen.clone(),
+                                      self.state.cfg.http_socket_mode,
                                       tls_server_config,
                                       self.state.gateway_state.clone(),
                                       http_gateway::GatewayAuthenticationToken::configured_value(),
@@ -1134,7 +1645,7 @@ impl Manager {
                             {
                                 Ok((mutex, timeout_result)) => {
                                     if timeout_result.timed_out() {
-                                        return Err(Error::BindTimeout(http_listen_addr.to_string()));
+                                        return Err(Error::BindTimeout(http_listen.to_string()));
                                     } else {
                                         mutex
                                     }
@@ -1146,7 +1657,7 @@ impl Manager {
                             };
                     }
                     http_gateway::ServerStartup::BindFailed => {
-                        return Err(Error::BadAddress(http_listen_addr.to_string()));
+                        return Err(Error::BadAddress(http_listen.to_string()));
                     }
                     http_gateway::ServerStartup::Started => break,
                 }
@@ -1254,79 +1765,71 @@ impl Manager {
                 }
             }
 
-            // Indicates if we need to examine our on-disk specfiles
-            // in order to reconcile them with whatever we're
-            // currently running.
-            //
-            // Takes into account filesystem events in the specs
-            // directory, as well as whether or not we need to
-            // reexamine specs after finishing some asynchronous
-            // operation on a service.
-            let mut updaters_to_register =
-                if self.spec_watcher.has_events() || self.services_need_reconciliation.is_set() {
-                    // This call *must* come first. If some other future
-                    // happens to complete before we get done spawning our
-                    // current batch of futures, it could set the flag to
-                    // true, but we wouldn't have taken another look at
-                    // its spec file to see if we needed to do anything
-                    // else. Thus, we could "lose" that signal if we
-                    // toggle *after* spawning these futures.
-                    //
-                    // This could mean, say, the "start" part of a service
-                    // restart could be greatly delayed (until some file
-                    // event in the specs directory is registered, or
-                    // another service finishes shutting down).
-                    self.services_need_reconciliation.toggle_if_set();
-                    self.maybe_spawn_service_futures_rsw_mlw_gsw_rhw_msw().await
-                } else {
-                    Vec::new()
-                };
+            // Carry out whatever the "spec-reconciliation" task has
+            // worked out needs doing to bring the running services in
+            // line with their spec files.
+            let mut services_started = Vec::new();
+            while let Ok(Some(Reconciliation { ops, done })) = reconciliation_receiver.try_next() {
+                services_started.extend(self.spawn_futures_from_operations_rsw_mlw_gsw_rhw_msw(ops)
+                                            .await);
+                done.send(()).ok();
+            }
 
             self.update_peers_from_watch_file_mlr_imlw()?;
             self.update_running_services_from_user_config_watcher_msw();
 
             // Restart all services that need it
-            self.restart_services_rsw_mlr_rhw_msw(&mut updaters_to_register);
+            self.restart_services_rsw_mlr_rhw_msw(&mut services_started);
+            let census_updates = self.census_updates.load(Ordering::Relaxed);
+            updaters_to_register.extend(services_started.into_iter()
+                                                        .map(|ident| (ident, census_updates)));
 
             self.restart_elections_rsw_mlr_rhw_msr(self.feature_flags);
-            self.census_ring
-                .write()
-                .update_from_rumors_rsr_mlr(&self.state.cfg.key_cache,
-                                            &self.butterfly.service_store,
-                                            &self.butterfly.election_store,
-                                            &self.butterfly.update_store,
-                                            &self.butterfly.member_list,
-                                            &self.butterfly.service_config_store,
-                                            &self.butterfly.service_file_store);
-
-            if self.check_for_changed_services_msr() || self.census_ring.read().changed() {
+
+            // The "census" task leaves the census alone from when it
+            // changes until this is cleared, at the end of the loop.
+            let census_changed = self.census_changed.is_set();
+            if self.check_for_changed_services_msr() || census_changed {
                 self.persist_state_rsr_mlr_gsw_msr().await;
             }
 
-            // we do not want to register the services for updating until the
-            // census is updated from the rumors above. Otherwise the updater
-            // threads may have stale census data
-            for ident in updaters_to_register {
-                if let Some(wrapper) = self.state.services.lock_msr().get(&ident) {
+            let census_updates = self.census_updates.load(Ordering::Relaxed);
+            for (ident, _) in updaters_to_register.iter()
+                                                  .filter(|(_, seen)| *seen < census_updates)
+            {
+                if let Some(wrapper) = self.state.services.lock_msr().get(ident) {
                     if let Some(service) = wrapper.service() {
                         self.service_updater.lock().register(service);
                     }
                 }
             }
+            updaters_to_register.retain(|(_, seen)| *seen >= census_updates);
 
-            for service_state in self.state.services.lock_msw().services() {
-                // time will be recorded automatically by HistogramTimer's drop implementation when
-                // this var goes out of scope
-                #[allow(unused_variables)]
-                let service_timer = service_hist.start_timer();
-                if service_state.tick(&self.census_ring.read(), &self.launcher) {
-                    self.gossip_latest_service_rumor_rsw_mlw_rhw(service_state.service().expect("Service missing in PersistentServiceWrapper"), None);
-                }
-                if service_state.is_ready_for_restart() {
-                    debug!("Service ready to restart, setting reconciliation flag");
-                    self.services_need_reconciliation.set()
+            {
+                // Every service is ticked with the same census, which
+                // the "census" task can't update in the meantime.
+                let census_ring = Arc::clone(&self.census_ring);
+                let census_ring = census_ring.read();
+                for service_state in self.state.services.lock_msw().services() {
+                    // time will be recorded automatically by HistogramTimer's drop implementation
+                    // when this var goes out of scope
+                    #[allow(unused_variables)]
+                    let service_timer = service_hist.start_timer();
+                    if service_state.tick(&census_ring, &self.launcher) {
+                        let service =
+                            service_state.service()
+                                         .expect("Service missing in PersistentServiceWrapper");
+                        self.gossip_latest_service_rumor_rsw_mlw_rhw(service, None);
+                    }
+                    if service_state.is_ready_for_restart() {
+                        debug!("Service ready to restart, setting reconciliation flag");
+                        self.services_need_reconciliation.set()
+                    }
                 }
             }
+            if census_changed {
+                self.census_changed.toggle_if_set();
+            }
 
             // This is really only needed until everything is running
             // in futures.
@@ -1348,6 +1851,11 @@ impl Manager {
                 next_cpu_measurement = Instant::now() + Duration::from_secs(1);
                 cpu_start = ProcessTime::now();
             }
+
+            if Instant::now() >= next_gossip_stats {
+                self.persist_gossip_stats_mlr_gsw();
+                next_gossip_stats = Instant::now() + gossip_stats_interval;
+            }
         }; // end main loop
 
         // When we make it down here, we've broken out of the main
@@ -1370,7 +1878,7 @@ impl Manager {
             }
             ShutdownMode::Normal | ShutdownMode::Departed => {
                 outputln!("Gracefully departing from butterfly network.");
-                self.butterfly.set_departed_mlw_smw_rhw();
+                self.butterfly.set_departed_mlw_smw_rhw_srkr();
 
                 #[allow(clippy::from_iter_instead_of_collect)]
                 let service_stop_futures =
@@ -1387,6 +1895,11 @@ impl Manager {
             }
         }
 
+        // Cancel spec reconciliation, the updaters, health checks,
+        // census updates, and event exporter, in that order (see
+        // `task_supervisor::ShutdownStage`).
+        task_supervisor::shutdown().await;
+
         self.butterfly.persist_data_rsr_mlr();
 
         match shutdown_mode {
@@ -1567,6 +2080,19 @@ impl Manager {
         self.persist_services_state_gsw_msr().await;
     }
 
+    /// # Locking (see locking.md)
+    /// * `GatewayState::inner` (write)
+    fn persist_supervisor_state_gsw(&self) {
+        let launcher_protocol_version = self.launcher.protocol_version();
+        let proxy = SupervisorConfigProxy { version: VERSION,
+                                            launcher_protocol_version };
+        let json = serde_json::to_string(&proxy).expect("SupervisorConfigProxy::serialize failure");
+        self.state
+            .gateway_state
+            .lock_gsw()
+            .set_supervisor_data(json);
+    }
+
     /// # Locking (see locking.md)
     /// * `GatewayState::inner` (write)
     fn persist_census_state_gsw(&self) {
@@ -1576,6 +2102,18 @@ impl Manager {
         self.state.gateway_state.lock_gsw().set_census_data(json);
     }
 
+    /// # Locking (see locking.md)
+    /// * `MemberList::entries` (read)
+    /// * `GatewayState::inner` (write)
+    fn persist_gossip_stats_mlr_gsw(&self) {
+        let stats = self.butterfly.gossip_stats_snapshot_mlr();
+        let json = serde_json::to_string(&stats).expect("GossipStatsSnapshot::serialize failure");
+        self.state
+            .gateway_state
+            .lock_gsw()
+            .set_gossip_stats_data(json);
+    }
+
     /// # Locking (see locking.md)
     /// * `RumorStore::list` (read)
     /// * `MemberList::entries` (read)
@@ -1617,7 +2155,8 @@ impl Manager {
                                        self.census_ring.clone(),
                                        self.state.gateway_state.clone(),
                                        self.pid_source,
-                                       self.feature_flags).await
+                                       self.feature_flags,
+                                       self.state.cfg.health_check_splay).await
                     {
                         Ok(service) => {
                             watched_services.push((service, svc_state.service_run_state().clone()))
@@ -1636,13 +2175,15 @@ impl Manager {
                                    self.census_ring.clone(),
                                    self.state.gateway_state.clone(),
                                    self.pid_source,
-                                   self.feature_flags).await
+                                   self.feature_flags,
+                                   self.state.cfg.health_check_splay).await
                 {
                     Ok(service) => {
-                        watched_services.push((service,
-                                               ServiceRunState::new(&self.state
-                                                                         .cfg
-                                                                         .service_restart_config)))
+                        let restart_config = self.state
+                                                 .cfg
+                                                 .service_restart_config
+                                                 .for_service(&service.spec());
+                        watched_services.push((service, ServiceRunState::new(&restart_config)))
                     }
                     Err(err) => warn!("Failed to create service '{}' from spec: {:?}", ident, err),
                 };
@@ -1670,7 +2211,15 @@ impl Manager {
 
         let json =
             serde_json::to_string(&services_to_render).expect("ServiceProxy::serialize failure");
-        self.state.gateway_state.lock_gsw().set_services_data(json);
+        let lifecycles = services_to_render.iter()
+                                           .map(|proxy| {
+                                               (proxy.service_group().clone(),
+                                                (proxy.lifecycle(), proxy.desired_state()))
+                                           })
+                                           .collect();
+        let mut gateway_state = self.state.gateway_state.lock_gsw();
+        gateway_state.set_services_data(json);
+        gateway_state.set_lifecycles(lifecycles);
     }
 
     /// Check if any elections need restarting.
@@ -1818,7 +2367,8 @@ impl Manager {
     /// * `RumorHeat::inner` (write)
     /// * `ManagerServices::inner` (write)
     async fn maybe_spawn_service_futures_rsw_mlw_gsw_rhw_msw(&mut self) -> Vec<PackageIdent> {
-        let ops = self.compute_service_operations_msr();
+        let ops =
+            Self::compute_service_operations_msr(&self.state, &self.spec_dir, &self.busy_services);
         self.spawn_futures_from_operations_rsw_mlw_gsw_rhw_msw(ops)
             .await
     }
@@ -1852,6 +2402,7 @@ impl Manager {
         where O: IntoIterator<Item = ServiceOperation>
     {
         let mut services_started = Vec::new();
+        let mut services_updated = false;
         for op in ops.into_iter() {
             match op {
                 ServiceOperation::Restart { to_stop: spec, .. } | ServiceOperation::Stop(spec) => {
@@ -1886,7 +2437,7 @@ impl Manager {
                            .get(&spec.ident)
                            .map_or(true, PersistentServiceWrapper::is_ready_for_restart)
                     {
-                        self.add_service_rsw_mlw_rhw_msr(spec.clone()).await;
+                        self.add_service_rsw_mlw_gsw_rhw_msr(spec.clone()).await;
                         services_started.push(spec.ident.clone());
                     }
                 }
@@ -1895,16 +2446,22 @@ impl Manager {
                     let mut services = self.state.services.lock_msw();
                     // Relies on spec.ident not having changed, which
                     // ServiceSpec#reconcile must guarantee.
-                    if let Some(service) = services.get_mut(&spec.ident)
-                                                   .and_then(PersistentServiceWrapper::service_mut)
+                    let restart_config = self.state.cfg.service_restart_config.for_service(&spec);
+                    if let Some((service, run_state)) =
+                        services.get_mut(&spec.ident)
+                                .and_then(PersistentServiceWrapper::service_and_run_state_mut)
                     {
                         service.set_spec(spec);
+                        services_updated = true;
                         self.gossip_latest_service_rumor_rsw_mlw_rhw(service, None);
                         for op in ops {
                             match op {
                                 RefreshOperation::RestartUpdater => {
                                     self.service_updater.lock().register(service);
                                 }
+                                RefreshOperation::ReconfigureRestarts => {
+                                    run_state.reconfigure_restarts(restart_config.clone());
+                                }
                             }
                         }
                     } else {
@@ -1919,6 +2476,11 @@ impl Manager {
                 }
             }
         }
+        // Services updated in place haven't changed state, so make
+        // sure the gateway picks up their new specs.
+        if services_updated {
+            self.persist_state_rsr_mlr_gsw_msr().await;
+        }
         services_started
     }
 
@@ -1929,19 +2491,21 @@ impl Manager {
     /// See `specs_to_operations` for the real logic.
     /// # Locking (see locking.md)
     /// * `ManagerServices::inner` (read)
-    fn compute_service_operations_msr(&mut self) -> Vec<ServiceOperation> {
+    fn compute_service_operations_msr(state: &ManagerState,
+                                      spec_dir: &SpecDir,
+                                      busy_services: &Mutex<HashSet<PackageIdent>>)
+                                      -> Vec<ServiceOperation> {
         // First, figure out what's currently running.
-        let service_map = self.state.services.lock_msr();
+        let service_map = state.services.lock_msr();
         let currently_running_specs = service_map.running_services().map(Service::spec);
 
         // Now, figure out what we should compare against, ignoring
         // any services that are currently doing something
         // asynchronously.
-        let busy_services = self.busy_services.lock();
-        let on_disk_specs = self.spec_dir
-                                .specs()
-                                .into_iter()
-                                .filter(|s| !busy_services.contains(&s.ident));
+        let busy_services = busy_services.lock();
+        let on_disk_specs = spec_dir.specs()
+                                    .into_iter()
+                                    .filter(|s| !busy_services.contains(&s.ident));
 
         Self::specs_to_operations(currently_running_specs, on_disk_specs)
     }
@@ -2124,6 +2688,93 @@ mod test {
         }
     }
 
+    mod gateway_state {
+        use super::*;
+        use crate::manager::service::HealthCheckHookStatus;
+        use sync::GatewayState;
+
+        fn kinds(events: &[ServiceEvent]) -> Vec<(ServiceEventKind, ServiceLifecycle)> {
+            events.iter().map(|e| (e.event, e.state)).collect()
+        }
+
+        fn observed(service_group: &ServiceGroup,
+                    state: ServiceLifecycle)
+                    -> HashMap<ServiceGroup, (ServiceLifecycle, DesiredState)> {
+            vec![(service_group.clone(), (state, DesiredState::Up))].into_iter()
+                                                                    .collect()
+        }
+
+        #[test]
+        fn subscribers_get_a_snapshot_then_every_change() {
+            let gateway_state = GatewayState::default();
+            let redis = ServiceGroup::new("redis", "default", None).unwrap();
+            gateway_state.lock_gsw()
+                         .set_lifecycles(observed(&redis, ServiceLifecycle::Down));
+
+            let (snapshot, mut receiver) = gateway_state.lock_gsr().subscribe_events();
+            assert_eq!(kinds(&snapshot),
+                       vec![(ServiceEventKind::Snapshot, ServiceLifecycle::Loaded)]);
+
+            let mut gsw = gateway_state.lock_gsw();
+            gsw.set_lifecycles(observed(&redis, ServiceLifecycle::Up));
+            gsw.set_health_of(redis.clone(), HealthCheckResult::Unknown);
+            gsw.set_health_of(redis.clone(), HealthCheckResult::Ok);
+            gsw.set_health_of(redis.clone(), HealthCheckResult::Ok);
+            gsw.set_lifecycles(HashMap::new());
+            drop(gsw);
+
+            let events = std::iter::from_fn(|| receiver.try_recv().ok()).collect::<Vec<_>>();
+            assert_eq!(kinds(&events),
+                       vec![(ServiceEventKind::Up, ServiceLifecycle::Up),
+                            (ServiceEventKind::HealthCheck, ServiceLifecycle::Up),
+                            (ServiceEventKind::Unloaded, ServiceLifecycle::Unloaded)]);
+            assert_eq!(events[1].health, Some(HealthCheckResult::Ok));
+        }
+
+        #[test]
+        fn health_check_history_is_kept_until_the_service_is_unloaded() {
+            let gateway_state = GatewayState::default();
+            let redis = ServiceGroup::new("redis", "default", None).unwrap();
+            let record = |status| HealthCheckRecord::new(status, &HealthCheckHookStatus::NoHook);
+            let statuses = |gateway_state: &GatewayState| {
+                gateway_state.lock_gsr()
+                             .health_history_of(&redis)
+                             .map(|history| history.iter().map(|r| r.status).collect::<Vec<_>>())
+            };
+
+            let mut gsw = gateway_state.lock_gsw();
+            gsw.set_lifecycles(observed(&redis, ServiceLifecycle::Up));
+            gsw.record_health_check(redis.clone(), record(HealthCheckResult::Ok));
+            gsw.remove(&redis);
+            gsw.record_health_check(redis.clone(), record(HealthCheckResult::Critical));
+            drop(gsw);
+            assert_eq!(statuses(&gateway_state),
+                       Some(vec![HealthCheckResult::Ok, HealthCheckResult::Critical]));
+
+            gateway_state.lock_gsw().set_lifecycles(HashMap::new());
+            assert_eq!(statuses(&gateway_state), None);
+        }
+
+        #[test]
+        fn readiness_follows_recorded_lifecycles() {
+            let gateway_state = GatewayState::default();
+            let redis = ServiceGroup::new("redis", "default", None).unwrap();
+            let is_ready =
+                |gateway_state: &GatewayState| gateway_state.lock_gsr().readiness().is_ready();
+
+            let mut gsw = gateway_state.lock_gsw();
+            gsw.set_lifecycles(observed(&redis, ServiceLifecycle::Down));
+            gsw.mark_specs_reconciled();
+            drop(gsw);
+            assert!(!is_ready(&gateway_state),
+                    "Services that haven't started are waited on");
+
+            gateway_state.lock_gsw()
+                         .set_lifecycles(observed(&redis, ServiceLifecycle::Up));
+            assert!(is_ready(&gateway_state));
+        }
+    }
+
     // Implementing Default in production code encourages passing the entirety of this struct
     // around when it would be better to be more targeted. However, it is very handy for test
     // code, so only implement it under test configuration.
@@ -2142,8 +2793,9 @@ mod test {
                             ctl_server_certificates:    None,
                             ctl_server_key:             None,
                             ctl_client_ca_certificates: None,
-                            http_listen:                HttpListenAddr::default(),
+                            http_listen:                HttpListen::default(),
                             http_disable:               false,
+                            http_socket_mode:           SocketMode::default(),
                             gossip_peers:               vec![],
                             gossip_permanent:           false,
                             ring_key:                   None,
@@ -2153,6 +2805,8 @@ mod test {
                             feature_flags:              FeatureFlag::empty(),
                             event_stream_config:        None,
                             keep_latest_packages:       None,
+                            verify_package_signers:     SignerVerification::Off,
+                            health_check_splay:         None,
                             sys_ip:                     IpAddr::V4(Ipv4Addr::LOCALHOST), }
         }
     }
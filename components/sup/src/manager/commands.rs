@@ -1,20 +1,30 @@
 //! All the code for responding to Supervisor commands
 
 use crate::{ctl_gateway::CtlRequest,
-            error::Error,
+            error::{Error,
+                    Result},
             manager::{action::{ActionSender,
+                               ShutdownInput,
                                SupervisorAction},
-                      service::{spec::ServiceSpec,
+                      converge::{self,
+                                 ConvergeAction,
+                                 ConvergencePlan},
+                      service::{spec::{ServiceOperation,
+                                       ServiceSpec},
                                 DesiredState,
-                                ProcessState},
+                                ProcessState,
+                                Service},
                       ManagerState},
             util};
-use habitat_butterfly as butterfly;
+use habitat_butterfly::{self as butterfly,
+                        message::RingKeys};
 use habitat_common::{command::package::install::InstallSource,
                      outputln,
-                     templating::package::Pkg,
+                     templating::{config::REDACTED_VALUE,
+                                  package::Pkg},
                      ui::UIWriter};
-use habitat_core::{package::{Identifiable,
+use habitat_core::{crypto::keys::Key,
+                   package::{Identifiable,
                              PackageIdent,
                              PackageTarget},
                    service::ServiceGroup};
@@ -33,6 +43,14 @@ use std::{convert::TryFrom,
 
 static LOGKEY: &str = "CMD";
 
+habitat_core::env_config_int!(
+    /// How many revisions of the ring key before the newest one gossip
+    /// is still accepted with once the ring key is reloaded.
+    RingKeyRevisionWindow,
+    usize,
+    HAB_SUP_RING_KEY_REVISION_WINDOW,
+    1);
+
 /// # Locking (see locking.md)
 /// * `ManagerServices::inner` (read)
 pub fn service_cfg_msr(mgr: &ManagerState,
@@ -57,6 +75,73 @@ pub fn service_cfg_msr(mgr: &ManagerState,
     Err(net::err(ErrCode::NotFound, format!("Service not loaded, {}", ident)))
 }
 
+/// # Locking (see locking.md)
+/// * `ManagerServices::inner` (read)
+pub fn service_effective_cfg_msr(mgr: &ManagerState,
+                                 req: &mut CtlRequest,
+                                 opts: protocol::ctl::SvcGetEffectiveCfg)
+                                 -> NetResult<()> {
+    let ident: PackageIdent = opts.ident.ok_or_else(err_update_client)?.into();
+    for service in mgr.services.lock_msr().running_services() {
+        if service.pkg.ident.satisfies(&ident) {
+            let mut spec = service.spec();
+            if spec.svc_encrypted_password.is_some() {
+                spec.svc_encrypted_password = Some(REDACTED_VALUE.to_string());
+            }
+            let spec = Some(spec.to_toml_string()?);
+            let spec_file = Some(service.spec_file().display().to_string());
+            let cfg = toml::to_string_pretty(&toml::Value::Table(service.cfg.to_redacted()))
+                .map_err(Error::from)?;
+            let msg =
+                protocol::ctl::ServiceEffectiveCfg { spec,
+                                                     spec_file,
+                                                     cfg: Some(cfg),
+                                                     gossip_incarnation:
+                                                         Some(service.cfg.gossip_incarnation),
+                                                     cfg_changed_at:
+                                                         epoch_seconds(service.cfg_changed_at()),
+                                                     cfg_rendered_at:
+                                                         service.cfg_rendered_at()
+                                                                .and_then(epoch_seconds) };
+            req.reply_complete(msg);
+            return Ok(());
+        }
+    }
+    Err(net::err(ErrCode::NotFound, format!("Service not loaded, {}", ident)))
+}
+
+/// Run a service's health check right away and reply with what it
+/// found. The services lock is only held long enough to start the
+/// check, not while waiting for it.
+///
+/// # Locking (see locking.md)
+/// * `ManagerServices::inner` (read)
+pub async fn service_health_check_run_msr(mgr: &ManagerState,
+                                          req: &mut CtlRequest,
+                                          opts: protocol::ctl::SvcHealthCheckRun)
+                                          -> NetResult<()> {
+    let service_group: ServiceGroup = opts.service_group.ok_or_else(err_update_client)?.into();
+    let check = mgr.services
+                   .lock_msr()
+                   .running_services()
+                   .find(|service| service.service_group == service_group)
+                   .map(Service::check_health_now);
+    let outcome = match check {
+        Some(check) => check.await,
+        None => {
+            return Err(net::err(ErrCode::NotFound,
+                                format!("Service not loaded, {}", service_group)));
+        }
+    };
+    let status: protocol::types::HealthCheckResult = outcome.result.into();
+    let duration_ms = outcome.duration.map(|duration| duration.as_millis() as u64);
+    let msg = protocol::ctl::ServiceHealthCheck { status: Some(status as i32),
+                                                  exit_code: outcome.exit_code,
+                                                  duration_ms };
+    req.reply_complete(msg);
+    Ok(())
+}
+
 pub fn service_cfg_validate(_mgr: &ManagerState,
                             req: &mut CtlRequest,
                             opts: protocol::ctl::SvcValidateCfg)
@@ -117,10 +202,12 @@ pub fn service_cfg_validate(_mgr: &ManagerState,
     // ))
 }
 
-pub fn service_cfg_set(mgr: &ManagerState,
-                       req: &mut CtlRequest,
-                       opts: protocol::ctl::SvcSetCfg)
-                       -> NetResult<()> {
+/// # Locking (see locking.md)
+/// * `Server::ring_keys` (read)
+pub fn service_cfg_set_srkr(mgr: &ManagerState,
+                            req: &mut CtlRequest,
+                            opts: protocol::ctl::SvcSetCfg)
+                            -> NetResult<()> {
     let cfg = opts.cfg.ok_or_else(err_update_client)?;
     let is_encrypted = opts.is_encrypted.unwrap_or(false);
     let version = opts.version.ok_or_else(err_update_client)?;
@@ -133,7 +220,7 @@ pub fn service_cfg_set(mgr: &ManagerState,
               service_group,);
     let mut client =
         match butterfly::client::Client::new(&mgr.cfg.gossip_listen.local_addr().to_string(),
-                                             mgr.cfg.ring_key.clone())
+                                             mgr.butterfly.ring_key_srkr())
         {
             Ok(client) => client,
             Err(err) => {
@@ -148,10 +235,12 @@ pub fn service_cfg_set(mgr: &ManagerState,
           })
 }
 
-pub fn service_file_put(mgr: &ManagerState,
-                        req: &mut CtlRequest,
-                        opts: protocol::ctl::SvcFilePut)
-                        -> NetResult<()> {
+/// # Locking (see locking.md)
+/// * `Server::ring_keys` (read)
+pub fn service_file_put_srkr(mgr: &ManagerState,
+                             req: &mut CtlRequest,
+                             opts: protocol::ctl::SvcFilePut)
+                             -> NetResult<()> {
     let content = opts.content.ok_or_else(err_update_client)?;
     let filename = opts.filename.ok_or_else(err_update_client)?;
     let is_encrypted = opts.is_encrypted.unwrap_or(false);
@@ -166,7 +255,7 @@ pub fn service_file_put(mgr: &ManagerState,
               service_group,);
     let mut client =
         match butterfly::client::Client::new(&mgr.cfg.gossip_listen.local_addr().to_string(),
-                                             mgr.cfg.ring_key.clone())
+                                             mgr.butterfly.ring_key_srkr())
         {
             Ok(client) => client,
             Err(err) => {
@@ -212,18 +301,47 @@ pub async fn service_load(mgr: &ManagerState,
     Ok(())
 }
 
+/// Apply a `hab svc update` patch to a loaded service's spec. The
+/// reply says whether the service will be restarted to pick up the
+/// changes; see `ServiceSpec::reconcile` for which fields require it.
 pub fn service_update(mgr: &ManagerState,
                       req: &mut CtlRequest,
                       opts: protocol::ctl::SvcUpdate,
                       action_sender: &ActionSender)
                       -> NetResult<()> {
     let ident: PackageIdent = opts.ident.clone().ok_or_else(err_update_client)?.into();
-    if let Some(mut service_spec) = mgr.cfg.spec_for_ident(&ident) {
-        service_spec.merge_svc_update(opts);
+    if let Some(current_spec) = mgr.cfg.spec_for_ident(&ident) {
+        let mut service_spec = current_spec.clone();
+        service_spec.merge_svc_update(opts)?;
+        if service_spec == current_spec {
+            req.info(format!("{} is already up to date", ident))?;
+            req.reply_complete(net::ok());
+            return Ok(());
+        }
+        let package = util::pkg::installed(&service_spec.ident).ok_or_else(|| {
+                          Error::PackageNotFound(service_spec.ident.clone())
+                      })?;
+        service_spec.validate(&package)?;
+
+        let message = match ServiceSpec::reconcile(Some(current_spec), Some(service_spec.clone())) {
+            Some(ServiceOperation::Restart { .. }) => {
+                format!("Updating {}; the service will be restarted to apply the changes",
+                        ident)
+            }
+            Some(ServiceOperation::Update(..)) => {
+                format!("Updating {} in place; the service will not be restarted",
+                        ident)
+            }
+            _ => {
+                format!("Updating {}; the changes will take effect when the service is next \
+                         started",
+                        ident)
+            }
+        };
         let action = SupervisorAction::UpdateService { service_spec };
         send_action(action, action_sender)?;
 
-        req.info(format!("Updating {}", ident))?;
+        req.info(message)?;
         req.reply_complete(net::ok());
         Ok(())
     } else {
@@ -309,14 +427,16 @@ pub fn service_stop(mgr: &ManagerState,
     Ok(())
 }
 
-pub fn supervisor_depart(mgr: &ManagerState,
-                         req: &mut CtlRequest,
-                         opts: protocol::ctl::SupDepart)
-                         -> NetResult<()> {
+/// # Locking (see locking.md)
+/// * `Server::ring_keys` (read)
+pub fn supervisor_depart_srkr(mgr: &ManagerState,
+                              req: &mut CtlRequest,
+                              opts: protocol::ctl::SupDepart)
+                              -> NetResult<()> {
     let member_id = opts.member_id.ok_or_else(err_update_client)?;
     let mut client =
         match butterfly::client::Client::new(&mgr.cfg.gossip_listen.local_addr().to_string(),
-                                             mgr.cfg.ring_key.clone())
+                                             mgr.butterfly.ring_key_srkr())
         {
             Ok(client) => client,
             Err(err) => {
@@ -334,6 +454,105 @@ pub fn supervisor_depart(mgr: &ManagerState,
     }
 }
 
+/// Compute the `ConvergencePlan` that takes the Supervisor to the
+/// submitted desired state and, unless this is a dry run, apply it.
+/// Every action is attempted even if an earlier one fails; the reply
+/// lists the outcome of each.
+///
+/// # Locking (see locking.md)
+/// * `GatewayState::inner` (read)
+/// * `Server::ring_keys` (read)
+pub async fn supervisor_converge_gsr_srkr(mgr: &ManagerState,
+                                          req: &mut CtlRequest,
+                                          opts: protocol::ctl::SupConverge,
+                                          action_sender: &ActionSender)
+                                          -> NetResult<()> {
+    use protocol::ctl::converge_action::{Kind,
+                                         Status};
+
+    let desired: converge::DesiredState =
+        opts.desired_state.ok_or_else(err_update_client)?.parse()?;
+    let applied_configs = converge::applied_configs(mgr.gateway_state.lock_gsr().census_data())?;
+    let plan = ConvergencePlan::new(&desired,
+                                    &mgr.cfg.specs()?,
+                                    &applied_configs,
+                                    mgr.cfg.organization.as_deref())?;
+    let dry_run = opts.dry_run.unwrap_or(false);
+
+    let mut reply = protocol::ctl::ConvergencePlan::default();
+    for action in plan.actions {
+        let kind = match action {
+            ConvergeAction::Load(_) => Kind::Load,
+            ConvergeAction::Unload(_) => Kind::Unload,
+            ConvergeAction::Update { .. } => Kind::Update,
+            ConvergeAction::ApplyConfig { .. } => Kind::ApplyConfig,
+        };
+        let mut msg =
+            protocol::ctl::ConvergeAction { kind:        Some(kind as i32),
+                                            ident:       Some(action.ident().clone().into()),
+                                            description: Some(action.to_string()),
+                                            status:      Some(Status::Planned as i32),
+                                            error:       None, };
+        if !dry_run {
+            match apply_converge_action_srkr(mgr, req, action, action_sender).await {
+                Ok(()) => msg.status = Some(Status::Applied as i32),
+                Err(e) => {
+                    outputln!("Failed to converge: {}", e);
+                    msg.status = Some(Status::Failed as i32);
+                    msg.error = Some(e.to_string());
+                }
+            }
+        }
+        reply.actions.push(msg);
+    }
+    req.reply_complete(reply);
+    Ok(())
+}
+
+/// Reload the ring key from the key cache: gossip is sent with its
+/// newest revision from now on, but still accepted if it's encrypted
+/// with one of the `RingKeyRevisionWindow` revisions before that.
+/// Replies with the revisions now accepted.
+///
+/// # Locking (see locking.md)
+/// * `GatewayState::inner` (write)
+/// * `Server::ring_keys` (write)
+#[allow(clippy::needless_pass_by_value)]
+pub fn supervisor_ring_key_reload_gsw_srkw(mgr: &ManagerState,
+                                           req: &mut CtlRequest,
+                                           _opts: protocol::ctl::SupRingKeyReload)
+                                           -> NetResult<()> {
+    let name = match mgr.cfg.ring_key {
+        Some(ref ring_key) => ring_key.named_revision().name(),
+        None => {
+            return Err(net::err(ErrCode::NotSupported,
+                                "This Supervisor is not on an encrypted ring"));
+        }
+    };
+    let window = RingKeyRevisionWindow::configured_value().into();
+    let ring_keys =
+        RingKeys::from_revisions(mgr.cfg.key_cache.ring_keys(name)?, window).ok_or_else(|| {
+            net::err(ErrCode::NotFound,
+                     format!("No revisions of the ring key {} found in {}",
+                             name,
+                             mgr.cfg.key_cache.as_ref().display()))
+        })?;
+    let current = ring_keys.current().named_revision().clone();
+    mgr.butterfly
+       .set_ring_keys_srkw(ring_keys)
+       .map_err(|e| net::err(ErrCode::NotSupported, e))?;
+    mgr.persist_ring_key_state_gsw_srkr();
+    outputln!("Sending gossip with ring key {}", current);
+
+    let accepted = mgr.butterfly
+                      .accepted_ring_key_revisions_srkr()
+                      .iter()
+                      .map(ToString::to_string)
+                      .collect();
+    req.reply_complete(protocol::ctl::RingKeyRevisions { accepted });
+    Ok(())
+}
+
 #[allow(clippy::needless_pass_by_value)]
 pub fn supervisor_restart(mgr: &ManagerState,
                           _req: &mut CtlRequest,
@@ -384,6 +603,12 @@ pub fn service_status_gsr(mgr: &ManagerState,
 // Private helper functions
 fn err_update_client() -> net::NetErr { net::err(ErrCode::UpdateClient, "client out of date") }
 
+fn epoch_seconds(time: SystemTime) -> Option<u64> {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .ok()
+        .map(|duration| duration.as_secs())
+}
+
 #[derive(Deserialize)]
 struct ServiceStatus {
     pkg:           Pkg,
@@ -463,6 +688,55 @@ fn duration_from_epoch_offset<'de, D>(d: D) -> result::Result<Duration, D::Error
     d.deserialize_u64(FromEpochOffset)
 }
 
+/// # Locking (see locking.md)
+/// * `Server::ring_keys` (read)
+async fn apply_converge_action_srkr(mgr: &ManagerState,
+                                    req: &mut CtlRequest,
+                                    action: ConvergeAction,
+                                    action_sender: &ActionSender)
+                                    -> Result<()> {
+    match action {
+        ConvergeAction::Load(spec) => {
+            let source = InstallSource::Ident(spec.ident.clone(), PackageTarget::active_target());
+            let package =
+                util::pkg::satisfy_or_install(req, &source, &spec.bldr_url, &spec.channel).await?;
+            spec.validate(&package)?;
+            mgr.cfg.save_spec_for(&spec)
+        }
+        ConvergeAction::Unload(service_spec) => {
+            let action = SupervisorAction::UnloadService { service_spec,
+                                                           shutdown_input:
+                                                               ShutdownInput::default() };
+            Ok(send_action(action, action_sender)?)
+        }
+        ConvergeAction::Update { desired: service_spec,
+                                 .. } => {
+            let source =
+                InstallSource::Ident(service_spec.ident.clone(), PackageTarget::active_target());
+            let package = util::pkg::satisfy_or_install(req,
+                                                        &source,
+                                                        &service_spec.bldr_url,
+                                                        &service_spec.channel).await?;
+            service_spec.validate(&package)?;
+            let action = SupervisorAction::UpdateService { service_spec };
+            Ok(send_action(action, action_sender)?)
+        }
+        ConvergeAction::ApplyConfig { service_group,
+                                      incarnation,
+                                      config,
+                                      .. } => {
+            let cfg = toml::to_string(&config)?;
+            if cfg.len() > protocol::butterfly::MAX_SVC_CFG_SIZE {
+                return Err(net::err(ErrCode::EntityTooLarge, "Configuration too large.").into());
+            }
+            let mut client =
+                butterfly::client::Client::new(&mgr.cfg.gossip_listen.local_addr().to_string(),
+                                               mgr.butterfly.ring_key_srkr())?;
+            Ok(client.send_service_config(service_group, incarnation, cfg.as_bytes(), false)?)
+        }
+    }
+}
+
 /// Helper function to ensure that all errors in sending are handled identically.
 fn send_action(action: SupervisorAction, sender: &ActionSender) -> NetResult<()> {
     if sender.send(action).is_err() {
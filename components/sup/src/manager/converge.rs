@@ -0,0 +1,436 @@
+//! Converges the Supervisor on a declared set of services, for
+//! config-management tools that want an idempotent "make it so this"
+//! primitive rather than a sequence of `hab svc` commands.
+//!
+//! A `DesiredState` is diffed against the loaded specs and the
+//! configuration gossiped to each service group, producing a
+//! `ConvergencePlan`. The plan can be reported as is (a dry run), or
+//! applied one action at a time, with the outcome of each action
+//! reported separately so that one failure doesn't hide the rest.
+
+use super::service::{spec::ServiceSpec,
+                     Topology};
+use crate::error::{Error,
+                   Result};
+use habitat_core::{package::{Identifiable,
+                             PackageIdent},
+                   service::ServiceGroup,
+                   util,
+                   ChannelIdent};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::{BTreeMap,
+                        HashMap},
+          fmt,
+          str::FromStr};
+
+/// Every service a Supervisor should be running. Loaded services
+/// that aren't listed are unloaded.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DesiredState {
+    #[serde(default)]
+    pub services: Vec<DesiredService>,
+}
+
+impl FromStr for DesiredState {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        toml::from_str(s).map_err(|e| Error::InvalidDesiredState(e.to_string()))
+    }
+}
+
+/// A single service in a `DesiredState`. Fields that are left out
+/// aren't managed: a loaded service keeps its current value, and a
+/// service that is loaded to converge gets the same default as with
+/// `hab svc load`.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DesiredService {
+    #[serde(with = "util::serde::string")]
+    pub ident:    PackageIdent,
+    pub group:    Option<String>,
+    pub channel:  Option<ChannelIdent>,
+    pub topology: Option<Topology>,
+    /// Configuration for the service group, as applied by `hab
+    /// config apply`.
+    pub config:   Option<toml::value::Table>,
+}
+
+/// The configuration most recently gossiped to a service group.
+#[derive(Debug, PartialEq)]
+pub struct AppliedConfig {
+    pub incarnation: u64,
+    pub value:       toml::value::Table,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConvergeAction {
+    Load(ServiceSpec),
+    Unload(ServiceSpec),
+    Update {
+        current: ServiceSpec,
+        desired: ServiceSpec,
+    },
+    ApplyConfig {
+        ident:         PackageIdent,
+        service_group: ServiceGroup,
+        incarnation:   u64,
+        config:        toml::value::Table,
+    },
+}
+
+impl ConvergeAction {
+    pub fn ident(&self) -> &PackageIdent {
+        match self {
+            ConvergeAction::Load(spec) | ConvergeAction::Unload(spec) => &spec.ident,
+            ConvergeAction::Update { desired, .. } => &desired.ident,
+            ConvergeAction::ApplyConfig { ident, .. } => ident,
+        }
+    }
+}
+
+impl fmt::Display for ConvergeAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConvergeAction::Load(spec) => {
+                write!(f,
+                       "load {} into group {} from channel {} with topology {}",
+                       spec.ident, spec.group, spec.channel, spec.topology)
+            }
+            ConvergeAction::Unload(spec) => write!(f, "unload {}", spec.ident),
+            ConvergeAction::Update { current, desired } => {
+                let mut changes = Vec::new();
+                if current.ident != desired.ident {
+                    changes.push(format!("ident {} -> {}", current.ident, desired.ident));
+                }
+                if current.group != desired.group {
+                    changes.push(format!("group {} -> {}", current.group, desired.group));
+                }
+                if current.channel != desired.channel {
+                    changes.push(format!("channel {} -> {}", current.channel, desired.channel));
+                }
+                if current.topology != desired.topology {
+                    changes.push(format!("topology {} -> {}", current.topology, desired.topology));
+                }
+                write!(f, "update {}: {}", desired.ident, changes.join(", "))
+            }
+            ConvergeAction::ApplyConfig { service_group,
+                                          incarnation,
+                                          .. } => {
+                write!(f,
+                       "apply configuration to {} as incarnation {}",
+                       service_group, incarnation)
+            }
+        }
+    }
+}
+
+/// The actions needed to take the Supervisor from its current state
+/// to a `DesiredState`: unloads first, then loads, spec updates, and
+/// configuration, each in order of package name.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConvergencePlan {
+    pub actions: Vec<ConvergeAction>,
+}
+
+impl ConvergencePlan {
+    pub fn new(desired: &DesiredState,
+               loaded: &[ServiceSpec],
+               applied_configs: &HashMap<ServiceGroup, AppliedConfig>,
+               organization: Option<&str>)
+               -> Result<Self> {
+        // A Supervisor runs at most one service per package name, so
+        // that is what specs are matched on.
+        let mut desired_by_name = BTreeMap::new();
+        for service in &desired.services {
+            if desired_by_name.insert(service.ident.name.as_str(), service)
+                              .is_some()
+            {
+                return Err(Error::InvalidDesiredState(format!("{} is listed more \
+                                                               than once",
+                                                              service.ident.name)));
+            }
+        }
+        let loaded_by_name = loaded.iter()
+                                   .map(|spec| (spec.ident.name.as_str(), spec))
+                                   .collect::<BTreeMap<_, _>>();
+
+        let mut unloads = Vec::new();
+        for (name, spec) in &loaded_by_name {
+            if !desired_by_name.contains_key(name) {
+                unloads.push(ConvergeAction::Unload((*spec).clone()));
+            }
+        }
+
+        let mut loads = Vec::new();
+        let mut updates = Vec::new();
+        let mut configs = Vec::new();
+        for (name, service) in desired_by_name {
+            let current = loaded_by_name.get(name).copied();
+            let spec = desired_spec(service, current);
+            match current {
+                None => loads.push(ConvergeAction::Load(spec.clone())),
+                Some(current) if *current != spec => {
+                    updates.push(ConvergeAction::Update { current: current.clone(),
+                                                          desired: spec.clone(), })
+                }
+                Some(_) => {}
+            }
+
+            if let Some(ref config) = service.config {
+                let service_group = ServiceGroup::new(name, &spec.group, organization)?;
+                let applied = applied_configs.get(&service_group);
+                if applied.map_or(true, |applied| applied.value != *config) {
+                    configs.push(ConvergeAction::ApplyConfig { ident: spec.ident,
+                                                               service_group,
+                                                               incarnation:
+                                                                   applied.map_or(1, |applied| {
+                                                                              applied.incarnation
+                                                                              + 1
+                                                                          }),
+                                                               config: config.clone() });
+                }
+            }
+        }
+
+        let actions = unloads.into_iter()
+                             .chain(loads)
+                             .chain(updates)
+                             .chain(configs)
+                             .collect();
+        Ok(ConvergencePlan { actions })
+    }
+
+    pub fn is_empty(&self) -> bool { self.actions.is_empty() }
+}
+
+/// The spec `service` describes: `current` with the fields the
+/// desired service sets replaced, or a new spec if it isn't loaded.
+fn desired_spec(service: &DesiredService, current: Option<&ServiceSpec>) -> ServiceSpec {
+    let mut spec = match current {
+        // Only pin a loaded service to a different package when the
+        // desired ident actually names something else.
+        Some(current) if current.ident.satisfies(&service.ident) => current.clone(),
+        Some(current) => {
+            ServiceSpec { ident: service.ident.clone(),
+                          ..current.clone() }
+        }
+        None => ServiceSpec::new(service.ident.clone()),
+    };
+    if let Some(ref group) = service.group {
+        spec.group = group.clone();
+    }
+    if let Some(ref channel) = service.channel {
+        spec.channel = channel.clone();
+    }
+    if let Some(topology) = service.topology {
+        spec.topology = topology;
+    }
+    spec
+}
+
+/// Extract the configuration applied to each service group from the
+/// census data that the HTTP gateway serves.
+pub fn applied_configs(census_data: &str) -> Result<HashMap<ServiceGroup, AppliedConfig>> {
+    #[derive(Deserialize)]
+    struct CensusRing {
+        census_groups: HashMap<String, CensusGroup>,
+    }
+
+    #[derive(Deserialize)]
+    struct CensusGroup {
+        service_config: Option<ServiceConfig>,
+    }
+
+    #[derive(Deserialize)]
+    struct ServiceConfig {
+        incarnation: u64,
+        value:       Value,
+    }
+
+    // Nothing has been gossiped before the census is first persisted.
+    if census_data.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let census: CensusRing =
+        serde_json::from_str(census_data).map_err(Error::ServiceDeserializationError)?;
+    let mut configs = HashMap::new();
+    for (service_group, group) in census.census_groups {
+        if let Some(config) = group.service_config {
+            let value =
+                serde_json::from_value(config.value).map_err(Error::ServiceDeserializationError)?;
+            configs.insert(service_group.parse()?,
+                           AppliedConfig { incarnation: config.incarnation,
+                                           value });
+        }
+    }
+    Ok(configs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(ident: &str, group: &str, channel: &str) -> ServiceSpec {
+        ServiceSpec { group: group.to_string(),
+                      channel: ChannelIdent::from(channel),
+                      ..ServiceSpec::new(ident.parse().unwrap()) }
+    }
+
+    fn config(port: i64) -> toml::value::Table {
+        let mut config = toml::value::Table::new();
+        config.insert("port".to_string(), toml::Value::Integer(port));
+        config
+    }
+
+    #[test]
+    fn desired_state_is_parsed_from_toml() {
+        let desired: DesiredState = r#"
+            [[services]]
+            ident = "core/redis"
+            group = "cache"
+            channel = "unstable"
+            topology = "leader"
+            config = { port = 6380 }
+
+            [[services]]
+            ident = "core/nginx"
+        "#.parse()
+                                    .unwrap();
+
+        assert_eq!(desired.services.len(), 2);
+        assert_eq!(desired.services[0].group.as_deref(), Some("cache"));
+        assert_eq!(desired.services[0].topology, Some(Topology::Leader));
+        assert_eq!(desired.services[0].config, Some(config(6380)));
+        assert_eq!(desired.services[1].channel, None);
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        match "[[services]]\nident = \"core/redis\"\nchanel = \"stable\"".parse::<DesiredState>() {
+            Err(Error::InvalidDesiredState(_)) => {}
+            other => panic!("Expected InvalidDesiredState, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn plan_loads_unloads_and_updates_services() {
+        let desired: DesiredState = r#"
+            [[services]]
+            ident = "core/redis"
+            channel = "unstable"
+
+            [[services]]
+            ident = "core/nginx"
+        "#.parse()
+                                    .unwrap();
+        let redis = spec("core/redis", "default", "stable");
+        let postgres = spec("core/postgresql", "default", "stable");
+
+        let plan = ConvergencePlan::new(&desired,
+                                        &[redis.clone(), postgres.clone()],
+                                        &HashMap::new(),
+                                        None).unwrap();
+
+        assert_eq!(plan.actions,
+                   vec![ConvergeAction::Unload(postgres),
+                        ConvergeAction::Load(ServiceSpec::new("core/nginx".parse().unwrap())),
+                        ConvergeAction::Update { current: redis,
+                                                 desired: spec("core/redis", "default", "unstable"), },]);
+    }
+
+    #[test]
+    fn plan_is_empty_when_converged() {
+        let desired: DesiredState = r#"
+            [[services]]
+            ident = "core/redis"
+            group = "cache"
+            config = { port = 6380 }
+        "#.parse()
+                                    .unwrap();
+        // A loaded service that is more specific than the desired
+        // ident still satisfies it.
+        let redis = spec("core/redis/4.0.14/20190319155852", "cache", "stable");
+        let mut applied = HashMap::new();
+        applied.insert("redis.cache".parse().unwrap(),
+                       AppliedConfig { incarnation: 3,
+                                       value:       config(6380), });
+
+        let plan = ConvergencePlan::new(&desired, &[redis], &applied, None).unwrap();
+
+        assert!(plan.is_empty(), "Unexpected actions: {:?}", plan.actions);
+    }
+
+    #[test]
+    fn plan_applies_changed_config_as_the_next_incarnation() {
+        let desired: DesiredState = r#"
+            [[services]]
+            ident = "core/redis"
+            config = { port = 6380 }
+
+            [[services]]
+            ident = "core/nginx"
+            config = { port = 80 }
+        "#.parse()
+                                    .unwrap();
+        let loaded = [spec("core/redis", "default", "stable"),
+                      spec("core/nginx", "default", "stable")];
+        let mut applied = HashMap::new();
+        applied.insert("redis.default".parse().unwrap(),
+                       AppliedConfig { incarnation: 3,
+                                       value:       config(6379), });
+
+        let plan = ConvergencePlan::new(&desired, &loaded, &applied, None).unwrap();
+
+        assert_eq!(plan.actions,
+                   vec![ConvergeAction::ApplyConfig { ident:         "core/nginx".parse().unwrap(),
+                                                      service_group: "nginx.default".parse()
+                                                                                    .unwrap(),
+                                                      incarnation:   1,
+                                                      config:        config(80), },
+                        ConvergeAction::ApplyConfig { ident:         "core/redis".parse().unwrap(),
+                                                      service_group: "redis.default".parse()
+                                                                                    .unwrap(),
+                                                      incarnation:   4,
+                                                      config:        config(6380), },]);
+    }
+
+    #[test]
+    fn duplicate_services_are_rejected() {
+        let desired: DesiredState = r#"
+            [[services]]
+            ident = "core/redis"
+
+            [[services]]
+            ident = "other/redis"
+        "#.parse()
+                                    .unwrap();
+
+        match ConvergencePlan::new(&desired, &[], &HashMap::new(), None) {
+            Err(Error::InvalidDesiredState(_)) => {}
+            other => panic!("Expected InvalidDesiredState, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn applied_configs_are_read_from_census_data() {
+        let census_data = r#"{
+            "census_groups": {
+                "redis.default": {
+                    "service_config": { "incarnation": 2, "value": { "port": 6380 } }
+                },
+                "nginx.default": { "service_config": null }
+            }
+        }"#;
+
+        let configs = applied_configs(census_data).unwrap();
+
+        assert_eq!(configs.len(), 1);
+        assert_eq!(configs[&"redis.default".parse().unwrap()],
+                   AppliedConfig { incarnation: 2,
+                                   value:       config(6380), });
+        assert!(applied_configs("").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,115 @@
+//! Whether the Supervisor has finished starting up, as the HTTP
+//! gateway's `/ready` endpoint reports it. The gateway answering at all
+//! only says the Supervisor is running; being ready says that the
+//! services it was meant to start have each had their chance to.
+use crate::manager::service::{DesiredState,
+                              ServiceLifecycle};
+use habitat_core::service::ServiceGroup;
+use serde::{ser::SerializeStruct,
+            Serialize,
+            Serializer};
+use std::collections::HashSet;
+
+/// Kept up to date as the state of each service is recorded (see
+/// `GatewayStateWriteGuard::set_lifecycles`), rather than worked out
+/// from every service whenever it's asked for.
+#[derive(Debug, Default)]
+pub struct Readiness {
+    /// Whether the spec files found when the Supervisor started have
+    /// all been acted on
+    specs_reconciled: bool,
+    /// Services meant to be up that have neither come up, nor failed
+    /// to, since they were loaded
+    not_ready:        HashSet<ServiceGroup>,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool { self.specs_reconciled && self.not_ready.is_empty() }
+
+    pub fn mark_specs_reconciled(&mut self) { self.specs_reconciled = true; }
+
+    /// Record the state a service is in. It's only waited on while it's
+    /// meant to be up and is still `Loaded`; once it's come up, or
+    /// failed on its first attempt and is being restarted, it's had
+    /// its chance.
+    pub fn observe(&mut self,
+                   service_group: &ServiceGroup,
+                   state: ServiceLifecycle,
+                   desired_state: DesiredState) {
+        if state == ServiceLifecycle::Loaded && desired_state == DesiredState::Up {
+            self.not_ready.insert(service_group.clone());
+        } else {
+            self.not_ready.remove(service_group);
+        }
+    }
+
+    pub fn forget(&mut self, service_group: &ServiceGroup) { self.not_ready.remove(service_group); }
+}
+
+impl Serialize for Readiness {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let mut not_ready = self.not_ready
+                                .iter()
+                                .map(|service_group| service_group.as_ref())
+                                .collect::<Vec<&str>>();
+        not_ready.sort_unstable();
+        let mut strukt = serializer.serialize_struct("readiness", 3)?;
+        strukt.serialize_field("ready", &self.is_ready())?;
+        strukt.serialize_field("specs_reconciled", &self.specs_reconciled)?;
+        strukt.serialize_field("not_ready", &not_ready)?;
+        strukt.end()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn service_group(name: &str) -> ServiceGroup {
+        ServiceGroup::new(name, "default", None).unwrap()
+    }
+
+    #[test]
+    fn not_ready_until_specs_are_reconciled() {
+        let mut readiness = Readiness::default();
+        assert!(!readiness.is_ready());
+        readiness.mark_specs_reconciled();
+        assert!(readiness.is_ready());
+    }
+
+    #[test]
+    fn services_are_waited_on_until_they_come_up_or_fail() {
+        let mut readiness = Readiness::default();
+        readiness.mark_specs_reconciled();
+        let redis = service_group("redis");
+        let nginx = service_group("nginx");
+
+        readiness.observe(&redis, ServiceLifecycle::Loaded, DesiredState::Up);
+        readiness.observe(&nginx, ServiceLifecycle::Loaded, DesiredState::Up);
+        assert_eq!(serde_json::to_value(&readiness).unwrap(),
+                   json!({ "ready": false,
+                           "specs_reconciled": true,
+                           "not_ready": ["nginx.default", "redis.default"] }));
+
+        readiness.observe(&redis, ServiceLifecycle::Up, DesiredState::Up);
+        readiness.observe(&nginx, ServiceLifecycle::Restarting, DesiredState::Up);
+        assert!(readiness.is_ready());
+    }
+
+    #[test]
+    fn stopped_and_unloaded_services_are_not_waited_on() {
+        let mut readiness = Readiness::default();
+        readiness.mark_specs_reconciled();
+        let redis = service_group("redis");
+        let nginx = service_group("nginx");
+
+        readiness.observe(&redis, ServiceLifecycle::Loaded, DesiredState::Down);
+        readiness.observe(&nginx, ServiceLifecycle::Loaded, DesiredState::Up);
+        assert!(!readiness.is_ready());
+        readiness.forget(&nginx);
+        assert!(readiness.is_ready());
+    }
+}
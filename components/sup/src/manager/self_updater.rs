@@ -1,7 +1,10 @@
 //! Encapsulates logic required for updating the Habitat Supervisor
 //! itself.
 
-use crate::util;
+use crate::{manager::task_supervisor::{self,
+                                       RestartPolicy,
+                                       ShutdownStage},
+            util};
 use habitat_common::command::package::install::InstallSource;
 use habitat_core::{package::{PackageIdent,
                              PackageInstall},
@@ -9,11 +12,12 @@ use habitat_core::{package::{PackageIdent,
 use log::{debug,
           trace,
           warn};
+use parking_lot::Mutex;
 use rand::Rng;
 use std::{borrow::Borrow,
+          sync::Arc,
           time::Duration};
-use tokio::{self,
-            sync::oneshot::{self,
+use tokio::{sync::oneshot::{self,
                             error::TryRecvError,
                             Receiver,
                             Sender},
@@ -50,6 +54,7 @@ pub struct SelfUpdater {
 }
 
 /// The subset of data from `SelfUpdater` needed to spawn the updater task.
+#[derive(Clone)]
 struct Runner {
     current:        PackageIdent,
     update_url:     String,
@@ -88,11 +93,16 @@ impl SelfUpdater {
     /// Spawn a new Supervisor updater task.
     fn init(runner: Runner) -> Receiver<PackageInstall> {
         let (tx, rx) = oneshot::channel();
-        tokio::spawn(Self::run(tx, runner));
+        // The sender is shared between restarts of the task.
+        let tx = Arc::new(Mutex::new(Some(tx)));
+        task_supervisor::spawn("self-updater",
+                               ShutdownStage::Updaters,
+                               RestartPolicy::Always,
+                               move || Self::run(Arc::clone(&tx), runner.clone()));
         rx
     }
 
-    async fn run(tx: Sender<PackageInstall>, runner: Runner) {
+    async fn run(tx: Arc<Mutex<Option<Sender<PackageInstall>>>>, runner: Runner) {
         // SUP_PKG_IDENT will always parse as a valid PackageIdent,
         // and thus a valid InstallSource
         let install_source: InstallSource = SUP_PKG_IDENT.parse().unwrap();
@@ -112,7 +122,9 @@ impl SelfUpdater {
                     if &current < package.ident() {
                         debug!("Self updater installing newer Supervisor, {}",
                                package.ident());
-                        tx.send(package).expect("Main thread has gone away!");
+                        if let Some(tx) = tx.lock().take() {
+                            tx.send(package).expect("Main thread has gone away!");
+                        }
                         break;
                     } else {
                         debug!("Supervisor package found is not newer than ours");
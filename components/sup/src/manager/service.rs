@@ -12,8 +12,11 @@ mod context;
 mod health;
 mod hook_runner;
 mod hooks;
+mod lifecycle;
+mod peer_file;
 #[cfg(windows)]
 mod pipe_hook_client;
+mod restart_record;
 pub mod spec;
 mod supervisor;
 mod terminator;
@@ -22,14 +25,24 @@ use self::{context::RenderContext,
            hook_runner::HookRunner,
            hooks::{HookCompileTable,
                    HookTable},
+           peer_file::PeerFile,
+           restart_record::{RestartRecord,
+                            RestartRecordFile},
            supervisor::{PidUpdate,
                         Supervisor}};
 pub use self::{health::{HealthCheckBundle,
+                        HealthCheckHistory,
                         HealthCheckHookStatus,
-                        HealthCheckResult},
+                        HealthCheckOutcome,
+                        HealthCheckRecord,
+                        HealthCheckResult,
+                        HealthCheckSplay},
                hooks::{HealthCheckHook,
                        ProcessOutput,
                        StandardStreams},
+               lifecycle::{ServiceEvent,
+                           ServiceEventKind,
+                           ServiceLifecycle},
                spec::{DesiredState,
                       ServiceSpec}};
 use crate::{census::{CensusGroup,
@@ -40,12 +53,18 @@ use crate::{census::{CensusGroup,
                     Result},
             manager::{event,
                       sync::GatewayState,
+                      task_supervisor::{self,
+                                        RestartPolicy,
+                                        ShutdownStage},
                       FsCfg,
                       ServicePidSource,
                       ShutdownConfig,
                       Sys}};
 use futures::future::{self,
-                      AbortHandle};
+                      AbortHandle,
+                      BoxFuture,
+                      FutureExt,
+                      Shared};
 use habitat_butterfly::rumor::service::Service as ServiceRumor;
 #[cfg(windows)]
 use habitat_common::templating::package::DEFAULT_USER;
@@ -71,7 +90,8 @@ use habitat_core::{crypto::Blake2bHash,
                    package::{metadata::Bind,
                              PackageIdent,
                              PackageInstall},
-                   service::{ServiceBind,
+                   service::{HealthCheckInterval,
+                             ServiceBind,
                              ServiceGroup},
                    ChannelIdent};
 use habitat_launcher_client::LauncherCli;
@@ -84,8 +104,10 @@ use log::{debug,
           trace};
 use parking_lot::RwLock;
 use prometheus::{register_histogram_vec,
+                 register_int_counter_vec,
                  HistogramTimer,
-                 HistogramVec};
+                 HistogramVec,
+                 IntCounterVec};
 use serde::{ser::{Error as _,
                   SerializeStruct},
             Deserialize,
@@ -95,14 +117,19 @@ use std::{self,
           collections::HashSet,
           fmt,
           fs,
+          iter,
           ops::Deref,
           path::{Path,
                  PathBuf},
           result,
           sync::{Arc,
-                 Mutex},
+                 Mutex,
+                 MutexGuard,
+                 Weak},
           time::{Duration,
+                 Instant,
                  SystemTime}};
+use tokio::sync::mpsc;
 
 use super::ServiceRestartConfig;
 use lazy_static::lazy_static;
@@ -117,6 +144,11 @@ lazy_static! {
         register_histogram_vec!("hab_sup_hook_duration_seconds",
                                 "The time it takes for a hook to run",
                                 &["hook"]).unwrap();
+    static ref RENDERS_AVOIDED: IntCounterVec =
+        register_int_counter_vec!("hab_sup_census_renders_avoided_total",
+                                  "The number of census updates that didn't require a service's \
+                                   templates to be re-rendered",
+                                  &["service_group"]).unwrap();
 }
 
 /// When evaluating whether a particular service group can satisfy a
@@ -251,6 +283,61 @@ impl Serialize for LastProcessState {
     }
 }
 
+/// Where a service is in its cycle of restarts after init / run hook
+/// failures, as the HTTP gateway shows it. Services that aren't failing
+/// have every field zeroed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RestartStatus {
+    /// The number of times in a row the service has failed and been restarted
+    pub consecutive_failures: u64,
+    /// The duration waited, or being waited, before the latest restart
+    pub backoff_period:       Duration,
+    /// The time at which the service will next be restarted, expressed as seconds since epoch,
+    /// or 0 if no restart is due
+    pub next_attempt_at:      u64,
+    /// Whether the service has stayed up for the cooldown period since its last restart, which
+    /// clears its failures
+    pub cooldown_reset:       bool,
+}
+
+/// Where one of a service's effective settings came from, for those
+/// its spec can override.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingSource {
+    Spec,
+    /// The Supervisor's flags, including their defaults
+    Supervisor,
+}
+
+impl SettingSource {
+    fn of<T>(spec_value: &Option<T>) -> Self {
+        if spec_value.is_some() {
+            SettingSource::Spec
+        } else {
+            SettingSource::Supervisor
+        }
+    }
+}
+
+/// Where each of a service's effective restart settings (see
+/// `ServiceRestartConfig::for_service`) came from, as the HTTP gateway
+/// shows them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RestartConfigSources {
+    pub min_backoff_period: SettingSource,
+    pub max_backoff_period: SettingSource,
+    pub cooldown_period:    SettingSource,
+}
+
+impl RestartConfigSources {
+    fn of(spec: &ServiceSpec) -> Self {
+        RestartConfigSources { min_backoff_period: SettingSource::of(&spec.min_backoff_period),
+                               max_backoff_period: SettingSource::of(&spec.max_backoff_period),
+                               cooldown_period:    SettingSource::of(&spec.cooldown_period), }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ServiceRunState {
     pub restart_count:      u64,
@@ -259,20 +346,52 @@ pub struct ServiceRunState {
     current_pid:            Option<Pid>,
     restart_state:          RestartState,
     restart_backoff:        Backoff,
+    /// Unlike `restart_count`, this is also reset once the cooldown period passes
+    consecutive_failures:   u64,
+    cooldown_reset:         bool,
     last_updated_at:        SystemTime,
+    /// Where the restart bookkeeping is kept, so it outlives the
+    /// Supervisor
+    restart_record:         Option<RestartRecordFile>,
 }
 
 impl ServiceRunState {
     pub fn new(restart_config: &ServiceRestartConfig) -> ServiceRunState {
-        ServiceRunState { restart_count:      0,
-                          restart_config:     restart_config.clone(),
-                          last_process_state: None,
-                          current_pid:        None,
-                          restart_state:      RestartState::None,
-                          restart_backoff:    Backoff::new(restart_config.min_backoff_period,
-                                                           restart_config.max_backoff_period,
-                                                           3f64),
-                          last_updated_at:    SystemTime::now(), }
+        ServiceRunState { restart_count:        0,
+                          restart_config:       restart_config.clone(),
+                          last_process_state:   None,
+                          current_pid:          None,
+                          restart_state:        RestartState::None,
+                          restart_backoff:      Backoff::new(restart_config.min_backoff_period,
+                                                             restart_config.max_backoff_period,
+                                                             3f64),
+                          consecutive_failures: 0,
+                          cooldown_reset:       false,
+                          last_updated_at:      SystemTime::now(),
+                          restart_record:       None, }
+    }
+
+    /// Keep the restart bookkeeping in `record_file`, carrying on from
+    /// whatever a previous Supervisor left there if the cooldown
+    /// period hasn't passed since.
+    fn keep_restart_record(&mut self, record_file: RestartRecordFile) {
+        if let Some((record, elapsed)) =
+            record_file.load(self.restart_config.cooldown_period, SystemTime::now())
+        {
+            self.consecutive_failures = record.consecutive_failures;
+            self.restart_backoff
+                .resume(Duration::from_millis(record.backoff_period_ms), elapsed);
+            // As far as the cooldown is concerned, the service is
+            // coming back from the restart that was recorded.
+            self.restart_state = RestartState::Restarted;
+        }
+        self.restart_record = Some(record_file);
+    }
+
+    fn forget_restart_record(&self) {
+        if let Some(record_file) = &self.restart_record {
+            record_file.remove();
+        }
     }
 
     pub fn mark_for_restart(&mut self,
@@ -296,14 +415,79 @@ impl ServiceRunState {
                                                           termination_reason: reason, });
         // Immediate restarts wipe out the restart out
         self.restart_count = 0;
+        self.consecutive_failures = 0;
+        self.cooldown_reset = false;
         self.restart_backoff.reset();
+        self.forget_restart_record();
         self.last_updated_at = timestamp;
     }
 
+    /// Switch to new restart settings, such as when the service's spec
+    /// overrides different ones. A restart already being waited on
+    /// isn't put off or brought forward; those after it are affected.
+    pub fn reconfigure_restarts(&mut self, restart_config: ServiceRestartConfig) {
+        self.restart_backoff
+            .set_bounds(restart_config.min_backoff_period,
+                        restart_config.max_backoff_period);
+        self.restart_config = restart_config;
+        self.last_updated_at = SystemTime::now();
+    }
+
+    /// Called once a restarted service has stayed up for the cooldown period.
     pub fn reset_backoff(&mut self) {
         self.restart_backoff.reset();
+        self.consecutive_failures = 0;
+        self.cooldown_reset = true;
+        self.forget_restart_record();
         self.last_updated_at = SystemTime::now();
     }
+
+    /// Whether the service is down, or about to be taken down, to be
+    /// started again.
+    pub fn is_restarting(&self) -> bool {
+        matches!(self.restart_state,
+                 RestartState::NeedsRestart
+                 | RestartState::NeedsImmediateRestart
+                 | RestartState::Restarting
+                 | RestartState::RestartingImmediately)
+    }
+
+    /// Record that the service is being restarted after a failure.
+    fn record_failure(&mut self) {
+        self.restart_count += 1;
+        self.consecutive_failures += 1;
+        self.cooldown_reset = false;
+        if let Some(record_file) = &self.restart_record {
+            let last_failure_at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
+                                                   .unwrap_or_default();
+            let backoff_period_ms = self.restart_backoff
+                                        .current_backoff_duration()
+                                        .unwrap_or_default()
+                                        .as_millis() as u64;
+            record_file.save(&RestartRecord { consecutive_failures: self.consecutive_failures,
+                                              last_failure_at: last_failure_at.as_secs(),
+                                              backoff_period_ms });
+        }
+    }
+
+    /// The time at which the service will next be restarted, expressed as seconds since epoch,
+    /// if it's down waiting to be.
+    fn next_restart_at(&self) -> Option<u64> {
+        self.restart_backoff
+            .duration_until_next_attempt_start()
+            .and_then(|duration| SystemTime::now().checked_add(duration))
+            .and_then(|timestamp| timestamp.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs())
+    }
+
+    pub fn restart_status(&self) -> RestartStatus {
+        RestartStatus { consecutive_failures: self.consecutive_failures,
+                        backoff_period:       self.restart_backoff
+                                                  .current_backoff_duration()
+                                                  .unwrap_or_default(),
+                        next_attempt_at:      self.next_restart_at().unwrap_or_default(),
+                        cooldown_reset:       self.cooldown_reset, }
+    }
 }
 
 #[derive(Debug)]
@@ -316,8 +500,10 @@ impl PersistentServiceWrapper {
     pub fn new(service: Service,
                restart_config: &ServiceRestartConfig)
                -> PersistentServiceWrapper {
-        PersistentServiceWrapper { run_state: ServiceRunState::new(restart_config),
-                                   inner:     Some(service), }
+        let mut run_state = ServiceRunState::new(&restart_config.for_service(&service.spec));
+        run_state.keep_restart_record(RestartRecordFile::new(&service.pkg.svc_data_path));
+        PersistentServiceWrapper { run_state,
+                                   inner: Some(service) }
     }
 
     /// Takes ownership of a service from another wrapper
@@ -342,6 +528,11 @@ impl PersistentServiceWrapper {
 
     pub fn service_mut(&mut self) -> Option<&mut Service> { self.inner.as_mut() }
 
+    pub fn service_and_run_state_mut(&mut self) -> Option<(&mut Service, &mut ServiceRunState)> {
+        let run_state = &mut self.run_state;
+        self.inner.as_mut().map(|service| (service, run_state))
+    }
+
     /// Returns the last time the run state or the service's process state
     /// has changed. This is used to determine when to write the changed state to
     /// the gateway.
@@ -389,7 +580,7 @@ impl PersistentServiceWrapper {
                                                    .restart_backoff
                                                    .record_attempt_start()
                                                    .unwrap_or_default();
-                        self.run_state.restart_count += 1;
+                        self.run_state.record_failure();
                         if restart_duration == Duration::from_secs(0) {
                             outputln!(preamble service.service_group, "Stopping service, will restart immediately");
                         } else {
@@ -446,6 +637,69 @@ impl PersistentServiceWrapper {
     }
 }
 
+/// A health check run on demand; see `Service::check_health_now`.
+pub(crate) type OnDemandHealthCheck = Shared<BoxFuture<'static, HealthCheckOutcome>>;
+
+/// The on-demand health check of a service that's underway, if there
+/// is one.
+#[derive(Default)]
+struct UnderwayHealthCheck(Mutex<Option<OnDemandHealthCheck>>);
+
+impl UnderwayHealthCheck {
+    fn lock(&self) -> MutexGuard<'_, Option<OnDemandHealthCheck>> {
+        self.0
+            .lock()
+            .expect("Could not unlock on-demand health check")
+    }
+}
+
+impl fmt::Debug for UnderwayHealthCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnderwayHealthCheck")
+         .field("underway", &self.lock().is_some())
+         .finish()
+    }
+}
+
+/// Everything needed to keep the result of one of a service's health
+/// checks wherever it's served from, without holding on to the
+/// service itself.
+#[derive(Clone)]
+struct HealthCheckRecorder {
+    service_group:          ServiceGroup,
+    service_health_result:  Arc<Mutex<HealthCheckResult>>,
+    gateway_state:          Arc<GatewayState>,
+    service_event_metadata: event::ServiceMetadata,
+}
+
+impl HealthCheckRecorder {
+    /// * Cache the health check result for the service
+    /// * Set the health check result for the service in the gateway state, and add it to the
+    ///   service's health check history there
+    /// * Send a `HealthCheckEvent` over the event stream
+    fn record(&self,
+              status: HealthCheckHookStatus,
+              result: HealthCheckResult,
+              interval: HealthCheckInterval) {
+        debug!("Caching HealthCheckResult = '{}' for '{}'",
+               result, self.service_group);
+        *self.service_health_result
+             .lock()
+             .expect("Could not unlock service_health_result") = result;
+
+        let record = HealthCheckRecord::new(result, &status);
+        let mut gsw = self.gateway_state.lock_gsw();
+        gsw.set_health_of(self.service_group.clone(), result);
+        gsw.record_health_check(self.service_group.clone(), record);
+        drop(gsw);
+
+        event::health_check(self.service_event_metadata.clone(),
+                            result,
+                            status,
+                            interval);
+    }
+}
+
 #[derive(Debug)]
 pub struct Service {
     spec:                    ServiceSpec,
@@ -475,6 +729,9 @@ pub struct Service {
     // hook, we need to wrap some Arc<Mutex<_>> protection around it
     // :(
     health_check_result:  Arc<Mutex<HealthCheckResult>>,
+    /// The health check run on demand (see `check_health_now`) that's
+    /// underway, if there is one.
+    on_demand_check:      Arc<UnderwayHealthCheck>,
     last_election_status: ElectionStatus,
     /// The binds that the current service package declares, both
     /// required and optional. We don't differentiate because this is
@@ -495,6 +752,23 @@ pub struct Service {
     /// census.
     unsatisfied_binds:    HashSet<ServiceBind>,
     hooks:                HookTable,
+    /// The census-driven file named by the spec's `peer_file`, if
+    /// any.
+    peer_file:            Option<PeerFile>,
+    /// How the service's health checks are spread out, going by its
+    /// spec's `health_check_splay`, or the Supervisor's if it hasn't
+    /// one of its own.
+    health_check_splay:   Option<HealthCheckSplay>,
+    /// The generations of the service's own census group and of the
+    /// groups it binds to, as of the last tick. See
+    /// `CensusGroup::generation`.
+    census_generations:   Option<Vec<u64>>,
+    /// When the service's configuration data (`cfg`) last changed,
+    /// or when the service was loaded if it hasn't since.
+    cfg_changed_at:       SystemTime,
+    /// When the service's configuration files were last rendered to
+    /// something new, if they have been since it was loaded.
+    cfg_rendered_at:      Option<SystemTime>,
     manager_fs_cfg:       Arc<FsCfg>,
     supervisor:           Arc<Mutex<Supervisor>>,
 
@@ -525,6 +799,12 @@ impl Service {
 
     pub(crate) fn spec(&self) -> ServiceSpec { self.spec.clone() }
 
+    pub(crate) fn spec_file(&self) -> &Path { &self.spec_file }
+
+    pub(crate) fn cfg_changed_at(&self) -> SystemTime { self.cfg_changed_at }
+
+    pub(crate) fn cfg_rendered_at(&self) -> Option<SystemTime> { self.cfg_rendered_at }
+
     pub(crate) fn set_spec(&mut self, spec: ServiceSpec) {
         trace!("Setting spec for {}: {:?}", self.spec.ident, spec);
         self.spec = spec
@@ -539,7 +819,8 @@ impl Service {
                           census_ring: Arc<RwLock<CensusRing>>,
                           gateway_state: Arc<GatewayState>,
                           pid_source: ServicePidSource,
-                          feature_flags: FeatureFlag)
+                          feature_flags: FeatureFlag,
+                          default_health_check_splay: Option<u32>)
                           -> Result<Service> {
         spec.validate(package)?;
         let all_pkg_binds = package.all_binds()?;
@@ -552,16 +833,32 @@ impl Service {
         let config_root = Self::config_root(&pkg, spec.config_from.as_ref());
         let hooks_root = Self::hooks_root(&pkg, spec.config_from.as_ref());
         let cfg = Cfg::new(&pkg, spec.config_from.as_ref())?;
+        let peer_file = spec.peer_file
+                            .as_deref()
+                            .map(|f| PeerFile::new(f, spec.peer_file_template.as_deref()))
+                            .transpose()?;
+        let health_check_splay =
+            spec.health_check_splay
+                .or(default_health_check_splay)
+                .map(|jitter| {
+                    HealthCheckSplay::new(&service_group, spec.health_check_interval, jitter)
+                });
         let mut service =
             Service { spec,
                       sys,
                       cfg,
                       config_renderer: CfgRenderer::new(config_root)?,
                       health_check_result: Arc::new(Mutex::new(HealthCheckResult::Unknown)),
+                      on_demand_check: Arc::default(),
                       hooks: HookTable::load(&pkg.name,
                                              hooks_root,
                                              svc_hooks_path(service_group.service()),
                                              feature_flags),
+                      peer_file,
+                      health_check_splay,
+                      census_generations: None,
+                      cfg_changed_at: SystemTime::now(),
+                      cfg_rendered_at: None,
                       last_election_status: ElectionStatus::None,
                       user_config_updated: false,
                       initialization_state:
@@ -639,7 +936,8 @@ impl Service {
                      census_ring: Arc<RwLock<CensusRing>>,
                      gateway_state: Arc<GatewayState>,
                      pid_source: ServicePidSource,
-                     feature_flags: FeatureFlag)
+                     feature_flags: FeatureFlag,
+                     default_health_check_splay: Option<u32>)
                      -> Result<Service> {
         // The package for a spec should already be installed.
         let fs_root_path = Path::new(&*FS_ROOT_PATH);
@@ -652,7 +950,8 @@ impl Service {
                            census_ring,
                            gateway_state,
                            pid_source,
-                           feature_flags).await
+                           feature_flags,
+                           default_health_check_splay).await
     }
 
     /// Create the service path for this package.
@@ -689,43 +988,94 @@ impl Service {
     /// appropriate actions upon receiving the results of a health check. The actions taken are:
     ///
     /// * Cache the health check result for this service
-    /// * Set the health check result for this service in the gateway state
+    /// * Set the health check result for this service in the gateway state, and add it to the
+    ///   service's health check history there
     /// * Send a `HealthCheckEvent` over the event stream
     fn start_health_checks(&mut self) {
         debug!("Starting health checks for {}", self.pkg.ident);
-        let mut rx = health::check_repeatedly(Arc::clone(&self.supervisor),
-                                              self.hooks.health_check.clone(),
-                                              self.spec.health_check_interval,
-                                              self.service_group.clone(),
-                                              self.pkg.clone(),
-                                              self.spec.svc_encrypted_password.clone());
-
+        let supervisor = Arc::clone(&self.supervisor);
+        let hook = self.hooks.health_check.clone();
+        let nominal_interval = self.spec.health_check_interval;
+        let splay = self.health_check_splay;
+        let package = self.pkg.clone();
+        let password = self.spec.svc_encrypted_password.clone();
         let service_group = self.service_group.clone();
-        let service_event_metadata = self.to_service_metadata();
-        let service_health_result = Arc::clone(&self.health_check_result);
-        let gateway_state = Arc::clone(&self.gateway_state);
+        let recorder = self.health_check_recorder();
         // Initialize the gateway_state for this service to Unknown.
-        gateway_state.lock_gsw()
-                     .set_health_of(service_group.clone(), HealthCheckResult::Unknown);
-        let f = async move {
-            while let Some(HealthCheckBundle { status,
-                                               result,
-                                               interval, }) = rx.recv().await
-            {
-                debug!("Caching HealthCheckResult = '{}' for '{}'",
-                       result, service_group);
-                *service_health_result.lock()
-                                      .expect("Could not unlock service_health_result") = result;
-
-                gateway_state.lock_gsw()
-                             .set_health_of(service_group.clone(), result);
-
-                event::health_check(service_event_metadata.clone(), result, status, interval);
-            }
+        self.gateway_state
+            .lock_gsw()
+            .set_health_of(service_group.clone(), HealthCheckResult::Unknown);
+        let f = move || {
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            let checks = health::check_repeatedly(Arc::clone(&supervisor),
+                                                  hook.clone(),
+                                                  nominal_interval,
+                                                  splay,
+                                                  service_group.clone(),
+                                                  package.clone(),
+                                                  password.clone(),
+                                                  tx);
+            let recorder = recorder.clone();
+            let results = async move {
+                while let Some(HealthCheckBundle { status,
+                                                   result,
+                                                   interval, }) = rx.recv().await
+                {
+                    recorder.record(status, result, interval);
+                }
+            };
+            future::join(checks, results).map(|_| ())
         };
-        let (f, handle) = future::abortable(f);
+        let handle = task_supervisor::spawn(format!("health-check/{}", self.service_group),
+                                            ShutdownStage::HealthChecks,
+                                            RestartPolicy::Always,
+                                            f);
         self.health_check_handle = Some(handle);
-        tokio::spawn(f);
+    }
+
+    /// Run the service's health check now, out of band of its regular
+    /// schedule, and record the result just as a scheduled check's
+    /// would be. A check asked for while another is underway doesn't
+    /// run the hook again, but shares the result of the one underway.
+    pub(crate) fn check_health_now(&self) -> OnDemandHealthCheck {
+        let mut underway = self.on_demand_check.lock();
+        if let Some(check) = underway.as_ref() {
+            debug!("Joining the health check of {} already underway",
+                   self.service_group);
+            return check.clone();
+        }
+
+        debug!("Running an on-demand health check of {}",
+               self.service_group);
+        let checking = health::check(Arc::clone(&self.supervisor),
+                                     self.hooks.health_check.clone(),
+                                     self.service_group.clone(),
+                                     self.pkg.clone(),
+                                     self.spec.svc_encrypted_password.clone());
+        let recorder = self.health_check_recorder();
+        let interval = self.spec.health_check_interval;
+        // Only a weak reference is kept, so a check that's never
+        // finished doesn't keep itself alive.
+        let underway_ref: Weak<UnderwayHealthCheck> = Arc::downgrade(&self.on_demand_check);
+        let check = async move {
+                        let (status, result) = checking.await;
+                        let outcome = HealthCheckOutcome::new(result, &status);
+                        recorder.record(status, result, interval);
+                        if let Some(underway) = underway_ref.upgrade() {
+                            *underway.lock() = None;
+                        }
+                        outcome
+                    }.boxed()
+                     .shared();
+        *underway = Some(check.clone());
+        check
+    }
+
+    fn health_check_recorder(&self) -> HealthCheckRecorder {
+        HealthCheckRecorder { service_group:          self.service_group.clone(),
+                              service_health_result:  Arc::clone(&self.health_check_result),
+                              gateway_state:          Arc::clone(&self.gateway_state),
+                              service_event_metadata: self.to_service_metadata(), }
     }
 
     /// Stop the endless future that performs health checks for the
@@ -734,6 +1084,7 @@ impl Service {
         if let Some(h) = self.health_check_handle.take() {
             debug!("Stopping health checks for {}", self.pkg.ident);
             h.abort();
+            outputln!(preamble self.service_group, "Health checking has been stopped");
         }
     }
 
@@ -816,6 +1167,9 @@ impl Service {
     /// Performs updates and executes hooks.
     ///
     /// Returns `true` if the service was marked to be restarted or reconfigured.
+    ///
+    /// # Locking (see locking.md)
+    /// * `GatewayState::inner` (write)
     fn tick(&mut self,
             run_state: &mut ServiceRunState,
             census_ring: &CensusRing,
@@ -839,16 +1193,28 @@ impl Service {
         // Binds may become unsatisfied as a service is running (e.g.,
         // service members disappear, etc.) This can affect the data
         // we pass to templates, so we must account for it here.
-        if census_ring.changed() {
+        let census_changed = self.census_changed(census_ring);
+        if census_changed {
             self.validate_binds(census_ring);
         }
 
         // TODO (DM): As a temporary fix, we return this `template_data_changed` boolean which does
         // not account for changes in the census ring. This is needed because when we restart a
         // service, we do not correctly produce the initial gossip message.
-        let (template_data_changed, template_update) = self.update_templates(census_ring);
-        if self.update_service_files(census_ring) {
+        let (template_data_changed, template_update) =
+            self.update_templates(census_ring, census_changed);
+        let service_files_updated = self.update_service_files(census_ring);
+        let peer_file_updated = self.update_peer_file(census_ring, census_changed);
+        if service_files_updated || peer_file_updated {
             self.file_updated();
+            self.gateway_state
+                .lock_gsw()
+                .publish(ServiceEventKind::FileUpdated, &self.service_group);
+        }
+        if template_update.config_changed {
+            self.gateway_state
+                .lock_gsw()
+                .publish(ServiceEventKind::ConfigUpdated, &self.service_group);
         }
 
         match self.spec.topology {
@@ -1062,14 +1428,41 @@ impl Service {
         }
     }
 
+    /// Returns `true` if the census groups this service renders
+    /// templates from (its own group and the groups it binds to) have
+    /// changed since the last time this was called.
+    fn census_changed(&mut self, census_ring: &CensusRing) -> bool {
+        let generations = iter::once(&self.service_group).chain(self.spec
+                                                                    .binds
+                                                                    .iter()
+                                                                    .map(|b| b.service_group()))
+                                                         .map(|sg| {
+                                                             census_ring.census_group_for(sg)
+                                                          .map_or(0, CensusGroup::generation)
+                                                         })
+                                                         .collect::<Vec<_>>();
+        if self.census_generations.as_ref() == Some(&generations) {
+            false
+        } else {
+            self.census_generations = Some(generations);
+            true
+        }
+    }
+
     /// Compares the current state of the service to the current state of the census ring and the
     /// user-config, and re-renders all templatable content to disk.
-    fn update_templates(&mut self, census_ring: &CensusRing) -> (bool, TemplateUpdate) {
+    fn update_templates(&mut self,
+                        census_ring: &CensusRing,
+                        census_changed: bool)
+                        -> (bool, TemplateUpdate) {
         let census_group =
             census_ring.census_group_for(&self.service_group)
                        .expect("Service update failed; unable to find own service group");
         let cfg_updated_from_rumors = self.update_gossip(census_group);
         let template_data_changed = cfg_updated_from_rumors || self.user_config_updated;
+        if template_data_changed {
+            self.cfg_changed_at = SystemTime::now();
+        }
 
         if self.user_config_updated {
             if let Err(e) = self.cfg.reload_user() {
@@ -1079,12 +1472,21 @@ impl Service {
             self.user_config_updated = false;
         }
 
-        let template_update = if template_data_changed || census_ring.changed() {
+        let template_update = if template_data_changed || census_changed {
             let ctx = self.render_context(census_ring);
-            TemplateUpdate::new(self.compile_hooks(&ctx),
-                                self.compile_configuration(&ctx),
+            let hooks = self.compile_hooks(&ctx);
+            let config_changed = self.compile_configuration(&ctx);
+            if config_changed {
+                self.cfg_rendered_at = Some(SystemTime::now());
+            }
+            TemplateUpdate::new(hooks,
+                                config_changed,
                                 self.hooks.reconfigure.is_some() || self.hooks.reload.is_some())
         } else {
+            if census_ring.changed() {
+                RENDERS_AVOIDED.with_label_values(&[self.service_group.as_ref()])
+                               .inc();
+            }
             TemplateUpdate::default()
         };
         (template_data_changed, template_update)
@@ -1400,6 +1802,9 @@ impl Service {
         if let Some(census_group) = census_ring.census_group_for(&self.service_group) {
             self.write_service_files(census_group, CensusGroup::service_files);
         }
+        // Always render the initial peer file, whether or not the
+        // census has changed since it was last looked at.
+        self.update_peer_file(census_ring, true);
     }
 
     /// Write service files from gossip data to disk under
@@ -1440,6 +1845,53 @@ impl Service {
         updated
     }
 
+    /// Render the service's peer file, if it has one, from the alive
+    /// members of its service group, writing it to disk under
+    /// [`svc_files_path()`](../../fs/fn.svc_files_path.html) if it
+    /// changed.
+    ///
+    /// To keep flapping members from causing a rewrite on every
+    /// census change, the file is rewritten at most once per
+    /// `PeerFileMinInterval`; changes arriving sooner are picked up
+    /// once the interval passes.
+    ///
+    /// Returns `true` if the file was written.
+    fn update_peer_file(&mut self, census_ring: &CensusRing, census_changed: bool) -> bool {
+        let peer_file = match self.peer_file.as_mut() {
+            Some(peer_file) => peer_file,
+            None => return false,
+        };
+        if census_changed {
+            peer_file.mark_stale();
+        }
+        let now = Instant::now();
+        if !peer_file.is_due(now) {
+            return false;
+        }
+        let census_group = match census_ring.census_group_for(&self.service_group) {
+            Some(census_group) => census_group,
+            None => return false,
+        };
+        let contents = match peer_file.render(census_group.members()) {
+            Ok(contents) => contents,
+            Err(e) => {
+                outputln!(preamble self.service_group, "Failed to render peer file {}, {}",
+                          peer_file.filename(), e);
+                return false;
+            }
+        };
+        let filename = peer_file.filename().to_string();
+        if self.write_cache_file(self.pkg.svc_files_path.join(&filename), contents.as_bytes()) {
+            outputln!(preamble self.service_group, "Peer file updated, {}", filename);
+            if let Some(peer_file) = self.peer_file.as_mut() {
+                peer_file.written(now);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
     /// Helper for constructing a new render context for the service.
     fn render_context<'a>(&'a self, census: &'a CensusRing) -> RenderContext<'a> {
         // Unsatisfied binds are filtered out; you only get bind
@@ -1578,6 +2030,27 @@ impl<'a> ServiceProxy<'a> {
                        service_run_state,
                        config_rendering }
     }
+
+    pub fn service_group(&self) -> &ServiceGroup { &self.service.service_group }
+
+    pub fn desired_state(&self) -> DesiredState { self.service.spec.desired_state }
+
+    /// What's observed of the service now: whether it's up, or down,
+    /// and if so, whether it's to be restarted.
+    pub fn lifecycle(&self) -> ServiceLifecycle {
+        let status = self.service
+                         .supervisor
+                         .lock()
+                         .expect("Couldn't lock supervisor")
+                         .status();
+        if status == ProcessState::Up {
+            ServiceLifecycle::Up
+        } else if self.service_run_state.is_restarting() {
+            ServiceLifecycle::Restarting
+        } else {
+            ServiceLifecycle::Down
+        }
+    }
 }
 
 impl<'a> Serialize for ServiceProxy<'a> {
@@ -1585,9 +2058,9 @@ impl<'a> Serialize for ServiceProxy<'a> {
         where S: Serializer
     {
         let num_fields: usize = if self.config_rendering == ConfigRendering::Full {
-            31
+            34
         } else {
-            30
+            33
         };
 
         let s = &self.service;
@@ -1620,17 +2093,11 @@ impl<'a> Serialize for ServiceProxy<'a> {
                                 .deref())?;
         strukt.serialize_field("last_process_state",
                                &self.service_run_state.last_process_state)?;
-        strukt.serialize_field("next_restart_at",
-                               &self.service_run_state
-                                    .restart_backoff
-                                    .duration_until_next_attempt_start()
-                                    .and_then(|duration| SystemTime::now().checked_add(duration))
-                                    .and_then(|timestamp| {
-                                        timestamp.duration_since(SystemTime::UNIX_EPOCH).ok()
-                                    })
-                                    .map(|duration| duration.as_secs()))?;
+        strukt.serialize_field("next_restart_at", &self.service_run_state.next_restart_at())?;
+        strukt.serialize_field("restart", &self.service_run_state.restart_status())?;
         strukt.serialize_field("restart_count", &self.service_run_state.restart_count)?;
         strukt.serialize_field("restart_config", &self.service_run_state.restart_config)?;
+        strukt.serialize_field("restart_config_sources", &RestartConfigSources::of(&s.spec))?;
         strukt.serialize_field("service_group", &s.service_group)?;
         strukt.serialize_field("spec_file", &s.spec_file)?;
         // Deprecated field; use spec_identifier instead
@@ -1638,6 +2105,7 @@ impl<'a> Serialize for ServiceProxy<'a> {
         strukt.serialize_field("spec_identifier", &s.spec.ident.to_string())?;
         strukt.serialize_field("svc_encrypted_password", &s.spec.svc_encrypted_password)?;
         strukt.serialize_field("health_check_interval", &s.spec.health_check_interval)?;
+        strukt.serialize_field("health_check_splay", &s.health_check_splay)?;
         strukt.serialize_field("sys", &s.sys)?;
         strukt.serialize_field("topology", &s.spec.topology)?;
         strukt.serialize_field("update_strategy", &s.spec.update_strategy)?;
@@ -1666,7 +2134,7 @@ mod tests {
         let sys = Sys::new(false,
                            GossipListenAddr::default(),
                            listen_ctl_addr,
-                           HttpListenAddr::default(),
+                           Some(HttpListenAddr::default()),
                            IpAddr::V4(Ipv4Addr::LOCALHOST));
 
         let ident = if cfg!(target_os = "linux") {
@@ -1692,16 +2160,18 @@ mod tests {
         let census_ring = Arc::new(RwLock::new(CensusRing::new(asys.member_id.clone())));
         let gs = Arc::default();
         PersistentServiceWrapper::new(Service::with_package(asys,
-                              &install,
-                              spec,
-                              afs,
-                              Some("haha"),
-                              census_ring,
-                              gs,
-                              ServicePidSource::Launcher,
-                              FeatureFlag::empty()).await
-                                                   .expect("I wanted a service to load, but it \
-                                                            didn't"), &ServiceRestartConfig::default())
+                                                            &install,
+                                                            spec,
+                                                            afs,
+                                                            Some("haha"),
+                                                            census_ring,
+                                                            gs,
+                                                            ServicePidSource::Launcher,
+                                                            FeatureFlag::empty(),
+                                                            None).await
+                                                                 .expect("I wanted a service to \
+                                                                          load, but it didn't"),
+                                      &ServiceRestartConfig::default())
     }
 
     // We only run this test case for x86 platforms as it is not worth the effort
@@ -1733,4 +2203,83 @@ mod tests {
                                                                    JSON but failed");
         assert_valid(&json_without_config, "http_gateway_services_schema.json");
     }
+
+    #[test]
+    fn restart_status_follows_failures_and_cooldown() {
+        let restart_config = ServiceRestartConfig::new(Duration::from_secs(10),
+                                                       Duration::from_secs(60),
+                                                       Duration::from_secs(300));
+        let mut run_state = ServiceRunState::new(&restart_config);
+        let zeroed = RestartStatus { consecutive_failures: 0,
+                                     backoff_period:       Duration::ZERO,
+                                     next_attempt_at:      0,
+                                     cooldown_reset:       false, };
+        assert_eq!(run_state.restart_status(), zeroed);
+
+        for failures in 1..=2 {
+            run_state.mark_for_restart(Some(1234),
+                                       ProcessTerminationReason::RunHookFailed,
+                                       SystemTime::now());
+            run_state.restart_backoff.record_attempt_start();
+            run_state.record_failure();
+            let status = run_state.restart_status();
+            assert_eq!(status.consecutive_failures, failures);
+            assert!(status.backoff_period >= Duration::from_secs(10));
+            assert!(status.backoff_period <= Duration::from_secs(60));
+            assert!(status.next_attempt_at > 0);
+            assert!(!status.cooldown_reset);
+        }
+
+        // Coming back up ends the wait, but not the run of failures
+        run_state.restart_backoff.record_attempt_end();
+        let status = run_state.restart_status();
+        assert_eq!(status.consecutive_failures, 2);
+        assert_eq!(status.next_attempt_at, 0);
+
+        run_state.reset_backoff();
+        assert_eq!(run_state.restart_status(),
+                   RestartStatus { cooldown_reset: true,
+                                   ..zeroed.clone() });
+        assert_eq!(run_state.restart_count, 2);
+
+        run_state.mark_for_immediate_restart(Some(1234),
+                                             ProcessTerminationReason::AppConfigUpdated,
+                                             SystemTime::now());
+        assert_eq!(run_state.restart_status(), zeroed);
+    }
+
+    #[test]
+    fn restart_record_carries_failures_over_to_a_new_run_state() {
+        let restart_config = ServiceRestartConfig::new(Duration::from_secs(10),
+                                                       Duration::from_secs(60),
+                                                       Duration::from_secs(300));
+        let data_dir = tempfile::TempDir::new().unwrap();
+        let mut run_state = ServiceRunState::new(&restart_config);
+        run_state.keep_restart_record(RestartRecordFile::new(data_dir.path()));
+        for _ in 0..2 {
+            run_state.mark_for_restart(Some(1234),
+                                       ProcessTerminationReason::RunHookFailed,
+                                       SystemTime::now());
+            run_state.restart_backoff.record_attempt_start();
+            run_state.record_failure();
+        }
+        let backoff_period = run_state.restart_status().backoff_period;
+
+        // As a new Supervisor would find it
+        let mut resumed = ServiceRunState::new(&restart_config);
+        resumed.keep_restart_record(RestartRecordFile::new(data_dir.path()));
+        let status = resumed.restart_status();
+        assert_eq!(status.consecutive_failures, 2);
+        assert_eq!(status.backoff_period.as_millis(),
+                   backoff_period.as_millis());
+        assert_eq!(status.next_attempt_at, 0);
+        assert_eq!(resumed.restart_state, RestartState::Restarted);
+
+        // Once the failures are cleared, there's nothing to carry over
+        resumed.reset_backoff();
+        let mut fresh = ServiceRunState::new(&restart_config);
+        fresh.keep_restart_record(RestartRecordFile::new(data_dir.path()));
+        assert_eq!(fresh.restart_status().consecutive_failures, 0);
+        assert_eq!(fresh.restart_state, RestartState::None);
+    }
 }
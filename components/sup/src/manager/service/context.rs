@@ -127,8 +127,8 @@ struct SystemInfo<'a> {
     hostname:          Cow<'a, String>,
     gossip_ip:         Cow<'a, IpAddr>,
     gossip_port:       Cow<'a, u16>,
-    http_gateway_ip:   Cow<'a, IpAddr>,
-    http_gateway_port: Cow<'a, u16>,
+    http_gateway_ip:   Cow<'a, Option<IpAddr>>,
+    http_gateway_port: Cow<'a, Option<u16>>,
     ctl_gateway_ip:    Cow<'a, IpAddr>,
     ctl_gateway_port:  Cow<'a, u16>,
     permanent:         Cow<'a, bool>,
@@ -494,8 +494,8 @@ two = 2
                          hostname:          Cow::Owned("MY_HOSTNAME".into()),
                          gossip_ip:         Cow::Owned(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0))),
                          gossip_port:       Cow::Owned(1234),
-                         http_gateway_ip:   Cow::Owned(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0))),
-                         http_gateway_port: Cow::Owned(5678),
+                         http_gateway_ip:   Cow::Owned(Some(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)))),
+                         http_gateway_port: Cow::Owned(Some(5678)),
                          ctl_gateway_ip:    Cow::Owned(IpAddr::V4(Ipv4Addr::LOCALHOST)),
                          ctl_gateway_port:  Cow::Owned(5679),
                          permanent:         Cow::Owned(false), };
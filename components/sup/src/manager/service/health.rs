@@ -8,23 +8,42 @@ use habitat_common::{outputln,
                      templating::package::Pkg};
 use habitat_core::service::{HealthCheckInterval,
                             ServiceGroup};
+use habitat_sup_protocol::types as proto;
 use log::{debug,
           error,
           trace};
 use rand::Rng;
-use serde::Serialize;
+use serde::{Serialize,
+            Serializer};
 use std::{cmp,
+          collections::VecDeque,
           convert::TryFrom,
           fmt,
           sync::{Arc,
                  Mutex},
-          time::Duration};
-use tokio::{sync::mpsc::{self,
-                         UnboundedReceiver},
+          time::{Duration,
+                 SystemTime}};
+use tokio::{sync::mpsc::UnboundedSender,
             time};
 
 static LOGKEY: &str = "HK";
 
+habitat_core::env_config_int!(
+    /// How many of a service's most recent health check results are
+    /// kept, to be served from the HTTP gateway's health history.
+    HealthCheckHistorySize,
+    usize,
+    HAB_SUP_HEALTH_CHECK_HISTORY_SIZE,
+    50);
+
+/// However a service's health checks are spread out, they're never
+/// run closer together than this.
+const MIN_SPLAYED_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The most of a health check hook's stdout or stderr that's kept in
+/// its history; anything after this is cut off.
+const MAX_RECORDED_OUTPUT_BYTES: usize = 4 * 1024;
+
 /// The possible service health result from the status of running the health check.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
 pub enum HealthCheckResult {
@@ -34,6 +53,17 @@ pub enum HealthCheckResult {
     Unknown,
 }
 
+impl From<HealthCheckResult> for proto::HealthCheckResult {
+    fn from(result: HealthCheckResult) -> Self {
+        match result {
+            HealthCheckResult::Ok => proto::HealthCheckResult::Ok,
+            HealthCheckResult::Warning => proto::HealthCheckResult::Warning,
+            HealthCheckResult::Critical => proto::HealthCheckResult::Critical,
+            HealthCheckResult::Unknown => proto::HealthCheckResult::Unknown,
+        }
+    }
+}
+
 /// Convert health check hook exit codes into `HealthCheckResult`.
 impl TryFrom<i32> for HealthCheckResult {
     type Error = Error;
@@ -99,13 +129,168 @@ pub struct HealthCheckBundle {
     pub interval: HealthCheckInterval,
 }
 
+/// What a health check run on demand, rather than on the service's
+/// schedule, found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HealthCheckOutcome {
+    pub result:    HealthCheckResult,
+    /// Only known if the hook ran to completion
+    pub exit_code: Option<i32>,
+    /// Only known if the hook could be started
+    pub duration:  Option<Duration>,
+}
+
+impl HealthCheckOutcome {
+    pub fn new(result: HealthCheckResult, status: &HealthCheckHookStatus) -> Self {
+        let exit_code = match status {
+            HealthCheckHookStatus::Ran(output, _) => output.exit_status().code(),
+            _ => None,
+        };
+        HealthCheckOutcome { result,
+                             exit_code,
+                             duration: status.maybe_duration() }
+    }
+}
+
+/// How a service's health checks are spread out, so that those of
+/// services on the same interval don't all run at once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct HealthCheckSplay {
+    /// How long the first check is put off. This is derived from the
+    /// service group, so it's the same every time the service starts,
+    /// but differs from one service to the next.
+    pub first_check_offset: Duration,
+    /// The most each check after the first is moved either way from
+    /// its interval
+    pub jitter:             Duration,
+}
+
+impl HealthCheckSplay {
+    pub fn new(service_group: &ServiceGroup,
+               interval: HealthCheckInterval,
+               jitter_secs: u32)
+               -> Self {
+        let interval_millis = Duration::from(interval).as_millis() as u64;
+        let first_check_offset = if interval_millis == 0 {
+            Duration::default()
+        } else {
+            Duration::from_millis(fnv1a(service_group.as_ref().as_bytes()) % interval_millis)
+        };
+        HealthCheckSplay { first_check_offset,
+                           jitter: Duration::from_secs(jitter_secs.into()) }
+    }
+
+    /// `interval`, moved by a random amount of up to `jitter` either
+    /// way, but no shorter than `MIN_SPLAYED_INTERVAL`.
+    fn jittered(&self, interval: HealthCheckInterval) -> HealthCheckInterval {
+        let jitter_millis = self.jitter.as_millis() as i64;
+        let delta = rand::thread_rng().gen_range(-jitter_millis..=jitter_millis);
+        let interval_millis = Duration::from(interval).as_millis() as i64;
+        let jittered = Duration::from_millis((interval_millis + delta).max(0) as u64);
+        cmp::max(jittered, MIN_SPLAYED_INTERVAL).into()
+    }
+}
+
+/// The 64-bit FNV-1a hash of `bytes`. Unlike the standard library's
+/// hashers, this is guaranteed to be the same from one build of the
+/// Supervisor to the next.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+                    (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+                })
+}
+
+/// A health check, as it's kept in a service's history.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct HealthCheckRecord {
+    /// When the check finished, in milliseconds since the epoch
+    pub timestamp: u64,
+    pub status:    HealthCheckResult,
+    /// Only known if the hook ran to completion
+    pub exit_code: Option<i32>,
+    pub stdout:    String,
+    pub stderr:    String,
+}
+
+impl HealthCheckRecord {
+    pub fn new(result: HealthCheckResult, status: &HealthCheckHookStatus) -> Self {
+        let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
+                                         .map(|since_epoch| since_epoch.as_millis() as u64)
+                                         .unwrap_or_default();
+        let (exit_code, stdout, stderr) = match status {
+            HealthCheckHookStatus::Ran(output, _) => {
+                (output.exit_status().code(),
+                 truncated(output.stdout().unwrap_or_default()),
+                 truncated(output.stderr().unwrap_or_default()))
+            }
+            _ => (None, String::new(), String::new()),
+        };
+        HealthCheckRecord { timestamp,
+                            status: result,
+                            exit_code,
+                            stdout,
+                            stderr }
+    }
+}
+
+/// Cut `output` down to `MAX_RECORDED_OUTPUT_BYTES`, without splitting
+/// a character.
+fn truncated(output: &str) -> String {
+    let mut end = output.len().min(MAX_RECORDED_OUTPUT_BYTES);
+    while !output.is_char_boundary(end) {
+        end -= 1;
+    }
+    output[..end].to_string()
+}
+
+/// A service's most recent health checks, oldest first. Once it's
+/// full, each new check pushes out the oldest.
+#[derive(Debug)]
+pub struct HealthCheckHistory {
+    records:  VecDeque<HealthCheckRecord>,
+    capacity: usize,
+}
+
+impl HealthCheckHistory {
+    pub fn new(capacity: usize) -> Self {
+        HealthCheckHistory { records: VecDeque::with_capacity(capacity),
+                             capacity }
+    }
+
+    pub fn push(&mut self, record: HealthCheckRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &HealthCheckRecord> { self.records.iter() }
+}
+
+/// Sized by `HAB_SUP_HEALTH_CHECK_HISTORY_SIZE`.
+impl Default for HealthCheckHistory {
+    fn default() -> Self { Self::new(HealthCheckHistorySize::configured_value().into()) }
+}
+
+/// Served as a list of the records, oldest first.
+impl Serialize for HealthCheckHistory {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
 /// Run the health check hook and get the hook status and result.
-async fn check(supervisor: Arc<Mutex<Supervisor>>,
-               hook: Option<Arc<HealthCheckHook>>,
-               service_group: ServiceGroup,
-               package: Pkg,
-               password: Option<String>)
-               -> (HealthCheckHookStatus, HealthCheckResult) {
+pub async fn check(supervisor: Arc<Mutex<Supervisor>>,
+                   hook: Option<Arc<HealthCheckHook>>,
+                   service_group: ServiceGroup,
+                   package: Pkg,
+                   password: Option<String>)
+                   -> (HealthCheckHookStatus, HealthCheckResult) {
     let status = if let Some(hook) = hook {
         let result = hook_runner::HookRunner::new(hook,
                                                   service_group.clone(),
@@ -166,77 +351,147 @@ async fn check(supervisor: Arc<Mutex<Supervisor>>,
     (status, result)
 }
 
-/// Start a task to repeatedly check the service health, followed by an appropriate delay, forever.
-/// The function returns the receiving end of a channel that acts as a stream of
-/// `HealthCheckBundle`s. When this receiving end is dropped or closed health checking will be
-/// stopped.
-pub fn check_repeatedly(supervisor: Arc<Mutex<Supervisor>>,
-                        hook: Option<Arc<HealthCheckHook>>,
-                        nominal_interval: HealthCheckInterval,
-                        service_group: ServiceGroup,
-                        package: Pkg,
-                        password: Option<String>)
-                        -> UnboundedReceiver<HealthCheckBundle> {
-    // TODO (CM): If we wanted to keep track of how many times
-    // a health check has failed in the past X executions, or
-    // do similar historical tracking, here's where we'd do
-    // it.
-
-    let service_group_clone = service_group.clone();
-    let (tx, rx) = mpsc::unbounded_channel();
-
-    tokio::spawn(async move {
-        let mut first_ok_health_check_recorded = false;
-        loop {
-            let (status, result) = check(Arc::clone(&supervisor),
-                                         hook.as_ref().map(Arc::clone),
-                                         service_group.clone(),
-                                         package.clone(),
-                                         password.clone()).await;
-
-            let interval = if result == HealthCheckResult::Ok {
-                if !first_ok_health_check_recorded {
-                    // If this was the first successful check, splay future health check runs across
-                    // the nominal interval
-                    let splay = rand::thread_rng().gen_range(0..u64::from(nominal_interval));
-                    let splay = Duration::from_secs(splay);
-                    debug!("Following `{}`'s first `ok` health-check, delaying a randomly chosen \
-                            {}s to introduce health-check splay",
-                           service_group,
-                           splay.as_secs());
-                    first_ok_health_check_recorded = true;
-                    splay.into()
-                } else {
-                    // routine health check
-                    nominal_interval
-                }
+/// Repeatedly check the service health, followed by an appropriate delay, forever. Each result is
+/// sent as a `HealthCheckBundle` on `tx`. When the receiving end of `tx` is dropped or closed
+/// health checking will be stopped. With a `splay`, the first check is put off, and every delay
+/// after a check is jittered, as it describes.
+#[allow(clippy::too_many_arguments)]
+pub async fn check_repeatedly(supervisor: Arc<Mutex<Supervisor>>,
+                              hook: Option<Arc<HealthCheckHook>>,
+                              nominal_interval: HealthCheckInterval,
+                              splay: Option<HealthCheckSplay>,
+                              service_group: ServiceGroup,
+                              package: Pkg,
+                              password: Option<String>,
+                              tx: UnboundedSender<HealthCheckBundle>) {
+    if let Some(splay) = splay {
+        debug!("Delaying `{}`'s first health-check by {}ms to splay it",
+               service_group,
+               splay.first_check_offset.as_millis());
+        time::sleep(splay.first_check_offset).await;
+    }
+
+    let mut first_ok_health_check_recorded = false;
+    loop {
+        let (status, result) = check(Arc::clone(&supervisor),
+                                     hook.as_ref().map(Arc::clone),
+                                     service_group.clone(),
+                                     package.clone(),
+                                     password.clone()).await;
+
+        let interval = if result == HealthCheckResult::Ok {
+            // A splayed service's checks were already spread out by
+            // its first one being put off.
+            if !first_ok_health_check_recorded && splay.is_none() {
+                // If this was the first successful check, splay future health check runs across
+                // the nominal interval
+                let splay = rand::thread_rng().gen_range(0..u64::from(nominal_interval));
+                let splay = Duration::from_secs(splay);
+                debug!("Following `{}`'s first `ok` health-check, delaying a randomly chosen {}s \
+                        to introduce health-check splay",
+                       service_group,
+                       splay.as_secs());
+                first_ok_health_check_recorded = true;
+                splay.into()
             } else {
-                // TODO (DM): Implment exponential backoff
-                // https://github.com/habitat-sh/habitat/issues/7265
-                // Until exponential backoff is implmented never wait longer than the default
-                // interval following a failing health check. If the configured interval is less
-                // than the default interval use it instead.
-                cmp::min(nominal_interval, HealthCheckInterval::default())
-            };
-
-            // This can only fail if the receiving end is closed or dropped indicating to stop
-            // executing health checks.
-            if tx.send(HealthCheckBundle { status,
-                                           result,
-                                           interval })
-                 .is_err()
-            {
-                break;
+                // routine health check
+                nominal_interval
             }
+        } else {
+            // TODO (DM): Implment exponential backoff
+            // https://github.com/habitat-sh/habitat/issues/7265
+            // Until exponential backoff is implmented never wait longer than the default
+            // interval following a failing health check. If the configured interval is less
+            // than the default interval use it instead.
+            cmp::min(nominal_interval, HealthCheckInterval::default())
+        };
+        let interval = match splay {
+            Some(splay) => splay.jittered(interval),
+            None => interval,
+        };
 
-            trace!("`{}` health-check was `{}` next check in {}",
-                   service_group,
-                   result,
-                   interval);
-            time::sleep(interval.into()).await;
+        // This can only fail if the receiving end is closed or dropped indicating to stop
+        // executing health checks.
+        if tx.send(HealthCheckBundle { status,
+                                       result,
+                                       interval })
+             .is_err()
+        {
+            break;
         }
-        outputln!(preamble service_group_clone, "Health checking has been stopped");
-    });
 
-    rx
+        trace!("`{}` health-check was `{}` next check in {}",
+               service_group,
+               result,
+               interval);
+        time::sleep(interval.into()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(status: HealthCheckResult) -> HealthCheckRecord {
+        HealthCheckRecord::new(status, &HealthCheckHookStatus::NoHook)
+    }
+
+    #[test]
+    fn history_keeps_only_the_most_recent_checks() {
+        let mut history = HealthCheckHistory::new(2);
+        history.push(record(HealthCheckResult::Ok));
+        history.push(record(HealthCheckResult::Critical));
+        history.push(record(HealthCheckResult::Warning));
+        assert_eq!(history.iter().map(|r| r.status).collect::<Vec<_>>(),
+                   vec![HealthCheckResult::Critical, HealthCheckResult::Warning]);
+    }
+
+    #[test]
+    fn history_of_no_size_keeps_nothing() {
+        let mut history = HealthCheckHistory::new(0);
+        history.push(record(HealthCheckResult::Ok));
+        assert_eq!(history.iter().count(), 0);
+    }
+
+    #[test]
+    fn first_checks_are_offset_within_the_interval_by_service_group() {
+        let interval = HealthCheckInterval::from(30);
+        let splay = |group: &str| {
+            HealthCheckSplay::new(&ServiceGroup::new("redis", group, None).unwrap(),
+                                  interval,
+                                  0)
+        };
+        assert_eq!(splay("default"), splay("default"));
+        assert_ne!(splay("default").first_check_offset,
+                   splay("canary").first_check_offset);
+        for group in &["default", "canary", "production"] {
+            assert!(splay(group).first_check_offset < Duration::from(interval));
+        }
+        assert_eq!(splay("default").jitter, Duration::default());
+    }
+
+    #[test]
+    fn jittered_intervals_stay_within_the_jitter_and_above_the_floor() {
+        let service_group = ServiceGroup::new("redis", "default", None).unwrap();
+        let splay = HealthCheckSplay::new(&service_group, HealthCheckInterval::from(10), 3);
+        for _ in 0..100 {
+            let interval = Duration::from(splay.jittered(HealthCheckInterval::from(10)));
+            assert!(interval >= Duration::from_secs(7) && interval <= Duration::from_secs(13),
+                    "{:?}",
+                    interval);
+        }
+        for _ in 0..100 {
+            let interval = Duration::from(splay.jittered(HealthCheckInterval::from(2)));
+            assert!(interval >= MIN_SPLAYED_INTERVAL, "{:?}", interval);
+        }
+    }
+
+    #[test]
+    fn recorded_output_is_truncated_on_a_character_boundary() {
+        assert_eq!(truncated("healthy"), "healthy");
+        let output = format!("a{}", "é".repeat(MAX_RECORDED_OUTPUT_BYTES));
+        let recorded = truncated(&output);
+        assert_eq!(recorded.len(), MAX_RECORDED_OUTPUT_BYTES - 1);
+        assert!(output.starts_with(&recorded));
+    }
 }
@@ -50,6 +50,10 @@ impl ProcessOutput {
 
     pub fn exit_status(&self) -> ExitStatus { self.exit_status }
 
+    pub fn stdout(&self) -> Option<&str> { self.standard_streams.stdout.as_deref() }
+
+    pub fn stderr(&self) -> Option<&str> { self.standard_streams.stderr.as_deref() }
+
     pub fn standard_streams(self) -> StandardStreams { self.standard_streams }
 }
 
@@ -783,8 +787,8 @@ mod tests {
                                  hostname: "hostname".to_string(),
                                  gossip_ip: "0.0.0.0".to_string(),
                                  gossip_port: 7777,
-                                 http_gateway_ip: "0.0.0.0".to_string(),
-                                 http_gateway_port: 9631,
+                                 http_gateway_ip: Some("0.0.0.0".to_string()),
+                                 http_gateway_port: Some(9631),
                                  ..Default::default() };
 
         let sg_one = service_group.clone(); // ServiceGroup::new("shield", "one", None).unwrap();
@@ -841,7 +845,7 @@ mod tests {
         let sys = Sys::new(true,
                            GossipListenAddr::default(),
                            ListenCtlAddr::default(),
-                           HttpListenAddr::default(),
+                           Some(HttpListenAddr::default()),
                            IpAddr::V4(Ipv4Addr::LOCALHOST));
         let cfg = Cfg::new(&pkg, Some(&concrete_path.as_path().to_path_buf()))
             .expect("Could not create config");
@@ -933,7 +937,7 @@ mod tests {
         let sys = Sys::new(true,
                            GossipListenAddr::default(),
                            ListenCtlAddr::default(),
-                           HttpListenAddr::default(),
+                           Some(HttpListenAddr::default()),
                            IpAddr::V4(Ipv4Addr::LOCALHOST));
         let cfg = Cfg::new(&pkg, Some(&concrete_path.as_path().to_path_buf()))
             .expect("Could not create config");
@@ -981,7 +985,7 @@ mod tests {
         let sys = Sys::new(true,
                            GossipListenAddr::default(),
                            ListenCtlAddr::default(),
-                           HttpListenAddr::default(),
+                           Some(HttpListenAddr::default()),
                            IpAddr::V4(Ipv4Addr::LOCALHOST));
         let cfg = Cfg::new(&pkg, Some(&concrete_path.as_path().to_path_buf()))
             .expect("Could not create config");
@@ -0,0 +1,158 @@
+//! The events served from the HTTP gateway's `/events` stream, as
+//! services are loaded, come up, go down, are restarted and unloaded,
+//! change health, and have configuration or files applied.
+use super::HealthCheckResult;
+use habitat_core::service::ServiceGroup;
+use serde::Serialize;
+use std::{fmt,
+          time::SystemTime};
+
+/// Where a loaded service is in its life, as far as subscribers to the
+/// event stream are concerned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceLifecycle {
+    /// Loaded, but not yet started
+    Loaded,
+    Up,
+    /// Stopped after having been started, without being restarted
+    Down,
+    /// Down, waiting to be started again
+    Restarting,
+    Unloaded,
+}
+
+impl ServiceLifecycle {
+    /// The state a service has moved to, given the state it was last
+    /// known to be in, if any, and what's `observed` of it now (which
+    /// is only ever `Up`, `Down` or `Restarting`). A service that's
+    /// never come up is still only `Loaded`, rather than `Down`.
+    pub fn next(previous: Option<ServiceLifecycle>, observed: ServiceLifecycle) -> Self {
+        match (previous, observed) {
+            (None, ServiceLifecycle::Down)
+            | (Some(ServiceLifecycle::Loaded), ServiceLifecycle::Down) => ServiceLifecycle::Loaded,
+            (_, observed) => observed,
+        }
+    }
+}
+
+/// What happened to a service.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceEventKind {
+    /// Sent for every loaded service when a subscriber connects,
+    /// rather than for anything that happened to it
+    Snapshot,
+    Loaded,
+    Up,
+    Down,
+    Restarting,
+    Unloaded,
+    HealthCheck,
+    ConfigUpdated,
+    FileUpdated,
+}
+
+impl From<ServiceLifecycle> for ServiceEventKind {
+    fn from(state: ServiceLifecycle) -> Self {
+        match state {
+            ServiceLifecycle::Loaded => ServiceEventKind::Loaded,
+            ServiceLifecycle::Up => ServiceEventKind::Up,
+            ServiceLifecycle::Down => ServiceEventKind::Down,
+            ServiceLifecycle::Restarting => ServiceEventKind::Restarting,
+            ServiceLifecycle::Unloaded => ServiceEventKind::Unloaded,
+        }
+    }
+}
+
+/// The name the event goes by in the stream; the same as it's
+/// serialized as.
+impl fmt::Display for ServiceEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match *self {
+            ServiceEventKind::Snapshot => "snapshot",
+            ServiceEventKind::Loaded => "loaded",
+            ServiceEventKind::Up => "up",
+            ServiceEventKind::Down => "down",
+            ServiceEventKind::Restarting => "restarting",
+            ServiceEventKind::Unloaded => "unloaded",
+            ServiceEventKind::HealthCheck => "health_check",
+            ServiceEventKind::ConfigUpdated => "config_updated",
+            ServiceEventKind::FileUpdated => "file_updated",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ServiceEvent {
+    pub event:         ServiceEventKind,
+    pub service_group: ServiceGroup,
+    /// The state the service is in once the event has happened
+    pub state:         ServiceLifecycle,
+    /// The service's latest health check result, if it's had one
+    pub health:        Option<HealthCheckResult>,
+    /// When the event happened, in milliseconds since the epoch
+    pub timestamp:     u64,
+}
+
+impl ServiceEvent {
+    pub fn new(event: ServiceEventKind,
+               service_group: ServiceGroup,
+               state: ServiceLifecycle,
+               health: Option<HealthCheckResult>)
+               -> Self {
+        let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
+                                         .map(|since_epoch| since_epoch.as_millis() as u64)
+                                         .unwrap_or_default();
+        ServiceEvent { event,
+                       service_group,
+                       state,
+                       health,
+                       timestamp }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn services_that_never_came_up_are_only_loaded() {
+        assert_eq!(ServiceLifecycle::next(None, ServiceLifecycle::Down),
+                   ServiceLifecycle::Loaded);
+        assert_eq!(ServiceLifecycle::next(Some(ServiceLifecycle::Loaded), ServiceLifecycle::Down),
+                   ServiceLifecycle::Loaded);
+        assert_eq!(ServiceLifecycle::next(None, ServiceLifecycle::Up),
+                   ServiceLifecycle::Up);
+    }
+
+    #[test]
+    fn services_that_came_up_go_down_or_restart() {
+        assert_eq!(ServiceLifecycle::next(Some(ServiceLifecycle::Up), ServiceLifecycle::Down),
+                   ServiceLifecycle::Down);
+        assert_eq!(ServiceLifecycle::next(Some(ServiceLifecycle::Up),
+                                          ServiceLifecycle::Restarting),
+                   ServiceLifecycle::Restarting);
+        assert_eq!(ServiceLifecycle::next(Some(ServiceLifecycle::Restarting),
+                                          ServiceLifecycle::Up),
+                   ServiceLifecycle::Up);
+    }
+
+    #[test]
+    fn events_are_named_as_they_are_serialized() {
+        for kind in &[ServiceEventKind::Snapshot,
+                      ServiceEventKind::Loaded,
+                      ServiceEventKind::Up,
+                      ServiceEventKind::Down,
+                      ServiceEventKind::Restarting,
+                      ServiceEventKind::Unloaded,
+                      ServiceEventKind::HealthCheck,
+                      ServiceEventKind::ConfigUpdated,
+                      ServiceEventKind::FileUpdated]
+        {
+            assert_eq!(serde_json::to_value(kind).unwrap(),
+                       serde_json::Value::String(kind.to_string()));
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Materializes a hosts-file-like list of a service group's alive
+//! members into the service's files directory, for services that
+//! can't consume the census any other way.
+
+use crate::{census::CensusMember,
+            error::{Error,
+                    Result}};
+use habitat_common::templating::TemplateRenderer;
+use serde::Serialize;
+use std::{path::Path,
+          time::{Duration,
+                 Instant}};
+
+habitat_core::env_config_duration!(
+    /// The minimum amount of time between rewrites of a service's
+    /// peer file. Changes to the census that arrive sooner than this
+    /// are held back and applied together once the interval has
+    /// passed, so flapping members don't run the `file-updated` hook
+    /// over and over.
+    PeerFileMinInterval,
+    HAB_PEER_FILE_MIN_INTERVAL_SECS => from_secs,
+    Duration::from_secs(10));
+
+/// Used when a spec names a `peer_file` but gives no
+/// `peer_file_template`: one line per alive member, with its address
+/// followed by its member ID.
+pub const DEFAULT_PEER_FILE_TEMPLATE: &str =
+    "{{#each members}}{{address}} {{member_id}}\n{{/each}}";
+
+const TEMPLATE_NAME: &str = "peer_file";
+
+#[derive(Serialize)]
+struct PeerFileContext<'a> {
+    members: Vec<PeerFileMember<'a>>,
+}
+
+#[derive(Serialize)]
+struct PeerFileMember<'a> {
+    member_id: &'a str,
+    address:   &'a str,
+    hostname:  &'a str,
+    leader:    bool,
+}
+
+impl<'a> From<&'a CensusMember> for PeerFileMember<'a> {
+    fn from(member: &'a CensusMember) -> Self {
+        PeerFileMember { member_id: &member.member_id,
+                         address:   &member.sys.ip,
+                         hostname:  &member.sys.hostname,
+                         leader:    member.leader, }
+    }
+}
+
+#[derive(Debug)]
+pub struct PeerFile {
+    filename:     String,
+    renderer:     TemplateRenderer,
+    min_interval: Duration,
+    last_written: Option<Instant>,
+    /// Set when the census has changed since the file was last
+    /// rendered.
+    stale:        bool,
+}
+
+impl PeerFile {
+    pub fn new(filename: &str, template: Option<&str>) -> Result<Self> {
+        // The file lives directly in the service's files directory.
+        if Path::new(filename).file_name().and_then(|f| f.to_str()) != Some(filename) {
+            return Err(Error::InvalidPeerFile(filename.to_string()));
+        }
+        let mut renderer = TemplateRenderer::new();
+        renderer.register_template_string(TEMPLATE_NAME,
+                                          template.unwrap_or(DEFAULT_PEER_FILE_TEMPLATE))
+                .map_err(|e| Error::InvalidPeerFileTemplate(e.to_string()))?;
+        Ok(PeerFile { filename: filename.to_string(),
+                      renderer,
+                      min_interval: PeerFileMinInterval::configured_value().into(),
+                      last_written: None,
+                      stale: true })
+    }
+
+    pub fn filename(&self) -> &str { &self.filename }
+
+    /// Note that the census has changed, and the file may need to be
+    /// rewritten.
+    pub fn mark_stale(&mut self) { self.stale = true; }
+
+    /// Returns `true` if the file should be re-rendered now: the
+    /// census has changed since the last render, and the minimum
+    /// interval since the last rewrite has passed.
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.stale
+        && self.last_written
+               .map_or(true, |t| now.duration_since(t) >= self.min_interval)
+    }
+
+    /// Render the file from the alive members in `members`, ordered
+    /// by member ID so that the output is stable.
+    pub fn render<'a, I>(&mut self, members: I) -> Result<String>
+        where I: IntoIterator<Item = &'a CensusMember>
+    {
+        let mut members = members.into_iter()
+                                 .filter(|m| m.alive())
+                                 .map(PeerFileMember::from)
+                                 .collect::<Vec<_>>();
+        members.sort_by(|a, b| a.member_id.cmp(b.member_id));
+        self.stale = false;
+        Ok(self.renderer
+               .render(TEMPLATE_NAME, &PeerFileContext { members })?)
+    }
+
+    /// Record that the file was rewritten at `now`.
+    pub fn written(&mut self, now: Instant) { self.last_written = Some(now); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(member_id: &str, ip: &str, alive: bool, leader: bool) -> CensusMember {
+        let mut member = CensusMember { member_id: member_id.to_string(),
+                                        alive,
+                                        leader,
+                                        ..Default::default() };
+        member.sys.ip = ip.to_string();
+        member
+    }
+
+    #[test]
+    fn peer_file_must_be_a_bare_filename() {
+        assert!(PeerFile::new("peers.txt", None).is_ok());
+        for filename in &["", "../peers.txt", "config/peers.txt", "/etc/hosts"] {
+            match PeerFile::new(filename, None) {
+                Err(Error::InvalidPeerFile(f)) => assert_eq!(&f, filename),
+                other => {
+                    panic!("Expected InvalidPeerFile for {:?}, got {:?}",
+                           filename, other)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_template_is_rejected() {
+        match PeerFile::new("peers.txt", Some("{{#each members}}")) {
+            Err(Error::InvalidPeerFileTemplate(_)) => (),
+            other => panic!("Expected InvalidPeerFileTemplate, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn default_template_lists_alive_members_in_order() {
+        let mut peer_file = PeerFile::new("peers.txt", None).unwrap();
+        let members = vec![member("bbb", "10.0.0.2", true, false),
+                           member("ccc", "10.0.0.3", false, false),
+                           member("aaa", "10.0.0.1", true, true),];
+
+        assert_eq!(peer_file.render(&members).unwrap(),
+                   "10.0.0.1 aaa\n10.0.0.2 bbb\n");
+    }
+
+    #[test]
+    fn template_context_includes_leader_flag() {
+        let mut peer_file = PeerFile::new("peers.txt",
+                                          Some("{{#each members}}{{hostname}}{{#if leader}} \
+                                                (leader){{/if}}\n{{/each}}")).unwrap();
+        let mut leader = member("aaa", "10.0.0.1", true, true);
+        leader.sys.hostname = "alpha".to_string();
+        let mut follower = member("bbb", "10.0.0.2", true, false);
+        follower.sys.hostname = "bravo".to_string();
+
+        assert_eq!(peer_file.render(&[leader, follower]).unwrap(),
+                   "alpha (leader)\nbravo\n");
+    }
+
+    #[test]
+    fn rewrites_wait_for_the_minimum_interval() {
+        let mut peer_file = PeerFile::new("peers.txt", None).unwrap();
+        let start = Instant::now();
+        assert!(peer_file.is_due(start), "A new peer file should be written");
+
+        peer_file.render(&[member("aaa", "10.0.0.1", true, false)])
+                 .unwrap();
+        peer_file.written(start);
+        assert!(!peer_file.is_due(start), "Nothing has changed yet");
+
+        peer_file.mark_stale();
+        assert!(!peer_file.is_due(start + peer_file.min_interval / 2),
+                "Rewrites within the minimum interval should be held back");
+        assert!(peer_file.is_due(start + peer_file.min_interval),
+                "Held back rewrites should happen once the interval passes");
+    }
+}
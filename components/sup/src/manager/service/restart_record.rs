@@ -0,0 +1,167 @@
+//! Keeps a service's restart bookkeeping in its data directory, so
+//! that when the Supervisor itself is restarted, a crash-looping
+//! service carries on with the backoff it had reached rather than
+//! going back to restarting it as quickly as possible.
+
+use habitat_core::fs::AtomicWriter;
+use log::{debug,
+          warn};
+use serde::{Deserialize,
+            Serialize};
+use std::{fs,
+          io,
+          path::{Path,
+                 PathBuf},
+          time::{Duration,
+                 SystemTime,
+                 UNIX_EPOCH}};
+
+const RESTART_RECORD_FILE: &str = ".hab-restart-record.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestartRecord {
+    /// The number of times in a row the service has failed and been restarted
+    pub consecutive_failures: u64,
+    /// When the service last failed, expressed as seconds since epoch
+    pub last_failure_at:      u64,
+    /// The duration waited before the latest restart, in milliseconds
+    pub backoff_period_ms:    u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct RestartRecordFile {
+    path: PathBuf,
+}
+
+impl RestartRecordFile {
+    pub fn new(svc_data_path: &Path) -> Self {
+        RestartRecordFile { path: svc_data_path.join(RESTART_RECORD_FILE), }
+    }
+
+    /// The record kept by a previous Supervisor, along with how long
+    /// ago the service last failed. Records that can't be read, that
+    /// claim the failure hasn't happened yet, or whose cooldown period
+    /// has passed as of `now` are removed rather than returned.
+    pub fn load(&self,
+                cooldown_period: Duration,
+                now: SystemTime)
+                -> Option<(RestartRecord, Duration)> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return None,
+            Err(err) => {
+                warn!("Unable to read restart record {}: {}",
+                      self.path.display(),
+                      err);
+                return None;
+            }
+        };
+        let record: RestartRecord = match serde_json::from_str(&contents) {
+            Ok(record) => record,
+            Err(err) => {
+                warn!("Discarding corrupt restart record {}: {}",
+                      self.path.display(),
+                      err);
+                self.remove();
+                return None;
+            }
+        };
+        let last_failure_at = UNIX_EPOCH + Duration::from_secs(record.last_failure_at);
+        match now.duration_since(last_failure_at) {
+            Ok(elapsed) if elapsed < cooldown_period => Some((record, elapsed)),
+            Ok(_) => {
+                debug!("Restart record {} has outlived the cooldown period",
+                       self.path.display());
+                self.remove();
+                None
+            }
+            Err(_) => {
+                warn!("Discarding restart record {} with a failure in the future",
+                      self.path.display());
+                self.remove();
+                None
+            }
+        }
+    }
+
+    pub fn save(&self, record: &RestartRecord) {
+        let result = AtomicWriter::new(&self.path).and_then(|w| {
+                                                      w.with_writer(|f| {
+                                                           serde_json::to_writer(f, record)
+                                                                .map_err(io::Error::from)
+                                                       })
+                                                  });
+        if let Err(err) = result {
+            warn!("Unable to write restart record {}: {}",
+                  self.path.display(),
+                  err);
+        }
+    }
+
+    pub fn remove(&self) {
+        match fs::remove_file(&self.path) {
+            Ok(()) => {}
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => {
+                warn!("Unable to remove restart record {}: {}",
+                      self.path.display(),
+                      err)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const COOLDOWN: Duration = Duration::from_secs(300);
+
+    fn record_at(last_failure_at: SystemTime) -> RestartRecord {
+        RestartRecord { consecutive_failures: 3,
+                        last_failure_at:      last_failure_at.duration_since(UNIX_EPOCH)
+                                                             .unwrap()
+                                                             .as_secs(),
+                        backoff_period_ms:    30_000, }
+    }
+
+    #[test]
+    fn records_are_loaded_until_the_cooldown_passes() {
+        let dir = TempDir::new().unwrap();
+        let file = RestartRecordFile::new(dir.path());
+        assert!(file.load(COOLDOWN, SystemTime::now()).is_none());
+
+        let failed_at = SystemTime::now();
+        let record = record_at(failed_at);
+        file.save(&record);
+        let (loaded, elapsed) = file.load(COOLDOWN, failed_at + Duration::from_secs(60))
+                                    .unwrap();
+        assert_eq!(loaded, record);
+        assert!(elapsed >= Duration::from_secs(60) && elapsed < Duration::from_secs(61));
+
+        assert!(file.load(COOLDOWN, failed_at + COOLDOWN + Duration::from_secs(1))
+                    .is_none());
+        assert!(!file.path.exists(), "Expired records should be removed");
+    }
+
+    #[test]
+    fn corrupt_records_are_discarded() {
+        let dir = TempDir::new().unwrap();
+        let file = RestartRecordFile::new(dir.path());
+        fs::write(&file.path, "{\"consecutive_failures\": ").unwrap();
+
+        assert!(file.load(COOLDOWN, SystemTime::now()).is_none());
+        assert!(!file.path.exists());
+    }
+
+    #[test]
+    fn future_dated_records_are_discarded() {
+        let dir = TempDir::new().unwrap();
+        let file = RestartRecordFile::new(dir.path());
+        file.save(&record_at(SystemTime::now() + Duration::from_secs(3600)));
+
+        assert!(file.load(COOLDOWN, SystemTime::now()).is_none());
+        assert!(!file.path.exists());
+    }
+}
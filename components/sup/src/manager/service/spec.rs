@@ -14,7 +14,9 @@ use habitat_core::{fs::atomic_write,
                    util,
                    ChannelIdent};
 use habitat_sup_protocol::{self,
-                           net};
+                           ctl::SvcUpdate,
+                           net,
+                           types::SignerVerification};
 use log::{debug,
           warn};
 use serde::{self,
@@ -99,6 +101,29 @@ pub struct ServiceSpec {
     pub desired_state:          DesiredState,
     pub shutdown_timeout:       Option<ShutdownTimeout>,
     pub svc_encrypted_password: Option<String>,
+    /// Overrides the Supervisor's `--verify-package-signers` policy
+    /// for this service.
+    pub signer_verification:    Option<SignerVerification>,
+    /// The name of a file, in the service's files directory, to keep
+    /// populated with the alive members of the service group.
+    pub peer_file:              Option<String>,
+    /// The Handlebars template used to render `peer_file`. See
+    /// `peer_file::DEFAULT_PEER_FILE_TEMPLATE` for the default.
+    pub peer_file_template:     Option<String>,
+    /// How many seconds either way to move each of the service's
+    /// health checks, after putting off the first (see
+    /// `HealthCheckSplay`). `None` leaves it to the Supervisor.
+    pub health_check_splay:     Option<u32>,
+    /// Overrides the Supervisor's `--service-min-backoff-period`
+    /// for this service, in seconds.
+    pub min_backoff_period:     Option<u32>,
+    /// Overrides the Supervisor's `--service-max-backoff-period`
+    /// for this service, in seconds.
+    pub max_backoff_period:     Option<u32>,
+    /// Overrides the Supervisor's
+    /// `--service-restart-cooldown-period` for this service, in
+    /// seconds.
+    pub cooldown_period:        Option<u32>,
     // it is important that the health check interval
     // is the last field to be serialized because it
     // is serialized as a table. Individual values
@@ -125,6 +150,13 @@ impl ServiceSpec {
                desired_state: DesiredState::default(),
                health_check_interval: HealthCheckInterval::default(),
                svc_encrypted_password: None,
+               signer_verification: None,
+               peer_file: None,
+               peer_file_template: None,
+               health_check_splay: None,
+               min_backoff_period: None,
+               max_backoff_period: None,
+               cooldown_period: None,
                shutdown_timeout: None }
     }
 
@@ -133,7 +165,7 @@ impl ServiceSpec {
     // be removed.
     fn deserialization_base() -> Self { Self::new(PackageIdent::default()) }
 
-    fn to_toml_string(&self) -> Result<String> {
+    pub(crate) fn to_toml_string(&self) -> Result<String> {
         if self.ident == PackageIdent::default() {
             return Err(Error::MissingRequiredIdent);
         }
@@ -282,10 +314,110 @@ impl ServiceSpec {
         if let Some(shutdown_timeout) = svc_load.shutdown_timeout {
             self.shutdown_timeout = Some(ShutdownTimeout::from(shutdown_timeout));
         }
+        if let Some(signer_verification) = svc_load.signer_verification {
+            if let Some(signer_verification) = SignerVerification::from_i32(signer_verification) {
+                self.signer_verification = Some(signer_verification);
+            } else {
+                warn!("Unable to parse signer verification value from SvcLoad protocol message; \
+                       ignoring: {}",
+                      signer_verification);
+            }
+        }
+        if let Some(peer_file) = svc_load.peer_file {
+            self.peer_file = Some(peer_file);
+        }
+        if let Some(peer_file_template) = svc_load.peer_file_template {
+            self.peer_file_template = Some(peer_file_template);
+        }
+        if let Some(health_check_splay) = svc_load.health_check_splay {
+            self.health_check_splay = Some(health_check_splay);
+        }
+        if let Some(min_backoff_period) = svc_load.min_backoff_period {
+            self.min_backoff_period = Some(min_backoff_period);
+        }
+        if let Some(max_backoff_period) = svc_load.max_backoff_period {
+            self.max_backoff_period = Some(max_backoff_period);
+        }
+        if let Some(cooldown_period) = svc_load.cooldown_period {
+            self.cooldown_period = Some(cooldown_period);
+        }
         Ok(self)
     }
 
-    pub fn merge_svc_update(&mut self, svc_update: habitat_sup_protocol::ctl::SvcUpdate) {
+    /// Apply a `hab svc update` patch to this spec. The patch is
+    /// validated in full before anything is changed, so on error the
+    /// spec is left as it was.
+    pub fn merge_svc_update(&mut self, svc_update: SvcUpdate) -> Result<()> {
+        let topology = svc_update_enum(svc_update.topology, Topology::from_i32, "topology")?;
+        let update_strategy = svc_update_enum(svc_update.update_strategy,
+                                              UpdateStrategy::from_i32,
+                                              "update strategy")?;
+        let update_condition = svc_update_enum(svc_update.update_condition,
+                                               UpdateCondition::from_i32,
+                                               "update condition")?;
+        let binding_mode = svc_update_enum(svc_update.binding_mode,
+                                           BindingMode::from_i32,
+                                           "binding mode")?;
+        let signer_verification = svc_update_enum(svc_update.signer_verification,
+                                                  SignerVerification::from_i32,
+                                                  "signer verification")?;
+        for field in &svc_update.unset {
+            if !SvcUpdate::UNSETTABLE_FIELDS.contains(&field.as_str()) {
+                return Err(net::err(net::ErrCode::BadPayload,
+                                    format!("Cannot unset unknown field '{}'", field)).into());
+            }
+            let is_set = match field.as_str() {
+                "bldr_url" => svc_update.bldr_url.is_some(),
+                "channel" => svc_update.bldr_channel.is_some(),
+                "topology" => topology.is_some(),
+                "update_strategy" => update_strategy.is_some(),
+                "update_condition" => update_condition.is_some(),
+                "binds" => svc_update.binds.is_some(),
+                "binding_mode" => binding_mode.is_some(),
+                "health_check_interval" => svc_update.health_check_interval.is_some(),
+                "health_check_splay" => svc_update.health_check_splay.is_some(),
+                "shutdown_timeout" => svc_update.shutdown_timeout.is_some(),
+                "svc_encrypted_password" => svc_update.svc_encrypted_password.is_some(),
+                "signer_verification" => signer_verification.is_some(),
+                "peer_file" => svc_update.peer_file.is_some(),
+                "peer_file_template" => svc_update.peer_file_template.is_some(),
+                _ => {
+                    unreachable!("'{}' is listed in UNSETTABLE_FIELDS but can't be unset",
+                                 field)
+                }
+            };
+            if is_set {
+                return Err(net::err(net::ErrCode::BadPayload,
+                                    format!("Cannot both set and unset '{}'", field)).into());
+            }
+        }
+
+        let defaults = Self::new(self.ident.clone());
+        for field in svc_update.unset {
+            match field.as_str() {
+                "bldr_url" => self.bldr_url = defaults.bldr_url.clone(),
+                "channel" => self.channel = defaults.channel.clone(),
+                "topology" => self.topology = defaults.topology,
+                "update_strategy" => self.update_strategy = defaults.update_strategy,
+                "update_condition" => self.update_condition = defaults.update_condition,
+                "binds" => self.binds = defaults.binds.clone(),
+                "binding_mode" => self.binding_mode = defaults.binding_mode,
+                "health_check_interval" => {
+                    self.health_check_interval = defaults.health_check_interval
+                }
+                "health_check_splay" => self.health_check_splay = defaults.health_check_splay,
+                "shutdown_timeout" => self.shutdown_timeout = defaults.shutdown_timeout,
+                "svc_encrypted_password" => {
+                    self.svc_encrypted_password = defaults.svc_encrypted_password.clone()
+                }
+                "signer_verification" => self.signer_verification = defaults.signer_verification,
+                "peer_file" => self.peer_file = defaults.peer_file.clone(),
+                "peer_file_template" => {
+                    self.peer_file_template = defaults.peer_file_template.clone()
+                }
+                _ => unreachable!("unset fields were validated above"),
+            }
+        }
         if let Some(group) = svc_update.group {
             self.group = group;
         }
@@ -295,44 +427,20 @@ impl ServiceSpec {
         if let Some(channel) = svc_update.bldr_channel {
             self.channel = channel.into();
         }
-        if let Some(topology) = svc_update.topology {
-            if let Some(topology) = Topology::from_i32(topology) {
-                self.topology = topology;
-            } else {
-                warn!("Unable to parse topology value from SvcUpdate protocol message; ignoring: \
-                       {}",
-                      topology);
-            }
+        if let Some(topology) = topology {
+            self.topology = topology;
         }
-        if let Some(update_strategy) = svc_update.update_strategy {
-            if let Some(update_strategy) = UpdateStrategy::from_i32(update_strategy) {
-                self.update_strategy = update_strategy;
-            } else {
-                warn!("Unable to parse update strategy value from SvcUpdate protocol message; \
-                       ignoring: {}",
-                      update_strategy);
-            }
+        if let Some(update_strategy) = update_strategy {
+            self.update_strategy = update_strategy;
         }
-        if let Some(update_condition) = svc_update.update_condition {
-            if let Some(update_condition) = UpdateCondition::from_i32(update_condition) {
-                self.update_condition = update_condition;
-            } else {
-                warn!("Unable to parse update condition value from SvcUpdate protocol message; \
-                       ignoring: {}",
-                      update_condition);
-            }
+        if let Some(update_condition) = update_condition {
+            self.update_condition = update_condition;
         }
         if let Some(list) = svc_update.binds {
             self.binds = list.into();
         }
-        if let Some(binding_mode) = svc_update.binding_mode {
-            if let Some(binding_mode) = BindingMode::from_i32(binding_mode) {
-                self.binding_mode = binding_mode;
-            } else {
-                warn!("Unable to parse binding mode value from SvcUpdate protocol message; \
-                       ignoring: {}",
-                      binding_mode);
-            }
+        if let Some(binding_mode) = binding_mode {
+            self.binding_mode = binding_mode;
         }
         if let Some(svc_encrypted_password) = svc_update.svc_encrypted_password {
             self.svc_encrypted_password = Some(svc_encrypted_password);
@@ -343,6 +451,19 @@ impl ServiceSpec {
         if let Some(shutdown_timeout) = svc_update.shutdown_timeout {
             self.shutdown_timeout = Some(ShutdownTimeout::from(shutdown_timeout));
         }
+        if let Some(signer_verification) = signer_verification {
+            self.signer_verification = Some(signer_verification);
+        }
+        if let Some(peer_file) = svc_update.peer_file {
+            self.peer_file = Some(peer_file);
+        }
+        if let Some(peer_file_template) = svc_update.peer_file_template {
+            self.peer_file_template = Some(peer_file_template);
+        }
+        if let Some(health_check_splay) = svc_update.health_check_splay {
+            self.health_check_splay = Some(health_check_splay);
+        }
+        Ok(())
     }
 
     /// Given an `old` and a `new` spec, figure out what operations
@@ -434,6 +555,13 @@ impl ServiceSpec {
                         desired_state: _,
                         shutdown_timeout,
                         svc_encrypted_password,
+                        signer_verification,
+                        peer_file,
+                        peer_file_template,
+                        health_check_splay,
+                        min_backoff_period,
+                        max_backoff_period,
+                        cooldown_period,
                         health_check_interval,
                     } = &running_spec;
 
@@ -462,8 +590,12 @@ impl ServiceSpec {
                         // TODO (CM): This probably doesn't need to be here
                         || shutdown_timeout != &disk_spec.shutdown_timeout
                         || svc_encrypted_password != &disk_spec.svc_encrypted_password
+                        || signer_verification != &disk_spec.signer_verification
+                        || peer_file != &disk_spec.peer_file
+                        || peer_file_template != &disk_spec.peer_file_template
                         // TODO (CM): This probably doesn't need to be here, either
                         || health_check_interval != &disk_spec.health_check_interval
+                        || health_check_splay != &disk_spec.health_check_splay
                     {
                         debug!("Reconciliation: '{}' queued for restart",
                                running_spec.ident);
@@ -478,6 +610,12 @@ impl ServiceSpec {
                         {
                             ops.insert(RefreshOperation::RestartUpdater);
                         }
+                        if min_backoff_period != &disk_spec.min_backoff_period
+                            || max_backoff_period != &disk_spec.max_backoff_period
+                            || cooldown_period != &disk_spec.cooldown_period
+                        {
+                            ops.insert(RefreshOperation::ReconfigureRestarts);
+                        }
 
                         // We should have *something* to do down
                         // here, but if we don't, let's be explicit
@@ -504,6 +642,9 @@ pub(crate) enum RefreshOperation {
     /// This can happen if a user wants to change the channel a
     /// service is updating from, for instance.
     RestartUpdater,
+    /// Switch the service over to new restart backoff and cooldown
+    /// periods.
+    ReconfigureRestarts,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -528,6 +669,20 @@ pub(crate) enum ServiceOperation {
     },
 }
 
+/// Convert an enum value from a `SvcUpdate` message, failing if it is out of range.
+fn svc_update_enum<T>(value: Option<i32>,
+                      from_i32: impl Fn(i32) -> Option<T>,
+                      name: &str)
+                      -> Result<Option<T>> {
+    value.map(|v| {
+             from_i32(v).ok_or_else(|| {
+                            net::err(net::ErrCode::BadPayload, format!("Invalid {}: {}", name, v))
+                            .into()
+                        })
+         })
+         .transpose()
+}
+
 impl FromStr for ServiceSpec {
     type Err = Error;
 
@@ -696,6 +851,13 @@ mod test {
                           config_from:            Some(PathBuf::from("/only/for/development")),
                           desired_state:          DesiredState::Down,
                           svc_encrypted_password: None,
+                          signer_verification:    None,
+                          peer_file:              None,
+                          peer_file_template:     None,
+                          health_check_splay:     None,
+                          min_backoff_period:     None,
+                          max_backoff_period:     None,
+                          cooldown_period:        None,
                           shutdown_timeout:       Some(ShutdownTimeout::from_str("10").unwrap()), };
         let toml = spec.to_toml_string().unwrap();
 
@@ -865,6 +1027,13 @@ mod test {
                           config_from:            Some(PathBuf::from("/only/for/development")),
                           desired_state:          DesiredState::Down,
                           svc_encrypted_password: None,
+                          signer_verification:    Some(SignerVerification::Strict),
+                          peer_file:              Some(String::from("peers.txt")),
+                          peer_file_template:     None,
+                          health_check_splay:     Some(5),
+                          min_backoff_period:     Some(1),
+                          max_backoff_period:     None,
+                          cooldown_period:        Some(60),
                           shutdown_timeout:       Some(ShutdownTimeout::default()), };
         spec.to_file(&path).unwrap();
         let toml = string_from_file(path);
@@ -880,6 +1049,12 @@ mod test {
         assert!(toml.contains(r#"desired_state = "down""#));
         assert!(toml.contains(r#"config_from = "/only/for/development""#));
         assert!(toml.contains(r#"binding_mode = "relaxed""#));
+        assert!(toml.contains(r#"signer_verification = "strict""#));
+        assert!(toml.contains(r#"peer_file = "peers.txt""#));
+        assert!(toml.contains(r#"health_check_splay = 5"#));
+        assert!(toml.contains(r#"min_backoff_period = 1"#));
+        assert!(!toml.contains(r#"max_backoff_period"#));
+        assert!(toml.contains(r#"cooldown_period = 60"#));
         assert!(toml.contains(r#"[health_check_interval]"#));
         assert!(toml.contains(r#"secs = 23"#));
         assert!(toml.contains(r#"nanos = 0"#));
@@ -1046,6 +1221,108 @@ mod test {
                    HealthCheckInterval::from_str("5").unwrap());
     }
 
+    #[test]
+    fn merge_svc_update_sets_and_unsets_fields() {
+        let mut spec = ServiceSpec::new(PackageIdent::from_str("core/redis").unwrap());
+        spec.shutdown_timeout = Some(ShutdownTimeout::from(30));
+        spec.binds = vec![ServiceBind::from_str("db:postgres.app").unwrap()];
+
+        let update =
+            habitat_sup_protocol::ctl::SvcUpdate { bldr_channel: Some("unstable".to_string()),
+                                                   peer_file: Some("peers.txt".to_string()),
+                                                   health_check_splay: Some(5),
+                                                   unset: vec!["binds".to_string(),
+                                                               "shutdown_timeout".to_string()],
+                                                   ..Default::default() };
+        spec.merge_svc_update(update).unwrap();
+
+        assert_eq!(spec.channel, ChannelIdent::unstable());
+        assert_eq!(spec.peer_file, Some("peers.txt".to_string()));
+        assert_eq!(spec.health_check_splay, Some(5));
+        assert!(spec.binds.is_empty());
+        assert_eq!(spec.shutdown_timeout, None);
+    }
+
+    #[test]
+    fn merge_svc_update_rejects_invalid_patches() {
+        let original = ServiceSpec::new(PackageIdent::from_str("core/redis").unwrap());
+        let updates =
+            vec![habitat_sup_protocol::ctl::SvcUpdate { topology: Some(42),
+                                                        bldr_channel:
+                                                            Some("unstable".to_string()),
+                                                        ..Default::default() },
+                 habitat_sup_protocol::ctl::SvcUpdate { unset: vec!["ident".to_string()],
+                                                        ..Default::default() },
+                 habitat_sup_protocol::ctl::SvcUpdate { bldr_channel:
+                                                            Some("unstable".to_string()),
+                                                        unset: vec!["channel".to_string()],
+                                                        ..Default::default() },];
+
+        for update in updates {
+            let mut spec = original.clone();
+            assert!(spec.merge_svc_update(update.clone()).is_err(),
+                    "{:?} should be rejected",
+                    update);
+            assert_eq!(spec, original,
+                       "A rejected patch should not change the spec");
+        }
+    }
+
+    /// A patch that sets every unsettable `SvcUpdate` field to
+    /// something other than its spec default. It is written without
+    /// `..` so that a new `SvcUpdate` field won't compile until it is
+    /// added here. `ident` only identifies the service and `group`
+    /// can't be unset, so both are left out.
+    fn svc_update_setting_every_field() -> SvcUpdate {
+        let binds = vec![ServiceBind::from_str("db:postgres.app").unwrap()];
+        SvcUpdate { ident:                  None,
+                    group:                  None,
+                    binds:                  Some(binds.into_iter().collect()),
+                    binding_mode:           Some(BindingMode::Relaxed as i32),
+                    bldr_url:               Some("https://bldr.example.com".to_string()),
+                    bldr_channel:           Some("unstable".to_string()),
+                    svc_encrypted_password: Some("sekrit".to_string()),
+                    topology:               Some(Topology::Leader as i32),
+                    update_strategy:        Some(UpdateStrategy::Rolling as i32),
+                    health_check_interval:
+                        Some(habitat_sup_protocol::types::HealthCheckInterval { seconds: 45 }),
+                    shutdown_timeout:       Some(30),
+                    update_condition:       Some(UpdateCondition::TrackChannel as i32),
+                    signer_verification:    Some(SignerVerification::Strict as i32),
+                    peer_file:              Some("peers.txt".to_string()),
+                    peer_file_template:     Some("{{member}}".to_string()),
+                    health_check_splay:     Some(5),
+                    unset:                  vec![], }
+    }
+
+    #[test]
+    fn every_merged_svc_update_field_is_unsettable() {
+        let ident = PackageIdent::from_str("core/redis").unwrap();
+        let mut updated = ServiceSpec::new(ident.clone());
+        updated.merge_svc_update(svc_update_setting_every_field())
+               .unwrap();
+
+        for field in SvcUpdate::UNSETTABLE_FIELDS {
+            let mut spec = updated.clone();
+            spec.merge_svc_update(SvcUpdate { unset: vec![field.to_string()],
+                                              ..Default::default() })
+                .unwrap();
+            assert_ne!(spec, updated,
+                       "Unsetting '{}' should change the spec",
+                       field);
+        }
+
+        let unset = SvcUpdate::UNSETTABLE_FIELDS.iter()
+                                                .map(ToString::to_string)
+                                                .collect();
+        updated.merge_svc_update(SvcUpdate { unset,
+                                             ..Default::default() })
+               .unwrap();
+        assert_eq!(updated,
+                   ServiceSpec::new(ident),
+                   "Every merged field should be listed in UNSETTABLE_FIELDS");
+    }
+
     mod reconcile {
         use super::*;
 
@@ -1169,10 +1446,26 @@ mod test {
                    restart,
                    svc_encrypted_password,
                    Some("monkeys".to_string()));
+        reconcile!(signer_verification_causes_restart,
+                   restart,
+                   signer_verification,
+                   Some(SignerVerification::Strict));
+        reconcile!(peer_file_causes_restart,
+                   restart,
+                   peer_file,
+                   Some("peers.txt".to_string()));
+        reconcile!(peer_file_template_causes_restart,
+                   restart,
+                   peer_file_template,
+                   Some("{{#each members}}{{address}}\n{{/each}}".to_string()));
         reconcile!(health_check_interval_causes_restart,
                    restart,
                    health_check_interval,
                    10000.into());
+        reconcile!(health_check_splay_causes_restart,
+                   restart,
+                   health_check_splay,
+                   Some(5));
 
         reconcile!(bldr_url_causes_update,
                    update,
@@ -1194,5 +1487,20 @@ mod test {
                    update_condition,
                    UpdateCondition::TrackChannel,
                    vec![RefreshOperation::RestartUpdater]);
+        reconcile!(min_backoff_period_causes_update,
+                   update,
+                   min_backoff_period,
+                   Some(1),
+                   vec![RefreshOperation::ReconfigureRestarts]);
+        reconcile!(max_backoff_period_causes_update,
+                   update,
+                   max_backoff_period,
+                   Some(60),
+                   vec![RefreshOperation::ReconfigureRestarts]);
+        reconcile!(cooldown_period_causes_update,
+                   update,
+                   cooldown_period,
+                   Some(30),
+                   vec![RefreshOperation::ReconfigureRestarts]);
     }
 }
@@ -4,10 +4,14 @@ mod rolling_update_worker;
 use self::{package_update_worker::PackageUpdateWorker,
            rolling_update_worker::RollingUpdateWorker};
 use crate::{census::CensusRing,
-            manager::service::{Service,
-                               UpdateStrategy}};
-use futures::future::{self,
-                      AbortHandle};
+            manager::{service::{Service,
+                                UpdateStrategy},
+                      task_supervisor::{self,
+                                        RestartPolicy,
+                                        ShutdownStage}}};
+use futures::future::{AbortHandle,
+                      BoxFuture,
+                      FutureExt};
 use habitat_common::outputln;
 use habitat_core::{package::PackageIdent,
                    service::ServiceGroup};
@@ -18,7 +22,6 @@ use std::{self,
           cmp::Ordering,
           collections::HashMap,
           fmt,
-          future::Future,
           sync::Arc,
           time::Duration};
 
@@ -120,7 +123,9 @@ impl ServiceUpdater {
         self.updates.lock().get(service_group).cloned()
     }
 
-    fn at_once_worker(&mut self, service: &Service) -> impl Future<Output = ()> + Send + 'static {
+    fn at_once_worker(&mut self,
+                      service: &Service)
+                      -> impl FnMut() -> BoxFuture<'static, ()> + Send + 'static {
         debug!("'{}' service updater spawning at-once worker watching for changes to '{}' from \
                 channel '{}'",
                service.service_group,
@@ -130,19 +135,25 @@ impl ServiceUpdater {
         let full_ident = service.pkg.ident.clone();
         let updates = Arc::clone(&self.updates);
         let package_update_worker = PackageUpdateWorker::new(service, self.period);
-        async move {
-            let new_ident = package_update_worker.update().await;
-            debug!("'{}' at-once updater found update from '{}' to '{}'",
-                   service_group, full_ident, new_ident);
-            Self::update_message(&new_ident, full_ident.as_ref());
-            updates.lock().insert(service_group, new_ident);
+        move || {
+            let service_group = service_group.clone();
+            let full_ident = full_ident.clone();
+            let updates = Arc::clone(&updates);
+            let package_update_worker = package_update_worker.clone();
+            async move {
+                let new_ident = package_update_worker.update().await;
+                debug!("'{}' at-once updater found update from '{}' to '{}'",
+                       service_group, full_ident, new_ident);
+                Self::update_message(&new_ident, full_ident.as_ref());
+                updates.lock().insert(service_group, new_ident);
+            }.boxed()
         }
     }
 
     fn rolling_worker(&mut self,
                       service: &Service,
                       census_ring: Arc<RwLock<CensusRing>>)
-                      -> impl Future<Output = ()> + Send + 'static {
+                      -> impl FnMut() -> BoxFuture<'static, ()> + Send + 'static {
         debug!("'{}' service updater spawning rolling worker watching for changes to '{}' from \
                 channel '{}'",
                service.service_group,
@@ -153,12 +164,18 @@ impl ServiceUpdater {
         let updates = Arc::clone(&self.updates);
         let worker =
             RollingUpdateWorker::new(service, census_ring, self.butterfly.clone(), self.period);
-        async move {
-            let new_ident = worker.run().await;
-            debug!("'{}' rolling updater found update from '{}' to '{}'",
-                   service_group, full_ident, new_ident);
-            Self::update_message(&new_ident, full_ident.as_ref());
-            updates.lock().insert(service_group, new_ident);
+        move || {
+            let service_group = service_group.clone();
+            let full_ident = full_ident.clone();
+            let updates = Arc::clone(&updates);
+            let worker = worker.clone();
+            async move {
+                let new_ident = worker.run().await;
+                debug!("'{}' rolling updater found update from '{}' to '{}'",
+                       service_group, full_ident, new_ident);
+                Self::update_message(&new_ident, full_ident.as_ref());
+                updates.lock().insert(service_group, new_ident);
+            }.boxed()
         }
     }
 
@@ -174,12 +191,15 @@ impl ServiceUpdater {
         };
     }
 
-    /// Make the worker abortable and spawn it
+    /// Spawn the worker as a supervised task, restarting it if it
+    /// panics
     fn spawn_worker(&mut self,
                     service_group: ServiceGroup,
-                    worker: impl Future<Output = ()> + Send + 'static) {
-        let (worker, abort_handle) = future::abortable(worker);
+                    worker: impl FnMut() -> BoxFuture<'static, ()> + Send + 'static) {
+        let abort_handle = task_supervisor::spawn(format!("service-updater/{}", service_group),
+                                                  ShutdownStage::Updaters,
+                                                  RestartPolicy::Always,
+                                                  worker);
         self.workers.insert(service_group, Worker(abort_handle));
-        tokio::spawn(worker);
     }
 }
@@ -55,6 +55,7 @@ impl PackageUpdateWorkerPeriod {
 /// When `run`, a `PackageUpdateWorker` returns a future that continuously checks for a change in
 /// version of the package being run by a service. If a change is detected, the package is installed
 /// and its identifier returned.
+#[derive(Clone)]
 pub struct PackageUpdateWorker {
     service_group:    ServiceGroup,
     ident:            PackageIdent,
@@ -54,6 +54,7 @@ enum FollowerUpdateTurnEvent {
 ///
 /// The basic behavior of the update is to elect an update leader. The leader waits for an update.
 /// When an update is detected, the leader is updated and each follower takes a turn to update.
+#[derive(Clone)]
 pub struct RollingUpdateWorker {
     service_group:         ServiceGroup,
     topology:              Topology,
@@ -22,8 +22,10 @@ pub struct Sys {
     pub gossip_port:       u16,
     pub ctl_gateway_ip:    IpAddr,
     pub ctl_gateway_port:  u16,
-    pub http_gateway_ip:   IpAddr,
-    pub http_gateway_port: u16,
+    /// `None` if the HTTP gateway isn't served over TCP, in which case
+    /// it isn't advertised to other Supervisors.
+    pub http_gateway_ip:   Option<IpAddr>,
+    pub http_gateway_port: Option<u16>,
     pub permanent:         bool,
 }
 
@@ -31,7 +33,7 @@ impl Sys {
     pub fn new(permanent: bool,
                gossip: GossipListenAddr,
                ctl: ListenCtlAddr,
-               http: HttpListenAddr,
+               http: Option<HttpListenAddr>,
                ip: IpAddr)
                -> Self {
         let host = habitat_core::os::net::hostname().unwrap_or_else(|e| {
@@ -50,8 +52,8 @@ impl Sys {
                gossip_port: gossip.port(),
                ctl_gateway_ip: ctl.ip(),
                ctl_gateway_port: ctl.port(),
-               http_gateway_ip: http.ip(),
-               http_gateway_port: http.port(),
+               http_gateway_ip: http.map(|http| http.ip()),
+               http_gateway_port: http.map(|http| http.port()),
                permanent }
     }
 
@@ -62,8 +64,8 @@ impl Sys {
                   gossip_port:       u32::from(self.gossip_port),
                   ctl_gateway_ip:    self.ctl_gateway_ip.to_string(),
                   ctl_gateway_port:  u32::from(self.ctl_gateway_port),
-                  http_gateway_ip:   self.http_gateway_ip.to_string(),
-                  http_gateway_port: u32::from(self.http_gateway_port), }
+                  http_gateway_ip:   self.http_gateway_ip.map(|ip| ip.to_string()),
+                  http_gateway_port: self.http_gateway_port.map(u32::from), }
     }
 
     pub fn ctl_listen(&self) -> SocketAddr {
@@ -72,7 +74,29 @@ impl Sys {
 
     pub fn gossip_listen(&self) -> SocketAddr { SocketAddr::new(self.gossip_ip, self.gossip_port) }
 
-    pub fn http_listen(&self) -> HttpListenAddr {
-        HttpListenAddr::new(self.http_gateway_ip, self.http_gateway_port)
+    pub fn http_listen(&self) -> Option<HttpListenAddr> {
+        self.http_gateway_ip
+            .zip(self.http_gateway_port)
+            .map(|(ip, port)| HttpListenAddr::new(ip, port))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn http_gateway_is_not_advertised_unless_served_over_tcp() {
+        let sys = Sys::new(false,
+                           GossipListenAddr::default(),
+                           ListenCtlAddr::default(),
+                           None,
+                           IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let sys_info = sys.as_sys_info();
+
+        assert_eq!(sys.http_listen(), None);
+        assert_eq!(sys_info.http_gateway_ip, None);
+        assert_eq!(sys_info.http_gateway_port, None);
     }
 }
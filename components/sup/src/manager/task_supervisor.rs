@@ -0,0 +1,377 @@
+//! Runs the Supervisor's long-lived background loops (spec
+//! reconciliation, updaters, health checks, census updates, and the
+//! event exporter) as named tasks.
+//!
+//! A panic in a plain `tokio::spawn`ed task is swallowed by the
+//! runtime, leaving a Supervisor that still answers on its gateways
+//! but no longer updates or health checks its services. Tasks spawned
+//! here instead have their panics caught and logged with the task's
+//! name, and are restarted according to their `RestartPolicy`. The
+//! state of every task is available from the HTTP gateway's
+//! `/supervisor/tasks` endpoint.
+
+use futures::future::{self,
+                      AbortHandle,
+                      Future,
+                      FutureExt};
+use habitat_common::{FeatureFlag,
+                     FEATURE_FLAGS};
+use lazy_static::lazy_static;
+use log::{debug,
+          error,
+          warn};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{any::Any,
+          cmp,
+          collections::BTreeMap,
+          env,
+          panic::AssertUnwindSafe,
+          sync::atomic::{AtomicU64,
+                         Ordering},
+          time::{Duration,
+                 Instant}};
+use tokio::{task::JoinHandle,
+            time};
+
+/// The delay before the first restart of a panicked task. Each
+/// further consecutive panic doubles it, up to `MAX_RESTART_DELAY`.
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+/// Names the task that should panic the first time it runs. Only
+/// honored in debug builds, for testing restarts.
+const TEST_PANIC_TASK_ENVVAR: &str = "HAB_FEAT_TEST_PANIC_TASK";
+
+lazy_static! {
+    static ref TASKS: Mutex<BTreeMap<String, Task>> = Mutex::new(BTreeMap::new());
+}
+
+static NEXT_TASK_ID: AtomicU64 = AtomicU64::new(0);
+
+/// What to do when a task panics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Leave the task in the `Failed` state.
+    Never,
+    /// Restart the task after a delay that grows with each
+    /// consecutive panic.
+    Always,
+}
+
+/// Tasks are cancelled on shutdown one stage at a time, in the order
+/// the stages are declared here. Spec reconciliation goes first, so
+/// that nothing new is started, and the event exporter goes last so
+/// that events raised while shutting down still get published.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShutdownStage {
+    SpecReconciliation,
+    Updaters,
+    HealthChecks,
+    Census,
+    EventExporter,
+}
+
+impl ShutdownStage {
+    const ORDER: [ShutdownStage; 5] = [ShutdownStage::SpecReconciliation,
+                                       ShutdownStage::Updaters,
+                                       ShutdownStage::HealthChecks,
+                                       ShutdownStage::Census,
+                                       ShutdownStage::EventExporter];
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskState {
+    Running,
+    /// The task panicked and is waiting to be restarted.
+    Restarting,
+    /// The task panicked and will not be restarted.
+    Failed,
+}
+
+/// The health of a single task, as reported by the HTTP gateway.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TaskHealth {
+    pub name:          String,
+    pub state:         TaskState,
+    pub restart_count: u32,
+    pub last_error:    Option<String>,
+}
+
+struct Task {
+    /// Distinguishes this task from any later task spawned with the
+    /// same name.
+    id:           u64,
+    stage:        ShutdownStage,
+    health:       TaskHealth,
+    abort_handle: AbortHandle,
+    join_handle:  JoinHandle<()>,
+}
+
+/// Spawn a named task that runs the future returned by `task`,
+/// calling `task` again to restart it if it panics (and `policy`
+/// allows). A task that completes normally, or is aborted, is
+/// forgotten; one that has `Failed` remains listed until another task
+/// with the same name is spawned. Spawning a task aborts any other
+/// task with the same name, so there's never more than one running.
+///
+/// The returned handle aborts the task, including any pending restart.
+pub fn spawn<F, Fut>(name: impl Into<String>,
+                     stage: ShutdownStage,
+                     policy: RestartPolicy,
+                     task: F)
+                     -> AbortHandle
+    where F: FnMut() -> Fut + Send + 'static,
+          Fut: Future<Output = ()> + Send + 'static
+{
+    let name = name.into();
+    let id = NEXT_TASK_ID.fetch_add(1, Ordering::Relaxed);
+    let (supervised, abort_handle) = future::abortable(supervise(name.clone(), id, policy, task));
+    let health = TaskHealth { name:          name.clone(),
+                              state:         TaskState::Running,
+                              restart_count: 0,
+                              last_error:    None, };
+    // Hold the lock until the join handle is stored, so the task
+    // can't finish and remove itself before it has been added.
+    let mut tasks = TASKS.lock();
+    let task_name = name.clone();
+    let join_handle = tokio::spawn(async move {
+        supervised.await.ok();
+        finished(&task_name, id);
+    });
+    debug!("Spawned task '{}'", name);
+    let replaced = tasks.insert(name.clone(),
+                                Task { id,
+                                       stage,
+                                       health,
+                                       abort_handle: abort_handle.clone(),
+                                       join_handle });
+    if let Some(replaced) = replaced {
+        if replaced.health.state != TaskState::Failed {
+            warn!("Task '{}' was spawned again while still running; aborting the old one",
+                  name);
+        }
+        replaced.abort_handle.abort();
+    }
+    abort_handle
+}
+
+/// The health of every task, ordered by name.
+pub fn tasks() -> Vec<TaskHealth> {
+    TASKS.lock()
+         .values()
+         .map(|task| task.health.clone())
+         .collect()
+}
+
+/// Cancel all tasks, one `ShutdownStage` at a time, waiting for the
+/// tasks in each stage to be dropped before moving on to the next.
+pub async fn shutdown() {
+    for stage in ShutdownStage::ORDER.iter() {
+        let join_handles = {
+            let mut tasks = TASKS.lock();
+            let names = tasks.iter()
+                             .filter(|(_, task)| task.stage == *stage)
+                             .map(|(name, _)| name.clone())
+                             .collect::<Vec<_>>();
+            names.into_iter()
+                 .filter_map(|name| tasks.remove(&name))
+                 .map(|task| {
+                     debug!("Cancelling task '{}'", task.health.name);
+                     task.abort_handle.abort();
+                     task.join_handle
+                 })
+                 .collect::<Vec<_>>()
+        };
+        future::join_all(join_handles).await;
+    }
+}
+
+async fn supervise<F, Fut>(name: String, id: u64, policy: RestartPolicy, mut task: F)
+    where F: FnMut() -> Fut,
+          Fut: Future<Output = ()>
+{
+    let mut restart_count = 0;
+    let mut restart_delay = MIN_RESTART_DELAY;
+    loop {
+        let started_at = Instant::now();
+        let run = async {
+            if restart_count == 0 && should_inject_panic(&name) {
+                panic!("Injected panic in task '{}'", name);
+            }
+            task().await
+        };
+        let err = match AssertUnwindSafe(run).catch_unwind().await {
+            Ok(()) => return,
+            Err(panic) => panic_message(&*panic),
+        };
+        error!("Task '{}' panicked: {}", name, err);
+
+        if policy == RestartPolicy::Never {
+            update(&name, id, |health| {
+                health.state = TaskState::Failed;
+                health.last_error = Some(err);
+            });
+            return;
+        }
+
+        // A task that ran for a good while before panicking starts
+        // over with the shortest delay.
+        if started_at.elapsed() > MAX_RESTART_DELAY {
+            restart_delay = MIN_RESTART_DELAY;
+        }
+        restart_count += 1;
+        update(&name, id, |health| {
+            health.state = TaskState::Restarting;
+            health.restart_count = restart_count;
+            health.last_error = Some(err);
+        });
+        debug!("Restarting task '{}' in {}s", name, restart_delay.as_secs());
+        time::sleep(restart_delay).await;
+        restart_delay = cmp::min(restart_delay * 2, MAX_RESTART_DELAY);
+        update(&name, id, |health| health.state = TaskState::Running);
+    }
+}
+
+fn update(name: &str, id: u64, f: impl FnOnce(&mut TaskHealth)) {
+    if let Some(task) = TASKS.lock().get_mut(name).filter(|task| task.id == id) {
+        f(&mut task.health);
+    }
+}
+
+fn finished(name: &str, id: u64) {
+    let mut tasks = TASKS.lock();
+    if tasks.get(name).map_or(false, |task| {
+                          task.id == id && task.health.state != TaskState::Failed
+                      })
+    {
+        debug!("Task '{}' finished", name);
+        tasks.remove(name);
+    }
+}
+
+fn should_inject_panic(name: &str) -> bool {
+    cfg!(debug_assertions)
+    && FEATURE_FLAGS.contains(FeatureFlag::TEST_PANIC_TASK)
+    && env::var(TEST_PANIC_TASK_ENVVAR).map_or(false, |task| task == name)
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(msg) = panic.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = panic.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{atomic::AtomicU32,
+                    Arc};
+
+    fn health_of(name: &str) -> Option<TaskHealth> {
+        tasks().into_iter().find(|health| health.name == name)
+    }
+
+    #[tokio::test]
+    async fn panicked_task_is_restarted() {
+        let name = "test-panicked-task-is-restarted";
+        let runs = Arc::new(AtomicU32::new(0));
+        let task_runs = Arc::clone(&runs);
+        spawn(name,
+              ShutdownStage::Updaters,
+              RestartPolicy::Always,
+              move || {
+                  let run = task_runs.fetch_add(1, Ordering::SeqCst);
+                  async move {
+                      if run == 0 {
+                          panic!("first run");
+                      }
+                      future::pending::<()>().await
+                  }
+              });
+
+        time::sleep(MIN_RESTART_DELAY + Duration::from_millis(500)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert_eq!(health_of(name),
+                   Some(TaskHealth { name:          name.to_string(),
+                                     state:         TaskState::Running,
+                                     restart_count: 1,
+                                     last_error:    Some("first run".to_string()), }));
+    }
+
+    #[tokio::test]
+    async fn panicked_task_without_restart_policy_fails() {
+        let name = "test-panicked-task-without-restart-policy-fails";
+        spawn(name, ShutdownStage::Updaters, RestartPolicy::Never, || {
+            async { panic!("{} failed", "task") }
+        });
+
+        time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(health_of(name),
+                   Some(TaskHealth { name:          name.to_string(),
+                                     state:         TaskState::Failed,
+                                     restart_count: 0,
+                                     last_error:    Some("task failed".to_string()), }));
+    }
+
+    #[tokio::test]
+    async fn completed_and_aborted_tasks_are_forgotten() {
+        let completed = "test-completed-task-is-forgotten";
+        spawn(completed,
+              ShutdownStage::Updaters,
+              RestartPolicy::Always,
+              || async {});
+        let aborted = "test-aborted-task-is-forgotten";
+        let handle = spawn(aborted,
+                           ShutdownStage::Updaters,
+                           RestartPolicy::Always,
+                           future::pending::<()>);
+        assert!(health_of(aborted).is_some());
+
+        handle.abort();
+        time::sleep(Duration::from_millis(500)).await;
+        assert!(health_of(completed).is_none());
+        assert!(health_of(aborted).is_none());
+    }
+    #[tokio::test]
+    async fn spawning_a_task_again_replaces_the_running_one() {
+        let name = "test-spawning-a-task-again-replaces-the-running-one";
+        let first_runs = Arc::new(AtomicU32::new(0));
+        let task_runs = Arc::clone(&first_runs);
+        spawn(name,
+              ShutdownStage::Updaters,
+              RestartPolicy::Always,
+              move || {
+                  let task_runs = Arc::clone(&task_runs);
+                  async move {
+                      loop {
+                          task_runs.fetch_add(1, Ordering::SeqCst);
+                          time::sleep(Duration::from_millis(50)).await;
+                      }
+                  }
+              });
+        let second = spawn(name,
+                           ShutdownStage::Updaters,
+                           RestartPolicy::Always,
+                           future::pending::<()>);
+
+        time::sleep(Duration::from_millis(200)).await;
+        let runs = first_runs.load(Ordering::SeqCst);
+        time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(first_runs.load(Ordering::SeqCst),
+                   runs,
+                   "The first task is still running");
+        assert_eq!(tasks().iter().filter(|health| health.name == name).count(),
+                   1);
+
+        // Aborting the task that replaced it leaves nothing behind
+        second.abort();
+        time::sleep(Duration::from_millis(500)).await;
+        assert!(health_of(name).is_none());
+    }
+}
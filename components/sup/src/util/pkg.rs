@@ -17,7 +17,9 @@ use habitat_common::{cli_config::CliConfig,
                      outputln,
                      ui::{NullUi,
                           UIWriter}};
-use habitat_core::{env as henv,
+use habitat_core::{crypto::{artifact,
+                            keys::KeyCache},
+                   env as henv,
                    fs::{self,
                         FS_ROOT_PATH},
                    package::{PackageIdent,
@@ -25,6 +27,8 @@ use habitat_core::{env as henv,
                              PackageTarget},
                    ChannelIdent,
                    AUTH_TOKEN_ENVVAR};
+use habitat_sup_protocol::types::SignerVerification;
+use log::warn;
 use std::path::Path;
 
 static LOGKEY: &str = "UT";
@@ -105,6 +109,53 @@ pub fn installed<T>(ident: T) -> Option<PackageInstall>
     PackageInstall::load(ident.as_ref(), Some(fs_root_path)).ok()
 }
 
+/// Check the signer and artifact hash recorded when `package` was
+/// installed against the public signing keys in `key_cache`.
+///
+/// If the package's artifact is still present in
+/// `artifact_cache_path`, it is re-verified and its hash compared to
+/// the recorded one. Packages installed before this metadata was
+/// recorded are allowed under `SignerVerification::Permissive` and
+/// refused under `SignerVerification::Strict`.
+pub fn verify_signer(package: &PackageInstall,
+                     key_cache: &KeyCache,
+                     artifact_cache_path: &Path,
+                     policy: SignerVerification)
+                     -> Result<()> {
+    if policy == SignerVerification::Off {
+        return Ok(());
+    }
+
+    let ident = package.ident();
+    let (signer, recorded_hash) = match (package.signer()?, package.artifact_hash()?) {
+        (Some(signer), Some(hash)) => (signer, hash),
+        _ if policy == SignerVerification::Permissive => {
+            warn!("No signer metadata recorded for {}, allowing it to start",
+                  ident);
+            return Ok(());
+        }
+        _ => return Err(Error::MissingPackageSignerMetadata(ident.clone())),
+    };
+
+    if key_cache.try_public_signing_key(&signer)?.is_none() {
+        return Err(Error::UnknownPackageSigner(ident.clone(), signer.to_string()));
+    }
+
+    if let Ok(archive_name) = ident.archive_name() {
+        let artifact_path = artifact_cache_path.join(archive_name);
+        if artifact_path.is_file() {
+            let (_, hash) = artifact::verify(&artifact_path, key_cache)?;
+            if hash != recorded_hash {
+                return Err(Error::PackageArtifactHashMismatch(ident.clone(),
+                                                              recorded_hash.to_string(),
+                                                              hash.to_string()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// Install a package but only consider packages from a channel. Do not consider any locally
 /// installed packages.
 ///
@@ -0,0 +1,8 @@
+ident = "sup-integration-test/gossip-ring"
+group = "default"
+bldr_url = "http://hab.sup.test"
+channel = "unstable"
+topology = "standalone"
+update_strategy = "none"
+binds = []
+desired_state = "up"
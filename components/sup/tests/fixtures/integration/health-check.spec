@@ -0,0 +1,12 @@
+ident = "sup-integration-test/health-check"
+group = "default"
+bldr_url = "http://hab.sup.test"
+channel = "unstable"
+topology = "standalone"
+update_strategy = "none"
+binds = []
+desired_state = "up"
+
+[health_check_interval]
+secs = 1
+nanos = 0
@@ -0,0 +1,9 @@
+ident = "sup-integration-test/peer-file"
+group = "default"
+bldr_url = "http://hab.sup.test"
+channel = "unstable"
+topology = "standalone"
+update_strategy = "at-once"
+binds = []
+desired_state = "up"
+peer_file = "peers.txt"
@@ -2,13 +2,30 @@
 /// Integration tests for exercising the hook and config recompilation
 /// behavior of the Supervisor
 use crate::utils::FileSystemSnapshot;
-use anyhow::Result;
+use anyhow::{anyhow,
+             Context,
+             Result};
 use glob::Pattern;
 use habitat_core as hcore;
-use habitat_sup::manager::service::ProcessTerminationReason;
-use hcore::os::process::Pid;
+use habitat_sup::manager::service::{DesiredState,
+                                    ProcessTerminationReason,
+                                    UpdateStrategy};
+use habitat_sup_protocol::types::HealthCheckResult;
+use hcore::{crypto::{keys::{generate_service_encryption_key_pair,
+                            generate_signing_key_pair,
+                            generate_user_encryption_key_pair,
+                            Key,
+                            KeyCache,
+                            RingKey},
+                     Blake2bHash,
+                     HashedEntry},
+            os::process::Pid};
 use lazy_static::lazy_static;
-use std::time::Duration;
+use std::{path::Path,
+          time::{Duration,
+                 Instant,
+                 SystemTime,
+                 UNIX_EPOCH}};
 
 mod utils;
 
@@ -55,15 +72,20 @@ async fn test_for_restart_on_config_application(test_name: &str,
 
     test_sup.apply_config(package_name, service_group, applied_config)
             .await?;
-    let final_service = test_sup.ensure_service_restarted(initial_service.process.pid.unwrap(),
-                                                          package_name,
-                                                          service_group,
-                                                          Duration::from_secs(30))
-                                .await?;
+    let restart = test_sup.ensure_service_restarted(&initial_service,
+                                                    package_name,
+                                                    service_group,
+                                                    Duration::from_secs(30))
+                          .await?;
+    let final_service = restart.service;
 
     let final_snapshot =
         FileSystemSnapshot::new(hab_root.svc_dir_path(package_name).as_path()).await?;
     let final_pid_snapshot = final_snapshot.file("PID")?;
+    assert_eq!(final_pid_snapshot.current_file_content()
+                                 .await?
+                                 .parse::<Pid>()?,
+               restart.pid);
 
     let delta =
         final_snapshot.modifications_since(&initial_snapshot,
@@ -81,6 +103,8 @@ async fn test_for_restart_on_config_application(test_name: &str,
     // Ensure the time between restarts is less than our configured minimum restart duration
     assert!(duration_between_restarts > Duration::ZERO);
     assert!(duration_between_restarts < service_min_backoff_period);
+    // Going down and coming back up once, at most
+    assert!(restart.transitions <= 2);
     assert_eq!(delta.updated(), updated_files);
     assert_eq!(delta.added(), vec![] as Vec<&str>);
     assert_eq!(delta.removed(), vec![] as Vec<&str>);
@@ -384,6 +408,651 @@ async fn restart_backoff_for_failed_init_hook() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn gateway_shows_where_failing_services_are_in_their_backoff() -> Result<()> {
+    let hab_root = utils::HabRoot::new("gateway_shows_where_failing_services_are_in_their_backoff");
+    let min_backoff = Duration::from_secs(2);
+    let max_backoff = Duration::from_secs(10);
+
+    utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/failing")
+                                                .run_hook("#!/bin/bash\n\nexit 1\n")
+                                                .build()
+                                                .await?;
+    utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/healthy")
+                                                .build()
+                                                .await?;
+
+    let mut test_sup = utils::TestSupBuilder::new().fs_root(&hab_root)
+                                                   .random_ports()
+                                                   .min_backoff(min_backoff)
+                                                   .max_backoff(max_backoff)
+                                                   .restart_cooldown(Duration::from_secs(60))
+                                                   .build()
+                                                   .await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+
+    let healthy = test_sup.ensure_service_started("healthy", "default", Duration::from_secs(10))
+                          .await?;
+    assert_eq!(healthy.restart,
+               utils::sup_gateway_api::RestartInfo::default());
+
+    // Caught waiting to be restarted after failing again
+    let waiting = |service: &utils::sup_gateway_api::ServiceInfo| {
+        service.restart.consecutive_failures >= 2 && service.restart.next_attempt_at > 0
+    };
+    let failing =
+        test_sup.wait_for_service_condition("failing", "default", waiting, Duration::from_secs(60))
+                .await?;
+    let backoff = failing.restart.backoff_period;
+    assert!(backoff >= min_backoff && backoff <= max_backoff,
+            "Backoff of {:?} is outside the configured bounds",
+            backoff);
+    assert_eq!(failing.restart.consecutive_failures, failing.restart_count);
+    assert!(!failing.restart.cooldown_reset);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    assert!(failing.restart.next_attempt_at <= now + max_backoff.as_secs());
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn failing_services_keep_their_backoff_across_supervisor_restarts() -> Result<()> {
+    let hab_root =
+        utils::HabRoot::new("failing_services_keep_their_backoff_across_supervisor_restarts");
+    let timeout = Duration::from_secs(60);
+
+    utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/failing")
+                                                .run_hook("#!/bin/bash\n\nexit 1\n")
+                                                .build()
+                                                .await?;
+
+    let mut test_sup = utils::TestSupBuilder::new().fs_root(&hab_root)
+                                                   .random_ports()
+                                                   .min_backoff(Duration::from_secs(1))
+                                                   .max_backoff(Duration::from_secs(5))
+                                                   .restart_cooldown(Duration::from_secs(300))
+                                                   .build()
+                                                   .await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+
+    let failing =
+        test_sup.wait_for_service_condition("failing",
+                                            "default",
+                                            |service| service.restart.consecutive_failures >= 2,
+                                            timeout)
+                .await?;
+    let failures_before_restart = failing.restart.consecutive_failures;
+
+    test_sup.restart(Duration::from_secs(10)).await?;
+
+    // A fresh backoff would start counting from one again
+    let failing = test_sup.wait_for_service_condition("failing",
+                                                      "default",
+                                                      |service| {
+                                                          service.restart.consecutive_failures
+                                                          > failures_before_restart
+                                                      },
+                                                      timeout)
+                          .await?;
+    assert!(failing.restart_count < failing.restart.consecutive_failures,
+            "Failures from before the restart should still be counted");
+    assert!(!failing.restart.cooldown_reset);
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn services_can_override_the_supervisors_restart_backoff() -> Result<()> {
+    let hab_root = utils::HabRoot::new("services_can_override_the_supervisors_restart_backoff");
+    let timeout = Duration::from_secs(60);
+
+    for ident in &["sup-integration-test/failing",
+                   "sup-integration-test/failing-slowly"]
+    {
+        utils::FixturePackageBuilder::new(&hab_root).ident(*ident)
+                                                    .run_hook("#!/bin/bash\n\nexit 1\n")
+                                                    .build()
+                                                    .await?;
+    }
+    let mut spec = hab_root.read_spec("default", "failing-slowly")?;
+    spec.min_backoff_period = Some(30);
+    spec.max_backoff_period = Some(60);
+    hab_root.write_spec("default", &spec)?;
+
+    let mut test_sup = utils::TestSupBuilder::new().fs_root(&hab_root)
+                                                   .random_ports()
+                                                   .min_backoff(Duration::from_secs(1))
+                                                   .max_backoff(Duration::from_secs(2))
+                                                   .restart_cooldown(Duration::from_secs(60))
+                                                   .build()
+                                                   .await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+
+    let failing =
+        test_sup.wait_for_service_condition("failing",
+                                            "default",
+                                            |service| service.restart.consecutive_failures >= 3,
+                                            timeout)
+                .await?;
+    let sources = failing.restart_sources
+                         .expect("restart_config_sources is missing");
+    assert_eq!(sources.min_backoff_period, "supervisor");
+    assert_eq!(sources.max_backoff_period, "supervisor");
+
+    // Failing just as often, but waiting far longer between restarts
+    let slow = test_sup.service_info("failing-slowly", "default")
+                       .await?
+                       .ok_or_else(|| anyhow!("failing-slowly.default isn't loaded"))?;
+    assert!(slow.restart.consecutive_failures < 2,
+            "Restarted {} times despite its own backoff",
+            slow.restart.consecutive_failures);
+    let config = slow.restart_config.expect("restart_config is missing");
+    assert_eq!(config.min_backoff_period, Duration::from_secs(30));
+    assert_eq!(config.max_backoff_period, Duration::from_secs(60));
+    assert_eq!(config.cooldown_period, Duration::from_secs(60));
+    let sources = slow.restart_sources
+                      .expect("restart_config_sources is missing");
+    assert_eq!(sources.min_backoff_period, "spec");
+    assert_eq!(sources.max_backoff_period, "spec");
+    assert_eq!(sources.cooldown_period, "supervisor");
+
+    // Changes to the spec apply without restarting the Supervisor
+    spec.min_backoff_period = None;
+    spec.max_backoff_period = None;
+    spec.cooldown_period = Some(120);
+    hab_root.write_spec("default", &spec)?;
+    let slow = test_sup.wait_for_service_condition("failing-slowly",
+                                                   "default",
+                                                   |service| {
+                                                       service.restart_sources
+                                                              .as_ref()
+                                                              .map_or(false, |s| {
+                                                                  s.cooldown_period == "spec"
+                                                              })
+                                                   },
+                                                   Duration::from_secs(20))
+                       .await?;
+    let config = slow.restart_config.expect("restart_config is missing");
+    assert_eq!(config.min_backoff_period, Duration::from_secs(1));
+    assert_eq!(config.max_backoff_period, Duration::from_secs(2));
+    assert_eq!(config.cooldown_period, Duration::from_secs(120));
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn supervisor_is_ready_once_its_services_have_started() -> Result<()> {
+    let hab_root = utils::HabRoot::new("supervisor_is_ready_once_its_services_have_started");
+    let timeout = Duration::from_secs(30);
+
+    utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/healthy")
+                                                .build()
+                                                .await?;
+    utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/failing")
+                                                .run_hook("#!/bin/bash\n\nexit 1\n")
+                                                .build()
+                                                .await?;
+    // Held up in its init hook until the test lets it go
+    let init_hook = r#"#!/bin/bash
+
+while [ ! -f "{{pkg.svc_data_path}}/go" ]; do
+    sleep 1
+done
+"#;
+    utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/slow-start")
+                                                .init_hook(init_hook)
+                                                .build()
+                                                .await?;
+    utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/stopped")
+                                                .build()
+                                                .await?;
+    let mut spec = hab_root.read_spec("default", "stopped")?;
+    spec.desired_state = DesiredState::Down;
+    hab_root.write_spec("default", &spec)?;
+
+    let mut test_sup = utils::TestSupBuilder::new().fs_root(&hab_root)
+                                                   .random_ports()
+                                                   .min_backoff(Duration::from_secs(1))
+                                                   .max_backoff(Duration::from_secs(2))
+                                                   .build()
+                                                   .await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+
+    // Services that came up, or failed to, aren't waited on; nor are
+    // those that aren't meant to be up.
+    let readiness = test_sup.wait_for_readiness(|readiness| {
+                                                    readiness.specs_reconciled
+                                                    && readiness.not_ready
+                                                       == vec!["slow-start.default"]
+                                                },
+                                                timeout)
+                            .await?;
+    assert!(!readiness.ready);
+
+    std::fs::write(hab_root.svc_dir_path("slow-start").join("data").join("go"),
+                   "")?;
+    let readiness = test_sup.await_ready(timeout).await?;
+    assert!(readiness.not_ready.is_empty());
+    let slow_start = test_sup.ensure_service_started("slow-start", "default", timeout)
+                             .await?;
+    assert!(slow_start.is_running());
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn test_supervisors_can_be_started_once_ready() -> Result<()> {
+    let hab_root = utils::HabRoot::new("test_supervisors_can_be_started_once_ready");
+
+    utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/healthy")
+                                                .build()
+                                                .await?;
+
+    let mut test_sup = utils::TestSupBuilder::new().fs_root(&hab_root)
+                                                   .random_ports()
+                                                   .wait_for_ready()
+                                                   .build()
+                                                   .await?;
+    test_sup.start(Duration::from_secs(30)).await?;
+
+    // Up already, without having to wait for it
+    let healthy = test_sup.service_info("healthy", "default")
+                          .await?
+                          .ok_or_else(|| anyhow!("healthy.default isn't loaded"))?;
+    assert!(healthy.is_running(), "{:?}", healthy);
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn gateway_streams_service_lifecycle_events() -> Result<()> {
+    let hab_root = utils::HabRoot::new("gateway_streams_service_lifecycle_events");
+    let token = "event-stream-token";
+    let timeout = Duration::from_secs(30);
+
+    let ident =
+        utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/event-stream")
+                                                    .health_check_hook("#!/bin/bash\n\nexit 0\n")
+                                                    .build()
+                                                    .await?;
+    utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/failing")
+                                                .run_hook("#!/bin/bash\n\nexit 1\n")
+                                                .build()
+                                                .await?;
+
+    let mut test_sup = utils::TestSupBuilder::new().fs_root(&hab_root)
+                                                   .random_ports()
+                                                   .min_backoff(Duration::from_secs(1))
+                                                   .max_backoff(Duration::from_secs(2))
+                                                   .restart_cooldown(Duration::from_secs(60))
+                                                   .env("HAB_SUP_GATEWAY_AUTH_TOKEN", token)
+                                                   .build()
+                                                   .await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    test_sup.ensure_service_started("event-stream", "default", timeout)
+            .await?;
+    test_sup.wait_for_health("event-stream", "default", utils::HealthCheck::Ok, timeout)
+            .await?;
+
+    // Like every other endpoint, events are only streamed to those
+    // with the token.
+    let unauthenticated = test_sup.api_client
+                                  .get(test_sup.gateway_url("/events"))
+                                  .send()
+                                  .await?;
+    assert_eq!(unauthenticated.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let mut events = test_sup.subscribe_events().await?;
+    let snapshot = events.wait_for(|e| e.is("snapshot", "event-stream.default"), timeout)
+                         .await?;
+    assert_eq!(snapshot.state, "up");
+    assert_eq!(snapshot.health.as_deref(), Some("Ok"));
+    // Whether it's caught restarting in the snapshot, or afterwards
+    events.wait_for(|e| e.service_group == "failing.default" && e.state == "restarting",
+                    timeout)
+          .await?;
+
+    test_sup.svc_stop(&ident).await?;
+    let down = events.wait_for(|e| e.is("down", "event-stream.default"), timeout)
+                     .await?;
+    assert_eq!(down.state, "down");
+
+    test_sup.svc_start(&ident).await?;
+    let up = events.wait_for(|e| e.is("up", "event-stream.default"), timeout)
+                   .await?;
+    assert_eq!(up.state, "up");
+    assert!(up.timestamp >= down.timestamp);
+    let health = events.wait_for(|e| e.is("health_check", "event-stream.default"), timeout)
+                       .await?;
+    assert_eq!(health.health.as_deref(), Some("Ok"));
+
+    test_sup.svc_unload(&ident).await?;
+    let unloaded = events.wait_for(|e| e.is("unloaded", "event-stream.default"), timeout)
+                         .await?;
+    assert_eq!(unloaded.state, "unloaded");
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn gateway_filters_and_pages_services() -> Result<()> {
+    let hab_root = utils::HabRoot::new("gateway_filters_and_pages_services");
+    let timeout = Duration::from_secs(20);
+
+    utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/alpha")
+                                                .build()
+                                                .await?;
+    utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/beta")
+                                                .service_group("canary")
+                                                .build()
+                                                .await?;
+    let gamma = utils::FixturePackageBuilder::new(&hab_root).ident("other-origin/gamma")
+                                                            .build()
+                                                            .await?;
+
+    let mut test_sup = utils::TestSupBuilder::new().fs_root(&hab_root)
+                                                   .random_ports()
+                                                   .build()
+                                                   .await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    test_sup.ensure_service_started("alpha", "default", timeout)
+            .await?;
+    test_sup.ensure_service_started("beta", "canary", timeout)
+            .await?;
+    test_sup.ensure_service_started("gamma", "default", timeout)
+            .await?;
+    test_sup.svc_stop(&gamma).await?;
+    test_sup.ensure_service_stopped("gamma", "default", timeout)
+            .await?;
+
+    let service_groups = |page: &utils::sup_gateway_api::ServicesPage| {
+        page.services
+            .iter()
+            .map(|service| service.service_group.clone())
+            .collect::<Vec<_>>()
+    };
+
+    let page = test_sup.services_matching("origin=other-origin").await?;
+    assert_eq!(page.total, 1);
+    assert_eq!(service_groups(&page), vec!["gamma.default"]);
+
+    let page = test_sup.services_matching("service_group=canary").await?;
+    assert_eq!(page.total, 1);
+    assert_eq!(service_groups(&page), vec!["beta.canary"]);
+
+    let page = test_sup.services_matching("state=down").await?;
+    assert_eq!(service_groups(&page), vec!["gamma.default"]);
+    let page = test_sup.services_matching("state=up").await?;
+    assert_eq!(service_groups(&page), vec!["alpha.default", "beta.canary"]);
+
+    let first = test_sup.services_matching("origin=sup-integration-test&limit=1")
+                        .await?;
+    let second = test_sup.services_matching("origin=sup-integration-test&limit=1&offset=1")
+                         .await?;
+    assert_eq!((first.total, second.total), (2, 2));
+    assert_eq!(service_groups(&first), vec!["alpha.default"]);
+    assert_eq!(service_groups(&second), vec!["beta.canary"]);
+
+    let err = test_sup.services_matching("name=alpha")
+                      .await
+                      .expect_err("Unknown query parameters should be rejected");
+    assert!(err.to_string().contains("400"), "{}", err);
+    assert!(err.to_string()
+               .contains("origin, service_group, state, limit, offset"),
+            "{}",
+            err);
+    assert!(test_sup.services_matching("limit=lots").await.is_err());
+
+    // Unfiltered, every service is still there
+    assert_eq!(test_sup.services().await?.len(), 3);
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn splayed_health_checks_are_spread_out() -> Result<()> {
+    let hab_root = utils::HabRoot::new("splayed_health_checks_are_spread_out");
+    let timeout = Duration::from_secs(30);
+    let interval = Duration::from_secs(10);
+    // Their first checks are put off by about 3.1, 8.3 and 1.8 secs.
+    let package_names = ["splay-one", "splay-two", "splay-three"];
+
+    for package_name in &package_names {
+        utils::FixturePackageBuilder::new(&hab_root).ident(&format!("sup-integration-test/{}",
+                                                                    package_name))
+                                                    .health_check_hook("#!/bin/bash\n\nexit 0\n")
+                                                    .build()
+                                                    .await?;
+        let mut spec = hab_root.read_spec("default", package_name)?;
+        spec.health_check_interval = interval.into();
+        spec.health_check_splay = Some(0);
+        hab_root.write_spec("default", &spec)?;
+    }
+
+    let mut test_sup = utils::TestSupBuilder::new().fs_root(&hab_root)
+                                                   .random_ports()
+                                                   .build()
+                                                   .await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+
+    let mut first_checks = Vec::new();
+    for package_name in &package_names {
+        let service = test_sup.wait_for_service_condition(package_name,
+                                                          "default",
+                                                          |s| s.health_check_splay.is_some(),
+                                                          timeout)
+                              .await?;
+        let splay = service.health_check_splay
+                           .expect("the service to be splayed");
+        assert!(splay.first_check_offset < interval, "{:?}", splay);
+        assert_eq!(splay.jitter, Duration::default());
+
+        let started_at = Instant::now();
+        let first_check = loop {
+            let history = test_sup.health_history(package_name, "default").await?;
+            if let Some(first_check) = history.first() {
+                break first_check.timestamp;
+            }
+            assert!(started_at.elapsed() < timeout,
+                    "{} was never health checked",
+                    package_name);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        };
+        first_checks.push(first_check);
+    }
+
+    // The services all started at about the same time, but weren't
+    // all checked then.
+    first_checks.sort_unstable();
+    assert!(first_checks.windows(2).all(|pair| pair[1] - pair[0] >= 500),
+            "Health checks were run at {:?}",
+            first_checks);
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn gateway_keeps_health_check_history_through_restarts() -> Result<()> {
+    let hab_root = utils::HabRoot::new("gateway_keeps_health_check_history_through_restarts");
+    let timeout = Duration::from_secs(30);
+
+    // Every other check fails, numbering each one, and counting on
+    // from where it left off after the service is restarted.
+    let health_check_hook = r#"#!/bin/bash
+
+count_file="{{pkg.svc_data_path}}/checks"
+count=$(( $(cat "$count_file" 2>/dev/null || echo 0) + 1 ))
+echo "$count" > "$count_file"
+echo "check $count"
+exit $(( count % 2 == 1 ? 0 : 2 ))
+"#;
+    let ident = utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/\
+                                                                    health-history")
+                                                            .health_check_hook(health_check_hook)
+                                                            .build()
+                                                            .await?;
+    let mut spec = hab_root.read_spec("default", "health-history")?;
+    spec.health_check_interval = 1_u64.into();
+    hab_root.write_spec("default", &spec)?;
+
+    let mut test_sup = utils::TestSupBuilder::new().fs_root(&hab_root)
+                                                   .random_ports()
+                                                   .build()
+                                                   .await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    test_sup.ensure_service_started("health-history", "default", timeout)
+            .await?;
+    test_sup.wait_for_health("health-history",
+                             "default",
+                             utils::HealthCheck::Critical,
+                             timeout)
+            .await?;
+
+    test_sup.svc_stop(&ident).await?.into_result()?;
+    test_sup.ensure_service_stopped("health-history", "default", timeout)
+            .await?;
+    let before_restart = test_sup.health_history("health-history", "default").await?;
+    assert!(before_restart.len() >= 2, "{:?}", before_restart);
+
+    test_sup.svc_start(&ident).await?.into_result()?;
+    test_sup.ensure_service_started("health-history", "default", timeout)
+            .await?;
+    let started_at = Instant::now();
+    let history = loop {
+        let history = test_sup.health_history("health-history", "default").await?;
+        if history.len() >= before_restart.len() + 2 {
+            break history;
+        }
+        assert!(started_at.elapsed() < timeout,
+                "No health checks were recorded after the restart: {:?}",
+                history);
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    };
+
+    // A check cut short by the service stopping goes unrecorded, so
+    // the checks are only known to be in order, not unbroken.
+    assert!(history.starts_with(&before_restart), "{:?}", history);
+    let mut last_check = 0;
+    for record in &history {
+        let check = record.stdout
+                          .trim()
+                          .strip_prefix("check ")
+                          .and_then(|check| check.parse::<u32>().ok())
+                          .ok_or_else(|| anyhow!("Unexpected health check output: {:?}", record))?;
+        assert!(check > last_check, "{:?}", history);
+        let (status, exit_code) = if check % 2 == 1 {
+            (utils::HealthCheck::Ok, 0)
+        } else {
+            (utils::HealthCheck::Critical, 2)
+        };
+        assert_eq!(record.status, status, "{:?}", history);
+        assert_eq!(record.exit_code, Some(exit_code), "{:?}", history);
+        last_check = check;
+    }
+    assert_eq!(history[0].stdout, "check 1\n");
+    assert!(history.windows(2)
+                   .all(|pair| pair[0].timestamp <= pair[1].timestamp),
+            "{:?}",
+            history);
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn health_checks_can_be_run_on_demand() -> Result<()> {
+    let hab_root = utils::HabRoot::new("health_checks_can_be_run_on_demand");
+    let timeout = Duration::from_secs(30);
+
+    // Healthy only while the `healthy` file exists, noting each run.
+    let health_check_hook = r#"#!/bin/bash
+
+echo "check" >> "{{pkg.svc_data_path}}/checks"
+sleep 1
+test -f "{{pkg.svc_data_path}}/healthy" || exit 2
+"#;
+    utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/health-on-demand")
+                                                .health_check_hook(health_check_hook)
+                                                .build()
+                                                .await?;
+    // Far longer than the test runs, so the only scheduled checks are
+    // the first one and, since it fails, a retry 30 seconds later.
+    let mut spec = hab_root.read_spec("default", "health-on-demand")?;
+    spec.health_check_interval = 3600_u64.into();
+    hab_root.write_spec("default", &spec)?;
+    let data_path = hab_root.svc_dir_path("health-on-demand").join("data");
+    let checks_run = || -> Result<usize> {
+        Ok(std::fs::read_to_string(data_path.join("checks"))?.lines()
+                                                             .count())
+    };
+
+    let mut test_sup = utils::TestSupBuilder::new().fs_root(&hab_root)
+                                                   .random_ports()
+                                                   .build()
+                                                   .await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    test_sup.ensure_service_started("health-on-demand", "default", timeout)
+            .await?;
+    test_sup.wait_for_health("health-on-demand",
+                             "default",
+                             utils::HealthCheck::Critical,
+                             timeout)
+            .await?;
+
+    // Checks asked for at the same time share a single run of the
+    // hook.
+    std::fs::write(data_path.join("healthy"), "")?;
+    let checks_before = checks_run()?;
+    let (first, second) = tokio::join!(test_sup.svc_health_check("health-on-demand", "default"),
+                                       test_sup.svc_health_check("health-on-demand", "default"));
+    let (first, second) = (first?, second?);
+    assert_eq!(checks_run()?, checks_before + 1);
+    assert_eq!(first, second);
+    assert_eq!(first.status(), HealthCheckResult::Ok);
+    assert_eq!(first.exit_code, Some(0));
+    assert!(first.duration_ms.unwrap_or_default() >= 1000, "{:?}", first);
+    // The result is kept just as a scheduled check's would be.
+    test_sup.wait_for_health("health-on-demand",
+                             "default",
+                             utils::HealthCheck::Ok,
+                             Duration::from_secs(1))
+            .await?;
+
+    std::fs::remove_file(data_path.join("healthy"))?;
+    let check = test_sup.svc_health_check("health-on-demand", "default")
+                        .await?;
+    assert_eq!(check.status(), HealthCheckResult::Critical);
+    assert_eq!(check.exit_code, Some(2));
+
+    let err = test_sup.svc_health_check("health-on-demand", "nonexistent")
+                      .await
+                      .expect_err("Checking a service that isn't loaded should fail");
+    assert!(format!("{:#}", err).contains("Service not loaded"),
+            "{:#}",
+            err);
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
 #[tokio::test]
 #[cfg_attr(feature = "ignore_integration_tests", ignore)]
 async fn restart_backoff_for_failed_run_hook() -> Result<()> {
@@ -477,22 +1146,23 @@ async fn restart_backoff_for_failed_run_hook() -> Result<()> {
     tokio::time::sleep(service_min_backoff_period.mul_f32(0.5)).await;
 
     // Make the application succeed again
+    let failing_service =
+        test_sup.get_service_state(package_name, service_group, Duration::from_secs(5))
+                .await?;
     let applied_config = r#"
     run_exit_code = 0
     run_sleep = 120
     "#;
     test_sup.apply_config(package_name, service_group, applied_config)
             .await?;
-    let pid = initial_pid_snapshot.current_file_content()
-                                  .await?
-                                  .parse::<Pid>()?;
 
     // Ensure the service restarts after config applicaiton
-    let initial_service = test_sup.ensure_service_restarted(pid,
+    let initial_service = test_sup.ensure_service_restarted(&failing_service,
                                                             package_name,
                                                             service_group,
                                                             Duration::from_secs(30))
-                                  .await?;
+                                  .await?
+                                  .service;
 
     // Ensure the service never restarts again
     let final_service =
@@ -907,3 +1577,2223 @@ async fn package_with_failing_install_hook_in_dependency_is_not_loaded() -> Resu
     test_sup.stop().await?;
     Ok(())
 }
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn package_with_unknown_signer_is_not_loaded() -> Result<()> {
+    let hab_root = utils::HabRoot::new("package_with_unknown_signer_is_not_loaded");
+
+    let service_min_backoff_period = Duration::from_secs(10);
+    let service_max_backoff_period = Duration::from_secs(30);
+    let service_restart_cooldown_period = Duration::from_secs(60);
+
+    let origin_name = "sup-integration-test";
+    let package_name = "unknown-signer";
+    let service_group = "default";
+
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               &hab_root).await?;
+
+    // Record a signer whose public key is not in the Supervisor's key
+    // cache; the spec file asks for strict signer verification.
+    let unknown_signer = "sup-integration-test-20160810182414";
+    utils::write_metafile(hab_root.signer_path(origin_name, package_name),
+                          unknown_signer).await?;
+    utils::write_metafile(hab_root.artifact_hash_path(origin_name, package_name),
+                          &Blake2bHash::from_bytes(package_name).to_string()).await?;
+
+    let mut test_sup =
+        utils::TestSup::new_with_random_ports(&hab_root,
+                                              service_min_backoff_period,
+                                              service_max_backoff_period,
+                                              service_restart_cooldown_period).await?;
+
+    test_sup.start(Duration::from_secs(10)).await?;
+
+    let error = test_sup.ensure_service_has_load_error(&format!("{}/{}",
+                                                                origin_name, package_name),
+                                                       Duration::from_secs(10))
+                        .await?;
+
+    assert_eq!(error,
+               format!("Package {} was signed by {}, which is not a public signing key in the \
+                        key cache",
+                       hab_root.pkg_ident(origin_name, package_name),
+                       unknown_signer));
+    assert!(test_sup.service_info(package_name, service_group)
+                    .await?
+                    .is_none());
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+/// Wait until the peer file at `path` lists exactly `expected_members`
+/// members, one per line, returning its contents.
+async fn await_peer_file_members(path: &Path,
+                                 expected_members: usize,
+                                 timeout: Duration)
+                                 -> Result<String> {
+    let started_at = Instant::now();
+    loop {
+        let contents = tokio::fs::read_to_string(path).await.unwrap_or_default();
+        if contents.lines().count() == expected_members {
+            return Ok(contents);
+        }
+        if started_at.elapsed() > timeout {
+            return Err(anyhow!("Peer file {} did not list {} members within \
+                                {:.2} secs; contents: {:?}",
+                               path.display(),
+                               expected_members,
+                               timeout.as_secs_f64(),
+                               contents));
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn peer_file_tracks_alive_members() -> Result<()> {
+    let hab_root_a = utils::HabRoot::new("peer_file_tracks_alive_members_a");
+    let hab_root_b = utils::HabRoot::new("peer_file_tracks_alive_members_b");
+
+    let service_min_backoff_period = Duration::from_secs(10);
+    let service_max_backoff_period = Duration::from_secs(30);
+    let service_restart_cooldown_period = Duration::from_secs(60);
+
+    let origin_name = "sup-integration-test";
+    let package_name = "peer-file";
+    let service_group = "default";
+
+    for hab_root in &[&hab_root_a, &hab_root_b] {
+        utils::setup_package_files(origin_name,
+                                   package_name,
+                                   service_group,
+                                   &FIXTURE_ROOT,
+                                   hab_root).await?;
+    }
+
+    let mut test_sup_a =
+        utils::TestSup::new_with_random_ports(&hab_root_a,
+                                              service_min_backoff_period,
+                                              service_max_backoff_period,
+                                              service_restart_cooldown_period).await?;
+    test_sup_a.env("HAB_PEER_FILE_MIN_INTERVAL_SECS", "1")?;
+    test_sup_a.start(Duration::from_secs(10)).await?;
+
+    let mut test_sup_b =
+        utils::TestSup::new_with_random_ports(&hab_root_b,
+                                              service_min_backoff_period,
+                                              service_max_backoff_period,
+                                              service_restart_cooldown_period).await?;
+    test_sup_b.arg("--peer")?
+              .arg(format!("127.0.0.1:{}", test_sup_a.butterfly_port))?;
+    test_sup_b.start(Duration::from_secs(10)).await?;
+
+    test_sup_a.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+              .await?;
+    test_sup_b.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+              .await?;
+
+    let peer_file = hab_root_a.svc_dir_path(package_name)
+                              .join("files")
+                              .join("peers.txt");
+    await_peer_file_members(&peer_file, 2, Duration::from_secs(30)).await?;
+
+    // Once the second Supervisor goes away, it should drop out of the
+    // first Supervisor's peer file.
+    test_sup_b.stop().await?;
+    await_peer_file_members(&peer_file, 1, Duration::from_secs(60)).await?;
+
+    test_sup_a.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn panicked_task_is_restarted() -> Result<()> {
+    let hab_root = utils::HabRoot::new("panicked_task_is_restarted");
+
+    let service_min_backoff_period = Duration::from_secs(10);
+    let service_max_backoff_period = Duration::from_secs(30);
+    let service_restart_cooldown_period = Duration::from_secs(60);
+
+    let origin_name = "sup-integration-test";
+    let package_name = "task-restart";
+    let service_group = "default";
+
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               &hab_root).await?;
+
+    let mut test_sup =
+        utils::TestSup::new_with_random_ports(&hab_root,
+                                              service_min_backoff_period,
+                                              service_max_backoff_period,
+                                              service_restart_cooldown_period).await?;
+    // Panic the service's health check task the first time it runs.
+    let task_name = format!("health-check/{}.{}", package_name, service_group);
+    test_sup.env("HAB_FEAT_TEST_PANIC_TASK", &task_name)?;
+    test_sup.start(Duration::from_secs(10)).await?;
+
+    test_sup.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+            .await?;
+    let task = test_sup.ensure_task_restarted(&task_name, Duration::from_secs(10))
+                       .await?;
+
+    assert_eq!(task.restart_count, 1);
+    assert_eq!(task.last_error,
+               Some(format!("Injected panic in task '{}'", task_name)));
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn spec_reconciliation_survives_a_panic() -> Result<()> {
+    let hab_root = utils::HabRoot::new("spec_reconciliation_survives_a_panic");
+
+    let mut test_sup =
+        utils::TestSupBuilder::new().fs_root(&hab_root)
+                                    .random_ports()
+                                    .env("HAB_FEAT_TEST_PANIC_TASK", "spec-reconciliation")
+                                    .build()
+                                    .await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    test_sup.ensure_task_restarted("spec-reconciliation", Duration::from_secs(10))
+            .await?;
+
+    // A service loaded once the task has been restarted still starts
+    utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/healthy")
+                                                .build()
+                                                .await?;
+    test_sup.ensure_service_started("healthy", "default", Duration::from_secs(10))
+            .await?;
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn census_updates_survive_a_panic() -> Result<()> {
+    let hab_root = utils::HabRoot::new("census_updates_survive_a_panic");
+
+    utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/healthy")
+                                                .build()
+                                                .await?;
+    let mut test_sup = utils::TestSupBuilder::new().fs_root(&hab_root)
+                                                   .random_ports()
+                                                   .env("HAB_FEAT_TEST_PANIC_TASK", "census")
+                                                   .build()
+                                                   .await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    test_sup.ensure_service_started("healthy", "default", Duration::from_secs(10))
+            .await?;
+    test_sup.ensure_task_restarted("census", Duration::from_secs(10))
+            .await?;
+
+    // Configuration applied once the task has been restarted still
+    // makes it into the census
+    test_sup.apply_config_and_wait("healthy",
+                                   "default",
+                                   "greeting = \"hello\"",
+                                   Duration::from_secs(10))
+            .await?;
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn svc_update_restarts_only_when_needed() -> Result<()> {
+    let hab_root = utils::HabRoot::new("svc_update_restarts_only_when_needed");
+
+    let service_min_backoff_period = Duration::from_secs(10);
+    let service_max_backoff_period = Duration::from_secs(30);
+    let service_restart_cooldown_period = Duration::from_secs(60);
+
+    let origin_name = "sup-integration-test";
+    let package_name = "svc-update";
+    let service_group = "default";
+
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               &hab_root).await?;
+
+    let mut test_sup =
+        utils::TestSup::new_with_random_ports(&hab_root,
+                                              service_min_backoff_period,
+                                              service_max_backoff_period,
+                                              service_restart_cooldown_period).await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+
+    let service =
+        test_sup.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+                .await?;
+    let pid = service.process.pid.expect("Service should have a PID");
+
+    // Changing the channel only restarts the updater, not the service.
+    test_sup.service_update(origin_name, package_name, &["--channel", "stable"])
+            .await?;
+    let service = test_sup.ensure_service_has_not_stopped_or_restarted(pid,
+                                                                       package_name,
+                                                                       service_group,
+                                                                       Duration::from_secs(5))
+                          .await?;
+    assert_eq!(service.channel, "stable");
+
+    // Changing the health check interval restarts the service.
+    test_sup.service_update(origin_name,
+                            package_name,
+                            &["--health-check-interval", "60"])
+            .await?;
+    test_sup.ensure_service_restarted(&service,
+                                      package_name,
+                                      service_group,
+                                      Duration::from_secs(10))
+            .await?;
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn converge_loads_and_updates_services() -> Result<()> {
+    let hab_root = utils::HabRoot::new("converge_loads_and_updates_services");
+
+    let service_min_backoff_period = Duration::from_secs(10);
+    let service_max_backoff_period = Duration::from_secs(30);
+    let service_restart_cooldown_period = Duration::from_secs(60);
+
+    let origin_name = "sup-integration-test";
+    let loaded_package_name = "converge";
+    let new_package_name = "converge-new";
+    let service_group = "default";
+
+    for package_name in &[loaded_package_name, new_package_name] {
+        utils::setup_package_files(origin_name,
+                                   package_name,
+                                   service_group,
+                                   &FIXTURE_ROOT,
+                                   &hab_root).await?;
+    }
+    // Install the new package without loading it.
+    std::fs::remove_file(hab_root.spec_path(new_package_name))?;
+
+    let desired_state = hab_root.as_ref().join("desired-state.toml");
+    std::fs::write(&desired_state,
+                   format!("[[services]]\nident = \"{origin}/{loaded}\"\nchannel = \
+                            \"stable\"\n\n[[services]]\nident = \"{origin}/{new}\"\n",
+                           origin = origin_name,
+                           loaded = loaded_package_name,
+                           new = new_package_name))?;
+
+    let mut test_sup =
+        utils::TestSup::new_with_random_ports(&hab_root,
+                                              service_min_backoff_period,
+                                              service_max_backoff_period,
+                                              service_restart_cooldown_period).await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+
+    let service = test_sup.ensure_service_started(loaded_package_name,
+                                                  service_group,
+                                                  Duration::from_secs(10))
+                          .await?;
+    let pid = service.process.pid.expect("Service should have a PID");
+
+    // A dry run reports the plan without applying it.
+    let plan = test_sup.converge(&desired_state, true).await?;
+    assert!(plan.contains(&format!("load {}/{}", origin_name, new_package_name)),
+            "Plan should load the new service:\n{}",
+            plan);
+    assert!(plan.contains("channel unstable -> stable"),
+            "Plan should update the loaded service's channel:\n{}",
+            plan);
+    assert!(!plan.contains("applied"),
+            "Dry run should not apply:\n{}",
+            plan);
+
+    let plan = test_sup.converge(&desired_state, false).await?;
+    assert!(!plan.contains("failed"),
+            "Every action should apply:\n{}",
+            plan);
+    test_sup.ensure_service_started(new_package_name, service_group, Duration::from_secs(10))
+            .await?;
+    let service = test_sup.ensure_service_has_not_stopped_or_restarted(pid,
+                                                                       loaded_package_name,
+                                                                       service_group,
+                                                                       Duration::from_secs(5))
+                          .await?;
+    assert_eq!(service.channel, "stable");
+
+    // Converging again has nothing left to do.
+    let plan = test_sup.converge(&desired_state, false).await?;
+    assert!(plan.contains("Already converged"),
+            "Unexpected plan:\n{}",
+            plan);
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn converge_installs_the_package_a_service_is_updated_to() -> Result<()> {
+    let hab_root = utils::HabRoot::new("converge_installs_the_package_a_service_is_updated_to");
+
+    let origin_name = "sup-integration-test";
+    let package_name = "converge-update";
+    let service_group = "default";
+    let init_hook = "#!/bin/bash\n\necho \"Initialized {{pkg.version}}\"\n";
+
+    utils::FixturePackageBuilder::new(&hab_root).ident(format!("{}/{}", origin_name, package_name))
+                                                .init_hook(init_hook)
+                                                .build()
+                                                .await?;
+
+    // The package the service is updated to is only in the artifact
+    // cache, so converging has to install it.
+    let (public, secret) = generate_signing_key_pair(&origin_name.parse()?);
+    let key_cache = KeyCache::new(hcore::fs::cache_key_path(&hab_root));
+    key_cache.setup()?;
+    key_cache.write_key(&public)?;
+    let updated =
+        utils::FixturePackageBuilder::new(&hab_root).ident(format!("{}/{}/2.0.0/20230101000000",
+                                                                   origin_name, package_name))
+                                                    .init_hook(init_hook)
+                                                    .build_artifact(&secret)
+                                                    .await?;
+    assert!(!hab_root.installed_pkg_dir_path(&updated).exists());
+
+    let desired_state = hab_root.as_ref().join("desired-state.toml");
+    std::fs::write(&desired_state,
+                   format!("[[services]]\nident = \"{}\"\n", updated))?;
+
+    let mut test_sup = utils::TestSupBuilder::new().fs_root(&hab_root)
+                                                   .random_ports()
+                                                   .build()
+                                                   .await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+
+    let service =
+        test_sup.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+                .await?;
+    hab_root.wait_for_hook_output_containing(package_name,
+                                             "init",
+                                             "Initialized 1.0.0",
+                                             Duration::from_secs(10))
+            .await?;
+
+    let plan = test_sup.converge(&desired_state, false).await?;
+    assert!(!plan.contains("failed"),
+            "Every action should apply:\n{}",
+            plan);
+    assert!(hab_root.installed_pkg_dir_path(&updated).is_dir(),
+            "{} should have been installed",
+            updated);
+    test_sup.ensure_service_restarted(&service,
+                                      package_name,
+                                      service_group,
+                                      Duration::from_secs(30))
+            .await?;
+    hab_root.wait_for_hook_output_containing(package_name,
+                                             "init",
+                                             "Initialized 2.0.0",
+                                             Duration::from_secs(10))
+            .await?;
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn idle_census_does_not_churn() -> Result<()> {
+    let hab_root_a = utils::HabRoot::new("idle_census_does_not_churn_a");
+    let hab_root_b = utils::HabRoot::new("idle_census_does_not_churn_b");
+
+    let service_min_backoff_period = Duration::from_secs(10);
+    let service_max_backoff_period = Duration::from_secs(30);
+    let service_restart_cooldown_period = Duration::from_secs(60);
+
+    let origin_name = "sup-integration-test";
+    let package_name = "census-soak";
+    let service_group = "default";
+
+    for hab_root in &[&hab_root_a, &hab_root_b] {
+        utils::setup_package_files(origin_name,
+                                   package_name,
+                                   service_group,
+                                   &FIXTURE_ROOT,
+                                   hab_root).await?;
+    }
+
+    let mut test_sup_a =
+        utils::TestSup::new_with_random_ports(&hab_root_a,
+                                              service_min_backoff_period,
+                                              service_max_backoff_period,
+                                              service_restart_cooldown_period).await?;
+    test_sup_a.start(Duration::from_secs(10)).await?;
+
+    let mut test_sup_b =
+        utils::TestSup::new_with_random_ports(&hab_root_b,
+                                              service_min_backoff_period,
+                                              service_max_backoff_period,
+                                              service_restart_cooldown_period).await?;
+    test_sup_b.arg("--peer")?
+              .arg(format!("127.0.0.1:{}", test_sup_a.butterfly_port))?;
+    test_sup_b.start(Duration::from_secs(10)).await?;
+
+    test_sup_a.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+              .await?;
+    test_sup_b.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+              .await?;
+
+    // Give the Supervisors time to find each other and settle down.
+    tokio::time::sleep(Duration::from_secs(30)).await;
+
+    let group = format!("{}.{}", package_name, service_group);
+    let metric = "hab_sup_census_group_generation";
+    let before = test_sup_a.service_group_metric(metric, &group)
+                           .await?
+                           .expect("Census group generation should be recorded");
+
+    tokio::time::sleep(Duration::from_secs(180)).await;
+
+    let after = test_sup_a.service_group_metric(metric, &group)
+                          .await?
+                          .expect("Census group generation should be recorded");
+    // Allow for a single blip, e.g. a member briefly suspected on a
+    // loaded machine; anything more means the idle census is churning.
+    assert!(after - before <= 1.0,
+            "Census group {} went from generation {} to {} while idle",
+            group,
+            before,
+            after);
+
+    test_sup_b.stop().await?;
+    test_sup_a.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn graceful_shutdown_runs_post_stop_hooks() -> Result<()> {
+    let hab_root = utils::HabRoot::new("graceful_shutdown_runs_post_stop_hooks");
+
+    let service_min_backoff_period = Duration::from_secs(10);
+    let service_max_backoff_period = Duration::from_secs(30);
+    let service_restart_cooldown_period = Duration::from_secs(60);
+
+    let origin_name = "sup-integration-test";
+    let package_name = "graceful-shutdown";
+    let service_group = "default";
+
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               &hab_root).await?;
+
+    let mut test_sup =
+        utils::TestSup::new_with_random_ports(&hab_root,
+                                              service_min_backoff_period,
+                                              service_max_backoff_period,
+                                              service_restart_cooldown_period).await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    test_sup.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+            .await?;
+
+    assert!(!hab_root.hook_output_log(package_name, "post-stop").exists());
+
+    assert!(test_sup.shutdown(Duration::from_secs(30)).await?,
+            "The Supervisor had to be killed");
+    hab_root.wait_for_hook_output_containing(package_name,
+                                             "post-stop",
+                                             "Stopped graceful-shutdown",
+                                             Duration::ZERO)
+            .await
+            .context("The post-stop hook did not run")?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn services_come_back_after_supervisor_restart() -> Result<()> {
+    let hab_root = utils::HabRoot::new("services_come_back_after_supervisor_restart");
+
+    let service_min_backoff_period = Duration::from_secs(10);
+    let service_max_backoff_period = Duration::from_secs(30);
+    let service_restart_cooldown_period = Duration::from_secs(60);
+
+    let origin_name = "sup-integration-test";
+    let package_name = "sup-restart";
+    let service_group = "default";
+
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               &hab_root).await?;
+
+    let mut test_sup =
+        utils::TestSup::new_with_random_ports(&hab_root,
+                                              service_min_backoff_period,
+                                              service_max_backoff_period,
+                                              service_restart_cooldown_period).await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    let service =
+        test_sup.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+                .await?;
+    let old_pid = service.process.pid.expect("Service should have a PID");
+
+    test_sup.restart(Duration::from_secs(40)).await?;
+    let service =
+        test_sup.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+                .await?;
+    assert_ne!(service.process.pid, Some(old_pid));
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn test_sup_builder_checks_its_configuration() -> Result<()> {
+    let hab_root = utils::HabRoot::new("test_sup_builder_checks_its_configuration");
+
+    let mut test_sup = utils::TestSupBuilder::new().fs_root(&hab_root)
+                                                   .ring("test-ring")
+                                                   .org("test-org")
+                                                   .peer("127.0.0.1:9638")
+                                                   .peer("127.0.0.2:9638")
+                                                   .arg("--local-gossip-mode")
+                                                   .env("HAB_TEST_VAR", "test")
+                                                   .build()
+                                                   .await?;
+    let args = test_sup.args.join(" ");
+    assert!(args.contains("--ring test-ring"), "{}", args);
+    assert!(args.contains("--org test-org"), "{}", args);
+    assert!(args.contains("--peer 127.0.0.1:9638 --peer 127.0.0.2:9638"),
+            "{}",
+            args);
+    assert!(args.contains("--service-min-backoff-period 10"), "{}", args);
+    assert!(args.ends_with("--local-gossip-mode"), "{}", args);
+    assert!(args.contains(&format!("--listen-http 0.0.0.0:{}", test_sup.http_port)),
+            "{}",
+            args);
+
+    assert!(utils::TestSupBuilder::new().random_ports()
+                                        .build()
+                                        .await
+                                        .is_err(),
+            "fs_root should be required");
+    assert!(utils::TestSupBuilder::new().fs_root(&hab_root)
+                                        .random_ports()
+                                        .http_port(test_sup.http_port.port())
+                                        .build()
+                                        .await
+                                        .is_err(),
+            "Explicit ports should conflict with random ones");
+    assert!(utils::TestSupBuilder::new().fs_root(&hab_root)
+                                        .http_port(test_sup.http_port.port())
+                                        .build()
+                                        .await
+                                        .is_err(),
+            "A port claimed by one TestSup should not be given to another");
+    assert!(utils::TestSupBuilder::new().fs_root(&hab_root)
+                                        .min_backoff(Duration::from_secs(30))
+                                        .max_backoff(Duration::from_secs(10))
+                                        .build()
+                                        .await
+                                        .is_err(),
+            "min_backoff should not be allowed to exceed max_backoff");
+    assert!(utils::TestSupBuilder::new().fs_root(&hab_root)
+                                        .env("FS_ROOT", "/")
+                                        .build()
+                                        .await
+                                        .is_err(),
+            "FS_ROOT should not be overridable through the builder");
+
+    // Never started, but this releases its ports.
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn test_sup_env_and_args_can_be_set_before_starting() -> Result<()> {
+    let hab_root = utils::HabRoot::new("test_sup_env_and_args_can_be_set_before_starting");
+
+    let mut test_sup = utils::TestSup::new_with_random_ports(&hab_root,
+                                                             Duration::from_secs(10),
+                                                             Duration::from_secs(30),
+                                                             Duration::from_secs(60)).await?;
+    test_sup.env("HAB_FEAT_REDACT_HTTP", "true")?
+            .arg("--local-gossip-mode")?;
+    assert!(test_sup.env("FS_ROOT", "/").is_err(),
+            "FS_ROOT should not be overridable without forcing it");
+    let fs_root = hab_root.as_ref().to_string_lossy().into_owned();
+    test_sup.force_env("FS_ROOT", &fs_root)?;
+
+    let env = test_sup.env_vars();
+    assert_eq!(env.get("HAB_FEAT_REDACT_HTTP").map(String::as_str),
+               Some("true"));
+    assert_eq!(env.get("FS_ROOT"), Some(&fs_root));
+    assert_eq!(test_sup.args.last().map(String::as_str),
+               Some("--local-gossip-mode"));
+
+    test_sup.start(Duration::from_secs(10)).await?;
+    assert!(test_sup.env("RUST_LOG", "debug").is_err(),
+            "The environment should not change under a running supervisor");
+
+    // With HTTP redacted, the gateway is up but answers nothing
+    let response = test_sup.api_client
+                           .get(test_sup.gateway_url("/services"))
+                           .send()
+                           .await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn config_applied_through_one_member_reaches_another() -> Result<()> {
+    let origin_name = "sup-integration-test";
+    let package_name = "gossip-ring";
+    let service_group = "default";
+
+    let mut ring =
+        utils::TestRing::new("config_applied_through_one_member_reaches_another", 3).await?;
+    // Only the last member runs the service; the config is applied
+    // through the first.
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               ring.hab_root(2)).await?;
+    ring.start(Duration::from_secs(10)).await?;
+    ring.wait_for_full_mesh(Duration::from_secs(60)).await?;
+    ring.member(2)
+        .ensure_service_started(package_name, service_group, Duration::from_secs(10))
+        .await?;
+
+    ring.member_mut(0)
+        .apply_config(package_name, service_group, r#"app_name = "Gossiped App""#)
+        .await?;
+
+    let config_file = ring.hab_root(2)
+                          .svc_dir_path(package_name)
+                          .join("config")
+                          .join("app-config.toml");
+    let started_at = Instant::now();
+    loop {
+        let contents = std::fs::read_to_string(&config_file).unwrap_or_default();
+        if contents.contains("Gossiped App") {
+            break;
+        }
+        if started_at.elapsed() > Duration::from_secs(30) {
+            ring.stop().await?;
+            return Err(anyhow!("Applied config never reached the service; config \
+                                file contains: {:?}",
+                               contents));
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+
+    ring.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn services_can_be_driven_through_the_ctl_gateway() -> Result<()> {
+    let hab_root = utils::HabRoot::new("services_can_be_driven_through_the_ctl_gateway");
+
+    let service_min_backoff_period = Duration::from_secs(10);
+    let service_max_backoff_period = Duration::from_secs(30);
+    let service_restart_cooldown_period = Duration::from_secs(60);
+
+    let origin_name = "sup-integration-test";
+    let package_name = "ctl-load";
+    let service_group = "default";
+
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               &hab_root).await?;
+    // The service is to be loaded at runtime, not at startup.
+    std::fs::remove_file(hab_root.spec_path(package_name))?;
+    let ident = hab_root.pkg_ident(origin_name, package_name);
+
+    let mut test_sup =
+        utils::TestSup::new_with_random_ports(&hab_root,
+                                              service_min_backoff_period,
+                                              service_max_backoff_period,
+                                              service_restart_cooldown_period).await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+
+    let load = habitat_sup_protocol::ctl::SvcLoad { bldr_url:
+                                                        Some(String::from("http://hab.sup.test")),
+                                                    bldr_channel: Some(String::from("unstable")),
+                                                    ..Default::default() };
+    test_sup.svc_load(&ident, load.clone())
+            .await?
+            .into_result()?;
+    test_sup.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+            .await?;
+
+    // Loading it again without `force` is refused, and says so.
+    let reply = test_sup.svc_load(&ident, load).await?;
+    let err = reply.err.expect("Loading a loaded service should fail");
+    assert!(err.msg.contains("Service already loaded"), "{}", err);
+
+    test_sup.svc_stop(&ident).await?.into_result()?;
+    test_sup.ensure_service_stopped(package_name, service_group, Duration::from_secs(10))
+            .await?;
+    test_sup.svc_start(&ident).await?.into_result()?;
+    test_sup.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+            .await?;
+    test_sup.svc_unload(&ident).await?.into_result()?;
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn supervisor_output_is_captured() -> Result<()> {
+    let hab_root = utils::HabRoot::new("supervisor_output_is_captured");
+
+    let service_min_backoff_period = Duration::from_secs(10);
+    let service_max_backoff_period = Duration::from_secs(30);
+    let service_restart_cooldown_period = Duration::from_secs(60);
+
+    let origin_name = "sup-integration-test";
+    let package_name = "sup-log";
+    let service_group = "default";
+
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               &hab_root).await?;
+
+    let mut test_sup =
+        utils::TestSup::new_with_random_ports(&hab_root,
+                                              service_min_backoff_period,
+                                              service_max_backoff_period,
+                                              service_restart_cooldown_period).await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+
+    let first = test_sup.wait_for_log_line(r"\(O\): Tick 1$", Duration::from_secs(20))
+                        .await?;
+    assert!(test_sup.logs().contains("Tick 0"));
+    let marker = test_sup.log_marker();
+    let later = test_sup.wait_for_log_line(r"\(O\): Tick 4$", Duration::from_secs(20))
+                        .await?;
+    let since = test_sup.logs_since(marker);
+    assert!(since.contains(&later), "{}", since);
+    assert!(!since.contains(&first), "{}", since);
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn stopped_and_unloaded_services_are_told_apart() -> Result<()> {
+    let hab_root = utils::HabRoot::new("stopped_and_unloaded_services_are_told_apart");
+
+    let service_min_backoff_period = Duration::from_secs(10);
+    let service_max_backoff_period = Duration::from_secs(30);
+    let service_restart_cooldown_period = Duration::from_secs(60);
+
+    let origin_name = "sup-integration-test";
+    let package_name = "svc-down";
+    let service_group = "default";
+
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               &hab_root).await?;
+    let ident = hab_root.pkg_ident(origin_name, package_name);
+
+    let mut test_sup =
+        utils::TestSup::new_with_random_ports(&hab_root,
+                                              service_min_backoff_period,
+                                              service_max_backoff_period,
+                                              service_restart_cooldown_period).await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    test_sup.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+            .await?;
+
+    test_sup.svc_stop(&ident).await?.into_result()?;
+    let down = test_sup.wait_for_service_down(package_name, service_group, Duration::from_secs(10))
+                       .await?;
+    match down {
+        utils::ServiceDown::Stopped(service) => assert_eq!(service.desired_state, "Down"),
+        other => panic!("Expected the service to be stopped, got {:?}", other),
+    }
+
+    test_sup.svc_unload(&ident).await?.into_result()?;
+    let down = test_sup.wait_for_service_down(package_name, service_group, Duration::from_secs(10))
+                       .await?;
+    assert!(matches!(down, utils::ServiceDown::Unloaded), "{:?}", down);
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn health_checks_can_be_waited_for() -> Result<()> {
+    let hab_root = utils::HabRoot::new("health_checks_can_be_waited_for");
+
+    let service_min_backoff_period = Duration::from_secs(10);
+    let service_max_backoff_period = Duration::from_secs(30);
+    let service_restart_cooldown_period = Duration::from_secs(60);
+
+    let origin_name = "sup-integration-test";
+    let package_name = "health-check";
+    let service_group = "default";
+
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               &hab_root).await?;
+
+    let mut test_sup =
+        utils::TestSup::new_with_random_ports(&hab_root,
+                                              service_min_backoff_period,
+                                              service_max_backoff_period,
+                                              service_restart_cooldown_period).await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    test_sup.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+            .await?;
+
+    // The health check hook exits 1, which is a warning.
+    test_sup.wait_for_health(package_name,
+                             service_group,
+                             utils::HealthCheck::Warning,
+                             Duration::from_secs(20))
+            .await?;
+    let err = test_sup.wait_for_health(package_name,
+                                       service_group,
+                                       utils::HealthCheck::Ok,
+                                       Duration::from_secs(3))
+                      .await
+                      .expect_err("The health check should never be OK");
+    let message = err.to_string();
+    assert!(message.contains("Warning"), "{}", message);
+    assert!(message.contains("Feeling a bit off"), "{}", message);
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn services_can_be_waited_on_for_any_condition() -> Result<()> {
+    let hab_root = utils::HabRoot::new("services_can_be_waited_on_for_any_condition");
+
+    let service_min_backoff_period = Duration::from_secs(10);
+    let service_max_backoff_period = Duration::from_secs(30);
+    let service_restart_cooldown_period = Duration::from_secs(60);
+
+    let origin_name = "sup-integration-test";
+    let package_name = "sup-restart";
+    let service_group = "default";
+
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               &hab_root).await?;
+
+    let mut test_sup =
+        utils::TestSup::new_with_random_ports(&hab_root,
+                                              service_min_backoff_period,
+                                              service_max_backoff_period,
+                                              service_restart_cooldown_period).await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+
+    let service = test_sup.wait_for_service_condition(package_name,
+                                                      service_group,
+                                                      |s| s.process.pid.is_some(),
+                                                      Duration::from_secs(10))
+                          .await?;
+    let started =
+        test_sup.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+                .await?;
+    assert_eq!(service.process.pid, started.process.pid);
+
+    let err = test_sup.wait_for_service_condition(package_name,
+                                                  service_group,
+                                                  |s| s.channel == "no-such-channel",
+                                                  Duration::from_secs(2))
+                      .await
+                      .expect_err("The service should never be in that channel");
+    assert!(err.to_string().contains("channel: \"unstable\""), "{}", err);
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn gateway_state_is_read_into_typed_views() -> Result<()> {
+    let hab_root = utils::HabRoot::new("gateway_state_is_read_into_typed_views");
+    let ident = "sup-integration-test/typed-gateway";
+    utils::FixturePackageBuilder::new(&hab_root).ident(ident)
+                                                .build()
+                                                .await?;
+
+    let mut test_sup = utils::TestSup::new_with_random_ports(&hab_root,
+                                                             Duration::from_secs(10),
+                                                             Duration::from_secs(30),
+                                                             Duration::from_secs(60)).await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    let started =
+        test_sup.ensure_service_started("typed-gateway", "default", Duration::from_secs(10))
+                .await?;
+    assert!(started.is_running());
+    assert!(started.process.state_entered > 0);
+
+    let services = test_sup.services().await?;
+    assert_eq!(services.len(), 1, "{:#?}", services);
+    assert_eq!(services[0].service_group, "typed-gateway.default");
+    assert_eq!(services[0].process.pid, started.process.pid);
+    assert!(test_sup.service_info("typed-gateway", "elsewhere")
+                    .await?
+                    .is_none());
+
+    let butterfly = test_sup.butterfly_info().await?;
+    assert_eq!(butterfly.alive_member_count(), 1, "{:#?}", butterfly);
+    assert!(butterfly.departed_members.is_empty());
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn stopping_a_supervisor_leaves_no_processes_behind() -> Result<()> {
+    let hab_root = utils::HabRoot::new("stopping_a_supervisor_leaves_no_processes_behind");
+    // A service that won't go quietly
+    let run_hook = "#!/bin/bash\n\ntrap '' TERM\nwhile true; do\n    sleep 1\ndone\n";
+    utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/long-runner")
+                                                .run_hook(run_hook)
+                                                .build()
+                                                .await?;
+
+    let mut test_sup = utils::TestSup::new_with_random_ports(&hab_root,
+                                                             Duration::from_secs(10),
+                                                             Duration::from_secs(30),
+                                                             Duration::from_secs(60)).await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    let service =
+        test_sup.ensure_service_started("long-runner", "default", Duration::from_secs(10))
+                .await?;
+    let pid = service.process
+                     .pid
+                     .ok_or_else(|| anyhow!("Service should have a PID"))?;
+
+    // Kill the launcher outright, rather than giving the Supervisor a
+    // chance to stop its service, which is left to the harness
+    assert!(!test_sup.shutdown(Duration::ZERO).await?);
+    assert!(!utils::process_tree::is_running(pid as u32)?,
+            "Service process {} outlived its supervisor",
+            pid);
+    test_sup.assert_no_orphans()?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn departed_members_are_seen_in_the_census() -> Result<()> {
+    let origin_name = "sup-integration-test";
+    let package_name = "gossip-ring";
+    let service_group = "default";
+
+    let mut ring = utils::TestRing::new("departed_members_are_seen_in_the_census", 3).await?;
+    // Only members running a service show up in the census.
+    for i in 0..3 {
+        utils::setup_package_files(origin_name,
+                                   package_name,
+                                   service_group,
+                                   &FIXTURE_ROOT,
+                                   ring.hab_root(i)).await?;
+    }
+    ring.start(Duration::from_secs(10)).await?;
+    ring.wait_for_full_mesh(Duration::from_secs(60)).await?;
+
+    let census = ring.member(0)
+                     .wait_for_member_count(3, Duration::from_secs(60))
+                     .await?;
+    let departing = ring.member(2).census().await?.local_member_id;
+    assert!(census.service_groups_of(&departing)
+                  .contains("gossip-ring.default"));
+
+    let depart = habitat_sup_protocol::ctl::SupDepart { member_id: Some(departing.clone()), };
+    ring.member(0)
+        .ctl_client
+        .request(depart)
+        .await?
+        .into_result()?;
+    ring.member(0)
+        .wait_for_member_departed(&departing, Duration::from_secs(30))
+        .await?;
+    ring.member(0)
+        .wait_for_member_count(2, Duration::from_secs(30))
+        .await?;
+
+    ring.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn applied_config_is_confirmed_and_stale_config_rejected() -> Result<()> {
+    let hab_root = utils::HabRoot::new("applied_config_is_confirmed_and_stale_config_rejected");
+
+    let service_min_backoff_period = Duration::from_secs(10);
+    let service_max_backoff_period = Duration::from_secs(30);
+    let service_restart_cooldown_period = Duration::from_secs(60);
+
+    let origin_name = "sup-integration-test";
+    let package_name = "gossip-ring";
+    let service_group = "default";
+
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               &hab_root).await?;
+
+    let mut test_sup =
+        utils::TestSup::new_with_random_ports(&hab_root,
+                                              service_min_backoff_period,
+                                              service_max_backoff_period,
+                                              service_restart_cooldown_period).await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    test_sup.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+            .await?;
+
+    let incarnation = test_sup.apply_config_and_wait(package_name,
+                                                     service_group,
+                                                     r#"app_name = "Landed App""#,
+                                                     Duration::from_secs(30))
+                              .await?;
+    let census = test_sup.census().await?;
+    assert_eq!(census.config_incarnation(package_name, service_group),
+               Some(incarnation));
+
+    let err = test_sup.apply_config_with_incarnation_and_wait(package_name,
+                                                              service_group,
+                                                              r#"app_name = "Stale App""#,
+                                                              incarnation - 1,
+                                                              Duration::from_secs(30))
+                      .await
+                      .expect_err("Stale configuration should be rejected");
+    assert!(err.to_string().contains("rejected"), "{}", err);
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn effective_cfg_shows_gossiped_config_with_secrets_redacted() -> Result<()> {
+    let hab_root = utils::HabRoot::new("effective_cfg_shows_gossiped_config_with_secrets_redacted");
+
+    let service_min_backoff_period = Duration::from_secs(10);
+    let service_max_backoff_period = Duration::from_secs(30);
+    let service_restart_cooldown_period = Duration::from_secs(60);
+
+    let origin_name = "sup-integration-test";
+    let package_name = "gossip-ring";
+    let service_group = "default";
+
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               &hab_root).await?;
+    let ident = hab_root.pkg_ident(origin_name, package_name);
+
+    let mut test_sup =
+        utils::TestSup::new_with_random_ports(&hab_root,
+                                              service_min_backoff_period,
+                                              service_max_backoff_period,
+                                              service_restart_cooldown_period).await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    test_sup.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+            .await?;
+
+    let before = test_sup.svc_effective_cfg(&ident).await?;
+    assert_eq!(before.spec_file.as_deref(),
+               Some(hab_root.spec_path(package_name).to_string_lossy().as_ref()));
+    let spec = before.spec.as_deref().unwrap_or_default();
+    assert!(spec.contains(r#"group = "default""#), "{}", spec);
+    assert_eq!(before.gossip_incarnation, Some(0));
+    assert!(before.cfg
+                  .as_deref()
+                  .unwrap_or_default()
+                  .contains("Default App"),
+            "{:?}",
+            before.cfg);
+
+    let incarnation = test_sup.apply_config_and_wait(package_name,
+                                                     service_group,
+                                                     "app_name = \"Gossiped App\"\ndb_password = \
+                                                      \"hunter2\"",
+                                                     Duration::from_secs(30))
+                              .await?;
+
+    // The census has the configuration by now, but the service picks
+    // it up on its next tick.
+    let timeout = Duration::from_secs(30);
+    let started_at = Instant::now();
+    let after = loop {
+        let effective = test_sup.svc_effective_cfg(&ident).await?;
+        if effective.gossip_incarnation == Some(incarnation) {
+            break effective;
+        }
+        if started_at.elapsed() > timeout {
+            return Err(anyhow!("Service didn't pick up configuration incarnation \
+                                {} within {:.2} secs; it has {:?}",
+                               incarnation,
+                               timeout.as_secs_f64(),
+                               effective.gossip_incarnation));
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    };
+    let cfg: toml::value::Table = toml::from_str(after.cfg.as_deref().unwrap_or_default())?;
+    assert_eq!(cfg["app_name"].as_str(), Some("Gossiped App"));
+    assert_eq!(cfg["db_password"].as_str(), Some("<redacted>"));
+    assert!(after.cfg_changed_at >= before.cfg_changed_at,
+            "{:?} < {:?}",
+            after.cfg_changed_at,
+            before.cfg_changed_at);
+    assert!(after.cfg_rendered_at.is_some());
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn ports_are_released_when_a_test_sup_is_dropped() -> Result<()> {
+    let hab_root = utils::HabRoot::new("ports_are_released_when_a_test_sup_is_dropped");
+
+    let port = {
+        let test_sup = utils::TestSupBuilder::new().fs_root(&hab_root)
+                                                   .random_ports()
+                                                   .build()
+                                                   .await?;
+        // Dropped without being stopped, as when a test panics.
+        test_sup.http_port.port()
+    };
+    let test_sup = utils::TestSupBuilder::new().fs_root(&hab_root)
+                                               .http_port(port)
+                                               .build()
+                                               .await?;
+    assert_eq!(test_sup.http_port.port(), port);
+    Ok(())
+}
+
+/// Wait until the file at `path` holds `expected`.
+async fn await_file_contents(path: &Path, expected: &str, timeout: Duration) -> Result<()> {
+    let started_at = Instant::now();
+    loop {
+        let contents = tokio::fs::read_to_string(path).await.ok();
+        if contents.as_deref() == Some(expected) {
+            return Ok(());
+        }
+        if started_at.elapsed() > timeout {
+            return Err(anyhow!("File {} did not contain {:?} within {:.2} secs; \
+                                contents: {:?}",
+                               path.display(),
+                               expected,
+                               timeout.as_secs_f64(),
+                               contents));
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn uploaded_files_land_in_the_service_files_directory() -> Result<()> {
+    let hab_root = utils::HabRoot::new("uploaded_files_land_in_the_service_files_directory");
+
+    let origin_name = "sup-integration-test";
+    let package_name = "file-upload";
+    let service_group = "default";
+
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               &hab_root).await?;
+
+    let mut test_sup = utils::TestSup::new_with_random_ports(&hab_root,
+                                                             Duration::from_secs(10),
+                                                             Duration::from_secs(30),
+                                                             Duration::from_secs(60)).await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    test_sup.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+            .await?;
+
+    let uploaded = hab_root.svc_dir_path(package_name)
+                           .join("files")
+                           .join("greeting.txt");
+    test_sup.upload_file(package_name,
+                         service_group,
+                         "greeting.txt",
+                         b"Hello, world\n",
+                         1,
+                         None)
+            .await?;
+    await_file_contents(&uploaded, "Hello, world\n", Duration::from_secs(10)).await?;
+
+    // A newer version replaces the file
+    test_sup.upload_file(package_name,
+                         service_group,
+                         "greeting.txt",
+                         b"Goodbye, world\n",
+                         2,
+                         None)
+            .await?;
+    await_file_contents(&uploaded, "Goodbye, world\n", Duration::from_secs(10)).await?;
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+const GREETING_TEMPLATE: &str = concat!(r#"greeting = "{{#if cfg.greeting}}{{cfg.greeting}}"#,
+                                        r#"{{else}}unset{{/if}}""#,
+                                        "\n");
+
+/// Set up a service in the "test-org" organization whose `greeting.toml`
+/// renders the `greeting` from its configuration, if it has one, and a
+/// Supervisor to run it. The Supervisor isn't started.
+async fn encrypted_config_test_sup(hab_root: &utils::HabRoot,
+                                   package_name: &str)
+                                   -> Result<utils::TestSup> {
+    utils::FixturePackageBuilder::new(hab_root).ident(format!("sup-integration-test/{}",
+                                                              package_name))
+                                               .config_template("greeting.toml", GREETING_TEMPLATE)
+                                               .build()
+                                               .await?;
+    utils::TestSupBuilder::new().fs_root(hab_root)
+                                .random_ports()
+                                .org("test-org")
+                                .env("RUST_LOG", "warn")
+                                .build()
+                                .await
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn encrypted_config_is_decrypted_and_rendered() -> Result<()> {
+    let hab_root = utils::HabRoot::new("encrypted_config_is_decrypted_and_rendered");
+    let package_name = "encrypted-config";
+    let mut test_sup = encrypted_config_test_sup(&hab_root, package_name).await?;
+
+    let (service_public, service_secret) =
+        generate_service_encryption_key_pair("test-org", &format!("{}.default", package_name));
+    let (user_public, user_secret) = generate_user_encryption_key_pair("test-user");
+    let key_cache = test_sup.key_cache();
+    key_cache.write_key(&service_secret)?;
+    key_cache.write_key(&user_public)?;
+
+    test_sup.start(Duration::from_secs(10)).await?;
+    let rendered = hab_root.svc_dir_path(package_name)
+                           .join("config")
+                           .join("greeting.toml");
+    await_file_contents(&rendered, "greeting = \"unset\"\n", Duration::from_secs(10)).await?;
+
+    test_sup.apply_encrypted_config(package_name,
+                                    "default",
+                                    r#"greeting = "Hello, secret world""#,
+                                    &user_secret,
+                                    &service_public)
+            .await?;
+    await_file_contents(&rendered,
+                        "greeting = \"Hello, secret world\"\n",
+                        Duration::from_secs(30)).await?;
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn encrypted_config_is_ignored_without_the_service_key() -> Result<()> {
+    let hab_root = utils::HabRoot::new("encrypted_config_is_ignored_without_the_service_key");
+    let package_name = "encrypted-config";
+    let mut test_sup = encrypted_config_test_sup(&hab_root, package_name).await?;
+
+    // The user's key is there, but the service's secret key isn't
+    let (service_public, _) =
+        generate_service_encryption_key_pair("test-org", &format!("{}.default", package_name));
+    let (user_public, user_secret) = generate_user_encryption_key_pair("test-user");
+    test_sup.key_cache().write_key(&user_public)?;
+
+    test_sup.start(Duration::from_secs(10)).await?;
+    let rendered = hab_root.svc_dir_path(package_name)
+                           .join("config")
+                           .join("greeting.toml");
+    await_file_contents(&rendered, "greeting = \"unset\"\n", Duration::from_secs(10)).await?;
+
+    test_sup.apply_encrypted_config(package_name,
+                                    "default",
+                                    r#"greeting = "Hello, secret world""#,
+                                    &user_secret,
+                                    &service_public)
+            .await?;
+    test_sup.wait_for_log_line(r"WARN .*key cache", Duration::from_secs(30))
+            .await?;
+    // Give the Supervisor a chance to (wrongly) render it anyway
+    tokio::time::sleep(Duration::from_secs(5)).await;
+    assert_eq!(std::fs::read_to_string(&rendered)?,
+               "greeting = \"unset\"\n");
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn config_only_lands_on_an_encrypted_ring_with_its_key() -> Result<()> {
+    let hab_root = utils::HabRoot::new("config_only_lands_on_an_encrypted_ring_with_its_key");
+
+    let origin_name = "sup-integration-test";
+    let package_name = "gossip-ring";
+    let service_group = "default";
+
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               &hab_root).await?;
+
+    let mut test_sup = utils::TestSupBuilder::new().fs_root(&hab_root)
+                                                   .random_ports()
+                                                   .with_ring("encrypted-ring")
+                                                   .build()
+                                                   .await?;
+    assert!(test_sup.args.join(" ").contains("--ring encrypted-ring"));
+    test_sup.start(Duration::from_secs(10)).await?;
+    test_sup.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+            .await?;
+
+    // Configuration sent with the wrong key is dropped. Had it been
+    // applied, its much higher incarnation would cause the
+    // configuration sent with the right key below to be rejected.
+    let mut wrong_client = test_sup.butterfly_client_with_wrong_ring_key()?;
+    let incarnation = utils::test_butterfly::Client::new_incarnation()?;
+    wrong_client.apply_with_incarnation(package_name,
+                                        service_group,
+                                        r#"app_name = "Wrong Key App""#,
+                                        incarnation + 1000)?;
+
+    test_sup.apply_config_with_incarnation_and_wait(package_name,
+                                                    service_group,
+                                                    r#"app_name = "Right Key App""#,
+                                                    incarnation,
+                                                    Duration::from_secs(30))
+            .await?;
+    let census = test_sup.census().await?;
+    assert_eq!(census.config_incarnation(package_name, service_group),
+               Some(incarnation));
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn ring_key_is_rotated_without_losing_rumors() -> Result<()> {
+    let origin_name = "sup-integration-test";
+    let package_name = "gossip-ring";
+    let service_group = "default";
+
+    let old_key = RingKey::new("rotating-ring");
+    let new_key = RingKey::new_with("rotating-ring",
+                                    &mut rand::thread_rng(),
+                                    "20991231235959".parse()?);
+    let mut ring = utils::TestRing::encrypted("ring_key_is_rotated_without_losing_rumors",
+                                              3,
+                                              old_key.clone()).await?;
+    // Only the last member runs the service
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               ring.hab_root(2)).await?;
+    ring.start(Duration::from_secs(10)).await?;
+    ring.wait_for_full_mesh(Duration::from_secs(60)).await?;
+    ring.member(2)
+        .ensure_service_started(package_name, service_group, Duration::from_secs(10))
+        .await?;
+
+    // Part way through the rotation, the first member sends with the
+    // new key, but still hears rumors from the members that haven't
+    // switched yet
+    ring.member_mut(0).rotate_ring_key(new_key.clone()).await?;
+    let incarnation = ring.member_mut(1)
+                          .apply_config_and_wait(package_name,
+                                                 service_group,
+                                                 r#"app_name = "Mid Rotation App""#,
+                                                 Duration::from_secs(30))
+                          .await?;
+    let started_at = Instant::now();
+    while ring.member(0)
+              .census()
+              .await?
+              .config_incarnation(package_name, service_group)
+          != Some(incarnation)
+    {
+        if started_at.elapsed() > Duration::from_secs(30) {
+            ring.stop().await?;
+            return Err(anyhow!("Configuration applied with the old ring key \
+                                never reached the member already on the new one"));
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+
+    ring.member_mut(1).rotate_ring_key(new_key.clone()).await?;
+    ring.member_mut(2).rotate_ring_key(new_key.clone()).await?;
+    let expected = vec![new_key.named_revision().to_string(),
+                        old_key.named_revision().to_string()];
+    for i in 0..3 {
+        assert_eq!(ring.member(i).ring_key_revisions().await?.accepted,
+                   expected);
+    }
+
+    // With every member on the new key, rumors sent with it land
+    // everywhere
+    ring.member_mut(0)
+        .apply_config(package_name, service_group, r#"app_name = "Rotated App""#)
+        .await?;
+    let config_file = ring.hab_root(2)
+                          .svc_dir_path(package_name)
+                          .join("config")
+                          .join("app-config.toml");
+    let started_at = Instant::now();
+    loop {
+        let contents = std::fs::read_to_string(&config_file).unwrap_or_default();
+        if contents.contains("Rotated App") {
+            break;
+        }
+        if started_at.elapsed() > Duration::from_secs(30) {
+            ring.stop().await?;
+            return Err(anyhow!("Configuration applied with the new ring key \
+                                never reached the service; config file contains: \
+                                {:?}",
+                               contents));
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    ring.wait_for_full_mesh(Duration::from_secs(60)).await?;
+
+    ring.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn gossip_stats_count_rumors_received_from_other_members() -> Result<()> {
+    let package_name = "gossip-ring";
+    let service_group = "default";
+
+    let mut ring =
+        utils::TestRing::new("gossip_stats_count_rumors_received_from_other_members", 2).await?;
+    for i in 0..2 {
+        ring.member_mut(i)
+            .env("HAB_SUP_GOSSIP_STATS_INTERVAL_SECS", "1")?;
+    }
+    ring.start(Duration::from_secs(10)).await?;
+    ring.wait_for_full_mesh(Duration::from_secs(60)).await?;
+
+    let before = ring.member(1).gossip_stats().await?;
+    assert_eq!(before.gossip_interval_ms, 1000);
+    assert!(before.membership.alive >= 1, "{:?}", before.membership);
+
+    // Nothing but gossip from the first member can bring the
+    // configuration to the second
+    ring.member_mut(0)
+        .apply_config(package_name, service_group, r#"app_name = "Counted App""#)
+        .await?;
+    let started_at = Instant::now();
+    let after = loop {
+        let stats = ring.member(1).gossip_stats().await?;
+        if stats.received("service-config") > before.received("service-config") {
+            break stats;
+        }
+        if started_at.elapsed() > Duration::from_secs(30) {
+            ring.stop().await?;
+            return Err(anyhow!("The second member never counted the \
+                                configuration rumor sent to the first; stats: \
+                                {:?}",
+                               stats));
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    };
+    assert_eq!(after.rumors["service-config"].held, 1);
+    let first = ring.member(0).gossip_stats().await?;
+    assert!(first.rumors["service-config"].sent >= 1, "{:?}", first);
+
+    ring.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn tls_gateway_serves_https_and_rejects_plain_http() -> Result<()> {
+    let hab_root = utils::HabRoot::new("tls_gateway_serves_https_and_rejects_plain_http");
+
+    let origin_name = "sup-integration-test";
+    let package_name = "tls-gateway";
+    let service_group = "default";
+
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               &hab_root).await?;
+
+    let cert = utils::generate_self_signed_cert(&hab_root.as_ref().join("tls"))?;
+    let mut test_sup =
+        utils::TestSupBuilder::new().fs_root(&hab_root)
+                                    .random_ports()
+                                    .with_tls(&cert.cert_path,
+                                              &cert.key_path,
+                                              Some(&cert.cert_path))
+                                    .build()
+                                    .await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    // This goes over https, trusting only our certificate
+    test_sup.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+            .await?;
+
+    let plain_url = format!("http://localhost:{}/services", test_sup.http_port);
+    // Whether the connection is dropped or an error comes back, all
+    // that matters is that nothing is served
+    if let Ok(res) = reqwest::get(&plain_url).await {
+        assert!(!res.status().is_success(),
+                "Plain HTTP request to a TLS gateway succeeded with {}",
+                res.status());
+    }
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn gateway_can_be_served_on_a_unix_socket() -> Result<()> {
+    use std::os::unix::{fs::PermissionsExt,
+                        net::UnixListener};
+
+    let hab_root = utils::HabRoot::new("gateway_can_be_served_on_a_unix_socket");
+    let timeout = Duration::from_secs(10);
+    utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/healthy")
+                                                .build()
+                                                .await?;
+
+    // As a Supervisor that crashed would leave it: the socket file,
+    // with nothing listening on it
+    let socket_path = hab_root.as_ref().join("gateway.sock");
+    drop(UnixListener::bind(&socket_path)?);
+
+    let mut test_sup = utils::TestSupBuilder::new().fs_root(&hab_root)
+                                                   .random_ports()
+                                                   .http_socket(&socket_path)
+                                                   .arg("--listen-http-socket-mode")
+                                                   .arg("0600")
+                                                   .build()
+                                                   .await?;
+    test_sup.start(timeout).await?;
+    test_sup.ensure_service_started("healthy", "default", timeout)
+            .await?;
+    let mode = std::fs::metadata(&socket_path)?.permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    // Whatever it leaves behind this time is cleared away too
+    test_sup.restart(Duration::from_secs(30)).await?;
+    test_sup.ensure_service_started("healthy", "default", timeout)
+            .await?;
+    test_sup.butterfly_info().await?;
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn file_system_snapshot_reports_permission_only_changes() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let hab_root = utils::HabRoot::new("file_system_snapshot_reports_permission_only_changes");
+    let dir = hab_root.as_ref().join("config");
+    std::fs::create_dir_all(&dir)?;
+    let config = dir.join("config.toml");
+    std::fs::write(&config, "secret = true\n")?;
+    std::fs::set_permissions(&config, std::fs::Permissions::from_mode(0o600))?;
+
+    let initial_snapshot = FileSystemSnapshot::new(&dir).await?;
+    std::fs::set_permissions(&config, std::fs::Permissions::from_mode(0o644))?;
+    let final_snapshot = FileSystemSnapshot::new(&dir).await?;
+
+    let delta = final_snapshot.modifications_since(&initial_snapshot, &[]);
+    assert_eq!(delta.attributes_updated(), vec!["config.toml"]);
+    assert_eq!(delta.updated(), vec![] as Vec<&str>);
+    assert_eq!(initial_snapshot.file("config.toml")?.mode(), 0o600);
+    assert_eq!(final_snapshot.file("config.toml")?.mode(), 0o644);
+    assert_eq!(final_snapshot.file("config.toml")?.uid(),
+               initial_snapshot.file("config.toml")?.uid());
+    assert_eq!(final_snapshot.file("config.toml")?.gid(),
+               initial_snapshot.file("config.toml")?.gid());
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn file_system_snapshot_records_symlinks_by_target() -> Result<()> {
+    let hab_root = utils::HabRoot::new("file_system_snapshot_records_symlinks_by_target");
+    let dir = hab_root.as_ref().join("config");
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("real.toml"), "real = true\n")?;
+    std::fs::write(dir.join("config.toml"), "real = true\n")?;
+
+    let initial_snapshot = FileSystemSnapshot::new(&dir).await?;
+    assert!(!initial_snapshot.file("config.toml")?.is_symlink());
+    assert_eq!(initial_snapshot.file("config.toml")?.symlink_target(), None);
+
+    // The link points at a file with the very same contents, so only
+    // a snapshot that doesn't follow it can tell the difference
+    std::fs::remove_file(dir.join("config.toml"))?;
+    std::os::unix::fs::symlink("real.toml", dir.join("config.toml"))?;
+    let final_snapshot = FileSystemSnapshot::new(&dir).await?;
+
+    let snapshot = final_snapshot.file("config.toml")?;
+    assert!(snapshot.is_symlink());
+    assert_eq!(snapshot.symlink_target(), Some(Path::new("real.toml")));
+    let delta = final_snapshot.modifications_since(&initial_snapshot, &[]);
+    assert_eq!(delta.updated(), vec!["config.toml"]);
+    assert_eq!(delta.added(), vec![] as Vec<&str>);
+    assert_eq!(delta.removed(), vec![] as Vec<&str>);
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn spec_changes_written_by_tests_are_picked_up() -> Result<()> {
+    let hab_root = utils::HabRoot::new("spec_changes_written_by_tests_are_picked_up");
+    let origin_name = "sup-integration-test";
+    let package_name = "spec-update";
+    let service_group = "default";
+
+    // The fixture has no spec file, so it's given the usual one
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               &hab_root).await?;
+    let mut spec = hab_root.read_spec(service_group, package_name)?;
+    assert_eq!(spec, utils::fixture_spec(origin_name, package_name));
+    assert_eq!(spec.update_strategy, UpdateStrategy::None);
+
+    let mut test_sup = utils::TestSup::new_with_random_ports(&hab_root,
+                                                             Duration::from_secs(10),
+                                                             Duration::from_secs(30),
+                                                             Duration::from_secs(60)).await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    test_sup.ensure_service_started(package_name, service_group, Duration::from_secs(10))
+            .await?;
+
+    spec.update_strategy = UpdateStrategy::AtOnce;
+    hab_root.write_spec(service_group, &spec)?;
+    test_sup.wait_for_service_condition(package_name,
+                                        service_group,
+                                        |service| service.update_strategy == "at-once",
+                                        Duration::from_secs(20))
+            .await?;
+    assert_eq!(hab_root.read_spec(service_group, package_name)?, spec);
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn services_can_be_built_without_fixtures_on_disk() -> Result<()> {
+    let hab_root = utils::HabRoot::new("services_can_be_built_without_fixtures_on_disk");
+    let user = hcore::users::get_current_username()?.ok_or_else(|| anyhow!("No username found"))?;
+
+    let run_hook = "#!/bin/bash\n\nwhile true; do\n    sleep 1\ndone\n";
+    let health_check_hook = "#!/bin/bash\n\necho 'All good'\nexit 0\n";
+    let library_ident = "sup-integration-test/built-library/1.2.3/20240101000000";
+
+    let library = utils::FixturePackageBuilder::new(&hab_root).ident(library_ident)
+                                                              .library()
+                                                              .build()
+                                                              .await?;
+    let service =
+        utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/built-service")
+                                                    .service_group("built")
+                                                    .run_hook(run_hook)
+                                                    .health_check_hook(health_check_hook)
+                                                    .config_template("greeting.toml",
+                                                                     "name = \"{{pkg.name}}\"\n")
+                                                    .dep(library.to_string())
+                                                    .svc_user(user)
+                                                    .build()
+                                                    .await?;
+    assert_eq!(service,
+               hab_root.pkg_ident("sup-integration-test", "built-service"));
+    let pkg_dir = hab_root.pkg_dir_path("sup-integration-test", "built-service");
+    assert_eq!(std::fs::read_to_string(pkg_dir.join("TDEPS"))?,
+               format!("{}\n", library));
+    assert_eq!(utils::FileSnapshot::new(pkg_dir.join("hooks/run"))?.mode() & 0o777,
+               0o755);
+    assert!(!hab_root.spec_path("built-library").exists());
+
+    let mut test_sup = utils::TestSup::new_with_random_ports(&hab_root,
+                                                             Duration::from_secs(10),
+                                                             Duration::from_secs(30),
+                                                             Duration::from_secs(60)).await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    test_sup.ensure_service_started("built-service", "built", Duration::from_secs(10))
+            .await?;
+    test_sup.wait_for_health("built-service",
+                             "built",
+                             utils::HealthCheck::Ok,
+                             Duration::from_secs(20))
+            .await?;
+    let rendered = hab_root.svc_dir_path("built-service")
+                           .join("config")
+                           .join("greeting.toml");
+    await_file_contents(&rendered,
+                        "name = \"built-service\"\n",
+                        Duration::from_secs(10)).await?;
+
+    test_sup.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn services_run_as_the_current_user_by_default() -> Result<()> {
+    let hab_root = utils::HabRoot::new("services_run_as_the_current_user_by_default");
+    let user = hcore::users::get_current_username()?.ok_or_else(|| anyhow!("No username found"))?;
+    #[cfg(not(windows))]
+    let group =
+        hcore::users::get_current_groupname()?.ok_or_else(|| anyhow!("No groupname found"))?;
+    #[cfg(windows)]
+    let group = utils::WINDOWS_SVC_GROUP.to_string();
+
+    let ident = "sup-integration-test/default-user";
+    let ident = utils::FixturePackageBuilder::new(&hab_root).ident(ident)
+                                                            .build()
+                                                            .await?;
+    let pkg_dir = hab_root.installed_pkg_dir_path(&ident);
+    assert_eq!(std::fs::read_to_string(pkg_dir.join("SVC_USER"))?, user);
+    assert_eq!(std::fs::read_to_string(pkg_dir.join("SVC_GROUP"))?, group);
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn svc_user_and_group_overrides_are_checked_and_win() -> Result<()> {
+    let hab_root = utils::HabRoot::new("svc_user_and_group_overrides_are_checked_and_win");
+    let origin_name = "sup-integration-test";
+    let package_name = "svc-user";
+    let service_group = "default";
+
+    // The fixture asks for "hab", which needn't exist here; root does
+    let user = hcore::users::get_username_by_uid(0)?.ok_or_else(|| anyhow!("No user 0 found"))?;
+    let group = hcore::users::get_groupname_by_gid(0)?.ok_or_else(|| anyhow!("No group 0 found"))?;
+    utils::setup_package_files_as(origin_name,
+                                  package_name,
+                                  service_group,
+                                  &FIXTURE_ROOT,
+                                  &hab_root,
+                                  Some(&user),
+                                  Some(&group)).await?;
+    let pkg_dir = hab_root.pkg_dir_path(origin_name, package_name);
+    assert_eq!(std::fs::read_to_string(pkg_dir.join("SVC_USER"))?, user);
+    assert_eq!(std::fs::read_to_string(pkg_dir.join("SVC_GROUP"))?, group);
+
+    let missing_user = "no-such-hab-test-user";
+    let err =
+        utils::setup_package_files_as(origin_name,
+                                      package_name,
+                                      service_group,
+                                      &FIXTURE_ROOT,
+                                      &hab_root,
+                                      Some(missing_user),
+                                      None).await
+                                           .expect_err("setup should fail for a missing user");
+    let expected = format!("Service user '{}' does not exist", missing_user);
+    assert!(format!("{:#}", err).contains(&expected), "{:#}", err);
+
+    let missing_group = "no-such-hab-test-group";
+    let err =
+        utils::FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/missing-group")
+                                                    .svc_group(missing_group)
+                                                    .build()
+                                                    .await
+                                                    .expect_err("build should fail for a missing \
+                                                                 group");
+    let expected = format!("Service group '{}' does not exist", missing_group);
+    assert!(format!("{:#}", err).contains(&expected), "{:#}", err);
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn bound_services_render_their_bind_data() -> Result<()> {
+    let hab_root = utils::HabRoot::new("bound_services_render_their_bind_data");
+    let origin_name = "sup-integration-test";
+    let services = [(origin_name, "bind-producer", "backend"),
+                    (origin_name, "bind-consumer", "default")];
+
+    // A bind to a service that isn't being set up is refused outright
+    let err = utils::setup_bound_services(&services[1..],
+                                          &[("bind-consumer", "backend", "bind-producer")],
+                                          &FIXTURE_ROOT,
+                                          &hab_root).await
+                                                    .expect_err("bind to a missing service");
+    assert!(err.to_string()
+               .contains("is to 'bind-producer', which is not one of the services"),
+            "{:?}",
+            err);
+    assert!(!hab_root.spec_path("bind-consumer").exists());
+
+    utils::setup_bound_services(&services,
+                                &[("bind-consumer", "backend", "bind-producer")],
+                                &FIXTURE_ROOT,
+                                &hab_root).await?;
+
+    let mut test_sup = utils::TestSup::new_with_random_ports(&hab_root,
+                                                             Duration::from_secs(10),
+                                                             Duration::from_secs(30),
+                                                             Duration::from_secs(60)).await?;
+    test_sup.start(Duration::from_secs(10)).await?;
+    test_sup.ensure_service_started("bind-producer", "backend", Duration::from_secs(10))
+            .await?;
+    test_sup.ensure_service_started("bind-consumer", "default", Duration::from_secs(20))
+            .await?;
+
+    let rendered = hab_root.svc_dir_path("bind-consumer")
+                           .join("config")
+                           .join("backend.toml");
+    await_file_contents(&rendered, "port = 4242\n", Duration::from_secs(20)).await
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn setting_up_package_files_again_resets_the_package() -> Result<()> {
+    let origin_name = "sup-integration-test";
+    let package_name = "sup-restart";
+    let service_group = "default";
+    let hab_root = utils::HabRoot::new("setting_up_package_files_again_resets_the_package");
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               &hab_root).await?;
+    let pkg_dir = hab_root.pkg_dir_path(origin_name, package_name);
+    let initial_snapshot = FileSystemSnapshot::new(&pkg_dir).await?;
+
+    // What an earlier phase of a test might leave behind
+    std::fs::write(pkg_dir.join("stale.toml"), "stale = true\n")?;
+    std::fs::create_dir_all(pkg_dir.join("stale/nested"))?;
+    std::fs::write(pkg_dir.join("stale/nested/file"), "stale\n")?;
+    std::fs::write(pkg_dir.join("hooks/run"), "#!/bin/sh\nexit 1\n")?;
+
+    utils::setup_package_files(origin_name,
+                               package_name,
+                               service_group,
+                               &FIXTURE_ROOT,
+                               &hab_root).await?;
+    let final_snapshot = FileSystemSnapshot::new(&pkg_dir).await?;
+    let delta = final_snapshot.modifications_since(&initial_snapshot, &[]);
+    assert!(delta.is_empty(), "{}", delta);
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn file_system_modifications_report_hashes_and_diffs() -> Result<()> {
+    let hab_root = utils::HabRoot::new("file_system_modifications_report_hashes_and_diffs");
+    let dir = hab_root.as_ref().join("config");
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("config.toml"), "a = 1\nb = \"old line\"\nc = 3\n")?;
+    std::fs::write(dir.join("stale.toml"), "stale = true\n")?;
+
+    let initial_snapshot = FileSystemSnapshot::new(&dir).await?;
+    std::fs::write(dir.join("config.toml"), "a = 1\nb = \"new line\"\nc = 3\n")?;
+    std::fs::remove_file(dir.join("stale.toml"))?;
+    std::fs::write(dir.join("fresh.toml"), "fresh = true\n")?;
+    let final_snapshot = FileSystemSnapshot::new(&dir).await?;
+
+    let delta = final_snapshot.modifications_since(&initial_snapshot, &[]);
+    let updated = &delta.updated_files()[0];
+    assert_eq!(updated.path, "config.toml");
+    assert!(matches!(updated.old_hash, HashedEntry::File(_)));
+    assert_ne!(updated.old_hash, updated.new_hash);
+    assert!(updated.new_modified_at >= updated.old_modified_at);
+    let diff = updated.diff
+                      .as_deref()
+                      .expect("small text files should be diffed");
+    assert!(diff.contains("-b = \"old line\"") && diff.contains("+b = \"new line\""),
+            "{}",
+            diff);
+    assert!(diff.contains(" a = 1"), "{}", diff);
+
+    assert_eq!(delta.added_files()[0].path, "fresh.toml");
+    assert_eq!(delta.added_files()[0].size, 13);
+    assert_eq!(delta.removed_files()[0].path, "stale.toml");
+    assert!(matches!(delta.removed_files()[0].hash, HashedEntry::File(_)));
+
+    let report = delta.to_string();
+    for expected in &["Added:\n  fresh.toml (hash ",
+                      "Removed:\n  stale.toml (hash ",
+                      "Updated:\n  config.toml\n    was hash ",
+                      "    +b = \"new line\""]
+    {
+        assert!(report.contains(expected),
+                "'{}' missing from report:\n{}",
+                expected,
+                report);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn file_system_snapshot_of_a_large_tree_is_complete_and_ordered() -> Result<()> {
+    let hab_root =
+        utils::HabRoot::new("file_system_snapshot_of_a_large_tree_is_complete_and_ordered");
+    let dir = hab_root.as_ref().join("tree");
+    std::fs::create_dir_all(&dir)?;
+    let empty_snapshot = FileSystemSnapshot::new(&dir).await?;
+
+    let mut expected = Vec::new();
+    for d in 0..10 {
+        let subdir = dir.join(format!("dir-{}", d));
+        std::fs::create_dir_all(&subdir)?;
+        for f in 0..30 {
+            let name = format!("file-{:02}", f);
+            std::fs::write(subdir.join(&name), format!("{} {}\n", d, f))?;
+            expected.push(format!("dir-{}/{}", d, name));
+        }
+    }
+    expected.sort();
+
+    let snapshot = FileSystemSnapshot::new(&dir).await?;
+    let delta = snapshot.modifications_since(&empty_snapshot, &[]);
+    assert_eq!(delta.added(),
+               expected.iter().map(String::as_str).collect::<Vec<_>>());
+    for file in &expected {
+        assert_eq!(snapshot.file(file)?,
+                   &utils::FileSnapshot::new(dir.join(file))?,
+                   "{}",
+                   file);
+    }
+
+    // Nothing changed, so a second snapshot should match exactly
+    let second_snapshot = FileSystemSnapshot::new(&dir).await?;
+    let delta = second_snapshot.modifications_since(&snapshot, &[]);
+    assert!(delta.is_empty(), "{}", delta);
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn file_system_snapshot_waits_for_single_files() -> Result<()> {
+    let hab_root = utils::HabRoot::new("file_system_snapshot_waits_for_single_files");
+    let dir = hab_root.as_ref().join("config");
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("app.toml"), "version = 1\n")?;
+    let snapshot = FileSystemSnapshot::new(&dir).await?;
+
+    let writer = {
+        let dir = dir.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            // Replaced atomically, the way the Supervisor renders
+            // templates
+            std::fs::write(dir.join("app.toml.tmp"), "version = 2\n")?;
+            std::fs::rename(dir.join("app.toml.tmp"), dir.join("app.toml"))?;
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            std::fs::write(dir.join("extra.toml"), "extra = true\n")?;
+            Ok::<_, std::io::Error>(())
+        })
+    };
+
+    let modified = snapshot.wait_for_modification("app.toml", Duration::from_secs(10))
+                           .await?;
+    assert_eq!(modified.current_file_content().await?, "version = 2\n");
+    let created = snapshot.wait_for_creation("extra.toml", Duration::from_secs(10))
+                          .await?;
+    assert_eq!(created.current_file_content().await?, "extra = true\n");
+    writer.await??;
+
+    let err = snapshot.wait_for_creation("app.toml", Duration::from_secs(1))
+                      .await
+                      .expect_err("app.toml was already in the snapshot");
+    assert!(err.to_string().contains("already"), "{}", err);
+    let err = snapshot.wait_for_creation("missing.toml", Duration::from_millis(300))
+                      .await
+                      .expect_err("missing.toml is never written");
+    assert!(err.to_string().contains("last seen missing"), "{}", err);
+    Ok(())
+}
+
+/// A `/services/{name}/{group}` body, trimmed down to what
+/// `ensure_service_restarted` looks at, plus a field it doesn't.
+fn canned_service(desired_state: &str,
+                  state: &str,
+                  pid: Option<Pid>,
+                  state_entered: u64,
+                  last_process_state: serde_json::Value)
+                  -> Result<utils::sup_gateway_api::ServiceInfo> {
+    let body = serde_json::json!({
+        "channel": "stable",
+        "desired_state": desired_state,
+        "initialized": true,
+        "last_process_state": last_process_state,
+        "process": { "pid": pid, "state": state, "state_entered": state_entered }
+    });
+    Ok(serde_json::from_value(body)?)
+}
+
+#[test]
+fn service_restarts_are_told_by_when_the_process_came_up() -> Result<()> {
+    let terminated = || {
+        serde_json::json!({ "pid": 100,
+                            "termination_reason": "run_hook_updated",
+                            "terminated_at": 1600000010 })
+    };
+    let earlier = canned_service("Up", "up", Some(100), 1600000000, serde_json::Value::Null)?;
+
+    // The same process, seen again
+    let service = canned_service("Up", "up", Some(100), 1600000000, serde_json::Value::Null)?;
+    assert!(!service.has_restarted_since(&earlier));
+    assert!(!service.process_changed_since(&earlier));
+
+    // Down, on its way back up
+    let service = canned_service("Up", "down", None, 1600000010, terminated())?;
+    assert!(!service.has_restarted_since(&earlier));
+    assert!(service.process_changed_since(&earlier));
+    assert!(!service.is_stopped());
+
+    // Back up, with the old pid reused
+    let service = canned_service("Up", "up", Some(100), 1600000011, terminated())?;
+    assert!(service.has_restarted_since(&earlier));
+    assert!(service.process_changed_since(&earlier));
+
+    // Back up within the same second, told by the process having ended
+    let service = canned_service("Up", "up", Some(100), 1600000000, terminated())?;
+    assert!(service.has_restarted_since(&earlier));
+
+    // Up in a new process, but the service is meant to be down
+    let service = canned_service("Down", "up", Some(101), 1600000011, terminated())?;
+    assert!(!service.has_restarted_since(&earlier));
+
+    // Stopped, so the Supervisor won't restart it
+    let service = canned_service("Down", "down", None, 1600000011, terminated())?;
+    assert!(!service.has_restarted_since(&earlier));
+    assert!(service.is_stopped());
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+async fn file_system_snapshot_skips_excluded_directories() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let hab_root = utils::HabRoot::new("file_system_snapshot_skips_excluded_directories");
+    let root = hab_root.as_ref().join("snapshot");
+    let unreadable = root.join("pkgs").join("unreadable");
+    std::fs::create_dir_all(&unreadable)?;
+    std::fs::write(unreadable.join("secret"), "secret\n")?;
+    for dir in &["config", "files", "data"] {
+        std::fs::create_dir_all(root.join("svc/pinger").join(dir))?;
+    }
+    std::fs::write(root.join("svc/pinger/config/app.toml"), "version = 1\n")?;
+    std::fs::write(root.join("svc/pinger/files/seed.txt"), "seed\n")?;
+    std::fs::write(root.join("svc/pinger/data/state"), "state\n")?;
+
+    std::fs::set_permissions(&unreadable, std::fs::Permissions::from_mode(0o000))?;
+    let result = snapshot_only_config_and_files(&root, &unreadable).await;
+    // Or the HabRoot can't be cleaned up
+    std::fs::set_permissions(&unreadable, std::fs::Permissions::from_mode(0o755))?;
+    result
+}
+
+/// Snapshot a service's config and files in `root`, and nothing in
+/// the unreadable directory beneath it.
+async fn snapshot_only_config_and_files(root: &Path, unreadable: &Path) -> Result<()> {
+    // Permissions don't stop root from reading anything, so only then
+    // does it take the filters to get past the directory
+    if std::fs::read_dir(unreadable).is_err() {
+        assert!(FileSystemSnapshot::new(root).await.is_err());
+    }
+    let include = vec![Pattern::new("svc/pinger/config")?,
+                       Pattern::new("svc/pinger/files/*.txt")?];
+    let exclude = vec![Pattern::new("pkgs")?];
+    let snapshot = FileSystemSnapshot::new_filtered(root, include.clone(), exclude.clone()).await?;
+    snapshot.file("svc/pinger/config/app.toml")?;
+    snapshot.file("svc/pinger/files/seed.txt")?;
+    assert!(snapshot.file("svc/pinger/data/state").is_err());
+    assert!(snapshot.file("pkgs/unreadable/secret").is_err());
+
+    std::fs::write(root.join("svc/pinger/config/app.toml"), "version = 2\n")?;
+    std::fs::write(root.join("svc/pinger/data/state"), "changed\n")?;
+    let later = FileSystemSnapshot::new_filtered(root, include, exclude).await?;
+    let delta = later.modifications_since(&snapshot, &[]);
+    assert_eq!(delta.updated(), vec!["svc/pinger/config/app.toml"]);
+    assert_eq!(delta.added(), vec![] as Vec<&str>);
+    assert_eq!(delta.removed(), vec![] as Vec<&str>);
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(feature = "ignore_integration_tests", ignore)]
+#[should_panic(expected = "different filters")]
+async fn file_system_snapshots_with_different_filters_are_not_compared() {
+    let hab_root =
+        utils::HabRoot::new("file_system_snapshots_with_different_filters_are_not_compared");
+    std::fs::create_dir_all(hab_root.as_ref().join("config")).unwrap();
+    let everything = FileSystemSnapshot::new(hab_root.as_ref()).await.unwrap();
+    let config = FileSystemSnapshot::new_filtered(hab_root.as_ref(),
+                                                  vec![Pattern::new("config").unwrap()],
+                                                  Vec::new()).await
+                                                             .unwrap();
+    config.modifications_since(&everything, &[]);
+}
@@ -0,0 +1,293 @@
+//! Build fixture packages from within a test, rather than authoring
+//! them on disk under `tests/fixtures`. The package is written
+//! straight into a `HabRoot`, along with a spec file to run it, the
+//! way `setup_package_files` would stage a fixture from disk:
+//!
+//!       let ident = FixturePackageBuilder::new(&hab_root).ident("sup-integration-test/pinger")
+//!                                                        .run_hook("...")
+//!                                                        .build()
+//!                                                        .await?;
+//!
+//! `build_artifact` writes the package as a signed artifact instead,
+//! for the Supervisor to install itself.
+//!
+//! Hooks are written exactly as they're given, and run however the
+//! platform runs hooks: with bash on Unix, and with PowerShell on
+//! Windows. Tests that run on both need to cfg-gate the scripts they
+//! pass in; the default run hook already is.
+use super::{fs::{fixture_spec,
+                 write_default_metafiles,
+                 write_metafile},
+            HabRoot};
+use crate::hcore::{crypto::{artifact,
+                            keys::SecretOriginSigningKey},
+                   package::{metadata::MetaFile,
+                             PackageIdent}};
+use anyhow::{anyhow,
+             Context,
+             Result};
+use std::{path::Path,
+          str::FromStr};
+use tempfile::NamedTempFile;
+use tokio::fs;
+use xz2::write::XzEncoder;
+
+/// Run by services that aren't given a run hook of their own.
+#[cfg(not(windows))]
+const DEFAULT_RUN_HOOK: &str = "#!/bin/bash\n\nwhile true; do\n    sleep 1\ndone\n";
+#[cfg(windows)]
+const DEFAULT_RUN_HOOK: &str = "while ($true) {\n    Start-Sleep -Seconds 1\n}\n";
+
+pub struct FixturePackageBuilder<'a> {
+    hab_root:          &'a HabRoot,
+    ident:             Option<String>,
+    service_group:     String,
+    run_hook:          Option<String>,
+    init_hook:         Option<String>,
+    health_check_hook: Option<String>,
+    config_templates:  Vec<(String, String)>,
+    deps:              Vec<String>,
+    svc_user:          Option<String>,
+    svc_group:         Option<String>,
+    /// Whether to write a spec file, so that the Supervisor runs the
+    /// package as a service.
+    spec:              bool,
+}
+
+impl<'a> FixturePackageBuilder<'a> {
+    pub fn new(hab_root: &'a HabRoot) -> Self {
+        FixturePackageBuilder { hab_root,
+                                ident: None,
+                                service_group: "default".to_string(),
+                                run_hook: None,
+                                init_hook: None,
+                                health_check_hook: None,
+                                config_templates: Vec::new(),
+                                deps: Vec::new(),
+                                svc_user: None,
+                                svc_group: None,
+                                spec: true }
+    }
+
+    /// The package's ident. An ident without a version and release is
+    /// given the ones `HabRoot::pkg_ident` uses, so that the package
+    /// can be found the same way as fixtures from disk.
+    pub fn ident(mut self, ident: impl Into<String>) -> Self {
+        self.ident = Some(ident.into());
+        self
+    }
+
+    /// The group that the spec file runs the service in; "default"
+    /// unless set.
+    pub fn service_group(mut self, service_group: impl Into<String>) -> Self {
+        self.service_group = service_group.into();
+        self
+    }
+
+    /// Without one, the service just sleeps until it's stopped.
+    pub fn run_hook(mut self, script: impl Into<String>) -> Self {
+        self.run_hook = Some(script.into());
+        self
+    }
+
+    pub fn init_hook(mut self, script: impl Into<String>) -> Self {
+        self.init_hook = Some(script.into());
+        self
+    }
+
+    pub fn health_check_hook(mut self, script: impl Into<String>) -> Self {
+        self.health_check_hook = Some(script.into());
+        self
+    }
+
+    /// Add a template to the package's `config` directory, for the
+    /// Supervisor to render into the service's.
+    pub fn config_template(mut self, name: impl Into<String>, contents: impl Into<String>) -> Self {
+        self.config_templates.push((name.into(), contents.into()));
+        self
+    }
+
+    /// Depend on another package, which the test needs to install
+    /// itself, e.g. with another builder and `library`. Dependencies
+    /// are recorded as transitive dependencies too.
+    pub fn dep(mut self, ident: impl Into<String>) -> Self {
+        self.deps.push(ident.into());
+        self
+    }
+
+    /// The user to run the service as; the current user unless set.
+    /// It has to exist, or `build` fails.
+    pub fn svc_user(mut self, user: impl Into<String>) -> Self {
+        self.svc_user = Some(user.into());
+        self
+    }
+
+    /// The group to run the service as; the current user's primary
+    /// group unless set, or `WINDOWS_SVC_GROUP` on Windows. It has to
+    /// exist, or `build` fails.
+    pub fn svc_group(mut self, group: impl Into<String>) -> Self {
+        self.svc_group = Some(group.into());
+        self
+    }
+
+    /// Install the package without a spec file, as a dependency of
+    /// other packages rather than a service of its own.
+    pub fn library(mut self) -> Self {
+        self.spec = false;
+        self
+    }
+
+    /// Write the package into the `HabRoot`, returning its fully
+    /// qualified ident.
+    pub async fn build(self) -> Result<PackageIdent> {
+        let ident = self.ident
+                        .as_deref()
+                        .ok_or_else(|| anyhow!("Fixture packages need an ident"))?;
+        let ident = self.qualified_ident(ident)?;
+        let deps = self.deps
+                       .iter()
+                       .map(|dep| {
+                           PackageIdent::from_str(dep).with_context(|| {
+                                                          format!("Invalid dependency '{}' of '{}'",
+                                                                  dep, ident)
+                                                      })
+                       })
+                       .collect::<Result<Vec<_>>>()?;
+
+        let pkg_dir = self.hab_root.installed_pkg_dir_path(&ident);
+        fs::create_dir_all(pkg_dir.join("hooks")).await
+                                                 .with_context(|| {
+                                                     format!("Failed to create package directory \
+                                                              '{}'",
+                                                             pkg_dir.display())
+                                                 })?;
+        write_metafile(pkg_dir.join(MetaFile::Ident.to_string()),
+                       &ident.to_string()).await?;
+        if !deps.is_empty() {
+            let deps = deps.iter()
+                           .map(|dep| format!("{}\n", dep))
+                           .collect::<String>();
+            write_metafile(pkg_dir.join(MetaFile::Deps.to_string()), &deps).await?;
+            write_metafile(pkg_dir.join(MetaFile::TDeps.to_string()), &deps).await?;
+        }
+        write_default_metafiles(&pkg_dir,
+                                self.svc_user.as_deref(),
+                                self.svc_group.as_deref()).await
+                                                          .context("Failed to write default \
+                                                                    metafiles")?;
+
+        write_hook(&pkg_dir,
+                   "run",
+                   self.run_hook.as_deref().unwrap_or(DEFAULT_RUN_HOOK)).await?;
+        if let Some(ref script) = self.init_hook {
+            write_hook(&pkg_dir, "init", script).await?;
+        }
+        if let Some(ref script) = self.health_check_hook {
+            write_hook(&pkg_dir, "health-check", script).await?;
+        }
+        if !self.config_templates.is_empty() {
+            let config_dir = pkg_dir.join("config");
+            fs::create_dir_all(&config_dir).await
+                                           .context("Failed to create config directory")?;
+            for (name, contents) in &self.config_templates {
+                fs::write(config_dir.join(name), contents).await
+                                                          .with_context(|| {
+                                                              format!("Failed to write config \
+                                                                       template '{}'",
+                                                                      name)
+                                                          })?;
+            }
+        }
+
+        if self.spec {
+            self.write_spec(&ident)?;
+        }
+        Ok(ident)
+    }
+
+    /// Write the package as an artifact signed with `signing_key` in
+    /// the `HabRoot`'s artifact cache, rather than installing it, and
+    /// return its fully qualified ident. No spec file is written. For
+    /// the Supervisor to install the package, the public half of
+    /// `signing_key` has to be in its key cache.
+    pub async fn build_artifact(mut self,
+                                signing_key: &SecretOriginSigningKey)
+                                -> Result<PackageIdent> {
+        let hab_root = self.hab_root;
+        self.spec = false;
+        let ident = self.build().await?;
+
+        let pkg_dir = hab_root.installed_pkg_dir_path(&ident);
+        let artifact_dir = hab_root.artifact_cache_path();
+        fs::create_dir_all(&artifact_dir).await
+                                         .context("Failed to create artifact cache")?;
+        let artifact_path = artifact_dir.join(ident.archive_name()?);
+        write_artifact(hab_root.as_ref(), &pkg_dir, &artifact_path, signing_key)
+            .with_context(|| format!("Failed to write artifact of {}", ident))?;
+        fs::remove_dir_all(&pkg_dir).await.with_context(|| {
+                                               format!("Failed to remove installed package '{}'",
+                                                       pkg_dir.display())
+                                           })?;
+        Ok(ident)
+    }
+
+    fn qualified_ident(&self, ident: &str) -> Result<PackageIdent> {
+        let ident = PackageIdent::from_str(ident).with_context(|| {
+                                                     format!("Invalid fixture package ident '{}'",
+                                                             ident)
+                                                 })?;
+        match (&ident.version, &ident.release) {
+            (Some(_), Some(_)) => Ok(ident),
+            (None, None) => Ok(self.hab_root.pkg_ident(&ident.origin, &ident.name)),
+            _ => {
+                Err(anyhow!("Fixture package ident '{}' needs a release as \
+                             well as a version",
+                            ident))
+            }
+        }
+    }
+
+    /// Write a spec file like those of the fixtures on disk, pinned to
+    /// exactly this package.
+    fn write_spec(&self, ident: &PackageIdent) -> Result<()> {
+        let mut spec = fixture_spec(&ident.origin, &ident.name);
+        spec.ident = ident.clone();
+        self.hab_root.write_spec(&self.service_group, &spec)
+    }
+}
+
+/// Pack the installed package in `pkg_dir` into a signed artifact at
+/// `artifact_path`, with its files at the same paths relative to
+/// `fs_root` as they're installed at.
+fn write_artifact(fs_root: &Path,
+                  pkg_dir: &Path,
+                  artifact_path: &Path,
+                  signing_key: &SecretOriginSigningKey)
+                  -> Result<()> {
+    let payload = NamedTempFile::new()?;
+    let mut tar = tar::Builder::new(XzEncoder::new(payload.reopen()?, 6));
+    tar.append_dir_all(pkg_dir.strip_prefix(fs_root)?, pkg_dir)?;
+    tar.into_inner()?.finish()?;
+    artifact::sign(payload.path(), artifact_path, signing_key)?;
+    Ok(())
+}
+
+/// Write a hook into the package's `hooks` directory, executable by
+/// its owner and everyone else.
+async fn write_hook(pkg_dir: &Path, name: &str, script: &str) -> Result<()> {
+    let path = pkg_dir.join("hooks").join(name);
+    fs::write(&path, script).await
+                            .with_context(|| format!("Failed to write {} hook", name))?;
+    #[cfg(not(windows))]
+    {
+        use std::{fs::Permissions,
+                  os::unix::fs::PermissionsExt};
+        fs::set_permissions(&path, Permissions::from_mode(0o755)).await
+                                                                 .with_context(|| {
+                                                                     format!("Failed to make {} \
+                                                                              hook executable",
+                                                                             name)
+                                                                 })?;
+    }
+    Ok(())
+}
@@ -1,68 +1,139 @@
 use anyhow::{anyhow,
-             bail,
              Context,
              Result};
 #[cfg(any(all(target_os = "linux",
                   any(target_arch = "x86_64", target_arch = "aarch64")),
               all(target_os = "windows", target_arch = "x86_64")))]
 use habitat_core::package::PackageTarget;
-use habitat_core::{crypto::Blake2bHash,
-                   package::PackageInstall,
-                   users};
-use std::{collections::{BinaryHeap,
-                        VecDeque},
+use habitat_core::{crypto::{hash_directory_filtered,
+                            HashedEntry},
+                   fs::{copy_dir_async,
+                        CopyDirOptions,
+                        Overwrite},
+                   package::{metadata::MetaFile,
+                             PackageIdent,
+                             PackageInstall},
+                   service::{ServiceBind,
+                             ServiceGroup},
+                   users,
+                   ChannelIdent};
+use habitat_sup::manager::service::{spec::ServiceSpec,
+                                    Topology,
+                                    UpdateStrategy};
+use std::{fmt,
+          fs::Metadata,
+          io,
+          num::NonZeroUsize,
           path::{Path,
                  PathBuf},
+          thread,
           time::{Duration,
-                 SystemTime}};
+                 SystemTime,
+                 UNIX_EPOCH}};
 use tokio::{fs::{self,
                  File},
             io::AsyncWriteExt,
+            task,
             time::Instant};
 
 use super::{FixtureRoot,
             HabRoot};
 use glob::Pattern;
 
+/// How often `FileSystemSnapshot::wait_for_modification` and
+/// `wait_for_creation` look at the file they're waiting on.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Files bigger than this aren't diffed when they're updated.
+const DIFF_SIZE_LIMIT: u64 = 8 * 1024;
+
+/// How many unchanged lines are shown around each change in a diff.
+const DIFF_CONTEXT_LINES: usize = 2;
+
+/// What services' `SVC_GROUP` is set to on Windows, where users have no
+/// primary group to default to. It's the group the Supervisor itself
+/// falls back to.
+#[cfg(windows)]
+pub const WINDOWS_SVC_GROUP: &str = "hab";
+
 /// A snapshot of the state of the folder.
 /// This is useful for test cases to verify only changes
 /// that are expected and understood have occurred.
 #[derive(Debug)]
 pub struct FileSystemSnapshot {
-    path:  PathBuf,
-    files: Vec<FileSnapshot>,
+    path:    PathBuf,
+    files:   Vec<FileSnapshot>,
+    filters: SnapshotFilters,
 }
 impl FileSystemSnapshot {
     pub async fn new(path: &Path) -> Result<FileSystemSnapshot> {
+        Self::new_filtered(path, Vec::new(), Vec::new()).await
+    }
+
+    /// Snapshot only part of the directory at `path`. Patterns are
+    /// matched against paths relative to `path`.
+    ///
+    /// With any `include` patterns, only files that match one, or are
+    /// in a directory that does, are snapshotted. Files and
+    /// directories that match any `exclude` pattern are left out,
+    /// along with everything in them. Directories that nothing can be
+    /// snapshotted in aren't read at all.
+    pub async fn new_filtered(path: &Path,
+                              include: Vec<Pattern>,
+                              exclude: Vec<Pattern>)
+                              -> Result<FileSystemSnapshot> {
+        let filters = SnapshotFilters { include, exclude };
         let mut files = Vec::new();
-        let mut queue = VecDeque::new();
-        queue.push_back(path.to_path_buf());
-        while let Some(root) = queue.pop_front() {
-            if root.is_dir() {
-                match fs::read_dir(&root).await {
-                    Ok(mut dir_entries) => {
-                        while let Some(entry) =
-                            dir_entries.next_entry()
-                                       .await
-                                       .context("Failed to read directory entry")?
-                        {
-                            let entry_path = entry.path();
-
-                            if entry_path.is_file() {
-                                files.push(FileSnapshot::new(entry_path).context("Failed to take file snapshot")?);
-                            } else if entry_path.is_dir() {
-                                queue.push_back(entry_path);
-                            }
-                        }
+        if path.is_dir() {
+            let root = path.to_path_buf();
+            let walk_filters = filters.clone();
+            let concurrency = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+            let hashes = task::spawn_blocking(move || {
+                             hash_directory_filtered(&root, concurrency, |entry, file_type| {
+                                 let relative =
+                                     entry.strip_prefix(&root).unwrap_or(entry).to_string_lossy();
+                                 walk_filters.admits(&relative, file_type.is_dir())
+                             })
+                         }).await
+                           .context("Failed to join directory hashing task")?
+                           .context("Failed to hash directory")?;
+            // Services may be writing while the snapshot is taken, so
+            // anything removed since the directory was listed is left
+            // out, rather than failing the whole snapshot.
+            for (file_path, hash) in hashes {
+                let hash = match hash {
+                    Ok(hash) => hash,
+                    Err(habitat_core::Error::IO(ref err))
+                        if err.kind() == io::ErrorKind::NotFound =>
+                    {
+                        continue
                     }
-                    Err(_) => continue,
-                }
-            } else if root.is_file() {
-                files.push(FileSnapshot::new(root).context("Failed to take file snapshot")?);
+                    Err(err) => {
+                        return Err(err).with_context(|| {
+                                           format!("Failed to take file snapshot of '{}'",
+                                                   file_path.display())
+                                       })
+                    }
+                };
+                let metadata = match file_path.symlink_metadata() {
+                    Ok(metadata) => metadata,
+                    Err(ref err) if err.kind() == io::ErrorKind::NotFound => continue,
+                    Err(err) => {
+                        return Err(err).with_context(|| {
+                                           format!("Failed to read file metadata of '{}'",
+                                                   file_path.display())
+                                       })
+                    }
+                };
+                files.push(FileSnapshot::with_metadata(file_path, hash, &metadata)?);
             }
+        } else if path.is_file() {
+            files.push(FileSnapshot::new(path.to_path_buf()).context("Failed to take file \
+                                                                      snapshot")?);
         }
         Ok(FileSystemSnapshot { path: path.to_path_buf(),
-                                files })
+                                files,
+                                filters })
     }
 
     pub fn file(&self, path: &str) -> Result<&FileSnapshot> {
@@ -72,6 +143,71 @@ impl FileSystemSnapshot {
             .with_context(|| format!("File not found '{}'", path))
     }
 
+    /// Wait for the file at `path` (relative to the snapshot's root)
+    /// to differ from how it was in this snapshot, in its contents or
+    /// modification time, and return a snapshot of it as it is then.
+    ///
+    /// Only that one file is looked at. It may go missing for a while
+    /// in the meantime, as it does when it's replaced by renaming
+    /// another file over it.
+    pub async fn wait_for_modification(&self,
+                                       path: &str,
+                                       timeout: Duration)
+                                       -> Result<FileSnapshot> {
+        let original = self.file(path)?;
+        self.wait_for_file(path, timeout, |current| {
+                current.hash != original.hash
+                || current.last_modified_at != original.last_modified_at
+            })
+            .await
+            .with_context(|| {
+                format!("File '{}' never changed from when it was snapshotted, {}",
+                        path,
+                        original.state())
+            })
+    }
+
+    /// Wait for a file that wasn't in this snapshot to appear at
+    /// `path` (relative to the snapshot's root), and return a snapshot
+    /// of it.
+    pub async fn wait_for_creation(&self, path: &str, timeout: Duration) -> Result<FileSnapshot> {
+        if self.file(path).is_ok() {
+            return Err(anyhow!("File '{}' was already in the snapshot", path));
+        }
+        self.wait_for_file(path, timeout, |_| true).await
+    }
+
+    /// Snapshot the file at `path` every `POLL_INTERVAL` until `done`
+    /// holds for it.
+    async fn wait_for_file(&self,
+                           path: &str,
+                           timeout: Duration,
+                           done: impl Fn(&FileSnapshot) -> bool)
+                           -> Result<FileSnapshot> {
+        let full_path = self.path.join(path);
+        let started_at = Instant::now();
+        let mut last_seen = None;
+        loop {
+            // Not being able to snapshot the file just means it isn't
+            // there (yet, or for the moment)
+            if let Ok(current) = FileSnapshot::new(full_path.clone()) {
+                if done(&current) {
+                    return Ok(current);
+                }
+                last_seen = Some(current);
+            }
+            if started_at.elapsed() > timeout {
+                return Err(anyhow!("Timed out after {:.2} secs waiting on file '{}'; \
+                                    it was last seen {}",
+                                   timeout.as_secs_f64(),
+                                   full_path.display(),
+                                   last_seen.map_or_else(|| "missing".to_string(),
+                                                         |snapshot| snapshot.state())));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
     pub fn modifications_since(&self,
                                other: &FileSystemSnapshot,
                                exclude: &[Pattern])
@@ -79,78 +215,124 @@ impl FileSystemSnapshot {
         if self.path != other.path {
             panic!("Cannot compare snapshot for different folders");
         }
+        if self.filters != other.filters {
+            panic!("Cannot compare snapshots taken with different filters: {:?} and {:?}",
+                   self.filters, other.filters);
+        }
 
-        // Some crazy functional programming magic, just because we can in rust :)
-        // This creates sorted vectors of files that were added, removed or updated
-        FileSystemModifications { added:
-                                      self.files
-                                          .iter()
-                                          .filter(|f| !other.files.iter().any(|o| o.path == f.path))
-                                          .filter_map(|f| {
-                                              f.path
-                                               .strip_prefix(self.path.as_path())
-                                               .unwrap()
-                                               .to_str()
-                                          })
-                                          .filter_map(|x| {
-                                              if exclude.iter().any(|p| p.matches(x)) {
-                                                  None
-                                              } else {
-                                                  Some(x.to_owned())
-                                              }
-                                          })
-                                          .collect::<BinaryHeap<String>>()
-                                          .into_sorted_vec(),
-                                  removed:
-                                      other.files
-                                           .iter()
-                                           .filter(|o| !self.files.iter().any(|f| o.path == f.path))
-                                           .filter_map(|f| {
-                                               f.path
-                                                .strip_prefix(self.path.as_path())
-                                                .unwrap()
-                                                .to_str()
-                                           })
-                                           .filter_map(|x| {
-                                               if exclude.iter().any(|p| p.matches(x)) {
-                                                   None
-                                               } else {
-                                                   Some(x.to_owned())
-                                               }
-                                           })
-                                           .collect::<BinaryHeap<String>>()
-                                           .into_sorted_vec(),
-                                  updated: self.files
-                                               .iter()
-                                               .filter_map(|f| {
-                                                   if let Some(old) =
-                                                       other.files
-                                                            .iter()
-                                                            .find(|of| of.path == f.path)
-                                                   {
-                                                       if old.hash != f.hash {
-                                                           f.path
-                                                            .strip_prefix(self.path.as_path())
-                                                            .unwrap()
-                                                            .to_str()
-                                                            .and_then(|x| {
-                                                                if exclude.iter()
-                                                                          .any(|p| p.matches(x))
-                                                                {
-                                                                    None
-                                                                } else {
-                                                                    Some(x.to_owned())
-                                                                }
-                                                            })
-                                                       } else {
-                                                           None
-                                                       }
-                                                   } else {
-                                                       None
-                                                   }
-                                               })
-                                               .collect::<BinaryHeap<String>>()
-                                               .into_sorted_vec(), }
+        let previous = |f: &FileSnapshot| other.files.iter().find(|o| o.path == f.path);
+        let mut modifications = FileSystemModifications::default();
+        for file in &self.files {
+            let path = match self.relative_path(file, exclude) {
+                Some(path) => path,
+                None => continue,
+            };
+            match previous(file) {
+                None => modifications.added.push(FileEntry::new(path, file)),
+                Some(old) if old.hash != file.hash => {
+                    modifications.updated
+                                 .push(UpdatedFile::new(path, old, file))
+                }
+                // A file whose contents changed is only reported as
+                // updated, whatever happened to its permissions and
+                // ownership
+                Some(old) if old.attributes != file.attributes => {
+                    modifications.attributes_updated.push(path)
+                }
+                Some(_) => (),
+            }
+        }
+        for file in &other.files {
+            if self.files.iter().any(|f| f.path == file.path) {
+                continue;
+            }
+            if let Some(path) = self.relative_path(file, exclude) {
+                modifications.removed.push(FileEntry::new(path, file));
+            }
+        }
+        modifications.added.sort_by(|a, b| a.path.cmp(&b.path));
+        modifications.removed.sort_by(|a, b| a.path.cmp(&b.path));
+        modifications.updated.sort_by(|a, b| a.path.cmp(&b.path));
+        modifications.attributes_updated.sort();
+        modifications
+    }
+
+    /// The path of `file` relative to the snapshot's root, unless it
+    /// matches any of `exclude`.
+    fn relative_path(&self, file: &FileSnapshot, exclude: &[Pattern]) -> Option<String> {
+        file.path
+            .strip_prefix(self.path.as_path())
+            .unwrap()
+            .to_str()
+            .filter(|x| !exclude.iter().any(|p| p.matches(x)))
+            .map(str::to_owned)
+    }
+}
+
+/// What parts of a directory a `FileSystemSnapshot` was taken of; see
+/// `FileSystemSnapshot::new_filtered`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct SnapshotFilters {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl SnapshotFilters {
+    /// Whether to snapshot the file, or look in the directory, at
+    /// `path`, relative to the snapshot's root.
+    fn admits(&self, path: &str, is_dir: bool) -> bool {
+        if self.exclude.iter().any(|p| p.matches(path)) {
+            return false;
+        }
+        if self.include.is_empty() {
+            return true;
+        }
+        let included =
+            Path::new(path).ancestors()
+                           .filter_map(Path::to_str)
+                           .any(|ancestor| self.include.iter().any(|p| p.matches(ancestor)));
+        included || (is_dir && self.include.iter().any(|p| could_match_beneath(p, path)))
+    }
+}
+
+/// Whether `pattern` could match anything beneath the directory `dir`,
+/// going by the part of the pattern before any wildcards.
+fn could_match_beneath(pattern: &Pattern, dir: &str) -> bool {
+    let pattern = pattern.as_str();
+    let literal = &pattern[..pattern.find(['*', '?', '[']).unwrap_or(pattern.len())];
+    let dir = format!("{}/", dir);
+    dir.starts_with(literal) || literal.starts_with(&dir)
+}
+
+/// The permissions and ownership of a file, as far as the platform
+/// has them.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct FileAttributes {
+    mode: u32,
+    uid:  Option<u32>,
+    gid:  Option<u32>,
+}
+
+impl FileAttributes {
+    #[cfg(unix)]
+    fn of(metadata: &Metadata) -> FileAttributes {
+        use std::os::unix::fs::MetadataExt;
+        FileAttributes { mode: metadata.mode() & 0o7777,
+                         uid:  Some(metadata.uid()),
+                         gid:  Some(metadata.gid()), }
+    }
+
+    #[cfg(not(unix))]
+    fn of(metadata: &Metadata) -> FileAttributes {
+        // The read-only flag is all there is to go on
+        let mode = if metadata.permissions().readonly() {
+            0o444
+        } else {
+            0o666
+        };
+        FileAttributes { mode,
+                         uid: None,
+                         gid: None }
     }
 }
 
@@ -158,20 +340,72 @@ impl FileSystemSnapshot {
 pub struct FileSnapshot {
     path:             PathBuf,
     last_modified_at: SystemTime,
-    hash:             Blake2bHash,
+    /// The hash of a file's contents, or the target of a symlink.
+    hash:             HashedEntry,
+    attributes:       FileAttributes,
+    size:             u64,
+    /// The contents of files no bigger than `DIFF_SIZE_LIMIT`, to
+    /// show how they changed.
+    contents:         Option<Vec<u8>>,
 }
 impl FileSnapshot {
     pub fn new(path: PathBuf) -> Result<FileSnapshot> {
+        let hash = HashedEntry::of(&path).context("Failed to hash file contents")?;
+        let metadata = path.symlink_metadata()
+                           .context("Failed to read file metadata")?;
+        Self::with_metadata(path, hash, &metadata)
+    }
+
+    /// Snapshot a file whose contents have already been hashed, and
+    /// metadata read.
+    fn with_metadata(path: PathBuf,
+                     hash: HashedEntry,
+                     metadata: &Metadata)
+                     -> Result<FileSnapshot> {
+        let size = metadata.len();
+        // Should the file be changing under us, the contents may not
+        // match the hash; they're only for showing to people, though.
+        let contents = match hash {
+            HashedEntry::File(_) if size <= DIFF_SIZE_LIMIT => std::fs::read(&path).ok(),
+            _ => None,
+        };
         Ok(FileSnapshot { last_modified_at:
-                              path.metadata()
-                                  .context("Failed to read file metadata")?
-                                  .modified()
-                                  .context("Failed to read file modification time")?,
-                          hash: Blake2bHash::from_file(&path).context("Failed to hash file \
-                                                                       contents")?,
+                              metadata.modified()
+                                      .context("Failed to read file modification time")?,
+                          hash,
+                          attributes: FileAttributes::of(metadata),
+                          size,
+                          contents,
                           path })
     }
 
+    /// The file's contents and modification time, for error messages.
+    fn state(&self) -> String {
+        format!("{}, modified at {}",
+                describe_hash(&self.hash),
+                describe_time(self.last_modified_at))
+    }
+
+    /// The file's permission bits. Symlinks don't have their own, so
+    /// theirs are meaningless.
+    pub fn mode(&self) -> u32 { self.attributes.mode }
+
+    /// The user that owns the file, on platforms with such a thing.
+    pub fn uid(&self) -> Option<u32> { self.attributes.uid }
+
+    /// The group that owns the file, on platforms with such a thing.
+    pub fn gid(&self) -> Option<u32> { self.attributes.gid }
+
+    pub fn is_symlink(&self) -> bool { matches!(self.hash, HashedEntry::Symlink(_)) }
+
+    /// Where the file points, if it's a symlink.
+    pub fn symlink_target(&self) -> Option<&Path> {
+        match self.hash {
+            HashedEntry::Symlink(ref target) => Some(target),
+            HashedEntry::File(_) => None,
+        }
+    }
+
     /// Reads the current contents of the file into a string
     pub async fn current_file_content(&self) -> Result<String> {
         String::from_utf8(fs::read(&self.path).await.context("Failed to read file contents")?).context("File contains non UTF-8 characters")
@@ -203,29 +437,271 @@ impl FileSnapshot {
     }
 }
 
+/// A file that's in only one of two snapshots being compared.
 #[derive(Debug)]
+pub struct FileEntry {
+    /// Relative to the snapshots' root
+    pub path: String,
+    pub hash: HashedEntry,
+    pub size: u64,
+}
+
+impl FileEntry {
+    fn new(path: String, file: &FileSnapshot) -> FileEntry {
+        FileEntry { path,
+                    hash: file.hash.clone(),
+                    size: file.size }
+    }
+}
+
+impl fmt::Display for FileEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f,
+               "{} ({}, {} bytes)",
+               self.path,
+               describe_hash(&self.hash),
+               self.size)
+    }
+}
+
+/// A file whose contents changed between two snapshots.
+#[derive(Debug)]
+pub struct UpdatedFile {
+    /// Relative to the snapshots' root
+    pub path:            String,
+    pub old_hash:        HashedEntry,
+    pub new_hash:        HashedEntry,
+    pub old_modified_at: SystemTime,
+    pub new_modified_at: SystemTime,
+    /// How the contents changed, if both versions are text no bigger
+    /// than `DIFF_SIZE_LIMIT`.
+    pub diff:            Option<String>,
+}
+
+impl UpdatedFile {
+    fn new(path: String, old: &FileSnapshot, new: &FileSnapshot) -> UpdatedFile {
+        let text = |file: &FileSnapshot| {
+            file.contents
+                .as_ref()
+                .and_then(|contents| std::str::from_utf8(contents).ok())
+                .map(str::to_owned)
+        };
+        let diff = match (text(old), text(new)) {
+            (Some(old), Some(new)) => Some(text_diff(&old, &new)),
+            _ => None,
+        };
+        UpdatedFile { path,
+                      old_hash: old.hash.clone(),
+                      new_hash: new.hash.clone(),
+                      old_modified_at: old.last_modified_at,
+                      new_modified_at: new.last_modified_at,
+                      diff }
+    }
+}
+
+impl fmt::Display for UpdatedFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.path)?;
+        writeln!(f,
+                 "    was {}, modified at {}",
+                 describe_hash(&self.old_hash),
+                 describe_time(self.old_modified_at))?;
+        write!(f,
+               "    now {}, modified at {}",
+               describe_hash(&self.new_hash),
+               describe_time(self.new_modified_at))?;
+        if let Some(ref diff) = self.diff {
+            for line in diff.lines() {
+                write!(f, "\n    {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How the files beneath a directory changed between two snapshots;
+/// see `FileSystemSnapshot::modifications_since`. Its `Display` is a
+/// report of all of it, for failing tests to show.
+#[derive(Debug, Default)]
 pub struct FileSystemModifications {
-    added:   Vec<String>,
-    removed: Vec<String>,
-    updated: Vec<String>,
+    added:              Vec<FileEntry>,
+    removed:            Vec<FileEntry>,
+    /// Files whose contents (or, for symlinks, targets) changed,
+    /// including files replaced by symlinks and vice versa.
+    updated:            Vec<UpdatedFile>,
+    /// Files whose permissions or ownership changed, but whose
+    /// contents didn't.
+    attributes_updated: Vec<String>,
 }
 
 impl FileSystemModifications {
-    pub fn added(&self) -> Vec<&str> { self.added.iter().map(String::as_str).collect() }
+    pub fn added(&self) -> Vec<&str> { self.added.iter().map(|f| f.path.as_str()).collect() }
 
-    pub fn removed(&self) -> Vec<&str> { self.removed.iter().map(String::as_str).collect() }
+    pub fn removed(&self) -> Vec<&str> { self.removed.iter().map(|f| f.path.as_str()).collect() }
 
-    pub fn updated(&self) -> Vec<&str> { self.updated.iter().map(String::as_str).collect() }
+    pub fn updated(&self) -> Vec<&str> { self.updated.iter().map(|f| f.path.as_str()).collect() }
+
+    pub fn attributes_updated(&self) -> Vec<&str> {
+        self.attributes_updated.iter().map(String::as_str).collect()
+    }
+
+    pub fn added_files(&self) -> &[FileEntry] { &self.added }
+
+    pub fn removed_files(&self) -> &[FileEntry] { &self.removed }
+
+    pub fn updated_files(&self) -> &[UpdatedFile] { &self.updated }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+        && self.removed.is_empty()
+        && self.updated.is_empty()
+        && self.attributes_updated.is_empty()
+    }
+}
+
+impl fmt::Display for FileSystemModifications {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "No files were modified");
+        }
+        let mut sections = Vec::new();
+        if !self.added.is_empty() {
+            sections.push(format!("Added:\n{}", list(&self.added)));
+        }
+        if !self.removed.is_empty() {
+            sections.push(format!("Removed:\n{}", list(&self.removed)));
+        }
+        if !self.updated.is_empty() {
+            sections.push(format!("Updated:\n{}", list(&self.updated)));
+        }
+        if !self.attributes_updated.is_empty() {
+            sections.push(format!("Permissions or ownership updated:\n{}",
+                                  list(&self.attributes_updated)));
+        }
+        write!(f, "{}", sections.join("\n"))
+    }
+}
+
+/// One item per line, indented under a heading.
+fn list(items: &[impl fmt::Display]) -> String {
+    items.iter()
+         .map(|item| format!("  {}", item))
+         .collect::<Vec<_>>()
+         .join("\n")
+}
+
+fn describe_hash(hash: &HashedEntry) -> String {
+    match hash {
+        HashedEntry::File(hash) => format!("hash {}", hash),
+        HashedEntry::Symlink(target) => format!("symlink to '{}'", target.display()),
+    }
+}
+
+fn describe_time(time: SystemTime) -> String {
+    format!("{:.3} secs since the epoch",
+            time.duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64())
+}
+
+/// A line-by-line diff of `old` and `new`, in the style of `diff -u`
+/// but without line numbers.
+fn text_diff(old: &str, new: &str) -> String {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+
+    // `common[i][j]` is the length of the longest common subsequence
+    // of `old[i..]` and `new[j..]`. Texts are no bigger than
+    // `DIFF_SIZE_LIMIT`, so this stays small enough.
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(('-', old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', new[j]));
+            j += 1;
+        }
+    }
+
+    // Only changes, and a few lines around them, are shown
+    let near_change = |k: usize| {
+        let start = k.saturating_sub(DIFF_CONTEXT_LINES);
+        let end = (k + DIFF_CONTEXT_LINES + 1).min(lines.len());
+        lines[start..end].iter().any(|(op, _)| *op != ' ')
+    };
+    let mut diff = vec!["--- before".to_string(), "+++ after".to_string()];
+    let mut in_hunk = false;
+    for (k, (op, line)) in lines.iter().enumerate() {
+        if near_change(k) {
+            if !in_hunk {
+                diff.push("@@".to_string());
+            }
+            diff.push(format!("{}{}", op, line));
+        }
+        in_hunk = near_change(k);
+    }
+    diff.join("\n")
+}
+
+/// How `setup_package_files` copies a fixture package into `hab_root`.
+fn fixture_copy_options(hab_root: &HabRoot) -> CopyDirOptions {
+    CopyDirOptions { follow_symlinks:      false,
+                     preserve_mtimes:      false,
+                     preserve_permissions: true,
+                     overwrite:            Overwrite::Always,
+                     mirror:               true,
+                     mirror_root:          Some(hab_root.as_ref().to_path_buf()), }
 }
 
 /// Copy fixture package files from `fixture_root` over to `hab_root`
 /// in the appropriate places for the Supervisor to find them.
+///
+/// Packages end up exact copies of their fixtures, so calling this
+/// again resets them, whatever an earlier phase of a test did to them.
 pub async fn setup_package_files(origin_name: &str,
                                  package_name: &str,
                                  service_group: &str,
                                  fixture_root: &FixtureRoot,
                                  hab_root: &HabRoot)
                                  -> Result<()> {
+    setup_package_files_as(origin_name,
+                           package_name,
+                           service_group,
+                           fixture_root,
+                           hab_root,
+                           None,
+                           None).await
+}
+
+/// Like `setup_package_files`, but with the service run as `svc_user`
+/// and `svc_group` where they're given, whatever the fixture's
+/// metafiles or the defaults would have it run as. Both have to exist
+/// on this system; setup fails if they don't, rather than the service
+/// failing to start later on.
+pub async fn setup_package_files_as(origin_name: &str,
+                                    package_name: &str,
+                                    service_group: &str,
+                                    fixture_root: &FixtureRoot,
+                                    hab_root: &HabRoot,
+                                    svc_user: Option<&str>,
+                                    svc_group: Option<&str>)
+                                    -> Result<()> {
     let origin_name = origin_name.to_string();
     let package_name = package_name.to_string();
     let service_group = service_group.to_string();
@@ -233,17 +709,19 @@ pub async fn setup_package_files(origin_name: &str,
     // Copy the expanded package directory over
     let expanded_fixture_dir = fixture_root.expanded_package_dir(&package_name);
     let hab_pkg_path = hab_root.pkg_dir_path(&origin_name, &package_name);
-    copy_dir(&expanded_fixture_dir, &hab_pkg_path).await
+    copy_dir_async(&expanded_fixture_dir,
+                   &hab_pkg_path,
+                   fixture_copy_options(hab_root)).await
                                                   .with_context(|| {
                                                       format!("Failed to copy fixture directory \
                                                                '{}' to '{}'",
                                                               expanded_fixture_dir.display(),
                                                               hab_pkg_path.display())
                                                   })?;
-    write_default_metafiles(hab_root, &origin_name, &package_name).await
-                                                                  .context("Failed to write \
-                                                                            default files for \
-                                                                            service")?;
+    write_default_metafiles(&hab_pkg_path, svc_user, svc_group).await
+                                                               .context("Failed to write \
+                                                                         default files for \
+                                                                         service")?;
 
     let install =
         PackageInstall::load(&hab_root.pkg_ident(&origin_name, &package_name),
@@ -255,112 +733,133 @@ pub async fn setup_package_files(origin_name: &str,
         for dependency in tdeps.iter() {
             let fixture_dir = fixture_root.expanded_package_dir(&dependency.name);
             let pkg_path = hab_root.pkg_dir_path(&dependency.origin, &dependency.name);
-            copy_dir(&fixture_dir, &pkg_path).await.with_context(|| {
-                                                        format!("Failed to copy transitive \
-                                                                 dependency directory '{}' to '{}'",
-                                                                fixture_dir.display(),
-                                                                pkg_path.display())
-                                                    })?;
-            write_default_metafiles(hab_root, &dependency.origin, &dependency.name).await.context("Failed to write meta files for native package")?;
+            let options = fixture_copy_options(hab_root);
+            copy_dir_async(&fixture_dir, &pkg_path, options).await
+                                                            .with_context(|| {
+                                                                format!("Failed to copy \
+                                                                         transitive dependency \
+                                                                         directory '{}' to '{}'",
+                                                                        fixture_dir.display(),
+                                                                        pkg_path.display())
+                                                            })?;
+            write_default_metafiles(&pkg_path, None, None).await
+                                                          .context("Failed to write meta files \
+                                                                    for native package")?;
         }
     }
 
-    // Ensure the directory for the spec files exists
-    let spec_dir = hab_root.spec_dir_path(&service_group);
-    fs::create_dir_all(spec_dir).await
-                                .context("Could not create spec directory")?;
-
-    // Copy the spec file over
+    // Stage the fixture's spec file, or one like it if it hasn't got
+    // one, running the package in `service_group`
     let spec_source = fixture_root.spec_path(&package_name);
-    let spec_destination = hab_root.spec_path(&package_name, &service_group);
-    if !spec_source.exists() {
-        return Err(anyhow!("Missing a spec file at {}", spec_source.display()));
-    }
-    fs::copy(&spec_source, &spec_destination).await
-                                             .with_context(|| {
-                                                 format!("Failed to copy '{}' to '{}'",
-                                                         spec_source.display(),
-                                                         spec_destination.display())
-                                             })?;
-    Ok(())
+    let spec = if spec_source.exists() {
+        ServiceSpec::from_file(&spec_source).with_context(|| {
+                                                format!("Failed to read spec file '{}'",
+                                                        spec_source.display())
+                                            })?
+    } else {
+        fixture_spec(&origin_name, &package_name)
+    };
+    hab_root.write_spec(&service_group, &spec)
 }
 
-/// Recursively copy the contents of `source_dir` into `dest_dir`
-pub async fn copy_dir<S, D>(source_dir: S, dest_dir: D) -> Result<()>
-    where S: AsRef<Path>,
-          D: AsRef<Path>
-{
-    let source_dir = source_dir.as_ref().to_path_buf();
-    if !source_dir.exists() {
-        bail!("Source directory '{}' does not exist", source_dir.display());
-    }
-    if !source_dir.is_dir() {
-        bail!("Source directory '{}' is not a directory",
-              source_dir.display());
-    }
-    let dest_dir = dest_dir.as_ref().to_path_buf();
-
-    let mut queue = VecDeque::new();
-    queue.push_back((source_dir, dest_dir));
-    while let Some((source_dir, dest_dir)) = queue.pop_front() {
-        fs::create_dir_all(&dest_dir).await.with_context(|| {
-                                                format!("Could not create directory '{}'",
-                                                        dest_dir.display())
-                                            })?;
-        let mut source_dir_entries =
-            fs::read_dir(&source_dir).await.with_context(|| {
-                                                format!("Could not read entries in '{}'",
-                                                        source_dir.display())
-                                            })?;
-        while let Some(entry) =
-            source_dir_entries.next_entry().await.with_context(|| {
-                                                      format!("Failed to read entries for \
-                                                               directory '{}'",
-                                                              source_dir.display())
-                                                  })?
-        {
-            let source = entry.path();
-            let destination = dest_dir.join(source.file_name().unwrap());
-
-            if source.is_file() {
-                fs::copy(&source, &destination).await.with_context(|| {
-                                                          format!("Could not copy '{}' to '{}'",
-                                                                  source.display(),
-                                                                  destination.display())
-                                                      })?;
-            } else if source.is_dir() {
-                queue.push_back((source, destination));
-            }
+/// A spec like those of the fixtures on disk: a standalone service
+/// that's never updated. `setup_package_files` uses it for fixtures
+/// without a spec file of their own, and tests can adjust it and
+/// stage it with `HabRoot::write_spec`.
+pub fn fixture_spec(origin_name: &str, package_name: &str) -> ServiceSpec {
+    let mut spec = ServiceSpec::new(PackageIdent::new(origin_name, package_name, None, None));
+    spec.bldr_url = "http://hab.sup.test".to_string();
+    spec.channel = ChannelIdent::unstable();
+    spec.topology = Topology::Standalone;
+    spec.update_strategy = UpdateStrategy::None;
+    spec
+}
+
+/// Stage several services with `setup_package_files`, each given as
+/// `(origin, package, service_group)`, and bind them to each other.
+///
+/// Each bind is given as `(consumer, bind name, producer)`, naming the
+/// packages of two of `services`. The consumer's spec is given a bind
+/// to the producer's service group. Binds to or from services that
+/// aren't being staged are refused before anything is copied.
+pub async fn setup_bound_services(services: &[(&str, &str, &str)],
+                                  binds: &[(&str, &str, &str)],
+                                  fixture_root: &FixtureRoot,
+                                  hab_root: &HabRoot)
+                                  -> Result<()> {
+    let staged_group = |package: &str| {
+        services.iter()
+                .find(|(_, p, _)| *p == package)
+                .map(|(_, _, group)| *group)
+    };
+    let staged = || {
+        services.iter()
+                .map(|(_, p, _)| *p)
+                .collect::<Vec<_>>()
+                .join(", ")
+    };
+    for (consumer, name, producer) in binds {
+        if staged_group(*consumer).is_none() {
+            return Err(anyhow!("Bind '{}' is from '{}', which is not one of the \
+                                services being set up ({})",
+                               name,
+                               consumer,
+                               staged()));
+        }
+        if staged_group(*producer).is_none() {
+            return Err(anyhow!("Bind '{}' of '{}' is to '{}', which is not one \
+                                of the services being set up ({})",
+                               name,
+                               consumer,
+                               producer,
+                               staged()));
         }
     }
+
+    for (origin, package, service_group) in services {
+        setup_package_files(origin, package, service_group, fixture_root, hab_root).await?;
+        let mut spec = hab_root.read_spec(service_group, package)?;
+        spec.binds = binds.iter()
+                          .filter(|(consumer, ..)| consumer == package)
+                          .map(|(_, name, producer)| -> Result<ServiceBind> {
+                              let group = staged_group(*producer).unwrap();
+                              Ok(ServiceBind::new(name, ServiceGroup::new(*producer, group, None)?))
+                          })
+                          .collect::<Result<_>>()?;
+        hab_root.write_spec(service_group, &spec)?;
+    }
     Ok(())
 }
 
-/// Write default `SVC_USER` and `SVC_GROUP` package metafiles unless one is already present in
-/// the target directory.
+/// Write `SVC_USER` and `SVC_GROUP` package metafiles, with `svc_user` and `svc_group` where
+/// they're given, and default values for any that aren't and aren't already present in the
+/// target directory.
 ///
 /// In an effort to execute a package when running test suites as a non-root user, the current
-/// username and the user's primary groupname will be used. If a fixture contains one or both of
-/// these metafiles, default values will *not* be used.
-async fn write_default_metafiles(hab_root: &HabRoot,
-                                 pkg_origin: &str,
-                                 pkg_name: &str)
-                                 -> Result<()> {
-    let svc_user_metafile = hab_root.svc_user_path(pkg_origin, pkg_name);
-    let svc_group_metafile = hab_root.svc_group_path(pkg_origin, pkg_name);
+/// username and the user's primary groupname will be used by default. If a fixture contains one
+/// or both of these metafiles, default values will *not* be used, but overrides still will be.
+/// Windows has no primary groups, so `WINDOWS_SVC_GROUP` is used there instead.
+pub async fn write_default_metafiles(pkg_dir: &Path,
+                                     svc_user: Option<&str>,
+                                     svc_group: Option<&str>)
+                                     -> Result<()> {
+    check_svc_user_and_group(svc_user, svc_group)?;
+    let svc_user_metafile = pkg_dir.join(MetaFile::SvcUser.to_string());
+    let svc_group_metafile = pkg_dir.join(MetaFile::SvcGroup.to_string());
 
-    if !svc_user_metafile.is_file() {
+    if let Some(user) = svc_user {
+        write_metafile(svc_user_metafile, user).await?;
+    } else if !svc_user_metafile.is_file() {
         write_metafile(svc_user_metafile,
                        users::get_current_username().context("Failed to get username")?
                                                     .context("No username found")?
                                                     .as_str()).await?;
     }
 
-    if !svc_group_metafile.is_file() {
-        write_metafile(svc_group_metafile,
-                       users::get_current_groupname().context("Failed to get groupname")?
-                                                     .context("No groupname found")?
-                                                     .as_str()).await?;
+    if let Some(group) = svc_group {
+        write_metafile(svc_group_metafile, group).await?;
+    } else if !svc_group_metafile.is_file() {
+        write_metafile(svc_group_metafile, &default_svc_group()?).await?;
     }
 
     // Write metafiles to convert the package to a native package on platforms without package
@@ -372,7 +871,7 @@ async fn write_default_metafiles(hab_root: &HabRoot,
     #[cfg(not(any(all(target_os = "linux", any(target_arch = "x86_64")),
                   all(target_os = "windows", target_arch = "x86_64"))))]
     {
-        let pkg_type_metafile = hab_root.pkg_type_path(pkg_origin, pkg_name);
+        let pkg_type_metafile = pkg_dir.join(MetaFile::PackageType.to_string());
         write_metafile(pkg_type_metafile, "native").await?;
     }
     // Write the TARGET metafile on all platforms that we support building packages for
@@ -380,7 +879,7 @@ async fn write_default_metafiles(hab_root: &HabRoot,
                   any(target_arch = "x86_64", target_arch = "aarch64")),
               all(target_os = "windows", target_arch = "x86_64")))]
     {
-        let target_metafile = hab_root.target_path(pkg_origin, pkg_name);
+        let target_metafile = pkg_dir.join(MetaFile::Target.to_string());
         write_metafile(target_metafile,
                        PackageTarget::active_target().to_string().as_str()).await?;
     }
@@ -388,8 +887,47 @@ async fn write_default_metafiles(hab_root: &HabRoot,
     Ok(())
 }
 
+/// The group that services run as unless told otherwise: the current
+/// user's primary group.
+#[cfg(not(windows))]
+fn default_svc_group() -> Result<String> {
+    users::get_current_groupname().context("Failed to get groupname")?
+                                  .context("No groupname found")
+}
+
+/// The group that services run as unless told otherwise. The
+/// Supervisor ignores `SVC_GROUP` on Windows, but only takes `SVC_USER`
+/// from packages that have both, so a group still has to be written.
+#[cfg(windows)]
+fn default_svc_group() -> Result<String> { Ok(WINDOWS_SVC_GROUP.to_string()) }
+
+/// Fail unless `user` and `group` exist on this system, so that tests
+/// asking for them find out at setup, rather than when their service
+/// fails to start.
+fn check_svc_user_and_group(user: Option<&str>, group: Option<&str>) -> Result<()> {
+    if let Some(user) = user {
+        let uid = users::get_uid_by_name(user).with_context(|| {
+                                                  format!("Failed to look up service user '{}'",
+                                                          user)
+                                              })?;
+        if uid.is_none() {
+            return Err(anyhow!("Service user '{}' does not exist on this system", user));
+        }
+    }
+    if let Some(group) = group {
+        let gid = users::get_gid_by_name(group).with_context(|| {
+                                                   format!("Failed to look up service group '{}'",
+                                                           group)
+                                               })?;
+        if gid.is_none() {
+            return Err(anyhow!("Service group '{}' does not exist on this system", group));
+        }
+    }
+    Ok(())
+}
+
 /// Write package metafile with provided content.
-async fn write_metafile<P>(metafile: P, content: &str) -> Result<()>
+pub async fn write_metafile<P>(metafile: P, content: &str) -> Result<()>
     where P: AsRef<Path>
 {
     let mut f = File::create(&metafile).await.with_context(|| {
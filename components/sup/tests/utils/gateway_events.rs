@@ -0,0 +1,126 @@
+//! Follow the server-sent events the Supervisor's HTTP gateway streams
+//! from `/events`, rather than polling `/services` for changes:
+//!
+//!       let mut events = test_sup.subscribe_events().await?;
+//!       events.wait_for(|e| e.is("up", "redis.default"), timeout).await?;
+//!
+//! The stream starts with a "snapshot" event for every service loaded
+//! when it was subscribed to.
+use anyhow::{anyhow,
+             Context,
+             Result};
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// An event about a service, as `ServiceEvent` is shown by the
+/// gateway.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct ServiceEvent {
+    /// e.g. "snapshot", "up" or "health_check"
+    pub event:         String,
+    /// e.g. "redis.default"
+    pub service_group: String,
+    /// "loaded", "up", "down", "restarting" or "unloaded"
+    pub state:         String,
+    /// e.g. "Ok", once the service has had a health check
+    #[serde(default)]
+    pub health:        Option<String>,
+    /// In milliseconds since the epoch.
+    pub timestamp:     u64,
+}
+
+impl ServiceEvent {
+    pub fn is(&self, event: &str, service_group: &str) -> bool {
+        self.event == event && self.service_group == service_group
+    }
+}
+
+pub struct EventStream {
+    response: reqwest::Response,
+    /// What's been read of the stream that doesn't make up a whole
+    /// event yet.
+    buffer:   String,
+    /// Every event read so far, for showing what was seen when an
+    /// expected event doesn't turn up.
+    seen:     Vec<ServiceEvent>,
+}
+
+impl EventStream {
+    pub fn new(response: reqwest::Response) -> Self {
+        EventStream { response,
+                      buffer: String::new(),
+                      seen: Vec::new() }
+    }
+
+    /// The next event, waiting up to `timeout` for one to be sent.
+    pub async fn next_event(&mut self, timeout: Duration) -> Result<ServiceEvent> {
+        tokio::time::timeout(timeout, self.read_event()).await
+                                                        .map_err(|_| {
+                                                            anyhow!("No event was sent within \
+                                                                     {:.2} secs",
+                                                                    timeout.as_secs_f64())
+                                                        })?
+    }
+
+    /// Read events until one that `predicate` holds for, and return
+    /// it. On timeout, the error lists every event seen.
+    pub async fn wait_for<F>(&mut self, predicate: F, timeout: Duration) -> Result<ServiceEvent>
+        where F: Fn(&ServiceEvent) -> bool
+    {
+        let started_at = Instant::now();
+        loop {
+            let remaining = timeout.checked_sub(started_at.elapsed())
+                                   .unwrap_or_default();
+            match self.next_event(remaining).await {
+                Ok(event) if predicate(&event) => return Ok(event),
+                Ok(_) => {}
+                Err(err) => {
+                    return Err(err.context(format!("Expected event was not sent; saw:\n{:#?}",
+                                                   self.seen)))
+                }
+            }
+        }
+    }
+
+    async fn read_event(&mut self) -> Result<ServiceEvent> {
+        loop {
+            if let Some(end) = self.buffer.find("\n\n") {
+                let message = self.buffer[..end].to_string();
+                self.buffer.drain(..end + 2);
+                let event = parse_event(&message)?;
+                self.seen.push(event.clone());
+                return Ok(event);
+            }
+            let chunk = self.response
+                            .chunk()
+                            .await
+                            .context("Failed to read from HTTP gateway event stream")?
+                            .ok_or_else(|| anyhow!("HTTP gateway event stream ended"))?;
+            self.buffer.push_str(&String::from_utf8_lossy(&chunk));
+        }
+    }
+}
+
+/// Parse a server-sent event, whose name has to match the one in its
+/// data.
+fn parse_event(message: &str) -> Result<ServiceEvent> {
+    let field = |name: &str| {
+        message.lines()
+               .find_map(|line| line.strip_prefix(name))
+               .map(str::trim)
+               .ok_or_else(|| anyhow!("Server-sent event without {}: '{}'", name, message))
+    };
+    let name = field("event:")?;
+    let event: ServiceEvent =
+        serde_json::from_str(field("data:")?).with_context(|| {
+                                                 format!("Failed to parse server-sent event '{}'",
+                                                         message)
+                                             })?;
+    if event.event != name {
+        return Err(anyhow!("Server-sent event named '{}' has data for '{}'",
+                           name,
+                           event.event));
+    }
+    Ok(event)
+}
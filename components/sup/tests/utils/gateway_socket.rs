@@ -0,0 +1,102 @@
+//! Reach an HTTP gateway served on a Unix domain socket.
+//!
+//! reqwest can't connect to a Unix domain socket itself, so connections
+//! to a local TCP port are relayed to the socket instead. A `TestSup`
+//! points its HTTP client at that port, and all of its gateway helpers
+//! work just as they do against a gateway listening on TCP.
+use anyhow::{anyhow,
+             Context,
+             Result};
+use std::{io,
+          net::{Ipv4Addr,
+                SocketAddrV4},
+          path::{Path,
+                 PathBuf},
+          time::Duration};
+use tokio::{net::{TcpListener,
+                  UnixStream},
+            task::JoinHandle,
+            time::Instant};
+
+/// Relays connections to a local TCP port to a Unix domain socket,
+/// until it's dropped. The socket doesn't have to exist yet, or stay
+/// around: each connection is relayed to whatever is listening on it
+/// at the time, and dropped if nothing is.
+#[derive(Debug)]
+pub struct GatewaySocketRelay {
+    path: PathBuf,
+    task: JoinHandle<()>,
+}
+
+impl GatewaySocketRelay {
+    pub async fn start(port: u16, path: PathBuf) -> Result<Self> {
+        let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port))
+            .await
+            .with_context(|| format!("Failed to bind gateway socket relay to port {}", port))?;
+        let socket_path = path.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((mut tcp, _)) = listener.accept().await {
+                let socket_path = socket_path.clone();
+                tokio::spawn(async move {
+                    if let Ok(mut socket) = UnixStream::connect(&socket_path).await {
+                        let _ = tokio::io::copy_bidirectional(&mut tcp, &mut socket).await;
+                    }
+                });
+            }
+        });
+        Ok(GatewaySocketRelay { path, task })
+    }
+
+    /// The socket connections are relayed to.
+    pub fn path(&self) -> &Path { &self.path }
+}
+
+impl Drop for GatewaySocketRelay {
+    fn drop(&mut self) { self.task.abort(); }
+}
+
+/// Wait for something to be listening on the socket at `path`.
+pub async fn await_socket(path: &Path, timeout: Duration) -> Result<()> {
+    let started_at = Instant::now();
+    loop {
+        match UnixStream::connect(path).await {
+            Ok(_) => return Ok(()),
+            Err(err)
+                if err.kind() == io::ErrorKind::NotFound
+                   || err.kind() == io::ErrorKind::ConnectionRefused =>
+            {
+                if started_at.elapsed() > timeout {
+                    return Err(anyhow!("Timed out waiting for socket {} to open up",
+                                       path.display()));
+                }
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+            Err(err) => {
+                return Err(anyhow!(err)).with_context(|| {
+                                            format!("Failed to connect to socket {}",
+                                                    path.display())
+                                        })
+            }
+        }
+    }
+}
+
+/// Wait for nothing to be listening on the socket at `path`, whether
+/// or not the file is left behind.
+pub async fn await_socket_closed(path: &Path, timeout: Duration) -> Result<()> {
+    let started_at = Instant::now();
+    loop {
+        match UnixStream::connect(path).await {
+            Err(err)
+                if err.kind() == io::ErrorKind::NotFound
+                   || err.kind() == io::ErrorKind::ConnectionRefused =>
+            {
+                return Ok(())
+            }
+            _ if started_at.elapsed() > timeout => {
+                return Err(anyhow!("Timed out waiting for socket {} to close", path.display()));
+            }
+            _ => tokio::time::sleep(Duration::from_millis(500)).await,
+        }
+    }
+}
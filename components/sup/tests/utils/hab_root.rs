@@ -4,15 +4,22 @@
 //! the (real) filesystem, which is deleted when the `HabRoot`
 //! instance is dropped.
 
-use crate::hcore::{fs::PKG_PATH,
+use crate::hcore::{fs::{cache_artifact_path,
+                        PKG_PATH},
                    package::{metadata::MetaFile,
                              PackageIdent}};
+use anyhow::{anyhow,
+             Context,
+             Result};
+use habitat_sup::manager::service::spec::ServiceSpec;
 
 use std::{path::{Path,
                  PathBuf},
-          string::ToString};
+          string::ToString,
+          time::Duration};
 use tempfile::{Builder,
                TempDir};
+use tokio::time::Instant;
 
 #[derive(Debug)]
 pub struct HabRoot(TempDir);
@@ -32,13 +39,17 @@ impl HabRoot {
     /// We assign a hard-coded version and release, because
     /// they aren't important for the things we're currently testing
     pub fn pkg_dir_path(&self, origin: &str, pkg_name: &str) -> PathBuf {
-        let ident = self.pkg_ident(origin, pkg_name);
+        self.installed_pkg_dir_path(&self.pkg_ident(origin, pkg_name))
+    }
 
+    /// Directory to which the "expanded package" files of `ident`,
+    /// which must be fully qualified, should be placed.
+    pub fn installed_pkg_dir_path(&self, ident: &PackageIdent) -> PathBuf {
         self.0
             .path()
             .join(PKG_PATH)
-            .join(ident.origin)
-            .join(ident.name)
+            .join(&ident.origin)
+            .join(&ident.name)
             .join(ident.version.as_ref().unwrap())
             .join(ident.release.as_ref().unwrap())
     }
@@ -51,51 +62,68 @@ impl HabRoot {
         PackageIdent::new(origin, pkg_name, Some("1.0.0"), Some("20170721000000"))
     }
 
-    /// Returns the path to the package type metafile for a given package.
-    // This is only used on non-x86 platforms
-    #[allow(dead_code)]
-    pub fn pkg_type_path(&self, origin: &str, pkg_name: &str) -> PathBuf {
-        self.pkg_dir_path(origin, pkg_name)
-            .join(MetaFile::PackageType.to_string())
-    }
-
-    /// Returns the path to the target metafile for a given package.
-    // This is only used on non-x86 platforms
-    #[allow(dead_code)]
-    pub fn target_path(&self, origin: &str, pkg_name: &str) -> PathBuf {
-        self.pkg_dir_path(origin, pkg_name)
-            .join(MetaFile::Target.to_string())
-    }
+    /// The directory the Supervisor looks for a package's artifact in
+    /// before downloading it from Builder.
+    pub fn artifact_cache_path(&self) -> PathBuf { cache_artifact_path(Some(self.0.path())) }
 
-    /// Returns the path to the service user metafile for a given package.
-    pub fn svc_user_path(&self, origin: &str, pkg_name: &str) -> PathBuf {
+    /// Returns the path to the signer metafile for a given package.
+    pub fn signer_path(&self, origin: &str, pkg_name: &str) -> PathBuf {
         self.pkg_dir_path(origin, pkg_name)
-            .join(MetaFile::SvcUser.to_string())
+            .join(MetaFile::Signer.to_string())
     }
 
-    /// Returns the path to the service group metafile for a given package.
-    pub fn svc_group_path(&self, origin: &str, pkg_name: &str) -> PathBuf {
+    /// Returns the path to the artifact hash metafile for a given package.
+    pub fn artifact_hash_path(&self, origin: &str, pkg_name: &str) -> PathBuf {
         self.pkg_dir_path(origin, pkg_name)
-            .join(MetaFile::SvcGroup.to_string())
+            .join(MetaFile::ArtifactHash.to_string())
     }
 
-    /// The path to which a spec file should be written for a given
-    /// package name.
-    pub fn spec_dir_path(&self, service_group: &str) -> PathBuf {
+    /// The directory the test Supervisor loads spec files from. It's
+    /// named after the Supervisor, which is always "default", rather
+    /// than any service group.
+    pub fn spec_dir_path(&self) -> PathBuf {
         self.0
             .as_ref()
             .to_path_buf()
             .join("hab")
             .join("sup")
-            .join(service_group)
+            .join("default")
             .join("specs")
     }
 
     /// The path to which a spec file should be written for a given
     /// package name.
-    pub fn spec_path(&self, pkg_name: &str, service_group: &str) -> PathBuf {
-        self.spec_dir_path(service_group)
-            .join(format!("{}.spec", pkg_name))
+    pub fn spec_path(&self, pkg_name: &str) -> PathBuf {
+        self.spec_dir_path().join(format!("{}.spec", pkg_name))
+    }
+
+    /// Read the spec file of `package_name` as the Supervisor would,
+    /// checking that it runs the package in `service_group`.
+    pub fn read_spec(&self, service_group: &str, package_name: &str) -> Result<ServiceSpec> {
+        let path = self.spec_path(package_name);
+        let spec = ServiceSpec::from_file(&path).with_context(|| {
+                                                    format!("Failed to read spec file '{}'",
+                                                            path.display())
+                                                })?;
+        if spec.group != service_group {
+            return Err(anyhow!("Spec file '{}' runs {} in group '{}', not '{}'",
+                               path.display(),
+                               package_name,
+                               spec.group,
+                               service_group));
+        }
+        Ok(spec)
+    }
+
+    /// Write the spec file for `spec`'s package as the Supervisor
+    /// would, running it in `service_group` whatever group `spec`
+    /// itself has.
+    pub fn write_spec(&self, service_group: &str, spec: &ServiceSpec) -> Result<()> {
+        let mut spec = spec.clone();
+        spec.group = service_group.to_string();
+        let path = self.spec_path(&spec.ident.name);
+        spec.to_file(&path)
+            .with_context(|| format!("Failed to write spec file '{}'", path.display()))
     }
 
     /// Path to the service directory for a package
@@ -109,6 +137,77 @@ impl HabRoot {
             .join("svc")
             .join(pkg_name.as_ref())
     }
+
+    /// The file the Supervisor captures the standard output of a
+    /// package's `hook_name` hook in, each time it runs the hook. Its
+    /// standard error goes next to it, in `<hook_name>.stderr.log`.
+    ///
+    /// The run hook is the exception: its output is only shown with
+    /// the Supervisor's own (see `TestSup::wait_for_log_line`).
+    pub fn hook_output_log(&self, pkg_name: &str, hook_name: &str) -> PathBuf {
+        self.svc_dir_path(pkg_name)
+            .join("logs")
+            .join(format!("{}.stdout.log", hook_name))
+    }
+
+    /// What the last run of a package's `hook_name` hook has written
+    /// to its standard output so far.
+    pub fn read_hook_output(&self, pkg_name: &str, hook_name: &str) -> Result<String> {
+        ensure_hook_output_captured(hook_name)?;
+        let path = self.hook_output_log(pkg_name, hook_name);
+        std::fs::read_to_string(&path).with_context(|| {
+                                          format!("Failed to read output of the {} hook of {} \
+                                                   from '{}'",
+                                                  hook_name,
+                                                  pkg_name,
+                                                  path.display())
+                                      })
+    }
+
+    /// Wait for a package's `hook_name` hook to have written `needle`
+    /// to its standard output, returning all it's written. Until the
+    /// hook has run, keep trying. On timeout, the error includes what
+    /// the hook last wrote.
+    pub async fn wait_for_hook_output_containing(&self,
+                                                 pkg_name: &str,
+                                                 hook_name: &str,
+                                                 needle: &str,
+                                                 timeout: Duration)
+                                                 -> Result<String> {
+        ensure_hook_output_captured(hook_name)?;
+        let path = self.hook_output_log(pkg_name, hook_name);
+        let started_at = Instant::now();
+        loop {
+            let last_seen = if path.exists() {
+                let output = self.read_hook_output(pkg_name, hook_name)?;
+                if output.contains(needle) {
+                    return Ok(output);
+                }
+                format!("it had written:\n{}", output)
+            } else {
+                String::from("it had not run")
+            };
+            if started_at.elapsed() > timeout {
+                return Err(anyhow!("The {} hook of {} did not write '{}' within \
+                                    {:.2} secs; {}",
+                                   hook_name,
+                                   pkg_name,
+                                   needle,
+                                   timeout.as_secs_f64(),
+                                   last_seen));
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
+/// The Supervisor captures the output of every hook but the run hook.
+fn ensure_hook_output_captured(hook_name: &str) -> Result<()> {
+    if hook_name == "run" {
+        return Err(anyhow!("The Supervisor does not capture the output of \
+                            run hooks; look for it in its own output instead"));
+    }
+    Ok(())
 }
 
 impl AsRef<Path> for HabRoot {
@@ -1,16 +1,38 @@
 //! Utility functions for testing a Supervisor
+pub mod fixture_package;
 pub mod fixture_root;
 pub mod fs;
+pub mod gateway_events;
+#[cfg(unix)]
+pub mod gateway_socket;
 pub mod hab_root;
+pub mod ports;
+pub mod process_tree;
+pub mod sup_gateway_api;
+pub mod sup_log;
 pub mod test_butterfly;
+pub mod test_ctl_gateway;
 pub mod test_helpers;
+pub mod test_ring;
 pub mod test_sup;
+pub mod tls;
 
 // Re-export the key structs of this package for ergonomics.
-pub use self::{fixture_root::FixtureRoot,
-               fs::{copy_dir,
+#[cfg(windows)]
+pub use self::fs::WINDOWS_SVC_GROUP;
+pub use self::{fixture_package::FixturePackageBuilder,
+               fixture_root::FixtureRoot,
+               fs::{fixture_spec,
+                    setup_bound_services,
                     setup_package_files,
+                    setup_package_files_as,
+                    write_metafile,
                     FileSnapshot,
                     FileSystemSnapshot},
                hab_root::HabRoot,
-               test_sup::TestSup};
+               sup_gateway_api::HealthCheck,
+               test_ring::TestRing,
+               test_sup::{ServiceDown,
+                          TestSup,
+                          TestSupBuilder},
+               tls::generate_self_signed_cert};
@@ -0,0 +1,144 @@
+//! Hand out TCP ports to test Supervisors, so that tests run in
+//! parallel don't step on each other.
+use anyhow::{anyhow,
+             Context,
+             Result};
+use lazy_static::lazy_static;
+use rand::{self,
+           distributions::{Distribution,
+                           Uniform}};
+use std::{collections::HashSet,
+          env,
+          fmt,
+          io,
+          ops::RangeInclusive,
+          sync::{Mutex,
+                 MutexGuard}};
+use tokio::net::TcpListener;
+
+/// Set this to e.g. "50000-50999" to have ports picked at random from
+/// that range, rather than handed out by the operating system. CI can
+/// use it to give each shard a range of its own.
+pub const PORT_RANGE_ENVVAR: &str = "HAB_TEST_PORT_RANGE";
+
+lazy_static! {
+    /// Keep track of all TCP ports currently being used by TestSup
+    /// instances. Allows us to run tests in parallel without fear of
+    /// port conflicts between them.
+    ///
+    /// This is a blocking mutex, so that `ClaimedPort` can release its
+    /// port when dropped; it's never held across an `await`.
+    static ref CLAIMED_PORTS: Mutex<HashSet<u16>> = {
+        Mutex::new(HashSet::new())
+    };
+}
+
+fn claimed_ports() -> MutexGuard<'static, HashSet<u16>> {
+    CLAIMED_PORTS.lock().expect("CLAIMED_PORTS lock poisoned")
+}
+
+/// A TCP port claimed for one test Supervisor. The claim is released
+/// when this is dropped, even if the test panics.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ClaimedPort(u16);
+
+impl ClaimedPort {
+    /// Claim a specific port, failing if another test already has.
+    /// Nothing checks whether anything else on the system is using
+    /// it.
+    pub fn claim(port: u16) -> Result<ClaimedPort> {
+        if claimed_ports().insert(port) {
+            Ok(ClaimedPort(port))
+        } else {
+            Err(anyhow!("TCP port {} is already claimed by another test", port))
+        }
+    }
+
+    pub fn port(&self) -> u16 { self.0 }
+}
+
+impl Drop for ClaimedPort {
+    fn drop(&mut self) { claimed_ports().remove(&self.0); }
+}
+
+impl fmt::Display for ClaimedPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "{}", self.0) }
+}
+
+/// Claim a free TCP port: one the system hasn't bound, and no other
+/// test Supervisor has claimed.
+///
+/// Ports are handed out by the system (by binding port 0), unless
+/// `PORT_RANGE_ENVVAR` is set, in which case they're picked at random
+/// from that range. Either way, the port is unbound again before it's
+/// returned, since the Supervisor has to be told it on its command
+/// line and bind it itself. Something else on the machine could take
+/// it in between; if that happens to you, you should probably buy
+/// lottery tickets.
+///
+/// A port that's taken is skipped straight away for another, up to
+/// `max_attempts` times.
+pub async fn unclaimed_port(max_attempts: u16) -> Result<ClaimedPort> {
+    let range = port_range()?;
+    for _ in 0..max_attempts {
+        let candidate = match range {
+            Some(ref range) => random_port(range),
+            None => 0,
+        };
+        let port = match TcpListener::bind(("127.0.0.1", candidate)).await {
+            Ok(listener) => {
+                listener.local_addr()
+                        .context("Failed to read back bound TCP port")?
+                        .port()
+            }
+            // If the port is in use carry on
+            Err(err) if err.kind() == io::ErrorKind::AddrInUse => continue,
+            // If we are unable to bind for any other reason, bubble that up
+            Err(err) => {
+                return Err(anyhow!(err)).with_context(|| {
+                                            format!("Failed to bind TCP port {} due to io error",
+                                                    candidate)
+                                        });
+            }
+        };
+        // The system hasn't bound it (and the listener that has is
+        // dropped by now). Now we make sure none of our other tests
+        // have claimed it.
+        if let Ok(claimed) = ClaimedPort::claim(port) {
+            return Ok(claimed);
+        }
+    }
+    Err(anyhow!("Failed to find an unclaimed TCP port in {} \
+                 attempts",
+                max_attempts))
+}
+
+/// The range set through `PORT_RANGE_ENVVAR`, if any.
+fn port_range() -> Result<Option<RangeInclusive<u16>>> {
+    let value = match env::var(PORT_RANGE_ENVVAR) {
+        Ok(value) => value,
+        Err(env::VarError::NotPresent) => return Ok(None),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to read {}", PORT_RANGE_ENVVAR))
+        }
+    };
+    let invalid = || {
+        anyhow!("{} must look like '50000-50999', not '{}'",
+                PORT_RANGE_ENVVAR,
+                value)
+    };
+    let (start, end) = value.split_once('-').ok_or_else(invalid)?;
+    let start = start.trim().parse::<u16>().map_err(|_| invalid())?;
+    let end = end.trim().parse::<u16>().map_err(|_| invalid())?;
+    if start == 0 || start > end {
+        return Err(invalid());
+    }
+    Ok(Some(start..=end))
+}
+
+/// Return a random port number from `range`.
+fn random_port(range: &RangeInclusive<u16>) -> u16 {
+    let between = Uniform::new_inclusive(range.start(), range.end());
+    let mut rng = rand::thread_rng();
+    between.sample(&mut rng)
+}
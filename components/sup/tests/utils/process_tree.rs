@@ -0,0 +1,278 @@
+//! Keep track of every process a test Supervisor starts, so that none
+//! of them outlive it: not the Supervisor itself, and not the services
+//! it runs.
+//!
+//! On Unix, the launcher is started in a process group of its own,
+//! which the Supervisor shares. Services are started in process groups
+//! of their own, though, and are reparented when the Supervisor goes
+//! away, so the groups of whatever is running under the launcher are
+//! gathered (see `ProcessTree::refresh`) while it's still there to ask.
+//! On Windows, the launcher is put in a job object, which everything it
+//! starts belongs to as well.
+use anyhow::{anyhow,
+             Result};
+use std::time::Duration;
+use tokio::{process::Child,
+            time::Instant};
+
+/// How long `ProcessTree::kill_and_wait` waits for killed processes
+/// to go away.
+const KILL_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct ProcessTree {
+    /// The pid of the launcher.
+    root:   u32,
+    #[cfg(not(windows))]
+    groups: std::collections::BTreeSet<i32>,
+    #[cfg(windows)]
+    job:    job::Job,
+}
+
+impl ProcessTree {
+    /// Start keeping track of the launcher just spawned as `child`.
+    /// On Unix, it has to have been spawned in its own process group.
+    pub fn new(child: &Child) -> Result<Self> {
+        let root = child.id()
+                        .ok_or_else(|| anyhow!("Test supervisor exited as soon as it started"))?;
+        #[cfg(not(windows))]
+        {
+            let groups = std::iter::once(root as i32).collect();
+            Ok(ProcessTree { root, groups })
+        }
+        #[cfg(windows)]
+        {
+            let job = job::Job::new()?;
+            job.assign(child)?;
+            Ok(ProcessTree { root, job })
+        }
+    }
+
+    /// Take note of the process groups of everything running under the
+    /// launcher, so that they can be killed even once they've been
+    /// orphaned. Done before the Supervisor is stopped.
+    #[cfg(not(windows))]
+    pub fn refresh(&mut self) -> Result<()> {
+        let processes = unix::processes()?;
+        let mut descendants = vec![self.root];
+        while let Some(parent) = descendants.pop() {
+            for process in processes.iter().filter(|p| p.ppid == parent) {
+                self.groups.insert(process.pgid);
+                descendants.push(process.pid);
+            }
+        }
+        // Whatever else happens, the test's own group is left alone.
+        self.groups.remove(&nix::unistd::getpgrp().as_raw());
+        Ok(())
+    }
+
+    /// Everything in a job object is already being kept track of.
+    #[cfg(windows)]
+    pub fn refresh(&mut self) -> Result<()> { Ok(()) }
+
+    /// The pids of the processes still running, if any.
+    pub fn survivors(&self) -> Result<Vec<u32>> {
+        #[cfg(not(windows))]
+        {
+            Ok(unix::processes()?.into_iter()
+                                 .filter(|p| !p.zombie && self.groups.contains(&p.pgid))
+                                 .map(|p| p.pid)
+                                 .collect())
+        }
+        #[cfg(windows)]
+        {
+            self.job.pids()
+        }
+    }
+
+    /// Kill every process still running, without waiting for them to
+    /// go.
+    pub fn kill(&self) -> Result<()> {
+        #[cfg(not(windows))]
+        {
+            use nix::{errno::Errno,
+                      sys::signal::{killpg,
+                                    Signal},
+                      unistd::Pid};
+            for group in &self.groups {
+                match killpg(Pid::from_raw(*group), Signal::SIGKILL) {
+                    Ok(()) | Err(Errno::ESRCH) => {}
+                    Err(err) => {
+                        return Err(anyhow!("Failed to kill process group {}: {}", group, err))
+                    }
+                }
+            }
+            Ok(())
+        }
+        #[cfg(windows)]
+        {
+            self.job.terminate()
+        }
+    }
+
+    /// Kill every process still running, and wait for them all to be
+    /// gone. If any aren't, the error lists them.
+    pub async fn kill_and_wait(&self) -> Result<()> {
+        let started_at = Instant::now();
+        loop {
+            self.kill()?;
+            let survivors = self.survivors()?;
+            if survivors.is_empty() {
+                return Ok(());
+            }
+            if started_at.elapsed() > KILL_TIMEOUT {
+                return Err(anyhow!("Processes started by the test supervisor \
+                                    (launcher pid {}) were still running {:.2} secs \
+                                    after being killed: {:?}",
+                                   self.root,
+                                   KILL_TIMEOUT.as_secs_f64(),
+                                   survivors));
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
+/// Whether the process `pid` is running. Zombies, which are dead but
+/// not yet reaped, aren't.
+#[cfg(not(windows))]
+pub fn is_running(pid: u32) -> Result<bool> {
+    Ok(unix::processes()?.iter().any(|p| p.pid == pid && !p.zombie))
+}
+
+#[cfg(not(windows))]
+mod unix {
+    use anyhow::{anyhow,
+                 Context,
+                 Result};
+    use std::process::Command;
+
+    pub struct Process {
+        pub pid:    u32,
+        pub ppid:   u32,
+        pub pgid:   i32,
+        pub zombie: bool,
+    }
+
+    /// Every process on the system, as `ps` lists them. `ps` takes the
+    /// same options on Linux and macOS, unlike `/proc`, which macOS
+    /// hasn't got.
+    pub fn processes() -> Result<Vec<Process>> {
+        let output = Command::new("ps").args(["-A", "-o", "pid=", "-o", "ppid=", "-o", "pgid=",
+                                              "-o", "stat="])
+                                       .output()
+                                       .context("Failed to run ps")?;
+        if !output.status.success() {
+            return Err(anyhow!("ps failed: {}", String::from_utf8_lossy(&output.stderr)));
+        }
+        String::from_utf8_lossy(&output.stdout).lines()
+                                               .filter(|line| !line.trim().is_empty())
+                                               .map(parse_process)
+                                               .collect()
+    }
+
+    fn parse_process(line: &str) -> Result<Process> {
+        let invalid = || anyhow!("Unexpected line from ps: '{}'", line);
+        let mut fields = line.split_whitespace();
+        let mut next = || fields.next().ok_or_else(invalid);
+        let pid = next()?.parse().map_err(|_| invalid())?;
+        let ppid = next()?.parse().map_err(|_| invalid())?;
+        let pgid = next()?.parse().map_err(|_| invalid())?;
+        let zombie = next()?.starts_with('Z');
+        Ok(Process { pid,
+                     ppid,
+                     pgid,
+                     zombie })
+    }
+}
+
+#[cfg(windows)]
+mod job {
+    use anyhow::{anyhow,
+                 Result};
+    use std::{io,
+              mem,
+              ptr};
+    use tokio::process::Child;
+    use winapi::{shared::{basetsd::ULONG_PTR,
+                          minwindef::{DWORD,
+                                      LPVOID}},
+                 um::{handleapi::CloseHandle,
+                      jobapi2::{AssignProcessToJobObject,
+                                CreateJobObjectW,
+                                QueryInformationJobObject,
+                                TerminateJobObject},
+                      winnt::{JobObjectBasicProcessIdList,
+                              HANDLE}}};
+
+    /// How many pids `Job::pids` has room for; far more than any test
+    /// runs.
+    const MAX_PIDS: usize = 1024;
+
+    /// `JOBOBJECT_BASIC_PROCESS_ID_LIST`, with room for more than one
+    /// pid.
+    #[repr(C)]
+    struct ProcessIdList {
+        assigned: DWORD,
+        listed:   DWORD,
+        pids:     [ULONG_PTR; MAX_PIDS],
+    }
+
+    pub struct Job(HANDLE);
+
+    // The handle is only ever used through the job object functions,
+    // which are safe to call from any thread.
+    unsafe impl Send for Job {}
+    unsafe impl Sync for Job {}
+
+    impl Job {
+        pub fn new() -> Result<Self> {
+            let handle = unsafe { CreateJobObjectW(ptr::null_mut(), ptr::null()) };
+            if handle.is_null() {
+                return Err(anyhow!("Failed to create job object: {}",
+                                   io::Error::last_os_error()));
+            }
+            Ok(Job(handle))
+        }
+
+        pub fn assign(&self, child: &Child) -> Result<()> {
+            let process = child.raw_handle()
+                               .ok_or_else(|| anyhow!("Test supervisor has already exited"))?;
+            if unsafe { AssignProcessToJobObject(self.0, process as HANDLE) } == 0 {
+                return Err(anyhow!("Failed to assign test supervisor to job object: \
+                                    {}",
+                                   io::Error::last_os_error()));
+            }
+            Ok(())
+        }
+
+        pub fn pids(&self) -> Result<Vec<u32>> {
+            let mut list: ProcessIdList = unsafe { mem::zeroed() };
+            let ok = unsafe {
+                QueryInformationJobObject(self.0,
+                                          JobObjectBasicProcessIdList,
+                                          &mut list as *mut ProcessIdList as LPVOID,
+                                          mem::size_of::<ProcessIdList>() as DWORD,
+                                          ptr::null_mut())
+            };
+            if ok == 0 {
+                return Err(anyhow!("Failed to list processes in job object: {}",
+                                   io::Error::last_os_error()));
+            }
+            Ok(list.pids[..list.listed as usize].iter()
+                                                .map(|pid| *pid as u32)
+                                                .collect())
+        }
+
+        pub fn terminate(&self) -> Result<()> {
+            if unsafe { TerminateJobObject(self.0, 1) } == 0 {
+                return Err(anyhow!("Failed to terminate job object: {}",
+                                   io::Error::last_os_error()));
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) { unsafe { CloseHandle(self.0) }; }
+    }
+}
@@ -0,0 +1,364 @@
+//! Typed views of what the Supervisor's HTTP gateway serves, which
+//! `TestSup` hands to tests rather than raw JSON.
+//!
+//! Only what the harness and tests look at is here. Fields the gateway
+//! adds are ignored, and those that could go missing have defaults, so
+//! that changes to the gateway don't break the harness; the gateway's
+//! JSON schemas are where it's checked strictly.
+
+// TODO: Replace these types with the actual serialized types
+// once https://github.com/habitat-sh/habitat/issues/8470 is resolved.
+use habitat_core::os::process::Pid;
+use habitat_sup::manager::service::ProcessTerminationReason;
+use serde::Deserialize;
+use std::{collections::{BTreeSet,
+                        HashMap},
+          time::Duration};
+
+/// A service, as `/services` and `/services/{name}/{group}` show it.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct ServiceInfo {
+    /// e.g. "redis.default"
+    #[serde(default)]
+    pub service_group:      String,
+    pub channel:            String,
+    pub desired_state:      String,
+    /// e.g. "at-once"
+    #[serde(default)]
+    pub update_strategy:    String,
+    pub process:            ProcessInfo,
+    #[serde(default)]
+    pub last_process_state: Option<LastProcessState>,
+    #[serde(default)]
+    pub next_restart_at:    Option<u64>,
+    #[serde(default)]
+    pub restart:            RestartInfo,
+    #[serde(default)]
+    pub restart_count:      u64,
+    #[serde(default)]
+    pub restart_config:     Option<RestartConfig>,
+    #[serde(default, rename = "restart_config_sources")]
+    pub restart_sources:    Option<RestartConfigSources>,
+    #[serde(default)]
+    pub health_check_splay: Option<HealthCheckSplay>,
+}
+
+/// A page of the services matching a `/services` query.
+#[derive(Debug)]
+pub struct ServicesPage {
+    /// How many services matched, across every page
+    pub total:    usize,
+    pub services: Vec<ServiceInfo>,
+}
+
+/// How a service's health checks are spread out.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct HealthCheckSplay {
+    pub first_check_offset: Duration,
+    pub jitter:             Duration,
+}
+
+/// Where a service is in its cycle of restarts after failures; all
+/// zeroes for services that aren't failing.
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+pub struct RestartInfo {
+    pub consecutive_failures: u64,
+    pub backoff_period:       Duration,
+    /// In seconds since the epoch, or 0 if no restart is due.
+    pub next_attempt_at:      u64,
+    pub cooldown_reset:       bool,
+}
+
+/// How a failing service is restarted.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct RestartConfig {
+    pub min_backoff_period: Duration,
+    pub max_backoff_period: Duration,
+    pub cooldown_period:    Duration,
+}
+
+/// Where each of a service's restart settings comes from.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct RestartConfigSources {
+    /// "spec" or "supervisor"
+    pub min_backoff_period: String,
+    pub max_backoff_period: String,
+    pub cooldown_period:    String,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct ProcessInfo {
+    /// "up" or "down"
+    pub state:         String,
+    pub pid:           Option<Pid>,
+    /// When the process entered `state`, in seconds since the epoch.
+    #[serde(default)]
+    pub state_entered: u64,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct LastProcessState {
+    pub pid:                Option<Pid>,
+    pub termination_reason: ProcessTerminationReason,
+    pub terminated_at:      u64,
+}
+
+impl ServiceInfo {
+    /// Whether the service is meant to be up, and is, with a process
+    /// of its own.
+    pub fn is_running(&self) -> bool {
+        self.desired_state == "Up" && self.process.state == "up" && self.process.pid.is_some()
+    }
+
+    /// Whether the service is running in a process started after
+    /// `earlier` was seen. A new process can be given the pid of the
+    /// old one, so it's when the process came up that's compared, not
+    /// its pid. The gateway only has that to the second, so a process
+    /// having ended since `earlier` counts as well.
+    pub fn has_restarted_since(&self, earlier: &ServiceInfo) -> bool {
+        self.is_running()
+        && (self.process.state_entered > earlier.process.state_entered
+            || self.last_process_state != earlier.last_process_state)
+    }
+
+    /// Whether the service's process has changed in any way since
+    /// `earlier`: gone up or down, been replaced, or ended.
+    pub fn process_changed_since(&self, earlier: &ServiceInfo) -> bool {
+        self.process != earlier.process || self.last_process_state != earlier.last_process_state
+    }
+
+    /// Whether the service is down and the Supervisor won't be
+    /// bringing it back up, because it's been stopped.
+    pub fn is_stopped(&self) -> bool {
+        self.desired_state == "Down" && self.process.state == "down"
+    }
+}
+/// The result of a service's health check, as `HealthCheckResult`
+/// is shown by the gateway: in upper case by `/health`, and as it's
+/// named in the code by `/health/history`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HealthCheck {
+    #[serde(alias = "Ok")]
+    Ok,
+    #[serde(alias = "Warning")]
+    Warning,
+    #[serde(alias = "Critical")]
+    Critical,
+    #[serde(alias = "Unknown")]
+    Unknown,
+}
+/// A service's last health check, as `/services/{name}/{group}/health`
+/// shows it.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct HealthCheckInfo {
+    pub status: HealthCheck,
+    #[serde(default)]
+    pub stdout: String,
+    #[serde(default)]
+    pub stderr: String,
+}
+/// One of a service's health checks, as
+/// `/services/{name}/{group}/health/history` shows it.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct HealthCheckRecord {
+    /// In milliseconds since the epoch.
+    pub timestamp: u64,
+    pub status:    HealthCheck,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub stdout:    String,
+    #[serde(default)]
+    pub stderr:    String,
+}
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct Task {
+    pub name:          String,
+    pub state:         String,
+    pub restart_count: u32,
+    pub last_error:    Option<String>,
+}
+
+/// What a Supervisor's census says about the members running
+/// services, and which service groups they're in. Fields the
+/// harness doesn't use are ignored, as are any the gateway adds.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct Census {
+    pub local_member_id: String,
+    pub census_groups:   HashMap<String, CensusGroup>,
+}
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct CensusGroup {
+    pub service_group:  String,
+    pub population:     HashMap<String, CensusMember>,
+    /// The configuration gossiped to the group, if any has been.
+    #[serde(default)]
+    pub service_config: Option<ServiceConfig>,
+}
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct ServiceConfig {
+    pub incarnation: u64,
+    pub value:       serde_json::Value,
+}
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct CensusMember {
+    pub member_id: String,
+    pub service:   String,
+    pub group:     String,
+    #[serde(default)]
+    pub alive:     bool,
+    #[serde(default)]
+    pub suspect:   bool,
+    #[serde(default)]
+    pub confirmed: bool,
+    #[serde(default)]
+    pub departed:  bool,
+}
+
+/// A member's health, as the census's flags for it have it, or as
+/// Butterfly reports it.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub enum MemberHealth {
+    Alive,
+    Suspect,
+    Confirmed,
+    Departed,
+}
+
+impl CensusMember {
+    pub fn health(&self) -> Option<MemberHealth> {
+        if self.departed {
+            Some(MemberHealth::Departed)
+        } else if self.confirmed {
+            Some(MemberHealth::Confirmed)
+        } else if self.suspect {
+            Some(MemberHealth::Suspect)
+        } else if self.alive {
+            Some(MemberHealth::Alive)
+        } else {
+            None
+        }
+    }
+}
+
+impl Census {
+    /// Every member in any service group.
+    pub fn members(&self) -> impl Iterator<Item = &CensusMember> {
+        self.census_groups
+            .values()
+            .flat_map(|group| group.population.values())
+    }
+
+    /// The ids of the members seen as alive, in any service group.
+    pub fn alive_member_ids(&self) -> BTreeSet<&str> {
+        self.members()
+            .filter(|member| member.health() == Some(MemberHealth::Alive))
+            .map(|member| member.member_id.as_str())
+            .collect()
+    }
+
+    /// How `member_id` is seen, if it's in any service group.
+    pub fn member_health(&self, member_id: &str) -> Option<MemberHealth> {
+        self.members()
+            .find(|member| member.member_id == member_id)
+            .and_then(CensusMember::health)
+    }
+
+    /// The incarnation of the configuration gossiped to
+    /// `package_name.service_group`, if any has been.
+    pub fn config_incarnation(&self, package_name: &str, service_group: &str) -> Option<u64> {
+        self.census_groups
+            .get(&format!("{}.{}", package_name, service_group))
+            .and_then(|group| group.service_config.as_ref())
+            .map(|config| config.incarnation)
+    }
+
+    /// The service groups `member_id` is in.
+    pub fn service_groups_of(&self, member_id: &str) -> BTreeSet<&str> {
+        self.census_groups
+            .values()
+            .filter(|group| group.population.contains_key(member_id))
+            .map(|group| group.service_group.as_str())
+            .collect()
+    }
+}
+
+/// What a Supervisor's gossip layer knows, as `/butterfly` shows it.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct ButterflyInfo {
+    /// Every member this Supervisor knows of, itself included, by id.
+    #[serde(default)]
+    pub membership:       HashMap<String, MembershipInfo>,
+    #[serde(default)]
+    pub departed_members: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct MembershipInfo {
+    pub health:      MemberHealth,
+    #[serde(default)]
+    pub incarnation: u64,
+}
+
+impl ButterflyInfo {
+    /// How many members are seen as alive, this Supervisor included.
+    pub fn alive_member_count(&self) -> usize {
+        self.membership
+            .values()
+            .filter(|member| member.health == MemberHealth::Alive)
+            .count()
+    }
+}
+
+/// The revisions of the ring key a Supervisor accepts gossip
+/// encrypted with, as `/butterfly/ring-key` shows them.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct RingKeyRevisions {
+    /// Newest first; gossip is sent with the first of them.
+    pub accepted: Vec<String>,
+}
+
+/// Whether the Supervisor has started up its services, as `/ready`
+/// shows it.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct Readiness {
+    pub ready:            bool,
+    pub specs_reconciled: bool,
+    /// The service groups still waited on, in order.
+    pub not_ready:        Vec<String>,
+}
+
+/// What gossip is doing, as `/butterfly/stats` shows it.
+#[derive(Debug, Deserialize)]
+pub struct GossipStats {
+    pub gossip_interval_ms: u64,
+    pub outbound_queue:     usize,
+    pub membership:         MembershipTally,
+    /// Keyed by rumor type, e.g. "service-config".
+    pub rumors:             HashMap<String, RumorStats>,
+}
+
+impl GossipStats {
+    /// How many rumors of `rumor_type` have been received in all.
+    pub fn received(&self, rumor_type: &str) -> u64 {
+        self.rumors
+            .get(rumor_type)
+            .map_or(0, |stats| stats.received)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MembershipTally {
+    pub alive:     usize,
+    pub suspect:   usize,
+    pub confirmed: usize,
+    pub departed:  usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RumorStats {
+    pub held:     usize,
+    pub received: u64,
+    pub sent:     u64,
+}
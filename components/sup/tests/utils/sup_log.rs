@@ -0,0 +1,140 @@
+//! Capture the output of a `hab-sup` run for tests, so that it can be
+//! asserted on, and shown when a test fails.
+use anyhow::{anyhow,
+             Context,
+             Result};
+use regex::Regex;
+use std::{fs,
+          io::{self,
+               Read,
+               Seek,
+               SeekFrom,
+               Write},
+          time::Duration};
+use tempfile::NamedTempFile;
+use tokio::{fs::OpenOptions,
+            io::{AsyncBufReadExt,
+                 AsyncRead,
+                 AsyncWriteExt,
+                 BufReader},
+            process::Child,
+            time::Instant};
+
+/// A position in a `SupLog`; see `SupLog::marker`.
+#[derive(Clone, Copy, Debug)]
+pub struct LogMarker(u64);
+
+/// The combined stdout and stderr of every process a `TestSup` has
+/// started, in a temporary file that's removed when this is dropped.
+/// Lines are written as they're read, so they're interleaved about
+/// the way they'd be on a terminal.
+pub struct SupLog {
+    file: NamedTempFile,
+    /// Whether lines are also passed through to the test's own
+    /// output, for `--nocapture`.
+    tee:  bool,
+}
+
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl SupLog {
+    pub fn new(tee: bool) -> Result<SupLog> {
+        let file = NamedTempFile::new().context("Failed to create supervisor log file")?;
+        Ok(SupLog { file, tee })
+    }
+
+    /// Take `child`'s stdout and stderr, which must have been piped,
+    /// and copy them into the log until it exits.
+    pub fn capture(&self, child: &mut Child) -> Result<()> {
+        let stdout = child.stdout
+                          .take()
+                          .ok_or_else(|| anyhow!("Supervisor stdout was not piped"))?;
+        let stderr = child.stderr
+                          .take()
+                          .ok_or_else(|| anyhow!("Supervisor stderr was not piped"))?;
+        self.copy_lines(stdout, Stream::Stdout);
+        self.copy_lines(stderr, Stream::Stderr);
+        Ok(())
+    }
+
+    /// Everything logged so far.
+    pub fn contents(&self) -> String { self.contents_since(LogMarker(0)) }
+
+    /// The current end of the log. Pass it to `contents_since` to see
+    /// only what's logged after this point.
+    pub fn marker(&self) -> LogMarker {
+        LogMarker(fs::metadata(self.file.path()).map(|m| m.len())
+                                                .unwrap_or_default())
+    }
+
+    /// Everything logged after `marker` was taken.
+    pub fn contents_since(&self, marker: LogMarker) -> String {
+        let read = || -> io::Result<Vec<u8>> {
+            let mut file = fs::File::open(self.file.path())?;
+            file.seek(SeekFrom::Start(marker.0))?;
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            Ok(bytes)
+        };
+        // The log is only for diagnosis; failing to read it shouldn't
+        // be what fails a test.
+        String::from_utf8_lossy(&read().unwrap_or_default()).into_owned()
+    }
+
+    /// The last `n` lines logged so far.
+    pub fn last_lines(&self, n: usize) -> Vec<String> {
+        let contents = self.contents();
+        let lines = contents.lines().collect::<Vec<_>>();
+        lines[lines.len().saturating_sub(n)..].iter()
+                                              .map(ToString::to_string)
+                                              .collect()
+    }
+
+    /// Wait until a line matching the regular expression `pattern` is
+    /// logged (or already has been), and return that line.
+    pub async fn wait_for_line(&self, pattern: &str, timeout: Duration) -> Result<String> {
+        let regex = Regex::new(pattern).with_context(|| format!("Invalid pattern '{}'", pattern))?;
+        let started_at = Instant::now();
+        loop {
+            if let Some(line) = self.contents().lines().find(|line| regex.is_match(line)) {
+                return Ok(line.to_string());
+            }
+            if started_at.elapsed() > timeout {
+                return Err(anyhow!("Test supervisor did not log a line matching '{}' \
+                                    within {:.2}secs; its last lines were:\n{}",
+                                   pattern,
+                                   timeout.as_secs_f64(),
+                                   self.last_lines(20).join("\n")));
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    fn copy_lines(&self, stream: impl AsyncRead + Unpin + Send + 'static, kind: Stream) {
+        let path = self.file.path().to_path_buf();
+        let tee = self.tee;
+        tokio::spawn(async move {
+            let mut file = OpenOptions::new().append(true).open(&path).await?;
+            let mut reader = BufReader::new(stream);
+            let mut line = Vec::new();
+            while reader.read_until(b'\n', &mut line).await? > 0 {
+                // Each line goes in a single write, so that those from
+                // stdout and stderr don't end up mixed together.
+                // Flushing makes the line visible to readers right away.
+                file.write_all(&line).await?;
+                file.flush().await?;
+                if tee {
+                    match kind {
+                        Stream::Stdout => io::stdout().write_all(&line)?,
+                        Stream::Stderr => io::stderr().write_all(&line)?,
+                    }
+                }
+                line.clear();
+            }
+            Ok::<_, io::Error>(())
+        });
+    }
+}
@@ -2,12 +2,20 @@
 //! test-focused API. Clients are configured for a specific service
 //! group (namely, the one of the test package we are running).
 //!
-//! No ring key or encryption abilities are currently supported.
+//! Rumors are encrypted with the ring key the client is created with,
+//! if any, and applied configuration and uploaded files can be
+//! encrypted for the service group they're sent to.
 
-use anyhow::{Context,
+use anyhow::{anyhow,
+             Context,
              Result};
 use habitat_butterfly::client::Client as ButterflyClient;
-use habitat_core::service::ServiceGroup;
+use habitat_core::{crypto::keys::{Key,
+                                  RingKey,
+                                  ServicePublicEncryptionKey,
+                                  UserSecretEncryptionKey},
+                   service::ServiceGroup};
+use habitat_sup_protocol::butterfly::MAX_FILE_PUT_SIZE_BYTES;
 use std::{net::SocketAddr,
           str,
           time::{SystemTime,
@@ -18,14 +26,17 @@ pub struct Client {
 }
 
 impl Client {
-    pub fn new(port: u16) -> Result<Client> {
+    /// A client for the Supervisor gossiping on `port`. If it's on an
+    /// encrypted ring, `ring_key` must be the ring's key, or it will
+    /// ignore everything sent to it.
+    pub fn new(port: u16, ring_key: Option<RingKey>) -> Result<Client> {
         let gossip_addr =
             format!("127.0.0.1:{}", port).parse::<SocketAddr>()
                                          .context("Could not parse Butterfly gossip address!")?;
         let butterfly_client =
-            ButterflyClient::new(&gossip_addr.to_string(), None).context("Could not create \
-                                                                          Butterfly Client for \
-                                                                          test!")?;
+            ButterflyClient::new(&gossip_addr.to_string(), ring_key).context("Could not create \
+                                                                              Butterfly Client \
+                                                                              for test!")?;
         Ok(Client { butterfly_client })
     }
 
@@ -34,12 +45,27 @@ impl Client {
     /// initially configured.
     ///
     /// A time-based incarnation value is automatically used,
-    /// resulting in less clutter in your tests.
+    /// resulting in less clutter in your tests. It is returned, so
+    /// that you can tell when the configuration has landed.
     pub fn apply(&mut self,
                  package_name: &str,
                  service_group: &str,
                  applied_config: &str)
-                 -> Result<()> {
+                 -> Result<u64> {
+        let incarnation = Self::new_incarnation()?;
+        self.apply_with_incarnation(package_name, service_group, applied_config, incarnation)?;
+        Ok(incarnation)
+    }
+
+    /// Apply the given configuration with the given incarnation. The
+    /// Supervisor ignores configuration whose incarnation isn't newer
+    /// than what it already has.
+    pub fn apply_with_incarnation(&mut self,
+                                  package_name: &str,
+                                  service_group: &str,
+                                  applied_config: &str,
+                                  incarnation: u64)
+                                  -> Result<()> {
         let config = applied_config.to_string();
         let config = config.as_bytes();
 
@@ -49,7 +75,6 @@ impl Client {
                                                                       applied_config)
                                                           })?;
 
-        let incarnation = Self::new_incarnation()?;
         self.butterfly_client
             .send_service_config(ServiceGroup::new(package_name, service_group, None).unwrap(),
                                  incarnation,
@@ -59,10 +84,117 @@ impl Client {
         Ok(())
     }
 
+    /// Apply the given configuration like `apply`, but encrypted by
+    /// the user key for the service key, as `hab config apply` does
+    /// for service groups with an organization. It goes to the service
+    /// group the service key is for (see `encrypt_for_service`), and
+    /// the Supervisor needs the service secret key and user public key
+    /// to decrypt it.
+    pub fn apply_encrypted(&mut self,
+                           package_name: &str,
+                           service_group: &str,
+                           applied_config: &str,
+                           user_secret: &UserSecretEncryptionKey,
+                           service_public: &ServicePublicEncryptionKey)
+                           -> Result<u64> {
+        // Validate the TOML, to save you from typos in your tests
+        toml::de::from_str::<toml::value::Value>(applied_config).with_context(|| {
+                                                                    format!("Invalid TOML: {}",
+                                                                            applied_config)
+                                                                })?;
+        let (service_group, config) = encrypt_for_service(package_name,
+                                                          service_group,
+                                                          applied_config.as_bytes(),
+                                                          user_secret,
+                                                          service_public)?;
+
+        let incarnation = Self::new_incarnation()?;
+        self.butterfly_client
+            .send_service_config(service_group, incarnation, &config, true)
+            .context("Cannot send the encrypted service configuration")?;
+        Ok(incarnation)
+    }
+
+    /// The equivalent of performing `hab file upload`: the Supervisor
+    /// writes `contents` to `filename` in the `files` directory of
+    /// each service in the group. A `version` that isn't newer than
+    /// that of the last upload of the same file is ignored.
+    ///
+    /// If `keys` are given, the contents are encrypted by the user
+    /// key for the service key, as the CLI does for service groups
+    /// with an organization. The file then goes to the service group
+    /// the service key is for, which must be `package_name` and
+    /// `service_group` in some organization. The Supervisor needs the
+    /// service secret key and user public key to decrypt it.
+    pub fn upload_file(&mut self,
+                       package_name: &str,
+                       service_group: &str,
+                       filename: &str,
+                       contents: &[u8],
+                       version: u64,
+                       keys: Option<(&UserSecretEncryptionKey, &ServicePublicEncryptionKey)>)
+                       -> Result<()> {
+        // The CLI refuses these, so there's no point in gossiping them
+        if contents.len() > MAX_FILE_PUT_SIZE_BYTES {
+            return Err(anyhow!("File {} is {} bytes; at most {} can be uploaded",
+                               filename,
+                               contents.len(),
+                               MAX_FILE_PUT_SIZE_BYTES));
+        }
+
+        let (service_group, body, encrypted) = match keys {
+            Some((user_key, service_key)) => {
+                let (group, body) = encrypt_for_service(package_name,
+                                                        service_group,
+                                                        contents,
+                                                        user_key,
+                                                        service_key)?;
+                (group, body, true)
+            }
+            None => {
+                (ServiceGroup::new(package_name, service_group, None)?, contents.to_vec(), false)
+            }
+        };
+
+        self.butterfly_client
+            .send_service_file(service_group, filename, version, &body, encrypted)
+            .context("Cannot send the service file")?;
+        Ok(())
+    }
+
     /// Generate a new incarnation number using the number of seconds
     /// since the Unix Epoch. As a result, this is unique to within a
     /// second, so beware! Might need to incorporate nanoseconds as well.
-    fn new_incarnation() -> Result<u64> {
+    pub fn new_incarnation() -> Result<u64> {
         Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
     }
 }
+
+/// Encrypt `payload` by `user_key` for `service_key`, as the CLI does,
+/// returning it along with the service group it's to be sent to: the
+/// one the service key is named for, which must be `package_name` and
+/// `service_group` in some organization.
+fn encrypt_for_service(package_name: &str,
+                       service_group: &str,
+                       payload: &[u8],
+                       user_key: &UserSecretEncryptionKey,
+                       service_key: &ServicePublicEncryptionKey)
+                       -> Result<(ServiceGroup, Vec<u8>)> {
+    let key_name = service_key.named_revision().name();
+    let group = key_name.parse::<ServiceGroup>().with_context(|| {
+                                                     format!("Service key {} is not named for a \
+                                                              service group",
+                                                             key_name)
+                                                 })?;
+    if group.service() != package_name || group.group() != service_group || group.org().is_none() {
+        return Err(anyhow!("Service key {} is not for {}.{} in an \
+                            organization",
+                           key_name,
+                           package_name,
+                           service_group));
+    }
+    let payload = user_key.encrypt_for_service(payload, service_key)
+                          .to_string()
+                          .into_bytes();
+    Ok((group, payload))
+}
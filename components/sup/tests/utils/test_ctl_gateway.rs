@@ -0,0 +1,166 @@
+//! Encapsulate a client for a Supervisor's control gateway and expose
+//! its functionality via a test-focused API. This is what the `hab
+//! svc` commands talk to, minus the CLI.
+//!
+//! TLS is not currently supported, as test Supervisors don't use it.
+
+use anyhow::{anyhow,
+             Context,
+             Result};
+use futures::{SinkExt,
+              StreamExt};
+use habitat_sup_protocol::{self as protocol,
+                           codec::{SrvCodec,
+                                   SrvMessage,
+                                   SrvTxn},
+                           message::MessageStatic,
+                           net::NetErr};
+use std::{fmt,
+          path::PathBuf,
+          time::Duration};
+use tokio::net::TcpStream;
+use tokio_util::codec::Framed;
+
+/// How long to wait for each message of the Supervisor's reply.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What a Supervisor said in reply to a request.
+#[derive(Debug)]
+pub struct Reply {
+    /// The console lines sent along the way, e.g. "The ... service
+    /// was successfully loaded".
+    pub lines: Vec<String>,
+    /// Set if the Supervisor refused or failed the request.
+    pub err:   Option<NetErr>,
+    /// Every other message in the reply, still encoded; see `parse`.
+    messages:  Vec<SrvMessage>,
+}
+
+impl Reply {
+    /// Turn a refusal or failure into an error, for tests that only
+    /// care that the request succeeded.
+    pub fn into_result(self) -> Result<Vec<String>> {
+        match self.err {
+            Some(err) => Err(anyhow!("Supervisor replied with an error: {}", err)),
+            None => Ok(self.lines),
+        }
+    }
+
+    /// Decode the `T` the Supervisor replied with, for requests
+    /// answered with something other than console lines.
+    pub fn parse<T>(&self) -> Result<T>
+        where T: prost::Message + MessageStatic + Default
+    {
+        if let Some(err) = &self.err {
+            return Err(anyhow!("Supervisor replied with an error: {}", err));
+        }
+        let message =
+            self.messages
+                .iter()
+                .find(|m| m.message_id() == T::MESSAGE_ID)
+                .ok_or_else(|| anyhow!("Supervisor didn't reply with a {}", T::MESSAGE_ID))?;
+        message.parse::<T>()
+               .with_context(|| format!("Failed to decode {}", T::MESSAGE_ID))
+    }
+}
+
+pub struct Client {
+    port:     u16,
+    sup_root: PathBuf,
+}
+
+impl Client {
+    /// A client for the Supervisor whose control gateway listens on
+    /// `port`, and whose state (including the ctl secret, which it
+    /// writes when it starts) is in `sup_root`.
+    pub fn new(port: u16, sup_root: PathBuf) -> Client { Client { port, sup_root } }
+
+    /// Send `request` to the Supervisor, and collect its reply. An
+    /// `Err` means the Supervisor couldn't be talked to at all; its
+    /// answer to the request itself is in the `Reply`.
+    ///
+    /// Every request is made over a new connection, authenticated
+    /// with the handshake the `hab` CLI uses.
+    pub async fn request(&self, request: impl Into<SrvMessage> + fmt::Debug) -> Result<Reply> {
+        let secret_key = self.secret_key()?;
+        let tcp_stream =
+            TcpStream::connect(("127.0.0.1", self.port)).await
+                                                        .with_context(|| {
+                                                            format!("Failed to connect to control \
+                                                                     gateway on port {}",
+                                                                    self.port)
+                                                        })?;
+        let mut stream = Framed::new(tcp_stream, SrvCodec::new());
+        let mut txn = SrvTxn::default();
+
+        let mut handshake =
+            SrvMessage::from(protocol::ctl::Handshake { secret_key: Some(secret_key), });
+        handshake.set_transaction(txn);
+        stream.send(handshake)
+              .await
+              .context("Failed to send control gateway handshake")?;
+        next_message(&mut stream).await?
+                                 .ok_or_else(|| {
+                                     anyhow!("Control gateway closed the connection during the \
+                                              handshake")
+                                 })?
+                                 .try_ok()
+                                 .context("Control gateway rejected the handshake")?;
+
+        txn.increment();
+        let description = format!("{:?}", request);
+        let mut message = request.into();
+        message.set_transaction(txn);
+        stream.send(message)
+              .await
+              .with_context(|| format!("Failed to send {}", description))?;
+
+        let mut reply = Reply { lines:    Vec::new(),
+                                err:      None,
+                                messages: Vec::new(), };
+        while let Some(message) = next_message(&mut stream).await? {
+            match message.message_id() {
+                "ConsoleLine" => {
+                    let line = message.parse::<protocol::ctl::ConsoleLine>()
+                                      .context("Failed to decode ConsoleLine")?;
+                    reply.lines.push(line.line);
+                }
+                "NetErr" => {
+                    let err = message.parse::<NetErr>()
+                                     .context("Failed to decode NetErr")?;
+                    reply.err = Some(err);
+                }
+                _ => reply.messages.push(message.clone()),
+            }
+            if message.is_complete() {
+                break;
+            }
+        }
+        Ok(reply)
+    }
+
+    fn secret_key(&self) -> Result<String> {
+        let mut secret_key = String::new();
+        let found =
+            protocol::read_secret_key(&self.sup_root, &mut secret_key).context("Failed to read \
+                                                                                control gateway \
+                                                                                secret")?;
+        if !found {
+            return Err(anyhow!("No control gateway secret in {}; has the test \
+                                supervisor been started?",
+                               self.sup_root.display()));
+        }
+        Ok(secret_key)
+    }
+}
+
+/// The next message from the control gateway, or `None` if it has
+/// closed the connection.
+async fn next_message(stream: &mut Framed<TcpStream, SrvCodec>) -> Result<Option<SrvMessage>> {
+    tokio::time::timeout(REPLY_TIMEOUT, stream.next()).await
+                                                      .context("Timed out waiting for the \
+                                                                control gateway to reply")?
+                                                      .transpose()
+                                                      .context("Failed to read from the control \
+                                                                gateway")
+}
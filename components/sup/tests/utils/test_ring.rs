@@ -0,0 +1,127 @@
+//! Encapsulate running several `hab-sup`s gossiping with each other
+//! for tests.
+use anyhow::{anyhow,
+             Context,
+             Result};
+use habitat_core::crypto::keys::RingKey;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use super::{HabRoot,
+            TestSup,
+            TestSupBuilder};
+
+/// A ring of Supervisors, each with its own `HabRoot` and ports. Every
+/// member but the first is given the first as its peer, so once
+/// they're started they all find each other.
+pub struct TestRing {
+    // Dropped after `members`, so that no Supervisor outlives the
+    // filesystem it runs in.
+    members:   Vec<TestSup>,
+    hab_roots: Vec<HabRoot>,
+}
+
+impl TestRing {
+    /// Set up (but don't start) a ring of `size` Supervisors. Their
+    /// `HabRoot`s are named after `name`, and are available through
+    /// `hab_root`, so packages can be set up in them before starting
+    /// the ring.
+    pub async fn new(name: &str, size: usize) -> Result<TestRing> {
+        Self::build(name, size, None).await
+    }
+
+    /// Like `new`, but every member is on the encrypted ring whose key
+    /// is `ring_key`.
+    pub async fn encrypted(name: &str, size: usize, ring_key: RingKey) -> Result<TestRing> {
+        Self::build(name, size, Some(ring_key)).await
+    }
+
+    async fn build(name: &str, size: usize, ring_key: Option<RingKey>) -> Result<TestRing> {
+        if size == 0 {
+            return Err(anyhow!("A TestRing needs at least one member"));
+        }
+        let hab_roots = (0..size).map(|i| HabRoot::new(&format!("{}_{}", name, i)))
+                                 .collect::<Vec<_>>();
+        let mut members: Vec<TestSup> = Vec::with_capacity(size);
+        for hab_root in &hab_roots {
+            let mut builder = TestSupBuilder::new().fs_root(hab_root).random_ports();
+            if let Some(ref ring_key) = ring_key {
+                builder = builder.ring_key(ring_key.clone());
+            }
+            if let Some(first) = members.first() {
+                builder = builder.peer(format!("127.0.0.1:{}", first.butterfly_port));
+            }
+            members.push(builder.build()
+                                .await
+                                .context("Failed to set up test ring member")?);
+        }
+        Ok(TestRing { members, hab_roots })
+    }
+
+    /// Start every member, the first (which the others peer with)
+    /// first. `timeout` applies to each member separately.
+    pub async fn start(&mut self, timeout: Duration) -> Result<()> {
+        for (i, member) in self.members.iter_mut().enumerate() {
+            member.start(timeout)
+                  .await
+                  .with_context(|| format!("Failed to start test ring member {}", i))?;
+        }
+        Ok(())
+    }
+
+    /// Stop every member, even if stopping one of them fails. The
+    /// first failure, if any, is returned.
+    pub async fn stop(&mut self) -> Result<()> {
+        let mut result = Ok(());
+        for (i, member) in self.members.iter_mut().enumerate() {
+            let stopped = member.stop()
+                                .await
+                                .with_context(|| format!("Failed to stop test ring member {}", i));
+            if result.is_ok() {
+                result = stopped;
+            }
+        }
+        result
+    }
+
+    pub fn member(&self, i: usize) -> &TestSup { &self.members[i] }
+
+    pub fn member_mut(&mut self, i: usize) -> &mut TestSup { &mut self.members[i] }
+
+    pub fn hab_root(&self, i: usize) -> &HabRoot { &self.hab_roots[i] }
+
+    /// Wait until every member sees every other member as alive, as
+    /// reported by its HTTP gateway.
+    pub async fn wait_for_full_mesh(&self, timeout: Duration) -> Result<()> {
+        let started_at = Instant::now();
+        let peers = self.members.len() - 1;
+        for (i, member) in self.members.iter().enumerate() {
+            loop {
+                // A member may or may not count itself, depending on
+                // whether its own rumor has come back around to it yet.
+                let alive = alive_member_count(member).await?;
+                if alive >= peers {
+                    break;
+                }
+                if started_at.elapsed() > timeout {
+                    return Err(anyhow!("Test ring member {} saw only {} of its {} peers \
+                                        alive within {:.2} secs",
+                                       i,
+                                       alive,
+                                       peers,
+                                       timeout.as_secs_f64()));
+                }
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How many members `sup`'s gossip layer currently considers alive.
+/// Until its HTTP gateway answers, that's none.
+async fn alive_member_count(sup: &TestSup) -> Result<usize> {
+    Ok(sup.butterfly_info()
+          .await
+          .map_or(0, |butterfly| butterfly.alive_member_count()))
+}
@@ -3,14 +3,20 @@ use crate::hcore::url::BLDR_URL_ENVVAR;
 use anyhow::{anyhow,
              Context,
              Result};
-use habitat_core::os::process::Pid;
+use habitat_core::{crypto::keys::{Key,
+                                  KeyCache,
+                                  RingKey,
+                                  ServicePublicEncryptionKey,
+                                  UserSecretEncryptionKey},
+                   fs::CACHE_KEY_PATH_POSTFIX,
+                   os::process::Pid,
+                   package::PackageIdent};
+use habitat_sup_protocol::ctl;
 use hyper::Method;
-use rand::{self,
-           distributions::{Distribution,
-                           Uniform}};
 use serde_json::Value;
-use std::{collections::HashSet,
+use std::{collections::HashMap,
           env,
+          fs,
           io,
           net::{Ipv4Addr,
                 SocketAddrV4},
@@ -18,102 +24,105 @@ use std::{collections::HashSet,
                  PathBuf},
           process::Stdio,
           time::Duration};
-use tokio::{net::{TcpListener,
-                  TcpStream},
+use tokio::{net::TcpStream,
             process::{Child,
                       Command},
-            sync::Mutex,
             time::Instant};
 
-use super::{test_butterfly,
+#[cfg(unix)]
+use super::gateway_socket::{self,
+                            GatewaySocketRelay};
+use super::{gateway_events::EventStream,
+            ports::{unclaimed_port,
+                    ClaimedPort},
+            process_tree::ProcessTree,
+            sup_gateway_api::{ButterflyInfo,
+                              Census,
+                              GossipStats,
+                              HealthCheck,
+                              HealthCheckInfo,
+                              HealthCheckRecord,
+                              MemberHealth,
+                              Readiness,
+                              RingKeyRevisions,
+                              ServiceInfo,
+                              ServicesPage,
+                              Task},
+            sup_log::{LogMarker,
+                      SupLog},
+            test_butterfly,
+            test_ctl_gateway,
             test_helpers::assert_valid};
-use lazy_static::lazy_static;
-
-lazy_static! {
-    /// Keep track of all TCP ports currently being used by TestSup
-    /// instances. Allows us to run tests in parallel without fear of
-    /// port conflicts between them.
-    static ref CLAIMED_PORTS: Mutex<HashSet<u16>> = {
-        Mutex::new(HashSet::new())
-    };
-}
+
+/// How long `TestSup::stop` waits for the Supervisor to shut down
+/// gracefully before killing it.
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How many of the Supervisor's last lines of output are shown when a
+/// test using it fails.
+const FAILURE_LOG_LINES: usize = 50;
+
+/// Environment variables the harness sets for the Supervisor itself.
+/// Pointing the Supervisor at the wrong `FS_ROOT`, for one, has it
+/// trample over whatever is really there, so these can only be changed
+/// through `TestSup::force_env`.
+const HARNESS_ENV_VARS: &[&str] = &["FS_ROOT", "HAB_SUP_BINARY", BLDR_URL_ENVVAR];
+
+/// Requests to the HTTP gateway carry the token the Supervisor is
+/// given in this, if any.
+const GATEWAY_AUTH_TOKEN_ENVVAR: &str = "HAB_SUP_GATEWAY_AUTH_TOKEN";
 
 pub struct TestSup {
     pub hab_root:         PathBuf,
-    pub http_port:        u16,
-    pub butterfly_port:   u16,
-    pub control_port:     u16,
+    pub http_port:        ClaimedPort,
+    pub butterfly_port:   ClaimedPort,
+    pub control_port:     ClaimedPort,
     pub butterfly_client: test_butterfly::Client,
+    pub ctl_client:       test_ctl_gateway::Client,
     pub api_client:       reqwest::Client,
+    /// "https" if the HTTP gateway is served over TLS, else "http".
+    pub gateway_scheme:   &'static str,
+    /// The key of the encrypted ring the Supervisor is on, if any.
+    pub ring_key:         Option<RingKey>,
+    /// Relays `http_port` to the HTTP gateway's Unix domain socket,
+    /// if it's served on one.
+    #[cfg(unix)]
+    pub gateway_relay:    Option<GatewaySocketRelay>,
+    /// The arguments `cmd` runs `hab-launch` with.
+    pub args:             Vec<String>,
     pub cmd:              Command,
     pub process:          Option<Child>,
+    /// Everything the Supervisor was last started with, for making
+    /// sure it's all gone once it's stopped.
+    process_tree:         Option<ProcessTree>,
+    /// Everything the Supervisor has written to stdout and stderr.
+    pub log:              SupLog,
+    /// Whether `start` waits for the Supervisor to be ready, rather
+    /// than just for its ports to open up.
+    pub wait_for_ready:   bool,
 }
 
-/// Return a free TCP port number. We test to see that the system has
-/// not already bound the port, while also tracking which ports are
-/// being used by other test supervisors that may be running alongside
-/// this one.
-///
-/// Once you receive a port number from this function, you can be
-/// reasonably sure that you're the only one that will be using
-/// it. There could be a race condition if the machine the tests are
-/// running on just happens to claim the same port number for
-/// something between the time we check and the time the TestSup
-/// claims it. If that happens to you, you should probably buy lottery
-/// tickets, though.
-///
-/// This function will recursively call itself with a decremented
-/// value for `tries` if it happens to pick a port that's already in
-/// use. Once all tries are used up, it panics! Yay!
-async fn unclaimed_port(max_attempts: u16) -> Result<u16> {
-    let mut attempts = 0;
-    loop {
-        let port = random_port();
-        match TcpListener::bind(format!("127.0.0.1:{}", port)).await {
-            Ok(_listener) => {
-                // The system hasn't bound it. Now we make sure none of
-                // our other tests have bound it.
-                let mut claimed_ports = CLAIMED_PORTS.lock().await;
-                if claimed_ports.contains(&port) {
-                    // Oops, another test is using it, try again
-                    tokio::time::sleep(Duration::from_millis(500)).await;
-                    attempts += 1;
-                } else {
-                    // Nobody was using it. Return the port; the TcpListener
-                    // that is currently bound to the port will be dropped,
-                    // thus freeing the port for our use.
-                    claimed_ports.insert(port);
-                    return Ok(port);
+impl Drop for TestSup {
+    /// Show what the Supervisor had to say if the test failed, which
+    /// is when it panics, or bails out (with `?`) before it gets
+    /// around to stopping the Supervisor.
+    ///
+    /// A Supervisor that's still running is killed, along with every
+    /// service it's running, so that none of them outlive the test.
+    fn drop(&mut self) {
+        if std::thread::panicking() || self.process.is_some() {
+            self.print_last_log_lines(FAILURE_LOG_LINES);
+        }
+        if self.process.is_some() {
+            if let Some(ref mut process_tree) = self.process_tree {
+                if let Err(err) = process_tree.refresh().and_then(|_| process_tree.kill()) {
+                    eprintln!("Failed to kill test supervisor processes: {:#}", err);
                 }
             }
-            // If the port is in use carry on
-            Err(err) if err.kind() == io::ErrorKind::AddrInUse => {
-                attempts += 1;
-            }
-            // If we are unable to bind for any other reason, bubble that up
-            Err(err) => {
-                return Err(anyhow!(err)).with_context(|| {
-                                            format!("Failed to bind TCP port {} due to io error",
-                                                    port)
-                                        });
-            }
-        }
-        if attempts > max_attempts {
-            return Err(anyhow!("Failed to find an unclaimed TCP port in {} \
-                                attempts",
-                               max_attempts));
         }
     }
 }
 
-/// Return a random unprivileged, unregistered TCP port number.
-fn random_port() -> u16 {
-    // IANA port registrations go to 49151
-    let between = Uniform::new_inclusive(49152, ::std::u16::MAX);
-    let mut rng = rand::thread_rng();
-    between.sample(&mut rng)
-}
-
 /// Find an executable relative to the current integration testing
 /// executable.
 ///
@@ -153,6 +162,15 @@ fn nocapture_set() -> bool {
     }
 }
 
+/// The key cache a Supervisor running in `fs_root` is given. It's
+/// passed explicitly, since by default a Supervisor not run as root
+/// looks in the user's home directory instead, and a test shouldn't
+/// pick up (or depend on) whatever keys happen to be there.
+fn key_cache_path(fs_root: &Path) -> PathBuf { fs_root.join(CACHE_KEY_PATH_POSTFIX) }
+
+/// Wait for something to be listening on the given local TCP port.
+/// Only a connection is made, with nothing sent over it, so this works
+/// just the same for ports that insist on a TLS handshake.
 async fn await_local_tcp_port(port: u16, timeout: Duration) -> Result<()> {
     let started_at = Instant::now();
     loop {
@@ -181,199 +199,775 @@ async fn await_local_tcp_port(port: u16, timeout: Duration) -> Result<()> {
     }
 }
 
-// TODO: Replace these types with the actual serialized types
-// once https://github.com/habitat-sh/habitat/issues/8470 is resolved.
-pub mod sup_gateway_api {
-    use habitat_core::os::process::Pid;
-    use habitat_sup::manager::service::ProcessTerminationReason;
-    use serde::Deserialize;
-
-    #[derive(Debug, Deserialize, PartialEq, Eq)]
-    pub struct Process {
-        pub state: String,
-        pub pid:   Option<Pid>,
-    }
-
-    #[derive(Debug, Deserialize, PartialEq, Eq)]
-    pub struct LastProcessState {
-        pub pid:                Option<Pid>,
-        pub termination_reason: ProcessTerminationReason,
-        pub terminated_at:      u64,
-    }
-    #[derive(Debug, Deserialize, PartialEq, Eq)]
-    pub struct Service {
-        pub desired_state:      String,
-        pub process:            Process,
-        pub last_process_state: Option<LastProcessState>,
-        pub next_restart_at:    Option<u64>,
-        pub restart_count:      u64,
+/// Wait for nothing to be listening on the given local TCP port.
+async fn await_local_tcp_port_closed(port: u16, timeout: Duration) -> Result<()> {
+    let started_at = Instant::now();
+    loop {
+        let timeout = timeout.saturating_sub(started_at.elapsed());
+        if timeout == Duration::ZERO {
+            return Err(anyhow!("Timed out waiting for tcp port {} to close", port));
+        }
+        match tokio::time::timeout(timeout,
+                                   TcpStream::connect(SocketAddrV4::new(Ipv4Addr::LOCALHOST,
+                                                                        port))).await
+        {
+            Ok(Err(err)) if err.kind() == io::ErrorKind::ConnectionRefused => return Ok(()),
+            _ => tokio::time::sleep(Duration::from_millis(500)).await,
+        }
     }
 }
 
-impl TestSup {
-    /// Create a new `TestSup` that will listen on randomly-selected
-    /// ports for both gossip and HTTP requests so tests run in
-    /// parallel don't step on each other.
-    ///
-    /// See also `new`.
-    pub async fn new_with_random_ports<R>(fs_root: R,
-                                          service_min_backoff_period: Duration,
-                                          service_max_backoff_period: Duration,
-                                          service_restart_cooldown_period: Duration)
-                                          -> Result<TestSup>
-        where R: AsRef<Path>
-    {
-        // We'll give 10 tries to find a free port number
-        let http_port =
-            unclaimed_port(10).await
-                              .context("Failed to allocate an unclaimed port for the \
-                                        supervisor HTTP server")?;
-        let butterfly_port =
-            unclaimed_port(10).await
-                              .context("Failed to allocate an unclaimed port for the \
-                                        supervisor Butterfly server")?;
-        let control_port =
-            unclaimed_port(10).await
-                              .context("Failed to allocate an unclaimed port for the \
-                                        supervisor Control Gateway server")?;
-
-        TestSup::new(fs_root,
-                     http_port,
-                     butterfly_port,
-                     control_port,
-                     service_min_backoff_period,
-                     service_max_backoff_period,
-                     service_restart_cooldown_period)
+/// Ask the launcher with the given pid to shut down.
+#[cfg(not(windows))]
+fn request_shutdown(pid: u32) -> Result<()> {
+    nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid as i32),
+                           nix::sys::signal::SIGTERM).context("Failed to send SIGTERM to test \
+                                                               supervisor process")
+}
+
+/// Ask the launcher with the given pid to shut down. It was started
+/// in a process group of its own, so that this reaches it (and its
+/// children) alone.
+#[cfg(windows)]
+fn request_shutdown(pid: u32) -> Result<()> {
+    use winapi::um::wincon::{GenerateConsoleCtrlEvent,
+                             CTRL_BREAK_EVENT};
+    if unsafe { GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, pid) } == 0 {
+        return Err(io::Error::last_os_error()).context("Failed to send ctrl-break to test \
+                                                        supervisor process");
     }
+    Ok(())
+}
 
-    /// Bundle up a Habitat Supervisor process along with an
-    /// associated Butterfly client for injecting new configuration
-    /// values. The Supervisor executable is the one that has been
-    /// compiled for the current `cargo test` invocation.
-    ///
-    /// The Supervisor is configured to run a single package for a
-    /// test. This package is assumed to have already been installed
-    /// relative to `fs_root` (i.e., the `FS_ROOT` environment
-    /// variable, which in our tests will be a randomly-named
-    /// temporary directory that this Supervisor will view as `/`.).
-    ///
-    /// A Butterfly client is also created for interacting with this
-    /// Supervisor and package. It is properly configured according to
-    /// the value provided for `butterfly_port`. To use it, see the
-    /// `apply_config` function.
-    ///
-    /// (No HTTP interaction with the Supervisor is currently called
-    /// for, so we don't have a HTTP client.)
-    pub fn new<R>(fs_root: R,
-                  http_port: u16,
-                  butterfly_port: u16,
-                  control_port: u16,
-                  service_min_backoff_period: Duration,
-                  service_max_backoff_period: Duration,
-                  service_restart_cooldown_period: Duration)
-                  -> Result<TestSup>
-        where R: AsRef<Path>
-    {
+/// Validate a list of services as shown by the HTTP gateway against
+/// its schema.
+fn assert_valid_services(services: &Value) -> Result<()> {
+    let json_string = serde_json::to_string(services)?;
+    assert_valid(&json_string, "http_gateway_services_schema.json");
+    Ok(())
+}
+
+/// How a service that `TestSup::wait_for_service_down` waited on went
+/// down.
+#[derive(Debug)]
+pub enum ServiceDown {
+    /// The Supervisor no longer knows of the service at all.
+    Unloaded,
+    /// The service is still loaded, but not running.
+    Stopped(ServiceInfo),
+}
+
+/// A restart that `TestSup::ensure_service_restarted` saw.
+#[derive(Debug)]
+pub struct ServiceRestart {
+    /// The service, running again.
+    pub service:     ServiceInfo,
+    /// The pid of its new process, which may be the same as the old
+    /// one's.
+    pub pid:         Pid,
+    /// How many times the service's process was seen to change while
+    /// waiting, going down and coming back up included. Changes that
+    /// came and went between two looks at the gateway aren't counted.
+    pub transitions: usize,
+}
+
+/// The files the HTTP gateway is served over TLS with; see
+/// `TestSupBuilder::with_tls`.
+#[derive(Clone, Debug)]
+struct GatewayTls {
+    cert_path: PathBuf,
+    key_path:  PathBuf,
+    ca_path:   Option<PathBuf>,
+}
+
+/// Configures a `TestSup`, starting from what most tests want: ports
+/// picked at random, so tests run in parallel don't step on each
+/// other, and backoff and cooldown periods short enough for tests.
+///
+/// Only `fs_root` has to be given.
+#[derive(Clone, Debug)]
+pub struct TestSupBuilder {
+    fs_root:          Option<PathBuf>,
+    http_port:        Option<u16>,
+    butterfly_port:   Option<u16>,
+    control_port:     Option<u16>,
+    random_ports:     bool,
+    min_backoff:      Duration,
+    max_backoff:      Duration,
+    restart_cooldown: Duration,
+    ring:             Option<String>,
+    ring_key:         Option<RingKey>,
+    tls:              Option<GatewayTls>,
+    http_socket:      Option<PathBuf>,
+    org:              Option<String>,
+    peers:            Vec<String>,
+    args:             Vec<String>,
+    env:              Vec<(String, String)>,
+    wait_for_ready:   bool,
+}
+
+impl Default for TestSupBuilder {
+    fn default() -> Self {
+        TestSupBuilder { fs_root:          None,
+                         http_port:        None,
+                         butterfly_port:   None,
+                         control_port:     None,
+                         random_ports:     false,
+                         min_backoff:      Duration::from_secs(10),
+                         max_backoff:      Duration::from_secs(30),
+                         restart_cooldown: Duration::from_secs(60),
+                         ring:             None,
+                         ring_key:         None,
+                         tls:              None,
+                         http_socket:      None,
+                         org:              None,
+                         peers:            Vec::new(),
+                         args:             Vec::new(),
+                         env:              Vec::new(),
+                         wait_for_ready:   false, }
+    }
+}
+
+impl TestSupBuilder {
+    pub fn new() -> Self { Self::default() }
+
+    /// The directory the Supervisor will see as `/` (i.e., its
+    /// `FS_ROOT`), which the test's packages are assumed to have
+    /// already been installed relative to.
+    pub fn fs_root(mut self, fs_root: impl AsRef<Path>) -> Self {
+        self.fs_root = Some(fs_root.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn http_port(mut self, port: u16) -> Self {
+        self.http_port = Some(port);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn butterfly_port(mut self, port: u16) -> Self {
+        self.butterfly_port = Some(port);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn control_port(mut self, port: u16) -> Self {
+        self.control_port = Some(port);
+        self
+    }
+
+    /// Insist on every port being picked at random. Ports not given
+    /// explicitly are anyway; this makes giving one an error.
+    pub fn random_ports(mut self) -> Self {
+        self.random_ports = true;
+        self
+    }
+
+    pub fn min_backoff(mut self, period: Duration) -> Self {
+        self.min_backoff = period;
+        self
+    }
+
+    pub fn max_backoff(mut self, period: Duration) -> Self {
+        self.max_backoff = period;
+        self
+    }
+
+    pub fn restart_cooldown(mut self, period: Duration) -> Self {
+        self.restart_cooldown = period;
+        self
+    }
+
+    /// Join the ring `name`, whose key must already be in the
+    /// Supervisor's key cache. See `with_ring` to have one made.
+    pub fn ring(mut self, name: impl Into<String>) -> Self {
+        self.ring = Some(name.into());
+        self
+    }
+
+    /// Put the Supervisor on an encrypted ring called `name`, with a
+    /// newly generated key. The key is available as
+    /// `TestSup::ring_key`, for giving to other Supervisors on the
+    /// same ring through `ring_key`.
+    pub fn with_ring(self, name: &str) -> Self { self.ring_key(RingKey::new(name)) }
+
+    /// Put the Supervisor on the encrypted ring `key` is for. The key
+    /// is written to a key cache under the `fs_root`, which the
+    /// Supervisor is pointed at, and rumors injected by the
+    /// `TestSup`'s Butterfly client are encrypted with it.
+    pub fn ring_key(mut self, key: RingKey) -> Self {
+        self.ring = Some(key.named_revision().name().clone());
+        self.ring_key = Some(key);
+        self
+    }
+
+    /// Serve the HTTP gateway over TLS, with the certificate chain in
+    /// `cert_path` and the private key in `key_path` (see
+    /// `tls::generate_self_signed_cert`). The `TestSup`'s HTTP client
+    /// trusts the CA certificate in `ca_path`, or, without one, any
+    /// certificate at all.
+    pub fn with_tls(mut self,
+                    cert_path: impl Into<PathBuf>,
+                    key_path: impl Into<PathBuf>,
+                    ca_path: Option<impl Into<PathBuf>>)
+                    -> Self {
+        self.tls = Some(GatewayTls { cert_path: cert_path.into(),
+                                     key_path:  key_path.into(),
+                                     ca_path:   ca_path.map(Into::into), });
+        self
+    }
+
+    /// Serve the HTTP gateway on a Unix domain socket at `path`,
+    /// rather than a TCP port. The `TestSup`'s HTTP client still talks
+    /// to `http_port`, which is relayed to the socket (see
+    /// `gateway_socket`), so the gateway helpers work all the same.
+    #[cfg(unix)]
+    pub fn http_socket(mut self, path: impl Into<PathBuf>) -> Self {
+        self.http_socket = Some(path.into());
+        self
+    }
+
+    /// Have `start` wait for the Supervisor's `/ready` endpoint to say
+    /// that its services have all been started, or failed to be, as
+    /// well as for its ports to open up.
+    pub fn wait_for_ready(mut self) -> Self {
+        self.wait_for_ready = true;
+        self
+    }
+
+    pub fn org(mut self, name: impl Into<String>) -> Self {
+        self.org = Some(name.into());
+        self
+    }
+
+    /// Add a peer for the Supervisor to gossip with. May be given more
+    /// than once.
+    pub fn peer(mut self, addr: impl Into<String>) -> Self {
+        self.peers.push(addr.into());
+        self
+    }
+
+    /// Pass an argument through to `hab-launch run` as is, after all
+    /// the others.
+    pub fn arg(mut self, raw: impl Into<String>) -> Self {
+        self.args.push(raw.into());
+        self
+    }
+
+    /// Set an environment variable for the Supervisor. Those the
+    /// harness sets itself can't be; see `TestSup::force_env`.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Check that the configuration makes sense, claim ports for it,
+    /// and bundle up a (not yet started) Supervisor process running
+    /// the `hab-sup` compiled for the current `cargo test` invocation,
+    /// along with a Butterfly client for injecting configuration into
+    /// it (see `TestSup::apply_config`) and an HTTP client for its
+    /// gateway.
+    pub async fn build(self) -> Result<TestSup> {
+        let fs_root = self.fs_root
+                          .clone()
+                          .ok_or_else(|| anyhow!("TestSupBuilder needs an fs_root"))?;
+        if self.random_ports
+           && (self.http_port.is_some()
+               || self.butterfly_port.is_some()
+               || self.control_port.is_some())
+        {
+            return Err(anyhow!("TestSupBuilder was asked for random ports, but \
+                                was also given explicit ones"));
+        }
+        if self.min_backoff > self.max_backoff {
+            return Err(anyhow!("TestSupBuilder's min_backoff ({:?}) is longer \
+                                than its max_backoff ({:?})",
+                               self.min_backoff,
+                               self.max_backoff));
+        }
+        if self.http_socket.is_some() && self.tls.is_some() {
+            return Err(anyhow!("TestSupBuilder can't serve the HTTP gateway over \
+                                TLS on a Unix domain socket"));
+        }
+        if let Some((key, _)) = self.env
+                                    .iter()
+                                    .find(|(key, _)| HARNESS_ENV_VARS.contains(&key.as_str()))
+        {
+            return Err(anyhow!("TestSupBuilder can't set {}, which the harness \
+                                sets itself; use TestSup::force_env to change it",
+                               key));
+        }
+
+        // We'll give 10 tries to find a free port number
+        let http_port = match self.http_port {
+            Some(port) => ClaimedPort::claim(port)?,
+            None => {
+                unclaimed_port(10).await
+                                  .context("Failed to allocate an unclaimed port for the \
+                                            supervisor HTTP server")?
+            }
+        };
+        let butterfly_port = match self.butterfly_port {
+            Some(port) => ClaimedPort::claim(port)?,
+            None => {
+                unclaimed_port(10).await
+                                  .context("Failed to allocate an unclaimed port for the \
+                                            supervisor Butterfly server")?
+            }
+        };
+        let control_port = match self.control_port {
+            Some(port) => ClaimedPort::claim(port)?,
+            None => {
+                unclaimed_port(10).await
+                                  .context("Failed to allocate an unclaimed port for the \
+                                            supervisor Control Gateway server")?
+            }
+        };
+
+        let cache = KeyCache::new(key_cache_path(&fs_root));
+        cache.setup()
+             .context("Failed to set up key cache for test supervisor")?;
+        if let Some(ref ring_key) = self.ring_key {
+            cache.write_key(ring_key)
+                 .context("Failed to write ring key for test supervisor")?;
+        }
+
+        let args = self.launcher_args(http_port.port(), butterfly_port.port(), control_port.port());
         let sup_exe = find_exe("hab-sup").context("Failed to find 'hab-sup' executable")?;
         let launcher_exe =
             find_exe("hab-launch").context("Failed to find 'hab-launch' executable")?;
 
-        let mut cmd = Command::new(launcher_exe);
-        let listen_host = "0.0.0.0";
-
-        cmd.env(
-            "FS_ROOT",
-            fs_root.as_ref().to_string_lossy().as_ref(),
-        )
-        // .env("HAB_INTERPRETER_IDENT", format!("{}/{}", origin, pkg_name))
-        .env("HAB_SUP_BINARY", &sup_exe)
-        .env(BLDR_URL_ENVVAR, "https://bldr.habitat.sh")
-        .env("HAB_BLDR_CHANNEL", "dev")
-        .arg("run")
-        .arg("--listen-gossip")
-        .arg(format!("{}:{}", listen_host, butterfly_port))
-        .arg("--listen-http")
-        .arg(format!("{}:{}", listen_host, http_port))
-        .arg("--listen-ctl")
-        .arg(format!("{}:{}", listen_host, control_port))
-        .arg("--service-min-backoff-period")
-        .arg(format!("{}", service_min_backoff_period.as_secs()))
-        .arg("--service-max-backoff-period")
-        .arg(format!("{}", service_max_backoff_period.as_secs()))
-        .arg("--service-restart-cooldown-period")
-        .arg(format!("{}", service_restart_cooldown_period.as_secs()))
-        // Note: we will have already dropped off the spec files
-        // needed to run our test service, so we don't supply a
-        // package identifier here
-        .stdin(Stdio::null());
-        if !nocapture_set() {
-            cmd.stdout(Stdio::null());
-            cmd.stderr(Stdio::null());
-        }
+        let mut cmd = std::process::Command::new(launcher_exe);
+        cmd.env("FS_ROOT", fs_root.to_string_lossy().as_ref())
+           .env("HAB_SUP_BINARY", &sup_exe)
+           .env(BLDR_URL_ENVVAR, "https://bldr.habitat.sh")
+           .env("HAB_BLDR_CHANNEL", "dev")
+           .envs(self.env.iter().cloned())
+           .args(&args)
+           .stdin(Stdio::null())
+           .stdout(Stdio::piped())
+           .stderr(Stdio::piped());
+        // A process group of its own, shared with the Supervisor, so
+        // that `ProcessTree` can kill them both, and nothing else.
+        #[cfg(not(windows))]
+        std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+        let mut cmd = Command::from(cmd);
         cmd.kill_on_drop(true);
+        #[cfg(windows)]
+        cmd.creation_flags(winapi::um::winbase::CREATE_NEW_PROCESS_GROUP);
 
-        let bc = test_butterfly::Client::new(butterfly_port).context("Failed to create \
-                                                                      butterfly client for test \
-                                                                      supervsior")?;
+        let bc = test_butterfly::Client::new(butterfly_port.port(), self.ring_key.clone())
+            .context("Failed to create butterfly client for test supervisor")?;
+        let ctl_client =
+            test_ctl_gateway::Client::new(control_port.port(),
+                                          fs_root.join("hab").join("sup").join("default"));
+        let mut api_client = reqwest::ClientBuilder::new();
+        if let Some(ref tls) = self.tls {
+            api_client = match tls.ca_path {
+                Some(ref ca_path) => {
+                    let pem = fs::read(ca_path).with_context(|| {
+                                                   format!("Failed to read CA certificate {}",
+                                                           ca_path.display())
+                                               })?;
+                    let ca = reqwest::Certificate::from_pem(&pem).with_context(|| {
+                                                                     format!("Invalid CA \
+                                                                              certificate {}",
+                                                                             ca_path.display())
+                                                                 })?;
+                    api_client.add_root_certificate(ca)
+                }
+                None => api_client.danger_accept_invalid_certs(true),
+            };
+        }
         let api_client =
-            reqwest::ClientBuilder::new().build()
-                                         .context("Failed to create reqwest API client for \
-                                                   test supervisor")?;
-        Ok(TestSup { hab_root: fs_root.as_ref().to_path_buf(),
+            api_client.build()
+                      .context("Failed to create reqwest API client for test supervisor")?;
+        let gateway_scheme = if self.tls.is_some() { "https" } else { "http" };
+        #[cfg(unix)]
+        let gateway_relay = match self.http_socket {
+            Some(ref path) => {
+                Some(GatewaySocketRelay::start(http_port.port(), path.clone()).await?)
+            }
+            None => None,
+        };
+        let log = SupLog::new(nocapture_set())?;
+        Ok(TestSup { hab_root: fs_root,
                      http_port,
                      butterfly_port,
                      control_port,
                      butterfly_client: bc,
+                     ctl_client,
                      api_client,
+                     gateway_scheme,
+                     ring_key: self.ring_key.clone(),
+                     #[cfg(unix)]
+                     gateway_relay,
+                     args,
                      cmd,
-                     process: None })
+                     process: None,
+                     process_tree: None,
+                     log,
+                     wait_for_ready: self.wait_for_ready })
+    }
+
+    fn launcher_args(&self, http_port: u16, butterfly_port: u16, control_port: u16) -> Vec<String> {
+        let listen_host = "0.0.0.0";
+        let listen_http = match self.http_socket {
+            Some(ref path) => format!("unix://{}", path.display()),
+            None => format!("{}:{}", listen_host, http_port),
+        };
+        let mut args = vec!["run".to_string(),
+                            "--listen-gossip".to_string(),
+                            format!("{}:{}", listen_host, butterfly_port),
+                            "--listen-http".to_string(),
+                            listen_http,
+                            "--listen-ctl".to_string(),
+                            format!("{}:{}", listen_host, control_port),
+                            "--service-min-backoff-period".to_string(),
+                            self.min_backoff.as_secs().to_string(),
+                            "--service-max-backoff-period".to_string(),
+                            self.max_backoff.as_secs().to_string(),
+                            "--service-restart-cooldown-period".to_string(),
+                            self.restart_cooldown.as_secs().to_string(),];
+        if let Some(ref ring) = self.ring {
+            args.extend(vec!["--ring".to_string(), ring.clone()]);
+        }
+        if let Some(ref fs_root) = self.fs_root {
+            args.extend(vec!["--cache-key-path".to_string(),
+                             key_cache_path(fs_root).to_string_lossy().into_owned()]);
+        }
+        if let Some(ref tls) = self.tls {
+            args.extend(vec!["--key".to_string(),
+                             tls.key_path.to_string_lossy().into_owned(),
+                             "--certs".to_string(),
+                             tls.cert_path.to_string_lossy().into_owned()]);
+        }
+        if let Some(ref org) = self.org {
+            args.extend(vec!["--org".to_string(), org.clone()]);
+        }
+        for peer in &self.peers {
+            args.extend(vec!["--peer".to_string(), peer.clone()]);
+        }
+        // Note: we will have already dropped off the spec files
+        // needed to run our test service, so we don't supply a
+        // package identifier here
+        args.extend(self.args.iter().cloned());
+        args
+    }
+}
+
+impl TestSup {
+    /// Create a new `TestSup` that will listen on randomly-selected
+    /// ports for both gossip and HTTP requests so tests run in
+    /// parallel don't step on each other.
+    ///
+    /// See `TestSupBuilder` for more options.
+    pub async fn new_with_random_ports<R>(fs_root: R,
+                                          service_min_backoff_period: Duration,
+                                          service_max_backoff_period: Duration,
+                                          service_restart_cooldown_period: Duration)
+                                          -> Result<TestSup>
+        where R: AsRef<Path>
+    {
+        TestSupBuilder::new().fs_root(fs_root)
+                             .random_ports()
+                             .min_backoff(service_min_backoff_period)
+                             .max_backoff(service_max_backoff_period)
+                             .restart_cooldown(service_restart_cooldown_period)
+                             .build()
+                             .await
     }
 
-    /// Spawn a process actually running the Supervisor.
+    /// Spawn a process actually running the Supervisor, and wait for
+    /// its ports to open up, and for it to be ready if it was built to
+    /// wait for that (see `TestSupBuilder::wait_for_ready`).
     pub async fn start(&mut self, timeout: Duration) -> Result<()> {
         let started_at = Instant::now();
-        let child = self.cmd
-                        .spawn()
-                        .context("Failed to spawn supervisor process")?;
+        let mut child = self.cmd
+                            .spawn()
+                            .context("Failed to spawn supervisor process")?;
+        self.process_tree = Some(ProcessTree::new(&child)?);
+        self.log.capture(&mut child)?;
         self.process = Some(child);
         let timeout = timeout.saturating_sub(started_at.elapsed());
-        tokio::try_join!(await_local_tcp_port(self.http_port, timeout),
-                         await_local_tcp_port(self.butterfly_port, timeout),
-                         await_local_tcp_port(self.control_port, timeout)
-                        ).context("Timed out waiting for test supervisor to start")?;
+        tokio::try_join!(self.await_gateway(timeout),
+                         await_local_tcp_port(self.butterfly_port.port(), timeout),
+                         await_local_tcp_port(self.control_port.port(), timeout)).with_context(|| {
+            format!("Timed out waiting for test supervisor to start; its last lines were:\n{}",
+                    self.log.last_lines(FAILURE_LOG_LINES).join("\n"))
+        })?;
+        if self.wait_for_ready {
+            self.await_ready(timeout.saturating_sub(started_at.elapsed()))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Poll `/ready` until the Supervisor says it's ready. On timeout,
+    /// the error includes what it was still waiting on.
+    pub async fn await_ready(&self, timeout: Duration) -> Result<Readiness> {
+        self.wait_for_readiness(|readiness| readiness.ready, timeout)
+            .await
+    }
+
+    /// Poll `/ready` until `predicate` holds for what it says, whether
+    /// or not that's that the Supervisor is ready, and return that.
+    /// On timeout, the error includes the last thing it said.
+    pub async fn wait_for_readiness<F>(&self, predicate: F, timeout: Duration) -> Result<Readiness>
+        where F: Fn(&Readiness) -> bool
+    {
+        let started_at = Instant::now();
+        let mut last_seen = None;
+        loop {
+            match self.readiness().await? {
+                Some(readiness) if predicate(&readiness) => return Ok(readiness),
+                seen => last_seen = seen.or(last_seen),
+            }
+            if started_at.elapsed() > timeout {
+                return Err(anyhow!("Timed out waiting on test supervisor's \
+                                    readiness; it last said {:?}",
+                                   last_seen));
+            }
+            tokio::time::sleep(Duration::from_millis(250)).await;
+        }
+    }
+
+    /// Set an environment variable for the Supervisor, from the next
+    /// time it's started on. Those the harness sets itself (`FS_ROOT`,
+    /// `HAB_SUP_BINARY`, and the Builder URL) are refused; use
+    /// `force_env` if a test really means to change one of them.
+    pub fn env(&mut self, key: &str, value: &str) -> Result<&mut Self> {
+        if HARNESS_ENV_VARS.contains(&key) {
+            return Err(anyhow!("{} is set by the test harness; use force_env to \
+                                change it anyway",
+                               key));
+        }
+        self.force_env(key, value)
+    }
+
+    /// Set an environment variable for the Supervisor, even one the
+    /// harness sets itself.
+    pub fn force_env(&mut self, key: &str, value: &str) -> Result<&mut Self> {
+        self.ensure_not_running("set an environment variable for")?;
+        self.cmd.env(key, value);
+        Ok(self)
+    }
+
+    /// Pass another argument to `hab-launch run`, after all the
+    /// others, from the next time the Supervisor is started on.
+    pub fn arg(&mut self, arg: impl Into<String>) -> Result<&mut Self> {
+        self.ensure_not_running("add an argument for")?;
+        let arg = arg.into();
+        self.cmd.arg(&arg);
+        self.args.push(arg);
+        Ok(self)
+    }
+
+    /// The environment variables the Supervisor is started with, on
+    /// top of those it inherits from the test: the harness's own, and
+    /// any set through `TestSupBuilder::env`, `env`, or `force_env`.
+    /// The arguments it's started with are in `args`.
+    pub fn env_vars(&self) -> HashMap<String, String> {
+        self.cmd
+            .as_std()
+            .get_envs()
+            .filter_map(|(key, value)| {
+                value.map(|value| {
+                         (key.to_string_lossy().into_owned(), value.to_string_lossy().into_owned())
+                     })
+            })
+            .collect()
+    }
+
+    fn ensure_not_running(&self, action: &str) -> Result<()> {
+        if self.process.is_some() {
+            return Err(anyhow!("Can't {} a test supervisor that's already \
+                                running; stop it first",
+                               action));
+        }
         Ok(())
     }
 
-    /// Stop the Supervisor.
-    /// TODO: Move this to a drop implementation of the supervisor
-    /// We need tokio 1.13 or later to make use of the `Mutex::blocking_lock` function to free up
-    /// the ports. We can also synchronously terminate the supervisor when the TestSup struct is
-    /// dropped
+    /// Everything the Supervisor has written to stdout and stderr so
+    /// far, across restarts.
+    pub fn logs(&self) -> String { self.log.contents() }
+
+    /// A marker for the current end of the Supervisor's output; see
+    /// `logs_since`.
+    pub fn log_marker(&self) -> LogMarker { self.log.marker() }
+
+    /// Everything the Supervisor has written since `marker` was taken
+    /// with `log_marker`.
+    pub fn logs_since(&self, marker: LogMarker) -> String { self.log.contents_since(marker) }
+
+    /// Wait for the Supervisor to write a line matching the regular
+    /// expression `pattern`, and return it. Lines written before this
+    /// is called count, too.
+    pub async fn wait_for_log_line(&self, pattern: &str, timeout: Duration) -> Result<String> {
+        self.log.wait_for_line(pattern, timeout).await
+    }
+
+    /// Print the last `n` lines the Supervisor wrote to the test's
+    /// stderr.
+    pub fn print_last_log_lines(&self, n: usize) {
+        eprintln!("---- last {} lines of test supervisor output ({}) ----\n{}",
+                  n,
+                  self.hab_root.display(),
+                  self.log.last_lines(n).join("\n"));
+    }
+
+    /// Stop the Supervisor, giving it `DEFAULT_SHUTDOWN_TIMEOUT` to
+    /// shut down gracefully before killing it, and anything it leaves
+    /// running.
     pub async fn stop(&mut self) -> Result<()> {
-        {
-            let mut claimed_ports = CLAIMED_PORTS.lock().await;
-            claimed_ports.remove(&self.http_port);
-            claimed_ports.remove(&self.butterfly_port);
-            claimed_ports.remove(&self.control_port);
-        }
-        if let Some(mut process) = self.process.take() {
-            if cfg!(not(windows)) {
-                if let Some(pid) = process.id() {
-                    nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid as i32),
-                                           nix::sys::signal::SIGTERM).context("Failed to send \
-                                                                               SIGTERM to test \
-                                                                               supervisor \
-                                                                               process")?;
-                    process.wait()
-                           .await
-                           .context("Failed to kill supervisor process")?;
-                }
-            } else {
+        self.shutdown(DEFAULT_SHUTDOWN_TIMEOUT).await?;
+        Ok(())
+    }
+
+    /// Ask the Supervisor to shut down the way an operator would
+    /// (SIGTERM, or ctrl-break on Windows), so that its shutdown path,
+    /// services' post-stop hooks included, actually runs. Waits up to
+    /// `timeout` for the launcher to exit and for the HTTP, gossip,
+    /// and control ports to close, and kills the launcher if it hasn't
+    /// exited by then. Whatever it leaves running, the Supervisor or
+    /// services included, is killed too; if any of that won't die,
+    /// this fails with their pids.
+    ///
+    /// Returns whether the shutdown was graceful. Either way, the
+    /// ports claimed for this Supervisor stay claimed until it's
+    /// dropped, so it can be started again.
+    pub async fn shutdown(&mut self, timeout: Duration) -> Result<bool> {
+        let started_at = Instant::now();
+        let mut process = match self.process.take() {
+            Some(process) => process,
+            None => return Ok(true),
+        };
+        if let Some(ref mut process_tree) = self.process_tree {
+            process_tree.refresh()?;
+        }
+        // No id means the process has already exited and been waited on.
+        if let Some(pid) = process.id() {
+            request_shutdown(pid)?;
+        }
+        let exited = match tokio::time::timeout(timeout, process.wait()).await {
+            Ok(status) => {
+                status.context("Failed to wait for supervisor process")?;
+                true
+            }
+            Err(_) => {
                 process.kill()
                        .await
                        .context("Failed to kill supervisor process")?;
+                false
             }
+        };
+        if let Some(ref process_tree) = self.process_tree {
+            process_tree.kill_and_wait().await?;
+        }
+        if !exited {
+            return Ok(false);
+        }
+        let timeout = timeout.saturating_sub(started_at.elapsed());
+        let ports_closed = tokio::try_join!(self.await_gateway_closed(timeout),
+                                            await_local_tcp_port_closed(self.butterfly_port
+                                                                            .port(),
+                                                                        timeout),
+                                            await_local_tcp_port_closed(self.control_port.port(),
+                                                                        timeout)).is_ok();
+        Ok(ports_closed)
+    }
+
+    /// Wait for the HTTP gateway to be listening, on its socket if it
+    /// has one.
+    async fn await_gateway(&self, timeout: Duration) -> Result<()> {
+        #[cfg(unix)]
+        if let Some(ref relay) = self.gateway_relay {
+            return gateway_socket::await_socket(relay.path(), timeout).await;
+        }
+        await_local_tcp_port(self.http_port.port(), timeout).await
+    }
+
+    async fn await_gateway_closed(&self, timeout: Duration) -> Result<()> {
+        #[cfg(unix)]
+        if let Some(ref relay) = self.gateway_relay {
+            return gateway_socket::await_socket_closed(relay.path(), timeout).await;
+        }
+        await_local_tcp_port_closed(self.http_port.port(), timeout).await
+    }
+
+    /// Fail if anything the Supervisor started, itself included, is
+    /// still running. `stop` already makes sure of that, so this is for
+    /// tests that want to say so explicitly.
+    pub fn assert_no_orphans(&self) -> Result<()> {
+        if self.process.is_some() {
+            return Err(anyhow!("Test supervisor is still running; stop it before \
+                                checking for orphans"));
+        }
+        let survivors = match self.process_tree {
+            Some(ref process_tree) => process_tree.survivors()?,
+            None => return Ok(()),
+        };
+        if survivors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("Processes started by the test supervisor are \
+                         still running: {:?}",
+                        survivors))
         }
+    }
+
+    /// Shut the Supervisor down and start a new one in its place,
+    /// with the same `hab_root`, ports, and command line. The ports
+    /// stay claimed throughout. `timeout` covers both the shutdown and
+    /// waiting for the new Supervisor's ports to come up.
+    pub async fn restart(&mut self, timeout: Duration) -> Result<()> {
+        let started_at = Instant::now();
+        if !self.shutdown(timeout).await? {
+            return Err(anyhow!("Test supervisor did not shut down gracefully, so \
+                                its ports may still be in use"));
+        }
+        self.butterfly_client =
+            test_butterfly::Client::new(self.butterfly_port.port(), self.ring_key.clone())
+                .context("Failed to create butterfly client for test supervisor")?;
+        self.start(timeout.saturating_sub(started_at.elapsed()))
+            .await
+            .context("Failed to restart test supervisor")
+    }
+
+    /// A Butterfly client for the Supervisor's encrypted ring, but
+    /// with the wrong key: one with the same name and different key
+    /// material. The Supervisor should ignore everything it sends.
+    pub fn butterfly_client_with_wrong_ring_key(&self) -> Result<test_butterfly::Client> {
+        let ring_key = self.ring_key
+                           .as_ref()
+                           .ok_or_else(|| anyhow!("Test supervisor is not on an encrypted ring"))?;
+        let wrong_key = RingKey::new(ring_key.named_revision().name());
+        test_butterfly::Client::new(self.butterfly_port.port(), Some(wrong_key))
+    }
+
+    /// The Supervisor's key cache, for staging the keys it needs to
+    /// decrypt what it's sent.
+    pub fn key_cache(&self) -> KeyCache { KeyCache::new(key_cache_path(&self.hab_root)) }
+
+    /// Put `ring_key`, a new revision of the ring's key, in the
+    /// Supervisor's key cache, and have it reload its ring key. From
+    /// then on, `butterfly_client` sends with `ring_key` too.
+    pub async fn rotate_ring_key(&mut self, ring_key: RingKey) -> Result<()> {
+        if self.ring_key.is_none() {
+            return Err(anyhow!("Test supervisor is not on an encrypted ring"));
+        }
+        self.key_cache()
+            .write_key(&ring_key)
+            .context("Failed to write new ring key for test supervisor")?;
+        self.ctl_client
+            .request(ctl::SupRingKeyReload::default())
+            .await
+            .context("Failed to reload ring key")?
+            .into_result()?;
+        self.butterfly_client =
+            test_butterfly::Client::new(self.butterfly_port.port(), Some(ring_key.clone()))
+                .context("Failed to create butterfly client for test supervisor")?;
+        self.ring_key = Some(ring_key);
         Ok(())
     }
 
@@ -386,36 +980,233 @@ impl TestSup {
                               -> Result<()> {
         self.butterfly_client
             .apply(package_name, service_group, toml_config)
-            .context("Failed to apply configuration")
+            .context("Failed to apply configuration")?;
+        Ok(())
+    }
+
+    /// The equivalent of performing `hab config apply` with the given
+    /// configuration for a service group in an organization, which
+    /// encrypts it for the service; see
+    /// `test_butterfly::Client::apply_encrypted`. Returns the
+    /// incarnation it was applied with.
+    pub async fn apply_encrypted_config(&mut self,
+                                        package_name: &str,
+                                        service_group: &str,
+                                        toml_config: &str,
+                                        user_secret: &UserSecretEncryptionKey,
+                                        service_public: &ServicePublicEncryptionKey)
+                                        -> Result<u64> {
+        self.butterfly_client
+            .apply_encrypted(package_name,
+                             service_group,
+                             toml_config,
+                             user_secret,
+                             service_public)
+            .context("Failed to apply encrypted configuration")
     }
 
-    /// Attempt to get state of the service from the API. This does not reattempt to
-    /// fetch the state if there is a failure.
-    pub async fn try_get_service_state(&self,
+    /// Apply configuration like `apply_config`, then wait for the
+    /// Supervisor's census to show it, returning the incarnation it
+    /// was applied with.
+    pub async fn apply_config_and_wait(&mut self,
                                        package_name: &str,
-                                       service_group: &str)
-                                       -> Result<Option<sup_gateway_api::Service>> {
-        let req = self.api_client
-                      .request(Method::GET,
-                               format!("http://localhost:{}/services/{}/{}",
-                                       self.http_port, package_name, service_group).as_str())
-                      .build()
-                      .context("Failed to construct API request to supervisor HTTP endpoint")?;
-        let res = self.api_client.execute(req).await.ok();
-        if let Some(res) = res {
-            let json = res.json::<Value>().await.ok();
-            if let Some(json) = json {
-                // Validate json response against schema
-                let json_string = serde_json::to_string(&Value::Array(vec![json.clone()]))?;
-                assert_valid(&json_string, "http_gateway_services_schema.json");
-                let service: sup_gateway_api::Service = serde_json::from_value(json)?;
-                Ok(Some(service))
-            } else {
-                Ok(None)
+                                       service_group: &str,
+                                       toml_config: &str,
+                                       timeout: Duration)
+                                       -> Result<u64> {
+        let incarnation = test_butterfly::Client::new_incarnation()?;
+        self.apply_config_with_incarnation_and_wait(package_name,
+                                                    service_group,
+                                                    toml_config,
+                                                    incarnation,
+                                                    timeout)
+            .await?;
+        Ok(incarnation)
+    }
+
+    /// Apply configuration with the given incarnation, then wait for
+    /// the Supervisor's census to show it. The Supervisor ignores
+    /// configuration that isn't newer than what it has; that's an
+    /// error here, rather than a wait that times out (or, if the
+    /// incarnations are equal, looks like success).
+    pub async fn apply_config_with_incarnation_and_wait(&mut self,
+                                                        package_name: &str,
+                                                        service_group: &str,
+                                                        toml_config: &str,
+                                                        incarnation: u64,
+                                                        timeout: Duration)
+                                                        -> Result<()> {
+        let rejected = |current: u64| {
+            anyhow!("Configuration for {}.{} with incarnation {} was rejected; the Supervisor \
+                     already has incarnation {}",
+                    package_name,
+                    service_group,
+                    incarnation,
+                    current)
+        };
+        if let Ok(census) = self.census().await {
+            if let Some(current) = census.config_incarnation(package_name, service_group) {
+                if current >= incarnation {
+                    return Err(rejected(current));
+                }
             }
-        } else {
-            Ok(None)
         }
+        self.butterfly_client
+            .apply_with_incarnation(package_name, service_group, toml_config, incarnation)
+            .context("Failed to apply configuration")?;
+        let census = self.wait_for_census(timeout, |census| {
+                             census.config_incarnation(package_name, service_group)
+                                   .map_or(false, |current| current >= incarnation)
+                         })
+                         .await
+                         .with_context(|| {
+                             format!("Configuration for {}.{} with incarnation {} never landed",
+                                     package_name, service_group, incarnation)
+                         })?;
+        match census.config_incarnation(package_name, service_group) {
+            Some(current) if current > incarnation => Err(rejected(current)),
+            _ => Ok(()),
+        }
+    }
+
+    /// The equivalent of performing `hab file upload` with the given
+    /// contents; see `test_butterfly::Client::upload_file`.
+    pub async fn upload_file(&mut self,
+                             package_name: &str,
+                             service_group: &str,
+                             filename: &str,
+                             contents: &[u8],
+                             version: u64,
+                             keys: Option<(&UserSecretEncryptionKey,
+                                     &ServicePublicEncryptionKey)>)
+                             -> Result<()> {
+        self.butterfly_client
+            .upload_file(package_name,
+                         service_group,
+                         filename,
+                         contents,
+                         version,
+                         keys)
+            .context("Failed to upload file")?;
+        Ok(())
+    }
+
+    /// The HTTP gateway's view of a service, checked against its
+    /// schema. `None` means the gateway doesn't know of the service,
+    /// or isn't answering (yet). This does not retry.
+    pub async fn service_info(&self,
+                              package_name: &str,
+                              service_group: &str)
+                              -> Result<Option<ServiceInfo>> {
+        let path = format!("/services/{}/{}", package_name, service_group);
+        let res = match self.gateway_get(&path).await? {
+            Some(res) if res.status().is_success() => res,
+            _ => return Ok(None),
+        };
+        let json = res.json::<Value>()
+                      .await
+                      .with_context(|| format!("Failed to read {} from HTTP gateway", path))?;
+        assert_valid_services(&Value::Array(vec![json.clone()]))?;
+        let service = serde_json::from_value(json).with_context(|| {
+                                                      format!("Failed to parse service {}.{}",
+                                                              package_name, service_group)
+                                                  })?;
+        Ok(Some(service))
+    }
+
+    /// Every service the Supervisor has loaded, as its HTTP gateway
+    /// shows them, checked against its schema.
+    pub async fn services(&self) -> Result<Vec<ServiceInfo>> {
+        let json = self.gateway_get("/services")
+                       .await?
+                       .ok_or_else(|| anyhow!("Test supervisor's HTTP gateway is not answering"))?
+                       .json::<Value>()
+                       .await
+                       .context("Failed to read services from HTTP gateway")?;
+        assert_valid_services(&json)?;
+        serde_json::from_value(json).context("Failed to parse services")
+    }
+
+    /// The services matching `query`, a `/services` query string such
+    /// as "origin=core&limit=10", checked against the gateway's schema.
+    /// A query the gateway rejects is an error, with its explanation.
+    pub async fn services_matching(&self, query: &str) -> Result<ServicesPage> {
+        let res = self.gateway_get(&format!("/services?{}", query))
+                      .await?
+                      .ok_or_else(|| anyhow!("Test supervisor's HTTP gateway is not answering"))?;
+        let status = res.status();
+        if !status.is_success() {
+            let reason = res.text().await.unwrap_or_default();
+            return Err(anyhow!("Query '{}' failed with {}: {}",
+                               query,
+                               status,
+                               reason));
+        }
+        let total = res.headers()
+                       .get("X-Total-Count")
+                       .and_then(|total| total.to_str().ok())
+                       .and_then(|total| total.parse().ok())
+                       .ok_or_else(|| anyhow!("No total count for query '{}'", query))?;
+        let json = res.json::<Value>()
+                      .await
+                      .with_context(|| format!("Failed to read services matching '{}'", query))?;
+        assert_valid_services(&json)?;
+        let services = serde_json::from_value(json).context("Failed to parse services")?;
+        Ok(ServicesPage { total, services })
+    }
+
+    /// Whether the Supervisor has started up its services, as its HTTP
+    /// gateway's `/ready` endpoint says, whether it's ready or not.
+    /// `None` means the gateway isn't answering (yet).
+    pub async fn readiness(&self) -> Result<Option<Readiness>> {
+        let res = match self.gateway_get("/ready").await? {
+            Some(res) => res,
+            None => return Ok(None),
+        };
+        let status = res.status();
+        if status != reqwest::StatusCode::OK && status != reqwest::StatusCode::SERVICE_UNAVAILABLE {
+            return Err(anyhow!("Unexpected response from /ready: {}", status));
+        }
+        let readiness: Readiness = res.json()
+                                      .await
+                                      .context("Failed to parse supervisor readiness")?;
+        if readiness.ready != (status == reqwest::StatusCode::OK) {
+            return Err(anyhow!("/ready answered {} with {:?}", status, readiness));
+        }
+        Ok(Some(readiness))
+    }
+
+    /// What the Supervisor's gossip layer knows, as its HTTP gateway
+    /// shows it.
+    pub async fn butterfly_info(&self) -> Result<ButterflyInfo> {
+        self.gateway_get("/butterfly")
+            .await?
+            .ok_or_else(|| anyhow!("Test supervisor's HTTP gateway is not answering"))?
+            .json()
+            .await
+            .context("Failed to parse supervisor butterfly state")
+    }
+
+    /// The revisions of the ring key the Supervisor accepts gossip
+    /// encrypted with, as its HTTP gateway shows them.
+    pub async fn ring_key_revisions(&self) -> Result<RingKeyRevisions> {
+        self.gateway_get("/butterfly/ring-key")
+            .await?
+            .ok_or_else(|| anyhow!("Test supervisor's HTTP gateway is not answering"))?
+            .json()
+            .await
+            .context("Failed to parse supervisor ring key revisions")
+    }
+
+    /// What the Supervisor's gossip is doing, as its HTTP gateway last
+    /// refreshed it (see `HAB_SUP_GOSSIP_STATS_INTERVAL_SECS`).
+    pub async fn gossip_stats(&self) -> Result<GossipStats> {
+        self.gateway_get("/butterfly/stats")
+            .await?
+            .ok_or_else(|| anyhow!("Test supervisor's HTTP gateway is not answering"))?
+            .json()
+            .await
+            .context("Failed to parse supervisor gossip stats")
     }
 
     /// Attempt to get state of the service from the API. This reattempts
@@ -424,7 +1215,7 @@ impl TestSup {
                                    package_name: &str,
                                    service_group: &str,
                                    timeout: Duration)
-                                   -> Result<sup_gateway_api::Service> {
+                                   -> Result<ServiceInfo> {
         let started_at = Instant::now();
         loop {
             if started_at.elapsed() > timeout {
@@ -433,7 +1224,7 @@ impl TestSup {
                                    package_name,
                                    service_group));
             }
-            if let Some(service_state) = self.try_get_service_state(package_name, service_group)
+            if let Some(service_state) = self.service_info(package_name, service_group)
                                              .await
                                              .with_context(|| {
                                                  format!("Failed to get state of the service {}.{}",
@@ -445,6 +1236,90 @@ impl TestSup {
         }
     }
 
+    /// Wait for the Supervisor to report an error for a service it
+    /// refused to load, returning the error message.
+    pub async fn ensure_service_has_load_error(&self,
+                                               ident: &str,
+                                               timeout: Duration)
+                                               -> Result<String> {
+        let started_at = Instant::now();
+        loop {
+            if let Some(res) = self.gateway_get("/errors").await? {
+                let mut errors = res.json::<HashMap<String, String>>()
+                                    .await
+                                    .context("Failed to parse supervisor errors")?;
+                if let Some(error) = errors.remove(ident) {
+                    return Ok(error);
+                }
+            }
+            if started_at.elapsed() > timeout {
+                return Err(anyhow!("Test supervisor did not report an error for {} \
+                                    within {:.2} secs",
+                                   ident,
+                                   timeout.as_secs_f64()));
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    /// Wait for the Supervisor to report that the background task
+    /// `name` has been restarted and is running again, returning its
+    /// state.
+    pub async fn ensure_task_restarted(&self, name: &str, timeout: Duration) -> Result<Task> {
+        let started_at = Instant::now();
+        loop {
+            if let Some(res) = self.gateway_get("/supervisor/tasks").await? {
+                let tasks = res.json::<Vec<Task>>()
+                               .await
+                               .context("Failed to parse supervisor tasks")?;
+                if let Some(task) = tasks.into_iter().find(|t| {
+                                                         t.name == name
+                                                         && t.restart_count > 0
+                                                         && t.state == "running"
+                                                     })
+                {
+                    return Ok(task);
+                }
+            }
+            if started_at.elapsed() > timeout {
+                return Err(anyhow!("Test supervisor did not restart task {} within \
+                                    {:.2} secs",
+                                   name,
+                                   timeout.as_secs_f64()));
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    /// Read a metric for a service group from the Supervisor's
+    /// Prometheus endpoint. Returns `None` if the metric hasn't been
+    /// recorded for the group yet.
+    pub async fn service_group_metric(&self,
+                                      metric: &str,
+                                      service_group: &str)
+                                      -> Result<Option<f64>> {
+        let req = self.api_client
+                      .request(Method::GET, self.gateway_url("/metrics").as_str())
+                      .build()
+                      .context("Failed to construct API request to supervisor HTTP endpoint")?;
+        let metrics = self.api_client
+                          .execute(req)
+                          .await
+                          .context("Failed to get supervisor metrics")?
+                          .text()
+                          .await
+                          .context("Failed to read supervisor metrics")?;
+        let prefix = format!("{}{{service_group=\"{}\"}} ", metric, service_group);
+        metrics.lines()
+               .find_map(|line| line.strip_prefix(&prefix))
+               .map(|value| {
+                   value.trim()
+                        .parse()
+                        .with_context(|| format!("Failed to parse {} value '{}'", metric, value))
+               })
+               .transpose()
+    }
+
     /// Send command to start a service. This does not wait for the service to be initialized.
     /// If you wish to ensure the service has started use `ensure_service_started` after calling
     /// this.
@@ -499,6 +1374,282 @@ impl TestSup {
         Ok(())
     }
 
+    /// Send command to update the spec of a loaded service, passing
+    /// `args` through to `hab svc update`. This does not wait for the
+    /// update to be applied.
+    pub async fn service_update(&self,
+                                origin: &str,
+                                package_name: &str,
+                                args: &[&str])
+                                -> Result<()> {
+        let hab_exe = find_exe("hab").context("Failed to find 'hab' executable")?;
+        let mut cmd = Command::new(&hab_exe);
+        cmd.env("FS_ROOT", self.hab_root.display().to_string())
+           .env("HAB_LICENSE", "accept-no-persist")
+           .arg("svc")
+           .arg("update")
+           .arg(format!("{}/{}", origin, package_name))
+           .args(args)
+           .arg("--remote-sup")
+           .arg(format!("localhost:{}", self.control_port))
+           .stdin(Stdio::null());
+        if !nocapture_set() {
+            cmd.stdout(Stdio::null());
+            cmd.stderr(Stdio::null());
+        }
+        cmd.kill_on_drop(true);
+        let status =
+            cmd.spawn()
+               .context("Failed to run hab cli")?
+               .wait()
+               .await
+               .with_context(|| format!("Failed to update service {}/{}", origin, package_name))?;
+        if !status.success() {
+            return Err(anyhow!("Updating service {}/{} failed: {}",
+                               origin,
+                               package_name,
+                               status));
+        }
+        Ok(())
+    }
+
+    /// Load the service `ident` through the control gateway, the way
+    /// `hab svc load` does. Anything but the identifier set in `opts`
+    /// is passed along as is. This does not wait for the service to
+    /// start; use `ensure_service_started` for that.
+    pub async fn svc_load(&self,
+                          ident: &PackageIdent,
+                          opts: ctl::SvcLoad)
+                          -> Result<test_ctl_gateway::Reply> {
+        let msg = ctl::SvcLoad { ident: Some(ident.clone().into()),
+                                 ..opts };
+        self.ctl_client
+            .request(msg)
+            .await
+            .with_context(|| format!("Failed to load service {}", ident))
+    }
+
+    /// Unload the service `ident` through the control gateway. This
+    /// does not wait for the service to be unloaded.
+    pub async fn svc_unload(&self, ident: &PackageIdent) -> Result<test_ctl_gateway::Reply> {
+        let msg = ctl::SvcUnload { ident:              Some(ident.clone().into()),
+                                   timeout_in_seconds: None, };
+        self.ctl_client
+            .request(msg)
+            .await
+            .with_context(|| format!("Failed to unload service {}", ident))
+    }
+
+    /// Stop the service `ident` through the control gateway. This
+    /// does not wait for the service to be stopped; use
+    /// `ensure_service_stopped` for that.
+    pub async fn svc_stop(&self, ident: &PackageIdent) -> Result<test_ctl_gateway::Reply> {
+        let msg = ctl::SvcStop { ident:              Some(ident.clone().into()),
+                                 timeout_in_seconds: None, };
+        self.ctl_client
+            .request(msg)
+            .await
+            .with_context(|| format!("Failed to stop service {}", ident))
+    }
+
+    /// Start the stopped service `ident` through the control gateway.
+    /// This does not wait for the service to start; use
+    /// `ensure_service_started` for that.
+    pub async fn svc_start(&self, ident: &PackageIdent) -> Result<test_ctl_gateway::Reply> {
+        let msg = ctl::SvcStart { ident: Some(ident.clone().into()), };
+        self.ctl_client
+            .request(msg)
+            .await
+            .with_context(|| format!("Failed to start service {}", ident))
+    }
+
+    /// The spec and merged (and redacted) configuration the running
+    /// service `ident` is using, as the control gateway reports them.
+    pub async fn svc_effective_cfg(&self,
+                                   ident: &PackageIdent)
+                                   -> Result<ctl::ServiceEffectiveCfg> {
+        let msg = ctl::SvcGetEffectiveCfg { ident: Some(ident.clone().into()), };
+        self.ctl_client
+            .request(msg)
+            .await
+            .with_context(|| format!("Failed to get the effective configuration of {}", ident))?
+            .parse()
+    }
+
+    /// Have the Supervisor run the health check of the service
+    /// `package_name.service_group` right away, rather than waiting for
+    /// its next scheduled one, and return what it found.
+    pub async fn svc_health_check(&self,
+                                  package_name: &str,
+                                  service_group: &str)
+                                  -> Result<ctl::ServiceHealthCheck> {
+        let group = format!("{}.{}", package_name, service_group);
+        let msg = ctl::SvcHealthCheckRun { service_group: Some(group.parse()?), };
+        self.ctl_client
+            .request(msg)
+            .await
+            .with_context(|| format!("Failed to run the health check of {}", group))?
+            .parse()
+    }
+
+    /// Run `hab sup converge` against the desired state in
+    /// `desired_state`, returning the plan it prints.
+    pub async fn converge(&self, desired_state: &Path, dry_run: bool) -> Result<String> {
+        let hab_exe = find_exe("hab").context("Failed to find 'hab' executable")?;
+        let mut cmd = Command::new(&hab_exe);
+        cmd.env("FS_ROOT", self.hab_root.display().to_string())
+           .env("HAB_LICENSE", "accept-no-persist")
+           .arg("sup")
+           .arg("converge")
+           .arg(desired_state)
+           .arg("--remote-sup")
+           .arg(format!("localhost:{}", self.control_port))
+           .stdin(Stdio::null());
+        if dry_run {
+            cmd.arg("--dry-run");
+        }
+        cmd.kill_on_drop(true);
+        let output =
+            cmd.output()
+               .await
+               .with_context(|| format!("Failed to converge on {}", desired_state.display()))?;
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        if !output.status.success() {
+            return Err(anyhow!("Converging on {} failed: {}\n{}{}",
+                               desired_state.display(),
+                               output.status,
+                               stdout,
+                               String::from_utf8_lossy(&output.stderr)));
+        }
+        Ok(stdout)
+    }
+
+    /// The URL of `path` on the Supervisor's HTTP gateway.
+    pub fn gateway_url(&self, path: &str) -> String {
+        format!("{}://localhost:{}{}",
+                self.gateway_scheme, self.http_port, path)
+    }
+
+    /// GET `path` from the Supervisor's HTTP gateway, with the token
+    /// the Supervisor was given, if any. `None` means the gateway
+    /// couldn't be reached, which callers polling it take to mean "not
+    /// yet".
+    async fn gateway_get(&self, path: &str) -> Result<Option<reqwest::Response>> {
+        let mut req = self.api_client
+                          .request(Method::GET, self.gateway_url(path).as_str());
+        if let Some(token) = self.env_vars().get(GATEWAY_AUTH_TOKEN_ENVVAR) {
+            req = req.bearer_auth(token);
+        }
+        let req = req.build()
+                     .context("Failed to construct API request to supervisor HTTP endpoint")?;
+        Ok(self.api_client.execute(req).await.ok())
+    }
+
+    /// Subscribe to the events the HTTP gateway streams about
+    /// services, starting with a "snapshot" of each one loaded now.
+    pub async fn subscribe_events(&self) -> Result<EventStream> {
+        let res = self.gateway_get("/events")
+                      .await?
+                      .ok_or_else(|| anyhow!("Test supervisor's HTTP gateway is not answering"))?;
+        if !res.status().is_success() {
+            return Err(anyhow!("Failed to subscribe to HTTP gateway events: {}",
+                               res.status()));
+        }
+        Ok(EventStream::new(res))
+    }
+
+    /// Poll the gateway's view of a service until `predicate` holds
+    /// for it, and return that view, so callers can pick out whatever
+    /// they matched on. Until the service is known to the gateway, or
+    /// the gateway answers, keep trying. On timeout, the error
+    /// includes the last view seen.
+    pub async fn wait_for_service_condition<F>(&self,
+                                               package_name: &str,
+                                               service_group: &str,
+                                               predicate: F,
+                                               timeout: Duration)
+                                               -> Result<ServiceInfo>
+        where F: Fn(&ServiceInfo) -> bool
+    {
+        let started_at = Instant::now();
+        let mut last_seen = None;
+        loop {
+            if let Some(service) = self.service_info(package_name, service_group).await? {
+                if predicate(&service) {
+                    return Ok(service);
+                }
+                last_seen = Some(service);
+            }
+            if started_at.elapsed() > timeout {
+                let last_seen = match last_seen {
+                    Some(service) => format!("{:#?}", service),
+                    None => String::from("nothing"),
+                };
+                return Err(anyhow!("Service {}.{} did not reach the expected state \
+                                    within {:.2} secs; last saw:\n{}",
+                                   package_name,
+                                   service_group,
+                                   timeout.as_secs_f64(),
+                                   last_seen));
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    /// The Supervisor's census, as its HTTP gateway shows it.
+    pub async fn census(&self) -> Result<Census> {
+        self.gateway_get("/census")
+            .await?
+            .ok_or_else(|| anyhow!("Test supervisor's HTTP gateway is not answering"))?
+            .json()
+            .await
+            .context("Failed to parse supervisor census")
+    }
+
+    /// Wait until the census shows exactly `alive` members alive,
+    /// counting this Supervisor. Only members running a service are
+    /// in the census.
+    pub async fn wait_for_member_count(&self, alive: usize, timeout: Duration) -> Result<Census> {
+        self.wait_for_census(timeout, |census| census.alive_member_ids().len() == alive)
+            .await
+            .with_context(|| format!("Test supervisor did not see {} alive members", alive))
+    }
+
+    /// Wait until the census shows `member_id` as departed.
+    pub async fn wait_for_member_departed(&self,
+                                          member_id: &str,
+                                          timeout: Duration)
+                                          -> Result<Census> {
+        self.wait_for_census(timeout, |census| {
+                census.member_health(member_id) == Some(MemberHealth::Departed)
+            })
+            .await
+            .with_context(|| format!("Test supervisor did not see {} depart", member_id))
+    }
+
+    async fn wait_for_census<F>(&self, timeout: Duration, predicate: F) -> Result<Census>
+        where F: Fn(&Census) -> bool
+    {
+        let started_at = Instant::now();
+        let mut last_seen = None;
+        loop {
+            // Until the gateway answers, or has a census to show, keep
+            // trying.
+            if let Ok(census) = self.census().await {
+                if predicate(&census) {
+                    return Ok(census);
+                }
+                last_seen = Some(census);
+            }
+            if started_at.elapsed() > timeout {
+                return Err(anyhow!("Timed out after {:.2} secs; last saw {:#?}",
+                                   timeout.as_secs_f64(),
+                                   last_seen));
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
     /// Ensure that a service that should be up has started.
     /// The following properties are verified:
     /// ```
@@ -511,32 +1662,16 @@ impl TestSup {
                                         package_name: &str,
                                         service_group: &str,
                                         timeout: Duration)
-                                        -> Result<sup_gateway_api::Service> {
-        let started_at = Instant::now();
-        loop {
-            if started_at.elapsed() > timeout {
-                return Err(anyhow!("Test supervisor failed to start service '{}.{}' \
-                                    within {:.2}secs",
-                                   package_name,
-                                   service_group,
-                                   timeout.as_secs_f64()));
-            }
-            if let Some(service) = self.try_get_service_state(package_name, service_group)
-                                       .await
-                                       .with_context(|| {
-                                           format!("Failed to get state of service {}.{}",
-                                                   package_name, service_group)
-                                       })?
-            {
-                if let ("up", "Up", Some(_)) = (service.process.state.as_str(),
-                                                service.desired_state.as_str(),
-                                                service.process.pid)
-                {
-                    return Ok(service);
-                }
-            }
-            tokio::time::sleep(Duration::from_millis(500)).await;
-        }
+                                        -> Result<ServiceInfo> {
+        self.wait_for_service_condition(package_name,
+                                        service_group,
+                                        ServiceInfo::is_running,
+                                        timeout)
+            .await
+            .with_context(|| {
+                format!("Test supervisor failed to start service '{}.{}'",
+                        package_name, service_group)
+            })
     }
 
     /// Ensure the a service that should be down has stopped.
@@ -551,7 +1686,7 @@ impl TestSup {
                                         package_name: &str,
                                         service_group: &str,
                                         timeout: Duration)
-                                        -> Result<sup_gateway_api::Service> {
+                                        -> Result<ServiceInfo> {
         let started_at = Instant::now();
         loop {
             if started_at.elapsed() > timeout {
@@ -561,7 +1696,7 @@ impl TestSup {
                                    service_group,
                                    timeout.as_secs_f64()));
             }
-            if let Some(service) = self.try_get_service_state(package_name, service_group)
+            if let Some(service) = self.service_info(package_name, service_group)
                                        .await
                                        .with_context(|| {
                                            format!("Failed to get state of service {}.{}",
@@ -583,6 +1718,115 @@ impl TestSup {
         }
     }
 
+    /// Wait for a service to go down, either by being unloaded (the
+    /// HTTP gateway answers 404 for it) or by being stopped (its
+    /// process is "down" and has no pid), and say which. Until the
+    /// gateway answers, keep trying.
+    pub async fn wait_for_service_down(&self,
+                                       package_name: &str,
+                                       service_group: &str,
+                                       timeout: Duration)
+                                       -> Result<ServiceDown> {
+        let started_at = Instant::now();
+        let path = format!("/services/{}/{}", package_name, service_group);
+        let mut last_seen = String::from("no answer from the HTTP gateway");
+        loop {
+            if started_at.elapsed() > timeout {
+                return Err(anyhow!("Test supervisor did not take service {}.{} down \
+                                    within {:.2} secs; last saw {}",
+                                   package_name,
+                                   service_group,
+                                   timeout.as_secs_f64(),
+                                   last_seen));
+            }
+            if let Some(res) = self.gateway_get(&path).await? {
+                if res.status() == reqwest::StatusCode::NOT_FOUND {
+                    return Ok(ServiceDown::Unloaded);
+                }
+                match res.json::<ServiceInfo>().await {
+                    Ok(service) => {
+                        if let ("down", None) =
+                            (service.process.state.as_str(), service.process.pid)
+                        {
+                            return Ok(ServiceDown::Stopped(service));
+                        }
+                        last_seen = format!("{:?}", service);
+                    }
+                    Err(err) => last_seen = format!("an unreadable answer ({})", err),
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    /// Wait for a service's health check to report `desired`. Until
+    /// the health check has run, or the gateway answers, keep trying.
+    /// On timeout, the error includes the last status seen and the
+    /// health check hook's output.
+    pub async fn wait_for_health(&self,
+                                 package_name: &str,
+                                 service_group: &str,
+                                 desired: HealthCheck,
+                                 timeout: Duration)
+                                 -> Result<()> {
+        let started_at = Instant::now();
+        let path = format!("/services/{}/{}/health", package_name, service_group);
+        let mut last_seen = None;
+        loop {
+            if let Some(res) = self.gateway_get(&path).await? {
+                // Critical and Unknown come with error statuses, but
+                // still with a body; only a missing one means "not
+                // yet".
+                if let Ok(health) = res.json::<HealthCheckInfo>().await {
+                    if health.status == desired {
+                        return Ok(());
+                    }
+                    last_seen = Some(health);
+                }
+            }
+            if started_at.elapsed() > timeout {
+                let last_seen = match last_seen {
+                    Some(health) => {
+                        format!("{:?}, with stdout:\n{}\nand stderr:\n{}",
+                                health.status, health.stdout, health.stderr)
+                    }
+                    None => String::from("no health check result"),
+                };
+                return Err(anyhow!("Service {}.{} did not become {:?} within {:.2} \
+                                    secs; last saw {}",
+                                   package_name,
+                                   service_group,
+                                   desired,
+                                   timeout.as_secs_f64(),
+                                   last_seen));
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    /// A service's most recent health checks, oldest first, as the
+    /// gateway keeps them.
+    pub async fn health_history(&self,
+                                package_name: &str,
+                                service_group: &str)
+                                -> Result<Vec<HealthCheckRecord>> {
+        let path = format!("/services/{}/{}/health/history",
+                           package_name, service_group);
+        let res = self.gateway_get(&path)
+                      .await?
+                      .ok_or_else(|| anyhow!("Test supervisor's HTTP gateway is not answering"))?;
+        if !res.status().is_success() {
+            return Err(anyhow!("Failed to get health history of {}.{}: {}",
+                               package_name,
+                               service_group,
+                               res.status()));
+        }
+        res.json().await.with_context(|| {
+                            format!("Failed to parse health history of {}.{}",
+                                    package_name, service_group)
+                        })
+    }
+
     /// Ensure a service that should be up has failed to start.
     /// The following properties are verified:
     /// ```
@@ -595,10 +1839,10 @@ impl TestSup {
                                                     package_name: &str,
                                                     service_group: &str,
                                                     timeout: Duration)
-                                                    -> Result<sup_gateway_api::Service> {
+                                                    -> Result<ServiceInfo> {
         let started_at = Instant::now();
         loop {
-            if let Some(service) = self.try_get_service_state(package_name, service_group)
+            if let Some(service) = self.service_info(package_name, service_group)
                                        .await
                                        .with_context(|| {
                                            format!("Failed to get state of service {}.{}",
@@ -630,52 +1874,64 @@ impl TestSup {
         }
     }
 
-    /// Ensure a service that should be up has undergone a restart.
+    /// Ensure a service that should be up has undergone a restart
+    /// since it was seen as `earlier`, returning the new process's pid
+    /// and how many changes to the process were seen along the way.
     /// The following properties are verified:
     /// ```
-    /// service.is_some() == true; // must always hold
-    /// service.desired_state == "Up"; // must always hold
-    /// service.process.state == "up" && service.process.pid != old_process_id; // must eventually hold
+    /// service.is_some() == true; // must eventually hold
+    /// service.has_restarted_since(earlier); // must eventually hold
+    /// !service.is_stopped(); // must always hold
     /// ```
+    /// The restart is told by when the new process came up, not by its
+    /// pid, which can be reused. If the service is stopped, and so
+    /// won't be restarted, this fails straight away.
     pub async fn ensure_service_restarted(&self,
-                                          old_process_id: Pid,
+                                          earlier: &ServiceInfo,
                                           package_name: &str,
                                           service_group: &str,
                                           timeout: Duration)
-                                          -> Result<sup_gateway_api::Service> {
+                                          -> Result<ServiceRestart> {
         let started_at = Instant::now();
+        let mut last_seen: Option<ServiceInfo> = None;
+        let mut transitions = 0;
         loop {
-            if started_at.elapsed() > timeout {
-                return Err(anyhow!("Test supervisor failed to restart service \
-                                    '{}.{}' within {:.2} secs",
-                                   package_name,
-                                   service_group,
-                                   timeout.as_secs_f64()));
-            }
-            if let Some(service) = self.try_get_service_state(package_name, service_group)
+            if let Some(service) = self.service_info(package_name, service_group)
                                        .await
                                        .with_context(|| {
                                            format!("Failed to get state of service {}.{}",
                                                    package_name, service_group)
                                        })?
             {
-                if service.desired_state != "Up" {
-                    return Err(anyhow!("The service {}.{} must have a desired state of \
-                                        'Up'",
-                                       package_name,
-                                       service_group));
+                if service.process_changed_since(last_seen.as_ref().unwrap_or(earlier)) {
+                    transitions += 1;
                 }
-                if let ("up", Some(process_id)) =
-                    (service.process.state.as_str(), service.process.pid)
-                {
-                    if process_id != old_process_id {
-                        return Ok(service);
-                    }
+                if service.has_restarted_since(earlier) {
+                    let pid = service.process.pid.expect("Running services have a pid");
+                    return Ok(ServiceRestart { service,
+                                               pid,
+                                               transitions });
                 }
-            } else {
-                return Err(anyhow!("Test supervisor is not running the service {}.{}",
+                if service.is_stopped() {
+                    return Err(anyhow!("Test supervisor stopped service {}.{} rather \
+                                        than restarting it:\n{:#?}",
+                                       package_name,
+                                       service_group,
+                                       service));
+                }
+                last_seen = Some(service);
+            }
+            if started_at.elapsed() > timeout {
+                let last_seen = match last_seen {
+                    Some(service) => format!("{:#?}", service),
+                    None => String::from("nothing"),
+                };
+                return Err(anyhow!("Test supervisor failed to restart service {}.{} \
+                                    within {:.2} secs; last saw:\n{}",
                                    package_name,
-                                   service_group));
+                                   service_group,
+                                   timeout.as_secs_f64(),
+                                   last_seen));
             }
             tokio::time::sleep(Duration::from_millis(500)).await;
         }
@@ -688,16 +1944,15 @@ impl TestSup {
     /// service.desired_state == "Up"; // must always hold
     /// service.process.state == "up" && service.process.pid == old_process_id; // must always hold
     /// ```
-    pub async fn ensure_service_has_not_stopped_or_restarted(
-        &self,
-        old_process_id: Pid,
-        package_name: &str,
-        service_group: &str,
-        timeout: Duration)
-        -> Result<sup_gateway_api::Service> {
+    pub async fn ensure_service_has_not_stopped_or_restarted(&self,
+                                                             old_process_id: Pid,
+                                                             package_name: &str,
+                                                             service_group: &str,
+                                                             timeout: Duration)
+                                                             -> Result<ServiceInfo> {
         let started_at = Instant::now();
         loop {
-            if let Some(service) = self.try_get_service_state(package_name, service_group)
+            if let Some(service) = self.service_info(package_name, service_group)
                                        .await
                                        .context("Failed to get state of service")?
             {
@@ -0,0 +1,38 @@
+//! Generate certificates for serving a test Supervisor's HTTP gateway
+//! over TLS (see `TestSupBuilder::with_tls`).
+use anyhow::{Context,
+             Result};
+use std::{fs,
+          path::{Path,
+                 PathBuf}};
+
+/// A self-signed certificate for `localhost` and its private key, in
+/// PEM files. Being self-signed, the certificate is also the CA that
+/// clients need to trust.
+#[derive(Clone, Debug)]
+pub struct TestCert {
+    pub cert_path: PathBuf,
+    pub key_path:  PathBuf,
+}
+
+/// Generate a new `TestCert` in `dir`, which is created if need be.
+pub fn generate_self_signed_cert(dir: &Path) -> Result<TestCert> {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
+        .context("Failed to generate self-signed certificate")?;
+    let cert_pem = cert.serialize_pem()
+                       .context("Failed to serialize self-signed certificate")?;
+    let key_pem = cert.serialize_private_key_pem();
+
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let test_cert = TestCert { cert_path: dir.join("cert.pem"),
+                               key_path:  dir.join("key.pem"), };
+    fs::write(&test_cert.cert_path, cert_pem).with_context(|| {
+                                                 format!("Failed to write {}",
+                                                         test_cert.cert_path.display())
+                                             })?;
+    fs::write(&test_cert.key_path, key_pem).with_context(|| {
+                                               format!("Failed to write {}",
+                                                       test_cert.key_path.display())
+                                           })?;
+    Ok(test_cert)
+}